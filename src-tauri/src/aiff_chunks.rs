@@ -0,0 +1,222 @@
+// AIFF chunk repair. Some exporters (Logic Pro among them) leave out the pad
+// byte after an odd-sized chunk or name the ID3 chunk in odd case. lofty's
+// chunk walk assumes even alignment, loses its place and finds no tag, and
+// its writer then appends a second ID3 chunk on every save. We walk the
+// chunks tolerantly ourselves and re-serialize with clean padding and a
+// single `ID3 ` chunk holding the frames of all of them (a later chunk's frame
+// wins over an earlier one with the same id, since lofty appended the later
+// ones). Quirky files are read from that copy in memory, and before a save
+// the file itself is rewritten that way, so lofty's writer finds the chunk and
+// replaces it instead of adding another.
+
+use std::{fs, io::{Cursor, Read, Seek, SeekFrom}, path::Path};
+use lofty::{id3::v2::Id3v2Tag, iff::aiff::AiffFile, AudioFile, FileType, ParseOptions, Probe, TagExt, TaggedFile};
+
+use crate::{ext_lower, write_atomic};
+
+struct RawChunk {
+  id: [u8; 4],
+  /// Offset of the chunk data (after the 8-byte header).
+  data_start: u64,
+  size: u32,
+  /// An odd-sized chunk was followed by its pad byte.
+  padded: bool,
+}
+
+struct Layout {
+  form_type: [u8; 4],
+  chunks: Vec<RawChunk>,
+  /// Where the walk gave up, if before the end of the file.
+  junk_from: Option<u64>,
+}
+
+fn is_aiff(p: &Path) -> bool { matches!(ext_lower(p).as_str(), "aif" | "aiff") }
+
+fn is_id3(id: &[u8; 4]) -> bool { id.eq_ignore_ascii_case(b"ID3 ") }
+
+fn valid_id(id: &[u8; 4]) -> bool { id.iter().all(|b| (0x20..=0x7E).contains(b)) }
+
+fn header_at<R: Read + Seek>(r: &mut R, at: u64) -> Option<([u8; 4], u32)> {
+  let mut h = [0u8; 8];
+  r.seek(SeekFrom::Start(at)).ok()?;
+  r.read_exact(&mut h).ok()?;
+  let id = [h[0], h[1], h[2], h[3]];
+  valid_id(&id).then(|| (id, u32::from_be_bytes([h[4], h[5], h[6], h[7]])))
+}
+
+/// Chunk list of an AIFF/AIFC stream. An odd chunk without its pad byte is
+/// detected by the next header only lining up one byte earlier.
+fn walk<R: Read + Seek>(r: &mut R, len: u64) -> Option<Layout> {
+  let mut head = [0u8; 12];
+  r.seek(SeekFrom::Start(0)).ok()?;
+  r.read_exact(&mut head).ok()?;
+  if &head[..4] != b"FORM" || !matches!(&head[8..12], b"AIFF" | b"AIFC") { return None; }
+  let mut layout = Layout { form_type: [head[8], head[9], head[10], head[11]], chunks: Vec::new(), junk_from: None };
+  let mut at = 12u64;
+  while at + 8 <= len {
+    let unpadded_prev = layout.chunks.last().is_some_and(|c| c.size % 2 == 1) && at > 12;
+    let found = match header_at(r, at) {
+      Some(h) => Some((at, h)),
+      None if unpadded_prev => header_at(r, at - 1).map(|h| (at - 1, h)),
+      None => None,
+    };
+    let Some((start, (id, size))) = found else { layout.junk_from = Some(at); break };
+    if start != at { if let Some(prev) = layout.chunks.last_mut() { prev.padded = false; } }
+    let data_start = start + 8;
+    if data_start + size as u64 > len { layout.junk_from = Some(start); break; }
+    let padded = size % 2 == 1 && data_start + (size as u64) < len;
+    layout.chunks.push(RawChunk { id, data_start, size, padded });
+    at = data_start + size as u64 + (size % 2) as u64;
+  }
+  if layout.junk_from.is_none() && at < len { layout.junk_from = Some(at); }
+  Some(layout)
+}
+
+/// Anything lofty would trip over: missing pad bytes, several ID3 chunks or
+/// an ID3 chunk id other than the two spellings lofty knows.
+fn has_quirks(l: &Layout) -> bool {
+  let id3: Vec<&RawChunk> = l.chunks.iter().filter(|c| is_id3(&c.id)).collect();
+  l.chunks.iter().any(|c| c.size % 2 == 1 && !c.padded)
+    || id3.len() > 1
+    || id3.iter().any(|c| &c.id != b"ID3 " && &c.id != b"id3 ")
+}
+
+fn chunk_data<'a>(bytes: &'a [u8], c: &RawChunk) -> &'a [u8] {
+  let start = c.data_start as usize;
+  &bytes[start..start + c.size as usize]
+}
+
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+  out.extend_from_slice(id);
+  out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+  out.extend_from_slice(data);
+  if data.len() % 2 == 1 { out.push(0); }
+}
+
+/// The ID3v2 tag in one chunk's data, via a stand-alone AIFF holding only it.
+fn parse_id3(data: &[u8]) -> Option<Id3v2Tag> {
+  let mut mini = b"FORM\0\0\0\0AIFF".to_vec();
+  push_chunk(&mut mini, b"ID3 ", data);
+  let form_size = (mini.len() - 8) as u32;
+  mini[4..8].copy_from_slice(&form_size.to_be_bytes());
+  let file = AiffFile::read_from(&mut Cursor::new(mini), ParseOptions::new().read_properties(false)).ok()?;
+  file.id3v2().cloned()
+}
+
+/// Data for the one ID3 chunk a rebuilt file gets (see the header); a single
+/// chunk is kept byte for byte, as is the first when none of them parses.
+fn merged_id3(bytes: &[u8], l: &Layout) -> Option<Vec<u8>> {
+  let chunks: Vec<&[u8]> = l.chunks.iter().filter(|c| is_id3(&c.id)).map(|c| chunk_data(bytes, c)).collect();
+  if chunks.len() < 2 { return chunks.first().map(|d| d.to_vec()); }
+  let mut tags = chunks.iter().filter_map(|d| parse_id3(d));
+  let Some(mut merged) = tags.next() else { return Some(chunks[0].to_vec()) };
+  for tag in tags {
+    for frame in tag { merged.insert(frame); }
+  }
+  let mut out = Vec::new();
+  merged.dump_to(&mut out).ok()?;
+  Some(out)
+}
+
+/// Re-serialize with even padding and the ID3 chunks merged into one `ID3 `
+/// chunk where the first was. Bytes after the last readable chunk are kept.
+fn rebuild(bytes: &[u8], l: &Layout) -> Vec<u8> {
+  let mut out = Vec::with_capacity(bytes.len());
+  out.extend_from_slice(b"FORM\0\0\0\0");
+  out.extend_from_slice(&l.form_type);
+  let mut id3 = merged_id3(bytes, l);
+  for c in &l.chunks {
+    if !is_id3(&c.id) { push_chunk(&mut out, &c.id, chunk_data(bytes, c)); continue; }
+    if let Some(data) = id3.take() { push_chunk(&mut out, b"ID3 ", &data); }
+  }
+  if let Some(j) = l.junk_from {
+    out.extend_from_slice(&bytes[j as usize..]);
+    if out.len() % 2 == 1 { out.push(0); }
+  }
+  let form_size = (out.len() - 8) as u32;
+  out[4..8].copy_from_slice(&form_size.to_be_bytes());
+  out
+}
+
+fn quirky_layout(p: &Path) -> Option<Layout> {
+  if !is_aiff(p) { return None; }
+  let mut f = fs::File::open(p).ok()?;
+  let len = f.metadata().ok()?.len();
+  walk(&mut f, len).filter(has_quirks)
+}
+
+/// For a quirky AIFF, the file as parsed from a repaired in-memory copy;
+/// `None` means lofty can read the file as it is.
+pub fn read_repaired(p: &Path) -> Option<lofty::error::Result<TaggedFile>> {
+  let layout = quirky_layout(p)?;
+  let bytes = fs::read(p).ok()?;
+  let fixed = rebuild(&bytes, &layout);
+  Some(Probe::with_file_type(Cursor::new(fixed), FileType::Aiff).read())
+}
+
+/// Before lofty saves a quirky AIFF: rewrite it cleanly with one merged ID3
+/// chunk, which the save then replaces. Returns the original bytes so a
+/// failed save can put them back.
+pub fn prepare_write(p: &Path) -> Result<Option<Vec<u8>>, String> {
+  let Some(layout) = quirky_layout(p) else { return Ok(None) };
+  let bytes = fs::read(p).map_err(|e| e.to_string())?;
+  write_atomic(p, &rebuild(&bytes, &layout))?;
+  Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::Accessor;
+
+  fn id3(title: Option<&str>, artist: Option<&str>) -> Vec<u8> {
+    let mut tag = Id3v2Tag::new();
+    if let Some(t) = title { tag.set_title(t.into()); }
+    if let Some(a) = artist { tag.set_artist(a.into()); }
+    let mut out = Vec::new();
+    tag.dump_to(&mut out).unwrap();
+    out
+  }
+
+  /// FORM/AIFF with the chunks as given; `unpadded` leaves out pad bytes.
+  fn aiff(chunks: &[(&[u8; 4], Vec<u8>)], unpadded: bool) -> Vec<u8> {
+    let mut out = b"FORM\0\0\0\0AIFF".to_vec();
+    for (id, data) in chunks {
+      out.extend_from_slice(*id);
+      out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+      out.extend_from_slice(data);
+      if data.len() % 2 == 1 && !unpadded { out.push(0); }
+    }
+    let size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&size.to_be_bytes());
+    out
+  }
+
+  fn layout(bytes: &[u8]) -> Layout { walk(&mut Cursor::new(bytes), bytes.len() as u64).unwrap() }
+
+  #[test]
+  fn duplicate_id3_chunks_merge_with_the_later_winning() {
+    let bytes = aiff(&[(b"COMM", vec![0; 18]), (b"APPL", vec![1, 2, 3]), (b"ID3 ", id3(Some("old"), Some("artist"))), (b"id3 ", id3(Some("new"), None))], true);
+    let l = layout(&bytes);
+    assert!(has_quirks(&l));
+    let fixed = rebuild(&bytes, &l);
+    let l2 = layout(&fixed);
+    assert!(!has_quirks(&l2));
+    assert_eq!(l2.chunks.iter().filter(|c| is_id3(&c.id)).count(), 1);
+    assert_eq!(l2.chunks.iter().map(|c| c.id).collect::<Vec<_>>(), vec![*b"COMM", *b"APPL", *b"ID3 "]);
+    let tag = parse_id3(chunk_data(&fixed, l2.chunks.last().unwrap())).unwrap();
+    assert_eq!(tag.title().as_deref(), Some("new"));
+    assert_eq!(tag.artist().as_deref(), Some("artist"));
+  }
+
+  #[test]
+  fn single_id3_chunk_is_kept_byte_for_byte() {
+    let tag = id3(Some("t"), Some("a"));
+    let bytes = aiff(&[(b"COMM", vec![0; 18]), (b"APPL", vec![9]), (b"ID3 ", tag.clone())], true);
+    let l = layout(&bytes);
+    let fixed = rebuild(&bytes, &l);
+    let l2 = layout(&fixed);
+    assert_eq!(chunk_data(&fixed, &l2.chunks[2]), &tag[..]);
+    assert!(l2.chunks[1].padded);
+  }
+}
@@ -0,0 +1,404 @@
+// "Do I already own this?" for an incoming promo folder against the library.
+// `hash` compares audio-stream hashes (`decode::audio_hash`: same audio, any
+// tags), cached in data dir `audio_hashes.json` while size + mtime match so
+// a second check against the same library is quick. `meta` compares artist +
+// title after normalization: case and accents folded, featured artists
+// dropped, and the mix name split off, where "Original Mix", "Extended Mix"
+// and the like count as no mix name at all. Matches come back as pairs with
+// a confidence; skipping or trashing is up to the existing file commands.
+
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}, sync::atomic::{AtomicUsize, Ordering}, time::UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+use crate::{command_span, data_dir, decode, jobs::JobHandle, library::audio_files_under, log_line, maintenance, meta_cache, profile, text_fold::fold_str, write_atomic};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OwnedMethod { Hash, Meta }
+
+/// Bracketed or dashed suffixes naming the release's default version.
+const NEUTRAL_MIXES: &[&str] = &["original mix", "original", "extended mix", "extended", "main mix", "album version", "original version"];
+const MIX_WORDS: &[&str] = &["mix", "remix", "rmx", "edit", "dub", "version", "rework", "bootleg", "vip", "remaster", "remastered"];
+const FEAT: &[&str] = &["feat.", "feat ", "ft.", "ft ", "featuring "];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedMatch {
+  new_path: String,
+  existing_path: String,
+  /// 1.0 for identical audio; 0.95 / 0.85 / 0.7 for meta matches, see `meta_confidence`.
+  confidence: f32,
+  reason: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedReport {
+  matches: Vec<OwnedMatch>,
+  new_files: usize,
+  library_files: usize,
+  /// Incoming files that couldn't be hashed or read.
+  unreadable: Vec<String>,
+  cancelled: bool,
+}
+
+//////////////////// meta normalization ////////////////////
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaKey {
+  /// Main artists, folded and sorted.
+  pub artists: Vec<String>,
+  pub title: String,
+  /// Mix name as written (folded), neutral ones included.
+  pub mix_raw: Option<String>,
+  /// `mix_raw` with neutral names as `None`.
+  pub mix: Option<String>,
+}
+
+fn squash(s: &str) -> String {
+  let cleaned: String = s.chars().map(|c| if c.is_alphanumeric() { c } else { ' ' }).collect();
+  cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `s` up to a "feat." marker (folded input).
+fn strip_feat(s: &str) -> &str {
+  FEAT.iter().filter_map(|f| s.find(&format!(" {}", f)).or_else(|| s.starts_with(f).then_some(0))).min().map_or(s, |i| &s[..i])
+}
+
+fn is_mix(s: &str) -> bool { s.split_whitespace().any(|w| MIX_WORDS.contains(&w)) }
+
+/// A mix name squashed, with "rmx" spelled out.
+fn mix_name(s: &str) -> String {
+  squash(s).split(' ').map(|w| if w == "rmx" { "remix" } else { w }).collect::<Vec<_>>().join(" ")
+}
+
+/// (title without brackets/feat, mix name) from a folded title.
+fn split_title(folded: &str) -> (String, Option<String>) {
+  let mut base = String::new();
+  let mut mix = None;
+  let mut rest = folded;
+  while let Some(open) = rest.find(['(', '[']) {
+    base.push_str(&rest[..open]);
+    let close_ch = if rest.as_bytes()[open] == b'(' { ')' } else { ']' };
+    let Some(len) = rest[open + 1..].find(close_ch) else { rest = &rest[open + 1..]; continue };
+    let inner = rest[open + 1..open + 1 + len].trim();
+    // "(feat. X)" goes; mix names are kept aside; anything else stays in the title.
+    let feat = FEAT.iter().any(|f| inner.starts_with(f));
+    if !feat && is_mix(inner) {
+      mix = Some(mix_name(inner));
+    } else if !feat {
+      base.push(' ');
+      base.push_str(inner);
+    }
+    rest = &rest[open + 2 + len..];
+  }
+  base.push_str(rest);
+  let mut base = strip_feat(&base).to_string();
+  // "Title - Dub Mix"
+  if mix.is_none() {
+    if let Some((head, tail)) = base.rsplit_once(" - ").filter(|(_, t)| is_mix(t)) {
+      mix = Some(mix_name(tail));
+      base = head.to_string();
+    }
+  }
+  (squash(&base), mix)
+}
+
+fn split_artists(folded: &str) -> Vec<String> {
+  let main = strip_feat(folded);
+  let mut s = format!(" {} ", main);
+  for sep in [" x ", " and ", " vs. ", " vs ", "&", ",", ";", "/"] { s = s.replace(sep, "\u{1}"); }
+  let mut out: Vec<String> = s.split('\u{1}').map(squash).filter(|a| !a.is_empty()).collect();
+  out.sort();
+  out.dedup();
+  out
+}
+
+/// Normalized artist + title (see the header); `None` when either is empty.
+pub fn meta_key(artist: &str, title: &str) -> Option<MetaKey> {
+  let (title, mix_raw) = split_title(&fold_str(title));
+  let artists = split_artists(&fold_str(artist));
+  if title.is_empty() || artists.is_empty() { return None; }
+  let mix = mix_raw.clone().filter(|m| !NEUTRAL_MIXES.contains(&m.as_str()));
+  Some(MetaKey { artists, title, mix_raw, mix })
+}
+
+/// Key from the cached tags, else from an "Artist - Title" file name.
+fn key_for(p: &Path) -> Option<MetaKey> {
+  let m = meta_cache::get(p).ok();
+  let (artist, title) = (m.as_ref().and_then(|m| m.artist.clone()), m.as_ref().and_then(|m| m.title.clone()));
+  if let (Some(a), Some(t)) = (&artist, &title) { return meta_key(a, t); }
+  let stem = p.file_stem()?.to_string_lossy().to_string();
+  let (a, t) = stem.split_once(" - ")?;
+  meta_key(artist.as_deref().unwrap_or(a), title.as_deref().unwrap_or(t))
+}
+
+/// Same title and mix: 0.95 when the written mix names agree too, 0.85 when
+/// only a neutral one differs ("(Original Mix)" vs nothing), 0.7 when one
+/// artist list contains the other. `None` otherwise.
+fn meta_confidence(a: &MetaKey, b: &MetaKey) -> Option<(f32, &'static str)> {
+  if a.title != b.title || a.mix != b.mix { return None; }
+  let subset = |x: &[String], y: &[String]| x.iter().all(|v| y.contains(v));
+  if a.artists == b.artists && a.mix_raw == b.mix_raw {
+    Some((0.95, "same artist and title"))
+  } else if a.artists == b.artists {
+    Some((0.85, "same artist and title; mix name differs only by original/extended"))
+  } else if subset(&a.artists, &b.artists) || subset(&b.artists, &a.artists) {
+    Some((0.7, "same title; one artist list contains the other"))
+  } else {
+    None
+  }
+}
+
+//////////////////// hash cache ////////////////////
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedHash { len: u64, mtime_ms: u64, hash: String }
+
+fn hash_cache_path() -> PathBuf { data_dir().join("audio_hashes.json") }
+
+pub fn canonical(p: &Path) -> String { fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()).to_string_lossy().to_string() }
+
+fn stat(p: &Path) -> Option<(u64, u64)> {
+  let m = fs::metadata(p).ok()?;
+  Some((m.len(), m.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64))
+}
+
+//////////////////// scanning ////////////////////
+
+/// Run `f` over `files` on a few threads, with job progress; `None` for
+/// files skipped by cancellation. `done` counts across calls within one phase.
+pub fn par_map<T: Send>(files: &[PathBuf], job: &JobHandle, done: &AtomicUsize, total: usize, f: impl Fn(&Path) -> T + Sync) -> Vec<Option<T>> {
+  let next = AtomicUsize::new(0);
+  let workers = profile::workers();
+  let parts: Vec<Vec<(usize, T)>> = std::thread::scope(|s| {
+    let handles: Vec<_> = (0..workers)
+      .map(|_| s.spawn(|| {
+        let mut mine = Vec::new();
+        loop {
+          let i = next.fetch_add(1, Ordering::Relaxed);
+          if i >= files.len() || job.is_cancelled() { break; }
+          mine.push((i, f(&files[i])));
+          job.progress(done.fetch_add(1, Ordering::Relaxed) as u64 + 1, total as u64);
+        }
+        mine
+      }))
+      .collect();
+    handles.into_iter().map(|h| h.join().unwrap_or_default()).collect()
+  });
+  let mut out: Vec<Option<T>> = std::iter::repeat_with(|| None).take(files.len()).collect();
+  for (i, v) in parts.into_iter().flatten() { out[i] = Some(v); }
+  out
+}
+
+/// The `audio_hashes.json` cache, loaded once per job and saved if it grew.
+pub struct HashCache {
+  entries: parking_lot::Mutex<HashMap<String, CachedHash>>,
+  loaded: usize,
+}
+
+impl HashCache {
+  pub fn load() -> Self {
+    let entries: HashMap<String, CachedHash> = fs::read_to_string(hash_cache_path()).ok().and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default();
+    Self { loaded: entries.len(), entries: parking_lot::Mutex::new(entries) }
+  }
+
+  /// Audio hash of each file (`None` if unreadable or skipped by cancellation).
+  pub fn hashes(&self, files: &[PathBuf], job: &JobHandle, done: &AtomicUsize, total: usize) -> Vec<Option<String>> {
+    par_map(files, job, done, total, |p| {
+      let (k, st) = (canonical(p), stat(p)?);
+      if let Some(c) = self.entries.lock().get(&k).filter(|c| (c.len, c.mtime_ms) == st) { return Some(c.hash.clone()); }
+      let hash = decode::audio_hash(p, Some(job.cancel_flag())).ok()?;
+      self.entries.lock().insert(k, CachedHash { len: st.0, mtime_ms: st.1, hash: hash.clone() });
+      Some(hash)
+    })
+    .into_iter()
+    .map(Option::flatten)
+    .collect()
+  }
+
+  /// Entries and file size of `audio_hashes.json`.
+  pub fn stats() -> (usize, u64) {
+    let bytes = fs::metadata(hash_cache_path()).map(|m| m.len()).unwrap_or(0);
+    (Self::load().entries.into_inner().len(), bytes)
+  }
+
+  /// Drop the hashes of files that are gone; `None` when `stop` cut it short.
+  /// Returns (pruned, bytes before, bytes after).
+  pub fn prune(stop: &dyn Fn() -> bool) -> Option<(usize, u64, u64)> {
+    let before = fs::metadata(hash_cache_path()).map(|m| m.len()).unwrap_or(0);
+    let mut entries = Self::load().entries.into_inner();
+    let keys: Vec<String> = entries.keys().cloned().collect();
+    let mut pruned = 0;
+    for (i, k) in keys.iter().enumerate() {
+      if i % 64 == 0 && stop() { return None; }
+      if maintenance::source_gone(Path::new(k)) { entries.remove(k); pruned += 1; }
+    }
+    if pruned == 0 { return Some((0, before, before)); }
+    let json = serde_json::to_vec(&entries).ok()?;
+    write_atomic(&hash_cache_path(), &json).ok()?;
+    Some((pruned, before, json.len() as u64))
+  }
+
+  pub fn save(self) -> Result<(), String> {
+    let entries = self.entries.into_inner();
+    if entries.len() == self.loaded { return Ok(()); }
+    let json = serde_json::to_vec(&entries).map_err(|e| e.to_string())?;
+    write_atomic(&hash_cache_path(), &json)
+  }
+}
+
+fn find_blocking(job: &JobHandle, new_folder: &str, library_folders: &[String], method: OwnedMethod) -> Result<OwnedReport, String> {
+  let new_root = PathBuf::from(new_folder);
+  job.begin_phase("scan", 0);
+  let incoming = job.timed("walk", || audio_files_under(&new_root)).map_err(|e| e.to_string())?;
+  let new_canon: HashSet<String> = incoming.iter().map(|p| canonical(p)).collect();
+  let mut library: Vec<PathBuf> = Vec::new();
+  for root in library_folders {
+    match job.timed("walk", || audio_files_under(Path::new(root))) {
+      Ok(files) => library.extend(files),
+      Err(e) => log_line(&format!("find_already_owned skip root \"{}\": {}", root, e)),
+    }
+  }
+  // The incoming folder may sit inside a library root, and roots may overlap.
+  let mut seen = HashSet::new();
+  library.retain(|p| {
+    let c = canonical(p);
+    !new_canon.contains(&c) && seen.insert(c)
+  });
+
+  let total = incoming.len() + library.len();
+  let done = AtomicUsize::new(0);
+  let mut matches = Vec::new();
+  let mut unreadable = Vec::new();
+  let path_str = |p: &PathBuf| p.to_string_lossy().to_string();
+
+  match method {
+    OwnedMethod::Hash => {
+      let cache = HashCache::load();
+      job.begin_phase("hash", total as u64);
+      let new_hashes = job.timed("hash", || cache.hashes(&incoming, job, &done, total));
+      let lib_hashes = job.timed("hash", || cache.hashes(&library, job, &done, total));
+      let mut by_hash: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+      for (p, h) in library.iter().zip(&lib_hashes) {
+        if let Some(h) = h { by_hash.entry(h.as_str()).or_default().push(p); }
+      }
+      for (p, h) in incoming.iter().zip(&new_hashes) {
+        let Some(h) = h else { unreadable.push(path_str(p)); continue };
+        for existing in by_hash.get(h.as_str()).into_iter().flatten() {
+          matches.push(OwnedMatch { new_path: path_str(p), existing_path: path_str(existing), confidence: 1.0, reason: "identical audio stream".into() });
+        }
+      }
+      cache.save()?;
+    }
+    OwnedMethod::Meta => {
+      job.begin_phase("analyze", total as u64);
+      let new_keys = job.timed("parse", || par_map(&incoming, job, &done, total, key_for));
+      let lib_keys = job.timed("parse", || par_map(&library, job, &done, total, key_for));
+      let mut by_title: HashMap<&str, Vec<(&PathBuf, &MetaKey)>> = HashMap::new();
+      for (p, k) in library.iter().zip(&lib_keys) {
+        if let Some(Some(k)) = k { by_title.entry(k.title.as_str()).or_default().push((p, k)); }
+      }
+      for (p, k) in incoming.iter().zip(&new_keys) {
+        let Some(Some(k)) = k else { unreadable.push(path_str(p)); continue };
+        for (existing, ek) in by_title.get(k.title.as_str()).into_iter().flatten() {
+          if let Some((confidence, reason)) = meta_confidence(k, ek) {
+            matches.push(OwnedMatch { new_path: path_str(p), existing_path: path_str(existing), confidence, reason: reason.into() });
+          }
+        }
+      }
+    }
+  }
+  let cancelled = job.is_cancelled();
+  // Skipped by cancellation, not unreadable.
+  if cancelled { unreadable.clear(); }
+  matches.sort_by(|a, b| a.new_path.cmp(&b.new_path).then(b.confidence.total_cmp(&a.confidence)));
+  Ok(OwnedReport { matches, new_files: incoming.len(), library_files: library.len(), unreadable, cancelled })
+}
+
+/// Files under `new_folder` that already exist somewhere under
+/// `library_folders`, by audio hash or by normalized artist + title; job kind
+/// "already-owned". Each pair says how sure the match is.
+#[tauri::command]
+pub async fn find_already_owned(
+  app: tauri::AppHandle,
+  new_folder: String,
+  library_folders: Vec<String>,
+  method: OwnedMethod,
+) -> Result<OwnedReport, String> {
+  let _span = command_span("find_already_owned");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "already-owned", &new_folder);
+    let res = find_blocking(&job, &new_folder, &library_folders, method);
+    if let Ok(r) = &res {
+      log_line(&format!("find_already_owned folder=\"{}\" method={:?} new={} library={} matches={}", new_folder, method, r.new_files, r.library_files, r.matches.len()));
+    }
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support;
+
+  fn confidence(a: (&str, &str), b: (&str, &str)) -> Option<f32> {
+    let (a, b) = (meta_key(a.0, a.1).unwrap(), meta_key(b.0, b.1).unwrap());
+    let c = meta_confidence(&a, &b).map(|(c, _)| c);
+    assert_eq!(c, meta_confidence(&b, &a).map(|(c, _)| c), "symmetric");
+    c
+  }
+
+  #[test]
+  fn neutral_mix_names_match_nothing() {
+    assert_eq!(confidence(("Artist", "Track (Original Mix)"), ("Artist", "Track")), Some(0.85));
+    assert_eq!(confidence(("Artist", "Track [Original Mix]"), ("Artist", "Track")), Some(0.85));
+    assert_eq!(confidence(("Artist", "Track (Extended Mix)"), ("Artist", "Track (Original Mix)")), Some(0.85));
+    assert_eq!(confidence(("Artist", "Track - Original Mix"), ("Artist", "Track (Original Mix)")), Some(0.95));
+    assert_eq!(confidence(("Artist", "Track (Original Mix)"), ("Artist", "Track (Original Mix)")), Some(0.95));
+  }
+
+  #[test]
+  fn other_mixes_and_versions_are_different_tracks() {
+    assert_eq!(confidence(("Artist", "Track (Dub Mix)"), ("Artist", "Track")), None);
+    assert_eq!(confidence(("Artist", "Track (Dub Mix)"), ("Artist", "Track (Original Mix)")), None);
+    assert_eq!(confidence(("Artist", "Track (Remix)"), ("Artist", "Track (Someone Remix)")), None);
+    assert_eq!(confidence(("Artist", "Track (Live)"), ("Artist", "Track")), None, "not a mix name: part of the title");
+    assert_eq!(confidence(("Artist", "Track - Dub Mix"), ("Artist", "Track (Dub Mix)")), Some(0.95));
+    assert_eq!(confidence(("Artist", "Track (Radio Edit)"), ("Artist", "Track [Radio Edit]")), Some(0.95));
+    assert_eq!(confidence(("Artist", "Track (Someone Rmx)"), ("Artist", "Track (Someone Remix)")), Some(0.95), "rmx is remix");
+  }
+
+  #[test]
+  fn case_accents_and_featured_artists_fold_away() {
+    assert_eq!(confidence(("ARTIST", "TRACK"), ("artist", "track")), Some(0.95));
+    assert_eq!(confidence(("Beyoncé", "Café"), ("Beyonce", "Cafe")), Some(0.95));
+    assert_eq!(confidence(("Artist feat. Guest", "Track"), ("Artist", "Track")), Some(0.95));
+    assert_eq!(confidence(("Artist ft. Guest", "Track"), ("Artist", "Track (feat. Guest)")), Some(0.95));
+    assert_eq!(confidence(("Artist", "Track feat. Guest"), ("Artist", "Track")), Some(0.95));
+    assert_eq!(confidence(("Artist", "Track!"), ("Artist", "Track")), Some(0.95), "punctuation");
+  }
+
+  #[test]
+  fn artist_lists_match_in_any_order_or_as_a_subset() {
+    assert_eq!(confidence(("A & B", "Track"), ("B, A", "Track")), Some(0.95));
+    assert_eq!(confidence(("A x B", "Track"), ("B and A", "Track")), Some(0.95));
+    assert_eq!(confidence(("A & B", "Track"), ("A", "Track")), Some(0.7));
+    assert_eq!(confidence(("A", "Track"), ("C", "Track")), None);
+    assert_eq!(confidence(("A", "Track"), ("A", "Tracks")), None);
+    assert!(meta_key("", "Track").is_none() && meta_key("A", "(Original Mix)").is_none());
+  }
+
+  #[test]
+  fn untagged_files_fall_back_to_the_file_name() {
+    let dir = test_support::scratch("already-owned");
+    let p = test_support::audio(&dir, "Artist feat. Guest - Track (Original Mix).mp3");
+    let key = key_for(&p).unwrap();
+    assert_eq!((key.artists, key.title.as_str(), key.mix), (vec!["artist".to_string()], "track", None));
+    assert!(key_for(&test_support::audio(&dir, "no separator.mp3")).is_none());
+  }
+}
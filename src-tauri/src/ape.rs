@@ -0,0 +1,243 @@
+// APEv2 on MP3: read-only by default (see `write_targets`), plus a one-shot
+// migration that folds the APE fields into ID3v2 and strips the APE block.
+// MP3Gain changes the gain stored in the MP3 frames themselves and keeps what
+// it did in APE (MP3GAIN_KEYS); without those fields the change can't be
+// undone. The migration keeps them in an APE block of their own, and only
+// `remove_mp3gain_undo`, after a native confirmation, takes them out.
+
+use std::path::Path;
+use lofty::{ItemKey, TagItem, TagType, Tag, TaggedFileExt};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::{audit, command_span, edit_tags_with, error::CmdError, ext_lower, log_line, read_tagged, shadow, WriteOutcome};
+
+/// What MP3Gain writes to APE: the gain steps it applied (and whether it
+/// clipped), and the track and album min/max global gain it measured.
+const MP3GAIN_KEYS: [&str; 3] = ["MP3GAIN_UNDO", "MP3GAIN_MINMAX", "MP3GAIN_ALBUM_MINMAX"];
+
+fn is_mp3gain(item: &TagItem) -> bool {
+  matches!(item.key(), ItemKey::Unknown(k) if MP3GAIN_KEYS.iter().any(|m| m.eq_ignore_ascii_case(k)))
+}
+
+/// `(key, value)` of each MP3Gain field in the APE tag, in tag order.
+fn mp3gain_fields(tf: &lofty::TaggedFile) -> Vec<(String, String)> {
+  let Some(ape) = tf.tag(TagType::Ape) else { return Vec::new() };
+  ape.items().filter_map(|i| match i.key() {
+    ItemKey::Unknown(k) if is_mp3gain(i) => Some((k.to_ascii_uppercase(), i.value().text().unwrap_or_default().to_string())),
+    _ => None,
+  }).collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mp3GainUndo {
+  /// `MP3GAIN_UNDO`, e.g. "+003,+003,N": steps per channel and whether
+  /// MP3Gain wrapped (clipped) the gain.
+  undo: Option<String>,
+  min_max: Option<String>,
+  album_min_max: Option<String>,
+  explanation: &'static str,
+}
+
+/// The MP3Gain fields in an MP3's APE tag, for `inspect_tags`.
+pub fn mp3gain_undo(tf: &lofty::TaggedFile, p: &Path) -> Option<Mp3GainUndo> {
+  if ext_lower(p) != "mp3" { return None; }
+  let fields = mp3gain_fields(tf);
+  if fields.is_empty() { return None; }
+  let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+  Some(Mp3GainUndo {
+    undo: get("MP3GAIN_UNDO"),
+    min_max: get("MP3GAIN_MINMAX"),
+    album_min_max: get("MP3GAIN_ALBUM_MINMAX"),
+    explanation: "MP3Gain changed the volume stored in this file's audio frames and keeps what it did in the APE tag. \
+      Removing these fields means MP3Gain can no longer undo that change; converting APE to ID3v2 keeps them.",
+  })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApeMigration {
+  path: String,
+  copied_items: usize,
+  copied_pictures: usize,
+  /// MP3Gain fields left in an APE block of their own.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  kept_mp3gain: Vec<String>,
+  #[serde(flatten)]
+  outcome: WriteOutcome,
+}
+
+/// Fold the APE fields into ID3v2 and strip the APE block (MP3Gain's
+/// fields stay in it). Goes through `edit_tags_with` on both tags, so locks,
+/// limits and the rest of the write path apply.
+#[tauri::command]
+pub fn convert_ape_to_id3(path: String) -> Result<ApeMigration, CmdError> {
+  let p = Path::new(&path);
+  if ext_lower(p) != "mp3" {
+    return Err("APE to ID3 migration only applies to MP3 files".to_string().into());
+  }
+  let ape = OnceCell::new();
+  let (mut copied_items, mut copied_pictures, mut kept_mp3gain) = (0, 0, Vec::new());
+  let outcome = edit_tags_with(p, |tf| {
+    let _ = ape.set(tf.tag(TagType::Ape).cloned().ok_or("file has no APE tag".to_string())?);
+    kept_mp3gain = mp3gain_fields(tf).into_iter().map(|(k, _)| k).collect();
+    Ok(vec![TagType::Id3v2, TagType::Ape])
+  }, |tag| {
+    let Some(ape) = ape.get() else { return };
+    if tag.tag_type() == TagType::Ape { return keep_only(tag, is_mp3gain); }
+    // Existing ID3v2 values win; APE only fills gaps.
+    for item in ape.items().filter(|i| !is_mp3gain(i)) {
+      if tag.get(item.key()).is_none() && tag.insert(item.clone()) { copied_items += 1; }
+    }
+    if tag.picture_count() == 0 {
+      for pic in ape.pictures() {
+        tag.push_picture(pic.clone());
+        copied_pictures += 1;
+      }
+    }
+  })?;
+
+  if !outcome.no_op { audit::record(&path, "tagStorage", Some("APE"), Some("ID3v2"), audit::Source::Manual); }
+  log_line(&format!("convert_ape_to_id3 path=\"{}\" items={} pictures={} kept_mp3gain={}", path, copied_items, copied_pictures, kept_mp3gain.join(",")));
+  Ok(ApeMigration { path, copied_items, copied_pictures, kept_mp3gain, outcome })
+}
+
+/// Leave only the APE items `keep` accepts, and no pictures; an APE tag left
+/// empty is stripped on save.
+fn keep_only(tag: &mut Tag, keep: impl Fn(&TagItem) -> bool) {
+  tag.retain(|i| keep(i));
+  while tag.picture_count() > 0 { tag.remove_picture(0); }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mp3GainRemoval {
+  /// `false` when the user declined; nothing was written.
+  confirmed: bool,
+  path: String,
+  removed: Vec<String>,
+  #[serde(flatten)]
+  outcome: WriteOutcome,
+}
+
+/// Take MP3Gain's undo fields out of an MP3's APE tag, after a native
+/// confirmation; the rest of the APE tag stays. Each field is audited.
+#[tauri::command]
+pub async fn remove_mp3gain_undo(window: tauri::Window, path: String) -> Result<Mp3GainRemoval, String> {
+  let _span = command_span("remove_mp3gain_undo");
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = Path::new(&path);
+    if ext_lower(p) != "mp3" { return Err("MP3Gain undo fields only exist on MP3 files".into()); }
+    let at = shadow::target(p)?.unwrap_or_else(|| p.to_path_buf());
+    let mut res = Mp3GainRemoval { confirmed: false, path: path.clone(), removed: Vec::new(), outcome: WriteOutcome { no_op: true, ..Default::default() } };
+    if mp3gain_fields(&read_tagged(&at).map_err(|e| e.to_string())?).is_empty() { return Err("file has no MP3Gain undo fields".into()); }
+    let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let msg = format!("Remove MP3Gain's undo information from \"{}\"? The volume change MP3Gain made to the audio can't be reverted afterwards.", name);
+    if !tauri::api::dialog::blocking::confirm(Some(&window), "Remove MP3Gain undo information", msg) { return Ok(res); }
+    res.confirmed = true;
+
+    let mut fields = Vec::new();
+    res.outcome = edit_tags_with(p, |tf| {
+      fields = mp3gain_fields(tf);
+      Ok(vec![TagType::Ape])
+    }, |tag| keep_only(tag, |i| !is_mp3gain(i)))?;
+    if res.outcome.no_op { fields.clear(); }
+    for (k, v) in &fields { audit::record(&path, k, Some(v), None, audit::Source::Manual); }
+    res.removed = fields.into_iter().map(|(k, _)| k).collect();
+    log_line(&format!("remove_mp3gain_undo path=\"{}\" removed={}", path, res.removed.join(",")));
+    Ok(res)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{edit_tags, field_locks::{self, LockedField}, inspect, read_comment, test_support};
+
+  fn ape_mp3(name: &str, ape: &[(ItemKey, &str)]) -> std::path::PathBuf {
+    let dir = test_support::scratch("ape");
+    let p = test_support::tagged(&dir, name, &[(ItemKey::TrackTitle, "ID3 title")]);
+    test_support::add_tag(&p, TagType::Ape, ape);
+    p
+  }
+
+  #[test]
+  fn migration_fills_id3_gaps_and_keeps_mp3gain_in_ape() {
+    let p = ape_mp3("gain.mp3", &[
+      (ItemKey::TrackTitle, "APE title"),
+      (ItemKey::Comment, "#from-ape"),
+      (ItemKey::Unknown("MP3GAIN_UNDO".into()), "+003,+003,N"),
+    ]);
+    let res = convert_ape_to_id3(p.to_string_lossy().to_string()).unwrap();
+    assert_eq!((res.copied_items, res.kept_mp3gain.clone()), (1, vec!["MP3GAIN_UNDO".to_string()]));
+    assert!(res.outcome.revision > 0, "tracked like any edit");
+    let tf = read_tagged(&p).unwrap();
+    let id3 = tf.tag(TagType::Id3v2).unwrap();
+    assert_eq!(id3.get_string(&ItemKey::TrackTitle), Some("ID3 title"));
+    assert_eq!(id3.get_string(&ItemKey::Comment), Some("#from-ape"));
+    assert_eq!(mp3gain_fields(&tf), vec![("MP3GAIN_UNDO".to_string(), "+003,+003,N".to_string())]);
+    assert_eq!(tf.tag(TagType::Ape).unwrap().item_count(), 1);
+  }
+
+  #[test]
+  fn migration_strips_an_ape_block_left_empty() {
+    let p = ape_mp3("plain.mp3", &[(ItemKey::Comment, "#from-ape")]);
+    convert_ape_to_id3(p.to_string_lossy().to_string()).unwrap();
+    let tf = read_tagged(&p).unwrap();
+    assert!(tf.tag(TagType::Ape).is_none());
+    assert_eq!(tf.tag(TagType::Id3v2).unwrap().get_string(&ItemKey::Comment), Some("#from-ape"));
+  }
+
+  #[test]
+  fn migration_respects_locks() {
+    let p = ape_mp3("locked.mp3", &[(ItemKey::Comment, "#from-ape")]);
+    field_locks::set(&p, &[LockedField::Comment], true).unwrap();
+    let res = convert_ape_to_id3(p.to_string_lossy().to_string()).unwrap();
+    assert_eq!(res.outcome.skipped_locked, vec![LockedField::Comment]);
+    let tf = read_tagged(&p).unwrap();
+    assert_eq!(tf.tag(TagType::Id3v2).unwrap().get_string(&ItemKey::Comment), None);
+    assert_eq!(tf.tag(TagType::Ape).unwrap().get_string(&ItemKey::Comment), Some("#from-ape"));
+  }
+
+  /// Fixtures: APE only, APE beside ID3v2 (with and without a comment), APE beside ID3v1.
+  fn combinations(dir: &Path) -> [(std::path::PathBuf, &'static str); 4] {
+    let only = test_support::audio(dir, "only.mp3");
+    let both = test_support::tagged(dir, "both.mp3", &[(ItemKey::Comment, "#id3")]);
+    let gap = test_support::tagged(dir, "gap.mp3", &[(ItemKey::TrackTitle, "T")]);
+    let v1 = test_support::audio(dir, "v1.mp3");
+    test_support::add_tag(&v1, TagType::Id3v1, &[(ItemKey::Comment, "#v1")]);
+    for p in [&only, &both, &gap, &v1] { test_support::add_tag(p, TagType::Ape, &[(ItemKey::Comment, "#ape")]); }
+    [(only, "#ape"), (both, "#id3"), (gap, "#ape"), (v1, "#ape")]
+  }
+
+  #[test]
+  fn mp3s_read_ape_after_id3v2_and_before_id3v1() {
+    let dir = test_support::scratch("ape-read");
+    for (p, want) in combinations(&dir) {
+      assert_eq!(read_comment(&read_tagged(&p).unwrap(), &p), want, "{}", p.display());
+      let report = serde_json::to_value(inspect::inspect_tags(p.to_string_lossy().to_string()).unwrap()).unwrap();
+      assert_eq!(report["hasApe"], true);
+    }
+    let plain = test_support::tagged(&dir, "plain.mp3", &[(ItemKey::Comment, "#id3")]);
+    assert_eq!(serde_json::to_value(inspect::inspect_tags(plain.to_string_lossy().to_string()).unwrap()).unwrap()["hasApe"], false);
+    assert!(crate::supported_ext(Path::new("a.mpc")));
+    assert_eq!(crate::tag_types_for_ext("mpc"), [TagType::Ape]);
+  }
+
+  #[test]
+  fn writes_update_an_ape_only_mp3_and_never_add_ape() {
+    let dir = test_support::scratch("ape-write");
+    let [(only, _), (both, _), ..] = combinations(&dir);
+    let plain = test_support::tagged(&dir, "plain.mp3", &[(ItemKey::TrackTitle, "T")]);
+    for p in [&only, &both, &plain] { edit_tags(p, |t| { t.insert_text(ItemKey::Comment, "#new".into()); }).unwrap(); }
+
+    assert_eq!(test_support::text(&only, TagType::Ape, &ItemKey::Comment).as_deref(), Some("#new"));
+    assert!(read_tagged(&only).unwrap().tag(TagType::Id3v2).is_none(), "no ID3v2 added beside the APE tag");
+    assert_eq!(test_support::text(&both, TagType::Id3v2, &ItemKey::Comment).as_deref(), Some("#new"));
+    assert_eq!(test_support::text(&both, TagType::Ape, &ItemKey::Comment).as_deref(), Some("#ape"));
+    assert!(read_tagged(&plain).unwrap().tag(TagType::Ape).is_none());
+  }
+}
@@ -0,0 +1,152 @@
+// Opt-in JSON API on the media server for external tools (scripts, DJ
+// software plugins). Off unless `apiEnabled` is set; every request needs the
+// per-session token in `X-Api-Token`. Handlers call the same functions as the
+// Tauri commands, so writes go through WRITE_LOCK and the audit log like any
+// other edit.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::hash::{BuildHasher, Hasher};
+use hyper::{Body, Method, Request, Response, StatusCode, header};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{audit, error::CmdError, list_folder, log_line, read_metadata, tag_ops, urls, write_comment_as, AppState, MediaBase};
+
+pub const TOKEN_HEADER: &str = "x-api-token";
+const MAX_BODY: u64 = 1 << 20;
+
+pub static API_ENABLED: AtomicBool = AtomicBool::new(false);
+
+// New every launch; std's RandomState is seeded from the OS RNG.
+static TOKEN: Lazy<String> = Lazy::new(|| {
+  let mut h = Sha256::new();
+  for _ in 0..4 {
+    h.update(std::collections::hash_map::RandomState::new().build_hasher().finish().to_le_bytes());
+  }
+  h.update(format!("{:?}{}", std::time::SystemTime::now(), std::process::id()));
+  h.finalize()[..16].iter().map(|b| format!("{:02x}", b)).collect()
+});
+
+#[derive(Deserialize)]
+struct CommentBody { path: String, comment: String }
+
+#[derive(Deserialize)]
+struct AddTagsBody { path: String, tags: Vec<String> }
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "application/json")
+    .header(header::CACHE_CONTROL, "no-store")
+    .body(Body::from(serde_json::to_vec(body).unwrap_or_default()))
+    .unwrap()
+}
+
+fn error_response(status: StatusCode, e: CmdError) -> Response<Body> {
+  json_response(status, &e)
+}
+
+pub fn token() -> &'static str { &TOKEN }
+
+/// `sent` is this session's token.
+pub fn token_matches(sent: &str) -> bool {
+  let want = TOKEN.as_bytes();
+  // No early exit on the first differing byte.
+  sent.len() == want.len() && sent.bytes().zip(want).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn token_ok(req: &Request<Body>) -> bool {
+  req.headers().get(TOKEN_HEADER).and_then(|v| v.to_str().ok()).is_some_and(token_matches)
+}
+
+async fn read_json<T: DeserializeOwned>(req: Request<Body>) -> Result<T, CmdError> {
+  let declared = req.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
+  if declared.is_some_and(|n| n > MAX_BODY) { return Err("request body too large".to_string().into()); }
+  let bytes = hyper::body::to_bytes(req.into_body()).await.map_err(|e| CmdError::from(e.to_string()))?;
+  if bytes.len() as u64 > MAX_BODY { return Err("request body too large".to_string().into()); }
+  serde_json::from_slice(&bytes).map_err(|e| CmdError::from(format!("invalid JSON body: {}", e)))
+}
+
+/// Run a blocking handler off the server's reactor and wrap its result.
+async fn blocking<T, F>(f: F) -> Response<Body>
+where
+  T: Serialize + Send + 'static,
+  F: FnOnce() -> Result<T, CmdError> + Send + 'static,
+{
+  match tauri::async_runtime::spawn_blocking(f).await {
+    Ok(Ok(v)) => json_response(StatusCode::OK, &v),
+    Ok(Err(e)) => error_response(StatusCode::UNPROCESSABLE_ENTITY, e),
+    Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string().into()),
+  }
+}
+
+/// Entry point for `/api/*` on the media server.
+pub async fn handle(req: Request<Body>) -> Response<Body> {
+  // Disabled looks the same as an unknown route.
+  if !API_ENABLED.load(Ordering::Relaxed) {
+    return error_response(StatusCode::NOT_FOUND, "not found".to_string().into());
+  }
+  // Browser pages get no access from any origin, token or not.
+  if req.headers().contains_key(header::ORIGIN) {
+    return error_response(StatusCode::FORBIDDEN, "cross-origin requests are not allowed".to_string().into());
+  }
+  if !token_ok(&req) {
+    return error_response(StatusCode::UNAUTHORIZED, "missing or invalid X-Api-Token".to_string().into());
+  }
+
+  let route = (req.method().clone(), req.uri().path().to_string());
+  let bad_request = |msg: &str| error_response(StatusCode::BAD_REQUEST, msg.to_string().into());
+  match (&route.0, route.1.as_str()) {
+    (&Method::GET, "/api/tracks") => {
+      let Some(folder) = urls::query_param(req.uri(), "folder") else { return bad_request("missing ?folder=") };
+      blocking(move || list_folder(folder, false)).await
+    }
+    (&Method::GET, "/api/meta") => {
+      let Some(path) = urls::query_param(req.uri(), "path") else { return bad_request("missing ?path=") };
+      blocking(move || read_metadata(path)).await
+    }
+    (&Method::POST, "/api/comment") => {
+      let body: CommentBody = match read_json(req).await { Ok(b) => b, Err(e) => return error_response(StatusCode::BAD_REQUEST, e) };
+      blocking(move || {
+        write_comment_as(&body.path, &body.comment, audit::Source::Api)?;
+        log_line(&format!("api write_comment path=\"{}\"", body.path));
+        Ok(serde_json::json!({ "path": body.path, "comment": body.comment }))
+      })
+      .await
+    }
+    (&Method::POST, "/api/tags/add") => {
+      let body: AddTagsBody = match read_json(req).await { Ok(b) => b, Err(e) => return error_response(StatusCode::BAD_REQUEST, e) };
+      blocking(move || {
+        let out = tag_ops::merge_file_tags(&body.path, &body.tags, &[], audit::Source::Api)?;
+        if out.changed { log_line(&format!("api add_tags path=\"{}\" -> \"{}\"", body.path, out.new_comment)); }
+        Ok(out)
+      })
+      .await
+    }
+    (_, "/api/tracks" | "/api/meta" | "/api/comment" | "/api/tags/add") => {
+      error_response(StatusCode::METHOD_NOT_ALLOWED, "method not allowed".to_string().into())
+    }
+    _ => error_response(StatusCode::NOT_FOUND, "not found".to_string().into()),
+  }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiInfo {
+  enabled: bool,
+  /// `None` until the media server is up.
+  base_url: Option<String>,
+  token: String,
+  header_name: &'static str,
+}
+
+/// Connection details for the settings screen. The token changes every launch.
+#[tauri::command]
+pub fn get_api_info(state: tauri::State<'_, AppState>) -> ApiInfo {
+  let base_url = match &*state.media_base.read() {
+    MediaBase::Ready(base) => Some(format!("{}/api", base)),
+    _ => None,
+  };
+  ApiInfo { enabled: API_ENABLED.load(Ordering::Relaxed), base_url, token: TOKEN.clone(), header_name: "X-Api-Token" }
+}
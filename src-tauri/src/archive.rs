@@ -0,0 +1,280 @@
+// Verified (read-only) archive folders. `open_folder_verified` snapshots a
+// quick hash of every audio file (size + first and last MiB) under the data
+// dir `verified/`, and from then on every write under that root is refused.
+// `verify_folder_unchanged` re-hashes and lists what differs, as evidence that
+// a session left the audio alone. Closing the folder drops the snapshot.
+
+use std::{
+  collections::BTreeMap,
+  fs,
+  io::{Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{command_span, data_dir, error::CmdError, jobs::JobHandle, library::audio_files, log_line, profile, write_atomic};
+
+const EDGE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileHash {
+  pub size: u64,
+  pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+  folder: String,
+  created_at: String,
+  /// Keyed by path relative to `folder`, `/`-separated.
+  files: BTreeMap<String, FileHash>,
+}
+
+/// Canonical roots that currently refuse writes. Loaded from the snapshot dir
+/// on first use so the protection survives a restart.
+static ROOTS: Lazy<RwLock<Vec<PathBuf>>> = Lazy::new(|| {
+  let roots = fs::read_dir(snapshots_dir()).into_iter().flatten().flatten()
+    .filter_map(|e| fs::read_to_string(e.path()).ok())
+    .filter_map(|j| serde_json::from_str::<Snapshot>(&j).ok())
+    .map(|s| PathBuf::from(s.folder))
+    .collect();
+  RwLock::new(roots)
+});
+
+fn snapshots_dir() -> PathBuf { data_dir().join("verified") }
+
+fn canonical(p: &Path) -> PathBuf { fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()) }
+
+fn snapshot_path(root: &Path) -> PathBuf {
+  let digest = Sha256::digest(root.to_string_lossy().as_bytes());
+  let id: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+  snapshots_dir().join(format!("{}.json", id))
+}
+
+fn load_snapshot(root: &Path) -> Option<Snapshot> {
+  fs::read_to_string(snapshot_path(root)).ok().and_then(|j| serde_json::from_str(&j).ok())
+}
+
+/// The verified root containing `p`, if any. Paths that don't exist yet
+/// (rename targets) are resolved through their parent.
+pub fn verified_root(p: &Path) -> Option<PathBuf> {
+  let roots = ROOTS.read();
+  if roots.is_empty() { return None; }
+  let resolved = if p.exists() { canonical(p) } else {
+    match (p.parent(), p.file_name()) {
+      (Some(dir), Some(name)) => canonical(dir).join(name),
+      _ => p.to_path_buf(),
+    }
+  };
+  roots.iter().find(|r| resolved.starts_with(r)).cloned()
+}
+
+/// Refuse writes under a verified root. Every tag save and file move goes
+/// through here.
+pub fn guard(p: &Path) -> Result<(), CmdError> {
+  match verified_root(p) {
+    Some(root) => Err(CmdError::ReadOnly {
+      path: p.to_string_lossy().to_string(),
+      root: root.to_string_lossy().to_string(),
+      message: format!("{} is in a verified archive folder ({}); writes are disabled.", p.display(), root.display()),
+    }),
+    None => Ok(()),
+  }
+}
+
+/// Size plus SHA-256 over the first and last MiB.
+fn quick_hash(p: &Path) -> Result<FileHash, String> {
+  let mut f = fs::File::open(p).map_err(|e| e.to_string())?;
+  let size = f.metadata().map_err(|e| e.to_string())?.len();
+  let mut h = Sha256::new();
+  h.update(size.to_le_bytes());
+  let mut buf = Vec::with_capacity(EDGE as usize);
+  (&mut f).take(EDGE).read_to_end(&mut buf).map_err(|e| e.to_string())?;
+  h.update(&buf);
+  if size > EDGE {
+    f.seek(SeekFrom::Start(EDGE.max(size - EDGE))).map_err(|e| e.to_string())?;
+    buf.clear();
+    f.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    h.update(&buf);
+  }
+  Ok(FileHash { size, hash: h.finalize()[..16].iter().map(|b| format!("{:02x}", b)).collect() })
+}
+
+/// Hash `files` on a few threads; `None` for files skipped by cancellation.
+fn hash_all(files: &[PathBuf], job: &JobHandle) -> Vec<Option<Result<FileHash, String>>> {
+  let total = files.len();
+  let next = AtomicUsize::new(0);
+  let done = AtomicUsize::new(0);
+  let workers = profile::workers();
+  let mut out: Vec<Option<Result<FileHash, String>>> = vec![None; total];
+  let parts: Vec<Vec<(usize, Result<FileHash, String>)>> = std::thread::scope(|s| {
+    let handles: Vec<_> = (0..workers)
+      .map(|_| s.spawn(|| {
+        let mut mine = Vec::new();
+        loop {
+          let i = next.fetch_add(1, Ordering::Relaxed);
+          if i >= total || job.is_cancelled() { break; }
+          mine.push((i, quick_hash(&files[i])));
+          job.progress(done.fetch_add(1, Ordering::Relaxed) as u64 + 1, total as u64);
+        }
+        mine
+      }))
+      .collect();
+    handles.into_iter().map(|h| h.join().unwrap_or_default()).collect()
+  });
+  for (i, r) in parts.into_iter().flatten() { out[i] = Some(r); }
+  out
+}
+
+fn rel_key(root: &Path, p: &Path) -> String {
+  p.strip_prefix(root).unwrap_or(p).components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileError {
+  path: String,
+  error: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifiedOpen {
+  folder: String,
+  created_at: String,
+  files: usize,
+  /// Files that couldn't be read; they are left out of the snapshot.
+  errors: Vec<FileError>,
+  /// An earlier snapshot was kept rather than replaced.
+  existing: bool,
+  cancelled: bool,
+}
+
+/// Snapshot `path` and make it read-only. Opening an already verified folder
+/// keeps the original snapshot, so a re-open can't hide changes.
+#[tauri::command]
+pub async fn open_folder_verified(app: tauri::AppHandle, path: String) -> Result<VerifiedOpen, String> {
+  let _span = command_span("open_folder_verified");
+  tauri::async_runtime::spawn_blocking(move || {
+    let root = canonical(Path::new(&path));
+    if let Some(s) = load_snapshot(&root) {
+      let mut roots = ROOTS.write();
+      if !roots.contains(&root) { roots.push(root); }
+      return Ok(VerifiedOpen { folder: s.folder, created_at: s.created_at, files: s.files.len(), errors: vec![], existing: true, cancelled: false });
+    }
+    let job = JobHandle::start(&app, "archive-snapshot", &path);
+    let res = (|| {
+      job.begin_phase("scan", 0);
+      let files = audio_files(&root, true).map_err(|e| e.to_string())?;
+      job.begin_phase("hash", files.len() as u64);
+      let hashes = hash_all(&files, &job);
+      if job.is_cancelled() {
+        return Ok(VerifiedOpen { folder: root.to_string_lossy().to_string(), created_at: String::new(), files: 0, errors: vec![], existing: false, cancelled: true });
+      }
+      let mut snap = Snapshot { folder: root.to_string_lossy().to_string(), created_at: Local::now().to_rfc3339(), files: BTreeMap::new() };
+      let mut errors = Vec::new();
+      for (p, h) in files.iter().zip(hashes) {
+        match h {
+          Some(Ok(h)) => { snap.files.insert(rel_key(&root, p), h); }
+          Some(Err(error)) => errors.push(FileError { path: p.to_string_lossy().to_string(), error }),
+          None => {}
+        }
+      }
+      fs::create_dir_all(snapshots_dir()).map_err(|e| e.to_string())?;
+      write_atomic(&snapshot_path(&root), &serde_json::to_vec(&snap).map_err(|e| e.to_string())?)?;
+      ROOTS.write().push(root.clone());
+      log_line(&format!("open_folder_verified folder=\"{}\" files={} errors={}", snap.folder, snap.files.len(), errors.len()));
+      Ok(VerifiedOpen { folder: snap.folder, created_at: snap.created_at, files: snap.files.len(), errors, existing: false, cancelled: false })
+    })();
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashChange {
+  path: String,
+  /// `None`: file is new since the snapshot.
+  before: Option<FileHash>,
+  /// `None`: file is gone (or unreadable, see `error`).
+  after: Option<FileHash>,
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyReport {
+  folder: String,
+  snapshot_at: String,
+  checked: usize,
+  changed: Vec<HashChange>,
+  cancelled: bool,
+}
+
+/// Re-hash a verified folder and report every file that differs from its snapshot.
+#[tauri::command]
+pub async fn verify_folder_unchanged(app: tauri::AppHandle, path: String) -> Result<VerifyReport, String> {
+  let _span = command_span("verify_folder_unchanged");
+  tauri::async_runtime::spawn_blocking(move || {
+    let root = canonical(Path::new(&path));
+    let snap = load_snapshot(&root).ok_or_else(|| format!("{} was not opened in verified mode", root.display()))?;
+    let job = JobHandle::start(&app, "archive-verify", &path);
+    let res = (|| {
+      job.begin_phase("scan", 0);
+      let files = audio_files(&root, true).map_err(|e| e.to_string())?;
+      job.begin_phase("hash", files.len() as u64);
+      let hashes = hash_all(&files, &job);
+      let cancelled = job.is_cancelled();
+      let mut expected = snap.files.clone();
+      let mut changed = Vec::new();
+      let mut checked = 0;
+      for (p, h) in files.iter().zip(hashes) {
+        let Some(h) = h else { continue };
+        checked += 1;
+        let key = rel_key(&root, p);
+        let before = expected.remove(&key);
+        let change = |after: Option<FileHash>, error: Option<String>| HashChange { path: p.to_string_lossy().to_string(), before: before.clone(), after, error };
+        match h {
+          Ok(after) if before.as_ref() == Some(&after) => {}
+          Ok(after) => changed.push(change(Some(after), None)),
+          Err(e) => changed.push(change(None, Some(e))),
+        }
+      }
+      // Only a complete pass can say a file is missing.
+      if !cancelled {
+        for (key, before) in expected {
+          changed.push(HashChange { path: root.join(&key).to_string_lossy().to_string(), before: Some(before), after: None, error: None });
+        }
+      }
+      log_line(&format!("verify_folder_unchanged folder=\"{}\" checked={} changed={} cancelled={}", snap.folder, checked, changed.len(), cancelled));
+      Ok(VerifyReport { folder: snap.folder.clone(), snapshot_at: snap.created_at.clone(), checked, changed, cancelled })
+    })();
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Leave verified mode: drop the snapshot and allow writes again.
+#[tauri::command]
+pub fn close_folder_verified(path: String) -> Result<bool, String> {
+  let root = canonical(Path::new(&path));
+  let existed = ROOTS.read().contains(&root);
+  ROOTS.write().retain(|r| r != &root);
+  match fs::remove_file(snapshot_path(&root)) {
+    Ok(()) => {}
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+    Err(e) => return Err(e.to_string()),
+  }
+  Ok(existed)
+}
@@ -0,0 +1,269 @@
+// Front covers from the images that sit in album folders (cover.jpg,
+// folder.png, ...). `suggest_artwork` lists and ranks them; the writer
+// (`prepare` + `embed`) downsizes anything over ARTWORK_MAX_PX and converts
+// non-JPEG/PNG images to JPEG, since players don't read WebP covers. JPEGs and
+// PNGs that already fit are embedded byte for byte.
+//
+// Files whose front cover already is the image aren't rewritten (`no_op`):
+// the same bytes, or, when only the container differs (EXIF, another
+// encoder's headers) at the same dimensions, the same pixels at PRINT_PX.
+
+use std::{fs, io::{BufReader, Cursor}, path::{Path, PathBuf}};
+use base64::{engine::general_purpose, Engine as _};
+use lofty::{MimeType, Picture, PictureType};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{audit, command_span, edit_tags, error::CmdError, field_locks::{self, LockedField}, front_cover, jobs::JobHandle, log_line, preflight::{self, Preflight}, read_tagged, snapshots};
+
+pub const ARTWORK_MAX_PX: u32 = 1400;
+const JPEG_QUALITY: u8 = 90;
+const THUMB_PX: u32 = 160;
+/// Side of the downscale whose pixels are compared when the bytes differ.
+const PRINT_PX: u32 = 32;
+const IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+/// Stems that name a cover outright; other names containing them rank lower.
+const ART_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkCandidate {
+  path: String,
+  file_name: String,
+  width: u32,
+  height: u32,
+  bytes: u64,
+  /// Stem is exactly one of the usual names ("cover", "folder", ...).
+  exact_name: bool,
+  /// Small JPEG preview as a data URL.
+  thumbnail: Option<String>,
+}
+
+fn image_ext(p: &Path) -> Option<String> {
+  let ext = p.extension()?.to_string_lossy().to_ascii_lowercase();
+  IMAGE_EXTS.contains(&ext.as_str()).then_some(ext)
+}
+
+fn dimensions(p: &Path) -> Option<(u32, u32)> {
+  let f = fs::File::open(p).ok()?;
+  image::io::Reader::new(BufReader::new(f)).with_guessed_format().ok()?.into_dimensions().ok()
+}
+
+/// JPEG preview of an image at most `px` on its longer side, as a data URL.
+pub fn thumbnail(bytes: &[u8], px: u32) -> Option<String> {
+  let img = image::load_from_memory(bytes).ok()?;
+  let mut out = Cursor::new(Vec::new());
+  img.thumbnail(px, px).write_to(&mut out, image::ImageOutputFormat::Jpeg(80)).ok()?;
+  Some(format!("data:image/jpeg;base64,{}", general_purpose::STANDARD.encode(out.into_inner())))
+}
+
+/// Exact names first, then square images (within 5%), then the larger.
+fn rank_key(c: &ArtworkCandidate) -> (bool, bool, u32) {
+  let (w, h) = (c.width.max(1) as f64, c.height.max(1) as f64);
+  let square = (w / h - 1.0).abs() <= 0.05;
+  (c.exact_name, square, c.width.min(c.height))
+}
+
+/// Cover-like images next to `path` (a file, or a folder itself), best first.
+#[tauri::command]
+pub async fn suggest_artwork(path: String) -> Result<Vec<ArtworkCandidate>, String> {
+  let _span = command_span("suggest_artwork");
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = PathBuf::from(&path);
+    let dir = if p.is_dir() { p } else { p.parent().map(Path::to_path_buf).ok_or("path has no folder")? };
+    let mut out = Vec::new();
+    for e in fs::read_dir(&dir).map_err(|e| e.to_string())?.flatten() {
+      let ip = e.path();
+      if !ip.is_file() || image_ext(&ip).is_none() { continue; }
+      let stem = ip.file_stem().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+      let exact_name = ART_NAMES.contains(&stem.as_str());
+      if !exact_name && !ART_NAMES.iter().any(|n| stem.contains(n)) { continue; }
+      let Some((width, height)) = dimensions(&ip) else { continue };
+      let bytes = fs::read(&ip).map_err(|e| e.to_string())?;
+      out.push(ArtworkCandidate {
+        path: ip.to_string_lossy().to_string(),
+        file_name: ip.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        width,
+        height,
+        bytes: bytes.len() as u64,
+        exact_name,
+        thumbnail: thumbnail(&bytes, THUMB_PX),
+      });
+    }
+    out.sort_by(|a, b| rank_key(b).cmp(&rank_key(a)).then_with(|| a.file_name.cmp(&b.file_name)));
+    Ok(out)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Image bytes ready to embed: `(bytes, mime, width, height)`.
+pub fn prepare(image_path: &Path, max_px: u32) -> Result<(Vec<u8>, MimeType, u32, u32), String> {
+  let raw = fs::read(image_path).map_err(|e| e.to_string())?;
+  let format = image::guess_format(&raw).map_err(|e| format!("not an image: {}", e))?;
+  let img = image::load_from_memory(&raw).map_err(|e| format!("image decode failed: {}", e))?;
+  let fits = img.width() <= max_px && img.height() <= max_px;
+  match format {
+    image::ImageFormat::Jpeg if fits => return Ok((raw, MimeType::Jpeg, img.width(), img.height())),
+    image::ImageFormat::Png if fits => return Ok((raw, MimeType::Png, img.width(), img.height())),
+    _ => {}
+  }
+  let img = if fits { img } else { img.thumbnail(max_px, max_px) };
+  let mut out = Cursor::new(Vec::new());
+  img.write_to(&mut out, image::ImageOutputFormat::Jpeg(JPEG_QUALITY)).map_err(|e| format!("image encode failed: {}", e))?;
+  Ok((out.into_inner(), MimeType::Jpeg, img.width(), img.height()))
+}
+
+/// What an image is compared by: its bytes, its size, and its pixels at PRINT_PX.
+struct CoverPrint {
+  bytes: [u8; 32],
+  width: u32,
+  height: u32,
+  pixels: Option<[u8; 32]>,
+}
+
+fn pixel_hash(img: &image::DynamicImage) -> [u8; 32] {
+  Sha256::digest(img.resize_exact(PRINT_PX, PRINT_PX, image::imageops::FilterType::Triangle).to_rgb8().as_raw()).into()
+}
+
+impl CoverPrint {
+  fn of(bytes: &[u8], width: u32, height: u32) -> Self {
+    let pixels = image::load_from_memory(bytes).ok().map(|img| pixel_hash(&img));
+    CoverPrint { bytes: Sha256::digest(bytes).into(), width, height, pixels }
+  }
+
+  /// `other` is this image; it's only decoded when the bytes differ but the dimensions match.
+  fn same_as(&self, other: &[u8]) -> bool {
+    if <[u8; 32]>::from(Sha256::digest(other)) == self.bytes { return true; }
+    let Some(want) = self.pixels else { return false };
+    let dims = image::io::Reader::new(Cursor::new(other)).with_guessed_format().ok().and_then(|r| r.into_dimensions().ok());
+    if dims != Some((self.width, self.height)) { return false; }
+    image::load_from_memory(other).is_ok_and(|img| pixel_hash(&img) == want)
+  }
+}
+
+/// Replace the front cover of `p` with `pic`. Returns `[artwork]` when it's locked.
+pub fn embed(p: &Path, pic: &Picture) -> Result<Vec<LockedField>, String> {
+  edit_tags(p, |tag| {
+    tag.remove_picture_type(PictureType::CoverFront);
+    tag.push_picture(pic.clone());
+  })
+  .map(|o| o.skipped_locked)
+  .map_err(String::from)
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ArtworkOptions {
+  /// Replace covers files already have; otherwise they are skipped.
+  overwrite: bool,
+  /// Longest side of the embedded image (default ARTWORK_MAX_PX).
+  max_px: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkResult {
+  path: String,
+  /// Had a cover and `overwrite` was off.
+  skipped: bool,
+  /// Already had this exact cover; not rewritten.
+  no_op: bool,
+  applied: bool,
+  error: Option<String>,
+  /// `["artwork"]` when it's locked (see `field_locks`); nothing was embedded.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtworkReport {
+  results: Vec<ArtworkResult>,
+  /// Files embedded (or, in a dry run, that would be).
+  written: usize,
+  /// Files that already had the cover.
+  identical: usize,
+  failed: usize,
+  /// What gets embedded after resizing/conversion.
+  width: u32,
+  height: u32,
+  bytes: u64,
+  dry_run: bool,
+  cancelled: bool,
+  snapshot_id: Option<String>,
+  /// Space and permission check run before writing (see `preflight`).
+  preflight: Preflight,
+}
+
+fn apply_one(path: &str, pic: &Picture, print: &CoverPrint, label: &str, opts: ArtworkOptions, dry_run: bool) -> ArtworkResult {
+  let p = Path::new(path);
+  let mut r = ArtworkResult { path: path.to_string(), skipped: false, no_op: false, applied: false, error: None, skipped_locked: Vec::new() };
+  let (had_cover, same) = match read_tagged(p) {
+    Ok(tf) => { let cover = front_cover(&tf); (cover.is_some(), cover.is_some_and(|c| print.same_as(c.data()))) }
+    Err(e) => { r.error = Some(e.to_string()); return r; }
+  };
+  if same { r.no_op = true; return r; }
+  if had_cover && !opts.overwrite { r.skipped = true; return r; }
+  if dry_run { r.skipped_locked = field_locks::hits(p, &[LockedField::Artwork]); return r; }
+  match embed(p, pic) {
+    Ok(locked) if !locked.is_empty() => r.skipped_locked = locked,
+    Ok(_) => {
+      audit::record(path, "artwork", had_cover.then_some("embedded"), Some(label), audit::Source::Batch);
+      r.applied = true;
+    }
+    Err(e) => r.error = Some(e),
+  }
+  r
+}
+
+fn apply_blocking(app: &tauri::AppHandle, job: &JobHandle, paths: &[String], image_path: &str, opts: ArtworkOptions, dry_run: bool) -> Result<ArtworkReport, CmdError> {
+  let (bytes, mime, width, height) = prepare(Path::new(image_path), opts.max_px.unwrap_or(ARTWORK_MAX_PX).max(16))?;
+  let size = bytes.len() as u64;
+  let print = CoverPrint::of(&bytes, width, height);
+  let pic = Picture::new_unchecked(PictureType::CoverFront, Some(mime), None, bytes);
+  let label = Path::new(image_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(paths));
+  if !dry_run { preflight::ensure(&preflight)?; }
+  let snapshot_id = if dry_run { None } else { snapshots::before_batch(app, "apply_folder_artwork", paths) };
+  let mut results = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("write", paths.len() as u64);
+  for (i, path) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    results.push(apply_one(path, &pic, &print, &label, opts, dry_run));
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  let would = |r: &ArtworkResult| r.applied || (dry_run && !r.skipped && !r.no_op && r.error.is_none() && r.skipped_locked.is_empty());
+  let written = results.iter().filter(|r| would(r)).count();
+  let identical = results.iter().filter(|r| r.no_op).count();
+  let failed = results.iter().filter(|r| r.error.is_some()).count();
+  if !dry_run {
+    log_line(&format!("apply_folder_artwork image=\"{}\" files={} applied={} identical={} failed={}", image_path, results.len(), written, identical, failed));
+  }
+  Ok(ArtworkReport { results, written, identical, failed, width, height, bytes: size, dry_run, cancelled, snapshot_id, preflight })
+}
+
+/// Embed `image_path` as the front cover of `paths`; job kind "artwork".
+/// `dry_run` reports which files would change and what would be embedded.
+#[tauri::command]
+pub async fn apply_folder_artwork(
+  app: tauri::AppHandle,
+  paths: Vec<String>,
+  image_path: String,
+  dry_run: bool,
+  options: Option<ArtworkOptions>,
+) -> Result<ArtworkReport, CmdError> {
+  let _span = command_span("apply_folder_artwork");
+  let opts = options.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "artwork", &image_path);
+    let res = apply_blocking(&app, &job, &paths, &image_path, opts, dry_run);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,95 @@
+// Append-only audit trail of metadata changes (data dir `audit.jsonl`, one
+// JSON object per line). Unlike the session log this is meant to be
+// machine-read: every field change records old/new and who caused it.
+// Entries carry the id of the app session that wrote them, and comment
+// writes also the value as saved, for `verify_session_writes`.
+
+use std::{fs, io::{BufRead, BufReader, Write}, path::{Path, PathBuf}};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{data_dir, log, LogLevel, meta_cache, shadow};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+  Manual,
+  Batch,
+  Rule,
+  Api,
+  Cli,
+  /// A change another program made, accepted as the new expected value.
+  External,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+  pub timestamp: String,
+  pub path: String,
+  pub field: String,
+  pub old: Option<String>,
+  pub new: Option<String>,
+  pub source: Source,
+  /// Comment as read back from the saved tags, when it could be.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub written: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub session: Option<String>,
+}
+
+static AUDIT_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+static SESSION: Lazy<String> = Lazy::new(|| format!("{}-{}", Local::now().format("%Y%m%dT%H%M%S"), std::process::id()));
+
+/// Held while the log file is rewritten (see `audit_export::prune_audit_log`).
+pub fn lock() -> parking_lot::MutexGuard<'static, ()> { AUDIT_LOCK.lock() }
+
+/// This process's session id, as stamped on its entries.
+pub fn session_id() -> &'static str { &SESSION }
+
+pub fn audit_path() -> PathBuf { data_dir().join("audit.jsonl") }
+
+/// Record one field change. Failures only hit the session log; the write
+/// itself already happened and must not be reported as failed.
+pub fn record(path: &str, field: &str, old: Option<&str>, new: Option<&str>, source: Source) {
+  record_written(path, field, old, new, None, source);
+}
+
+/// `record` for a comment that was just saved; the value as written comes
+/// from the metadata cache the save refreshed.
+pub fn record_comment(path: &str, old: Option<&str>, new: Option<&str>, source: Source) {
+  let written = meta_cache::get(Path::new(path)).ok().map(|m| m.comment);
+  record_written(path, "comment", old, new, written, source);
+}
+
+fn record_written(path: &str, field: &str, old: Option<&str>, new: Option<&str>, written: Option<String>, source: Source) {
+  // Shadow writes leave the file at `path` alone.
+  if old == new || shadow::active() { return; }
+  let entry = AuditEntry {
+    timestamp: Local::now().to_rfc3339(),
+    path: path.to_string(),
+    field: field.to_string(),
+    old: old.map(|s| s.to_string()),
+    new: new.map(|s| s.to_string()),
+    source,
+    written,
+    session: Some(SESSION.clone()),
+  };
+  let _guard = AUDIT_LOCK.lock();
+  let res = serde_json::to_string(&entry).map_err(|e| e.to_string()).and_then(|line| {
+    let p = audit_path();
+    if let Some(dir) = p.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+    let mut f = fs::OpenOptions::new().create(true).append(true).open(&p).map_err(|e| e.to_string())?;
+    writeln!(f, "{}", line).map_err(|e| e.to_string())
+  });
+  if let Err(e) = res { log(LogLevel::Warn, &format!("audit write failed: {}", e)); }
+}
+
+/// Every readable entry, oldest first; malformed lines are skipped.
+pub fn read_entries() -> Vec<AuditEntry> {
+  let _guard = AUDIT_LOCK.lock();
+  let Ok(f) = fs::File::open(audit_path()) else { return Vec::new() };
+  BufReader::new(f).lines().map_while(Result::ok).filter_map(|l| serde_json::from_str(&l).ok()).collect()
+}
@@ -0,0 +1,220 @@
+// Audit log export for external review, and pruning. Both stream
+// `audit.jsonl` line by line, so a log of millions of entries never sits in
+// memory. The export schema is the AUDIT_COLUMNS row (or object) per entry
+// followed by a summary; bump AUDIT_EXPORT_SCHEMA_VERSION on any breaking
+// change. In CSV the summary follows the entries after one blank line, as
+// `metric,value` rows.
+
+use std::{collections::{BTreeMap, HashSet}, fs, io::{BufRead, BufReader, BufWriter, Write}, path::PathBuf};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit::{self, AuditEntry, Source}, command_span, data_dir, export::csv_field, log_line};
+
+pub const AUDIT_EXPORT_SCHEMA_VERSION: u32 = 1;
+const AUDIT_COLUMNS: &[&str] = &["timestamp", "path", "field", "old", "new", "source"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat { #[default] Json, Csv }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct AuditFilters {
+  /// RFC 3339, or `YYYY-MM-DD` for the start of that local day.
+  from: Option<String>,
+  /// RFC 3339, or `YYYY-MM-DD` for the end of that local day (inclusive).
+  to: Option<String>,
+  path_prefix: Option<String>,
+  /// Empty = every field.
+  fields: Vec<String>,
+  /// Empty = every source.
+  sources: Vec<Source>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRow<'a> {
+  timestamp: &'a str,
+  path: &'a str,
+  field: &'a str,
+  old: Option<&'a str>,
+  new: Option<&'a str>,
+  source: Source,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditExportSummary {
+  dest: String,
+  entries: usize,
+  files_touched: usize,
+  /// Field -> entries.
+  fields_changed: BTreeMap<String, usize>,
+  /// Lines in the log that couldn't be parsed (not exported).
+  skipped_lines: usize,
+}
+
+fn bound(s: &str, end_of_day: bool) -> Result<DateTime<FixedOffset>, String> {
+  if let Ok(t) = DateTime::parse_from_rfc3339(s) { return Ok(t); }
+  let d = NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| format!("\"{}\" is not a date (YYYY-MM-DD or RFC 3339)", s))?;
+  let t = if end_of_day { d.and_hms_milli_opt(23, 59, 59, 999) } else { d.and_hms_opt(0, 0, 0) }.ok_or("invalid date")?;
+  Local.from_local_datetime(&t).earliest().map(|t| t.fixed_offset()).ok_or_else(|| format!("\"{}\" doesn't exist in local time", s))
+}
+
+struct Filter {
+  from: Option<DateTime<FixedOffset>>,
+  to: Option<DateTime<FixedOffset>>,
+  f: AuditFilters,
+}
+
+impl Filter {
+  fn new(f: AuditFilters) -> Result<Self, String> {
+    Ok(Self {
+      from: f.from.as_deref().map(|s| bound(s, false)).transpose()?,
+      to: f.to.as_deref().map(|s| bound(s, true)).transpose()?,
+      f,
+    })
+  }
+
+  fn keeps(&self, e: &AuditEntry) -> bool {
+    if self.from.is_some() || self.to.is_some() {
+      let Ok(t) = DateTime::parse_from_rfc3339(&e.timestamp) else { return false };
+      if self.from.is_some_and(|from| t < from) || self.to.is_some_and(|to| t > to) { return false; }
+    }
+    self.f.path_prefix.as_ref().is_none_or(|p| e.path.starts_with(p.as_str()))
+      && (self.f.fields.is_empty() || self.f.fields.contains(&e.field))
+      && (self.f.sources.is_empty() || self.f.sources.contains(&e.source))
+  }
+}
+
+fn source_token(s: Source) -> String {
+  serde_json::to_value(s).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+fn export_blocking(dest: &str, format: AuditFormat, filters: AuditFilters) -> Result<AuditExportSummary, String> {
+  let filter = Filter::new(filters)?;
+  let dest_path = PathBuf::from(dest);
+  let tmp = dest_path.with_extension("partial");
+  let mut w = BufWriter::new(fs::File::create(&tmp).map_err(|e| e.to_string())?);
+  let write_err = |e: std::io::Error| e.to_string();
+  match format {
+    AuditFormat::Csv => writeln!(w, "{}", AUDIT_COLUMNS.join(",")).map_err(write_err)?,
+    AuditFormat::Json => {
+      let filters = serde_json::to_string(&filter.f).map_err(|e| e.to_string())?;
+      writeln!(w, "{{\"schemaVersion\":{},\"exportedAt\":\"{}\",\"filters\":{},\"entries\":[", AUDIT_EXPORT_SCHEMA_VERSION, Local::now().to_rfc3339(), filters)
+        .map_err(write_err)?;
+    }
+  }
+
+  let mut sum = AuditExportSummary { dest: dest.to_string(), ..Default::default() };
+  let mut files: HashSet<String> = HashSet::new();
+  if let Ok(f) = fs::File::open(audit::audit_path()) {
+    for line in BufReader::new(f).lines().map_while(Result::ok) {
+      if line.trim().is_empty() { continue; }
+      let Ok(e) = serde_json::from_str::<AuditEntry>(&line) else { sum.skipped_lines += 1; continue };
+      if !filter.keeps(&e) { continue; }
+      match format {
+        AuditFormat::Csv => {
+          let row = [e.timestamp.as_str(), &e.path, &e.field, e.old.as_deref().unwrap_or(""), e.new.as_deref().unwrap_or(""), &source_token(e.source)];
+          writeln!(w, "{}", row.iter().map(|v| csv_field(v)).collect::<Vec<_>>().join(",")).map_err(write_err)?;
+        }
+        AuditFormat::Json => {
+          if sum.entries > 0 { w.write_all(b",\n").map_err(write_err)?; }
+          let row = ExportRow { timestamp: &e.timestamp, path: &e.path, field: &e.field, old: e.old.as_deref(), new: e.new.as_deref(), source: e.source };
+          serde_json::to_writer(&mut w, &row).map_err(|e| e.to_string())?;
+        }
+      }
+      sum.entries += 1;
+      *sum.fields_changed.entry(e.field).or_default() += 1;
+      files.insert(e.path);
+    }
+  }
+  sum.files_touched = files.len();
+
+  match format {
+    AuditFormat::Csv => {
+      writeln!(w, "\nmetric,value\nentries,{}\nfilesTouched,{}", sum.entries, sum.files_touched).map_err(write_err)?;
+      for (field, n) in &sum.fields_changed { writeln!(w, "{},{}", csv_field(&format!("field:{}", field)), n).map_err(write_err)?; }
+    }
+    AuditFormat::Json => {
+      let summary = serde_json::json!({ "entries": sum.entries, "filesTouched": sum.files_touched, "fieldsChanged": sum.fields_changed });
+      writeln!(w, "\n],\"summary\":{}}}", summary).map_err(write_err)?;
+    }
+  }
+  w.into_inner().map_err(|e| e.to_string())?.sync_all().map_err(write_err)?;
+  fs::rename(&tmp, &dest_path).map_err(|e| e.to_string())?;
+  log_line(&format!("export_audit_log dest=\"{}\" entries={} files={}", dest, sum.entries, sum.files_touched));
+  Ok(sum)
+}
+
+/// Write the audit entries matching `filters` to `dest` as JSON (default) or CSV.
+#[tauri::command]
+pub async fn export_audit_log(dest: String, format: Option<AuditFormat>, filters: Option<AuditFilters>) -> Result<AuditExportSummary, String> {
+  let _span = command_span("export_audit_log");
+  tauri::async_runtime::spawn_blocking(move || export_blocking(&dest, format.unwrap_or_default(), filters.unwrap_or_default()))
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditPruneSummary {
+  pruned: usize,
+  kept: usize,
+  /// Where the pruned entries went, when archiving.
+  archive: Option<String>,
+}
+
+fn prune_blocking(older_than_days: u32, archive: bool) -> Result<AuditPruneSummary, String> {
+  let cutoff = Local::now() - chrono::Duration::days(older_than_days as i64);
+  let src = audit::audit_path();
+  let archive_path = archive.then(|| data_dir().join(format!("audit-archive-{}.jsonl", Local::now().format("%Y%m%d"))));
+  let tmp = src.with_extension("jsonl.partial");
+  let write_err = |e: std::io::Error| e.to_string();
+
+  // Appends wait while the log is rewritten, so none land in the old file.
+  let _guard = audit::lock();
+  let Ok(f) = fs::File::open(&src) else { return Ok(AuditPruneSummary { pruned: 0, kept: 0, archive: None }) };
+  let mut keep = BufWriter::new(fs::File::create(&tmp).map_err(|e| e.to_string())?);
+  let mut out: Option<BufWriter<fs::File>> = None;
+  let (mut pruned, mut kept) = (0usize, 0usize);
+  for line in BufReader::new(f).lines() {
+    let line = line.map_err(|e| e.to_string())?;
+    if line.trim().is_empty() { continue; }
+    // Lines that don't parse are kept: pruning never loses what it can't date.
+    let old = serde_json::from_str::<AuditEntry>(&line).ok()
+      .and_then(|e| DateTime::parse_from_rfc3339(&e.timestamp).ok())
+      .is_some_and(|t| t < cutoff);
+    if old {
+      if let Some(p) = &archive_path {
+        if out.is_none() { out = Some(BufWriter::new(fs::OpenOptions::new().create(true).append(true).open(p).map_err(|e| e.to_string())?)); }
+        if let Some(a) = out.as_mut() { writeln!(a, "{}", line).map_err(write_err)?; }
+      }
+      pruned += 1;
+    } else {
+      writeln!(keep, "{}", line).map_err(write_err)?;
+      kept += 1;
+    }
+  }
+  if let Some(a) = out { a.into_inner().map_err(|e| e.to_string())?.sync_all().map_err(write_err)?; }
+  keep.into_inner().map_err(|e| e.to_string())?.sync_all().map_err(write_err)?;
+  if pruned == 0 {
+    let _ = fs::remove_file(&tmp);
+  } else {
+    fs::rename(&tmp, &src).map_err(|e| e.to_string())?;
+  }
+  log_line(&format!("prune_audit_log older_than_days={} pruned={} kept={} archived={}", older_than_days, pruned, kept, archive));
+  Ok(AuditPruneSummary { pruned, kept, archive: archive_path.filter(|_| pruned > 0).map(|p| p.to_string_lossy().to_string()) })
+}
+
+/// Drop audit entries older than `older_than_days`; with `archive`, they are
+/// appended to data dir `audit-archive-<date>.jsonl` instead of deleted.
+#[tauri::command]
+pub async fn prune_audit_log(older_than_days: u32, archive: bool) -> Result<AuditPruneSummary, String> {
+  let _span = command_span("prune_audit_log");
+  tauri::async_runtime::spawn_blocking(move || prune_blocking(older_than_days, archive))
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,99 @@
+// Autocomplete for artist / genre / album while editing, so spellings stay
+// consistent ("Sol" -> "Solomun"). Counts come from the metadata cache and
+// are updated with every entry change; the index is persisted next to the
+// cache (data dir `autocomplete.json`) so it's warm at startup. Matching is
+// case- and accent-folded: "beyonce" finds "Beyoncé". "Various Artists" on a
+// flagged compilation isn't counted as an artist (see `compilation`).
+
+use std::{collections::HashMap, fs, path::PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{compilation, data_dir, meta_cache::{self, CachedMeta}, text_fold::fold_str, write_atomic};
+
+const DEFAULT_LIMIT: usize = 10;
+
+/// Per field: exact value as written in the tags -> number of files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+  artist: HashMap<String, usize>,
+  genre: HashMap<String, usize>,
+  album: HashMap<String, usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+  /// The most common spelling among values that fold the same.
+  pub value: String,
+  pub count: usize,
+}
+
+fn index_path() -> PathBuf { data_dir().join("autocomplete.json") }
+
+pub fn load() -> Option<Index> { serde_json::from_str(&fs::read_to_string(index_path()).ok()?).ok() }
+
+pub fn save(idx: &Index) -> Result<(), String> {
+  write_atomic(&index_path(), &serde_json::to_vec(idx).map_err(|e| e.to_string())?)
+}
+
+fn values(m: &CachedMeta) -> [(usize, Option<&str>); 3] {
+  [(0, compilation::artist_for_counts(m.artist.as_deref(), m.compilation)), (1, m.genre.as_deref()), (2, m.album.as_deref())]
+}
+
+impl Index {
+  fn field_mut(&mut self, i: usize) -> &mut HashMap<String, usize> {
+    match i { 0 => &mut self.artist, 1 => &mut self.genre, _ => &mut self.album }
+  }
+
+  pub fn add(&mut self, m: &CachedMeta) {
+    for (i, v) in values(m) {
+      let Some(v) = v.map(str::trim).filter(|v| !v.is_empty()) else { continue };
+      *self.field_mut(i).entry(v.to_string()).or_insert(0) += 1;
+    }
+  }
+
+  pub fn remove(&mut self, m: &CachedMeta) {
+    for (i, v) in values(m) {
+      let Some(v) = v.map(str::trim).filter(|v| !v.is_empty()) else { continue };
+      let field = self.field_mut(i);
+      if let Some(n) = field.get_mut(v) {
+        *n -= 1;
+        if *n == 0 { field.remove(v); }
+      }
+    }
+  }
+}
+
+/// Values whose folded form starts with the folded prefix, or has a word
+/// that does ("bohm" -> "Ben Böhmer"). Ranked by count, then name.
+fn suggest(field: &HashMap<String, usize>, prefix: &str, limit: usize) -> Vec<Suggestion> {
+  let want = fold_str(prefix.trim());
+  // Spelling variants ("Solomun" / "SOLOMUN") share one suggestion.
+  let mut groups: HashMap<String, (usize, &str, usize)> = HashMap::new();
+  for (value, &n) in field {
+    let folded = fold_str(value);
+    let hit = want.is_empty() || folded.starts_with(&want) || folded.split_whitespace().any(|w| w.starts_with(&want));
+    if !hit { continue; }
+    let g = groups.entry(folded).or_insert((0, value, 0));
+    g.0 += n;
+    if n > g.2 || (n == g.2 && value.as_str() < g.1) { g.1 = value; g.2 = n; }
+  }
+  let mut out: Vec<Suggestion> = groups.into_values().map(|(count, v, _)| Suggestion { value: v.to_string(), count }).collect();
+  out.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+  out.truncate(limit);
+  out
+}
+
+/// Most frequent existing values of `field` ("artist", "genre" or "album")
+/// matching `prefix`. An empty prefix lists the most common values.
+#[tauri::command]
+pub fn autocomplete(field: String, prefix: String, limit: Option<usize>) -> Result<Vec<Suggestion>, String> {
+  let limit = limit.unwrap_or(DEFAULT_LIMIT);
+  meta_cache::with_index(|idx| match field.as_str() {
+    "artist" => Ok(suggest(&idx.artist, &prefix, limit)),
+    "genre" => Ok(suggest(&idx.genre, &prefix, limit)),
+    "album" => Ok(suggest(&idx.album, &prefix, limit)),
+    other => Err(format!("no autocomplete for field \"{}\" (artist, genre or album)", other)),
+  })
+}
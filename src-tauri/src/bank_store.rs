@@ -0,0 +1,162 @@
+// Single way in to bank files (`tags.<bank>.json`, and the legacy
+// `tags.json`). Every read, write and read-modify-write of one file runs
+// under that file's lock, so a repair write-back, a dedupe and a save from the
+// UI can't interleave or race on the shared temp file. Writes are atomic and
+// keep the previous contents as `<file>.bak`. Bank writes also get their
+// `modifiedAt` stamps and tombstones (`banks::stamp_changes`).
+//
+// The locks are tokio mutexes so background tasks can `.lock().await` them;
+// commands and blocking threads take them with `blocking_lock`.
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc};
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use crate::{bank_path, banks::{self, BankDocument}, error::CmdError, write_atomic};
+
+#[derive(Default)]
+pub struct BankStore {
+  locks: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+/// Sibling backup of the last contents we replaced.
+fn backup_path(path: &Path) -> PathBuf {
+  let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  path.with_file_name(format!("{}.bak", name))
+}
+
+/// Replace `path` atomically, first copying what's there to `.bak`. Callers
+/// hold the file's lock.
+pub fn replace_locked(path: &Path, bytes: &[u8]) -> Result<(), String> {
+  if let Some(dir) = path.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+  if path.exists() { fs::copy(path, backup_path(path)).map_err(|e| format!("backup failed: {}", e))?; }
+  write_atomic(path, bytes)
+}
+
+impl BankStore {
+  fn lock_for(&self, path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+    self.locks.lock().entry(path.to_path_buf()).or_default().clone()
+  }
+
+  fn with_lock<T>(&self, path: &Path, f: impl FnOnce() -> T) -> T {
+    let lock = self.lock_for(path);
+    let _guard = lock.blocking_lock();
+    f()
+  }
+
+  /// Bank text (see `banks::read_bank_text`), creating the file with `empty` when it doesn't exist yet.
+  pub fn read_or_create(&self, bank: &str, empty: &str) -> Result<String, CmdError> {
+    let path = bank_path(bank);
+    self.with_lock(&path, || match banks::read_bank_text(bank)? {
+      Some(s) => Ok(s),
+      None => {
+        replace_locked(&path, empty.as_bytes())?;
+        Ok(empty.to_string())
+      }
+    })
+  }
+
+  pub fn load(&self, bank: &str) -> Result<BankDocument, String> {
+    self.with_lock(&bank_path(bank), || banks::load_bank(bank))
+  }
+
+  /// Replace a bank file (or `tags.json`) with `json`, which must parse:
+  /// a bad payload is refused rather than left on disk for the next read.
+  pub fn write(&self, path: &Path, json: &str) -> Result<(), String> {
+    serde_json::from_str::<Value>(json).map_err(|e| format!("refusing to write invalid JSON to {}: {}", path.display(), e))?;
+    self.with_lock(path, || replace_locked(path, json.as_bytes()))
+  }
+
+  /// Replace a bank with the frontend's `json`, stamping what changed.
+  pub fn write_bank(&self, bank: &str, json: &str) -> Result<(), String> {
+    let path = bank_path(bank);
+    let mut doc: BankDocument = serde_json::from_str(json).map_err(|e| format!("refusing to write invalid bank to {}: {}", path.display(), e))?;
+    self.with_lock(&path, || {
+      let old = banks::load_bank(bank)?;
+      banks::stamp_changes(&old, &mut doc);
+      let json = serde_json::to_string_pretty(&doc).map_err(|e| e.to_string())?;
+      replace_locked(&path, json.as_bytes())
+    })
+  }
+
+  /// Load, edit and save one bank under a single lock. `f` returns its
+  /// result and whether the document changed; unchanged banks aren't written.
+  pub fn update<T>(&self, bank: &str, f: impl FnOnce(&mut BankDocument) -> (T, bool)) -> Result<T, String> {
+    self.with_lock(&bank_path(bank), || {
+      let mut doc = banks::load_bank(bank)?;
+      let old = doc.clone();
+      let (out, changed) = f(&mut doc);
+      if changed {
+        banks::stamp_changes(&old, &mut doc);
+        let json = serde_json::to_string_pretty(&doc).map_err(|e| e.to_string())?;
+        replace_locked(&bank_path(bank), json.as_bytes())?;
+      }
+      Ok(out)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn named(doc: &BankDocument) -> Vec<String> { doc.tags.iter().map(|t| t.name.clone()).collect() }
+
+  fn tag(name: &str) -> banks::BankTag { serde_json::from_value(serde_json::json!({ "id": banks::new_id(), "name": name })).unwrap() }
+
+  fn bank_json(names: &[&str]) -> String {
+    let mut doc = BankDocument::empty();
+    doc.tags = names.iter().map(|n| tag(n)).collect();
+    serde_json::to_string(&doc).unwrap()
+  }
+
+  #[test]
+  fn interleaved_access_leaves_valid_json_with_the_last_write() {
+    let store = BankStore::default();
+    let bank = "store-interleaved";
+    let path = bank_path(bank);
+    store.write_bank(bank, &bank_json(&["Seed"])).unwrap();
+    std::thread::scope(|s| {
+      for t in 0..4 {
+        let store = &store;
+        s.spawn(move || for i in 0..25 {
+          match (t + i) % 4 {
+            0 => { store.write_bank(bank, &bank_json(&[&format!("Saved{}-{}", t, i)])).unwrap(); }
+            1 => { store.update(bank, |doc| { doc.tags.push(tag(&format!("Merged{}-{}", t, i))); ((), true) }).unwrap(); }
+            2 => { store.load(bank).unwrap(); }
+            _ => { serde_json::from_str::<Value>(&store.read_or_create(bank, "{}").unwrap()).unwrap(); }
+          }
+        });
+      }
+    });
+    store.write_bank(bank, &bank_json(&["Last", "Write"])).unwrap();
+    let on_disk: BankDocument = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(named(&on_disk), ["Last", "Write"]);
+    serde_json::from_str::<BankDocument>(&fs::read_to_string(backup_path(&path)).unwrap()).expect("the backup parses too");
+  }
+
+  #[test]
+  fn concurrent_merges_are_not_lost() {
+    let store = BankStore::default();
+    let bank = "store-merges";
+    store.write_bank(bank, &bank_json(&[])).unwrap();
+    std::thread::scope(|s| {
+      for t in 0..8 {
+        let store = &store;
+        s.spawn(move || for i in 0..10 {
+          store.update(bank, |doc| { doc.tags.push(tag(&format!("T{}-{}", t, i))); ((), true) }).unwrap();
+        });
+      }
+    });
+    assert_eq!(store.load(bank).unwrap().tags.len(), 80);
+  }
+
+  #[test]
+  fn invalid_json_is_refused_and_the_file_kept() {
+    let store = BankStore::default();
+    let path = bank_path("store-invalid");
+    store.write(&path, r#"{"version":4,"tags":[]}"#).unwrap();
+    assert!(store.write(&path, "{ not json").unwrap_err().contains("refusing"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"version":4,"tags":[]}"#);
+  }
+}
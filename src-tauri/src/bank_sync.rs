@@ -0,0 +1,332 @@
+// Differential bank sync between two machines. `export_bank_changes` bundles
+// the entries changed and removed since a point in time (from the schema-2
+// `modifiedAt` stamps and tombstones); `apply_bank_changes` merges a bundle
+// from the other machine. An entry edited (or removed) on both sides since the
+// bundle's `since` is a conflict and always reported: "last-writer-wins"
+// keeps the later timestamp, "manual" changes nothing and returns both sides.
+
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{banks::{now_stamp, parse_stamp, BankDocument, BankTag, TagRef, Tombstone}, error::CmdError, handshake, log_line, write_atomic, AppState};
+
+const BUNDLE_FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankChangeBundle {
+  format: u32,
+  /// Bank it was exported from; informational, any bank can apply it.
+  bank: String,
+  /// Changes after this time are included; `None` = the whole bank.
+  since: Option<String>,
+  exported_at: String,
+  /// Added or modified entries, whole.
+  changed: Vec<BankTag>,
+  removed: Vec<Tombstone>,
+}
+
+fn parse_since(since: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+  since.map(|s| parse_stamp(s).ok_or_else(|| format!("not an RFC 3339 timestamp: {}", s))).transpose()
+}
+
+/// Changed after `since`. Unknown stamps count as changed.
+fn after(stamp: Option<&str>, since: Option<DateTime<Utc>>) -> bool {
+  match (stamp.and_then(parse_stamp), since) {
+    (Some(t), Some(s)) => t > s,
+    _ => true,
+  }
+}
+
+/// `a` is strictly later than `b`; an unreadable stamp loses.
+fn later(a: Option<&str>, b: Option<&str>) -> bool {
+  match (a.and_then(parse_stamp), b.and_then(parse_stamp)) {
+    (Some(a), Some(b)) => a > b,
+    (Some(_), None) => true,
+    _ => false,
+  }
+}
+
+fn changes_since(bank: &str, doc: BankDocument, since_timestamp: Option<String>) -> Result<BankChangeBundle, String> {
+  let since = parse_since(since_timestamp.as_deref())?;
+  Ok(BankChangeBundle {
+    format: BUNDLE_FORMAT,
+    bank: bank.to_string(),
+    since: since_timestamp,
+    exported_at: now_stamp(),
+    changed: doc.tags.into_iter().filter(|t| after(t.modified_at.as_deref(), since)).collect(),
+    removed: doc.removed.into_iter().filter(|r| after(Some(&r.removed_at), since)).collect(),
+  })
+}
+
+/// Entries of `bank` added, modified or removed after `since_timestamp`
+/// (RFC 3339; `None` exports everything). With `dest` the bundle is also
+/// written there as JSON for carrying to the other machine.
+#[tauri::command]
+pub fn export_bank_changes(
+  state: tauri::State<'_, AppState>,
+  bank: String,
+  since_timestamp: Option<String>,
+  dest: Option<String>,
+) -> Result<BankChangeBundle, String> {
+  let bundle = changes_since(&bank, state.banks.load(&bank)?, since_timestamp)?;
+  if let Some(dest) = &dest {
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    write_atomic(Path::new(dest), json.as_bytes())?;
+  }
+  log_line(&format!("export_bank_changes bank=\"{}\" changed={} removed={}", bank, bundle.changed.len(), bundle.removed.len()));
+  Ok(bundle)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy { LastWriterWins, Manual }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankConflict {
+  id: String,
+  name: String,
+  /// This machine's entry; `None` when it was removed here.
+  local: Option<BankTag>,
+  /// The bundle's entry; `None` when it was removed there.
+  incoming: Option<BankTag>,
+  local_removed_at: Option<String>,
+  incoming_removed_at: Option<String>,
+  /// "local" or "incoming" under last-writer-wins; `None` under manual.
+  resolution: Option<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankSyncReport {
+  dry_run: bool,
+  added: Vec<TagRef>,
+  updated: Vec<TagRef>,
+  removed: Vec<TagRef>,
+  /// Incoming changes the bank already had.
+  unchanged: usize,
+  conflicts: Vec<BankConflict>,
+}
+
+impl BankSyncReport {
+  fn changed(&self) -> bool {
+    !self.added.is_empty() || !self.updated.is_empty() || !self.removed.is_empty()
+  }
+}
+
+fn winner(strategy: ConflictStrategy, incoming_wins: bool) -> Option<&'static str> {
+  match strategy {
+    ConflictStrategy::Manual => None,
+    ConflictStrategy::LastWriterWins => Some(if incoming_wins { "incoming" } else { "local" }),
+  }
+}
+
+fn merge(doc: &mut BankDocument, bundle: &BankChangeBundle, strategy: ConflictStrategy, report: &mut BankSyncReport) -> Result<(), String> {
+  let since = parse_since(bundle.since.as_deref())?;
+
+  for inc in &bundle.changed {
+    let local = doc.tags.iter().position(|t| t.id == inc.id);
+    let tomb = doc.removed.iter().position(|r| r.id == inc.id);
+    match (local, tomb) {
+      (Some(i), _) => {
+        let cur = &doc.tags[i];
+        if cur.same_content(inc) { report.unchanged += 1; continue; }
+        if after(cur.modified_at.as_deref(), since) {
+          let resolution = winner(strategy, later(inc.modified_at.as_deref(), cur.modified_at.as_deref()));
+          report.conflicts.push(BankConflict {
+            id: inc.id.clone(), name: inc.name.clone(), local: Some(cur.clone()), incoming: Some(inc.clone()),
+            local_removed_at: None, incoming_removed_at: None, resolution,
+          });
+          if resolution != Some("incoming") { continue; }
+        }
+        doc.tags[i] = inc.clone();
+        report.updated.push(TagRef::from(inc));
+      }
+      (None, Some(r)) if after(Some(&doc.removed[r].removed_at), since) => {
+        // Removed here, edited there.
+        let ts = doc.removed[r].clone();
+        let resolution = winner(strategy, later(inc.modified_at.as_deref(), Some(&ts.removed_at)));
+        report.conflicts.push(BankConflict {
+          id: inc.id.clone(), name: inc.name.clone(), local: None, incoming: Some(inc.clone()),
+          local_removed_at: Some(ts.removed_at), incoming_removed_at: None, resolution,
+        });
+        if resolution != Some("incoming") { continue; }
+        doc.removed.remove(r);
+        doc.tags.push(inc.clone());
+        report.added.push(TagRef::from(inc));
+      }
+      (None, r) => {
+        if let Some(r) = r { doc.removed.remove(r); }
+        doc.tags.push(inc.clone());
+        report.added.push(TagRef::from(inc));
+      }
+    }
+  }
+
+  for ts in &bundle.removed {
+    let Some(i) = doc.tags.iter().position(|t| t.id == ts.id) else { report.unchanged += 1; continue };
+    let cur = &doc.tags[i];
+    if after(cur.modified_at.as_deref(), since) {
+      // Edited here, removed there.
+      let resolution = winner(strategy, later(Some(&ts.removed_at), cur.modified_at.as_deref()));
+      report.conflicts.push(BankConflict {
+        id: ts.id.clone(), name: cur.name.clone(), local: Some(cur.clone()), incoming: None,
+        local_removed_at: None, incoming_removed_at: Some(ts.removed_at.clone()), resolution,
+      });
+      if resolution != Some("incoming") { continue; }
+    }
+    let gone = doc.tags.remove(i);
+    report.removed.push(TagRef::from(&gone));
+    if !doc.removed.iter().any(|r| r.id == ts.id) { doc.removed.push(ts.clone()); }
+  }
+  Ok(())
+}
+
+/// Merge a bundle from `export_bank_changes` into `bank`. Conflicts are
+/// listed whatever the strategy; with `dry_run` nothing is written.
+#[tauri::command]
+pub fn apply_bank_changes(
+  state: tauri::State<'_, AppState>,
+  bank: String,
+  bundle: BankChangeBundle,
+  conflict_strategy: ConflictStrategy,
+  dry_run: bool,
+) -> Result<BankSyncReport, CmdError> {
+  if bundle.format > BUNDLE_FORMAT { return Err(format!("bundle format {} is newer than this app supports", bundle.format).into()); }
+  if !dry_run { handshake::check_bank_write()?; }
+  let report = state.banks.update(&bank, |doc| {
+    let mut report = BankSyncReport { dry_run, ..Default::default() };
+    let res = merge(doc, &bundle, conflict_strategy, &mut report);
+    let changed = res.is_ok() && !dry_run && report.changed();
+    (res.map(|_| report), changed)
+  })??;
+  if !dry_run {
+    log_line(&format!(
+      "apply_bank_changes bank=\"{}\" from=\"{}\" added={} updated={} removed={} conflicts={} strategy={:?}",
+      bank, bundle.bank, report.added.len(), report.updated.len(), report.removed.len(), report.conflicts.len(), conflict_strategy
+    ));
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::Map;
+
+  const SYNCED: &str = "2024-03-01T00:00:00.000Z";
+
+  fn at(day: u32) -> String { format!("2024-03-{:02}T12:00:00.000Z", day) }
+
+  fn tag(id: &str, name: &str, day: u32) -> BankTag {
+    BankTag {
+      id: id.into(), name: name.into(), kind: Some("optional".into()), parent: None, amount_range: None,
+      color: None, group: None, description: None, modified_at: Some(format!("2024-02-{:02}T12:00:00.000Z", day)), extra: Map::new(),
+    }
+  }
+
+  /// Both machines as they were at the last sync.
+  fn base() -> BankDocument {
+    let mut doc = BankDocument::empty();
+    doc.tags = vec![tag("a", "House", 1), tag("b", "Vocal", 2), tag("c", "Energy", 3), tag("d", "Old", 4)];
+    doc
+  }
+
+  fn edit(doc: &mut BankDocument, id: &str, day: u32, f: impl FnOnce(&mut BankTag)) {
+    let t = doc.tags.iter_mut().find(|t| t.id == id).unwrap();
+    f(t);
+    t.modified_at = Some(at(day));
+  }
+
+  fn remove(doc: &mut BankDocument, id: &str, day: u32) {
+    let i = doc.tags.iter().position(|t| t.id == id).unwrap();
+    let t = doc.tags.remove(i);
+    doc.removed.push(Tombstone { id: t.id, name: t.name, removed_at: at(day) });
+  }
+
+  /// The two histories after the sync:
+  /// desktop: colour A (2nd), add E (2nd), remove D (3rd), edit C (4th);
+  /// laptop: rename B (2nd), edit C (3rd), remove A (5th).
+  fn diverged() -> (BankDocument, BankDocument) {
+    let (mut desktop, mut laptop) = (base(), base());
+    edit(&mut desktop, "a", 2, |t| t.color = Some("#ff0000".into()));
+    desktop.tags.push(BankTag { modified_at: Some(at(2)), ..tag("e", "Dub", 1) });
+    remove(&mut desktop, "d", 3);
+    edit(&mut desktop, "c", 4, |t| t.description = Some("desktop".into()));
+    edit(&mut laptop, "b", 2, |t| t.name = "Vocals".into());
+    edit(&mut laptop, "c", 3, |t| t.description = Some("laptop".into()));
+    remove(&mut laptop, "a", 5);
+    (desktop, laptop)
+  }
+
+  fn apply(doc: &mut BankDocument, from: &BankDocument, strategy: ConflictStrategy) -> BankSyncReport {
+    let bundle = changes_since("main", from.clone(), Some(SYNCED.into())).unwrap();
+    let mut report = BankSyncReport::default();
+    merge(doc, &bundle, strategy, &mut report).unwrap();
+    report
+  }
+
+  fn by_id(doc: &BankDocument) -> Vec<BankTag> {
+    let mut tags = doc.tags.clone();
+    tags.sort_by(|a, b| a.id.cmp(&b.id));
+    tags
+  }
+
+  fn conflicts(r: &BankSyncReport) -> Vec<(&str, Option<&str>)> {
+    let mut v: Vec<_> = r.conflicts.iter().map(|c| (c.id.as_str(), c.resolution)).collect();
+    v.sort();
+    v
+  }
+
+  #[test]
+  fn bundles_hold_only_what_changed_since() {
+    let (desktop, _) = diverged();
+    let bundle = changes_since("main", desktop.clone(), Some(SYNCED.into())).unwrap();
+    let mut changed: Vec<&str> = bundle.changed.iter().map(|t| t.id.as_str()).collect();
+    changed.sort();
+    assert_eq!(changed, ["a", "c", "e"]);
+    assert_eq!(bundle.removed.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["d"]);
+    assert_eq!(changes_since("main", desktop.clone(), None).unwrap().changed.len(), desktop.tags.len());
+    assert!(changes_since("main", desktop, Some("last week".into())).is_err());
+  }
+
+  #[test]
+  fn last_writer_wins_converges_both_ways() {
+    let (mut desktop, mut laptop) = diverged();
+    let (from_desktop, from_laptop) = (desktop.clone(), laptop.clone());
+    let on_desktop = apply(&mut desktop, &from_laptop, ConflictStrategy::LastWriterWins);
+    let on_laptop = apply(&mut laptop, &from_desktop, ConflictStrategy::LastWriterWins);
+
+    assert_eq!(by_id(&desktop), by_id(&laptop));
+    let names: Vec<String> = by_id(&desktop).into_iter().map(|t| t.name).collect();
+    assert_eq!(names, ["Vocals", "Energy", "Dub"]);
+    assert_eq!(by_id(&desktop)[1].description.as_deref(), Some("desktop"), "the later edit of C");
+    // Both edited C; A was coloured on one side and removed, later, on the other.
+    assert_eq!(conflicts(&on_desktop), [("a", Some("incoming")), ("c", Some("local"))]);
+    assert_eq!(conflicts(&on_laptop), [("a", Some("local")), ("c", Some("incoming"))]);
+    assert!(desktop.removed.iter().any(|r| r.id == "a") && laptop.removed.iter().any(|r| r.id == "d"));
+
+    let again = apply(&mut desktop, &from_laptop, ConflictStrategy::LastWriterWins);
+    assert!(!again.changed(), "a second apply changes nothing");
+  }
+
+  #[test]
+  fn manual_returns_conflicts_and_keeps_local_entries() {
+    let (mut desktop, laptop) = diverged();
+    let before = desktop.clone();
+    let report = apply(&mut desktop, &laptop, ConflictStrategy::Manual);
+    assert_eq!(conflicts(&report), [("a", None), ("c", None)]);
+    let c = report.conflicts.iter().find(|c| c.id == "c").unwrap();
+    assert_eq!(c.local.as_ref().and_then(|t| t.description.as_deref()), Some("desktop"));
+    assert_eq!(c.incoming.as_ref().and_then(|t| t.description.as_deref()), Some("laptop"));
+    let a = report.conflicts.iter().find(|c| c.id == "a").unwrap();
+    assert_eq!((a.incoming.is_none(), a.incoming_removed_at.as_deref()), (true, Some(at(5).as_str())));
+
+    let kept = |doc: &BankDocument, id: &str| doc.tags.iter().find(|t| t.id == id).cloned();
+    assert_eq!(kept(&desktop, "a"), kept(&before, "a"));
+    assert_eq!(kept(&desktop, "c"), kept(&before, "c"));
+    assert_eq!(report.updated.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(), ["Vocals"], "the rest still merges");
+  }
+}
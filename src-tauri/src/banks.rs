@@ -0,0 +1,681 @@
+// Typed view of a tag bank file (`tags.<bank>.json`), matching TagsFile /
+// TagDef in src/types.ts. Fields this side doesn't know about are kept in
+// `extra` so reading and re-writing a bank never drops frontend data.
+//
+// Schema 2 adds `modifiedAt` per entry and `removed` tombstones, which
+// `bank_sync` diffs between machines. The frontend doesn't maintain either:
+// `stamp_changes` fills them in on every write from the previous contents.
+// Schema 3 adds `presets` (see `presets`), which the frontend doesn't write
+// either; a bank write without them keeps the ones on disk.
+// Schema 4 makes `id` the stable handle for an entry, a UUID: migration
+// replaces older ids (slugs of the name) and moves `parent` links along; on
+// every write one missing or shared with an earlier entry gets a fresh UUID,
+// and nothing else here changes an existing id. Entries stay in stored order
+// through dedupe, sync and import; `reorder_bank_tags` is the one way to move
+// them.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  path::Path,
+};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use tauri::Manager;
+
+use crate::{bank_path, bank_store, command_span, error::CmdError, handshake, lenient_json, log_line, presets::Preset, read_comment, read_tagged, split_comment_tokens, tag_policy, AppState, TAGS_SCHEMA_VERSION};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AmountRange {
+  pub min: f64,
+  pub max: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankTag {
+  /// Empty only in files written before schema 4; see `assign_ids`.
+  #[serde(default)]
+  pub id: String,
+  pub name: String,
+  /// "main" | "mandatory" | "optional"
+  #[serde(rename = "type", default)]
+  pub kind: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub amount_range: Option<AmountRange>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub color: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub group: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  /// RFC 3339, UTC; last write that changed this entry.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub modified_at: Option<String>,
+  #[serde(flatten)]
+  pub extra: Map<String, Value>,
+}
+
+/// An entry deleted from the bank, kept so the deletion can be synced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+  pub id: String,
+  pub name: String,
+  pub removed_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankDocument {
+  #[serde(default = "schema_default")]
+  pub version: u32,
+  #[serde(default)]
+  pub tags: Vec<BankTag>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub removed: Vec<Tombstone>,
+  /// `None` when the file (or the frontend's write) has no `presets` key.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub presets: Option<Vec<Preset>>,
+  #[serde(flatten)]
+  pub extra: Map<String, Value>,
+}
+
+// Files without a version predate versioning.
+fn schema_default() -> u32 { 1 }
+
+/// Timestamp format of `modifiedAt` / `removedAt`.
+pub fn now_stamp() -> String { Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true) }
+
+pub fn parse_stamp(s: &str) -> Option<DateTime<Utc>> { DateTime::parse_from_rfc3339(s).ok().map(|d| d.with_timezone(&Utc)) }
+
+/// A random (version 4) UUID, hyphenated lowercase.
+pub fn new_id() -> String { uuid::Uuid::new_v4().to_string() }
+
+/// A UUID in the hyphenated form `new_id` writes (any case).
+fn is_uuid(id: &str) -> bool {
+  uuid::Uuid::try_parse(id).is_ok_and(|u| u.hyphenated().to_string().eq_ignore_ascii_case(id))
+}
+
+/// Give entries with no id, or one an earlier entry (or `taken`) already
+/// has, a fresh one. Returns (old, new) for each replaced non-empty id.
+fn assign_ids(tags: &mut [BankTag], taken: &HashSet<String>) -> Vec<(String, String)> {
+  let mut seen = taken.clone();
+  let mut replaced = Vec::new();
+  for t in tags {
+    if !t.id.is_empty() && seen.insert(t.id.clone()) { continue; }
+    let id = new_id();
+    if !t.id.is_empty() { replaced.push((std::mem::replace(&mut t.id, id.clone()), id.clone())); } else { t.id = id.clone(); }
+    seen.insert(id);
+  }
+  replaced
+}
+
+/// The 3 -> 4 migration: replace ids that aren't UUIDs (schema 3 files carry
+/// slugs of the name) or that an earlier entry already has, and point
+/// `parent` links and tombstones at the new ids. Returns (old, new) for each
+/// replaced id.
+fn migrate_ids(doc: &mut BankDocument) -> Vec<(String, String)> {
+  let mut kept = HashSet::new();
+  // Old id -> new id of the first entry that had it.
+  let mut map: HashMap<String, String> = HashMap::new();
+  let mut replaced = Vec::new();
+  for t in &mut doc.tags {
+    if is_uuid(&t.id) && kept.insert(t.id.clone()) { continue; }
+    let id = new_id();
+    let old = std::mem::replace(&mut t.id, id.clone());
+    // A repeat of a kept UUID leaves links to the entry that kept it.
+    if !old.is_empty() && !kept.contains(&old) { map.entry(old.clone()).or_insert_with(|| id.clone()); }
+    replaced.push((old, id.clone()));
+    kept.insert(id);
+  }
+  for t in &mut doc.tags {
+    if let Some(to) = t.parent.as_ref().and_then(|p| map.get(p)) { t.parent = Some(to.clone()); }
+  }
+  for ts in &mut doc.removed {
+    if let Some(to) = map.get(&ts.id) { ts.id = to.clone(); }
+  }
+  replaced
+}
+
+impl BankTag {
+  /// Same rule as `isRecognizedToken` in src/lib/tags.ts: exact name, or
+  /// name followed by an in-range integer for amount tags.
+  pub fn matches(&self, token: &str) -> bool {
+    match &self.amount_range {
+      None => token == self.name,
+      Some(r) => token
+        .strip_prefix(self.name.as_str())
+        .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|n| n.parse::<f64>().ok())
+        .is_some_and(|v| v >= r.min && v <= r.max),
+    }
+  }
+}
+
+impl BankTag {
+  /// Same entry data, timestamps aside.
+  pub fn same_content(&self, other: &BankTag) -> bool {
+    BankTag { modified_at: None, ..self.clone() } == BankTag { modified_at: None, ..other.clone() }
+  }
+}
+
+impl BankDocument {
+  pub fn find(&self, token: &str) -> Option<&BankTag> { self.tags.iter().find(|t| t.matches(token)) }
+
+  pub fn empty() -> Self { BankDocument { version: TAGS_SCHEMA_VERSION, tags: Vec::new(), removed: Vec::new(), presets: None, extra: Map::new() } }
+
+  /// Bring an older schema up to date; `written_at` stamps entries that have
+  /// no `modifiedAt` yet (the file's mtime). Returns whether anything changed.
+  pub fn migrate(&mut self, written_at: &str) -> bool {
+    if self.version >= TAGS_SCHEMA_VERSION { return false; }
+    for t in self.tags.iter_mut().filter(|t| t.modified_at.is_none()) { t.modified_at = Some(written_at.to_string()); }
+    // 2 -> 3: `presets` starts out absent; only the version moves.
+    // 3 -> 4: every entry gets a unique UUID.
+    if self.version < 4 {
+      let replaced = migrate_ids(self);
+      if !replaced.is_empty() { log_line(&format!("bank migration gave {} entries a UUID id", replaced.len())); }
+    }
+    self.version = TAGS_SCHEMA_VERSION;
+    true
+  }
+}
+
+/// Fill in `modifiedAt` and tombstones on `new`, about to replace `old`:
+/// entries whose content changed without a new timestamp get `now`, entries
+/// that disappeared get a tombstone, re-added ones lose theirs. Entries
+/// without a unique id get one (see the header).
+pub fn stamp_changes(old: &BankDocument, new: &mut BankDocument) {
+  let now = now_stamp();
+  assign_ids(&mut new.tags, &HashSet::new());
+  let before: HashMap<&str, &BankTag> = old.tags.iter().map(|t| (t.id.as_str(), t)).collect();
+  for t in &mut new.tags {
+    match before.get(t.id.as_str()) {
+      Some(prev) if !t.same_content(prev) && t.modified_at.as_ref().is_none_or(|m| prev.modified_at.as_ref() == Some(m)) => {
+        t.modified_at = Some(now.clone());
+      }
+      Some(prev) if t.modified_at.is_none() => t.modified_at = prev.modified_at.clone(),
+      None if t.modified_at.is_none() => t.modified_at = Some(now.clone()),
+      _ => {}
+    }
+  }
+  // The frontend writes only `version` and `tags`; carry the tombstones over.
+  for ts in &old.removed {
+    if !new.removed.iter().any(|r| r.id == ts.id) { new.removed.push(ts.clone()); }
+  }
+  for t in &old.tags {
+    if !new.tags.iter().any(|n| n.id == t.id) && !new.removed.iter().any(|r| r.id == t.id) {
+      new.removed.push(Tombstone { id: t.id.clone(), name: t.name.clone(), removed_at: now.clone() });
+    }
+  }
+  new.removed.retain(|r| !new.tags.iter().any(|t| t.id == r.id));
+  if new.presets.is_none() { new.presets = old.presets.clone(); }
+  new.version = new.version.max(TAGS_SCHEMA_VERSION);
+}
+
+fn file_stamp(path: &Path) -> String {
+  fs::metadata(path).and_then(|m| m.modified()).map(|t| DateTime::<Utc>::from(t).to_rfc3339_opts(SecondsFormat::Millis, true)).unwrap_or_else(|_| now_stamp())
+}
+
+/// Rewrite a parsed bank in the current schema when it is older.
+fn migrate_text(bank: &str, path: &Path, s: String) -> Result<String, CmdError> {
+  let Ok(mut doc) = serde_json::from_str::<BankDocument>(&s) else { return Ok(s) };
+  let from = doc.version;
+  if !doc.migrate(&file_stamp(path)) { return Ok(s); }
+  let json = serde_json::to_string_pretty(&doc).map_err(|e| e.to_string())?;
+  bank_store::replace_locked(path, json.as_bytes())?;
+  log_line(&format!("bank \"{}\" migrated from schema {} to {}", bank, from, doc.version));
+  Ok(json)
+}
+
+/// Raw text of a bank as strict JSON; `None` when the file doesn't exist.
+/// Hand edits (BOM, comments, trailing commas) are repaired and written back.
+/// A file that still doesn't parse is moved aside to `<file>.corrupt-<time>`
+/// and reported as `Corrupt`, so it is never replaced by an empty bank unseen.
+/// Banks in an older schema are migrated and written back the same way.
+/// Callers hold the bank's lock: go through `BankStore`.
+pub fn read_bank_text(bank: &str) -> Result<Option<String>, CmdError> {
+  let path = bank_path(bank);
+  let s = match fs::read_to_string(&path) {
+    Ok(s) => s,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(CmdError::from_io(&path, &e)),
+  };
+  if serde_json::from_str::<Value>(&s).is_ok() { return migrate_text(bank, &path, s).map(Some); }
+  if let Some(fixed) = lenient_json::repair(&s) {
+    bank_store::replace_locked(&path, fixed.as_bytes())?;
+    log_line(&format!("bank \"{}\" repaired (BOM/comments/trailing commas) and rewritten", bank));
+    return migrate_text(bank, &path, fixed).map(Some);
+  }
+  let err = serde_json::from_str::<Value>(&s).err().map(|e| e.to_string()).unwrap_or_default();
+  let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  let backup = path.with_file_name(format!("{}.corrupt-{}", name, Local::now().format("%Y%m%d-%H%M%S")));
+  fs::rename(&path, &backup).map_err(|e| CmdError::from_io(&path, &e))?;
+  log_line(&format!("bank \"{}\" unparseable ({}); moved to \"{}\"", bank, err, backup.display()));
+  Err(CmdError::Corrupt {
+    path: path.to_string_lossy().to_string(),
+    message: format!("Bank \"{}\" could not be read ({}). The file was moved to {}.", bank, err, backup.display()),
+  })
+}
+
+/// Parse a bank; a missing file is an empty bank, a malformed one is an error.
+/// Same locking rule as `read_bank_text`.
+pub fn load_bank(bank: &str) -> Result<BankDocument, String> {
+  match read_bank_text(bank).map_err(|e| e.to_string())? {
+    Some(s) => serde_json::from_str(&s).map_err(|e| format!("bank {} is not valid: {}", bank, e)),
+    None => Ok(BankDocument::empty()),
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagUsage {
+  /// Bank tag name, or the raw token for hashtags the bank doesn't define.
+  pub name: String,
+  pub tag_id: Option<String>,
+  pub color: Option<String>,
+  pub group: Option<String>,
+  pub count: usize,
+}
+
+/// Comment tokens over `paths`, joined against `doc`'s entries, most used
+/// first. Files that fail to read are skipped (and logged).
+fn usage(doc: &BankDocument, paths: &[String]) -> Vec<TagUsage> {
+  let mut by_name: HashMap<String, TagUsage> = HashMap::new();
+  for path in paths {
+    let p = Path::new(path);
+    let tf = match read_tagged(p) {
+      Ok(tf) => tf,
+      Err(e) => { log_line(&format!("tag_usage_stats skip \"{}\": {}", path, e)); continue; }
+    };
+    for tok in split_comment_tokens(&read_comment(&tf, p)) {
+      if tok.starts_with("TagB:") { continue; }
+      let def = doc.find(&tok);
+      let name = def.map(|d| d.name.clone()).unwrap_or(tok);
+      by_name
+        .entry(name.clone())
+        .or_insert_with(|| TagUsage {
+          name,
+          tag_id: def.map(|d| d.id.clone()),
+          color: def.and_then(|d| d.color.clone()),
+          group: def.and_then(|d| d.group.clone()),
+          count: 0,
+        })
+        .count += 1;
+    }
+  }
+  let mut out: Vec<TagUsage> = by_name.into_values().collect();
+  out.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+  out
+}
+
+/// Count comment tokens over `paths`, joined against the bank's entries.
+#[tauri::command]
+pub async fn tag_usage_stats(app: tauri::AppHandle, paths: Vec<String>, bank: String) -> Result<Vec<TagUsage>, String> {
+  let _span = command_span("tag_usage_stats");
+  tauri::async_runtime::spawn_blocking(move || {
+    let doc = app.state::<AppState>().banks.load(&bank)?;
+    Ok(usage(&doc, &paths))
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagRef {
+  pub id: String,
+  pub name: String,
+}
+
+impl From<&BankTag> for TagRef {
+  fn from(t: &BankTag) -> Self { TagRef { id: t.id.clone(), name: t.name.clone() } }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergedGroup {
+  /// The first entry of the group; the others were folded into it.
+  pub kept: TagRef,
+  pub removed: Vec<TagRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DedupeReport {
+  pub bank: String,
+  pub dry_run: bool,
+  pub merged: Vec<MergedGroup>,
+  /// Entries whose name was empty (or only whitespace), dropped.
+  pub empty_removed: Vec<TagRef>,
+}
+
+impl DedupeReport {
+  pub fn changed(&self) -> bool { !self.merged.is_empty() || !self.empty_removed.is_empty() }
+}
+
+/// Key two entries collide on: the configured tag policy, then case folding
+/// (`#Melodic` and `#melodic ` are the same tag to a reader).
+pub fn dedupe_key(name: &str, policy: &tag_policy::TagPolicy) -> String {
+  tag_policy::normalize_tag(name, policy).unwrap_or_else(|_| name.trim().to_string()).to_lowercase()
+}
+
+/// Fold colliding entries into the first of each group: first color, group,
+/// parent and amount range win, descriptions are unioned. `parent` links to
+/// removed ids are pointed at the kept entry.
+fn dedupe(doc: &mut BankDocument, bank: &str, dry_run: bool) -> DedupeReport {
+  let policy = tag_policy::policy();
+  let mut report = DedupeReport { bank: bank.to_string(), dry_run, merged: Vec::new(), empty_removed: Vec::new() };
+  let mut kept: Vec<BankTag> = Vec::new();
+  let mut index: HashMap<String, usize> = HashMap::new();
+  let mut groups: HashMap<usize, Vec<TagRef>> = HashMap::new();
+  let mut renamed_ids: HashMap<String, String> = HashMap::new();
+
+  for t in std::mem::take(&mut doc.tags) {
+    if t.name.trim().is_empty() { report.empty_removed.push(TagRef::from(&t)); continue; }
+    let key = dedupe_key(&t.name, &policy);
+    let Some(&i) = index.get(&key) else {
+      index.insert(key, kept.len());
+      kept.push(t);
+      continue;
+    };
+    let k = &mut kept[i];
+    if k.color.is_none() { k.color = t.color.clone(); }
+    if k.group.is_none() { k.group = t.group.clone(); }
+    if k.parent.is_none() { k.parent = t.parent.clone(); }
+    if k.amount_range.is_none() { k.amount_range = t.amount_range; }
+    if let Some(d) = t.description.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+      match &mut k.description {
+        Some(existing) if existing.split('\n').any(|l| l.trim() == d) => {}
+        Some(existing) if !existing.trim().is_empty() => { existing.push('\n'); existing.push_str(d); }
+        other => *other = Some(d.to_string()),
+      }
+    }
+    renamed_ids.insert(t.id.clone(), k.id.clone());
+    groups.entry(i).or_default().push(TagRef::from(&t));
+  }
+
+  for t in &mut kept {
+    if let Some(to) = t.parent.as_ref().and_then(|p| renamed_ids.get(p)) { t.parent = Some(to.clone()); }
+    // An entry merged into its own parent would otherwise point at itself.
+    if t.parent.as_deref() == Some(t.id.as_str()) { t.parent = None; }
+  }
+  let mut merged: Vec<(usize, Vec<TagRef>)> = groups.into_iter().collect();
+  merged.sort_by_key(|(i, _)| *i);
+  report.merged = merged.into_iter().map(|(i, removed)| MergedGroup { kept: TagRef::from(&kept[i]), removed }).collect();
+  doc.tags = kept;
+  report
+}
+
+/// Merge entries of `bank` whose names collide after normalization and drop
+/// empty ones. With `dry_run` only the report is returned.
+#[tauri::command]
+pub fn dedupe_bank(state: tauri::State<'_, AppState>, bank: String, dry_run: bool) -> Result<DedupeReport, CmdError> {
+  if !dry_run { handshake::check_bank_write()?; }
+  let report = state.banks.update(&bank, |doc| {
+    let report = dedupe(doc, &bank, dry_run);
+    let changed = !dry_run && report.changed();
+    (report, changed)
+  })?;
+  if !dry_run && report.changed() {
+    log_line(&format!("dedupe_bank bank=\"{}\" merged={} empty={}", bank, report.merged.len(), report.empty_removed.len()));
+  }
+  Ok(report)
+}
+
+/// Dry-run dedupe of a bank's raw JSON as it is loaded; emits
+/// `bank-duplicates` with the report when there is something to fix.
+pub fn warn_duplicates(app: &tauri::AppHandle, bank: &str, json: &str) {
+  let Ok(mut doc) = serde_json::from_str::<BankDocument>(json) else { return };
+  let report = dedupe(&mut doc, bank, true);
+  if report.changed() { let _ = app.emit_all("bank-duplicates", report); }
+}
+
+/// Move the entries of `bank` listed in `ordered_ids` to the front, in that
+/// order; the others follow in their stored order. Returns the entries as
+/// stored afterwards.
+#[tauri::command]
+pub fn reorder_bank_tags(state: tauri::State<'_, AppState>, bank: String, ordered_ids: Vec<String>) -> Result<Vec<BankTag>, CmdError> {
+  handshake::check_bank_write()?;
+  let mut listed = HashSet::new();
+  if let Some(dup) = ordered_ids.iter().find(|id| !listed.insert(id.as_str())) { return Err(format!("id {} is listed twice", dup).into()); }
+  let tags = state.banks.update(&bank, |doc| {
+    if let Some(missing) = ordered_ids.iter().find(|id| !doc.tags.iter().any(|t| &t.id == *id)) {
+      return (Err(format!("bank {} has no entry {}", bank, missing)), false);
+    }
+    let before: Vec<String> = doc.tags.iter().map(|t| t.id.clone()).collect();
+    let (mut front, rest): (Vec<BankTag>, Vec<BankTag>) = std::mem::take(&mut doc.tags).into_iter().partition(|t| listed.contains(t.id.as_str()));
+    front.sort_by_key(|t| ordered_ids.iter().position(|id| *id == t.id));
+    front.extend(rest);
+    doc.tags = front;
+    let changed = doc.tags.iter().map(|t| &t.id).ne(before.iter());
+    (Ok(doc.tags.clone()), changed)
+  })??;
+  log_line(&format!("reorder_bank_tags bank=\"{}\" listed={}", bank, ordered_ids.len()));
+  Ok(tags)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IdChange {
+  pub from: String,
+  pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+  pub bank: String,
+  pub dry_run: bool,
+  /// Appended, in the source's order, with the ids they have in `bank`.
+  pub added: Vec<TagRef>,
+  /// Source entries whose name `bank` (or an earlier source entry) already
+  /// has, as `dedupe_bank` compares names; the existing entry is kept as it is.
+  pub skipped: Vec<TagRef>,
+  /// Source ids already used in `bank` by another entry, and the fresh id
+  /// each imported entry got instead.
+  pub id_map: Vec<IdChange>,
+}
+
+/// Append the entries of the bank file at `source` that `bank` doesn't have
+/// by name. Incoming ids that clash are regenerated (and listed in `id_map`);
+/// `parent` links follow the new ids, or the existing entry for skipped ones.
+#[tauri::command]
+pub fn import_bank_tags(state: tauri::State<'_, AppState>, bank: String, source: String, dry_run: bool) -> Result<ImportReport, CmdError> {
+  if !dry_run { handshake::check_bank_write()?; }
+  let text = fs::read_to_string(&source).map_err(|e| format!("{}: {}", source, e))?;
+  let text = if serde_json::from_str::<Value>(&text).is_ok() { text } else { lenient_json::repair(&text).unwrap_or(text) };
+  let incoming: BankDocument = serde_json::from_str(&text).map_err(|e| format!("{} is not a bank: {}", source, e))?;
+  let policy = tag_policy::policy();
+  let report = state.banks.update(&bank, |doc| {
+    let mut report = ImportReport { bank: bank.clone(), dry_run, added: Vec::new(), skipped: Vec::new(), id_map: Vec::new() };
+    let by_name: HashMap<String, String> = doc.tags.iter().map(|t| (dedupe_key(&t.name, &policy), t.id.clone())).collect();
+    // Source id -> id in `bank`, for the parent links.
+    let mut links: HashMap<String, String> = HashMap::new();
+    let mut new_tags = Vec::new();
+    for t in incoming.tags.into_iter().filter(|t| !t.name.trim().is_empty()) {
+      let key = dedupe_key(&t.name, &policy);
+      if let Some(existing) = by_name.get(&key) {
+        links.insert(t.id.clone(), existing.clone());
+        report.skipped.push(TagRef::from(&t));
+      } else if new_tags.iter().any(|n: &BankTag| dedupe_key(&n.name, &policy) == key) {
+        report.skipped.push(TagRef::from(&t));
+      } else {
+        new_tags.push(t);
+      }
+    }
+    let taken: HashSet<String> = doc.tags.iter().map(|t| t.id.clone()).chain(doc.removed.iter().map(|r| r.id.clone())).collect();
+    let source_ids: Vec<String> = new_tags.iter().map(|t| t.id.clone()).collect();
+    let replaced = assign_ids(&mut new_tags, &taken);
+    for (from, t) in source_ids.iter().zip(&new_tags).filter(|(from, _)| !from.is_empty()) { links.entry(from.clone()).or_insert_with(|| t.id.clone()); }
+    for t in &mut new_tags {
+      if let Some(to) = t.parent.as_ref().and_then(|p| links.get(p)) { t.parent = Some(to.clone()); }
+      t.modified_at = None;
+    }
+    report.id_map = replaced.into_iter().map(|(from, to)| IdChange { from, to }).collect();
+    report.added = new_tags.iter().map(TagRef::from).collect();
+    let changed = !dry_run && !new_tags.is_empty();
+    if changed { doc.tags.extend(new_tags); }
+    (report, changed)
+  })?;
+  if !dry_run {
+    log_line(&format!("import_bank_tags bank=\"{}\" source=\"{}\" added={} skipped={} regenerated_ids={}", bank, source, report.added.len(), report.skipped.len(), report.id_map.len()));
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bank_store::BankStore;
+
+  fn tag(id: &str, name: &str, parent: Option<&str>) -> BankTag {
+    BankTag {
+      id: id.into(), name: name.into(), kind: Some("optional".into()), parent: parent.map(str::to_string), amount_range: None,
+      color: None, group: None, description: None, modified_at: None, extra: Map::new(),
+    }
+  }
+
+  fn ids(doc: &BankDocument) -> Vec<String> { doc.tags.iter().map(|t| t.id.clone()).collect() }
+
+  #[test]
+  fn migration_gives_every_entry_a_uuid_and_keeps_parents() {
+    let kept = new_id();
+    let mut doc = BankDocument {
+      version: 3,
+      tags: vec![
+        tag("house", "House", None),
+        tag("deep_house", "DeepHouse", Some("house")),
+        tag("", "Loose", Some("deep_house")),
+        tag(&kept, "Kept", None),
+        tag(&kept, "Twin", Some(&kept)),
+        tag("house", "HouseAgain", Some("nowhere")),
+      ],
+      removed: vec![Tombstone { id: "old_tag".into(), name: "OldTag".into(), removed_at: "2024-01-01T00:00:00.000Z".into() }],
+      presets: None,
+      extra: Map::new(),
+    };
+    assert!(doc.migrate("2024-01-01T00:00:00.000Z"));
+    assert_eq!(doc.version, TAGS_SCHEMA_VERSION);
+    let ids = ids(&doc);
+    assert!(ids.iter().all(|id| is_uuid(id)), "{:?}", ids);
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+    let names: Vec<&str> = doc.tags.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, ["House", "DeepHouse", "Loose", "Kept", "Twin", "HouseAgain"], "stored order");
+    assert_eq!(ids[3], kept, "a UUID is kept");
+    let parent = |i: usize| doc.tags[i].parent.as_deref();
+    assert_eq!(parent(1), Some(ids[0].as_str()));
+    assert_eq!(parent(2), Some(ids[1].as_str()));
+    assert_eq!(parent(4), Some(kept.as_str()), "a repeated UUID leaves links to the first entry");
+    assert_eq!(parent(5), Some("nowhere"));
+    assert!(doc.tags.iter().all(|t| t.modified_at.is_some()));
+    assert_eq!(doc.removed[0].id, "old_tag", "no entry had that id");
+    assert!(!doc.migrate("2024-01-01T00:00:00.000Z"), "already current");
+  }
+
+  #[test]
+  fn ids_survive_every_bank_operation() {
+    let store = BankStore::default();
+    let bank = "ids-round-trip";
+    fs::write(bank_path(bank), r##"{ "version": 3, "tags": [
+      { "id": "house", "name": "House", "type": "main", "uiOnly": 7 },
+      { "id": "deep_house", "name": "DeepHouse", "parent": "house" },
+      { "id": "vocal", "name": "Vocal", "color": "#00ff00" }
+    ] }"##).unwrap();
+
+    // Migrated on the first read, and written back.
+    let doc = store.load(bank).unwrap();
+    let original = ids(&doc);
+    assert!(original.iter().all(|id| is_uuid(id)));
+    assert_eq!(doc.tags[1].parent.as_deref(), Some(original[0].as_str()));
+    let on_disk: BankDocument = serde_json::from_str(&fs::read_to_string(bank_path(bank)).unwrap()).unwrap();
+    assert_eq!((on_disk.version, ids(&on_disk)), (TAGS_SCHEMA_VERSION, original.clone()));
+    assert_eq!(on_disk.tags[0].extra.get("uiOnly"), Some(&Value::from(7)), "unknown fields round-trip");
+
+    // A frontend save, reordered and edited, keeps every id.
+    let mut ui = doc.clone();
+    ui.tags.reverse();
+    ui.tags[0].color = Some("#ff0000".into());
+    store.write_bank(bank, &serde_json::to_string(&ui).unwrap()).unwrap();
+    let saved = store.load(bank).unwrap();
+    assert_eq!(ids(&saved), original.iter().rev().cloned().collect::<Vec<_>>());
+    assert!(saved.removed.is_empty());
+
+    // Dedupe folds a late duplicate into the first entry and keeps its id.
+    let dup = new_id();
+    store.update(bank, |doc| { doc.tags.push(tag(&dup, "HOUSE", None)); ((), true) }).unwrap();
+    let report = store.update(bank, |doc| { let r = dedupe(doc, bank, false); let c = r.changed(); (r, c) }).unwrap();
+    assert_eq!(report.merged[0].removed[0].id, dup);
+    let deduped = store.load(bank).unwrap();
+    assert_eq!(ids(&deduped), ids(&saved));
+    assert!(deduped.removed.iter().any(|r| r.id == dup));
+
+    // Imports keep their own ids unless one is taken.
+    let taken: HashSet<String> = ids(&deduped).into_iter().collect();
+    let mut incoming = vec![tag(&original[0], "Clash", None), tag("", "NoId", None), tag(&new_id(), "Fresh", None)];
+    let fresh = incoming[2].id.clone();
+    let replaced = assign_ids(&mut incoming, &taken);
+    assert_eq!(replaced.len(), 1);
+    assert_eq!(replaced[0].0, original[0]);
+    assert_eq!(incoming[0].id, replaced[0].1);
+    assert!(is_uuid(&incoming[1].id));
+    assert_eq!(incoming[2].id, fresh);
+  }
+
+  /// A bank as src/App.tsx saves it (`JSON.stringify` of a TagsFile): nulls for
+  /// unset optional fields, integer ranges, and keys this side doesn't model.
+  const FRONTEND_BANK: &str = r##"{"version":4,"tags":[{"id":"0b7a3c4e-1f2d-4e5a-9b6c-7d8e9f0a1b2c","name":"House","type":"main","parent":null,"amountRange":null},{"id":"5c6d7e8f-9a0b-4c1d-8e2f-3a4b5c6d7e8f","name":"Energy","type":"optional","parent":"0b7a3c4e-1f2d-4e5a-9b6c-7d8e9f0a1b2c","amountRange":{"min":1,"max":5},"color":"#ff8800","group":"Mood","description":"How hard it hits","modifiedAt":"2024-05-01T10:00:00.000Z","pinned":true},{"id":"9e8d7c6b-5a4f-4e3d-8c2b-1a0f9e8d7c6b","name":"Vocal","type":"mandatory","color":null,"group":null,"description":null}],"removed":[{"id":"1a2b3c4d-5e6f-4a7b-8c9d-0e1f2a3b4c5d","name":"Old","removedAt":"2024-04-01T09:30:00.000Z"}],"presets":[{"name":"Warmup","actions":[{"type":"add_tags","tags":["House"]},{"type":"set_field","field":"genre","value":"House"}]}],"ui":{"collapsed":["Mood"]}}"##;
+
+  /// `v` as the frontend reads it back: null members dropped (this side
+  /// writes them as absent keys) and every number a double.
+  fn as_js(v: Value) -> Value {
+    match v {
+      Value::Object(m) => Value::Object(m.into_iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k, as_js(v))).collect()),
+      Value::Array(a) => Value::Array(a.into_iter().map(as_js).collect()),
+      Value::Number(n) => Value::from(n.as_f64()),
+      v => v,
+    }
+  }
+
+  #[test]
+  fn frontend_banks_round_trip_through_the_typed_document() {
+    let doc: BankDocument = serde_json::from_str(FRONTEND_BANK).unwrap();
+    let energy = &doc.tags[1];
+    assert_eq!((energy.kind.as_deref(), energy.parent.as_deref()), (Some("optional"), Some(doc.tags[0].id.as_str())));
+    assert_eq!(energy.amount_range, Some(AmountRange { min: 1.0, max: 5.0 }));
+    assert_eq!((energy.color.as_deref(), energy.group.as_deref(), energy.description.as_deref()), (Some("#ff8800"), Some("Mood"), Some("How hard it hits")));
+    assert_eq!(energy.extra.get("pinned"), Some(&Value::Bool(true)));
+    assert_eq!((doc.tags[2].color.clone(), doc.tags[2].group.clone()), (None, None), "null reads as unset");
+    assert_eq!((doc.removed.len(), doc.presets.as_ref().map(Vec::len)), (1, Some(1)));
+
+    let written = serde_json::to_value(&doc).unwrap();
+    let original: Value = serde_json::from_str(FRONTEND_BANK).unwrap();
+    assert_eq!(as_js(written.clone()), as_js(original), "every field, known or not, survives");
+    let again: BankDocument = serde_json::from_value(written.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&again).unwrap(), written);
+
+    // Files from before versioning, with entries missing everything optional.
+    let bare: BankDocument = serde_json::from_str(r#"{"tags":[{"name":"Solo"}]}"#).unwrap();
+    assert_eq!((bare.version, bare.tags[0].id.as_str(), bare.tags[0].kind.clone(), bare.presets.clone()), (1, "", None, None));
+  }
+
+  #[test]
+  fn usage_carries_the_bank_colour_and_group() {
+    let doc: BankDocument = serde_json::from_str(FRONTEND_BANK).unwrap();
+    let dir = crate::test_support::scratch("tag-usage");
+    let paths: Vec<String> = ["Energy3;House;#unknown;TagB:x;", "Energy5;Energy9;", "House;"].iter().enumerate()
+      .map(|(i, c)| crate::test_support::tagged(&dir, &format!("{}.mp3", i), &[(lofty::ItemKey::Comment, c)]).to_string_lossy().to_string())
+      .chain(["/nowhere/missing.mp3".to_string()])
+      .collect();
+    let got: Vec<(String, Option<String>, Option<String>, usize)> = usage(&doc, &paths).into_iter().map(|u| (u.name, u.color, u.group, u.count)).collect();
+    let row = |n: &str, c: Option<&str>, g: Option<&str>, k| (n.to_string(), c.map(str::to_string), g.map(str::to_string), k);
+    assert_eq!(got, [
+      row("Energy", Some("#ff8800"), Some("Mood"), 2),
+      row("House", None, None, 2),
+      row("#unknown", None, None, 1),
+      row("Energy9", None, None, 1),
+    ]);
+  }
+}
@@ -0,0 +1,87 @@
+// Headless mode for scripted pipelines: `audio-tagger --cli <subcommand> …`
+// runs one command against the same functions the GUI invokes and exits
+// before any window or the media server exists. Results are JSON on stdout;
+// log lines and errors go to stderr. Exit codes: 0 ok, 1 the command failed,
+// 2 bad usage. Writes are audited with source "cli".
+//
+// Windows release builds use the GUI subsystem, so output only shows up
+// when stdout/stderr are redirected (pipes, files), not in a bare console.
+
+use std::{io::Write, path::Path, sync::atomic::{AtomicBool, Ordering}};
+use serde::Serialize;
+
+use crate::{apply_runtime_settings, audit, export, load_prefs, meta_cache, read_folder, read_metadata, tag_ops, touched, write_comment_as};
+
+/// Set for the whole process in CLI mode; `log` writes to stderr instead of the session file.
+pub static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+const USAGE: &str = "usage: audio-tagger --cli <command>
+  scan <folder>                 supported files directly in <folder>
+  read <file>                   metadata of one file
+  set-comment <file> <text>     replace the comment
+  add-tags <file> <tag>...      add comment tags (tag policy applies)
+  export-csv <folder> <out>     one CSV row per file in <folder>";
+
+fn print_json<T: Serialize>(v: &T) -> Result<(), String> {
+  let mut out = std::io::stdout().lock();
+  serde_json::to_writer_pretty(&mut out, v).map_err(|e| e.to_string())?;
+  writeln!(out).map_err(|e| e.to_string())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Written<'a> {
+  path: &'a str,
+  comment: &'a str,
+}
+
+fn dispatch(cmd: &str, rest: &[String]) -> Option<Result<(), String>> {
+  let res = match (cmd, rest) {
+    ("scan", [folder]) => read_folder(folder, false).map_err(String::from).and_then(|v| print_json(&v)),
+    ("read", [file]) => read_metadata(file.clone()).map_err(String::from).and_then(|v| print_json(&v)),
+    ("set-comment", [file, text]) => write_comment_as(file, text, audit::Source::Cli)
+      .map_err(String::from)
+      .and_then(|_| print_json(&Written { path: file, comment: text })),
+    ("add-tags", [file, tags @ ..]) if !tags.is_empty() => {
+      tag_ops::merge_file_tags(file, tags, &[], audit::Source::Cli).and_then(|v| print_json(&v))
+    }
+    ("export-csv", [folder, out]) => export::export_csv_blocking(folder, out).and_then(|v| print_json(&v)),
+    _ => return None,
+  };
+  Some(res)
+}
+
+/// Entry point for `--cli`; `args` are the words after it. Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+  ACTIVE.store(true, Ordering::Relaxed);
+  let Some((cmd, rest)) = args.split_first() else {
+    eprintln!("{}", USAGE);
+    return 2;
+  };
+  if matches!(cmd.as_str(), "help" | "--help" | "-h") {
+    println!("{}", USAGE);
+    return 0;
+  }
+  if let Some(folder) = rest.first().filter(|_| cmd == "scan" || cmd == "export-csv") {
+    if !Path::new(folder).is_dir() {
+      eprintln!("error: {} is not a folder", folder);
+      return 1;
+    }
+  }
+  // Same tag policy, precedence and formats as the GUI would use.
+  apply_runtime_settings(&load_prefs().settings.unwrap_or_default());
+  let Some(res) = dispatch(cmd, rest) else {
+    eprintln!("error: bad arguments for \"{}\"\n{}", cmd, USAGE);
+    return 2;
+  };
+  // What the GUI does on exit; reads and writes update both stores.
+  touched::flush();
+  meta_cache::flush();
+  match res {
+    Ok(()) => 0,
+    Err(e) => {
+      eprintln!("error: {}", e);
+      1
+    }
+  }
+}
@@ -0,0 +1,337 @@
+// Per-track colour labels. Reading takes Serato's track colour (the COLOR
+// entry of "Serato Markers2": a GEOB frame in ID3v2, SERATO_MARKERS_V2 in
+// Vorbis comments) and falls back to our own TRACK_COLOR field (TXXX in
+// ID3v2, a plain key in Vorbis comments and APE, the iTunes freeform atom in
+// MP4). Rekordbox keeps its colours in its library database, not in the
+// file, so there is nothing of its to read here.
+//
+// `write_color_label` always writes TRACK_COLOR. With the Serato target it
+// also patches the COLOR entry of an existing ID3v2 Markers2 blob, and only
+// when the blob decodes and re-encodes to the exact bytes on disk; anything
+// else (no blob, no colour entry, an encoding we don't reproduce, Vorbis or
+// MP4 files) is left alone and reported in `serato`. Serato may show a stored
+// colour as the nearest one of its palette.
+
+use std::path::Path;
+use base64::{alphabet, engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig}, Engine as _};
+use lofty::{id3::v2::GeneralEncapsulatedObject, ItemKey, ItemValue, Tag, TagItem, TagType, TaggedFileExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit, edit_tags, error::CmdError, log_line, read_tagged, shadow};
+
+const NAME: &str = "TRACK_COLOR";
+const MARKERS2: &str = "Serato Markers2";
+/// Serato wraps its base64 at this many characters.
+const LINE: usize = 72;
+
+/// Serato writes base64 both with and without trailing '='.
+const LENIENT: GeneralPurpose = GeneralPurpose::new(
+  &alphabet::STANDARD,
+  GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent).with_decode_allow_trailing_bits(true),
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorTarget { Neutral, Serato }
+
+/// What happened to the Serato colour of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SeratoWrite {
+  NotRequested,
+  Written,
+  /// Already that colour.
+  Unchanged,
+  /// Serato hasn't analysed the file (no Markers2 blob).
+  NoMarkers,
+  /// The blob has no COLOR entry to patch.
+  NoColorEntry,
+  /// The blob didn't decode, or wouldn't re-encode to the same bytes.
+  Unparsed,
+  /// Serato colour writes are only done in ID3v2 tags for now.
+  Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorWriteResult {
+  path: String,
+  /// "#RRGGBB".
+  color: String,
+  /// Every field already held the colour; nothing was saved.
+  no_op: bool,
+  serato: SeratoWrite,
+  #[serde(flatten)]
+  shadow: shadow::Mark,
+}
+
+fn key(tt: TagType) -> Option<ItemKey> {
+  match tt {
+    TagType::Id3v2 | TagType::VorbisComments | TagType::Ape => Some(ItemKey::Unknown(NAME.into())),
+    TagType::Mp4Ilst => Some(ItemKey::Unknown(format!("----:com.apple.iTunes:{}", NAME))),
+    _ => None,
+  }
+}
+
+/// "#RRGGBB" from "#rrggbb", "rrggbb" or "0xRRGGBB".
+fn normalize(hex: &str) -> Option<String> {
+  let h = hex.trim();
+  let h = h.strip_prefix('#').or_else(|| h.strip_prefix("0x")).unwrap_or(h);
+  (h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit())).then(|| format!("#{}", h.to_ascii_uppercase()))
+}
+
+fn rgb(hex: &str) -> [u8; 3] {
+  let n = u32::from_str_radix(&hex[1..], 16).unwrap_or(0);
+  [(n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+// Markers2 data: 0x01 0x01, base64 wrapped with '\n', NUL padding. The
+// decoded payload is 0x01 0x01, entries of (name NUL, u32 BE length, body),
+// and a closing NUL.
+
+fn decode_markers(data: &[u8]) -> Option<Vec<u8>> {
+  let text = data.strip_prefix(&[1, 1])?;
+  let mut b64: Vec<u8> = text.iter().take_while(|b| **b != 0).filter(|b| **b != b'\n').copied().collect();
+  // A dangling sixth of a byte; Serato leaves these at the end.
+  if b64.len() % 4 == 1 { b64.pop(); }
+  let payload = LENIENT.decode(&b64).ok()?;
+  payload.starts_with(&[1, 1]).then_some(payload)
+}
+
+fn encode_markers(payload: &[u8], padded: bool, len: usize) -> Vec<u8> {
+  let b64 = if padded { general_purpose::STANDARD.encode(payload) } else { general_purpose::STANDARD_NO_PAD.encode(payload) };
+  let mut out = vec![1, 1];
+  for (i, line) in b64.as_bytes().chunks(LINE).enumerate() {
+    if i > 0 { out.push(b'\n'); }
+    out.extend_from_slice(line);
+  }
+  if out.len() < len { out.resize(len, 0); }
+  out
+}
+
+/// Byte offset of the COLOR entry's 4-byte body in `payload`.
+fn color_offset(payload: &[u8]) -> Option<usize> {
+  let mut at = 2;
+  loop {
+    let name_len = payload.get(at..)?.iter().position(|b| *b == 0)?;
+    if name_len == 0 { return None; }
+    let name = &payload[at..at + name_len];
+    at += name_len + 1;
+    let len = u32::from_be_bytes(payload.get(at..at + 4)?.try_into().ok()?) as usize;
+    at += 4;
+    if at + len > payload.len() { return None; }
+    if name == b"COLOR" { return (len == 4).then_some(at); }
+    at += len;
+  }
+}
+
+fn color_of_markers(data: &[u8]) -> Option<String> {
+  let payload = decode_markers(data)?;
+  let at = color_offset(&payload)?;
+  let c = &payload[at + 1..at + 4];
+  Some(format!("#{:02X}{:02X}{:02X}", c[0], c[1], c[2]))
+}
+
+fn geob_markers(item: &TagItem) -> Option<GeneralEncapsulatedObject> {
+  let ItemValue::Binary(b) = item.value() else { return None };
+  GeneralEncapsulatedObject::parse(b).ok().filter(|g| g.descriptor.as_deref() == Some(MARKERS2))
+}
+
+fn serato_color(tag: &Tag) -> Option<String> {
+  match tag.tag_type() {
+    TagType::Id3v2 => tag.get_items(&ItemKey::Unknown("GEOB".into())).filter_map(geob_markers).find_map(|g| color_of_markers(&g.data)),
+    TagType::VorbisComments => {
+      // base64 of the whole GEOB body: mime, file name, descriptor, data.
+      let text: String = tag.get_string(&ItemKey::Unknown("SERATO_MARKERS_V2".into()))?.chars().filter(|c| *c != '\n').collect();
+      let body = LENIENT.decode(text.trim()).ok()?;
+      let marker = format!("{}\0", MARKERS2);
+      let at = body.windows(marker.len()).position(|w| w == marker.as_bytes())?;
+      color_of_markers(&body[at + marker.len()..])
+    }
+    _ => None,
+  }
+}
+
+fn neutral_color(tag: &Tag) -> Option<String> { tag.get_string(&key(tag.tag_type())?).and_then(normalize) }
+
+/// The file's colour label, Serato's first, as "#RRGGBB".
+pub fn of_file(tf: &lofty::TaggedFile) -> Option<String> {
+  tf.tags().iter().find_map(serato_color).or_else(|| tf.tags().iter().find_map(neutral_color))
+}
+
+/// Patch the Markers2 colour in an ID3v2 tag, when it's safe to.
+fn write_serato(tag: &mut Tag, color: [u8; 3]) -> SeratoWrite {
+  let geob = ItemKey::Unknown("GEOB".into());
+  let Some((index, g)) = tag.get_items(&geob).enumerate().find_map(|(i, item)| geob_markers(item).map(|g| (i, g))) else {
+    return SeratoWrite::NoMarkers;
+  };
+  let original = match tag.get_items(&geob).nth(index).map(TagItem::value) {
+    Some(ItemValue::Binary(b)) => b.clone(),
+    _ => return SeratoWrite::Unparsed,
+  };
+  let Some(mut payload) = decode_markers(&g.data) else { return SeratoWrite::Unparsed };
+  let padded = g.data.contains(&b'=');
+  // Only touch blobs we'd write back byte for byte.
+  if g.as_bytes() != original || encode_markers(&payload, padded, g.data.len()) != g.data { return SeratoWrite::Unparsed; }
+  let Some(at) = color_offset(&payload) else { return SeratoWrite::NoColorEntry };
+  if payload[at + 1..at + 4] == color { return SeratoWrite::Unchanged; }
+  payload[at + 1..at + 4].copy_from_slice(&color);
+  let patched = GeneralEncapsulatedObject { data: encode_markers(&payload, padded, g.data.len()), ..g };
+
+  let mut seen = 0;
+  tag.retain(|item| {
+    if item.key() != &geob { return true; }
+    seen += 1;
+    seen != index + 1
+  });
+  tag.push_unchecked(TagItem::new(geob, ItemValue::Binary(patched.as_bytes())));
+  SeratoWrite::Written
+}
+
+/// Set the colour label of `path` to `color_hex` ("#RRGGBB"). TRACK_COLOR is
+/// always written; `targets` containing `serato` also updates Serato's colour
+/// where that can be done safely (see `ColorWriteResult::serato`).
+#[tauri::command]
+pub fn write_color_label(path: String, color_hex: String, targets: Vec<ColorTarget>) -> Result<ColorWriteResult, CmdError> {
+  let color = normalize(&color_hex).ok_or_else(|| format!("not a colour: {}", color_hex))?;
+  let p = Path::new(&path);
+  let old = read_tagged(p).ok().and_then(|tf| of_file(&tf));
+  let want_serato = targets.contains(&ColorTarget::Serato);
+  let mut serato = if want_serato { SeratoWrite::Unsupported } else { SeratoWrite::NotRequested };
+  let outcome = edit_tags(p, |tag| {
+    if let Some(k) = key(tag.tag_type()) { tag.insert_unchecked(TagItem::new(k, ItemValue::Text(color.clone()))); }
+    if want_serato && tag.tag_type() == TagType::Id3v2 { serato = write_serato(tag, rgb(&color)); }
+  })?;
+  if !outcome.no_op { audit::record(&path, "color", old.as_deref(), Some(&color), audit::Source::Manual); }
+  log_line(&format!("write_color_label path=\"{}\" color={} serato={:?} no_op={}", path, color, serato, outcome.no_op));
+  Ok(ColorWriteResult { path, color, no_op: outcome.no_op, serato, shadow: outcome.shadow })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::{AudioFile, TextEncoding};
+  use crate::test_support;
+
+  fn entry(name: &str, body: &[u8]) -> Vec<u8> {
+    [name.as_bytes(), &[0], &(body.len() as u32).to_be_bytes(), body].concat()
+  }
+
+  /// A Markers2 payload as Serato writes it: colour, a named cue, BPM lock.
+  fn payload(color: Option<[u8; 3]>) -> Vec<u8> {
+    let cue = [&[0, 0, 0, 0, 0x3A, 0x98, 0, 0xCC, 0, 0, 0, 0][..], b"Drop the bass 1\0"].concat();
+    let mut out = vec![1, 1];
+    if let Some(c) = color { out.extend(entry("COLOR", &[0, c[0], c[1], c[2]])); }
+    out.extend(entry("CUE", &cue));
+    out.extend(entry("BPMLOCK", &[0]));
+    out.push(0);
+    out
+  }
+
+  /// The GEOB data: 0x01 0x01, base64 wrapped at 72 with '\n', NULs up to 470 bytes.
+  fn blob(payload: &[u8], padded: bool, tail: &str) -> Vec<u8> {
+    let b64 = if padded { general_purpose::STANDARD.encode(payload) } else { general_purpose::STANDARD_NO_PAD.encode(payload) } + tail;
+    let lines: Vec<&str> = b64.as_bytes().chunks(72).map(|l| std::str::from_utf8(l).unwrap()).collect();
+    let mut out = [&[1u8, 1][..], lines.join("\n").as_bytes()].concat();
+    out.resize(470, 0);
+    out
+  }
+
+  fn geob(data: Vec<u8>) -> GeneralEncapsulatedObject {
+    GeneralEncapsulatedObject { encoding: TextEncoding::Latin1, mime_type: Some("application/octet-stream".into()), file_name: None, descriptor: Some(MARKERS2.into()), data }
+  }
+
+  /// An MP3 whose ID3v2 tag carries `data` as its Markers2 GEOB.
+  fn serato_mp3(dir: &Path, name: &str, data: Vec<u8>) -> std::path::PathBuf {
+    let p = test_support::audio(dir, name);
+    let mut tf = lofty::read_from_path(&p).unwrap();
+    let mut tag = Tag::new(TagType::Id3v2);
+    tag.insert_text(ItemKey::TrackTitle, "Title".into());
+    tag.push_unchecked(TagItem::new(ItemKey::Unknown("GEOB".into()), ItemValue::Binary(geob(data).as_bytes())));
+    tf.insert_tag(tag);
+    tf.save_to_path(&p).unwrap();
+    p
+  }
+
+  fn markers_on_disk(p: &Path) -> Vec<u8> {
+    let tf = lofty::read_from_path(p).unwrap();
+    let tag = tf.tag(TagType::Id3v2).unwrap();
+    let markers = tag.get_items(&ItemKey::Unknown("GEOB".into())).find_map(geob_markers).unwrap();
+    markers.data
+  }
+
+  fn write(p: &Path, color: &str, targets: Vec<ColorTarget>) -> ColorWriteResult {
+    write_color_label(p.to_string_lossy().to_string(), color.into(), targets).unwrap()
+  }
+
+  #[test]
+  fn markers_blobs_decode_and_reencode_byte_for_byte() {
+    let p = payload(Some([0xCC, 0, 0]));
+    for padded in [false, true] {
+      let data = blob(&p, padded, "");
+      assert!(data.contains(&b'\n'), "long enough to wrap");
+      assert_eq!(decode_markers(&data).as_deref(), Some(&p[..]));
+      assert_eq!(encode_markers(&p, padded, data.len()), data);
+      assert_eq!(color_of_markers(&data).as_deref(), Some("#CC0000"));
+    }
+    assert_eq!(color_of_markers(&blob(&payload(None), false, "")), None);
+    assert_eq!(decode_markers(b"\x01\x01!!not base64!!"), None);
+    assert_eq!(decode_markers(&blob(b"\x02\x02", false, "")), None, "wrong payload version");
+  }
+
+  #[test]
+  fn serato_colour_is_patched_and_the_rest_of_the_blob_kept() {
+    let dir = test_support::scratch("color-serato");
+    let p = serato_mp3(&dir, "a.mp3", blob(&payload(Some([0xCC, 0, 0])), false, ""));
+    assert_eq!(of_file(&read_tagged(&p).unwrap()).as_deref(), Some("#CC0000"));
+
+    let r = write(&p, "00ff00", vec![ColorTarget::Neutral, ColorTarget::Serato]);
+    assert_eq!((r.color.as_str(), r.serato, r.no_op), ("#00FF00", SeratoWrite::Written, false));
+    assert_eq!(markers_on_disk(&p), blob(&payload(Some([0, 0xFF, 0])), false, ""), "only the colour bytes change");
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::Unknown(NAME.into())).as_deref(), Some("#00FF00"));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackTitle).as_deref(), Some("Title"));
+    assert_eq!(of_file(&read_tagged(&p).unwrap()).as_deref(), Some("#00FF00"));
+
+    assert_eq!(write(&p, "#00FF00", vec![ColorTarget::Serato]).serato, SeratoWrite::Unchanged);
+    // Neutral only: Serato's colour still wins on read.
+    assert_eq!(write(&p, "#0000FF", vec![ColorTarget::Neutral]).serato, SeratoWrite::NotRequested);
+    assert_eq!(of_file(&read_tagged(&p).unwrap()).as_deref(), Some("#00FF00"));
+  }
+
+  #[test]
+  fn blobs_that_wont_round_trip_are_refused() {
+    let dir = test_support::scratch("color-refused");
+    // Serato's dangling extra character: readable, but not reproduced on write.
+    assert_eq!(payload(Some([0xCC, 0, 0])).len() % 3, 0, "whole base64 quads, so the extra character dangles");
+    let dangling = blob(&payload(Some([0xCC, 0, 0])), false, "A");
+    let p = serato_mp3(&dir, "dangling.mp3", dangling.clone());
+    assert_eq!(of_file(&read_tagged(&p).unwrap()).as_deref(), Some("#CC0000"));
+    assert_eq!(write(&p, "#00FF00", vec![ColorTarget::Serato]).serato, SeratoWrite::Unparsed);
+    assert_eq!(markers_on_disk(&p), dangling, "left untouched");
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::Unknown(NAME.into())).as_deref(), Some("#00FF00"), "the neutral field is still written");
+
+    let corrupt = [&[1u8, 1][..], b"AQEk%%%%"].concat();
+    let p = serato_mp3(&dir, "corrupt.mp3", corrupt.clone());
+    assert_eq!(write(&p, "#00FF00", vec![ColorTarget::Serato]).serato, SeratoWrite::Unparsed);
+    assert_eq!(markers_on_disk(&p), corrupt);
+    assert_eq!(of_file(&read_tagged(&p).unwrap()).as_deref(), Some("#00FF00"), "falls back to TRACK_COLOR");
+
+    let p = serato_mp3(&dir, "nocolor.mp3", blob(&payload(None), false, ""));
+    assert_eq!(write(&p, "#00FF00", vec![ColorTarget::Serato]).serato, SeratoWrite::NoColorEntry);
+    let p = test_support::tagged(&dir, "plain.mp3", &[(ItemKey::TrackTitle, "T")]);
+    assert_eq!(write(&p, "#00FF00", vec![ColorTarget::Serato]).serato, SeratoWrite::NoMarkers);
+  }
+
+  #[test]
+  fn vorbis_markers_are_read_and_only_the_neutral_field_written() {
+    let dir = test_support::scratch("color-vorbis");
+    let body = general_purpose::STANDARD.encode(geob(blob(&payload(Some([0x33, 0x99, 0xFF])), false, "")).as_bytes());
+    let wrapped: Vec<&str> = body.as_bytes().chunks(72).map(|l| std::str::from_utf8(l).unwrap()).collect();
+    let p = test_support::audio(&dir, "a.flac");
+    test_support::add_tag(&p, TagType::VorbisComments, &[(ItemKey::Unknown("SERATO_MARKERS_V2".into()), &wrapped.join("\n"))]);
+    assert_eq!(of_file(&read_tagged(&p).unwrap()).as_deref(), Some("#3399FF"));
+    assert_eq!(write(&p, "#112233", vec![ColorTarget::Serato]).serato, SeratoWrite::Unsupported);
+    assert_eq!(test_support::text(&p, TagType::VorbisComments, &ItemKey::Unknown(NAME.into())).as_deref(), Some("#112233"));
+    assert!(write_color_label(p.to_string_lossy().to_string(), "red".into(), vec![]).is_err());
+  }
+}
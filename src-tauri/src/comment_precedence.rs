@@ -0,0 +1,224 @@
+// Which tag's comment wins when a file carries several (ID3v2 + APE on MP3,
+// RIFF INFO + ID3v2 on WAV) and they disagree. DJ tools each pick their own,
+// so `comment_precedence` in Settings lets the user match the one they use:
+// extension -> ordered tag type names as `inspect_tags` prints them
+// ("Id3v2", "Ape", "RiffInfo", ...). Extensions without an entry keep the
+// built-in read order. Conflicts are flagged on TrackMeta rather than
+// resolved silently; `resolve_comment_conflict` makes every tag agree.
+
+use std::{collections::HashMap, path::Path};
+use lofty::{ItemKey, TagType, TaggedFileExt};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::{audit, edit_tags_with, error::CmdError, ext_lower, inspect::tag_type_name, log_line, read_order_for_ext, WriteOutcome};
+
+const KNOWN: &[TagType] = &[
+  TagType::Id3v2, TagType::Ape, TagType::Id3v1, TagType::RiffInfo, TagType::AiffText, TagType::VorbisComments, TagType::Mp4Ilst,
+];
+
+static ORDER: Lazy<RwLock<HashMap<String, Vec<TagType>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+pub fn parse_tag_type(name: &str) -> Option<TagType> {
+  KNOWN.iter().copied().find(|tt| tag_type_name(*tt).eq_ignore_ascii_case(name.trim()))
+}
+
+/// Normalized copy of a `comment_precedence` setting (lowercase extensions,
+/// canonical tag type names, no repeats), or what's wrong with it.
+pub fn validate(raw: &HashMap<String, Vec<String>>) -> Result<HashMap<String, Vec<String>>, String> {
+  let mut out = HashMap::new();
+  for (ext, names) in raw {
+    let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+    let mut order: Vec<String> = Vec::new();
+    for n in names {
+      let tt = parse_tag_type(n).ok_or_else(|| format!("unknown tag type \"{}\" for .{}", n, ext))?;
+      let name = tag_type_name(tt);
+      if !order.contains(&name) { order.push(name); }
+    }
+    if !order.is_empty() { out.insert(ext, order); }
+  }
+  Ok(out)
+}
+
+pub fn set(raw: &HashMap<String, Vec<String>>) {
+  let parsed = raw.iter()
+    .map(|(ext, names)| (ext.to_ascii_lowercase(), names.iter().filter_map(|n| parse_tag_type(n)).collect()))
+    .collect();
+  *ORDER.write() = parsed;
+}
+
+/// Comment read order for `p`: the user's, else the built-in read order.
+pub fn order_for(p: &Path) -> Vec<TagType> {
+  let ext = ext_lower(p);
+  ORDER.read().get(&ext).cloned().unwrap_or_else(|| read_order_for_ext(&ext))
+}
+
+fn comment_of(tf: &lofty::TaggedFile, tt: TagType) -> Option<&str> {
+  tf.tag(tt)?.get_string(&ItemKey::Comment).map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// The tag type `read_comment` takes the comment from, if any has one.
+pub fn winner(tf: &lofty::TaggedFile, p: &Path) -> Option<TagType> {
+  order_for(p).into_iter().find(|tt| tf.tag(*tt).and_then(|t| t.get_string(&ItemKey::Comment)).is_some())
+}
+
+/// Some other tag carries a different non-empty comment than the one shown.
+/// ID3v1 is left out: its 28-30 byte field is a truncated copy at best.
+pub fn conflicts(tf: &lofty::TaggedFile, p: &Path, shown: &str) -> bool {
+  let shown = shown.trim();
+  let skip = winner(tf, p);
+  tf.tags().iter()
+    .map(|t| t.tag_type())
+    .filter(|tt| Some(*tt) != skip && *tt != TagType::Id3v1)
+    .filter_map(|tt| comment_of(tf, tt))
+    .any(|c| c != shown)
+}
+
+/// Copy the comment of the `keep` tag to every other tag on `path` (ID3v1
+/// excepted), so all readers agree. Goes through `edit_tags_with`, so locks,
+/// limits and the rest of the write path apply. Returns the comments now
+/// stored.
+#[tauri::command]
+pub fn resolve_comment_conflict(path: String, keep: String) -> Result<CommentResolution, CmdError> {
+  let p = Path::new(&path);
+  let keep_tt = parse_tag_type(&keep).ok_or_else(|| format!("unknown tag type \"{}\"", keep))?;
+  let value = OnceCell::new();
+  let mut changed: Vec<(TagType, Option<String>)> = Vec::new();
+  let outcome = edit_tags_with(p, |tf| {
+    let kept = tf.tag(keep_tt).ok_or_else(|| format!("{} has no {} tag", p.display(), tag_type_name(keep_tt)))?;
+    let _ = value.set(kept.get_string(&ItemKey::Comment).unwrap_or_default().to_string());
+    Ok(tf.tags().iter().map(|t| t.tag_type()).filter(|tt| *tt != keep_tt && *tt != TagType::Id3v1).collect())
+  }, |tag| {
+    let value = value.get().map(String::as_str).unwrap_or_default();
+    let old = tag.get_string(&ItemKey::Comment).map(str::to_string);
+    if old.as_deref().unwrap_or_default() == value { return; }
+    if value.is_empty() { tag.remove_key(&ItemKey::Comment); } else { tag.insert_text(ItemKey::Comment, value.to_string()); }
+    changed.push((tag.tag_type(), old));
+  })?;
+  let value = value.into_inner().unwrap_or_default();
+  // A locked comment keeps its disagreeing copies.
+  if !outcome.skipped_locked.is_empty() {
+    log_line(&format!("resolve_comment_conflict path=\"{}\" skipped: comment locked", path));
+  } else if !outcome.no_op {
+    for (_, old) in &changed { audit::record_comment(&path, old.as_deref(), Some(&value), audit::Source::Manual); }
+    let updated: Vec<String> = changed.iter().map(|(tt, _)| tag_type_name(*tt)).collect();
+    log_line(&format!("resolve_comment_conflict path=\"{}\" keep={} updated={}", path, tag_type_name(keep_tt), updated.join(",")));
+  }
+  Ok(CommentResolution { comment: value, outcome })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentResolution {
+  /// The `keep` tag's comment, now in every other tag unless locked.
+  comment: String,
+  #[serde(flatten)]
+  outcome: WriteOutcome,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::Ordering;
+  use crate::{field_locks::{self, LockedField}, read_tagged, tagged_at, test_support};
+
+  fn ape_and_id3(name: &str) -> std::path::PathBuf {
+    let dir = test_support::scratch("precedence");
+    let p = test_support::tagged(&dir, name, &[(ItemKey::Comment, "#id3")]);
+    test_support::add_tag(&p, TagType::Ape, &[(ItemKey::Comment, "#ape")]);
+    p
+  }
+
+  #[test]
+  fn resolving_writes_the_other_tags_through_edit_tags() {
+    let p = ape_and_id3("a.mp3");
+    let tf = read_tagged(&p).unwrap();
+    assert!(conflicts(&tf, &p, "#id3"));
+    tagged_at::EMBED.store(true, Ordering::Relaxed);
+    let res = resolve_comment_conflict(p.to_string_lossy().to_string(), "Ape".into());
+    tagged_at::EMBED.store(false, Ordering::Relaxed);
+    let res = res.unwrap();
+    assert_eq!(res.comment, "#ape");
+    assert!(!res.outcome.no_op && res.outcome.revision > 0, "tracked like any edit");
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::Comment).as_deref(), Some("#ape"));
+    let tf = read_tagged(&p).unwrap();
+    assert!(!conflicts(&tf, &p, "#ape"));
+    assert!(tagged_at::of_file(&tf).is_some(), "the ID3v2 write is stamped");
+    let again = resolve_comment_conflict(p.to_string_lossy().to_string(), "Ape".into()).unwrap();
+    assert!(again.outcome.no_op);
+  }
+
+  #[test]
+  fn a_locked_comment_keeps_its_copies() {
+    let p = ape_and_id3("locked.mp3");
+    field_locks::set(&p, &[LockedField::Comment], true).unwrap();
+    let res = resolve_comment_conflict(p.to_string_lossy().to_string(), "Id3v2".into()).unwrap();
+    assert_eq!(res.comment, "#id3");
+    assert_eq!(res.outcome.skipped_locked, vec![LockedField::Comment]);
+    assert_eq!(test_support::text(&p, TagType::Ape, &ItemKey::Comment).as_deref(), Some("#ape"));
+  }
+
+  #[test]
+  fn a_missing_keep_tag_is_an_error() {
+    let dir = test_support::scratch("precedence");
+    let p = test_support::tagged(&dir, "one.mp3", &[(ItemKey::Comment, "#id3")]);
+    assert!(resolve_comment_conflict(p.to_string_lossy().to_string(), "Ape".into()).is_err());
+    assert!(resolve_comment_conflict(p.to_string_lossy().to_string(), "Nope".into()).is_err());
+  }
+
+  fn shown(p: &Path) -> (String, bool) {
+    let meta = crate::read_metadata(p.to_string_lossy().to_string()).unwrap();
+    (meta.comment, meta.comment_conflicts)
+  }
+
+  #[test]
+  fn riff_info_wins_on_wav_and_the_id3v2_copy_is_flagged() {
+    let dir = test_support::scratch("precedence-wav");
+    let p = test_support::audio(&dir, "a.wav");
+    test_support::add_tag(&p, TagType::RiffInfo, &[(ItemKey::Comment, "#riff")]);
+    test_support::add_tag(&p, TagType::Id3v2, &[(ItemKey::Comment, "#id3")]);
+    assert_eq!(winner(&read_tagged(&p).unwrap(), &p), Some(TagType::RiffInfo));
+    assert_eq!(shown(&p), ("#riff".to_string(), true));
+  }
+
+  #[test]
+  fn id3v2_wins_on_aiff_and_the_text_chunk_is_flagged() {
+    let dir = test_support::scratch("precedence-aiff");
+    let p = test_support::tagged(&dir, "a.aiff", &[(ItemKey::Comment, "#id3")]);
+    // An ANNO chunk by hand: lofty 0.18 panics adding text chunks to an AIFF.
+    let mut bytes = std::fs::read(&p).unwrap();
+    bytes.extend_from_slice(b"ANNO\0\0\0\x06#text\0");
+    let size = (bytes.len() - 8) as u32;
+    bytes[4..8].copy_from_slice(&size.to_be_bytes());
+    std::fs::write(&p, bytes).unwrap();
+    assert_eq!(test_support::text(&p, TagType::AiffText, &ItemKey::Comment).as_deref(), Some("#text"));
+    assert_eq!(shown(&p), ("#id3".to_string(), true));
+  }
+
+  #[test]
+  fn the_setting_changes_which_comment_is_shown() {
+    // A `.wave` file, so no other test's files read through the changed order.
+    let dir = test_support::scratch("precedence-setting");
+    let p = dir.join("a.wave");
+    std::fs::rename(test_support::audio(&dir, "a.wav"), &p).unwrap();
+    test_support::add_tag(&p, TagType::RiffInfo, &[(ItemKey::Comment, "#riff")]);
+    test_support::add_tag(&p, TagType::Id3v2, &[(ItemKey::Comment, "#id3")]);
+    assert_eq!(shown(&p).0, "#id3", "no built-in order: the primary tag");
+    let raw = HashMap::from([(".WAVE".to_string(), vec!["riffinfo".to_string(), "ID3v2".to_string()])]);
+    let setting = validate(&raw).unwrap();
+    assert_eq!(setting, HashMap::from([("wave".to_string(), vec!["RiffInfo".to_string(), "Id3v2".to_string()])]));
+    set(&setting);
+    let custom = shown(&p);
+    set(&HashMap::new());
+    assert_eq!(custom, ("#riff".to_string(), true));
+  }
+
+  #[test]
+  fn an_id3v1_copy_that_differs_is_not_a_conflict() {
+    let dir = test_support::scratch("precedence-id3v1");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#house;#deep;#vocal;#peak-time;")]);
+    test_support::add_tag(&p, TagType::Id3v1, &[(ItemKey::Comment, "#house;#deep;#vocal;#pe")]);
+    assert_eq!(shown(&p), ("#house;#deep;#vocal;#peak-time;".to_string(), false));
+  }
+}
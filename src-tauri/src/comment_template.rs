@@ -0,0 +1,358 @@
+// Structured comments from a template, e.g. "KEY {key} | {bpm} BPM | {tags}".
+// Placeholders resolve per file. With cleanup on, a separator-delimited
+// segment whose placeholders all came out empty is dropped along with its
+// label, so a missing key gives "124 BPM | #deep" rather than "KEY  | 124 BPM".
+//
+// Existing comment tokens are split into tags (single words) and prose (the
+// rest). The rendered text is split back into tokens: the file's tags and
+// other hashtags in it become tokens of their own and the rest of each
+// `;`-part one prose token, so "KEY 8A | 124 BPM | #deep" keeps "#deep" as a
+// tag. The template replaces the prose; tags it leaves out are kept after
+// it, and the `TagB:` bank marker is always carried over to the new comment.
+
+use std::path::Path;
+use lofty::{Accessor, ItemKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  error::CmdError, field_limits::FieldLimitHit, field_locks::{self, LockedField}, inspect::{plan_for_path, WritePlan}, load_prefs, log_line, preferred_tag, preflight::{self, Preflight}, read_comment, read_tagged, retry_queue,
+  save_prefs, snapshots, split_comment_tokens, tag_ops::join_tokens,
+};
+
+const SEPARATORS: &[char] = &['|', '/', ',', ';', '·', '•'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Var { Bpm, Key, Tags, Genre, Year, ExistingProse }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece { Text(String), Var(Var) }
+
+#[derive(Debug, Clone, Default)]
+struct Segment {
+  pieces: Vec<Piece>,
+  sep_after: Option<char>,
+}
+
+#[derive(Debug, Default)]
+struct Values {
+  bpm: Option<String>,
+  key: Option<String>,
+  genre: Option<String>,
+  year: Option<String>,
+  tags: Vec<String>,
+  prose: Vec<String>,
+  bank: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedTemplate {
+  pub name: String,
+  pub template: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateResult {
+  path: String,
+  before: String,
+  after: String,
+  changed: bool,
+  error: Option<String>,
+  /// Dry runs only: the tag types the write would go to.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  plan: Option<WritePlan>,
+  /// Changed fields left alone because they're locked (see `field_locks`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+  /// Values cut or left out for a tag type's length limit (see `field_limits`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<FieldLimitHit>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateReport {
+  results: Vec<TemplateResult>,
+  /// Taken before writing (see `snapshots`); None on a dry run.
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+fn parse_var(name: &str) -> Result<Var, String> {
+  Ok(match name.trim() {
+    "bpm" => Var::Bpm,
+    "key" => Var::Key,
+    "tags" => Var::Tags,
+    "genre" => Var::Genre,
+    "year" => Var::Year,
+    "existing_prose" => Var::ExistingProse,
+    other => return Err(format!("unknown placeholder {{{}}}", other)),
+  })
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>, String> {
+  let mut segs = vec![Segment::default()];
+  let mut text = String::new();
+  let mut chars = template.chars();
+  fn flush(text: &mut String, seg: &mut Segment) {
+    if !text.is_empty() { seg.pieces.push(Piece::Text(std::mem::take(text))); }
+  }
+  while let Some(c) = chars.next() {
+    let seg = segs.last_mut().unwrap();
+    if c == '{' {
+      let mut name = String::new();
+      let mut closed = false;
+      for c in chars.by_ref() {
+        if c == '}' { closed = true; break; }
+        name.push(c);
+      }
+      if !closed { return Err(format!("unterminated placeholder {{{}", name)); }
+      flush(&mut text, seg);
+      seg.pieces.push(Piece::Var(parse_var(&name)?));
+    } else if SEPARATORS.contains(&c) {
+      flush(&mut text, seg);
+      seg.sep_after = Some(c);
+      segs.push(Segment::default());
+    } else {
+      text.push(c);
+    }
+  }
+  flush(&mut text, segs.last_mut().unwrap());
+  Ok(segs)
+}
+
+fn value_of(v: Var, vals: &Values) -> String {
+  match v {
+    Var::Bpm => vals.bpm.clone().unwrap_or_default(),
+    Var::Key => vals.key.clone().unwrap_or_default(),
+    Var::Genre => vals.genre.clone().unwrap_or_default(),
+    Var::Year => vals.year.clone().unwrap_or_default(),
+    Var::Tags => vals.tags.iter().map(|t| if t.starts_with('#') { t.clone() } else { format!("#{}", t) }).collect::<Vec<_>>().join(" "),
+    Var::ExistingProse => vals.prose.join(" "),
+  }
+}
+
+fn join_sep(c: char) -> String {
+  match c {
+    ';' => ";".into(),
+    ',' => ", ".into(),
+    c => format!(" {} ", c),
+  }
+}
+
+fn render(segs: &[Segment], vals: &Values, cleanup: bool) -> String {
+  let mut out = String::new();
+  if !cleanup {
+    for s in segs {
+      for p in &s.pieces {
+        match p { Piece::Text(t) => out.push_str(t), Piece::Var(v) => out.push_str(&value_of(*v, vals)) }
+      }
+      if let Some(c) = s.sep_after { out.push(c); }
+    }
+    return out;
+  }
+  let mut pending_sep: Option<char> = None;
+  for s in segs {
+    let mut any_var = false;
+    let mut any_value = false;
+    let mut text = String::new();
+    for p in &s.pieces {
+      match p {
+        Piece::Text(t) => text.push_str(t),
+        Piece::Var(v) => {
+          any_var = true;
+          let val = value_of(*v, vals);
+          any_value |= !val.trim().is_empty();
+          text.push_str(&val);
+        }
+      }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() || (any_var && !any_value) { continue; }
+    if let Some(c) = pending_sep { out.push_str(&join_sep(c)); }
+    out.push_str(&text);
+    pending_sep = s.sep_after.or(pending_sep);
+  }
+  out
+}
+
+/// `rendered` as comment tokens (see the header). A separator left next to a
+/// tag that moved out, or next to another one, is dropped.
+fn tokens_of(rendered: &str, vals: &Values) -> Vec<String> {
+  let is_sep = |w: &str| w.chars().count() == 1 && w.chars().all(|c| SEPARATORS.contains(&c));
+  let mut out: Vec<String> = Vec::new();
+  for part in split_comment_tokens(rendered) {
+    let (mut prose, mut tags): (Vec<&str>, Vec<String>) = (Vec::new(), Vec::new());
+    for w in part.split_whitespace() {
+      // As the file has it: "{tags}" renders "deep" as "#deep".
+      match vals.tags.iter().find(|t| *t == w || w.strip_prefix('#') == Some(t.as_str())) {
+        Some(t) => tags.push(t.clone()),
+        None if w.len() > 1 && w.starts_with('#') => tags.push(w.to_string()),
+        None if is_sep(w) && prose.last().is_none_or(|p| is_sep(p)) => {}
+        None => prose.push(w),
+      }
+    }
+    while prose.last().is_some_and(|p| is_sep(p)) { prose.pop(); }
+    let prose = prose.join(" ");
+    let prose = prose.trim_end_matches(|c: char| SEPARATORS.contains(&c));
+    for t in (!prose.is_empty()).then(|| prose.to_string()).into_iter().chain(tags) {
+      if !out.contains(&t) { out.push(t); }
+    }
+  }
+  out
+}
+
+/// "124.00" -> "124"; anything non-numeric is kept as written.
+fn tidy_bpm(s: &str) -> String {
+  let s = s.trim();
+  match s.parse::<f64>() {
+    Ok(v) if v.fract() == 0.0 => format!("{}", v as i64),
+    _ => s.to_string(),
+  }
+}
+
+fn values_for(path: &str) -> Result<(Values, String), String> {
+  let p = Path::new(path);
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let comment = read_comment(&tf, p);
+  let mut vals = Values::default();
+  if let Some(tag) = preferred_tag(&tf, p) {
+    let text = |k: ItemKey| tag.get_string(&k).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    vals.bpm = text(ItemKey::Bpm).or_else(|| text(ItemKey::IntegerBpm)).map(|b| tidy_bpm(&b));
+    vals.key = text(ItemKey::InitialKey);
+    vals.genre = tag.genre().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    vals.year = tag.year().map(|y| y.to_string())
+      .or_else(|| text(ItemKey::RecordingDate).map(|d| d.chars().take(4).collect()));
+  }
+  for tok in split_comment_tokens(&comment) {
+    if let Some(b) = tok.strip_prefix("TagB:") { vals.bank = Some(b.to_string()); }
+    else if tok.contains(char::is_whitespace) { vals.prose.push(tok); }
+    else if !vals.tags.contains(&tok) { vals.tags.push(tok); }
+  }
+  Ok((vals, comment))
+}
+
+fn apply_one(path: &str, segs: &[Segment], cleanup: bool, dry_run: bool) -> TemplateResult {
+  let mut res = TemplateResult {
+    path: path.to_string(), before: String::new(), after: String::new(), changed: false, error: None, plan: None, skipped_locked: Vec::new(), limited: Vec::new(),
+  };
+  let (vals, before) = match values_for(path) {
+    Ok(v) => v,
+    Err(e) => { res.error = Some(e); return res; }
+  };
+  let mut tokens = tokens_of(&render(segs, &vals, cleanup), &vals);
+  for t in &vals.tags {
+    if !tokens.contains(t) { tokens.push(t.clone()); }
+  }
+  if let Some(b) = &vals.bank {
+    if !tokens.iter().any(|t| t.starts_with("TagB:")) { tokens.push(format!("TagB:{}", b)); }
+  }
+  let after = join_tokens(&tokens);
+  res.changed = after != before;
+  if res.changed && dry_run {
+    res.plan = plan_for_path(Path::new(path)).ok();
+    res.skipped_locked = field_locks::hits(Path::new(path), &[LockedField::Comment]);
+  }
+  if res.changed && !dry_run {
+    match retry_queue::write_comment(path, &after) {
+      Ok(o) => { res.changed = !o.no_op; (res.skipped_locked, res.limited) = (o.skipped_locked, o.limited); }
+      Err(e) => res.error = Some(e.to_string()),
+    }
+  }
+  res.before = before;
+  res.after = after;
+  res
+}
+
+/// Render `template` for every path; with `dry_run` nothing is written and the
+/// results are the previews. `cleanup` (default on) collapses empty segments.
+#[tauri::command]
+pub fn apply_comment_template(app: tauri::AppHandle, paths: Vec<String>, template: String, dry_run: bool, cleanup: Option<bool>) -> Result<TemplateReport, CmdError> {
+  let segs = parse(&template)?;
+  let cleanup = cleanup.unwrap_or(true);
+  let preflight = preflight::rewrite(&paths);
+  let mut snapshot_id = None;
+  if !dry_run {
+    preflight::ensure(&preflight)?;
+    snapshot_id = snapshots::before_batch(&app, "apply_comment_template", &paths);
+  }
+  let results: Vec<TemplateResult> = paths.iter().map(|p| apply_one(p, &segs, cleanup, dry_run)).collect();
+  if !dry_run {
+    let changed = results.iter().filter(|r| r.changed && r.error.is_none()).count();
+    log_line(&format!("apply_comment_template files={} changed={} template=\"{}\"", results.len(), changed, template));
+  }
+  Ok(TemplateReport { results, snapshot_id, preflight })
+}
+
+#[tauri::command]
+pub fn save_comment_template(name: String, template: String) -> Result<(), String> {
+  let name = name.trim().to_string();
+  if name.is_empty() { return Err("template name is empty".into()); }
+  parse(&template)?;
+  let mut p = load_prefs();
+  p.comment_templates.retain(|t| t.name != name);
+  p.comment_templates.push(SavedTemplate { name, template });
+  save_prefs(&p)
+}
+
+#[tauri::command]
+pub fn delete_comment_template(name: String) -> Result<(), String> {
+  let mut p = load_prefs();
+  p.comment_templates.retain(|t| t.name != name);
+  save_prefs(&p)
+}
+
+#[tauri::command]
+pub fn list_comment_templates() -> Vec<SavedTemplate> { load_prefs().comment_templates }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::ItemKey;
+  use crate::test_support;
+
+  fn vals(tags: &[&str], prose: &[&str]) -> Values {
+    Values {
+      bpm: Some("124".into()),
+      key: Some("8A".into()),
+      tags: tags.iter().map(|t| t.to_string()).collect(),
+      prose: prose.iter().map(|t| t.to_string()).collect(),
+      ..Default::default()
+    }
+  }
+
+  fn tokens(template: &str, v: &Values) -> Vec<String> {
+    tokens_of(&render(&parse(template).unwrap(), v, true), v)
+  }
+
+  #[test]
+  fn tags_come_back_as_their_own_tokens() {
+    let v = vals(&["#melodic", "deep"], &[]);
+    assert_eq!(tokens("KEY {key} | {bpm} BPM | {tags}", &v), ["KEY 8A | 124 BPM", "#melodic", "deep"]);
+    // A tag in the middle doesn't leave "| |" behind.
+    assert_eq!(tokens("{key} | {tags} | {bpm} BPM", &v), ["8A | 124 BPM", "#melodic", "deep"]);
+    assert_eq!(tokens("{tags}", &v), ["#melodic", "deep"]);
+  }
+
+  #[test]
+  fn semicolons_in_the_template_still_split() {
+    let v = vals(&["deep"], &["great opener"]);
+    assert_eq!(tokens("{bpm} BPM; {existing_prose}; {tags}", &v), ["124 BPM", "great opener", "deep"]);
+  }
+
+  #[test]
+  fn tags_left_out_and_the_bank_marker_are_kept() {
+    let dir = test_support::scratch("comment-template");
+    let p = test_support::tagged(&dir, "a.mp3", &[
+      (ItemKey::IntegerBpm, "124"),
+      (ItemKey::InitialKey, "8A"),
+      (ItemKey::Comment, "#melodic;old note here;deep;TagB:Main;"),
+    ]);
+    let res = apply_one(&p.to_string_lossy(), &parse("KEY {key} | {bpm} BPM").unwrap(), true, true);
+    assert_eq!(res.error, None);
+    assert_eq!(res.after, "KEY 8A | 124 BPM;#melodic;deep;TagB:Main;");
+    let res = apply_one(&p.to_string_lossy(), &parse("{tags} | {existing_prose}").unwrap(), true, true);
+    assert_eq!(res.after, "old note here;#melodic;deep;TagB:Main;");
+  }
+}
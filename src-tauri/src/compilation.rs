@@ -0,0 +1,125 @@
+// Compilations: the compilation flag (TCMP in ID3v2, the iTunes `cpil`
+// atom, COMPILATION in Vorbis comments and APE) and the "Various Artists"
+// album artist that usually comes with it. Artist-centric features skip that
+// placeholder on flagged files: autocomplete doesn't count it, and
+// `{albumartist}` in name templates reads as empty there, so
+// `{albumartist|artist}` falls back to the track artist.
+
+use lofty::{ItemKey, Tag};
+
+use crate::text_fold::fold_str;
+
+/// Album artist placeholders, folded.
+const VARIOUS: &[&str] = &["various artists", "various", "va", "v.a.", "v/a"];
+
+/// `Some(true)` / `Some(false)` when the tag has the flag, `None` without it.
+pub fn of_tag(tag: &Tag) -> Option<bool> {
+  match tag.get_string(&ItemKey::FlagCompilation)?.trim() {
+    "1" => Some(true),
+    "0" => Some(false),
+    s if s.eq_ignore_ascii_case("true") => Some(true),
+    s if s.eq_ignore_ascii_case("false") => Some(false),
+    _ => None,
+  }
+}
+
+/// Set the flag; `false` writes an explicit "0" as iTunes does.
+pub fn set(tag: &mut Tag, on: bool) {
+  tag.insert_text(ItemKey::FlagCompilation, if on { "1" } else { "0" }.into());
+}
+
+pub fn is_various(name: &str) -> bool { VARIOUS.contains(&fold_str(name.trim()).as_str()) }
+
+/// `name` unless it's the "Various Artists" placeholder of a flagged compilation.
+pub fn artist_for_counts(name: Option<&str>, compilation: Option<bool>) -> Option<&str> {
+  name.filter(|n| compilation != Some(true) || !is_various(n))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{fs, path::{Path, PathBuf}};
+  use lofty::{TagExt, TagType, TaggedFileExt};
+  use crate::{meta_cache, test_support, zip_export::template_base};
+
+  fn atom(kind: &[u8], body: &[u8]) -> Vec<u8> {
+    [&(body.len() as u32 + 8).to_be_bytes()[..], kind, body].concat()
+  }
+
+  /// An `ilst` data atom: type 1 is UTF-8, 21 a big-endian integer.
+  fn data(kind: u32, value: &[u8]) -> Vec<u8> { atom(b"data", &[&kind.to_be_bytes()[..], &[0; 4], value].concat()) }
+
+  /// An M4A laid out as iTunes writes a compilation track: an audio trak,
+  /// then udta/meta/ilst with ©ART, aART, ©nam and cpil.
+  fn itunes_m4a(dir: &Path, name: &str) -> PathBuf {
+    let mdhd = atom(b"mdhd", &[&[0u8; 12][..], &44100u32.to_be_bytes(), &44100u32.to_be_bytes(), &[0; 4]].concat());
+    let hdlr = atom(b"hdlr", &[&[0u8; 8][..], b"soun", &[0; 12], b"\0"].concat());
+    let trak = atom(b"trak", &atom(b"mdia", &[mdhd, hdlr].concat()));
+    let ilst = atom(b"ilst", &[
+      atom(b"\xA9ART", &data(1, b"Artist")),
+      atom(b"aART", &data(1, b"Various Artists")),
+      atom(b"\xA9nam", &data(1, b"Title")),
+      atom(b"cpil", &data(21, &[1])),
+    ].concat());
+    let meta_hdlr = atom(b"hdlr", &[&[0u8; 8][..], b"mdir", b"appl", &[0; 8], b"\0"].concat());
+    let udta = atom(b"udta", &atom(b"meta", &[&[0u8; 4][..], &meta_hdlr, &ilst].concat()));
+    let moov = atom(b"moov", &[trak, udta].concat());
+    let p = dir.join(name);
+    fs::write(&p, [atom(b"ftyp", b"M4A \0\0\0\0M4A mp42isom"), moov, atom(b"mdat", &[0; 512])].concat()).unwrap();
+    p
+  }
+
+  /// The fixtures, each the way its usual ripper flags a compilation track.
+  fn fixtures(dir: &Path) -> Vec<PathBuf> {
+    let items = [(ItemKey::TrackArtist, "Artist"), (ItemKey::AlbumArtist, "Various Artists"), (ItemKey::TrackTitle, "Title"), (ItemKey::FlagCompilation, "1")];
+    // iTunes MP3 rips are ID3v2.3 with TCMP.
+    let mp3 = test_support::audio(dir, "itunes.mp3");
+    test_support::id3v23(&mp3, &[("TPE1", "Artist"), ("TPE2", "Various Artists"), ("TIT2", "Title"), ("TCMP", "1")]);
+    vec![mp3, itunes_m4a(dir, "itunes.m4a"), test_support::tagged(dir, "itunes.aiff", &items), test_support::tagged(dir, "rip.flac", &items)]
+  }
+
+  #[test]
+  fn the_flag_reads_from_every_format() {
+    let dir = test_support::scratch("compilation");
+    for p in fixtures(&dir) {
+      let m = meta_cache::get(&p).unwrap_or_else(|e| panic!("{}: {}", p.display(), e));
+      assert_eq!((m.compilation, m.artist.as_deref()), (Some(true), Some("Artist")), "{}", p.display());
+      assert_eq!(artist_for_counts(m.artist.as_deref(), m.compilation), Some("Artist"));
+    }
+    let plain = test_support::tagged(&dir, "plain.mp3", &[(ItemKey::TrackTitle, "Title")]);
+    assert_eq!(meta_cache::get(&plain).unwrap().compilation, None);
+  }
+
+  #[test]
+  fn templates_fall_back_to_the_track_artist_on_compilations() {
+    let dir = test_support::scratch("compilation-names");
+    for p in fixtures(&dir) {
+      assert_eq!(template_base(&p, "{albumartist|artist} - {title}", 0), "Artist - Title", "{}", p.display());
+    }
+    let unflagged = test_support::tagged(&dir, "va.mp3", &[(ItemKey::TrackArtist, "Artist"), (ItemKey::AlbumArtist, "Various Artists"), (ItemKey::TrackTitle, "Title")]);
+    assert_eq!(template_base(&unflagged, "{albumartist|artist} - {title}", 0), "Various Artists - Title");
+  }
+
+  #[test]
+  fn various_artists_is_only_dropped_from_flagged_files() {
+    for name in ["Various Artists", "various", "VA", "V.A.", "v/a", " Various Artists "] { assert!(is_various(name), "{}", name); }
+    assert!(!is_various("Vanilla Ice"));
+    assert_eq!(artist_for_counts(Some("Various Artists"), Some(true)), None);
+    assert_eq!(artist_for_counts(Some("Various Artists"), Some(false)), Some("Various Artists"));
+    assert_eq!(artist_for_counts(Some("Various Artists"), None), Some("Various Artists"));
+  }
+
+  #[test]
+  fn set_writes_one_or_an_explicit_zero() {
+    let dir = test_support::scratch("compilation-set");
+    let p = test_support::audio(&dir, "a.mp3");
+    for on in [true, false] {
+      let mut tag = Tag::new(TagType::Id3v2);
+      set(&mut tag, on);
+      tag.save_to_path(&p).unwrap();
+      assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::FlagCompilation).as_deref(), Some(if on { "1" } else { "0" }));
+      let tf = lofty::read_from_path(&p).unwrap();
+      assert_eq!(tf.tag(TagType::Id3v2).and_then(of_tag), Some(on));
+    }
+  }
+}
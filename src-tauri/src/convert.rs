@@ -0,0 +1,412 @@
+// Delivery copies in another format (promoters want AIFF/WAV or 320 MP3s,
+// masters are FLAC). Any source symphonia decodes (MP3 included) is written
+// as PCM or encoded to CBR MP3 with LAME, then the source's tags and
+// artwork are copied over through lofty's generic items, which maps keys to
+// the target's tag type on save, with the source's field locks. Strictly
+// additive: sources are only read, and existing outputs are kept unless
+// `overwrite` is set.
+
+use std::{fs, io::{BufWriter, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+use lofty::{AudioFile, Tag, TaggedFileExt};
+use mp3lame_encoder::{Bitrate, InterleavedPcm, MonoPcm, Quality};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  command_span, decode, edit_tags_untracked, error::CmdError, field_limits::FieldLimitHit, field_locks, jobs::JobHandle, log_line, long_paths, preferred_tag, preflight::{self, Preflight}, read_tagged,
+  zip_export::template_base,
+};
+
+/// Tags and artwork copied onto each output, for the space estimate.
+const TAG_ALLOWANCE: u64 = 1 << 20;
+
+/// Constant bitrates offered for MP3, in kbps.
+const MP3_BITRATES: [u32; 4] = [128, 192, 256, 320];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetFormat { Aiff, Wav, Mp3 }
+
+impl TargetFormat {
+  fn ext(self) -> &'static str {
+    match self { TargetFormat::Aiff => "aiff", TargetFormat::Wav => "wav", TargetFormat::Mp3 => "mp3" }
+  }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ConvertOptions {
+  /// 16 or 24; default is the source's width when it is one of those, else 16.
+  /// PCM targets only.
+  bit_depth: Option<u16>,
+  /// MP3 only: one of MP3_BITRATES; default 320.
+  bitrate: Option<u32>,
+  /// Output name without extension, as in zip export (see `template_base`).
+  name_template: Option<String>,
+  /// Replace outputs that already exist. Off by default.
+  overwrite: bool,
+  /// Only run the pre-flight space and permission check.
+  dry_run: bool,
+}
+
+/// What was actually written, so the delivery can be documented.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodeParams {
+  format: TargetFormat,
+  sample_rate: u32,
+  channels: usize,
+  /// 16 for MP3: the width the encoder is fed.
+  bit_depth: u16,
+  /// MP3 only, kbps.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  bitrate: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertResult {
+  path: String,
+  output: Option<String>,
+  params: Option<EncodeParams>,
+  /// Tags couldn't be carried over; the audio was still written.
+  tag_warning: Option<String>,
+  /// Tag values cut or left out for the target's length limits (see `field_limits`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<FieldLimitHit>,
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertReport {
+  dest_dir: String,
+  files: Vec<ConvertResult>,
+  cancelled: bool,
+  /// Estimated output size against free space in `dest_dir` (see `preflight`).
+  preflight: Preflight,
+}
+
+/// Header with placeholder sizes, PCM frames, then the sizes patched in.
+struct PcmWriter {
+  w: BufWriter<fs::File>,
+  format: TargetFormat,
+  channels: usize,
+  bit_depth: u16,
+  frames: u64,
+}
+
+/// 80-bit IEEE extended, as AIFF's COMM chunk stores the sample rate.
+fn extended(rate: u32) -> [u8; 10] {
+  let mut out = [0u8; 10];
+  if rate == 0 { return out; }
+  let lz = (rate as u64).leading_zeros();
+  out[..2].copy_from_slice(&(16383 + 63 - lz as u16).to_be_bytes());
+  out[2..].copy_from_slice(&((rate as u64) << lz).to_be_bytes());
+  out
+}
+
+impl PcmWriter {
+  fn create(path: &Path, format: TargetFormat, info: &decode::StreamInfo, bit_depth: u16) -> Result<Self, String> {
+    let mut w = BufWriter::new(fs::File::create(long_paths::extended(path)).map_err(|e| e.to_string())?);
+    let ch = info.channels as u16;
+    let block = ch * (bit_depth / 8);
+    let mut head: Vec<u8> = Vec::new();
+    match format {
+      TargetFormat::Wav => {
+        head.extend_from_slice(b"RIFF\0\0\0\0WAVEfmt ");
+        head.extend_from_slice(&16u32.to_le_bytes());
+        head.extend_from_slice(&1u16.to_le_bytes());
+        head.extend_from_slice(&ch.to_le_bytes());
+        head.extend_from_slice(&info.sample_rate.to_le_bytes());
+        head.extend_from_slice(&(info.sample_rate * block as u32).to_le_bytes());
+        head.extend_from_slice(&block.to_le_bytes());
+        head.extend_from_slice(&bit_depth.to_le_bytes());
+        head.extend_from_slice(b"data\0\0\0\0");
+      }
+      TargetFormat::Aiff => {
+        head.extend_from_slice(b"FORM\0\0\0\0AIFFCOMM");
+        head.extend_from_slice(&18u32.to_be_bytes());
+        head.extend_from_slice(&ch.to_be_bytes());
+        head.extend_from_slice(&0u32.to_be_bytes());
+        head.extend_from_slice(&bit_depth.to_be_bytes());
+        head.extend_from_slice(&extended(info.sample_rate));
+        head.extend_from_slice(b"SSND\0\0\0\0");
+        head.extend_from_slice(&[0u8; 8]);
+      }
+      TargetFormat::Mp3 => return Err("not a PCM format".into()),
+    }
+    w.write_all(&head).map_err(|e| e.to_string())?;
+    Ok(Self { w, format, channels: info.channels, bit_depth, frames: 0 })
+  }
+
+  fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+    for &s in samples {
+      let s = s.clamp(-1.0, 1.0) as f64;
+      match (self.bit_depth, self.format) {
+        (24, TargetFormat::Wav) => self.w.write_all(&((s * 8_388_607.0).round() as i32).to_le_bytes()[..3])?,
+        (24, _) => self.w.write_all(&((s * 8_388_607.0).round() as i32).to_be_bytes()[1..])?,
+        (_, TargetFormat::Wav) => self.w.write_all(&((s * 32_767.0).round() as i16).to_le_bytes())?,
+        _ => self.w.write_all(&((s * 32_767.0).round() as i16).to_be_bytes())?,
+      }
+    }
+    self.frames += (samples.len() / self.channels) as u64;
+    Ok(())
+  }
+
+  fn finish(mut self) -> Result<(), String> {
+    let data = self.frames * self.channels as u64 * (self.bit_depth / 8) as u64;
+    let pad = data % 2;
+    if pad == 1 { self.w.write_all(&[0]).map_err(|e| e.to_string())?; }
+    let too_big = || format!("output over 4 GiB ({} bytes of audio)", data);
+    let mut patch = |at: u64, bytes: &[u8]| -> std::io::Result<()> {
+      self.w.seek(SeekFrom::Start(at))?;
+      self.w.write_all(bytes)
+    };
+    let res = match self.format {
+      TargetFormat::Wav => {
+        let riff = u32::try_from(36 + data + pad).map_err(|_| too_big())?;
+        patch(4, &riff.to_le_bytes()).and_then(|_| patch(40, &(data as u32).to_le_bytes()))
+      }
+      _ => {
+        let form = u32::try_from(4 + 26 + 16 + data + pad).map_err(|_| too_big())?;
+        patch(4, &form.to_be_bytes())
+          .and_then(|_| patch(22, &(self.frames as u32).to_be_bytes()))
+          .and_then(|_| patch(42, &(8 + data as u32).to_be_bytes()))
+      }
+    };
+    res.and_then(|_| self.w.flush()).map_err(|e| e.to_string())?;
+    self.w.get_ref().sync_all().map_err(|e| e.to_string())
+  }
+}
+
+/// LAME fed 16-bit samples, frames appended to the file as they come out.
+struct Mp3Writer {
+  w: BufWriter<fs::File>,
+  enc: mp3lame_encoder::Encoder,
+  channels: usize,
+  pcm: Vec<i16>,
+  out: Vec<u8>,
+}
+
+impl Mp3Writer {
+  fn create(path: &Path, info: &decode::StreamInfo, kbps: u32) -> Result<Self, String> {
+    if info.channels > 2 { return Err(format!("MP3 takes mono or stereo; the source has {} channels", info.channels)); }
+    let lame = |e: mp3lame_encoder::BuildError| format!("MP3 encoder: {}", e);
+    let mut b = mp3lame_encoder::Builder::new().ok_or("MP3 encoder couldn't start")?;
+    b.set_num_channels(info.channels as u8).map_err(lame)?;
+    b.set_sample_rate(info.sample_rate).map_err(lame)?;
+    b.set_brate(match kbps { 128 => Bitrate::Kbps128, 192 => Bitrate::Kbps192, 256 => Bitrate::Kbps256, _ => Bitrate::Kbps320 }).map_err(lame)?;
+    b.set_quality(Quality::Best).map_err(lame)?;
+    let enc = b.build().map_err(lame)?;
+    let w = BufWriter::new(fs::File::create(long_paths::extended(path)).map_err(|e| e.to_string())?);
+    Ok(Self { w, enc, channels: info.channels, pcm: Vec::new(), out: Vec::new() })
+  }
+
+  fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+    self.pcm.clear();
+    self.pcm.extend(samples.iter().map(|s| (s.clamp(-1.0, 1.0) as f64 * 32_767.0).round() as i16));
+    self.out.clear();
+    self.out.reserve(mp3lame_encoder::max_required_buffer_size(self.pcm.len() / self.channels));
+    let res = match self.channels {
+      1 => self.enc.encode_to_vec(MonoPcm(&self.pcm), &mut self.out),
+      _ => self.enc.encode_to_vec(InterleavedPcm(&self.pcm), &mut self.out),
+    };
+    res.map_err(|e| format!("MP3 encoder: {}", e))?;
+    self.w.write_all(&self.out).map_err(|e| e.to_string())
+  }
+
+  fn finish(mut self) -> Result<(), String> {
+    self.out.clear();
+    self.out.reserve(7200);
+    self.enc.flush_to_vec::<mp3lame_encoder::FlushNoGap>(&mut self.out).map_err(|e| format!("MP3 encoder: {}", e))?;
+    self.w.write_all(&self.out).and_then(|_| self.w.flush()).map_err(|e| e.to_string())?;
+    self.w.get_ref().sync_all().map_err(|e| e.to_string())
+  }
+}
+
+/// Where `convert_one` writes the decoded audio.
+enum Output {
+  Pcm(PcmWriter),
+  Mp3(Mp3Writer),
+}
+
+impl Output {
+  fn create(path: &Path, format: TargetFormat, info: &decode::StreamInfo, bit_depth: u16, kbps: u32) -> Result<Self, String> {
+    match format {
+      TargetFormat::Mp3 => Mp3Writer::create(path, info, kbps).map(Output::Mp3),
+      _ => PcmWriter::create(path, format, info, bit_depth).map(Output::Pcm),
+    }
+  }
+
+  fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+    match self {
+      Output::Pcm(w) => w.write(samples).map_err(|e| e.to_string()),
+      Output::Mp3(w) => w.write(samples),
+    }
+  }
+
+  fn finish(self) -> Result<(), String> {
+    match self { Output::Pcm(w) => w.finish(), Output::Mp3(w) => w.finish() }
+  }
+}
+
+/// Every item and picture of the source's preferred tag into `dst`. Keys the
+/// target tag type has no mapping for are dropped by lofty on insert.
+fn copy_tags(src: &Tag, dst: &mut Tag) {
+  for item in src.items() { dst.insert(item.clone()); }
+  for pic in src.pictures() { dst.push_picture(pic.clone()); }
+}
+
+fn output_path(p: &Path, dir: &Path, template: &str, index: usize, fmt: TargetFormat, taken: &mut Vec<PathBuf>) -> PathBuf {
+  let base = template_base(p, template, index);
+  let mut out = dir.join(format!("{}.{}", base, fmt.ext()));
+  let mut n = 2;
+  // Two sources with the same name in one batch still get two outputs.
+  while taken.iter().any(|t| t.to_string_lossy().eq_ignore_ascii_case(&out.to_string_lossy())) {
+    out = dir.join(format!("{} ({}).{}", base, n, fmt.ext()));
+    n += 1;
+  }
+  taken.push(out.clone());
+  out
+}
+
+/// What `convert_one` wrote: the encode parameters, why the tags didn't
+/// carry over (if so) and the length limits they hit.
+type Converted = (EncodeParams, Option<String>, Vec<FieldLimitHit>);
+
+/// Decode `src` into `out` (via a `.part` file), then carry the tags over.
+fn convert_one(job: &JobHandle, src: &Path, out: &Path, fmt: TargetFormat, opts: &ConvertOptions) -> Result<Converted, String> {
+  if out.exists() && !opts.overwrite { return Err(format!("{} already exists", out.display())); }
+  if out.exists() && fs::canonicalize(src).ok() == fs::canonicalize(out).ok() { return Err("output would replace the source".into()); }
+  let part = out.with_extension(format!("{}.part", fmt.ext()));
+  let mut writer: Option<Output> = None;
+  let kbps = opts.bitrate.unwrap_or(320);
+  let mut params: Option<EncodeParams> = None;
+  let mut write_err: Option<String> = None;
+  let res = decode::decode_f32(src, Some(job.cancel_flag()), |info, samples| {
+    if writer.is_none() {
+      let depth = opts.bit_depth.or(info.bits_per_sample.map(|b| b as u16)).filter(|d| *d == 24 && fmt != TargetFormat::Mp3).unwrap_or(16);
+      match Output::create(&part, fmt, info, depth, kbps) {
+        Ok(w) => writer = Some(w),
+        Err(e) => { write_err = Some(e); return false; }
+      }
+      let bitrate = (fmt == TargetFormat::Mp3).then_some(kbps);
+      params = Some(EncodeParams { format: fmt, sample_rate: info.sample_rate, channels: info.channels, bit_depth: depth, bitrate });
+    }
+    match writer.as_mut().map(|w| w.write(samples)) {
+      Some(Err(e)) => { write_err = Some(e); false }
+      _ => true,
+    }
+  });
+  let finished = res.and(write_err.map_or(Ok(()), Err))
+    .and_then(|_| writer.ok_or_else(|| "no audio decoded".to_string()))
+    .and_then(Output::finish)
+    .and_then(|_| fs::rename(long_paths::extended(&part), long_paths::extended(out)).map_err(|e| e.to_string()));
+  if let Err(e) = finished {
+    let _ = fs::remove_file(long_paths::extended(&part));
+    return Err(e);
+  }
+
+  // Locks left from an overwritten output would keep the fresh file's fields empty.
+  let mut limited = Vec::new();
+  let tag_warning = field_locks::clear(out).and_then(|_| read_tagged(src).map_err(|e| e.to_string())).and_then(|tf| {
+    let Some(src_tag) = preferred_tag(&tf, src).or_else(|| tf.tags().first()) else { return Ok(()) };
+    // A fresh output: not one of the user's files, so not recorded as touched.
+    limited = edit_tags_untracked(out, |dst| copy_tags(src_tag, dst)).map_err(String::from)?.limited;
+    Ok(())
+  }).and_then(|_| field_locks::copy(src, out)).err();
+  Ok((params.unwrap_or(EncodeParams { format: fmt, sample_rate: 0, channels: 0, bit_depth: 16, bitrate: None }), tag_warning, limited))
+}
+
+/// PCM bytes `src` decodes to at the depth `convert_one` picks (MP3: its
+/// duration at the bitrate), plus TAG_ALLOWANCE.
+fn estimated_size(src: &Path, fmt: TargetFormat, opts: &ConvertOptions) -> u64 {
+  let Ok(tf) = read_tagged(src) else {
+    // Unreadable sources fail on their own later; guess a lossless ratio.
+    return fs::metadata(long_paths::extended(src)).map(|m| m.len() * 2).unwrap_or(0);
+  };
+  let props = tf.properties();
+  if fmt == TargetFormat::Mp3 {
+    return (props.duration().as_secs_f64() * opts.bitrate.unwrap_or(320) as f64 * 125.0) as u64 + TAG_ALLOWANCE;
+  }
+  let depth = opts.bit_depth.or(props.bit_depth().map(u16::from)).filter(|d| *d == 24).unwrap_or(16);
+  let frames = props.duration().as_secs_f64() * props.sample_rate().unwrap_or(44_100) as f64;
+  (frames * props.channels().unwrap_or(2) as f64 * (depth / 8) as f64) as u64 + TAG_ALLOWANCE
+}
+
+fn convert_blocking(job: &JobHandle, paths: &[String], fmt: TargetFormat, opts: &ConvertOptions, dest_dir: &str) -> Result<ConvertReport, CmdError> {
+  if let Some(b) = opts.bitrate.filter(|b| !MP3_BITRATES.contains(b)) { return Err(format!("unsupported MP3 bitrate {} (128, 192, 256 or 320 kbps)", b).into()); }
+  if let Some(d) = opts.bit_depth.filter(|d| *d != 16 && *d != 24) { return Err(format!("unsupported bit depth {} (16 or 24)", d).into()); }
+  let dir = PathBuf::from(dest_dir);
+  let preflight = job.timed("preflight", || preflight::copies(&dir, paths.iter().map(|p| estimated_size(Path::new(p), fmt, opts)).sum(), None));
+  if opts.dry_run { return Ok(ConvertReport { dest_dir: dest_dir.to_string(), files: Vec::new(), cancelled: false, preflight }); }
+  preflight::ensure(&preflight)?;
+  fs::create_dir_all(&dir).map_err(|e| CmdError::from_io(&dir, &e))?;
+  let template = opts.name_template.clone().filter(|t| !t.trim().is_empty()).unwrap_or_else(|| "{name}".into());
+  let mut taken = Vec::new();
+  let mut files = Vec::new();
+  let mut cancelled = false;
+  for (i, path) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let src = Path::new(path);
+    let out = output_path(src, &dir, &template, i, fmt, &mut taken);
+    let mut r = ConvertResult { path: path.clone(), output: None, params: None, tag_warning: None, limited: Vec::new(), error: None };
+    match convert_one(job, src, &out, fmt, opts) {
+      Ok((params, warning, limited)) => {
+        (r.output, r.params) = (Some(out.to_string_lossy().to_string()), Some(params));
+        (r.tag_warning, r.limited) = (warning, limited);
+      }
+      Err(_) if job.is_cancelled() => { cancelled = true; break; }
+      Err(e) => r.error = Some(e),
+    }
+    files.push(r);
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  let failed = files.iter().filter(|f| f.error.is_some()).count();
+  log_line(&format!("convert_files format={} dest=\"{}\" files={} failed={} cancelled={}", fmt.ext(), dest_dir, files.len(), failed, cancelled));
+  Ok(ConvertReport { dest_dir: dest_dir.to_string(), files, cancelled, preflight })
+}
+
+/// Write converted copies of `paths` into `dest_dir` (job kind "convert").
+/// Cancelling stops after removing the partial output of the current file.
+#[tauri::command]
+pub async fn convert_files(app: tauri::AppHandle, paths: Vec<String>, target_format: TargetFormat, options: Option<ConvertOptions>, dest_dir: String) -> Result<ConvertReport, CmdError> {
+  let _span = command_span("convert_files");
+  let opts = options.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "convert", &dest_dir);
+    let res = convert_blocking(&job, &paths, target_format, &opts, &dest_dir);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support;
+
+  #[test]
+  fn mp3_outputs_are_sized_by_bitrate() {
+    let dir = test_support::scratch("convert");
+    let src = test_support::audio(&dir, "a.flac");
+    let kbps = |b| ConvertOptions { bitrate: Some(b), ..Default::default() };
+    let secs = read_tagged(&src).unwrap().properties().duration().as_secs_f64();
+    assert_eq!(estimated_size(&src, TargetFormat::Mp3, &kbps(320)), (secs * 40_000.0) as u64 + TAG_ALLOWANCE);
+    assert!(estimated_size(&src, TargetFormat::Mp3, &kbps(128)) < estimated_size(&src, TargetFormat::Mp3, &kbps(320)));
+    // 4410 stereo frames at 16 bits.
+    assert_eq!(estimated_size(&src, TargetFormat::Wav, &kbps(320)), 17_640 + TAG_ALLOWANCE);
+  }
+
+  #[test]
+  fn mp3_takes_mono_or_stereo() {
+    let dir = test_support::scratch("convert");
+    let info = decode::StreamInfo { sample_rate: 48_000, channels: 6, n_frames: None, bits_per_sample: Some(24) };
+    let err = Output::create(&dir.join("a.mp3.part"), TargetFormat::Mp3, &info, 16, 320).err().unwrap();
+    assert!(err.contains("6 channels"), "{}", err);
+    assert!(!dir.join("a.mp3.part").exists());
+  }
+}
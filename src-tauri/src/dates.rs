@@ -0,0 +1,155 @@
+// Release / original release dates. Stored as ISO 8601 with whatever
+// precision is really known (yyyy-mm-dd, yyyy-mm or yyyy). Files in the wild
+// carry "2021-00-00", "20210305", "2021/3/5", "05.03.2021" or full
+// timestamps; `normalize_date` maps all of those onto the ISO form.
+//
+// lofty maps RecordingDate to TDRC (ID3v2.4), DATE (Vorbis), ©day (MP4) and
+// ICRD (RIFF), and OriginalReleaseDate to TDOR / ORIGINALDATE. ID3v2.3 files
+// are upgraded on read (TYER -> TDRC, TORY -> TDOR); the v2.3 TDAT day/month
+// frame is kept as an unknown item and merged in here. Writes are always
+// ID3v2.4, which has no TDAT, so `fold_tdat` turns TYER+TDAT into one full
+// TDRC whenever such a tag is saved.
+
+use chrono::NaiveDate;
+use lofty::{ItemKey, Tag};
+
+fn valid_ymd(y: i32, m: u32, d: u32) -> Option<String> {
+  if !(1000..=9999).contains(&y) { return None; }
+  if !(1..=12).contains(&m) { return Some(format!("{:04}", y)); }
+  if NaiveDate::from_ymd_opt(y, m, d).is_none() { return Some(format!("{:04}-{:02}", y, m)); }
+  Some(format!("{:04}-{:02}-{:02}", y, m, d))
+}
+
+/// Best-effort ISO date, degrading to yyyy-mm / yyyy when parts are missing
+/// or zero. `None` when no plausible year is present.
+pub fn normalize_date(raw: &str) -> Option<String> {
+  let s = raw.trim();
+  // Drop a time part ("2021-03-05T12:00:00", "2021-03-05 12:00").
+  let s = s.split(['T', ' ']).next().unwrap_or("");
+  let groups: Vec<&str> = s.split(|c: char| !c.is_ascii_digit()).filter(|g| !g.is_empty()).collect();
+  let num = |g: &str| g.parse::<u32>().ok();
+
+  match groups.as_slice() {
+    // Compact forms: 20210305, 202103, 2021
+    [g] if g.len() == 8 => valid_ymd(g[..4].parse().ok()?, num(&g[4..6])?, num(&g[6..8])?),
+    [g] if g.len() == 6 => valid_ymd(g[..4].parse().ok()?, num(&g[4..6])?, 0),
+    [g] if g.len() == 4 => valid_ymd(g.parse().ok()?, 0, 0),
+    [y, m] if y.len() == 4 => valid_ymd(y.parse().ok()?, num(m)?, 0),
+    [y, m, d, ..] if y.len() == 4 => valid_ymd(y.parse().ok()?, num(m)?, num(d)?),
+    // Day-first European form: 05.03.2021
+    [d, m, y] if y.len() == 4 && d.len() <= 2 => valid_ymd(y.parse().ok()?, num(m)?, num(d)?),
+    _ => None,
+  }
+}
+
+/// Recording/release date of a tag, merging an ID3v2.3 TDAT (DDMM) into a year-only TDRC.
+pub fn release_date(tag: &Tag) -> Option<String> {
+  let raw = tag.get_string(&ItemKey::RecordingDate).or_else(|| tag.get_string(&ItemKey::Year))?;
+  let date = normalize_date(raw)?;
+  if date.len() == 4 {
+    if let Some(tdat) = tag.get_string(&ItemKey::Unknown("TDAT".into())).filter(|t| t.len() == 4) {
+      if let Some(full) = normalize_date(&format!("{}-{}-{}", date, &tdat[2..], &tdat[..2])) { return Some(full); }
+    }
+  }
+  Some(date)
+}
+
+/// Merge a v2.3 TDAT into the tag's TDRC and drop it (see the header).
+pub fn fold_tdat(tag: &mut Tag) {
+  let tdat = ItemKey::Unknown("TDAT".into());
+  if tag.get_string(&tdat).is_none() { return; }
+  if let Some(full) = release_date(tag) { tag.insert_text(ItemKey::RecordingDate, full); }
+  tag.remove_key(&tdat);
+}
+
+pub fn original_date(tag: &Tag) -> Option<String> {
+  tag.get_string(&ItemKey::OriginalReleaseDate).and_then(normalize_date)
+}
+
+/// Normalize a value for writing; empty clears the field.
+pub fn date_for_write(raw: &str) -> Result<Option<String>, String> {
+  if raw.trim().is_empty() { return Ok(None); }
+  normalize_date(raw).map(Some).ok_or_else(|| format!("not a date: {}", raw))
+}
+
+pub fn set_date(tag: &mut Tag, key: ItemKey, value: Option<&str>) {
+  match value {
+    Some(v) => { tag.insert_text(key.clone(), v.to_string()); }
+    None => tag.remove_key(&key),
+  }
+  // A stale v2.3 day/month would be merged back in on the next read.
+  if key == ItemKey::RecordingDate { tag.remove_key(&ItemKey::Unknown("TDAT".into())); }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{borrow::Cow, fs, path::Path};
+  use lofty::{id3::v2::FrameId, mpeg::MpegFile, AudioFile, ParseOptions, TagType, TaggedFileExt};
+  use crate::{edit_tags, preferred_tag, read_tagged, test_support};
+
+  #[test]
+  fn dates_from_the_wild_are_normalized() {
+    for (raw, want) in [
+      ("2021-03-05", Some("2021-03-05")),
+      ("20210305", Some("2021-03-05")),
+      ("2021-00-00", Some("2021")),
+      ("2021-03-00", Some("2021-03")),
+      ("2021-02-30", Some("2021-02")),
+      ("2021/3/5", Some("2021-03-05")),
+      ("05.03.2021", Some("2021-03-05")),
+      ("2021-03-05T12:00:00", Some("2021-03-05")),
+      ("202103", Some("2021-03")),
+      ("0000", None),
+      ("soon", None),
+    ] {
+      assert_eq!(normalize_date(raw).as_deref(), want, "{}", raw);
+    }
+  }
+
+  fn dates_of(p: &Path) -> (Option<String>, Option<String>) {
+    let tf = read_tagged(p).unwrap();
+    let tag = preferred_tag(&tf, p);
+    (tag.and_then(release_date), tag.and_then(original_date))
+  }
+
+  #[test]
+  fn dates_round_trip_in_every_format() {
+    let dir = test_support::scratch("dates-formats");
+    // RIFF INFO, a WAV's preferred tag, has no original date.
+    for (name, kept) in [("a.mp3", true), ("a.flac", true), ("a.wav", false), ("a.aiff", true)] {
+      let p = test_support::tagged(&dir, name, &[(ItemKey::TrackTitle, "T")]);
+      let (release, original) = (date_for_write("20210305").unwrap(), date_for_write("1998-00-00").unwrap());
+      edit_tags(&p, |tag| {
+        set_date(tag, ItemKey::RecordingDate, release.as_deref());
+        set_date(tag, ItemKey::OriginalReleaseDate, original.as_deref());
+      }).unwrap();
+      assert_eq!(dates_of(&p), (Some("2021-03-05".into()), kept.then(|| "1998".into())), "{}", name);
+    }
+  }
+
+  fn id3v2_text(p: &Path, id: &'static str) -> Option<String> {
+    let mpeg = MpegFile::read_from(&mut fs::File::open(p).unwrap(), ParseOptions::new()).unwrap();
+    mpeg.id3v2().and_then(|t| t.get_text(&FrameId::Valid(Cow::Borrowed(id))).map(str::to_string))
+  }
+
+  #[test]
+  fn a_v23_year_and_day_read_as_one_date_and_save_as_tdrc() {
+    let dir = test_support::scratch("dates-v23");
+    let p = test_support::audio(&dir, "a.mp3");
+    test_support::id3v23(&p, &[("TIT2", "Old"), ("TYER", "2021"), ("TDAT", "0503"), ("TORY", "1998")]);
+    assert_eq!(dates_of(&p), (Some("2021-03-05".into()), Some("1998".into())));
+
+    // Saving for another field writes v2.4: the day moves into TDRC.
+    edit_tags(&p, |tag| { tag.insert_text(ItemKey::TrackTitle, "New".into()); }).unwrap();
+    assert_eq!((id3v2_text(&p, "TDRC").as_deref(), id3v2_text(&p, "TDAT")), (Some("2021-03-05"), None));
+    assert_eq!(dates_of(&p).0.as_deref(), Some("2021-03-05"));
+    assert!(read_tagged(&p).unwrap().tag(TagType::Id3v2).is_some());
+
+    // A new date replaces both frames.
+    let q = test_support::audio(&dir, "b.mp3");
+    test_support::id3v23(&q, &[("TYER", "2021"), ("TDAT", "0503")]);
+    edit_tags(&q, |tag| set_date(tag, ItemKey::RecordingDate, Some("2022-07"))).unwrap();
+    assert_eq!((id3v2_text(&q, "TDRC").as_deref(), id3v2_text(&q, "TDAT")), (Some("2022-07"), None));
+  }
+}
@@ -0,0 +1,133 @@
+// PCM decoding via symphonia, for analysis only (peaks, renders, audio hashes). Playback
+// stays in the webview through the media server.
+
+use std::{fs::File, path::Path, sync::atomic::{AtomicBool, Ordering}};
+use sha2::{Digest, Sha256};
+use symphonia::core::{
+  audio::SampleBuffer,
+  codecs::{CodecParameters, DecoderOptions, CODEC_TYPE_NULL},
+  errors::Error as SymError,
+  formats::{FormatOptions, FormatReader},
+  io::MediaSourceStream,
+  meta::MetadataOptions,
+  probe::Hint,
+};
+
+use crate::long_paths;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamInfo {
+  pub sample_rate: u32,
+  pub channels: usize,
+  /// Total frames if the container declares it (used for progress and bucket sizing).
+  pub n_frames: Option<u64>,
+  /// Source sample width when the codec declares one (FLAC, PCM).
+  pub bits_per_sample: Option<u32>,
+}
+
+fn open_track(path: &Path) -> Result<(Box<dyn FormatReader>, u32, CodecParameters), String> {
+  let file = File::open(long_paths::extended(path)).map_err(|e| e.to_string())?;
+  let mss = MediaSourceStream::new(Box::new(file), Default::default());
+  let mut hint = Hint::new();
+  if let Some(ext) = path.extension().and_then(|e| e.to_str()) { hint.with_extension(ext); }
+
+  let probed = symphonia::default::get_probe()
+    .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+    .map_err(|e| format!("unsupported audio: {}", e))?;
+  let format = probed.format;
+  let track = format
+    .tracks()
+    .iter()
+    .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+    .ok_or("no decodable audio track")?;
+  let (id, params) = (track.id, track.codec_params.clone());
+  Ok((format, id, params))
+}
+
+/// What the container headers say about the first audio track.
+#[derive(Debug, Clone, Default)]
+pub struct ProbedStream {
+  /// Short name, e.g. "aac" or "pcm_s16le".
+  pub codec: Option<String>,
+  pub sample_rate: Option<u32>,
+  pub channels: Option<usize>,
+  pub duration_secs: Option<f64>,
+}
+
+/// Headers only, no decoding.
+pub fn probe_stream(path: &Path) -> Result<ProbedStream, String> {
+  let (_, _, params) = open_track(path)?;
+  Ok(ProbedStream {
+    codec: symphonia::default::get_codecs().get_codec(params.codec).map(|d| d.short_name.to_string()),
+    sample_rate: params.sample_rate,
+    channels: params.channels.map(|c| c.count()),
+    duration_secs: params.n_frames.zip(params.sample_rate).filter(|(_, r)| *r > 0).map(|(n, r)| n as f64 / r as f64),
+  })
+}
+
+/// SHA-256 over the audio track's packet payloads (no decoding). Tags live
+/// outside the packets, so the hash survives retagging and tag stripping.
+pub fn audio_hash(path: &Path, cancel: Option<&AtomicBool>) -> Result<String, String> {
+  let (mut format, track_id, _) = open_track(path)?;
+  let mut h = Sha256::new();
+  loop {
+    if cancel.is_some_and(|c| c.load(Ordering::Relaxed)) { return Err("cancelled".into()); }
+    match format.next_packet() {
+      Ok(p) if p.track_id() == track_id => h.update(p.buf()),
+      Ok(_) => {}
+      Err(SymError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+      Err(SymError::ResetRequired) => break,
+      Err(e) => return Err(e.to_string()),
+    }
+  }
+  Ok(h.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Decode the default track to interleaved f32, handing each decoded block to
+/// `on_block`. Return `false` from the callback (or set `cancel`) to stop early;
+/// a cancelled decode is an error so callers don't cache partial results.
+pub fn decode_f32<F>(path: &Path, cancel: Option<&AtomicBool>, mut on_block: F) -> Result<StreamInfo, String>
+where
+  F: FnMut(&StreamInfo, &[f32]) -> bool,
+{
+  let (mut format, track_id, params) = open_track(path)?;
+  let mut decoder = symphonia::default::get_codecs()
+    .make(&params, &DecoderOptions::default())
+    .map_err(|e| format!("unsupported codec: {}", e))?;
+
+  let mut info = StreamInfo {
+    sample_rate: params.sample_rate.unwrap_or(44_100),
+    channels: params.channels.map(|c| c.count()).unwrap_or(2).max(1),
+    n_frames: params.n_frames,
+    bits_per_sample: params.bits_per_sample,
+  };
+  let mut buf: Option<SampleBuffer<f32>> = None;
+
+  loop {
+    if cancel.is_some_and(|c| c.load(Ordering::Relaxed)) { return Err("cancelled".into()); }
+    let packet = match format.next_packet() {
+      Ok(p) => p,
+      Err(SymError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+      Err(SymError::ResetRequired) => break,
+      Err(e) => return Err(e.to_string()),
+    };
+    if packet.track_id() != track_id { continue; }
+    let decoded = match decoder.decode(&packet) {
+      Ok(d) => d,
+      // Corrupt frame: skip it like players do rather than failing the whole file.
+      Err(SymError::DecodeError(_)) => continue,
+      Err(e) => return Err(e.to_string()),
+    };
+    let spec = *decoded.spec();
+    info.sample_rate = spec.rate;
+    info.channels = spec.channels.count().max(1);
+    let needed = decoded.capacity() as u64;
+    let b = match &mut buf {
+      Some(b) if b.capacity() as u64 >= needed * info.channels as u64 => b,
+      _ => buf.insert(SampleBuffer::<f32>::new(needed, spec)),
+    };
+    b.copy_interleaved_ref(decoded);
+    if !on_block(&info, b.samples()) { break; }
+  }
+  Ok(info)
+}
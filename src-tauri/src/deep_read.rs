@@ -0,0 +1,181 @@
+// One-call read for the detail panel. The list only needs `read_metadata`'s
+// light fields; the panel also wants the full-size art, lyrics, every tag
+// type's comment, audio properties and the tag byte sizes. The readers run
+// concurrently on scoped threads and each field carries its own error, so a
+// broken picture block doesn't cost the panel the rest. Embedded pictures are
+// held to `picture_budget`. Writes still waiting in the retry or drive queues
+// overlay the comment read from disk.
+
+use std::path::PathBuf;
+use base64::{engine::general_purpose, Engine as _};
+use lofty::{AudioFile, ItemKey, TaggedFileExt};
+use serde::Serialize;
+
+use crate::{
+  command_span, inspect::tag_type_name, meta_cache, payload_guard::{self, Guarded}, picture_budget, preferred_tag, retry_queue, tag_size::{self, TagSizeReport}, touched,
+  track_meta_from, volumes, TrackMeta,
+};
+
+/// A field that was read (`value`) or failed on its own (`error`).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Field<T> {
+  value: Option<T>,
+  error: Option<String>,
+}
+
+impl<T> From<Result<T, String>> for Field<T> {
+  fn from(r: Result<T, String>) -> Self {
+    match r {
+      Ok(v) => Field { value: Some(v), error: None },
+      Err(e) => Field { value: None, error: Some(e) },
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagComment {
+  tag_type: String,
+  comment: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PictureInfo {
+  /// lofty picture type, e.g. "CoverFront".
+  pic_type: String,
+  mime: Option<String>,
+  bytes: usize,
+  description: Option<String>,
+  /// Full size, as embedded.
+  data_url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioProps {
+  duration_secs: f64,
+  bitrate_kbps: Option<u32>,
+  sample_rate: Option<u32>,
+  bit_depth: Option<u8>,
+  channels: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingOverlay {
+  /// Comment still to be written; `meta.comment` shows it already.
+  comment: String,
+  /// What the file holds right now.
+  on_disk: String,
+  /// "retry" (locked file) or "volume" (drive not connected).
+  queue: &'static str,
+  queued_at: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMetaDeep {
+  path: String,
+  meta: Field<TrackMeta>,
+  lyrics: Field<Option<String>>,
+  comments: Field<Vec<TagComment>>,
+  pictures: Field<Vec<PictureInfo>>,
+  properties: Field<AudioProps>,
+  tag_sizes: Field<TagSizeReport>,
+  pending: Option<PendingOverlay>,
+}
+
+fn pictures(tf: &lofty::TaggedFile) -> Vec<PictureInfo> {
+  tf.tags().iter().flat_map(|t| t.pictures()).map(|pic| {
+    let mime = pic.mime_type().map(|m| m.to_string());
+    PictureInfo {
+      pic_type: format!("{:?}", pic.pic_type()),
+      bytes: pic.data().len(),
+      description: pic.description().map(|s| s.to_string()),
+      data_url: format!("data:{};base64,{}", mime.as_deref().unwrap_or("image/jpeg"), general_purpose::STANDARD.encode(pic.data())),
+      mime,
+    }
+  }).collect()
+}
+
+fn properties(tf: &lofty::TaggedFile) -> AudioProps {
+  let pr = tf.properties();
+  AudioProps {
+    duration_secs: pr.duration().as_secs_f64(),
+    bitrate_kbps: pr.audio_bitrate(),
+    sample_rate: pr.sample_rate(),
+    bit_depth: pr.bit_depth(),
+    channels: pr.channels(),
+  }
+}
+
+/// Newest queued comment for `path`, retry queue first (it's the later write).
+fn pending(path: &str) -> Option<(String, &'static str, String)> {
+  if let Some((comment, at)) = retry_queue::pending_comment(path) { return Some((comment, "retry", at)); }
+  volumes::pending_writes().into_iter().rev().find(|w| w.path == path).map(|w| (w.comment, "volume", w.queued_at))
+}
+
+fn read_deep(path: String) -> TrackMetaDeep {
+  let p = PathBuf::from(&path);
+  // The parse feeds most fields; the byte-level tag scan reads the file on its own.
+  let (parsed, tag_sizes) = std::thread::scope(|s| {
+    let sizes = s.spawn(|| tag_size::tag_size_report(path.clone()));
+    let parsed = picture_budget::read(&p).map_err(|e| e.to_string());
+    (parsed, sizes.join().unwrap_or_else(|_| Err("tag size reader panicked".into())))
+  });
+
+  let (tf, truncated) = match parsed {
+    Ok(read) => read,
+    Err(e) => {
+      return TrackMetaDeep {
+        path,
+        meta: Err(e.clone()).into(),
+        lyrics: Err(e.clone()).into(),
+        comments: Err(e.clone()).into(),
+        pictures: Err(e.clone()).into(),
+        properties: Err(e).into(),
+        tag_sizes: tag_sizes.into(),
+        pending: None,
+      };
+    }
+  };
+  let (mut meta, comments, pics) = std::thread::scope(|s| {
+    let comments = s.spawn(|| tf.tags().iter()
+      .filter_map(|t| t.get_string(&ItemKey::Comment).map(|c| TagComment { tag_type: tag_type_name(t.tag_type()), comment: c.to_string() }))
+      .collect::<Vec<_>>());
+    let pics = s.spawn(|| pictures(&tf));
+    let mut meta = track_meta_from(&path, &tf, true);
+    meta.pictures_truncated = truncated;
+    (meta.last_touched_by_app, meta.externally_modified_since) = touched::status(&p, &tf);
+    let join = |name: &str| format!("{} reader panicked", name);
+    (meta, comments.join().map_err(|_| join("comment")), pics.join().map_err(|_| join("picture")))
+  });
+  meta_cache::store(&p, &tf);
+
+  let lyrics = preferred_tag(&tf, &p).and_then(|t| t.get_string(&ItemKey::Lyrics)).map(|s| s.to_string());
+  let pending = pending(&path).map(|(comment, queue, queued_at)| {
+    let on_disk = std::mem::replace(&mut meta.comment, comment.clone());
+    PendingOverlay { comment, on_disk, queue, queued_at }
+  });
+  TrackMetaDeep {
+    path,
+    meta: Ok(meta).into(),
+    lyrics: Ok(lyrics).into(),
+    comments: comments.into(),
+    pictures: pics.into(),
+    properties: Ok(properties(&tf)).into(),
+    tag_sizes: tag_sizes.into(),
+    pending,
+  }
+}
+
+/// Everything the detail panel shows, in one round trip. Never fails as a
+/// whole: an unreadable file comes back with every field's `error` set.
+/// Large art can push it over the payload cap (see `payload_guard`).
+#[tauri::command]
+pub async fn read_metadata_deep(path: String) -> Result<Guarded<TrackMetaDeep>, String> {
+  let _span = command_span("read_metadata_deep");
+  tauri::async_runtime::spawn_blocking(move || payload_guard::guard("read_metadata_deep", read_deep(path))).await.map_err(|e| e.to_string())?
+}
@@ -0,0 +1,451 @@
+// Which encoder made a file and how, for picking the better of two copies.
+// MP3s carry this in the first audio frame, which lofty doesn't read: a
+// Xing ("Xing" for VBR, "Info" for CBR) or Fraunhofer VBRI header, and after
+// Xing the LAME tag with the encoder version, VBR method, declared preset and
+// the encoder delay and padding. Other formats name their encoder in the tag:
+// the vendor string of Vorbis comments (FLAC, Ogg, Opus), the MP4 ©too atom,
+// TSSE in ID3v2 and ISFT in RIFF INFO, all read through
+// `ItemKey::EncoderSoftware`; MP3s without a LAME or VBRI header fall back to
+// TSSE too. `TrackMeta` takes the summary from the metadata cache, so the
+// headers are read once per change to the file (`meta_cache::encoder`).
+
+use std::{fs, io::{Read, Seek, SeekFrom}, path::Path};
+use lofty::{FileType, ItemKey, TagType, TaggedFileExt};
+use serde::Serialize;
+
+use crate::{command_span, long_paths, read_tagged};
+
+/// Bytes read from the first audio frame on: the headers plus SCAN_FRAMES frames.
+const HEAD: u64 = 64 * 1024;
+/// Without a Xing or VBRI header, a file whose first SCAN_FRAMES frames share
+/// one bitrate counts as CBR.
+const SCAN_FRAMES: usize = 40;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LameTag {
+  /// As written, e.g. "LAME3.100", "Lavc58.91"; trailing padding trimmed.
+  pub version: String,
+  /// "cbr", "abr", "vbr-old", "vbr-mtrh", "vbr-mt", "vbr-m4", "cbr-2pass",
+  /// "abr-2pass" or "unknown".
+  pub vbr_method: &'static str,
+  pub lowpass_hz: Option<u32>,
+  /// The declared preset ("V2", "320 kbps", "extreme"); for VBR files
+  /// without one, the V level the Xing quality implies.
+  pub preset: Option<String>,
+  /// ABR target, CBR bitrate or VBR minimum; 255 means 255 or more.
+  pub bitrate_kbps: Option<u32>,
+  /// Samples of silence the encoder added at the start and end.
+  pub encoder_delay: u32,
+  pub padding: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mp3Info {
+  /// "MPEG-1", "MPEG-2" or "MPEG-2.5".
+  pub version: &'static str,
+  pub layer: u8,
+  pub sample_rate: u32,
+  /// "stereo", "joint", "dual" or "mono".
+  pub channel_mode: &'static str,
+  /// First frame's bitrate; an "Info" file's only one.
+  pub bitrate_kbps: u32,
+  /// "Xing", "Info", "VBRI", or `None` for a bare stream.
+  pub header: Option<&'static str>,
+  pub frames: Option<u32>,
+  pub bytes: Option<u32>,
+  /// Xing or VBRI quality indicator (0-100 for Xing, higher is better).
+  pub quality: Option<u32>,
+  pub lame: Option<LameTag>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodingInfo {
+  path: String,
+  /// Compact name for lists, e.g. "LAME 3.100", "FhG", "reference libFLAC 1.4.3 20230623".
+  encoder: Option<String>,
+  /// Where `encoder` came from: "lameTag", "vbriHeader", "vendor" or "encoderTag".
+  encoder_source: Option<&'static str>,
+  /// MP3 only: variable (or average) bitrate; `None` when it can't be told.
+  vbr: Option<bool>,
+  /// MP3 only.
+  mp3: Option<Mp3Info>,
+}
+
+struct Frame {
+  version: &'static str,
+  layer: u8,
+  mono: bool,
+  channel_mode: &'static str,
+  bitrate_kbps: u32,
+  sample_rate: u32,
+  len: usize,
+}
+
+fn frame(h: &[u8]) -> Option<Frame> {
+  if h.len() < 4 || h[0] != 0xFF || h[1] & 0xE0 != 0xE0 { return None; }
+  let (version, v1) = match (h[1] >> 3) & 3 { 0 => ("MPEG-2.5", false), 2 => ("MPEG-2", false), 3 => ("MPEG-1", true), _ => return None };
+  let layer = match (h[1] >> 1) & 3 { 1 => 3u8, 2 => 2, 3 => 1, _ => return None };
+  let index = (h[2] >> 4) as usize;
+  if index == 0 || index == 15 { return None; }
+  const V1: [[u32; 14]; 3] = [
+    [32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+  ];
+  const V2_L1: [u32; 14] = [32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+  const V2_L23: [u32; 14] = [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+  let bitrate_kbps = match (v1, layer) { (true, l) => V1[l as usize - 1][index - 1], (false, 1) => V2_L1[index - 1], _ => V2_L23[index - 1] };
+  let base = match (h[2] >> 2) & 3 { 0 => 44100, 1 => 48000, 2 => 32000, _ => return None };
+  let sample_rate = match version { "MPEG-1" => base, "MPEG-2" => base / 2, _ => base / 4 };
+  let pad = ((h[2] >> 1) & 1) as u32;
+  let len = match layer {
+    1 => (12 * bitrate_kbps * 1000 / sample_rate + pad) * 4,
+    3 if !v1 => 72 * bitrate_kbps * 1000 / sample_rate + pad,
+    _ => 144 * bitrate_kbps * 1000 / sample_rate + pad,
+  } as usize;
+  let (mono, channel_mode) = match h[3] >> 6 { 0 => (false, "stereo"), 1 => (false, "joint"), 2 => (false, "dual"), _ => (true, "mono") };
+  Some(Frame { version, layer, mono, channel_mode, bitrate_kbps, sample_rate, len })
+}
+
+/// First frame in `b` that another frame follows, so a stray 0xFF in junk
+/// before the audio isn't taken for one.
+fn first_frame(b: &[u8]) -> Option<(usize, Frame)> {
+  (0..b.len().saturating_sub(4)).find_map(|at| {
+    let f = frame(&b[at..])?;
+    let next = b.get(at + f.len..).map(frame);
+    matches!(next, Some(Some(_)) | None).then_some((at, f))
+  })
+}
+
+fn be32(b: &[u8], at: usize) -> Option<u32> { Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?)) }
+
+fn vbr_method(n: u8) -> &'static str {
+  match n {
+    1 => "cbr",
+    2 => "abr",
+    3 => "vbr-old",
+    4 => "vbr-mtrh",
+    5 => "vbr-mt",
+    6 => "vbr-m4",
+    8 => "cbr-2pass",
+    9 => "abr-2pass",
+    _ => "unknown",
+  }
+}
+
+fn preset_name(n: u16) -> Option<String> {
+  Some(match n {
+    8..=320 => format!("{} kbps", n),
+    410..=500 if n.is_multiple_of(10) => format!("V{}", (500 - n) / 10),
+    1000 => "r3mix".into(),
+    1001 => "standard".into(),
+    1002 => "extreme".into(),
+    1003 => "insane".into(),
+    1004 => "fast standard".into(),
+    1005 => "fast extreme".into(),
+    1006 => "medium".into(),
+    1007 => "fast medium".into(),
+    _ => return None,
+  })
+}
+
+/// LAME writes the full tag from 3.90 on (and ffmpeg in the same layout);
+/// before that only the version string.
+fn has_extension(version: &str) -> bool {
+  let Some(v) = version.strip_prefix("LAME") else { return version.starts_with("Lav") };
+  let minor: String = v.strip_prefix("3.").unwrap_or("").chars().take_while(char::is_ascii_digit).collect();
+  !v.starts_with("3.") || minor.parse::<u32>().is_ok_and(|m| m >= 90)
+}
+
+/// The LAME tag at the start of `b`, `quality` being the Xing field.
+fn lame_tag(b: &[u8], quality: Option<u32>) -> Option<LameTag> {
+  let raw = b.get(..9)?;
+  if !raw[0].is_ascii_alphabetic() { return None; }
+  let version = String::from_utf8_lossy(raw).trim_end_matches(['\0', ' ', 'U']).to_string();
+  let mut tag = LameTag { version, vbr_method: "unknown", lowpass_hz: None, preset: None, bitrate_kbps: None, encoder_delay: 0, padding: 0 };
+  if !has_extension(&tag.version) || b.len() < 36 { return Some(tag); }
+  let method = b[9] & 0x0F;
+  tag.vbr_method = vbr_method(method);
+  tag.lowpass_hz = (b[10] != 0).then(|| b[10] as u32 * 100);
+  tag.bitrate_kbps = (b[20] != 0).then_some(b[20] as u32);
+  tag.encoder_delay = ((b[21] as u32) << 4) | (b[22] as u32 >> 4);
+  tag.padding = ((b[22] as u32 & 0x0F) << 8) | b[23] as u32;
+  tag.preset = preset_name(u16::from_be_bytes([b[26], b[27]]) & 0x07FF).or_else(|| {
+    // LAME sets the Xing quality to 100 - 10 * V - q.
+    matches!(method, 3..=6).then_some(())?;
+    quality.filter(|q| *q <= 100).map(|q| format!("V{}", (100 - q) / 10))
+  });
+  Some(tag)
+}
+
+/// Headers of the first frame of `b`, and whether the stream is VBR.
+fn mp3_info(b: &[u8]) -> Option<(Mp3Info, Option<bool>)> {
+  let (at, f) = first_frame(b)?;
+  let body = &b[at..];
+  let mut info = Mp3Info {
+    version: f.version, layer: f.layer, sample_rate: f.sample_rate, channel_mode: f.channel_mode, bitrate_kbps: f.bitrate_kbps,
+    header: None, frames: None, bytes: None, quality: None, lame: None,
+  };
+  // Xing sits after the side information, whose size depends on version and channels.
+  let xing = 4 + match (f.version == "MPEG-1", f.mono) { (true, false) => 32, (true, true) | (false, false) => 17, (false, true) => 9 };
+  let vbr = match body.get(xing..xing + 4) {
+    Some(id @ (b"Xing" | b"Info")) if f.layer == 3 => {
+      let flags = be32(body, xing + 4)?;
+      let mut p = xing + 8;
+      let mut field = |bit: u32, len: usize| {
+        if flags & bit == 0 { return None; }
+        let v = be32(body, p);
+        p += len;
+        v
+      };
+      info.frames = field(1, 4);
+      info.bytes = field(2, 4);
+      field(4, 100);
+      info.quality = field(8, 4);
+      info.header = Some(if id == b"Xing" { "Xing" } else { "Info" });
+      info.lame = body.get(p..).and_then(|l| lame_tag(l, info.quality));
+      match info.lame.as_ref().map(|l| l.vbr_method) {
+        Some("cbr" | "cbr-2pass") => Some(false),
+        Some("unknown") | None => Some(id == b"Xing"),
+        Some(_) => Some(true),
+      }
+    }
+    _ if body.get(36..40) == Some(b"VBRI") => {
+      info.header = Some("VBRI");
+      info.quality = body.get(42..44).map(|q| u16::from_be_bytes([q[0], q[1]]) as u32);
+      info.bytes = be32(body, 44);
+      info.frames = be32(body, 48);
+      Some(true)
+    }
+    _ => {
+      let mut rates = Vec::new();
+      let mut p = 0;
+      while rates.len() < SCAN_FRAMES {
+        let Some(next) = body.get(p..).and_then(frame) else { break };
+        rates.push(next.bitrate_kbps);
+        p += next.len.max(1);
+      }
+      (rates.len() >= 2).then(|| rates.iter().any(|r| *r != rates[0]))
+    }
+  };
+  Some((info, vbr))
+}
+
+/// `HEAD` bytes of `p` past any leading ID3v2 tag.
+fn audio_head(p: &Path) -> Option<Vec<u8>> {
+  let mut f = fs::File::open(long_paths::extended(p)).ok()?;
+  let mut id3 = [0u8; 10];
+  let start = match f.read_exact(&mut id3) {
+    Ok(()) if &id3[..3] == b"ID3" => {
+      let size = id3[6..10].iter().fold(0u64, |acc, &x| (acc << 7) | (x & 0x7f) as u64);
+      10 + size + if id3[5] & 0x10 != 0 { 10 } else { 0 }
+    }
+    _ => 0,
+  };
+  f.seek(SeekFrom::Start(start)).ok()?;
+  let mut head = Vec::new();
+  f.take(HEAD).read_to_end(&mut head).ok()?;
+  Some(head)
+}
+
+fn display(lame_version: &str) -> String {
+  // "LAME3.100" -> "LAME 3.100", "Lavc58.91" -> "Lavc 58.91".
+  match lame_version.find(|c: char| c.is_ascii_digit()) {
+    Some(i) if i > 0 => format!("{} {}", &lame_version[..i], &lame_version[i..]),
+    _ => lame_version.to_string(),
+  }
+}
+
+fn report(p: &Path, tf: &lofty::TaggedFile) -> EncodingInfo {
+  let mut out = EncodingInfo { path: p.to_string_lossy().to_string(), encoder: None, encoder_source: None, vbr: None, mp3: None };
+  if tf.file_type() == FileType::Mpeg {
+    if let Some((info, vbr)) = audio_head(p).as_deref().and_then(mp3_info) {
+      if let Some(l) = &info.lame {
+        out.encoder = Some(display(&l.version));
+        out.encoder_source = Some("lameTag");
+      } else if info.header == Some("VBRI") {
+        out.encoder = Some("FhG".into());
+        out.encoder_source = Some("vbriHeader");
+      }
+      out.vbr = vbr;
+      out.mp3 = Some(info);
+    }
+  }
+  if out.encoder.is_none() {
+    let found = tf.tags().iter().find_map(|t| {
+      // lofty reads TSSE back as EncoderSettings.
+      let tsse = || (t.tag_type() == TagType::Id3v2).then(|| t.get_string(&ItemKey::EncoderSettings)).flatten();
+      let s = t.get_string(&ItemKey::EncoderSoftware).or_else(tsse)?.trim();
+      (!s.is_empty()).then(|| (s.to_string(), if t.tag_type() == TagType::VorbisComments { "vendor" } else { "encoderTag" }))
+    });
+    if let Some((s, source)) = found {
+      out.encoder = Some(s);
+      out.encoder_source = Some(source);
+    }
+  }
+  out
+}
+
+/// `encoder` and `vbr` for `TrackMeta`.
+pub fn summary(p: &Path, tf: &lofty::TaggedFile) -> (Option<String>, Option<bool>) {
+  let r = report(p, tf);
+  (r.encoder, r.vbr)
+}
+
+/// Encoder and, for MP3s, the Xing/VBRI and LAME headers of `path`.
+#[tauri::command]
+pub async fn encoding_info(path: String) -> Result<EncodingInfo, String> {
+  let _span = command_span("encoding_info");
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = Path::new(&path);
+    let tf = read_tagged(p).map_err(|e| e.to_string())?;
+    Ok(report(p, &tf))
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{path::PathBuf, time::SystemTime};
+  use crate::{meta_cache, test_support};
+
+  /// MPEG-1 Layer III, 44.1 kHz, stereo frame of `kbps` (128 or 160), silent.
+  fn plain_frame(kbps: u32) -> Vec<u8> {
+    let (index, len) = match kbps { 128 => (0x90, 417), _ => (0xA0, 522) };
+    let mut f = vec![0u8; len];
+    f[..4].copy_from_slice(&[0xFF, 0xFB, index, 0x00]);
+    f
+  }
+
+  /// A LAME tag: version, VBR method, lowpass/100, bitrate, delay, padding, preset.
+  fn lame(version: &[u8; 9], method: u8, lowpass: u8, kbps: u8, delay: u32, padding: u32, preset: u16) -> Vec<u8> {
+    let mut l = vec![0u8; 36];
+    l[..9].copy_from_slice(version);
+    l[9] = 0x10 | method;
+    l[10] = lowpass;
+    l[20] = kbps;
+    l[21..24].copy_from_slice(&[(delay >> 4) as u8, ((delay & 0x0F) << 4 | padding >> 8) as u8, padding as u8]);
+    l[26..28].copy_from_slice(&preset.to_be_bytes());
+    l
+  }
+
+  /// A Xing or Info first frame (frames, bytes and quality fields) carrying
+  /// `tag` after them, then `frames` plain 128 kbps frames.
+  fn xing_file(dir: &Path, name: &str, id: &[u8; 4], quality: u32, tag: &[u8], frames: usize) -> PathBuf {
+    let mut first = plain_frame(128);
+    first[36..40].copy_from_slice(id);
+    first[40..44].copy_from_slice(&0x0Bu32.to_be_bytes());
+    first[44..48].copy_from_slice(&(frames as u32).to_be_bytes());
+    first[48..52].copy_from_slice(&((frames as u32 + 1) * 417).to_be_bytes());
+    first[52..56].copy_from_slice(&quality.to_be_bytes());
+    first[56..56 + tag.len()].copy_from_slice(tag);
+    write(dir, name, first, frames)
+  }
+
+  fn write(dir: &Path, name: &str, mut bytes: Vec<u8>, frames: usize) -> PathBuf {
+    bytes.extend(plain_frame(128).repeat(frames));
+    let p = dir.join(name);
+    fs::write(&p, bytes).unwrap();
+    p
+  }
+
+  fn info(p: &Path) -> EncodingInfo { report(p, &read_tagged(p).unwrap()) }
+
+  #[test]
+  fn lame_vbr_reads_version_method_preset_and_gap() {
+    let dir = test_support::scratch("encoder-lame-vbr");
+    let p = xing_file(&dir, "v2.mp3", b"Xing", 78, &lame(b"LAME3.100", 4, 190, 32, 576, 1000, 480), 20);
+    let r = info(&p);
+    assert_eq!((r.encoder.as_deref(), r.encoder_source, r.vbr), (Some("LAME 3.100"), Some("lameTag"), Some(true)));
+    let mp3 = r.mp3.unwrap();
+    assert_eq!((mp3.version, mp3.layer, mp3.channel_mode, mp3.header), ("MPEG-1", 3, "stereo", Some("Xing")));
+    assert_eq!((mp3.frames, mp3.quality), (Some(20), Some(78)));
+    let l = mp3.lame.unwrap();
+    assert_eq!((l.vbr_method, l.preset.as_deref(), l.lowpass_hz, l.bitrate_kbps), ("vbr-mtrh", Some("V2"), Some(19000), Some(32)));
+    assert_eq!((l.encoder_delay, l.padding), (576, 1000));
+  }
+
+  #[test]
+  fn lame_cbr_and_the_quality_fallback() {
+    let dir = test_support::scratch("encoder-lame-cbr");
+    let cbr = info(&xing_file(&dir, "cbr.mp3", b"Info", 0, &lame(b"LAME3.99r", 1, 170, 128, 576, 1500, 128), 20));
+    assert_eq!((cbr.encoder.as_deref(), cbr.vbr), (Some("LAME 3.99r"), Some(false)));
+    let l = cbr.mp3.unwrap().lame.unwrap();
+    assert_eq!((l.vbr_method, l.preset.as_deref(), l.bitrate_kbps), ("cbr", Some("128 kbps"), Some(128)));
+
+    // No preset written: the Xing quality (100 - 10 * V - q) gives the level.
+    let v0 = info(&xing_file(&dir, "v0.mp3", b"Xing", 98, &lame(b"LAME3.98 ", 3, 195, 32, 576, 100, 0), 20));
+    assert_eq!(v0.mp3.unwrap().lame.unwrap().preset.as_deref(), Some("V0"));
+  }
+
+  #[test]
+  fn ffmpeg_and_old_lame_headers() {
+    let dir = test_support::scratch("encoder-other");
+    let lavc = info(&xing_file(&dir, "lavc.mp3", b"Info", 0, &lame(b"Lavc58.91", 1, 0, 128, 1105, 300, 0), 20));
+    assert_eq!((lavc.encoder.as_deref(), lavc.vbr), (Some("Lavc 58.91"), Some(false)));
+    assert_eq!(lavc.mp3.unwrap().lame.unwrap().encoder_delay, 1105);
+
+    // Before 3.90 only the version string; Xing alone says VBR.
+    let old = info(&xing_file(&dir, "old.mp3", b"Xing", 50, b"LAME3.12 ", 20));
+    assert_eq!((old.encoder.as_deref(), old.vbr), (Some("LAME 3.12"), Some(true)));
+    let l = old.mp3.unwrap().lame.unwrap();
+    assert_eq!((l.vbr_method, l.preset), ("unknown", None));
+  }
+
+  #[test]
+  fn fraunhofer_vbri() {
+    let dir = test_support::scratch("encoder-fhg");
+    let mut first = plain_frame(128);
+    first[36..40].copy_from_slice(b"VBRI");
+    first[42..44].copy_from_slice(&75u16.to_be_bytes());
+    first[44..48].copy_from_slice(&(21u32 * 417).to_be_bytes());
+    first[48..52].copy_from_slice(&21u32.to_be_bytes());
+    let r = info(&write(&dir, "fhg.mp3", first, 20));
+    assert_eq!((r.encoder.as_deref(), r.encoder_source, r.vbr), (Some("FhG"), Some("vbriHeader"), Some(true)));
+    let mp3 = r.mp3.unwrap();
+    assert_eq!((mp3.header, mp3.quality, mp3.frames), (Some("VBRI"), Some(75), Some(21)));
+  }
+
+  #[test]
+  fn bare_streams_are_told_by_their_bitrates_and_named_by_tsse() {
+    let dir = test_support::scratch("encoder-bare");
+    let cbr = info(&test_support::tagged(&dir, "cbr.mp3", &[(ItemKey::EncoderSoftware, "Audition 3.0")]));
+    assert_eq!((cbr.encoder.as_deref(), cbr.encoder_source, cbr.vbr), (Some("Audition 3.0"), Some("encoderTag"), Some(false)));
+    assert_eq!(cbr.mp3.unwrap().header, None);
+
+    let mixed: Vec<u8> = [plain_frame(160), plain_frame(128)].concat().repeat(10);
+    let vbr = info(&write(&dir, "vbr.mp3", mixed, 0));
+    assert_eq!((vbr.encoder, vbr.vbr), (None, Some(true)));
+  }
+
+  #[test]
+  fn flac_names_its_vendor() {
+    let dir = test_support::scratch("encoder-flac");
+    let r = info(&test_support::tagged(&dir, "a.flac", &[(ItemKey::EncoderSoftware, "reference libFLAC 1.4.3 20230623")]));
+    assert_eq!((r.encoder.as_deref(), r.encoder_source, r.vbr), (Some("reference libFLAC 1.4.3 20230623"), Some("vendor"), None));
+    assert!(r.mp3.is_none());
+  }
+
+  #[test]
+  fn track_meta_reads_the_headers_only_on_a_miss() {
+    let dir = test_support::scratch("encoder-cache");
+    let p = xing_file(&dir, "a.mp3", b"Xing", 78, &lame(b"LAME3.100", 4, 190, 32, 576, 1000, 480), 20);
+    let tf = read_tagged(&p).unwrap();
+    assert_eq!(meta_cache::encoder(&p, &tf), (Some("LAME 3.100".into()), Some(true)));
+    // Same size and mtime, different headers: a current entry answers.
+    let modified = fs::metadata(&p).unwrap().modified().unwrap();
+    let mut bytes = fs::read(&p).unwrap();
+    bytes[56..65].copy_from_slice(b"LAME3.99r");
+    fs::write(&p, &bytes).unwrap();
+    fs::File::options().write(true).open(&p).unwrap().set_modified(modified).unwrap();
+    assert_eq!(meta_cache::encoder(&p, &tf).0.as_deref(), Some("LAME 3.100"));
+    // Touched: a miss, read again.
+    fs::File::options().write(true).open(&p).unwrap().set_modified(SystemTime::now() + std::time::Duration::from_secs(5)).unwrap();
+    assert_eq!(meta_cache::encoder(&p, &tf).0.as_deref(), Some("LAME 3.99r"));
+  }
+}
@@ -0,0 +1,99 @@
+// Typed command errors. Serialized as `{ kind, message, ... }` so the frontend
+// can branch on `kind` instead of string-matching OS messages.
+// Commands that don't need the distinction keep returning `String`.
+
+use std::{fmt, io, path::Path};
+use serde::Serialize;
+
+use crate::{file_health, handshake, volumes};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all_fields = "camelCase")]
+pub enum CmdError {
+  /// EPERM/EACCES. On macOS this usually means folder access was revoked
+  /// (sandbox / TCC) rather than the file being gone.
+  PermissionDenied { path: String, message: String },
+  /// The removable volume (or scanned root) holding `path` is gone. `queued`
+  /// is set when the write was kept for `retry_volume`.
+  VolumeUnavailable { path: String, root: String, message: String, queued: bool },
+  /// 0-byte or truncated file (see file_health.rs).
+  Corrupt { path: String, message: String },
+  /// `path` is under a folder opened with `open_folder_verified`.
+  ReadOnly { path: String, root: String, message: String },
+  /// A saved field read back different from what was written, even after a
+  /// second save (see write_verify.rs). `field` is the lofty item key.
+  VerificationFailed { path: String, field: String, expected: String, actual: String, message: String },
+  /// A batch's pre-flight estimate doesn't fit on `folder`'s volume (see preflight.rs). Bytes.
+  InsufficientSpace { folder: String, required: u64, available: u64, shortfall: u64, message: String },
+  /// Pre-flight couldn't create a file in these target folders.
+  NoWriteAccess { folders: Vec<String>, message: String },
+  /// A value is longer than `tag_type` can safely hold and the limit strategy is
+  /// "reject" (see field_limits.rs). Bytes.
+  FieldTooLong { path: String, tag_type: String, field: String, limit: usize, length: usize, message: String },
+  /// Not an enabled, tag-writable format (see formats.rs); never written.
+  Unsupported { path: String, ext: String, message: String },
+  /// The frontend's tag schema isn't ours and the mismatch wasn't confirmed
+  /// with `force_compatibility` (see handshake.rs); bank writes are refused.
+  SchemaMismatch { frontend: u32, backend: u32, compatibility: handshake::Compatibility, message: String },
+  Other { message: String },
+}
+
+impl CmdError {
+  pub fn permission_denied(path: &Path) -> Self {
+    CmdError::PermissionDenied {
+      path: path.to_string_lossy().to_string(),
+      message: format!(
+        "Permission denied for {}. The app may have lost access to this folder; re-select it to grant access again.",
+        path.display()
+      ),
+    }
+  }
+
+  /// Classify an IO error against the path it happened on.
+  pub fn from_io(path: &Path, e: &io::Error) -> Self {
+    if e.kind() == io::ErrorKind::PermissionDenied {
+      Self::permission_denied(path)
+    } else if let Some(root) = volumes::is_volume_error(e).then(|| volumes::detect(path)).flatten() {
+      volumes::unavailable(path, &root, false)
+    } else {
+      CmdError::Other { message: e.to_string() }
+    }
+  }
+
+  pub fn from_lofty(path: &Path, e: &lofty::error::LoftyError) -> Self {
+    match e.kind() {
+      lofty::error::ErrorKind::Io(io) => Self::from_io(path, io),
+      // Parse errors on a damaged file get the plain reason instead of lofty's.
+      _ => match file_health::check(path) {
+        h if !h.is_ok() => CmdError::Corrupt { path: path.to_string_lossy().to_string(), message: h.reason().to_string() },
+        _ => CmdError::Other { message: e.to_string() },
+      },
+    }
+  }
+}
+
+impl fmt::Display for CmdError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CmdError::PermissionDenied { message, .. }
+      | CmdError::VolumeUnavailable { message, .. }
+      | CmdError::Corrupt { message, .. }
+      | CmdError::ReadOnly { message, .. }
+      | CmdError::VerificationFailed { message, .. }
+      | CmdError::InsufficientSpace { message, .. }
+      | CmdError::NoWriteAccess { message, .. }
+      | CmdError::FieldTooLong { message, .. }
+      | CmdError::Unsupported { message, .. }
+      | CmdError::SchemaMismatch { message, .. }
+      | CmdError::Other { message } => f.write_str(message),
+    }
+  }
+}
+
+impl From<String> for CmdError {
+  fn from(message: String) -> Self { CmdError::Other { message } }
+}
+
+impl From<CmdError> for String {
+  fn from(e: CmdError) -> Self { e.to_string() }
+}
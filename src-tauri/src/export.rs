@@ -0,0 +1,245 @@
+// JSON export of track metadata for external tools (static sites, scripts).
+// The document shape is defined by the structs below: ExportHeader fields
+// followed by `tracks: [ExportTrack, ...]`. Bump EXPORT_SCHEMA_VERSION on
+// any breaking change. `export_csv_blocking` writes the same tracks as one
+// CSV row each (CLI mode).
+//
+// Without `inlineArt` tracks come from the metadata cache, duration and
+// bitrate included, so exporting a library that's been scanned opens only
+// the files changed since; `filesOpened` in the summary says how many.
+
+use std::{fs, io::{BufWriter, Write}, path::{Path, PathBuf}};
+use chrono::Local;
+use lofty::AudioFile;
+use serde::{Deserialize, Serialize};
+
+use crate::{command_span, log_line, meta_cache, read_folder, read_tagged, split_comment_tokens, track_meta_cached, track_meta_from, TrackMeta};
+
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ExportSource {
+  Folder(String),
+  Paths(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ExportOptions {
+  /// Inline the embedded cover as a data URL; otherwise `pictureDataUrl` is null.
+  inline_art: bool,
+  pretty: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportHeader {
+  schema_version: u32,
+  exported_at: String,
+  root: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportTrack {
+  #[serde(flatten)]
+  meta: TrackMeta,
+  relative_path: String,
+  tags: Vec<String>,
+  duration_secs: Option<f64>,
+  bitrate_kbps: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFailure {
+  path: String,
+  error: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+  dest: String,
+  tracks_written: usize,
+  failed: Vec<ExportFailure>,
+  /// Audio files read because the cache had nothing current for them.
+  files_opened: usize,
+}
+
+/// Deepest directory containing every path (for `relativePath` of ad-hoc selections).
+fn common_root(paths: &[String]) -> Option<PathBuf> {
+  let mut root: Option<PathBuf> = None;
+  for p in paths {
+    let parent = Path::new(p).parent()?.to_path_buf();
+    root = Some(match root {
+      None => parent,
+      Some(r) => r.ancestors().find(|a| parent.starts_with(a))?.to_path_buf(),
+    });
+  }
+  root
+}
+
+fn relative_to(root: Option<&Path>, p: &str) -> String {
+  root
+    .and_then(|r| Path::new(p).strip_prefix(r).ok())
+    .map(|r| r.to_string_lossy().replace('\\', "/"))
+    .unwrap_or_else(|| p.to_string())
+}
+
+/// The track, and whether its file had to be opened.
+fn export_track(path: &str, root: Option<&Path>, opts: &ExportOptions) -> Result<(ExportTrack, bool), String> {
+  let (meta, duration_secs, bitrate_kbps, opened) = if opts.inline_art {
+    let tf = read_tagged(path).map_err(|e| e.to_string())?;
+    let props = tf.properties();
+    let duration = props.duration();
+    (track_meta_from(path, &tf, true), (!duration.is_zero()).then_some(duration.as_secs_f64()), props.audio_bitrate(), true)
+  } else {
+    let (m, opened) = meta_cache::lookup(Path::new(path))?;
+    (track_meta_cached(path, &m), m.duration_ms.map(|ms| ms as f64 / 1000.0), m.bitrate_kbps, opened)
+  };
+  let track = ExportTrack { relative_path: relative_to(root, path), tags: split_comment_tokens(&meta.comment), duration_secs, bitrate_kbps, meta };
+  Ok((track, opened))
+}
+
+fn export_json_blocking(source: ExportSource, dest: String, opts: ExportOptions) -> Result<ExportSummary, String> {
+  let (paths, root) = match source {
+    ExportSource::Folder(f) => {
+      let list = read_folder(&f, false).map_err(|e| e.to_string())?;
+      (list.into_iter().map(|x| x.path).collect::<Vec<_>>(), Some(PathBuf::from(f)))
+    }
+    ExportSource::Paths(p) => { let r = common_root(&p); (p, r) }
+  };
+
+  let dest_path = PathBuf::from(&dest);
+  let tmp = dest_path.with_extension("json.partial");
+  let file = fs::File::create(&tmp).map_err(|e| e.to_string())?;
+  let mut w = BufWriter::new(file);
+
+  // Stream: header fields, then one track at a time, never the whole document in memory.
+  let header = ExportHeader {
+    schema_version: EXPORT_SCHEMA_VERSION,
+    exported_at: Local::now().to_rfc3339(),
+    root: root.as_ref().map(|r| r.to_string_lossy().to_string()),
+  };
+  let header = serde_json::to_string(&header).map_err(|e| e.to_string())?;
+  let nl = if opts.pretty { "\n" } else { "" };
+  let write_err = |e: std::io::Error| e.to_string();
+  write!(w, "{},\"tracks\":[{}", header.trim_end_matches('}'), nl).map_err(write_err)?;
+
+  let mut written = 0usize;
+  let mut opened = 0usize;
+  let mut failed = Vec::new();
+  for p in &paths {
+    match export_track(p, root.as_deref(), &opts) {
+      Ok((t, o)) => {
+        opened += o as usize;
+        if written > 0 { write!(w, ",{}", nl).map_err(write_err)?; }
+        if opts.pretty { serde_json::to_writer_pretty(&mut w, &t) } else { serde_json::to_writer(&mut w, &t) }
+          .map_err(|e| e.to_string())?;
+        written += 1;
+      }
+      Err(e) => failed.push(ExportFailure { path: p.clone(), error: e }),
+    }
+  }
+  write!(w, "{}]}}{}", nl, nl).map_err(write_err)?;
+  w.into_inner().map_err(|e| e.to_string())?.sync_all().map_err(write_err)?;
+  fs::rename(&tmp, &dest_path).map_err(|e| e.to_string())?;
+
+  log_line(&format!("export_json dest=\"{}\" tracks={} failed={} files_opened={}", dest, written, failed.len(), opened));
+  Ok(ExportSummary { dest, tracks_written: written, failed, files_opened: opened })
+}
+
+#[tauri::command]
+pub async fn export_json(folder_or_paths: ExportSource, dest: String, options: Option<ExportOptions>) -> Result<ExportSummary, String> {
+  let _span = command_span("export_json");
+  let opts = options.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || export_json_blocking(folder_or_paths, dest, opts))
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+const CSV_COLUMNS: &[&str] = &["relativePath", "path", "title", "artists", "genre", "releaseDate", "tags", "comment", "durationSecs", "bitrateKbps"];
+
+pub fn csv_field(s: &str) -> String {
+  if s.contains([',', '"', '\n', '\r']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+/// Every supported file directly in `folder` as a CSV row; multi-valued
+/// columns (artists, tags) are joined with "; ".
+pub fn export_csv_blocking(folder: &str, dest: &str) -> Result<ExportSummary, String> {
+  let root = PathBuf::from(folder);
+  let list = read_folder(folder, false).map_err(|e| e.to_string())?;
+  let dest_path = PathBuf::from(dest);
+  let tmp = dest_path.with_extension("csv.partial");
+  let mut w = BufWriter::new(fs::File::create(&tmp).map_err(|e| e.to_string())?);
+  let write_err = |e: std::io::Error| e.to_string();
+  writeln!(w, "{}", CSV_COLUMNS.join(",")).map_err(write_err)?;
+
+  let mut written = 0usize;
+  let mut opened = 0usize;
+  let mut failed = Vec::new();
+  for f in &list {
+    match export_track(&f.path, Some(&root), &ExportOptions::default()) {
+      Ok((t, o)) => {
+        opened += o as usize;
+        let row = [
+          t.relative_path,
+          t.meta.path.clone(),
+          t.meta.title.clone().unwrap_or_default(),
+          t.meta.artists.join("; "),
+          t.meta.genre.clone().unwrap_or_default(),
+          t.meta.release_date.clone().unwrap_or_default(),
+          t.tags.join("; "),
+          t.meta.comment.clone(),
+          t.duration_secs.map(|d| format!("{:.3}", d)).unwrap_or_default(),
+          t.bitrate_kbps.map(|b| b.to_string()).unwrap_or_default(),
+        ];
+        writeln!(w, "{}", row.iter().map(|v| csv_field(v)).collect::<Vec<_>>().join(",")).map_err(write_err)?;
+        written += 1;
+      }
+      Err(e) => failed.push(ExportFailure { path: f.path.clone(), error: e }),
+    }
+  }
+  w.into_inner().map_err(|e| e.to_string())?.sync_all().map_err(write_err)?;
+  fs::rename(&tmp, &dest_path).map_err(|e| e.to_string())?;
+  log_line(&format!("export_csv dest=\"{}\" tracks={} failed={} files_opened={}", dest, written, failed.len(), opened));
+  Ok(ExportSummary { dest: dest.to_string(), tracks_written: written, failed, files_opened: opened })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, SystemTime};
+  use lofty::ItemKey;
+  use crate::test_support;
+
+  #[test]
+  fn a_warm_cache_exports_without_opening_unchanged_files() {
+    let (dir, out) = (test_support::scratch("export"), test_support::scratch("export-out"));
+    let files = ["a.mp3", "b.flac", "c.wav"].map(|n| test_support::tagged(&dir, n, &[(ItemKey::TrackTitle, n), (ItemKey::Comment, "#warm;")]));
+    let folder = dir.to_string_lossy().to_string();
+    let csv = |name: &str| out.join(name).to_string_lossy().to_string();
+    let cold = export_csv_blocking(&folder, &csv("cold.csv")).unwrap();
+    assert_eq!((cold.tracks_written, cold.files_opened), (3, 3));
+
+    // Junk of the same size and time: an export that opened these would fail to parse them.
+    for p in &files[..2] {
+      let (len, mtime) = fs::metadata(p).map(|m| (m.len(), m.modified().unwrap())).unwrap();
+      fs::write(p, vec![0u8; len as usize]).unwrap();
+      fs::File::options().write(true).open(p).unwrap().set_modified(mtime).unwrap();
+    }
+    let warm = export_csv_blocking(&folder, &csv("warm.csv")).unwrap();
+    assert_eq!((warm.tracks_written, warm.files_opened), (3, 0));
+    assert_eq!(fs::read(csv("warm.csv")).unwrap(), fs::read(csv("cold.csv")).unwrap());
+    let json = export_json_blocking(ExportSource::Folder(folder.clone()), csv("warm.json"), ExportOptions::default()).unwrap();
+    assert_eq!((json.tracks_written, json.files_opened), (3, 0));
+
+    // A file changed since is the one opened.
+    fs::File::options().write(true).open(&files[2]).unwrap().set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+    let touched = export_json_blocking(ExportSource::Folder(folder), csv("touched.json"), ExportOptions::default()).unwrap();
+    assert_eq!((touched.tracks_written, touched.files_opened), (3, 1));
+  }
+}
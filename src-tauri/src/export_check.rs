@@ -0,0 +1,482 @@
+// Checks a playlist or library export before it's needed elsewhere: M3U /
+// M3U8, Rekordbox XML (COLLECTION tracks) and Traktor NML (COLLECTION
+// entries). Every referenced file is looked up on this machine, component by
+// component so a name whose case differs from the disk is caught: an error
+// where the volume is case-sensitive (it isn't found), a warning where it
+// isn't (it only resolves there). XML attribute values are checked for what
+// XML 1.0 doesn't allow raw (`<`, a stray `&`, control characters),
+// durations and BPMs against what the target software takes, and playlist
+// references against the collection. The file itself is only read.
+
+use std::{collections::{HashMap, HashSet}, ffi::OsString, fs, path::{Component, Path, PathBuf}};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+
+use crate::{command_span, log_line};
+
+/// Rekordbox's tempo range; it keeps two decimals.
+const REKORDBOX_BPM: (f64, f64) = (1.0, 999.99);
+const TRAKTOR_BPM: (f64, f64) = (1.0, 999.0);
+/// Longer than a day is a broken value, whatever the format.
+const MAX_DURATION_SECS: f64 = 86_400.0;
+/// Dangling playlist references listed in the warning.
+const LIST_DANGLING: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportKind { Auto, M3u, RekordboxXml, Nml }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity { Error, Warning }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueCode { NoLocation, BadLocation, Missing, CaseMismatch, XmlEscape, Duration, Bpm, Encoding, DanglingReference }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+  severity: Severity,
+  code: IssueCode,
+  message: String,
+}
+
+fn error(code: IssueCode, message: impl Into<String>) -> Issue { Issue { severity: Severity::Error, code, message: message.into() } }
+fn warning(code: IssueCode, message: impl Into<String>) -> Issue { Issue { severity: Severity::Warning, code, message: message.into() } }
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryReport {
+  /// Position among the export's tracks, from 0.
+  index: usize,
+  line: usize,
+  title: Option<String>,
+  /// As written in the file.
+  location: Option<String>,
+  /// Where that points on this machine.
+  resolved: Option<String>,
+  issues: Vec<Issue>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportValidation {
+  path: String,
+  kind: ExportKind,
+  entries: usize,
+  errors: usize,
+  warnings: usize,
+  /// Not tied to one entry: encoding, playlist references.
+  file_issues: Vec<Issue>,
+  /// Entries with at least one issue, in file order.
+  problems: Vec<EntryReport>,
+}
+
+/// An entry and the file it should point at, before the disk is asked.
+struct Parsed {
+  report: EntryReport,
+  path: Option<PathBuf>,
+}
+
+fn line_starts(text: &str) -> Vec<usize> {
+  std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect()
+}
+
+fn line_of(starts: &[usize], at: usize) -> usize { starts.partition_point(|s| *s <= at) }
+
+// ---- XML ----
+
+struct Element<'a> {
+  name: &'a str,
+  /// Values as written, entities and all.
+  attrs: Vec<(&'a str, &'a str)>,
+  close: bool,
+  at: usize,
+}
+
+impl Element<'_> {
+  fn raw(&self, name: &str) -> Option<&str> { self.attrs.iter().find(|(n, _)| *n == name).map(|(_, v)| *v) }
+  fn get(&self, name: &str) -> Option<String> { self.raw(name).map(unescape) }
+}
+
+fn attributes(s: &str) -> Vec<(&str, &str)> {
+  let mut out = Vec::new();
+  let mut rest = s;
+  while let Some(eq) = rest.find('=') {
+    let name = rest[..eq].trim();
+    let after = rest[eq + 1..].trim_start();
+    let Some(q) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else { break };
+    let Some(end) = after[1..].find(q) else { break };
+    out.push((name, &after[1..1 + end]));
+    rest = &after[end + 2..];
+  }
+  out
+}
+
+/// Start and end tags in document order. Comments, CDATA and declarations are skipped.
+fn elements(text: &str) -> Vec<Element<'_>> {
+  let b = text.as_bytes();
+  let mut out = Vec::new();
+  let mut i = 0;
+  while let Some(off) = text[i..].find('<') {
+    let at = i + off;
+    let rest = &text[at..];
+    let skip_to = |end: &str| rest.find(end).map_or(text.len(), |e| at + e + end.len());
+    if rest.starts_with("<!--") { i = skip_to("-->"); continue; }
+    if rest.starts_with("<![CDATA[") { i = skip_to("]]>"); continue; }
+    if rest.starts_with("<?") || rest.starts_with("<!") { i = skip_to(">"); continue; }
+    let close = rest.starts_with("</");
+    let start = at + if close { 2 } else { 1 };
+    // The `>` that isn't inside a quoted value.
+    let (mut j, mut quote) = (start, None);
+    while j < b.len() {
+      match (quote, b[j]) {
+        (Some(q), c) if c == q => quote = None,
+        (None, c @ (b'"' | b'\'')) => quote = Some(c),
+        (None, b'>') => break,
+        _ => {}
+      }
+      j += 1;
+    }
+    let inner = text[start..j].trim_end_matches('/');
+    let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+    out.push(Element { name: &inner[..name_end], attrs: attributes(&inner[name_end..]), close, at });
+    i = (j + 1).min(text.len());
+  }
+  out
+}
+
+fn is_illegal(c: char) -> bool {
+  matches!(c, '\u{0}'..='\u{8}' | '\u{B}' | '\u{C}' | '\u{E}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}')
+}
+
+/// Length of the entity or character reference `s` starts with, if XML has it.
+fn entity(s: &str) -> Option<(usize, char)> {
+  let end = s.find(';')?;
+  let name = &s[1..end];
+  let c = match name.strip_prefix('#') {
+    Some(num) => {
+      let n = match num.strip_prefix('x') { Some(h) => u32::from_str_radix(h, 16).ok(), None => num.parse().ok() };
+      n.and_then(char::from_u32).filter(|c| !is_illegal(*c))?
+    }
+    None => match name { "amp" => '&', "lt" => '<', "gt" => '>', "quot" => '"', "apos" => '\'', _ => return None },
+  };
+  Some((end + 1, c))
+}
+
+fn unescape(raw: &str) -> String {
+  let mut out = String::with_capacity(raw.len());
+  let mut i = 0;
+  while let Some((off, c)) = raw[i..].char_indices().next() {
+    let at = i + off;
+    match (c, entity(&raw[at..]).filter(|_| c == '&')) {
+      (_, Some((len, decoded))) => { out.push(decoded); i = at + len; }
+      _ => { out.push(c); i = at + c.len_utf8(); }
+    }
+  }
+  out
+}
+
+/// What in a raw attribute value XML 1.0 doesn't allow.
+fn escape_issue(field: &str, raw: &str) -> Option<Issue> {
+  let mut bad: Vec<String> = Vec::new();
+  let mut note = |s: String| if !bad.contains(&s) { bad.push(s) };
+  for (i, c) in raw.char_indices() {
+    match c {
+      '<' => note("unescaped <".into()),
+      '&' if entity(&raw[i..]).is_none() => note("unescaped & or bad reference".into()),
+      c if is_illegal(c) => note(format!("control character U+{:04X}", c as u32)),
+      _ => {}
+    }
+  }
+  (!bad.is_empty()).then(|| error(IssueCode::XmlEscape, format!("{}: {}", field, bad.join(", "))))
+}
+
+fn escape_issues(e: &Element, out: &mut Vec<Issue>) {
+  out.extend(e.attrs.iter().filter_map(|(n, v)| escape_issue(n, v)));
+}
+
+// ---- values ----
+
+fn check_duration(field: &str, v: &str, whole_seconds: bool) -> Option<Issue> {
+  let Ok(secs) = v.trim().parse::<f64>() else { return Some(error(IssueCode::Duration, format!("{} \"{}\" is not a number", field, v))) };
+  if !secs.is_finite() || secs < 0.0 { return Some(error(IssueCode::Duration, format!("{} {} is negative", field, v))); }
+  if secs == 0.0 { return Some(warning(IssueCode::Duration, format!("{} is 0; the track shows no length until analysed", field))); }
+  if secs > MAX_DURATION_SECS { return Some(warning(IssueCode::Duration, format!("{} {} is longer than a day", field, v))); }
+  if whole_seconds && secs.fract() != 0.0 { return Some(warning(IssueCode::Duration, format!("{} {} should be whole seconds", field, v))); }
+  None
+}
+
+/// 0 means "no BPM" everywhere and passes. `decimals` is what the target keeps.
+fn check_bpm(field: &str, v: &str, (lo, hi): (f64, f64), decimals: Option<usize>) -> Option<Issue> {
+  let Ok(bpm) = v.trim().parse::<f64>() else { return Some(error(IssueCode::Bpm, format!("{} \"{}\" is not a number", field, v))) };
+  if bpm == 0.0 { return None; }
+  if !(lo..=hi).contains(&bpm) { return Some(error(IssueCode::Bpm, format!("{} {} is outside {}..{}", field, v, lo, hi))); }
+  let places = v.trim().split_once('.').map_or(0, |(_, f)| f.trim_end_matches('0').len());
+  match decimals {
+    Some(d) if places > d => Some(warning(IssueCode::Bpm, format!("{} {} will be rounded to {} decimals", field, v, d))),
+    _ => None,
+  }
+}
+
+/// A `file://` path part ("/C:/Music/a%20b.mp3") as a path.
+fn uri_path(rest: &str) -> Result<PathBuf, String> {
+  let b = rest.as_bytes();
+  let hex = |i: usize| b.get(i).is_some_and(u8::is_ascii_hexdigit);
+  if (0..b.len()).any(|i| b[i] == b'%' && !(hex(i + 1) && hex(i + 2))) { return Err("a % that isn't an escape".into()); }
+  let s = percent_decode_str(rest).decode_utf8().map_err(|_| "escapes don't decode to UTF-8".to_string())?;
+  match s.as_bytes() {
+    [b'/', d, b':', ..] if d.is_ascii_alphabetic() => Ok(PathBuf::from(&s[1..])),
+    _ => Ok(PathBuf::from(s.as_ref())),
+  }
+}
+
+// ---- formats ----
+
+fn rekordbox(text: &str) -> (Vec<Parsed>, Vec<Issue>) {
+  let starts = line_starts(text);
+  let (mut out, mut file_issues) = (Vec::new(), Vec::new());
+  let (mut ids, mut locations) = (HashSet::new(), HashSet::new());
+  // (reference, by location rather than TrackID)
+  let mut refs: Vec<(String, bool)> = Vec::new();
+  let (mut in_playlists, mut by_location) = (false, false);
+  for e in elements(text) {
+    match (e.name, e.close) {
+      ("PLAYLISTS", close) => in_playlists = !close,
+      ("NODE", false) if in_playlists => by_location = e.raw("KeyType") == Some("1"),
+      ("TRACK", false) if in_playlists => refs.extend(e.get("Key").map(|k| (k, by_location))),
+      ("TRACK", false) => {
+        let mut r = EntryReport { index: out.len(), line: line_of(&starts, e.at), title: e.get("Name"), location: e.get("Location"), ..Default::default() };
+        escape_issues(&e, &mut r.issues);
+        let path = match r.location.as_deref() {
+          None => { r.issues.push(error(IssueCode::NoLocation, "no Location")); None }
+          Some(loc) => match loc.strip_prefix("file://localhost").ok_or_else(|| "Rekordbox expects file://localhost/...".to_string()).and_then(uri_path) {
+            Ok(p) => Some(p),
+            Err(msg) => { r.issues.push(error(IssueCode::BadLocation, msg)); None }
+          },
+        };
+        r.issues.extend(e.get("TotalTime").and_then(|v| check_duration("TotalTime", &v, true)));
+        r.issues.extend(e.get("AverageBpm").and_then(|v| check_bpm("AverageBpm", &v, REKORDBOX_BPM, Some(2))));
+        ids.extend(e.get("TrackID"));
+        locations.extend(r.location.clone());
+        out.push(Parsed { report: r, path });
+      }
+      _ => {}
+    }
+  }
+  let dangling: Vec<&str> = refs.iter().filter(|(k, loc)| !if *loc { locations.contains(k) } else { ids.contains(k) }).map(|(k, _)| k.as_str()).collect();
+  if !dangling.is_empty() {
+    let shown: Vec<&str> = dangling.iter().take(LIST_DANGLING).copied().collect();
+    file_issues.push(warning(IssueCode::DanglingReference, format!("{} playlist entries name no COLLECTION track: {}", dangling.len(), shown.join(", "))));
+  }
+  (out, file_issues)
+}
+
+/// Traktor's VOLUME + DIR ("/:Music/:House/:") + FILE as a path here.
+fn nml_path(volume: &str, dir: &str, file: &str) -> PathBuf {
+  let rel = format!("{}{}", dir.replace("/:", "/"), file);
+  if volume.len() == 2 && volume.ends_with(':') { return PathBuf::from(format!("{}{}", volume, rel)); }
+  // macOS: other volumes are mounted by name, the boot volume is "/".
+  let mounted = Path::new("/Volumes").join(volume).join(rel.trim_start_matches('/'));
+  if mounted.exists() { mounted } else { PathBuf::from(rel) }
+}
+
+fn nml(text: &str) -> (Vec<Parsed>, Vec<Issue>) {
+  let starts = line_starts(text);
+  let mut out: Vec<Parsed> = Vec::new();
+  let (mut keys, mut refs) = (HashSet::new(), Vec::new());
+  let (mut in_collection, mut in_playlists) = (false, false);
+  for e in elements(text) {
+    match (e.name, e.close) {
+      ("COLLECTION", close) => in_collection = !close,
+      ("PLAYLISTS", close) => in_playlists = !close,
+      ("PRIMARYKEY", false) if in_playlists && e.raw("TYPE") == Some("TRACK") => refs.extend(e.get("KEY")),
+      ("ENTRY", false) if in_collection => {
+        let mut r = EntryReport { index: out.len(), line: line_of(&starts, e.at), title: e.get("TITLE"), ..Default::default() };
+        escape_issues(&e, &mut r.issues);
+        out.push(Parsed { report: r, path: None });
+      }
+      (name, false) if in_collection => {
+        let Some(cur) = out.last_mut() else { continue };
+        escape_issues(&e, &mut cur.report.issues);
+        match name {
+          "LOCATION" => {
+            let (volume, dir, file) = (e.get("VOLUME").unwrap_or_default(), e.get("DIR").unwrap_or_default(), e.get("FILE"));
+            let Some(file) = file else { cur.report.issues.push(error(IssueCode::NoLocation, "LOCATION has no FILE")); continue };
+            keys.insert(format!("{}{}{}", volume, dir, file));
+            cur.report.location = Some(format!("{}{}{}", volume, dir, file));
+            cur.path = Some(nml_path(&volume, &dir, &file));
+          }
+          "INFO" => cur.report.issues.extend(e.get("PLAYTIME").and_then(|v| check_duration("PLAYTIME", &v, true))),
+          "TEMPO" => cur.report.issues.extend(e.get("BPM").and_then(|v| check_bpm("BPM", &v, TRAKTOR_BPM, None))),
+          _ => {}
+        }
+      }
+      _ => {}
+    }
+  }
+  for p in out.iter_mut().filter(|p| p.report.location.is_none() && p.report.issues.iter().all(|i| i.code != IssueCode::NoLocation)) {
+    p.report.issues.push(error(IssueCode::NoLocation, "no LOCATION"));
+  }
+  let dangling: Vec<&str> = refs.iter().filter(|k| !keys.contains(*k)).map(String::as_str).collect();
+  let mut file_issues = Vec::new();
+  if !dangling.is_empty() {
+    let shown: Vec<&str> = dangling.iter().take(LIST_DANGLING).copied().collect();
+    file_issues.push(warning(IssueCode::DanglingReference, format!("{} playlist entries name no COLLECTION entry: {}", dangling.len(), shown.join(", "))));
+  }
+  (out, file_issues)
+}
+
+/// `ascii_only` for `.m3u`, which many players read in the system code page.
+fn m3u(text: &str, base: &Path, ascii_only: bool) -> Vec<Parsed> {
+  let mut out = Vec::new();
+  // (duration, title) from the #EXTINF line before an entry.
+  let mut info: Option<(String, Option<String>)> = None;
+  for (i, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix("#EXTINF:") {
+      let (secs, title) = rest.split_once(',').map_or((rest, None), |(s, t)| (s, Some(t.trim().to_string())));
+      // Extended attributes (`tvg-id="..."`) may follow the duration.
+      info = Some((secs.split_whitespace().next().unwrap_or_default().to_string(), title.filter(|t| !t.is_empty())));
+      continue;
+    }
+    if line.is_empty() || line.starts_with('#') { continue; }
+    let (secs, title) = info.take().unzip();
+    let mut r = EntryReport { index: out.len(), line: i + 1, title: title.flatten(), location: Some(line.to_string()), ..Default::default() };
+    if let Some(secs) = secs.filter(|s| s.trim() != "-1") { r.issues.extend(check_duration("#EXTINF", &secs, false)); }
+    if ascii_only && !line.is_ascii() {
+      r.issues.push(warning(IssueCode::Encoding, "non-ASCII path in an .m3u; players read those in the system code page (.m3u8 is UTF-8)"));
+    }
+    if !cfg!(windows) && line.contains('\\') {
+      r.issues.push(warning(IssueCode::BadLocation, "backslash separators only work on Windows"));
+    }
+    let path = match line.strip_prefix("file://") {
+      Some(rest) => match uri_path(rest.strip_prefix("localhost").unwrap_or(rest)) {
+        Ok(p) => Some(p),
+        Err(msg) => { r.issues.push(error(IssueCode::BadLocation, msg)); None }
+      },
+      None => Some(base.join(line)),
+    };
+    out.push(Parsed { report: r, path });
+  }
+  out
+}
+
+// ---- the disk ----
+
+/// Directory listings, read once per directory.
+#[derive(Default)]
+struct Disk { dirs: HashMap<PathBuf, Option<Vec<OsString>>> }
+
+impl Disk {
+  /// `p` spelled as on disk, going case-insensitive where the exact name
+  /// isn't there; `None` when some part isn't found at all.
+  fn spelled(&mut self, p: &Path) -> Option<PathBuf> {
+    let mut cur = PathBuf::new();
+    for c in p.components() {
+      let Component::Normal(name) = c else { cur.push(c.as_os_str()); continue };
+      let dir = if cur.as_os_str().is_empty() { PathBuf::from(".") } else { cur.clone() };
+      let names = self.dirs.entry(dir.clone()).or_insert_with(|| fs::read_dir(&dir).ok().map(|rd| rd.flatten().map(|e| e.file_name()).collect())).as_ref()?;
+      let hit = names.iter().find(|n| n.as_os_str() == name).or_else(|| {
+        let want = name.to_string_lossy().to_lowercase();
+        names.iter().find(|n| n.to_string_lossy().to_lowercase() == want)
+      })?;
+      cur.push(hit);
+    }
+    Some(cur)
+  }
+}
+
+/// Why a path from another machine can't resolve here.
+fn foreign(p: &Path) -> Option<&'static str> {
+  let s = p.to_string_lossy();
+  let drive = s.as_bytes().get(1) == Some(&b':') && s.as_bytes()[0].is_ascii_alphabetic();
+  if !cfg!(windows) && drive { return Some("a Windows drive path"); }
+  if cfg!(windows) && s.starts_with('/') { return Some("a macOS/Linux path"); }
+  None
+}
+
+fn check_location(disk: &mut Disk, p: &Path, out: &mut Vec<Issue>) {
+  let spelled = disk.spelled(p);
+  if p.exists() {
+    if let Some(real) = spelled.filter(|r| r.as_path() != p) {
+      out.push(warning(IssueCode::CaseMismatch, format!("on disk it's \"{}\"; this only resolves on case-insensitive volumes", real.display())));
+    }
+  } else if let Some(real) = spelled {
+    out.push(error(IssueCode::CaseMismatch, format!("not found; on disk it's \"{}\"", real.display())));
+  } else {
+    let why = foreign(p).map(|w| format!(" ({} on this machine)", w)).unwrap_or_default();
+    out.push(error(IssueCode::Missing, format!("file not found{}", why)));
+  }
+}
+
+fn detect(path: &Path, text: &str) -> Result<ExportKind, String> {
+  let ext = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+  let head = &text[..text.len().min(4096)];
+  match ext.as_str() {
+    "m3u" | "m3u8" => Ok(ExportKind::M3u),
+    "nml" => Ok(ExportKind::Nml),
+    _ if head.contains("<DJ_PLAYLISTS") => Ok(ExportKind::RekordboxXml),
+    _ if head.contains("<NML") => Ok(ExportKind::Nml),
+    _ if head.trim_start().starts_with("#EXTM3U") => Ok(ExportKind::M3u),
+    _ => Err(format!("can't tell what kind of export {} is", path.display())),
+  }
+}
+
+fn validate_blocking(path: &str, kind: ExportKind) -> Result<ExportValidation, String> {
+  let p = Path::new(path);
+  let bytes = fs::read(p).map_err(|e| format!("{}: {}", path, e))?;
+  let mut file_issues = Vec::new();
+  let body = match bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+    Some(rest) => {
+      if p.extension().is_some_and(|e| e.eq_ignore_ascii_case("m3u8")) {
+        file_issues.push(warning(IssueCode::Encoding, "starts with a UTF-8 byte order mark, which some players take as part of the first line"));
+      }
+      rest
+    }
+    None => &bytes[..],
+  };
+  let text = match std::str::from_utf8(body) {
+    Ok(t) => t.to_string(),
+    Err(_) => {
+      file_issues.push(error(IssueCode::Encoding, "not valid UTF-8"));
+      String::from_utf8_lossy(body).to_string()
+    }
+  };
+  let kind = if kind == ExportKind::Auto { detect(p, &text)? } else { kind };
+  let parsed = match kind {
+    ExportKind::RekordboxXml => { let (e, f) = rekordbox(&text); file_issues.extend(f); e }
+    ExportKind::Nml => { let (e, f) = nml(&text); file_issues.extend(f); e }
+    _ => {
+      let ascii_only = p.extension().is_some_and(|e| e.eq_ignore_ascii_case("m3u"));
+      m3u(&text, p.parent().unwrap_or(Path::new(".")), ascii_only)
+    }
+  };
+
+  let mut disk = Disk::default();
+  let entries = parsed.len();
+  let problems: Vec<EntryReport> = parsed.into_iter().filter_map(|Parsed { mut report, path }| {
+    if let Some(target) = path {
+      check_location(&mut disk, &target, &mut report.issues);
+      report.resolved = Some(target.to_string_lossy().to_string());
+    }
+    (!report.issues.is_empty()).then_some(report)
+  }).collect();
+  let all = || problems.iter().flat_map(|r| &r.issues).chain(&file_issues);
+  let errors = all().filter(|i| i.severity == Severity::Error).count();
+  let warnings = all().count() - errors;
+  log_line(&format!("validate_export path=\"{}\" kind={:?} entries={} errors={} warnings={}", path, kind, entries, errors, warnings));
+  Ok(ExportValidation { path: path.to_string(), kind, entries, errors, warnings, file_issues, problems })
+}
+
+/// Re-read an exported playlist or library file and report, per entry, what
+/// would break importing it elsewhere (see the header). `kind` is guessed
+/// from the extension and contents when `auto` or left out.
+#[tauri::command]
+pub async fn validate_export(path: String, kind: Option<ExportKind>) -> Result<ExportValidation, String> {
+  let _span = command_span("validate_export");
+  tauri::async_runtime::spawn_blocking(move || validate_blocking(&path, kind.unwrap_or(ExportKind::Auto)))
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,209 @@
+// Files whose content doesn't match their extension ("song.mp3" that is a
+// FLAC stream, from a downloader that renamed it). lofty and the webview both
+// go by the extension, so these fail to parse or play. `sniff` reads the first
+// SNIFF_BYTES (plus a few bytes past a leading ID3 tag), which is cheap
+// enough for every scan when `verify_extensions_on_scan` is on. A fix is
+// refused when the extension it calls for isn't enabled (see formats.rs):
+// the renamed file would drop out of the listing.
+
+use std::{fs, io::{Read, Seek, SeekFrom}, path::{Path, PathBuf}, sync::atomic::AtomicBool};
+use serde::Serialize;
+
+use crate::{archive, audit, command_span, ext_lower, folder_entries, folder_watch, formats, log_line, long_paths, shadow};
+
+pub static ON_SCAN: AtomicBool = AtomicBool::new(false);
+
+const SNIFF_BYTES: u64 = 4096;
+
+/// A plausible MPEG audio frame header at the start of `h`.
+fn mpeg_frame(h: &[u8]) -> bool {
+  h.len() >= 4 && h[0] == 0xFF && h[1] & 0xE0 == 0xE0
+    && (h[1] >> 3) & 3 != 1 // reserved version
+    && (h[1] >> 1) & 3 != 0 // layer 0 is ADTS
+    && !matches!(h[2] >> 4, 0 | 15)
+    && (h[2] >> 2) & 3 != 3
+}
+
+/// Format from the signature at the start of `b`. An MPEG frame may follow
+/// zero padding, which some encoders leave before the first frame.
+fn classify(b: &[u8]) -> Option<&'static str> {
+  let at = |o: usize, sig: &[u8]| b.get(o..o + sig.len()) == Some(sig);
+  if at(0, b"fLaC") { return Some("flac"); }
+  if (at(0, b"RIFF") || at(0, b"RF64")) && at(8, b"WAVE") { return Some("wav"); }
+  if at(0, b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) { return Some("aiff"); }
+  if at(4, b"ftyp") { return Some("m4a"); }
+  if at(0, b"OggS") { return Some(if at(28, b"OpusHead") { "opus" } else { "ogg" }); }
+  if at(0, b"MPCK") || at(0, b"MP+") { return Some("mpc"); }
+  if at(0, b"MAC ") { return Some("ape"); }
+  if at(0, b"wvpk") { return Some("wv"); }
+  if b.len() >= 2 && b[0] == 0xFF && b[1] & 0xF6 == 0xF0 { return Some("aac"); }
+  let start = b.iter().position(|&x| x != 0)?;
+  mpeg_frame(&b[start..]).then_some("mp3")
+}
+
+/// Real container format of `p`, `None` when the content isn't recognized.
+pub fn sniff(p: &Path) -> Option<&'static str> {
+  let mut f = fs::File::open(long_paths::extended(p)).ok()?;
+  let mut head = Vec::new();
+  f.by_ref().take(SNIFF_BYTES).read_to_end(&mut head).ok()?;
+  // ID3v2 fronts MP3 but also turns up on FLAC and AAC: look past it.
+  if head.len() >= 10 && &head[..3] == b"ID3" {
+    let size = head[6..10].iter().fold(0u64, |acc, &x| (acc << 7) | (x & 0x7f) as u64);
+    let footer = if head[5] & 0x10 != 0 { 10 } else { 0 };
+    let mut after = Vec::new();
+    f.seek(SeekFrom::Start(10 + size + footer)).ok()?;
+    f.take(SNIFF_BYTES).read_to_end(&mut after).ok()?;
+    return Some(classify(&after).unwrap_or("mp3"));
+  }
+  classify(&head)
+}
+
+fn ext_fits(ext: &str, format: &str) -> bool {
+  match format {
+    "aiff" => matches!(ext, "aiff" | "aif"),
+    "m4a" => matches!(ext, "m4a" | "m4b" | "mp4"),
+    "opus" => matches!(ext, "opus" | "ogg"),
+    _ => ext == format,
+  }
+}
+
+/// The sniffed format when it disagrees with the extension.
+pub fn mismatch(p: &Path) -> Option<&'static str> {
+  let real = sniff(p)?;
+  (!ext_fits(&ext_lower(p), real)).then_some(real)
+}
+
+fn fixed_path(p: &Path, format: &str) -> PathBuf { p.with_extension(format) }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionMismatch {
+  path: String,
+  extension: String,
+  real_format: &'static str,
+  /// Same name with the extension the content calls for.
+  suggested_path: String,
+  /// Whether that extension is enabled; `fix_extension` refuses when not.
+  suggested_enabled: bool,
+}
+
+/// Files directly in `folder` whose content doesn't match the extension.
+/// Runs whatever the `verify_extensions_on_scan` setting says.
+#[tauri::command]
+pub async fn verify_extensions(folder: String) -> Result<Vec<ExtensionMismatch>, String> {
+  let _span = command_span("verify_extensions");
+  tauri::async_runtime::spawn_blocking(move || {
+    let mut paths = folder_entries(Path::new(&folder), false)?;
+    paths.sort();
+    let out: Vec<ExtensionMismatch> = paths
+      .iter()
+      .filter_map(|p| {
+        let real = mismatch(p)?;
+        Some(ExtensionMismatch {
+          path: p.to_string_lossy().to_string(),
+          extension: ext_lower(p),
+          real_format: real,
+          suggested_path: fixed_path(p, real).to_string_lossy().to_string(),
+          suggested_enabled: formats::is_enabled(real),
+        })
+      })
+      .collect();
+    log_line(&format!("verify_extensions folder=\"{}\" files={} mismatched={}", folder, paths.len(), out.len()));
+    Ok(out)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionFix {
+  /// `false` when the user declined; nothing was renamed.
+  confirmed: bool,
+  path: String,
+  real_format: &'static str,
+  renamed_to: Option<String>,
+}
+
+/// The real format of `p` and the path `fix_extension` renames it to, or why
+/// it won't.
+fn planned_fix(p: &Path) -> Result<(&'static str, PathBuf), String> {
+  let real = match sniff(p) {
+    None => return Err(format!("content of {} isn't a recognized audio format", p.display())),
+    Some(real) if ext_fits(&ext_lower(p), real) => return Err(format!("{} already has the right extension", p.display())),
+    Some(real) => real,
+  };
+  if !formats::is_enabled(real) {
+    return Err(format!("the .{} extension isn't enabled, so the renamed file wouldn't be listed; enable it in Settings first", real));
+  }
+  let target = fixed_path(p, real);
+  if target.exists() { return Err(format!("rename target exists: {}", target.display())); }
+  Ok((real, target))
+}
+
+/// Rename `p` to `target`; returns where the file went: `target`, or the
+/// shadow copy's new path.
+fn rename_to(p: &Path, target: &Path) -> Result<PathBuf, String> {
+  archive::guard(p).map_err(|e| e.to_string())?;
+  match shadow::rename(p, target)? {
+    Some(copy) => {
+      let (f, t) = (p.to_string_lossy().to_string(), copy.to_string_lossy().to_string());
+      audit::record(&f, "path", Some(&f), Some(&t), audit::Source::Manual);
+      Ok(copy)
+    }
+    None => {
+      folder_watch::migrate(p, target, audit::Source::Manual);
+      Ok(target.to_path_buf())
+    }
+  }
+}
+
+/// Rename `path` to the extension its content calls for, after a native
+/// confirmation. Everything stored per path follows the file to its new name.
+#[tauri::command]
+pub async fn fix_extension(window: tauri::Window, path: String) -> Result<ExtensionFix, String> {
+  let _span = command_span("fix_extension");
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = PathBuf::from(&path);
+    let (real, target) = planned_fix(&p)?;
+    let mut res = ExtensionFix { confirmed: false, path: path.clone(), real_format: real, renamed_to: None };
+    let name = |q: &Path| q.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let msg = format!("\"{}\" contains {} audio. Rename it to \"{}\"?", name(&p), real.to_uppercase(), name(&target));
+    if !tauri::api::dialog::blocking::confirm(Some(&window), "Fix file extension", msg) { return Ok(res); }
+    res.confirmed = true;
+    let to = rename_to(&p, &target)?.to_string_lossy().to_string();
+    log_line(&format!("fix_extension path=\"{}\" to=\"{}\"", path, to));
+    res.renamed_to = Some(to);
+    Ok(res)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{field_locks::{self, LockedField}, test_support};
+
+  #[test]
+  fn fixes_follow_the_file_and_stay_listed() {
+    let dir = test_support::scratch("extension-check");
+    let mp3 = test_support::audio(&dir, "a.mp3");
+    let wrong = dir.join("a.wav");
+    fs::rename(&mp3, &wrong).unwrap();
+    field_locks::set(&wrong, &[LockedField::Comment], true).unwrap();
+    let (real, target) = planned_fix(&wrong).unwrap();
+    assert_eq!((real, &target), ("mp3", &mp3));
+
+    assert_eq!(rename_to(&wrong, &target).unwrap(), mp3);
+    assert_eq!(field_locks::locked(&mp3), [LockedField::Comment]);
+    assert!(field_locks::locked(&wrong).is_empty());
+
+    // Ogg isn't enabled by default: the fixed file would vanish from the list.
+    let ogg = dir.join("b.mp3");
+    let mut head = b"OggS".to_vec();
+    head.resize(64, 0);
+    fs::write(&ogg, head).unwrap();
+    assert!(planned_fix(&ogg).unwrap_err().contains(".ogg extension isn't enabled"));
+  }
+}
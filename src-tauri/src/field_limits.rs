@@ -0,0 +1,243 @@
+// Per-format field length limits, enforced in the write path for every
+// target tag. RIFF INFO is read by hardware that cuts long chunks and ID3v1
+// has fixed-width slots; a value over the limit gets the configured strategy
+// instead of a blind write that ends in "#melo". Only fields the edit
+// changed are checked, so an over-long value already in a file isn't touched
+// by an unrelated edit. Lengths are UTF-8 bytes.
+//
+// A WAV writes RIFF INFO and ID3v2 together; only the RIFF copy is limited, so
+// truncating or skipping it leaves the ID3v2 copy whole.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use lofty::{ItemKey, ItemValue, Tag, TagItem, TagType};
+use serde::{Deserialize, Serialize};
+
+use crate::{dates, error::CmdError};
+
+/// RIFF INFO text chunks. The format allows more; players and CDJs don't.
+const RIFF_INFO_MAX: usize = 255;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitStrategy {
+  /// Fail the whole write.
+  Reject,
+  /// Drop whole tokens (`;`-separated tags, else words) from the end until it
+  /// fits; a date keeps its year.
+  #[default]
+  Truncate,
+  /// Leave this tag type as it was and write the others.
+  Skip,
+}
+
+pub static STRATEGY: AtomicU8 = AtomicU8::new(LimitStrategy::Truncate as u8);
+
+fn strategy() -> LimitStrategy {
+  match STRATEGY.load(Ordering::Relaxed) {
+    x if x == LimitStrategy::Reject as u8 => LimitStrategy::Reject,
+    x if x == LimitStrategy::Skip as u8 => LimitStrategy::Skip,
+    _ => LimitStrategy::Truncate,
+  }
+}
+
+/// Longest value `key` can safely hold in a `tt` tag, if limited.
+pub fn limit(tt: TagType, key: &ItemKey) -> Option<usize> {
+  match tt {
+    TagType::RiffInfo => Some(RIFF_INFO_MAX),
+    TagType::Id3v1 => match key {
+      ItemKey::TrackTitle | ItemKey::TrackArtist | ItemKey::AlbumTitle => Some(30),
+      // ID3v1.1: the last two bytes hold the track number.
+      ItemKey::Comment => Some(28),
+      ItemKey::Year | ItemKey::RecordingDate => Some(4),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+/// Longest prefix of `value` within `max` bytes that ends on a token
+/// boundary; empty when even the first token doesn't fit.
+pub fn truncate_at_token(value: &str, max: usize) -> String {
+  let (sep, tokens): (&str, Vec<&str>) = if value.contains(';') {
+    (";", value.split(';').map(str::trim).filter(|t| !t.is_empty()).collect())
+  } else {
+    (" ", value.split_whitespace().collect())
+  };
+  let mut out = String::new();
+  for t in tokens {
+    // Comments keep their trailing ';' (see `tag_ops::join_tokens`).
+    let next = if sep == ";" { format!("{}{};", out, t) } else if out.is_empty() { t.to_string() } else { format!("{} {}", out, t) };
+    if next.len() > max { break; }
+    out = next;
+  }
+  out
+}
+
+/// `value` cut to fit `max` for `key`: a date keeps its year ("2023-05-01"
+/// in a 4-byte ID3v1 slot is "2023"), other text whole tokens.
+fn cut(key: &ItemKey, value: &str, max: usize) -> String {
+  if matches!(key, ItemKey::Year | ItemKey::RecordingDate) {
+    if let Some(d) = dates::normalize_date(value).filter(|_| max >= 4) { return d[..4].to_string(); }
+  }
+  truncate_at_token(value, max)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction { Truncated, Dropped, Skipped }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldLimitHit {
+  /// lofty tag type, e.g. "RiffInfo".
+  pub tag_type: String,
+  /// lofty item key, e.g. "Comment".
+  pub field: String,
+  pub limit: usize,
+  pub length: usize,
+  pub action: LimitAction,
+  /// What went into this tag type instead; `None` when nothing did.
+  pub written: Option<String>,
+}
+
+fn changed_text<'a>(before: &Tag, after: &'a Tag) -> Vec<(&'a ItemKey, &'a str)> {
+  after.items()
+    .filter(|i| !before.items().any(|b| b == *i))
+    .filter_map(|i| match i.value() { ItemValue::Text(t) => Some((i.key(), t.as_str())), _ => None })
+    .collect()
+}
+
+/// Check `tag` (a `tt` target, `before` the edit) against the limits and
+/// apply the strategy. A `Skip` restores `tag` to `before`.
+pub fn enforce(tt: TagType, before: &Tag, tag: &mut Tag, path: &str) -> Result<Vec<FieldLimitHit>, CmdError> {
+  enforce_with(strategy(), tt, before, tag, path)
+}
+
+fn enforce_with(strategy: LimitStrategy, tt: TagType, before: &Tag, tag: &mut Tag, path: &str) -> Result<Vec<FieldLimitHit>, CmdError> {
+  let over: Vec<(ItemKey, String, usize)> = changed_text(before, tag)
+    .into_iter()
+    .filter_map(|(k, v)| limit(tt, k).filter(|max| v.len() > *max).map(|max| (k.clone(), v.to_string(), max)))
+    .collect();
+  if over.is_empty() { return Ok(Vec::new()); }
+
+  let hit = |k: &ItemKey, v: &str, max: usize, action, written| FieldLimitHit {
+    tag_type: format!("{:?}", tt), field: format!("{:?}", k), limit: max, length: v.len(), action, written,
+  };
+  match strategy {
+    LimitStrategy::Reject => {
+      let (k, v, max) = &over[0];
+      Err(CmdError::FieldTooLong {
+        path: path.to_string(),
+        tag_type: format!("{:?}", tt),
+        field: format!("{:?}", k),
+        limit: *max,
+        length: v.len(),
+        message: format!("{:?} is {} bytes; {:?} tags hold at most {} here. Nothing was written.", k, v.len(), tt, max),
+      })
+    }
+    LimitStrategy::Skip => {
+      *tag = before.clone();
+      Ok(over.iter().map(|(k, v, max)| hit(k, v, *max, LimitAction::Skipped, None)).collect())
+    }
+    LimitStrategy::Truncate => Ok(over.iter().map(|(k, v, max)| {
+      let cut = cut(k, v, *max);
+      if cut.is_empty() {
+        tag.remove_key(k);
+        hit(k, v, *max, LimitAction::Dropped, None)
+      } else {
+        tag.insert(TagItem::new(k.clone(), ItemValue::Text(cut.clone())));
+        hit(k, v, *max, LimitAction::Truncated, Some(cut))
+      }
+    }).collect()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn truncation_drops_whole_tokens() {
+    assert_eq!(truncate_at_token("#melodic;#deep;#afro;", 15), "#melodic;#deep;");
+    assert_eq!(truncate_at_token("A Very Long Title", 10), "A Very");
+    assert_eq!(truncate_at_token("#averyveryverylongtag;", 8), "");
+  }
+
+  #[test]
+  fn a_date_in_an_id3v1_slot_keeps_its_year() {
+    let before = Tag::new(TagType::Id3v1);
+    let mut tag = before.clone();
+    tag.insert_text(ItemKey::Year, "2023-05-01".into());
+    let hits = enforce(TagType::Id3v1, &before, &mut tag, "a.mp3").unwrap();
+    assert_eq!(tag.get_string(&ItemKey::Year), Some("2023"));
+    assert_eq!((hits.len(), hits[0].action, hits[0].written.as_deref()), (1, LimitAction::Truncated, Some("2023")));
+    // Nothing date-like to keep: dropped, as before.
+    assert_eq!(cut(&ItemKey::Year, "sometime", 4), "");
+  }
+
+  /// A `tt` tag holding `key` = `value`, edited from an empty one.
+  fn edited(tt: TagType, key: &ItemKey, value: &str) -> (Tag, Tag) {
+    let before = Tag::new(tt);
+    let mut tag = before.clone();
+    tag.insert_text(key.clone(), value.to_string());
+    (before, tag)
+  }
+
+  #[test]
+  fn each_strategy_on_each_limited_format() {
+    let comment = "#melodic;#deep;#afro;".repeat(14);
+    let title = "A Title Much Longer Than Thirty Bytes";
+    for (tt, key, value, max, cut_to) in [
+      (TagType::RiffInfo, ItemKey::Comment, comment.as_str(), RIFF_INFO_MAX, truncate_at_token(&comment, RIFF_INFO_MAX)),
+      (TagType::Id3v1, ItemKey::TrackTitle, title, 30, "A Title Much Longer Than".to_string()),
+      (TagType::Id3v1, ItemKey::Comment, "#melodic;#deep;#afro;#techno;", 28, "#melodic;#deep;#afro;".to_string()),
+    ] {
+      let case = format!("{:?} {:?}", tt, key);
+      let (before, mut tag) = edited(tt, &key, value);
+      match enforce_with(LimitStrategy::Reject, tt, &before, &mut tag, "a") {
+        Err(CmdError::FieldTooLong { limit, length, .. }) => assert_eq!((limit, length), (max, value.len()), "{}", case),
+        other => panic!("{}: {:?}", case, other),
+      }
+
+      let (before, mut tag) = edited(tt, &key, value);
+      let hits = enforce_with(LimitStrategy::Truncate, tt, &before, &mut tag, "a").unwrap();
+      assert_eq!(tag.get_string(&key), Some(cut_to.as_str()), "{}", case);
+      assert_eq!((hits.len(), hits[0].action, hits[0].written.as_deref(), hits[0].limit), (1, LimitAction::Truncated, Some(cut_to.as_str()), max), "{}", case);
+
+      let (before, mut tag) = edited(tt, &key, value);
+      let hits = enforce_with(LimitStrategy::Skip, tt, &before, &mut tag, "a").unwrap();
+      assert_eq!(tag.get_string(&key), None, "{}: left as it was", case);
+      assert_eq!((hits.len(), hits[0].action, hits[0].written.as_deref()), (1, LimitAction::Skipped, None), "{}", case);
+
+      // The same value in ID3v2 has no limit under any strategy.
+      for strategy in [LimitStrategy::Reject, LimitStrategy::Truncate, LimitStrategy::Skip] {
+        let (before, mut tag) = edited(TagType::Id3v2, &key, value);
+        assert!(enforce_with(strategy, TagType::Id3v2, &before, &mut tag, "a").unwrap().is_empty());
+        assert_eq!(tag.get_string(&key), Some(value));
+      }
+    }
+  }
+
+  #[test]
+  fn a_single_token_too_long_is_dropped() {
+    let long = format!("#{};", "x".repeat(40));
+    let (before, mut tag) = edited(TagType::Id3v1, &ItemKey::Comment, &long);
+    let hits = enforce_with(LimitStrategy::Truncate, TagType::Id3v1, &before, &mut tag, "a").unwrap();
+    assert_eq!(tag.get_string(&ItemKey::Comment), None);
+    assert_eq!((hits[0].action, hits[0].written.as_deref()), (LimitAction::Dropped, None));
+  }
+
+  #[test]
+  fn a_wav_keeps_the_whole_value_in_id3v2_when_riff_info_is_cut() {
+    let dir = crate::test_support::scratch("limits-wav");
+    let p = crate::test_support::audio(&dir, "a.wav");
+    let comment = "#melodic;#deep;#afro;".repeat(14);
+    let outcome = crate::edit_tags(&p, |tag| { tag.insert_text(ItemKey::Comment, comment.clone()); }).unwrap();
+    let riff = truncate_at_token(&comment, RIFF_INFO_MAX);
+    let hits: Vec<(&str, &str, LimitAction, Option<&str>)> =
+      outcome.limited.iter().map(|h| (h.tag_type.as_str(), h.field.as_str(), h.action, h.written.as_deref())).collect();
+    assert_eq!(hits, [("RiffInfo", "Comment", LimitAction::Truncated, Some(riff.as_str()))]);
+    assert_eq!(crate::test_support::text(&p, TagType::RiffInfo, &ItemKey::Comment), Some(riff));
+    assert_eq!(crate::test_support::text(&p, TagType::Id3v2, &ItemKey::Comment), Some(comment));
+  }
+}
@@ -0,0 +1,272 @@
+// Per-file field locks, e.g. the ISRC and title of a released master. Kept in
+// data dir `field_locks.json`, keyed by canonical path. `edit_tags` puts a
+// locked field back to what the file held after the edit closure ran, so
+// every write through it (single edits, batches, templates, normalization,
+// conversions onto their copies) skips it and reports it in
+// `WriteOutcome::skipped_locked` instead of failing. Edits that reach past
+// the usual tag types (comment precedence, the APE migration) use
+// `edit_tags_with`, which enforces them the same way.
+//
+// Locks travel with the file's data: snapshots and tag manifests carry them,
+// conversions copy them onto the output, and renames move them along.
+
+use std::{collections::{BTreeSet, HashMap}, fs, path::{Path, PathBuf}};
+use lofty::{ItemKey, Tag};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{data_dir, log_line, write_atomic};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LockedField { Title, Artist, Album, Genre, Comment, Isrc, Bpm, Key, ReleaseDate, OriginalDate, TrackNumber, Artwork }
+
+impl LockedField {
+  /// Items the field is stored under; empty for artwork (pictures).
+  fn keys(self) -> Vec<ItemKey> {
+    match self {
+      LockedField::Title => vec![ItemKey::TrackTitle],
+      LockedField::Artist => vec![ItemKey::TrackArtist],
+      LockedField::Album => vec![ItemKey::AlbumTitle],
+      LockedField::Genre => vec![ItemKey::Genre],
+      LockedField::Comment => vec![ItemKey::Comment],
+      LockedField::Isrc => vec![ItemKey::Isrc],
+      LockedField::Bpm => vec![ItemKey::Bpm, ItemKey::IntegerBpm],
+      LockedField::Key => vec![ItemKey::InitialKey],
+      // What `dates::release_date` reads, TDAT included.
+      LockedField::ReleaseDate => vec![ItemKey::RecordingDate, ItemKey::Year, ItemKey::Unknown("TDAT".into())],
+      LockedField::OriginalDate => vec![ItemKey::OriginalReleaseDate],
+      LockedField::TrackNumber => vec![ItemKey::TrackNumber, ItemKey::TrackTotal],
+      LockedField::Artwork => Vec::new(),
+    }
+  }
+
+  pub fn name(self) -> String {
+    serde_json::to_value(self).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+  }
+
+  /// The field name `MetaPatch` writes are audited under.
+  pub fn audit_name(self) -> &'static str {
+    match self {
+      LockedField::ReleaseDate => "release_date",
+      LockedField::OriginalDate => "original_date",
+      LockedField::Title => "title",
+      LockedField::Artist => "artist",
+      LockedField::Genre => "genre",
+      _ => "",
+    }
+  }
+}
+
+type Locks = HashMap<String, BTreeSet<LockedField>>;
+
+static STORE: Lazy<Mutex<Option<Locks>>> = Lazy::new(|| Mutex::new(None));
+
+fn locks_path() -> PathBuf { data_dir().join("field_locks.json") }
+
+fn key(p: &Path) -> String {
+  fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()).to_string_lossy().to_string()
+}
+
+fn with_locks<T>(f: impl FnOnce(&mut Locks) -> T) -> T {
+  let mut s = STORE.lock();
+  let locks = s.get_or_insert_with(|| {
+    fs::read_to_string(locks_path()).ok().and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default()
+  });
+  f(locks)
+}
+
+fn save(locks: &Locks) -> Result<(), String> {
+  let p = locks_path();
+  if let Some(dir) = p.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+  write_atomic(&p, &serde_json::to_vec_pretty(locks).map_err(|e| e.to_string())?)
+}
+
+/// Locked fields of `p`, in a fixed order.
+pub fn locked(p: &Path) -> Vec<LockedField> {
+  with_locks(|locks| {
+    // Most libraries have no locks; skip the canonicalize on every scanned file.
+    if locks.is_empty() { return Vec::new(); }
+    locks.get(&key(p)).map(|s| s.iter().copied().collect()).unwrap_or_default()
+  })
+}
+
+/// Which of the `changed` fields a write to `p` would skip, for dry runs.
+pub fn hits(p: &Path, changed: &[LockedField]) -> Vec<LockedField> {
+  let locked = locked(p);
+  changed.iter().copied().filter(|f| locked.contains(f)).collect()
+}
+
+/// Add `fields` to `p`'s locks (`lock`) or take them off; returns what's locked now.
+pub fn set(p: &Path, fields: &[LockedField], lock: bool) -> Result<Vec<LockedField>, String> {
+  let k = key(p);
+  with_locks(|locks| {
+    let set = locks.entry(k.clone()).or_default();
+    for f in fields { if lock { set.insert(*f); } else { set.remove(f); } }
+    let now: Vec<LockedField> = set.iter().copied().collect();
+    if now.is_empty() { locks.remove(&k); }
+    save(locks)?;
+    Ok(now)
+  })
+}
+
+/// Give `dst` the locks of `src` as well (conversion outputs, once their tags are copied).
+pub fn copy(src: &Path, dst: &Path) -> Result<(), String> {
+  let fields = locked(src);
+  if fields.is_empty() { return Ok(()); }
+  set(dst, &fields, true).map(|_| ())
+}
+
+/// Carry `from`'s locks over a rename; `from` is the canonical path from
+/// before it (see `folder_watch::migrate`).
+pub fn rename(from: &Path, to: &Path) {
+  let res = with_locks(|locks| match locks.remove(&*from.to_string_lossy()) {
+    Some(fields) => { locks.insert(key(to), fields); save(locks) }
+    None => Ok(()),
+  });
+  if let Err(e) = res { log_line(&format!("field_locks rename failed: {}", e)); }
+}
+
+/// Drop every lock on `p`, e.g. before a fresh file is written over it.
+pub fn clear(p: &Path) -> Result<(), String> {
+  let k = key(p);
+  with_locks(|locks| if locks.remove(&k).is_some() { save(locks) } else { Ok(()) })
+}
+
+fn same_items(a: &Tag, b: &Tag, k: &ItemKey) -> bool {
+  let (x, y): (Vec<_>, Vec<_>) = (a.get_items(k).collect(), b.get_items(k).collect());
+  x.len() == y.len() && x.iter().all(|i| y.contains(i))
+}
+
+fn same_pictures(a: &Tag, b: &Tag) -> bool {
+  a.picture_count() == b.picture_count() && a.pictures().iter().all(|pic| b.pictures().contains(pic))
+}
+
+/// Undo the edit's changes to `locked` fields of `tag` (`before` is the tag as
+/// read). Returns the fields that had to be put back.
+pub fn enforce(before: &Tag, tag: &mut Tag, locked: &[LockedField]) -> Vec<LockedField> {
+  let mut skipped = Vec::new();
+  for &field in locked {
+    if field == LockedField::Artwork {
+      if same_pictures(before, tag) { continue; }
+      while tag.picture_count() > 0 { tag.remove_picture(0); }
+      for pic in before.pictures() { tag.push_picture(pic.clone()); }
+    } else {
+      let keys = field.keys();
+      if keys.iter().all(|k| same_items(before, tag, k)) { continue; }
+      for k in &keys {
+        tag.remove_key(k);
+        for item in before.get_items(k) { tag.push(item.clone()); }
+      }
+    }
+    skipped.push(field);
+  }
+  skipped
+}
+
+/// Lock `fields` of `path` against every write; returns all its locked fields.
+#[tauri::command]
+pub fn lock_fields(path: String, fields: Vec<LockedField>) -> Result<Vec<LockedField>, String> {
+  let now = set(Path::new(&path), &fields, true)?;
+  log_line(&format!("lock_fields path=\"{}\" fields={:?}", path, fields));
+  Ok(now)
+}
+
+#[tauri::command]
+pub fn unlock_fields(path: String, fields: Vec<LockedField>) -> Result<Vec<LockedField>, String> {
+  let now = set(Path::new(&path), &fields, false)?;
+  log_line(&format!("unlock_fields path=\"{}\" fields={:?}", path, fields));
+  Ok(now)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::TagType;
+  use crate::{apply_meta_patch, audit, folder_watch, tag_ops, test_support, write_comment_as, MetaPatch};
+
+  fn locked_file(name: &str, fields: &[LockedField]) -> PathBuf {
+    let dir = test_support::scratch("locks");
+    let p = test_support::tagged(&dir, name, &[(ItemKey::TrackTitle, "Master"), (ItemKey::TrackArtist, "Artist"), (ItemKey::Comment, "#keep")]);
+    set(&p, fields, true).unwrap();
+    p
+  }
+
+  fn title(p: &Path) -> Option<String> { test_support::text(p, TagType::Id3v2, &ItemKey::TrackTitle) }
+  fn comment(p: &Path) -> Option<String> { test_support::text(p, TagType::Id3v2, &ItemKey::Comment) }
+
+  #[test]
+  fn enforce_puts_locked_items_back() {
+    let mut before = Tag::new(TagType::Id3v2);
+    before.insert_text(ItemKey::TrackTitle, "Master".into());
+    before.insert_text(ItemKey::Isrc, "GBAAA0000001".into());
+    let mut tag = before.clone();
+    tag.insert_text(ItemKey::TrackTitle, "Edit".into());
+    tag.remove_key(&ItemKey::Isrc);
+    tag.insert_text(ItemKey::Genre, "House".into());
+    let skipped = enforce(&before, &mut tag, &[LockedField::Title, LockedField::Isrc, LockedField::Bpm]);
+    assert_eq!(skipped, vec![LockedField::Title, LockedField::Isrc]);
+    assert_eq!(tag.get_string(&ItemKey::TrackTitle), Some("Master"));
+    assert_eq!(tag.get_string(&ItemKey::Isrc), Some("GBAAA0000001"));
+    assert_eq!(tag.get_string(&ItemKey::Genre), Some("House"));
+  }
+
+  #[test]
+  fn comment_write_skips_a_locked_comment() {
+    let p = locked_file("comment.mp3", &[LockedField::Comment]);
+    let out = write_comment_as(&p.to_string_lossy(), "#new", audit::Source::Manual).unwrap();
+    assert_eq!(out.skipped_locked, vec![LockedField::Comment]);
+    assert!(out.no_op);
+    assert_eq!(comment(&p).as_deref(), Some("#keep"));
+  }
+
+  #[test]
+  fn metadata_patch_writes_only_unlocked_fields() {
+    let p = locked_file("patch.mp3", &[LockedField::Title]);
+    let patch = MetaPatch { title: Some("Radio Edit".into()), artist: Some("Someone Else".into()), ..Default::default() };
+    let out = apply_meta_patch(&p, &patch).unwrap();
+    assert_eq!(out.skipped_locked, vec![LockedField::Title]);
+    assert_eq!(title(&p).as_deref(), Some("Master"));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackArtist).as_deref(), Some("Someone Else"));
+  }
+
+  #[test]
+  fn batch_tag_merge_skips_a_locked_comment() {
+    let p = locked_file("merge.mp3", &[LockedField::Comment]);
+    let out = tag_ops::merge_file_tags(&p.to_string_lossy(), &["#added".into()], &[], audit::Source::Batch).unwrap();
+    assert_eq!(out.skipped_locked, vec![LockedField::Comment]);
+    assert_eq!(comment(&p).as_deref(), Some("#keep"));
+  }
+
+  #[test]
+  fn locks_follow_a_rename() {
+    let p = locked_file("before.mp3", &[LockedField::Isrc]);
+    let to = p.with_file_name("after.mp3");
+    fs::rename(&p, &to).unwrap();
+    folder_watch::migrate(&p, &to, audit::Source::Manual);
+    assert!(locked(&p).is_empty());
+    assert_eq!(locked(&to), vec![LockedField::Isrc]);
+  }
+
+  /// Every save goes through `edit_tags_inner`, which enforces the locks. A
+  /// new writer that saves on its own fails here until it is routed through
+  /// it (or, like the padding rewrite, changes no field at all and is listed).
+  #[test]
+  fn no_writer_saves_around_edit_tags() {
+    const ALLOWED: [&str; 2] = ["main.rs", "id3_padding.rs"];
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut offenders = Vec::new();
+    for entry in fs::read_dir(&src).unwrap() {
+      let p = entry.unwrap().path();
+      let name = p.file_name().unwrap().to_string_lossy().to_string();
+      if p.extension().is_none_or(|e| e != "rs") || ALLOWED.contains(&name.as_str()) || name == "test_support.rs" { continue; }
+      let text = fs::read_to_string(&p).unwrap();
+      let code = text.split("#[cfg(test)]").next().unwrap_or("");
+      for needle in ["save_tagged_file_to_path(", ".save_to_path(", ".save_to(", "remove_from_path("] {
+        if code.contains(needle) { offenders.push(format!("{} calls {}", name, needle)); }
+      }
+    }
+    assert!(offenders.is_empty(), "writes bypassing edit_tags: {:?}", offenders);
+  }
+}
@@ -0,0 +1,176 @@
+// Spotting 0-byte and truncated files (aborted downloads) before they reach
+// the tag reader or the player. `check` only reads the first bytes, so it is
+// cheap enough to run on every scanned file.
+
+use std::{fs, io::Read, path::{Path, PathBuf}, sync::atomic::Ordering};
+use serde::Serialize;
+
+use crate::{archive, audit, command_span, ext_lower, extension_check, log_line, long_paths, read_tagged, shadow, supported_ext};
+
+pub const QUARANTINE_DIR: &str = "_corrupt";
+const ZERO_PAD_SCAN: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus { Ok, Empty, Corrupt, Mismatched }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+  pub status: FileStatus,
+  /// Human-readable, for the list tooltip.
+  pub status_reason: Option<String>,
+  /// Sniffed format of a `Mismatched` file ("flac" for a FLAC named .mp3).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub real_format: Option<&'static str>,
+}
+
+impl Health {
+  pub fn ok() -> Self { Health { status: FileStatus::Ok, status_reason: None, real_format: None } }
+  fn corrupt(reason: String) -> Self { Health { status: FileStatus::Corrupt, status_reason: Some(reason), real_format: None } }
+  fn mismatched(ext: &str, real: &'static str) -> Self {
+    Health { status: FileStatus::Mismatched, status_reason: Some(format!("{} audio named .{}", real.to_uppercase(), ext)), real_format: Some(real) }
+  }
+  pub fn is_ok(&self) -> bool { self.status == FileStatus::Ok }
+  pub fn reason(&self) -> &str { self.status_reason.as_deref().unwrap_or("damaged file") }
+}
+
+/// Size and magic-byte check. WAV/AIFF also compare the declared chunk size
+/// with the real length, which catches most truncated downloads. With
+/// `verify_extensions_on_scan` on, content in another format is reported as
+/// `Mismatched` instead of corrupt.
+pub fn check(p: &Path) -> Health {
+  let Ok(meta) = fs::metadata(long_paths::extended(p)) else { return Health::ok() }; // missing files are someone else's error
+  let len = meta.len();
+  if len == 0 { return Health { status: FileStatus::Empty, status_reason: Some("0-byte file (aborted download?)".into()), real_format: None }; }
+
+  let mut head = [0u8; 12];
+  let n = match fs::File::open(long_paths::extended(p)).and_then(|mut f| f.read(&mut head)) {
+    Ok(n) => n,
+    Err(_) => return Health::ok(),
+  };
+  if n < head.len() { return Health::corrupt(format!("file is only {} bytes", len)); }
+  if extension_check::ON_SCAN.load(Ordering::Relaxed) {
+    if let Some(real) = extension_check::mismatch(p) { return Health::mismatched(&ext_lower(p), real); }
+  }
+
+  let id3 = &head[..3] == b"ID3";
+  let be32 = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64;
+  let le32 = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as u64;
+  let declared_short = |declared: u64| (declared > len).then(|| Health::corrupt(format!("truncated: header declares {} bytes, file has {}", declared, len)));
+
+  match ext_lower(p).as_str() {
+    "mp3" if id3 || (head[0] == 0xFF && head[1] & 0xE0 == 0xE0) => Health::ok(),
+    "mp3" if head[0] == 0 && frame_after_zeros(p) => Health::ok(),
+    "mp3" => Health::corrupt("no ID3 tag or MPEG frame at the start".into()),
+    "flac" if id3 || &head[..4] == b"fLaC" => Health::ok(),
+    "flac" => Health::corrupt("missing fLaC signature".into()),
+    "wav" if &head[..4] == b"RIFF" && &head[8..12] == b"WAVE" => declared_short(le32(&head[4..8]) + 8).unwrap_or(Health::ok()),
+    // RF64 keeps the real size elsewhere; the magic is all we check.
+    "wav" if &head[..4] == b"RF64" => Health::ok(),
+    "wav" => Health::corrupt("missing RIFF/WAVE header".into()),
+    "aiff" | "aif" if &head[..4] == b"FORM" && matches!(&head[8..12], b"AIFF" | b"AIFC") => declared_short(be32(&head[4..8]) + 8).unwrap_or(Health::ok()),
+    "aiff" | "aif" => Health::corrupt("missing FORM/AIFF header".into()),
+    "m4a" if &head[4..8] == b"ftyp" => Health::ok(),
+    "m4a" => Health::corrupt("missing ftyp box".into()),
+    "mpc" if id3 || &head[..4] == b"MPCK" || &head[..3] == b"MP+" => Health::ok(),
+    "mpc" => Health::corrupt("missing Musepack signature".into()),
+    _ => Health::ok(),
+  }
+}
+
+/// Some encoders and rippers pad the start of an MP3 with zero bytes; an
+/// MPEG frame sync right after them in the first ZERO_PAD_SCAN bytes is fine.
+fn frame_after_zeros(p: &Path) -> bool {
+  let mut buf = vec![0u8; ZERO_PAD_SCAN];
+  let Ok(n) = fs::File::open(long_paths::extended(p)).and_then(|mut f| f.read(&mut buf)) else { return false };
+  let Some(i) = buf[..n].iter().position(|&b| b != 0) else { return false };
+  i + 1 < n && buf[i] == 0xFF && buf[i + 1] & 0xE0 == 0xE0
+}
+
+/// `check` plus a full tag parse; for explicit sweeps, not scans.
+fn check_deep(p: &Path) -> Health {
+  let h = check(p);
+  if !h.is_ok() { return h; }
+  match read_tagged(p) {
+    Ok(_) => h,
+    Err(e) => Health::corrupt(format!("unreadable: {}", e)),
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedFile {
+  path: String,
+  moved_to: Option<String>,
+  health: Health,
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantineReport {
+  /// `false` when the user declined (nothing was moved).
+  confirmed: bool,
+  files: Vec<QuarantinedFile>,
+}
+
+fn free_target(dir: &Path, name: &str) -> PathBuf {
+  let candidate = dir.join(name);
+  if !candidate.exists() { return candidate; }
+  let p = Path::new(name);
+  let stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let ext = p.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+  (2..).map(|n| dir.join(format!("{} ({}){}", stem, n, ext))).find(|c| !c.exists()).unwrap()
+}
+
+/// Move empty/corrupt files in `folder` into `folder/_corrupt` after a
+/// native confirmation dialog listing what will move.
+#[tauri::command]
+pub async fn quarantine_bad_files(window: tauri::Window, folder: String) -> Result<QuarantineReport, String> {
+  let _span = command_span("quarantine_bad_files");
+  tauri::async_runtime::spawn_blocking(move || {
+    let dir = PathBuf::from(&folder);
+    let mut files: Vec<QuarantinedFile> = fs::read_dir(&dir).map_err(|e| e.to_string())?
+      .flatten()
+      .map(|e| e.path())
+      .filter(|p| p.is_file() && supported_ext(p))
+      .filter_map(|p| {
+        let health = check_deep(&p);
+        // Misnamed files are fine under the right extension; see `fix_extension`.
+        matches!(health.status, FileStatus::Empty | FileStatus::Corrupt).then(|| QuarantinedFile { path: p.to_string_lossy().to_string(), moved_to: None, health, error: None })
+      })
+      .collect();
+    if files.is_empty() { return Ok(QuarantineReport { confirmed: true, files }); }
+
+    let names: Vec<String> = files.iter().take(10)
+      .map(|f| Path::new(&f.path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default())
+      .collect();
+    let more = if files.len() > names.len() { format!("\n… and {} more", files.len() - names.len()) } else { String::new() };
+    let msg = format!("Move {} damaged file(s) into \"{}\"?\n\n{}{}", files.len(), QUARANTINE_DIR, names.join("\n"), more);
+    if !tauri::api::dialog::blocking::confirm(Some(&window), "Quarantine damaged files", msg) {
+      return Ok(QuarantineReport { confirmed: false, files });
+    }
+
+    let target_dir = dir.join(QUARANTINE_DIR);
+    fs::create_dir_all(&target_dir).map_err(|e| e.to_string())?;
+    for f in &mut files {
+      let name = Path::new(&f.path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+      let target = free_target(&target_dir, &name);
+      let moved = archive::guard(Path::new(&f.path)).map_err(|e| e.to_string()).and_then(|_| shadow::rename(Path::new(&f.path), &target));
+      match moved {
+        Ok(moved) => {
+          let to = moved.unwrap_or(target).to_string_lossy().to_string();
+          audit::record(&f.path, "path", Some(&f.path), Some(&to), audit::Source::Manual);
+          f.moved_to = Some(to);
+        }
+        Err(e) => f.error = Some(e.to_string()),
+      }
+    }
+    let moved = files.iter().filter(|f| f.moved_to.is_some()).count();
+    log_line(&format!("quarantine_bad_files folder=\"{}\" moved={} failed={}", folder, moved, files.len() - moved));
+    Ok(QuarantineReport { confirmed: true, files })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
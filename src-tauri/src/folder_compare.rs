@@ -0,0 +1,247 @@
+// Tag comparison between two folders, e.g. a gig crate synced from the main
+// library. Files are paired by name (case-folded, with our own "03 - " track
+// number prefix ignored, so a numbered copy still pairs with its original) or
+// by audio hash (`already_owned::HashCache`, so renamed copies pair too).
+// Files that share a key within one folder pair up in walk order; the rest
+// land in the only-in lists. Values compare as the tracks would play: comment
+// tokens in any order, BPMs numerically, keys in any notation.
+
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::atomic::AtomicUsize};
+use lofty::{ItemKey, TaggedFileExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  already_owned::{canonical, par_map, HashCache}, command_span, export::csv_field, jobs::JobHandle, library::audio_files_under, log_line,
+  name_hints::parse_tag_key, preferred_tag, read_comment, read_tagged, split_comment_tokens, track_numbers::strip_own_prefix, write_atomic,
+};
+
+const BPM_TOLERANCE: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchBy { Name, Hash }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompareField { Comment, Title, Artist, Bpm, Key, Artwork }
+
+const ALL_FIELDS: &[CompareField] =
+  &[CompareField::Comment, CompareField::Title, CompareField::Artist, CompareField::Bpm, CompareField::Key, CompareField::Artwork];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CompareOptions {
+  /// Also write the report here: CSV when it ends in ".csv", JSON otherwise.
+  export_to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDiff {
+  field: CompareField,
+  a: Option<String>,
+  b: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComparedPair {
+  a: String,
+  b: String,
+  /// Empty for equal pairs.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  diffs: Vec<FieldDiff>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareReport {
+  equal: Vec<ComparedPair>,
+  different: Vec<ComparedPair>,
+  only_in_a: Vec<String>,
+  only_in_b: Vec<String>,
+  /// Files that couldn't be hashed or read; they aren't in the lists above.
+  unreadable: Vec<String>,
+  fields: Vec<CompareField>,
+  cancelled: bool,
+  exported_to: Option<String>,
+}
+
+/// The compared values of one file.
+struct Side {
+  comment: String,
+  tokens: Vec<String>,
+  title: Option<String>,
+  artist: Option<String>,
+  bpm: Option<String>,
+  key: Option<String>,
+  artwork: bool,
+}
+
+fn read_side(p: &Path) -> Option<Side> {
+  let tf = read_tagged(p).ok()?;
+  let tag = preferred_tag(&tf, p);
+  let text = |k: ItemKey| tag.and_then(|t| t.get_string(&k)).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+  let comment = read_comment(&tf, p);
+  let mut tokens = split_comment_tokens(&comment);
+  tokens.sort();
+  Some(Side {
+    tokens,
+    comment,
+    title: text(ItemKey::TrackTitle),
+    artist: text(ItemKey::TrackArtist),
+    bpm: text(ItemKey::Bpm).or_else(|| text(ItemKey::IntegerBpm)),
+    key: text(ItemKey::InitialKey),
+    artwork: tf.tags().iter().any(|t| !t.pictures().is_empty()),
+  })
+}
+
+fn same_bpm(a: &Option<String>, b: &Option<String>) -> bool {
+  match (a.as_deref().and_then(|v| v.parse::<f64>().ok()), b.as_deref().and_then(|v| v.parse::<f64>().ok())) {
+    (Some(x), Some(y)) => (x - y).abs() <= BPM_TOLERANCE,
+    _ => a == b,
+  }
+}
+
+fn same_key(a: &Option<String>, b: &Option<String>) -> bool {
+  match (a.as_deref().and_then(parse_tag_key), b.as_deref().and_then(parse_tag_key)) {
+    (Some(x), Some(y)) => x == y,
+    _ => a == b,
+  }
+}
+
+fn diff(a: &Side, b: &Side, fields: &[CompareField]) -> Vec<FieldDiff> {
+  let art = |s: &Side| Some(if s.artwork { "yes" } else { "no" }.to_string());
+  let nonempty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+  fields
+    .iter()
+    .filter_map(|&field| {
+      let (same, va, vb) = match field {
+        CompareField::Comment => (a.tokens == b.tokens, nonempty(&a.comment), nonempty(&b.comment)),
+        CompareField::Title => (a.title == b.title, a.title.clone(), b.title.clone()),
+        CompareField::Artist => (a.artist == b.artist, a.artist.clone(), b.artist.clone()),
+        CompareField::Bpm => (same_bpm(&a.bpm, &b.bpm), a.bpm.clone(), b.bpm.clone()),
+        CompareField::Key => (same_key(&a.key, &b.key), a.key.clone(), b.key.clone()),
+        CompareField::Artwork => (a.artwork == b.artwork, art(a), art(b)),
+      };
+      (!same).then_some(FieldDiff { field, a: va, b: vb })
+    })
+    .collect()
+}
+
+fn name_key(p: &Path) -> Option<String> {
+  let stem = p.file_stem()?.to_string_lossy().to_string();
+  let ext = p.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+  Some(format!("{}.{}", strip_own_prefix(&stem).to_lowercase(), ext))
+}
+
+fn write_report(r: &CompareReport, dest: &str) -> Result<(), String> {
+  let bytes = if dest.to_lowercase().ends_with(".csv") {
+    let mut out = String::from("status,a,b,field,valueA,valueB\n");
+    let mut row = |cols: [&str; 6]| {
+      out.push_str(&cols.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+      out.push('\n');
+    };
+    for p in &r.equal { row(["equal", &p.a, &p.b, "", "", ""]); }
+    for p in &r.different {
+      for d in &p.diffs {
+        let field = serde_json::to_value(d.field).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+        row(["different", &p.a, &p.b, &field, d.a.as_deref().unwrap_or(""), d.b.as_deref().unwrap_or("")]);
+      }
+    }
+    for a in &r.only_in_a { row(["onlyInA", a, "", "", "", ""]); }
+    for b in &r.only_in_b { row(["onlyInB", "", b, "", "", ""]); }
+    for u in &r.unreadable { row(["unreadable", u, "", "", "", ""]); }
+    out.into_bytes()
+  } else {
+    serde_json::to_vec_pretty(r).map_err(|e| e.to_string())?
+  };
+  write_atomic(Path::new(dest), &bytes)
+}
+
+fn compare_blocking(job: &JobHandle, folder_a: &str, folder_b: &str, match_by: MatchBy, fields: Vec<CompareField>, opts: CompareOptions) -> Result<CompareReport, String> {
+  let fields = if fields.is_empty() { ALL_FIELDS.to_vec() } else { fields };
+  if canonical(Path::new(folder_a)) == canonical(Path::new(folder_b)) { return Err("both sides are the same folder".into()); }
+  job.begin_phase("scan", 0);
+  let files_a = job.timed("walk", || audio_files_under(Path::new(folder_a))).map_err(String::from)?;
+  let files_b = job.timed("walk", || audio_files_under(Path::new(folder_b))).map_err(String::from)?;
+  let path_str = |p: &PathBuf| p.to_string_lossy().to_string();
+  let mut report = CompareReport { fields: fields.clone(), ..Default::default() };
+
+  // Hashing reads every file once more, as a phase of its own.
+  let total = files_a.len() + files_b.len();
+  let (keys_a, keys_b): (Vec<Option<String>>, Vec<Option<String>>) = match match_by {
+    MatchBy::Name => (files_a.iter().map(|p| name_key(p)).collect(), files_b.iter().map(|p| name_key(p)).collect()),
+    MatchBy::Hash => {
+      let cache = HashCache::load();
+      let done = AtomicUsize::new(0);
+      job.begin_phase("hash", total as u64);
+      let a = job.timed("hash", || cache.hashes(&files_a, job, &done, total));
+      let b = job.timed("hash", || cache.hashes(&files_b, job, &done, total));
+      cache.save()?;
+      (a, b)
+    }
+  };
+  let done = AtomicUsize::new(0);
+  job.begin_phase("read", total as u64);
+  let sides_a = job.timed("read", || par_map(&files_a, job, &done, total, read_side));
+  let sides_b = job.timed("read", || par_map(&files_b, job, &done, total, read_side));
+  report.cancelled = job.is_cancelled();
+  if report.cancelled { return Ok(report); }
+
+  let mut by_key: HashMap<&str, Vec<usize>> = HashMap::new();
+  for (i, k) in keys_b.iter().enumerate().rev() {
+    match (k, &sides_b[i]) {
+      (Some(k), Some(Some(_))) => by_key.entry(k.as_str()).or_default().push(i),
+      _ => report.unreadable.push(path_str(&files_b[i])),
+    }
+  }
+  for (i, k) in keys_a.iter().enumerate() {
+    let (Some(k), Some(Some(a))) = (k, &sides_a[i]) else { report.unreadable.push(path_str(&files_a[i])); continue };
+    // Walk order: indices were pushed in reverse, so pop takes the first.
+    let Some(j) = by_key.get_mut(k.as_str()).and_then(|v| v.pop()) else { report.only_in_a.push(path_str(&files_a[i])); continue };
+    let Some(Some(b)) = &sides_b[j] else { continue };
+    let pair = ComparedPair { a: path_str(&files_a[i]), b: path_str(&files_b[j]), diffs: diff(a, b, &fields) };
+    if pair.diffs.is_empty() { report.equal.push(pair) } else { report.different.push(pair) }
+  }
+  let mut left: Vec<usize> = by_key.into_values().flatten().collect();
+  left.sort();
+  report.only_in_b = left.into_iter().map(|j| path_str(&files_b[j])).collect();
+  report.unreadable.sort();
+
+  if let Some(dest) = opts.export_to {
+    job.timed("export", || write_report(&report, &dest))?;
+    report.exported_to = Some(dest);
+  }
+  Ok(report)
+}
+
+/// Pair the files of `folder_a` and `folder_b` (both walked recursively) by
+/// name or audio hash and compare `fields` (empty = all); job kind
+/// "compare-folders".
+#[tauri::command]
+pub async fn compare_folders(
+  app: tauri::AppHandle,
+  folder_a: String,
+  folder_b: String,
+  match_by: MatchBy,
+  fields: Vec<CompareField>,
+  options: Option<CompareOptions>,
+) -> Result<CompareReport, String> {
+  let _span = command_span("compare_folders");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "compare-folders", &folder_b);
+    let res = compare_blocking(&job, &folder_a, &folder_b, match_by, fields, options.unwrap_or_default());
+    if let Ok(r) = &res {
+      log_line(&format!(
+        "compare_folders a=\"{}\" b=\"{}\" by={:?} equal={} different={} only_a={} only_b={} unreadable={}",
+        folder_a, folder_b, match_by, r.equal.len(), r.different.len(), r.only_in_a.len(), r.only_in_b.len(), r.unreadable.len()
+      ));
+    }
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
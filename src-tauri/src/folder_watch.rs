@@ -0,0 +1,95 @@
+// Watches the open folder so the list follows changes made by other apps
+// without a rescan. Files that settle there arrive as `folder-file-added`,
+// disappearances as `folder-file-removed` (both `{path}`), and a rename
+// inside the folder as one `file-renamed {old, new}` instead of a remove and
+// an add (paired in `watcher`). On a rename the stores keyed by path
+// (metadata cache, touched records, field locks, retry queue, removed-tag and
+// preview history) move over to the new name first. The app's own renames
+// call `migrate` themselves; when the poll loop sees one of those afterwards
+// it only passes it on.
+
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, time::Duration};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{audit, field_locks, log_line, meta_cache, preview_history, removed_tags, retry_queue, touched, watcher::{FsEvent, PollWatcher}};
+
+const POLL_EVERY: Duration = Duration::from_secs(2);
+const SETTLE_FOR: Duration = Duration::from_secs(2);
+const RENAME_WINDOW: Duration = Duration::from_secs(6);
+
+static OPEN: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+/// Targets of renames `migrate` already handled, until the poll loop sees them.
+static MIGRATED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileRenamed {
+  old: String,
+  new: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FolderFile {
+  path: String,
+}
+
+/// Watch `folder` from now on; called when the user opens one.
+pub fn watch(folder: &str) { *OPEN.lock() = Some(PathBuf::from(folder)); }
+
+/// The file is already gone, so canonicalize through its parent like the
+/// stores did when they keyed it.
+fn canonical_gone(p: &Path) -> PathBuf {
+  match (p.parent().and_then(|d| fs::canonicalize(d).ok()), p.file_name()) {
+    (Some(dir), Some(name)) => dir.join(name),
+    _ => p.to_path_buf(),
+  }
+}
+
+/// Move everything stored under `from` to `to`, which it was just renamed to
+/// (by `source`), and audit the new path. Every rename of a file the app
+/// knows goes through here.
+pub fn migrate(from: &Path, to: &Path, source: audit::Source) {
+  let canon = canonical_gone(from);
+  meta_cache::rename(&canon, to);
+  touched::rename(&canon, to);
+  field_locks::rename(&canon, to);
+  retry_queue::rename(from, to);
+  removed_tags::rename(from, to);
+  preview_history::rename(from, to);
+  if source != audit::Source::External { MIGRATED.lock().insert(to.to_path_buf()); }
+  let (old, new) = (from.to_string_lossy().to_string(), to.to_string_lossy().to_string());
+  audit::record(&old, "path", Some(&old), Some(&new), source);
+  log_line(&format!("file_renamed old=\"{}\" new=\"{}\"", old, new));
+}
+
+/// Background poll loop; started once from setup.
+pub fn start(app: tauri::AppHandle) {
+  std::thread::spawn(move || {
+    let mut w = PollWatcher::new(SETTLE_FOR).track_renames(RENAME_WINDOW);
+    loop {
+      let roots: Vec<PathBuf> = OPEN.lock().iter().cloned().collect();
+      w.set_roots(&roots);
+      for ev in w.poll() {
+        let path = |p: &Path| FolderFile { path: p.to_string_lossy().to_string() };
+        let _ = match ev {
+          FsEvent::Added(p) => {
+            // An in-app rename the loop didn't pair up: the stores already moved.
+            MIGRATED.lock().remove(&p);
+            app.emit_all("folder-file-added", path(&p))
+          }
+          FsEvent::Removed(p) => app.emit_all("folder-file-removed", path(&p)),
+          FsEvent::Renamed { from, to } => {
+            if !MIGRATED.lock().remove(&to) { migrate(&from, &to, audit::Source::External); }
+            app.emit_all("file-renamed", FileRenamed { old: from.to_string_lossy().to_string(), new: to.to_string_lossy().to_string() })
+          }
+          FsEvent::Modified(_) => Ok(()),
+        };
+      }
+      std::thread::sleep(POLL_EVERY);
+    }
+  });
+}
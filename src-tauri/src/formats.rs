@@ -0,0 +1,95 @@
+// Which file extensions the app lists. The enabled set lives in Settings
+// (`extensions`) and is checked live by `supported_ext`, so a change applies
+// on the next scan. Only extensions in KNOWN can be enabled: those are the
+// ones lofty reads and writes. Other media files (and disabled KNOWN ones)
+// can be listed greyed out by `include_unsupported` scans; `ensure_writable`
+// keeps every tag write away from them.
+
+use std::path::Path;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::{error::CmdError, ext_lower};
+
+pub const DEFAULT_EXTENSIONS: &[&str] = &["mp3", "flac", "wav", "aiff", "aif", "m4a", "mpc"];
+
+/// (extension, readable, writable, playable in the webview). "Playable" means
+/// every platform's webview decodes it; FLAC/AIFF/Ogg work on some only.
+const KNOWN: &[(&str, bool, bool, bool)] = &[
+  ("mp3", true, true, true),
+  ("flac", true, true, false),
+  ("wav", true, true, true),
+  ("aiff", true, true, false),
+  ("aif", true, true, false),
+  ("m4a", true, true, true),
+  ("m4b", true, true, true),
+  ("aac", true, true, true),
+  ("mpc", true, true, false),
+  ("ogg", true, true, false),
+  ("opus", true, true, false),
+  ("ape", true, true, false),
+  ("wv", true, true, false),
+];
+
+/// Audio and video containers that aren't KNOWN, listed (not tagged) by
+/// `include_unsupported` scans.
+const MEDIA_ONLY: &[&str] = &["mkv", "mka", "webm", "mp4", "m4v", "mov", "avi", "wma", "ac3", "dts", "dsf", "dff", "caf", "amr", "mid", "midi"];
+
+static ENABLED: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(default_extensions()));
+
+pub fn default_extensions() -> Vec<String> { DEFAULT_EXTENSIONS.iter().map(|s| s.to_string()).collect() }
+
+pub fn set_enabled(list: &[String]) { *ENABLED.write() = list.to_vec(); }
+
+/// `ext` is already lowercase (see `ext_lower`).
+pub fn is_enabled(ext: &str) -> bool { ENABLED.read().iter().any(|e| e == ext) }
+
+/// `ext` names an audio or video container, supported or not.
+pub fn is_media(ext: &str) -> bool { MEDIA_ONLY.contains(&ext) || KNOWN.iter().any(|(k, ..)| *k == ext) }
+
+/// Tags may only be written to enabled, writable formats, whatever the caller asks.
+pub fn ensure_writable(p: &Path) -> Result<(), CmdError> {
+  let ext = ext_lower(p);
+  if is_enabled(&ext) && KNOWN.iter().any(|(k, _, writable, _)| *k == ext && *writable) { return Ok(()); }
+  Err(CmdError::Unsupported {
+    path: p.to_string_lossy().to_string(),
+    message: format!("{} is not a supported audio format (.{}); it can be listed but not tagged.", p.display(), ext),
+    ext,
+  })
+}
+
+/// Lowercase, strip a leading dot, drop duplicates; unknown extensions are an error.
+pub fn validate(list: &[String]) -> Result<Vec<String>, String> {
+  let mut out: Vec<String> = Vec::new();
+  for raw in list {
+    let e = raw.trim().trim_start_matches('.').to_ascii_lowercase();
+    if e.is_empty() || out.contains(&e) { continue; }
+    if !KNOWN.iter().any(|(k, ..)| *k == e) {
+      return Err(format!("unsupported extension \".{}\" (known: {})", e, KNOWN.iter().map(|(k, ..)| *k).collect::<Vec<_>>().join(", ")));
+    }
+    out.push(e);
+  }
+  if out.is_empty() { return Err("at least one extension must stay enabled".into()); }
+  Ok(out)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatInfo {
+  ext: &'static str,
+  readable: bool,
+  writable: bool,
+  playable_in_webview: bool,
+  enabled: bool,
+}
+
+/// Every extension the backend can handle, with what it can do and whether
+/// the current settings list it.
+#[tauri::command]
+pub fn supported_formats() -> Vec<FormatInfo> {
+  KNOWN
+    .iter()
+    .map(|&(ext, readable, writable, playable_in_webview)| FormatInfo { ext, readable, writable, playable_in_webview, enabled: is_enabled(ext) })
+    .collect()
+}
@@ -0,0 +1,179 @@
+// Full-text search over comments (and lyrics, titles, ... on request) across
+// folders. Runs over the metadata cache: the folders are walked, stale or
+// missing entries re-read (`meta_cache::refresh`), then every match is done
+// in memory, so a warm library of 10k tracks answers well under a second.
+// Matching is case- and accent-insensitive (`text_fold`); "all" mode requires
+// every whitespace-separated term somewhere in the searched fields, "phrase"
+// the whole query in one field.
+
+use std::{collections::HashSet, path::PathBuf, time::Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::{command_span, library, log_line, meta_cache::{self, CachedMeta}, startup_scan, text_fold::{fold_mapped, fold_str}};
+
+/// Characters of context on each side of the first match in a snippet.
+const CONTEXT_CHARS: usize = 40;
+const MAX_RESULTS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField { Comment, Lyrics, Title, Artist, Album }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode { #[default] All, Phrase }
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct SearchOptions {
+  mode: MatchMode,
+  /// Ignore `folders` and search the folder opened last.
+  current_folder_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldHit {
+  field: SearchField,
+  snippet: String,
+  /// `[start, end)` in UTF-16 code units of `snippet`, for the UI to highlight.
+  highlights: Vec<(usize, usize)>,
+  /// The snippet was cut at the start / end of the field.
+  clipped_start: bool,
+  clipped_end: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextMatch {
+  path: String,
+  title: Option<String>,
+  artist: Option<String>,
+  hits: Vec<FieldHit>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchReport {
+  matches: Vec<TextMatch>,
+  /// More files matched than MAX_RESULTS.
+  truncated: bool,
+  searched: usize,
+  /// Entries re-read before searching.
+  refreshed: usize,
+  took_ms: u64,
+}
+
+/// Byte ranges in `text` where any of `terms` (already folded) occur; marks
+/// the terms found in `seen`.
+fn find_all(text: &str, terms: &[String], seen: &mut [bool]) -> Vec<(usize, usize)> {
+  let (folded, map) = fold_mapped(text);
+  let orig = |i: usize| if i < map.len() { map[i] } else { text.len() };
+  let mut out: Vec<(usize, usize)> = Vec::new();
+  for (ti, t) in terms.iter().enumerate() {
+    for (i, _) in folded.match_indices(t.as_str()) {
+      out.push((orig(i), orig(i + t.len())));
+      seen[ti] = true;
+    }
+  }
+  out.sort();
+  // Merge overlaps ("sun" inside "sunrise" when both are terms).
+  let mut merged: Vec<(usize, usize)> = Vec::new();
+  for (a, b) in out {
+    match merged.last_mut() {
+      Some(last) if a <= last.1 => last.1 = last.1.max(b),
+      _ => merged.push((a, b)),
+    }
+  }
+  merged
+}
+
+fn utf16_len(s: &str) -> usize { s.encode_utf16().count() }
+
+/// Snippet around the first of `ranges` (byte offsets in `text`).
+fn snippet(field: SearchField, text: &str, ranges: &[(usize, usize)]) -> FieldHit {
+  let first = ranges[0];
+  let start = text[..first.0].char_indices().rev().nth(CONTEXT_CHARS - 1).map_or(0, |(i, _)| i);
+  let end = text[first.1..].char_indices().nth(CONTEXT_CHARS).map_or(text.len(), |(i, _)| first.1 + i);
+  let base = utf16_len(&text[..start]);
+  let highlights = ranges
+    .iter()
+    .filter(|(a, b)| *a >= start && *b <= end)
+    .map(|(a, b)| (utf16_len(&text[..*a]) - base, utf16_len(&text[..*b]) - base))
+    .collect();
+  FieldHit { field, snippet: text[start..end].to_string(), highlights, clipped_start: start > 0, clipped_end: end < text.len() }
+}
+
+fn field_text(m: &CachedMeta, f: SearchField) -> Option<&str> {
+  match f {
+    SearchField::Comment => Some(m.comment.as_str()),
+    SearchField::Lyrics => m.lyrics.as_deref(),
+    SearchField::Title => m.title.as_deref(),
+    SearchField::Artist => m.artist.as_deref(),
+    SearchField::Album => m.album.as_deref(),
+  }
+}
+
+/// Hits when every term occurs in some field (phrase mode has just one term).
+fn match_one(m: &CachedMeta, fields: &[SearchField], terms: &[String]) -> Option<Vec<FieldHit>> {
+  let mut hits = Vec::new();
+  let mut seen = vec![false; terms.len()];
+  for &f in fields {
+    let Some(text) = field_text(m, f).filter(|t| !t.is_empty()) else { continue };
+    let ranges = find_all(text, terms, &mut seen);
+    if ranges.is_empty() { continue; }
+    hits.push(snippet(f, text, &ranges));
+  }
+  seen.iter().all(|s| *s).then_some(hits)
+}
+
+fn search_blocking(folders: Vec<String>, query: &str, fields: Vec<SearchField>, recursive: bool, opts: SearchOptions) -> Result<SearchReport, String> {
+  let started = Instant::now();
+  let terms: Vec<String> = match opts.mode {
+    MatchMode::Phrase => vec![fold_str(query.trim())],
+    MatchMode::All => query.split_whitespace().map(fold_str).collect(),
+  };
+  if terms.iter().all(|t| t.is_empty()) { return Err("empty search".into()); }
+  let folders = if opts.current_folder_only {
+    vec![startup_scan::get_last_folder().ok_or("no folder is open")?]
+  } else {
+    folders
+  };
+  let fields = if fields.is_empty() { vec![SearchField::Comment] } else { fields };
+
+  let mut paths: Vec<PathBuf> = Vec::new();
+  for f in &folders { paths.extend(library::audio_files(&PathBuf::from(f), recursive).map_err(String::from)?); }
+  // Overlapping folders list the same files twice.
+  let mut unique = HashSet::new();
+  paths.retain(|p| unique.insert(p.clone()));
+  let refreshed = meta_cache::refresh(&paths);
+  let cached = meta_cache::cached_many(&paths);
+
+  let mut matches = Vec::new();
+  let mut truncated = false;
+  for (p, m) in &cached {
+    let Some(hits) = match_one(m, &fields, &terms) else { continue };
+    if matches.len() == MAX_RESULTS { truncated = true; break; }
+    matches.push(TextMatch { path: p.to_string_lossy().to_string(), title: m.title.clone(), artist: m.artist.clone(), hits });
+  }
+  let took_ms = started.elapsed().as_millis() as u64;
+  log_line(&format!("full_text_search folders={} files={} refreshed={} matches={} ms={}", folders.len(), cached.len(), refreshed, matches.len(), took_ms));
+  Ok(SearchReport { matches, truncated, searched: cached.len(), refreshed, took_ms })
+}
+
+/// Search `fields` (empty = comments) of the files in `folders`, directly in
+/// them or below with `recursive`, for `query`.
+#[tauri::command]
+pub async fn full_text_search(
+  folders: Vec<String>,
+  query: String,
+  fields: Vec<SearchField>,
+  recursive: bool,
+  options: Option<SearchOptions>,
+) -> Result<SearchReport, String> {
+  let _span = command_span("full_text_search");
+  tauri::async_runtime::spawn_blocking(move || search_blocking(folders, &query, fields, recursive, options.unwrap_or_default()))
+    .await
+    .map_err(|e| e.to_string())?
+}
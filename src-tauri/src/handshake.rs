@@ -0,0 +1,145 @@
+// Startup contract between the UI and the backend. The frontend sends its
+// TAGS_SCHEMA_VERSION and app version; the answer carries ours, the features
+// this build has (`CAPABILITIES`, for gating UI that ships ahead of a backend)
+// and how the two schemas compare. While they differ, every command that
+// changes a bank (`write_tags_file*`, reorder, imports, dedupe, sync, presets)
+// is refused with `CmdError::SchemaMismatch`; dry runs still answer. A
+// frontend on another schema would drop fields it doesn't know or write ones
+// we don't.
+// `force_compatibility` lets the user accept that and write anyway.
+//
+// Before any handshake (the CLI, an older UI) writes go through as before.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{error::CmdError, log, log_line, LogLevel, TAGS_SCHEMA_VERSION};
+
+pub const CAPABILITIES: &[&str] = &[
+  "api_mode",
+  "bank_presets",
+  "bank_sync",
+  "batch_write",
+  "color_label",
+  "compare_folders",
+  "deep_read",
+  "job_notifications",
+  "peaks",
+  "preview_cues",
+  "unsupported_listing",
+  "workspace_stats",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compatibility { Older, Equal, Newer }
+
+/// The frontend's schema version from the last handshake.
+static FRONTEND_SCHEMA: Lazy<Mutex<Option<u32>>> = Lazy::new(|| Mutex::new(None));
+static FORCED: AtomicBool = AtomicBool::new(false);
+
+fn compare(frontend: u32) -> Compatibility {
+  match frontend.cmp(&TAGS_SCHEMA_VERSION) {
+    std::cmp::Ordering::Less => Compatibility::Older,
+    std::cmp::Ordering::Equal => Compatibility::Equal,
+    std::cmp::Ordering::Greater => Compatibility::Newer,
+  }
+}
+
+/// Refuses bank writes from a frontend on another schema, unless forced.
+pub fn check_bank_write() -> Result<(), CmdError> {
+  let Some(frontend) = *FRONTEND_SCHEMA.lock() else { return Ok(()) };
+  let compatibility = compare(frontend);
+  if compatibility == Compatibility::Equal || FORCED.load(Ordering::Relaxed) { return Ok(()); }
+  Err(CmdError::SchemaMismatch {
+    frontend,
+    backend: TAGS_SCHEMA_VERSION,
+    compatibility,
+    message: format!(
+      "The interface uses tag schema {} but the backend uses {}; bank changes aren't saved until you confirm the mismatch.",
+      frontend, TAGS_SCHEMA_VERSION
+    ),
+  })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HandshakeInfo {
+  schema_version: u32,
+  app_version: &'static str,
+  capabilities: &'static [&'static str],
+  /// The frontend's schema relative to ours.
+  compatibility: Compatibility,
+  /// Whether the frontend's app version is a different build.
+  app_version_differs: bool,
+}
+
+/// Called by the UI at startup. A new handshake clears an earlier
+/// `force_compatibility`: a reloaded frontend has to confirm again.
+#[tauri::command]
+pub fn handshake(frontend_schema_version: u32, frontend_app_version: String) -> HandshakeInfo {
+  let compatibility = compare(frontend_schema_version);
+  *FRONTEND_SCHEMA.lock() = Some(frontend_schema_version);
+  FORCED.store(false, Ordering::Relaxed);
+  let app_version = env!("CARGO_PKG_VERSION");
+  let level = if compatibility == Compatibility::Equal { LogLevel::Info } else { LogLevel::Warn };
+  log(level, &format!(
+    "handshake frontend_schema={} backend_schema={} frontend_app={} backend_app={} compatibility={:?}",
+    frontend_schema_version, TAGS_SCHEMA_VERSION, frontend_app_version, app_version, compatibility
+  ));
+  HandshakeInfo {
+    schema_version: TAGS_SCHEMA_VERSION,
+    app_version,
+    capabilities: CAPABILITIES,
+    compatibility,
+    app_version_differs: frontend_app_version != app_version,
+  }
+}
+
+/// The user accepted a schema mismatch: allow bank writes until the next handshake.
+#[tauri::command]
+pub fn force_compatibility() {
+  FORCED.store(true, Ordering::Relaxed);
+  log_line(&format!("force_compatibility frontend_schema={:?} backend_schema={}", *FRONTEND_SCHEMA.lock(), TAGS_SCHEMA_VERSION));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{fs, path::Path};
+
+  #[test]
+  fn mismatch_refuses_bank_writes_until_forced() {
+    handshake(TAGS_SCHEMA_VERSION + 1, "other".into());
+    assert!(matches!(check_bank_write(), Err(CmdError::SchemaMismatch { compatibility: Compatibility::Newer, .. })));
+    force_compatibility();
+    assert!(check_bank_write().is_ok());
+    // A reload has to confirm again.
+    handshake(TAGS_SCHEMA_VERSION + 1, "other".into());
+    assert!(check_bank_write().is_err());
+    handshake(TAGS_SCHEMA_VERSION, env!("CARGO_PKG_VERSION").into());
+    assert!(check_bank_write().is_ok());
+  }
+
+  #[test]
+  fn every_bank_mutation_checks_the_handshake() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut unchecked = Vec::new();
+    for entry in fs::read_dir(&src).unwrap() {
+      let p = entry.unwrap().path();
+      if p.extension().is_none_or(|e| e != "rs") { continue; }
+      let text = fs::read_to_string(&p).unwrap();
+      let code = text.split("#[cfg(test)]").next().unwrap_or("");
+      // Good enough for this source: every fn starts on a line of its own.
+      for body in code.split("\nfn ").flat_map(|s| s.split("\npub fn ")).skip(1) {
+        let mutates = ["banks.update(", "banks.write(", "banks.write_bank("].iter().any(|n| body.contains(n));
+        if mutates && !body.contains("check_bank_write()") {
+          unchecked.push(format!("{}: {}", p.file_name().unwrap().to_string_lossy(), body.split('(').next().unwrap_or("")));
+        }
+      }
+    }
+    assert!(unchecked.is_empty(), "bank writes without check_bank_write: {:?}", unchecked);
+  }
+}
@@ -0,0 +1,241 @@
+// ID3v2 padding on MP3s. lofty rewrites the whole file on every save and
+// leaves no padding, while other taggers reserve anything up to a megabyte;
+// either way every comment edit changes the file size and makes cloud sync
+// upload the whole track again. With `compact_padding` on, MP3 saves write
+// the ID3v2 tag themselves: in place when it fits the existing tag with at
+// most PADDING_BUDGET to spare, otherwise rewritten with exactly that much
+// padding, so later small edits land in place.
+
+use std::{fs, io::{self, Read, Seek, SeekFrom, Write}, path::Path, sync::atomic::{AtomicBool, Ordering}};
+use lofty::{TagExt, TagType, TaggedFileExt};
+use serde::Serialize;
+
+use crate::{archive, ext_lower, log_line, media_streams, shadow, write_atomic, WRITE_LOCK};
+
+pub static COMPACT: AtomicBool = AtomicBool::new(false);
+
+/// Padding kept after a compact write.
+pub const PADDING_BUDGET: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Id3Padding {
+  /// Whole tag on disk: header, frames, padding (and footer).
+  pub tag_bytes: u64,
+  pub padding_bytes: u64,
+}
+
+struct Region {
+  /// Bytes from the start of the file the tag occupies.
+  len: u64,
+  frames_end: u64,
+  footer: bool,
+}
+
+pub fn syncsafe(b: &[u8]) -> u64 { b.iter().fold(0u64, |acc, &x| (acc << 7) | (x & 0x7f) as u64) }
+
+pub fn to_syncsafe(n: u64) -> [u8; 4] {
+  [((n >> 21) & 0x7f) as u8, ((n >> 14) & 0x7f) as u8, ((n >> 7) & 0x7f) as u8, (n & 0x7f) as u8]
+}
+
+/// The ID3v2 tag at the start of `bytes`, walking frames to find where the
+/// padding begins. `bytes` needs only cover the tag; `None` when it doesn't
+/// (a size field larger than the file).
+fn region(bytes: &[u8]) -> Option<Region> {
+  if bytes.len() < 10 || &bytes[..3] != b"ID3" { return None; }
+  let (version, flags) = (bytes[3], bytes[5]);
+  let size = syncsafe(&bytes[6..10]);
+  let end = (10 + size) as usize;
+  if end > bytes.len() { return None; }
+  let mut at = 10usize;
+  if flags & 0x40 != 0 && bytes.len() >= 14 {
+    // Extended header: v2.4 counts its own size field, v2.3 doesn't.
+    at += match version { 4 => syncsafe(&bytes[10..14]) as usize, _ => u32::from_be_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]) as usize + 4 };
+  }
+  let (id_len, head_len) = if version == 2 { (3, 6) } else { (4, 10) };
+  while at + head_len <= end && bytes[at] != 0 {
+    let s = &bytes[at + id_len..at + head_len - if version == 2 { 0 } else { 2 }];
+    let frame = match version {
+      2 => ((s[0] as usize) << 16) | ((s[1] as usize) << 8) | s[2] as usize,
+      4 => syncsafe(s) as usize,
+      _ => u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as usize,
+    };
+    at += head_len + frame;
+  }
+  let footer = flags & 0x10 != 0;
+  Some(Region { len: end as u64 + if footer { 10 } else { 0 }, frames_end: at.min(end) as u64, footer })
+}
+
+fn read_region(p: &Path) -> Option<Region> {
+  let mut f = fs::File::open(p).ok()?;
+  let mut head = [0u8; 10];
+  f.read_exact(&mut head).ok()?;
+  if &head[..3] != b"ID3" { return None; }
+  let mut tag = vec![0u8; 10 + syncsafe(&head[6..10]) as usize];
+  f.seek(SeekFrom::Start(0)).ok()?;
+  f.read_exact(&mut tag).ok()?;
+  region(&tag)
+}
+
+/// Tag and padding size of an MP3's leading ID3v2 tag, for `inspect_tags`.
+pub fn measure(p: &Path) -> Option<Id3Padding> {
+  let r = read_region(p)?;
+  Some(Id3Padding { tag_bytes: r.len, padding_bytes: r.len.saturating_sub(r.frames_end + if r.footer { 10 } else { 0 }) })
+}
+
+/// `tag` (header + frames, as lofty dumps it) with its size field covering
+/// `padding` zero bytes, which are appended.
+fn padded(mut tag: Vec<u8>, padding: u64) -> Vec<u8> {
+  let body = tag.len() as u64 - 10 + padding;
+  tag[6..10].copy_from_slice(&to_syncsafe(body));
+  tag.resize(tag.len() + padding as usize, 0);
+  tag
+}
+
+fn write_tag(p: &Path, tag: Vec<u8>) -> io::Result<()> {
+  let new_len = tag.len() as u64;
+  let old = read_region(p);
+  match &old {
+    Some(r) if !r.footer && new_len <= r.len && r.len - new_len <= PADDING_BUDGET => {
+      // Same size on disk: only the tag bytes change.
+      let mut f = fs::OpenOptions::new().write(true).open(p)?;
+      f.write_all(&padded(tag, r.len - new_len))?;
+      f.sync_all()
+    }
+    _ => {
+      let bytes = fs::read(p)?;
+      let audio = &bytes[old.map(|r| r.len as usize).unwrap_or(0).min(bytes.len())..];
+      let mut out = padded(tag, PADDING_BUDGET);
+      out.extend_from_slice(audio);
+      write_atomic(p, &out).map_err(io::Error::other)
+    }
+  }
+}
+
+/// Compact-mode save of an MP3; `None` when lofty's own save applies (mode
+/// off, other formats). Tags other than ID3v2 still go through lofty.
+/// Failures to write are `ErrorKind::Io`, like lofty's own.
+pub fn save_compact(tf: &lofty::TaggedFile, p: &Path) -> Option<lofty::error::Result<()>> {
+  if !COMPACT.load(Ordering::Relaxed) || ext_lower(p) != "mp3" { return None; }
+  let res = tf.tags().iter().try_for_each(|tag| {
+    let mut dump = Vec::new();
+    if tag.tag_type() == TagType::Id3v2 { tag.dump_to(&mut dump)?; }
+    // An empty ID3v2 tag dumps to nothing: let lofty strip it.
+    if dump.len() < 10 { return tag.save_to_path(p); }
+    write_tag(p, dump).map_err(Into::into)
+  });
+  Some(res)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaddingRewrite {
+  path: String,
+  size_before: u64,
+  size_after: u64,
+  padding_before: u64,
+  padding_after: u64,
+  #[serde(flatten)]
+  shadow: shadow::Mark,
+}
+
+/// One-off shrink of an MP3 whose tag carries more than PADDING_BUDGET of
+/// padding. The frames are kept byte for byte; only padding is dropped.
+#[tauri::command]
+pub fn rewrite_with_minimal_padding(path: String) -> Result<PaddingRewrite, String> {
+  let p = Path::new(&path);
+  archive::guard(p).map_err(|e| e.to_string())?;
+  let copy = shadow::target(p)?;
+  let at = copy.as_deref().unwrap_or(p);
+  media_streams::wait_for(at);
+  let _guard = WRITE_LOCK.lock();
+  let bytes = fs::read(at).map_err(|e| e.to_string())?;
+  if bytes.len() >= 10 && &bytes[..3] == b"ID3" && 10 + syncsafe(&bytes[6..10]) > bytes.len() as u64 {
+    return Err(format!("ID3v2 size field says {} bytes but the file has only {}", 10 + syncsafe(&bytes[6..10]), bytes.len()));
+  }
+  let r = region(&bytes).ok_or("no ID3v2 tag at the start of the file")?;
+  // A footer repeats the size, and a v2.3 extended header records the padding.
+  if r.footer || bytes[5] & 0x40 != 0 { return Err("ID3v2 tags with a footer or extended header aren't supported".into()); }
+  let before = Id3Padding { tag_bytes: r.len, padding_bytes: r.len - r.frames_end };
+  let mut res = PaddingRewrite { path: path.clone(), size_before: bytes.len() as u64, size_after: bytes.len() as u64, padding_before: before.padding_bytes, padding_after: before.padding_bytes, shadow: shadow::Mark::of(copy.as_deref()) };
+  if before.padding_bytes <= PADDING_BUDGET { return Ok(res); }
+  let mut out = padded(bytes[..r.frames_end as usize].to_vec(), PADDING_BUDGET);
+  out.extend_from_slice(&bytes[r.len as usize..]);
+  media_streams::before_replace(at);
+  write_atomic(at, &out)?;
+  res.size_after = out.len() as u64;
+  res.padding_after = PADDING_BUDGET;
+  log_line(&format!("rewrite_with_minimal_padding path=\"{}\" bytes {} -> {}", path, res.size_before, res.size_after));
+  Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// v2.4 tag: header, one TIT2 frame with `text`, `padding` zero bytes.
+  fn tag(text: &str, padding: usize) -> Vec<u8> {
+    let mut frame = vec![3u8];
+    frame.extend_from_slice(text.as_bytes());
+    let mut out = b"ID3\x04\0\0".to_vec();
+    out.extend_from_slice(&to_syncsafe((10 + frame.len() + padding) as u64));
+    out.extend_from_slice(b"TIT2");
+    out.extend_from_slice(&to_syncsafe(frame.len() as u64));
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(&frame);
+    out.resize(out.len() + padding, 0);
+    out
+  }
+
+  #[test]
+  fn region_finds_the_padding() {
+    let mut bytes = tag("title", 100);
+    bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    let r = region(&bytes).unwrap();
+    assert_eq!(r.len, bytes.len() as u64 - 4);
+    assert_eq!(r.frames_end, r.len - 100);
+  }
+
+  #[test]
+  fn size_field_past_the_end_is_rejected_not_sliced() {
+    let mut bytes = tag("title", 100);
+    bytes[6..10].copy_from_slice(&to_syncsafe(1 << 20));
+    assert!(region(&bytes).is_none());
+    // A huge frame size inside a valid tag stops the walk at the tag's end.
+    let mut bytes = tag("title", 100);
+    bytes[14..18].copy_from_slice(&to_syncsafe(1 << 20));
+    assert_eq!(region(&bytes).unwrap().frames_end, bytes.len() as u64);
+  }
+
+  #[test]
+  fn repeated_comment_edits_keep_the_size_within_the_budget() {
+    use lofty::ItemKey;
+    use crate::test_support;
+    let dir = test_support::scratch("padding-compact");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#start;")]);
+    let base = fs::metadata(&p).unwrap().len();
+    let comment = |deep: usize| format!("#house;{}", "#deep;".repeat(deep));
+    // (comment, size, padding) after each write; "#start;" and "#house;" are the same length.
+    let mut seen = Vec::new();
+    COMPACT.store(true, Ordering::Relaxed);
+    for deep in [7, 100, 300, 650, 20, 7, 0] {
+      let res = crate::edit_tags(&p, |tag| { tag.insert_text(ItemKey::Comment, comment(deep)); }).map_err(String::from);
+      seen.push((res.map(|o| o.no_op), fs::metadata(&p).unwrap().len(), measure(&p).unwrap().padding_bytes));
+    }
+    COMPACT.store(false, Ordering::Relaxed);
+    let grown = base + 7 * 6 + PADDING_BUDGET;
+    assert_eq!(seen, [
+      // Past the tag lofty left: rewritten with the budget as padding.
+      (Ok(false), grown, PADDING_BUDGET),
+      // Longer and shorter again, all within the padding: in place, same size.
+      (Ok(false), grown, PADDING_BUDGET - 93 * 6),
+      (Ok(false), grown, PADDING_BUDGET - 293 * 6),
+      (Ok(false), grown, PADDING_BUDGET - 643 * 6),
+      (Ok(false), grown, PADDING_BUDGET - 13 * 6),
+      (Ok(false), grown, PADDING_BUDGET),
+      // More than the budget to spare: rewritten down to it.
+      (Ok(false), base + PADDING_BUDGET, PADDING_BUDGET),
+    ]);
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::Comment), Some(comment(0)));
+  }
+}
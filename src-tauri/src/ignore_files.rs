@@ -0,0 +1,182 @@
+// `.taggerignore`: gitignore-style patterns dropped into any folder to keep
+// its stems, projects or bounces out of every scan. A file's patterns apply
+// below its folder; a deeper file overrides a shallower one, and `!pattern`
+// brings a path back, as in git. Files above the scanned root count too, so
+// opening one subfolder of a crate keeps the crate's rules. `scan_excludes`
+// in Settings is the outermost layer, relative to each scanned root.
+//
+// Recursive scans (`library`), single-folder listings (including the startup
+// scan and the extension check) and the poll watcher all check paths against
+// `Rules`, so an ignored file is never listed and never raises a watcher event.
+
+use std::{collections::BTreeMap, path::Path};
+use ignore::{gitignore::{Gitignore, GitignoreBuilder}, Match};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::{log, LogLevel};
+
+pub const FILE_NAME: &str = ".taggerignore";
+/// Reported as the source of `scan_excludes` hits.
+pub const SETTINGS: &str = "settings";
+
+static EXCLUDES: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Ignore file (or SETTINGS) -> entries it kept out of a scan.
+pub type Skipped = BTreeMap<String, usize>;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IgnoreHits {
+  pub source: String,
+  /// Files and folders skipped; a skipped folder counts once.
+  pub entries: usize,
+}
+
+pub fn report(skipped: Skipped) -> Vec<IgnoreHits> {
+  skipped.into_iter().map(|(source, entries)| IgnoreHits { source, entries }).collect()
+}
+
+/// Blank lines dropped; an invalid pattern is an error naming it.
+pub fn validate(patterns: &[String]) -> Result<Vec<String>, String> {
+  let mut b = GitignoreBuilder::new("/");
+  let mut out = Vec::new();
+  for p in patterns.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+    b.add_line(None, p).map_err(|e| format!("scan exclude \"{}\": {}", p, e))?;
+    out.push(p.to_string());
+  }
+  Ok(out)
+}
+
+pub fn set_excludes(patterns: &[String]) { *EXCLUDES.write() = patterns.to_vec(); }
+
+struct Layer {
+  source: String,
+  matcher: Gitignore,
+}
+
+/// The ignore layers in force in one folder, outermost first.
+#[derive(Default)]
+pub struct Rules {
+  layers: Vec<Layer>,
+}
+
+impl Rules {
+  /// Rules at `root`: the settings excludes, then each ignore file from the
+  /// top of the filesystem down to `root`.
+  pub fn for_root(root: &Path) -> Rules {
+    let mut rules = Rules::default();
+    let excludes = EXCLUDES.read();
+    if !excludes.is_empty() {
+      let mut b = GitignoreBuilder::new(root);
+      for p in excludes.iter() { let _ = b.add_line(None, p); }
+      if let Ok(matcher) = b.build() { rules.layers.push(Layer { source: SETTINGS.into(), matcher }); }
+    }
+    let ancestors: Vec<&Path> = root.ancestors().collect();
+    for dir in ancestors.into_iter().rev() { rules.enter(dir); }
+    rules
+  }
+
+  /// Take on `dir`'s ignore file, if it has one. Returns whether it did, for `leave`.
+  pub fn enter(&mut self, dir: &Path) -> bool {
+    let file = dir.join(FILE_NAME);
+    if !file.is_file() { return false; }
+    let (matcher, err) = Gitignore::new(&file);
+    // Bad lines are skipped; the rest of the file still applies.
+    if let Some(e) = err { log(LogLevel::Warn, &format!("taggerignore \"{}\": {}", file.display(), e)); }
+    self.layers.push(Layer { source: file.to_string_lossy().to_string(), matcher });
+    true
+  }
+
+  pub fn leave(&mut self, entered: bool) {
+    if entered { self.layers.pop(); }
+  }
+
+  /// The ignore file (or SETTINGS) that excludes `p`; the deepest one with
+  /// an opinion decides. A pattern naming one of `p`'s folders ("stems/")
+  /// excludes `p` too, so single paths are judged as a walk would judge them.
+  pub fn ignored_by(&self, p: &Path, is_dir: bool) -> Option<&str> {
+    for layer in self.layers.iter().rev() {
+      // The matcher panics on paths outside its folder.
+      if !p.starts_with(layer.matcher.path()) { continue; }
+      match layer.matcher.matched_path_or_any_parents(p, is_dir) {
+        Match::Ignore(_) => return Some(&layer.source),
+        Match::Whitelist(_) => return None,
+        Match::None => {}
+      }
+    }
+    None
+  }
+
+  /// `ignored_by`, counting the hit in `skipped`.
+  pub fn skip(&self, p: &Path, is_dir: bool, skipped: &mut Skipped) -> bool {
+    let Some(source) = self.ignored_by(p, is_dir) else { return false };
+    *skipped.entry(source.to_string()).or_default() += 1;
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use crate::test_support;
+
+  fn ignore(dir: &Path, lines: &str) -> String {
+    fs::write(dir.join(FILE_NAME), lines).unwrap();
+    dir.join(FILE_NAME).to_string_lossy().to_string()
+  }
+
+  #[test]
+  fn deeper_files_override_shallower_ones() {
+    let root = test_support::scratch("ignore-depth");
+    let sub = root.join("sub");
+    fs::create_dir_all(&sub).unwrap();
+    let top = ignore(&root, "*.wav\n");
+    let deep = ignore(&sub, "!keep.wav\ndrop.flac\n");
+    let mut rules = Rules::for_root(&root);
+    assert_eq!(rules.ignored_by(&root.join("a.wav"), false), Some(top.as_str()));
+    let entered = rules.enter(&sub);
+    assert_eq!(rules.ignored_by(&sub.join("keep.wav"), false), None);
+    assert_eq!(rules.ignored_by(&sub.join("other.wav"), false), Some(top.as_str()));
+    assert_eq!(rules.ignored_by(&sub.join("drop.flac"), false), Some(deep.as_str()));
+    rules.leave(entered);
+    assert_eq!(rules.ignored_by(&sub.join("keep.wav"), false), Some(top.as_str()));
+  }
+
+  #[test]
+  fn folder_patterns_cover_the_files_below() {
+    let root = test_support::scratch("ignore-parents");
+    let stems = root.join("Stems").join("Drums");
+    fs::create_dir_all(&stems).unwrap();
+    let top = ignore(&root, "Stems/\n");
+    // Judged on its own, without walking through Stems first.
+    let rules = Rules::for_root(&stems);
+    assert_eq!(rules.ignored_by(&stems.join("kick.wav"), false), Some(top.as_str()));
+    assert_eq!(rules.ignored_by(&root.join("mix.wav"), false), None);
+  }
+
+  #[test]
+  fn ignore_files_override_the_settings_excludes() {
+    let root = test_support::scratch("ignore-settings");
+    set_excludes(&["*.settings-test.wav".into()]);
+    let plain = Rules::for_root(&root);
+    assert_eq!(plain.ignored_by(&root.join("a.settings-test.wav"), false), Some(SETTINGS));
+    ignore(&root, "!a.settings-test.wav\n");
+    let rules = Rules::for_root(&root);
+    set_excludes(&[]);
+    assert_eq!(rules.ignored_by(&root.join("a.settings-test.wav"), false), None);
+    assert_eq!(rules.ignored_by(&root.join("b.settings-test.wav"), false), Some(SETTINGS));
+  }
+
+  #[test]
+  fn folder_listings_leave_ignored_files_out() {
+    let root = test_support::scratch("ignore-listing");
+    test_support::audio(&root, "keep.wav");
+    test_support::audio(&root, "bounce.wav");
+    ignore(&root, "bounce*\n");
+    let listed: Vec<String> = crate::read_folder(&root.to_string_lossy(), false).unwrap().into_iter().map(|f| f.file_name).collect();
+    assert_eq!(listed, ["keep.wav"]);
+  }
+}
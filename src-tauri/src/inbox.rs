@@ -0,0 +1,158 @@
+// Inbox rules: when a new audio file settles in a configured folder, add the
+// rule's tags (and optionally rename it). `{date}` in tags/templates expands
+// to today's date (YYYY-MM-DD). Every automatic change is audited as "rule".
+// A file the rule renamed shows up again under its new name; that arrival is
+// skipped, or the template would be applied over and over.
+
+use std::{collections::HashSet, path::{Path, PathBuf}, time::Duration};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::{archive, audit, folder_watch, load_prefs, log_line, save_prefs, shadow, tag_ops, volumes, watcher::{FsEvent, PollWatcher}};
+
+const POLL_EVERY: Duration = Duration::from_secs(2);
+const SETTLE_FOR: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxRule {
+  pub folder: String,
+  pub add_tags: Vec<String>,
+  #[serde(default)]
+  pub rename_template: Option<String>,
+  #[serde(default = "enabled_default")]
+  pub enabled: bool,
+}
+
+fn enabled_default() -> bool { true }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InboxEvent {
+  folder: String,
+  path: String,
+  renamed_to: Option<String>,
+  tags: Vec<String>,
+  error: Option<String>,
+}
+
+// Live copy of the rules so the poll loop doesn't re-read prefs.json every tick.
+static RULES: Lazy<Mutex<Vec<InboxRule>>> = Lazy::new(|| Mutex::new(load_prefs().inbox_rules));
+
+/// Names this module renamed files to, not yet seen by the poll loop.
+static RENAMED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+fn persist(rules: Vec<InboxRule>) -> Result<(), String> {
+  let mut p = load_prefs();
+  p.inbox_rules = rules.clone();
+  save_prefs(&p)?;
+  *RULES.lock() = rules;
+  Ok(())
+}
+
+fn expand(template: &str, stem: &str) -> String {
+  template
+    .replace("{date}", &Local::now().format("%Y-%m-%d").to_string())
+    .replace("{name}", stem)
+}
+
+fn rename_by_template(p: &Path, template: &str) -> Result<Option<PathBuf>, String> {
+  let stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let ext = p.extension().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let name = expand(template, &stem);
+  let name: String = name.chars().filter(|c| !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')).collect();
+  let name = name.trim();
+  if name.is_empty() || name == stem { return Ok(None); }
+  let target = p.with_file_name(format!("{}.{}", name, ext));
+  if target.exists() { return Err(format!("rename target exists: {}", target.display())); }
+  archive::guard(p).map_err(|e| e.to_string())?;
+  match shadow::rename(p, &target)? {
+    Some(copy) => {
+      let (from, to) = (p.to_string_lossy().to_string(), copy.to_string_lossy().to_string());
+      audit::record(&from, "path", Some(&from), Some(&to), audit::Source::Rule);
+      Ok(Some(copy))
+    }
+    None => {
+      RENAMED.lock().insert(target.clone());
+      folder_watch::migrate(p, &target, audit::Source::Rule);
+      Ok(Some(target))
+    }
+  }
+}
+
+fn apply_rule(app: &tauri::AppHandle, rule: &InboxRule, p: &Path) {
+  let path = p.to_string_lossy().to_string();
+  let tags: Vec<String> = rule.add_tags.iter().map(|t| expand(t, "")).collect();
+  let mut ev = InboxEvent { folder: rule.folder.clone(), path: path.clone(), renamed_to: None, tags: tags.clone(), error: None };
+
+  if let Err(e) = tag_ops::merge_file_tags(&path, &tags, &[], audit::Source::Rule) {
+    ev.error = Some(e);
+  } else if let Some(t) = &rule.rename_template {
+    match rename_by_template(p, t) {
+      Ok(Some(target)) => ev.renamed_to = Some(target.to_string_lossy().to_string()),
+      Ok(None) => {}
+      Err(e) => ev.error = Some(e),
+    }
+  }
+  log_line(&format!("inbox_rule folder=\"{}\" path=\"{}\" error={:?}", rule.folder, path, ev.error));
+  let _ = app.emit_all("inbox-file-processed", ev);
+}
+
+/// Background poll loop; started once from setup.
+pub fn start(app: tauri::AppHandle) {
+  std::thread::spawn(move || {
+    let mut w = PollWatcher::new(SETTLE_FOR);
+    loop {
+      // Rules on a removed drive pause until `retry_volume` brings the root back.
+      let rules: Vec<InboxRule> = RULES.lock().iter()
+        .filter(|r| r.enabled && volumes::detect(Path::new(&r.folder)).is_none())
+        .cloned()
+        .collect();
+      let roots: Vec<PathBuf> = rules.iter().map(|r| PathBuf::from(&r.folder)).collect();
+      w.set_roots(&roots);
+      for ev in w.poll() {
+        let FsEvent::Added(p) = ev else { continue };
+        if RENAMED.lock().remove(&p) { continue; }
+        let Some(parent) = p.parent() else { continue };
+        if let Some(rule) = rules.iter().find(|r| Path::new(&r.folder) == parent) {
+          apply_rule(&app, rule, &p);
+        }
+      }
+      std::thread::sleep(POLL_EVERY);
+    }
+  });
+}
+
+#[tauri::command]
+pub fn set_inbox_rule(folder: String, add_tags: Vec<String>, rename_template: Option<String>) -> Result<InboxRule, String> {
+  let rename_template = rename_template.filter(|t| !t.trim().is_empty());
+  let rule = InboxRule { folder: folder.clone(), add_tags, rename_template, enabled: true };
+  let mut rules = RULES.lock().clone();
+  rules.retain(|r| Path::new(&r.folder) != Path::new(&folder));
+  rules.push(rule.clone());
+  persist(rules)?;
+  Ok(rule)
+}
+
+#[tauri::command]
+pub fn set_inbox_rule_enabled(folder: String, enabled: bool) -> Result<(), String> {
+  let mut rules = RULES.lock().clone();
+  let Some(r) = rules.iter_mut().find(|r| Path::new(&r.folder) == Path::new(&folder)) else {
+    return Err(format!("no inbox rule for {}", folder));
+  };
+  r.enabled = enabled;
+  persist(rules)
+}
+
+#[tauri::command]
+pub fn remove_inbox_rule(folder: String) -> Result<(), String> {
+  let mut rules = RULES.lock().clone();
+  rules.retain(|r| Path::new(&r.folder) != Path::new(&folder));
+  persist(rules)
+}
+
+#[tauri::command]
+pub fn list_inbox_rules() -> Vec<InboxRule> { RULES.lock().clone() }
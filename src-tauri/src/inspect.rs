@@ -0,0 +1,173 @@
+// Low-level view of which tag blocks a file carries, for support and for
+// explaining why a comment shows up (or not) in other DJ software.
+
+use std::path::Path;
+use lofty::{ItemKey, TaggedFileExt};
+use serde::Serialize;
+
+use crate::{ape, id3_padding, read_order_for_ext, read_tagged, ext_lower, write_strategy, write_targets};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagBlockReport {
+  tag_type: String,
+  item_count: u32,
+  picture_count: u32,
+  comment: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectReport {
+  path: String,
+  file_type: String,
+  primary_tag_type: String,
+  tags: Vec<TagBlockReport>,
+  has_ape: bool,
+  read_order: Vec<String>,
+  write_targets: Vec<String>,
+  /// Leading ID3v2 tag of an MP3 and how much of it is padding.
+  id3_padding: Option<id3_padding::Id3Padding>,
+  /// MP3Gain's undo fields in the APE tag of an MP3, with why they matter.
+  mp3gain_undo: Option<ape::Mp3GainUndo>,
+}
+
+pub fn tag_type_name(tt: lofty::TagType) -> String { format!("{:?}", tt) }
+
+#[tauri::command]
+pub fn inspect_tags(path: String) -> Result<InspectReport, String> {
+  let p = Path::new(&path);
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let tags = tf
+    .tags()
+    .iter()
+    .map(|t| TagBlockReport {
+      tag_type: tag_type_name(t.tag_type()),
+      item_count: t.item_count(),
+      picture_count: t.picture_count(),
+      comment: t.get_string(&ItemKey::Comment).map(|s| s.to_string()),
+    })
+    .collect();
+  Ok(InspectReport {
+    path: path.clone(),
+    file_type: format!("{:?}", tf.file_type()),
+    primary_tag_type: tag_type_name(tf.primary_tag_type()),
+    tags,
+    has_ape: tf.tag(lofty::TagType::Ape).is_some(),
+    read_order: read_order_for_ext(&ext_lower(p)).iter().map(|t| tag_type_name(*t)).collect(),
+    write_targets: write_targets(&tf, p).into_iter().map(tag_type_name).collect(),
+    id3_padding: id3_padding::measure(p),
+    mp3gain_undo: ape::mp3gain_undo(&tf, p),
+  })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedTag {
+  tag_type: String,
+  exists: bool,
+}
+
+/// What a comment/metadata write to this file would touch, in write order.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WritePlan {
+  targets: Vec<PlannedTag>,
+  /// Set when the file doesn't get the default targets for its extension.
+  override_reason: Option<String>,
+}
+
+pub fn plan_for(tf: &lofty::TaggedFile, p: &Path) -> WritePlan {
+  let (targets, reason) = write_strategy(tf, p);
+  WritePlan {
+    targets: targets.into_iter().map(|tt| PlannedTag { tag_type: tag_type_name(tt), exists: tf.tag(tt).is_some() }).collect(),
+    override_reason: reason,
+  }
+}
+
+pub fn plan_for_path(p: &Path) -> Result<WritePlan, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  Ok(plan_for(&tf, p))
+}
+
+#[tauri::command]
+pub fn write_plan(path: String) -> Result<WritePlan, String> { plan_for_path(Path::new(&path)) }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+  use lofty::TagType;
+  use crate::{edit_tags, tag_targets, test_support};
+
+  fn targets(plan: &WritePlan) -> Vec<(String, bool)> { plan.targets.iter().map(|t| (t.tag_type.clone(), t.exists)).collect() }
+  fn t(name: &str, exists: bool) -> (String, bool) { (name.to_string(), exists) }
+
+  #[test]
+  fn plans_follow_the_built_in_strategy() {
+    let dir = test_support::scratch("write-plan");
+    let cases = [
+      ("a.mp3", vec![t("Id3v2", true)]),
+      // lofty's primary tag for WAV is ID3v2.
+      ("a.wav", vec![t("RiffInfo", false), t("Id3v2", true)]),
+      ("a.aiff", vec![t("Id3v2", true)]),
+      ("a.flac", vec![t("VorbisComments", true)]),
+    ];
+    for (name, want) in cases {
+      let p = test_support::tagged(&dir, name, &[(ItemKey::TrackTitle, "T")]);
+      let plan = plan_for_path(&p).unwrap();
+      assert_eq!((targets(&plan), plan.override_reason.is_some()), (want, false), "{}", name);
+    }
+    let untagged = plan_for_path(&test_support::audio(&dir, "b.mp3")).unwrap();
+    assert_eq!(targets(&untagged), [t("Id3v2", false)]);
+
+    // An APE-only MP3 keeps its APE tag.
+    let ape = test_support::audio(&dir, "ape.mp3");
+    test_support::add_tag(&ape, TagType::Ape, &[(ItemKey::TrackTitle, "T")]);
+    let plan = plan_for_path(&ape).unwrap();
+    assert_eq!(targets(&plan), [t("Ape", true)]);
+    assert!(plan.override_reason.unwrap().contains("APE-only"));
+  }
+
+  /// An AIFF whose NAME text chunk reads `name`.
+  fn aiff_with_name(dir: &std::path::Path, file: &str, name: &str) -> std::path::PathBuf {
+    let p = test_support::audio(dir, file);
+    let mut bytes = std::fs::read(&p).unwrap();
+    bytes.extend_from_slice(b"NAME");
+    bytes.extend_from_slice(&(name.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(name.as_bytes());
+    if name.len() % 2 == 1 { bytes.push(0); }
+    let size = (bytes.len() - 8) as u32;
+    bytes[4..8].copy_from_slice(&size.to_be_bytes());
+    std::fs::write(&p, bytes).unwrap();
+    p
+  }
+
+  #[test]
+  fn the_setting_replaces_the_targets_and_leads_the_read_order() {
+    // .aif only: the other tests use .aiff, so they keep the built-in targets.
+    let dir = test_support::scratch("write-plan-custom");
+    let setting = HashMap::from([(".AIF".to_string(), vec!["aifftext".to_string(), "id3v2".to_string()])]);
+    let setting = tag_targets::validate(&setting).unwrap();
+    assert_eq!(setting["aif"], ["AiffText", "Id3v2"]);
+    tag_targets::set(&setting);
+
+    let p = aiff_with_name(&dir, "a.aif", "Old");
+    let plan = plan_for_path(&p).unwrap();
+    assert_eq!(targets(&plan), [t("AiffText", true), t("Id3v2", false)]);
+    assert!(plan.override_reason.unwrap().contains("write targets"));
+    edit_tags(&p, |tag| { tag.insert_text(ItemKey::TrackTitle, "New".into()); }).unwrap();
+    assert_eq!(test_support::text(&p, TagType::AiffText, &ItemKey::TrackTitle).as_deref(), Some("New"));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackTitle).as_deref(), Some("New"));
+    assert_eq!(crate::read_order_for_ext("aif")[..2], [TagType::AiffText, TagType::Id3v2]);
+
+    // Without text chunks to update, only ID3v2 is written.
+    let bare = test_support::tagged(&dir, "b.aif", &[(ItemKey::TrackTitle, "T")]);
+    let plan = plan_for_path(&bare).unwrap();
+    assert_eq!(targets(&plan), [t("Id3v2", true)]);
+    assert!(plan.override_reason.unwrap().contains("only updated"));
+
+    tag_targets::set(&HashMap::new());
+    assert!(plan_for_path(&p).unwrap().override_reason.is_none());
+  }
+}
@@ -0,0 +1,328 @@
+// Registry of long-running backend jobs (decoding, renders). Each job gets an
+// id the frontend can cancel by; workers poll the cancel flag between blocks
+// and report through `job-started` / `job-progress` / `job-finished` events.
+// Finished jobs also land in `perf` with their duration and `timed` phases,
+// and long ones can raise an OS notification (see `notifications`).
+//
+// Jobs with distinct stages (scan, analyze, write) announce each with
+// `begin_phase`; `progress` then counts within the current one. Progress
+// events carry the rate over the last RATE_WINDOW and the ETA of the current
+// phase, both computed here, and a per-job `seq` that grows with every event
+// so the UI can drop ones delivered out of order. `job-finished` always
+// comes, cancelled or not, with how far each phase got; a handle dropped
+// without `finish` (an early return, a panic) still sends it.
+
+use std::{collections::{HashMap, VecDeque}, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc}, time::{Duration, Instant}};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{notifications, perf};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+  pub job_id: String,
+  pub kind: String,
+  /// What the job works on (usually a path), so the UI can match events to rows.
+  pub label: String,
+  pub started_at: String,
+  /// Within the current phase, if any.
+  pub done: u64,
+  pub total: u64,
+  pub phase: Option<String>,
+  /// Phases begun so far, the current one last.
+  pub phases: Vec<PhaseProgress>,
+  /// Items per second over the last RATE_WINDOW; `None` until measurable.
+  pub rate: Option<f64>,
+  /// Time left in the current phase at `rate`.
+  pub eta_ms: Option<u64>,
+  /// Grows with every event of the job, `job-finished` included.
+  pub seq: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseProgress {
+  pub name: String,
+  pub done: u64,
+  pub total: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus { Done, Cancelled, Failed }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobFinished {
+  job_id: String,
+  kind: String,
+  status: JobStatus,
+  error: Option<String>,
+  seq: u64,
+  took_ms: u64,
+  /// What got done, also when cancelled: the last counts and each phase's.
+  done: u64,
+  total: u64,
+  phases: Vec<PhaseProgress>,
+}
+
+/// Rate samples older than this are dropped (the last two are always kept).
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// At most one rate sample per this; progress may be reported per block.
+const SAMPLE_EVERY: Duration = Duration::from_millis(100);
+/// Shortest sample span a rate is computed over.
+const MIN_SPAN: Duration = Duration::from_millis(250);
+
+struct Entry {
+  info: JobInfo,
+  cancel: Arc<AtomicBool>,
+  /// Cleared by `set_job_notify` to keep this job quiet when it ends.
+  notify: bool,
+  /// (when, done) in the current phase, oldest first.
+  samples: VecDeque<(Instant, u64)>,
+}
+
+impl Entry {
+  fn sample(&mut self, done: u64, now: Instant) {
+    if self.samples.back().is_some_and(|(t, _)| now.duration_since(*t) < SAMPLE_EVERY) { return; }
+    self.samples.push_back((now, done));
+    while self.samples.len() > 2 && self.samples.front().is_some_and(|(t, _)| now.duration_since(*t) > RATE_WINDOW) { self.samples.pop_front(); }
+  }
+
+  /// Rate and ETA brought up to date and the next `seq` taken, for an event.
+  fn stamp(&mut self) -> JobInfo {
+    let rate = match (self.samples.front(), self.samples.back()) {
+      (Some((t0, d0)), Some((t1, d1))) if t1.duration_since(*t0) >= MIN_SPAN && d1 > d0 => Some((d1 - d0) as f64 / t1.duration_since(*t0).as_secs_f64()),
+      _ => None,
+    };
+    let i = &mut self.info;
+    i.rate = rate;
+    i.eta_ms = match rate {
+      _ if i.total > 0 && i.done >= i.total => Some(0),
+      Some(r) if i.total > 0 => Some(((i.total - i.done) as f64 / r * 1000.0) as u64),
+      _ => None,
+    };
+    i.seq += 1;
+    i.clone()
+  }
+}
+
+static JOBS: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Live job; dropping it unregisters the job (call `finish` first to notify the UI).
+pub struct JobHandle {
+  app: tauri::AppHandle,
+  id: String,
+  kind: String,
+  label: String,
+  cancel: Arc<AtomicBool>,
+  started: Instant,
+  /// (phase, total time, runs) in first-use order.
+  phases: Mutex<Vec<(String, Duration, u64)>>,
+  finished: bool,
+}
+
+impl JobHandle {
+  pub fn start(app: &tauri::AppHandle, kind: &str, label: &str) -> Self {
+    let id = format!("{}-{}", kind, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let cancel = Arc::new(AtomicBool::new(false));
+    let info = JobInfo { job_id: id.clone(), kind: kind.to_string(), label: label.to_string(), started_at: Local::now().to_rfc3339(),
+      done: 0, total: 0, phase: None, phases: Vec::new(), rate: None, eta_ms: None, seq: 0,
+    };
+    JOBS.lock().insert(id.clone(), Entry { info: info.clone(), cancel: cancel.clone(), notify: true, samples: VecDeque::new() });
+    let _ = app.emit_all("job-started", info);
+    Self { app: app.clone(), id, kind: kind.to_string(), label: label.to_string(), cancel, started: Instant::now(), phases: Mutex::new(Vec::new()), finished: false }
+  }
+
+  pub fn id(&self) -> &str { &self.id }
+  pub fn app(&self) -> &tauri::AppHandle { &self.app }
+  pub fn cancel_flag(&self) -> &AtomicBool { &self.cancel }
+  pub fn is_cancelled(&self) -> bool { self.cancel.load(Ordering::Relaxed) }
+
+  /// Start the stage `name` ("scan", "analyze", "write", …) of `total`
+  /// items, 0 while unknown. Counts restart; the event goes out at once.
+  pub fn begin_phase(&self, name: &str, total: u64) {
+    let info = {
+      let mut jobs = JOBS.lock();
+      let Some(e) = jobs.get_mut(&self.id) else { return };
+      e.info.phases.push(PhaseProgress { name: name.to_string(), done: 0, total });
+      e.info.phase = Some(name.to_string());
+      e.info.done = 0;
+      e.info.total = total;
+      e.samples.clear();
+      e.sample(0, Instant::now());
+      e.stamp()
+    };
+    let _ = self.app.emit_all("job-progress", info);
+  }
+
+  /// Record progress in the current phase; events are only emitted when the
+  /// whole percent changes. Workers reporting out of order can't move `done`
+  /// back while `total` stays.
+  pub fn progress(&self, done: u64, total: u64) {
+    let info = {
+      let mut jobs = JOBS.lock();
+      let Some(e) = jobs.get_mut(&self.id) else { return };
+      let done = if total == e.info.total { done.max(e.info.done) } else { done };
+      let pct = |d: u64, t: u64| (d.min(t) * 100).checked_div(t).unwrap_or(0);
+      let unchanged = pct(done, total) == pct(e.info.done, e.info.total) && total == e.info.total;
+      e.info.done = done;
+      e.info.total = total;
+      if let Some(p) = e.info.phases.last_mut() { p.done = done; p.total = total; }
+      e.sample(done, Instant::now());
+      if unchanged { return; }
+      e.stamp()
+    };
+    let _ = self.app.emit_all("job-progress", info);
+  }
+
+  /// Run `f`, adding its time to `phase` ("walk", "parse", "write", …) for
+  /// the perf panel. Meant to wrap each file's step, so totals accumulate.
+  pub fn timed<T>(&self, phase: &str, f: impl FnOnce() -> T) -> T {
+    let t = Instant::now();
+    let out = f();
+    let took = t.elapsed();
+    let mut phases = self.phases.lock();
+    match phases.iter_mut().find(|(p, ..)| p == phase) {
+      Some((_, d, n)) => { *d += took; *n += 1; }
+      None => phases.push((phase.to_string(), took, 1)),
+    }
+    out
+  }
+
+  fn emit_finished(&self, status: JobStatus, error: Option<String>) {
+    let info = JOBS.lock().get_mut(&self.id).map(|e| { e.info.seq += 1; e.info.clone() });
+    let Some(i) = info else { return };
+    let took_ms = self.started.elapsed().as_millis() as u64;
+    let ev = JobFinished { job_id: self.id.clone(), kind: self.kind.clone(), status, error, seq: i.seq, took_ms, done: i.done, total: i.total, phases: i.phases };
+    let _ = self.app.emit_all("job-finished", ev);
+  }
+
+  /// Emit `job-finished`; status is derived from the outcome and the cancel flag.
+  pub fn finish<T>(mut self, res: &Result<T, String>) {
+    // Batches that stop early still return their partial results as Ok.
+    let status = match res {
+      _ if self.is_cancelled() => JobStatus::Cancelled,
+      Ok(_) => JobStatus::Done,
+      Err(_) => JobStatus::Failed,
+    };
+    self.finished = true;
+    self.emit_finished(status, res.as_ref().err().cloned());
+    let took = self.started.elapsed();
+    perf::record_job(&self.kind, &self.label, took, status, &self.phases.lock());
+    let entry = JOBS.lock().get(&self.id).filter(|e| e.notify).map(|e| e.info.clone());
+    if let Some(info) = entry { notifications::job_finished(&self.app, &info, status, took, res.as_ref().err().map(String::as_str)); }
+  }
+}
+
+impl Drop for JobHandle {
+  fn drop(&mut self) {
+    if !self.finished {
+      let status = if self.is_cancelled() { JobStatus::Cancelled } else { JobStatus::Failed };
+      self.emit_finished(status, Some("job ended without a result".into()));
+    }
+    JOBS.lock().remove(&self.id);
+  }
+}
+
+#[tauri::command]
+pub fn cancel_job(job_id: String) -> bool {
+  match JOBS.lock().get(&job_id) {
+    Some(e) => { e.cancel.store(true, Ordering::Relaxed); true }
+    None => false,
+  }
+}
+
+/// Opt a running job out of (or back into) its completion notification.
+#[tauri::command]
+pub fn set_job_notify(job_id: String, notify: bool) -> bool {
+  match JOBS.lock().get_mut(&job_id) {
+    Some(e) => { e.notify = notify; true }
+    None => false,
+  }
+}
+
+/// No job registered right now.
+pub fn is_idle() -> bool { JOBS.lock().is_empty() }
+
+#[tauri::command]
+pub fn list_jobs() -> Vec<JobInfo> { JOBS.lock().values().map(|e| e.info.clone()).collect() }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(total: u64) -> Entry {
+    let info = JobInfo { job_id: "t-1".into(), kind: "t".into(), label: String::new(), started_at: String::new(),
+      done: 0, total, phase: Some("write".into()), phases: Vec::new(), rate: None, eta_ms: None, seq: 0,
+    };
+    Entry { info, cancel: Arc::new(AtomicBool::new(false)), notify: false, samples: VecDeque::new() }
+  }
+
+  /// Feed `e` `per_sec` items a second for `secs`, reported every 50 ms, from `t`.
+  fn run(e: &mut Entry, t: &mut Instant, per_sec: f64, secs: f64) {
+    let start = e.info.done as f64;
+    for step in 1..=(secs * 20.0) as u64 {
+      *t += Duration::from_millis(50);
+      e.info.done = (start + per_sec * step as f64 / 20.0) as u64;
+      e.sample(e.info.done, *t);
+    }
+  }
+
+  fn near(got: Option<u64>, want: u64) -> bool { got.is_some_and(|g| (g as f64 - want as f64).abs() <= want as f64 * 0.1) }
+
+  #[test]
+  fn a_steady_workload_gets_its_true_eta() {
+    let (mut e, mut t) = (entry(1000), Instant::now());
+    e.sample(0, t);
+    run(&mut e, &mut t, 40.0, 5.0);
+    let i = e.stamp();
+    assert!(i.rate.is_some_and(|r| (r - 40.0).abs() < 2.0), "{:?}", i.rate);
+    assert!(near(i.eta_ms, 20_000), "{:?}", i.eta_ms);
+    run(&mut e, &mut t, 40.0, 10.0);
+    let later = e.stamp();
+    assert!(later.eta_ms < i.eta_ms && near(later.eta_ms, 10_000), "{:?}", later.eta_ms);
+    assert!(later.seq > i.seq);
+  }
+
+  #[test]
+  fn the_eta_follows_a_slowdown_within_the_window() {
+    let (mut e, mut t) = (entry(2000), Instant::now());
+    e.sample(0, t);
+    run(&mut e, &mut t, 100.0, 10.0);
+    // Network drive gets slow: after a full window only the new speed counts.
+    run(&mut e, &mut t, 10.0, 12.0);
+    let i = e.stamp();
+    assert!(i.rate.is_some_and(|r| (r - 10.0).abs() < 1.0), "{:?}", i.rate);
+    assert!(near(i.eta_ms, (2000 - i.done) * 100), "{:?} for {} left", i.eta_ms, 2000 - i.done);
+  }
+
+  #[test]
+  fn no_eta_without_a_rate_and_zero_when_done() {
+    let (mut e, mut t) = (entry(100), Instant::now());
+    e.sample(0, t);
+    t += Duration::from_millis(100);
+    e.info.done = 5;
+    e.sample(5, t);
+    assert_eq!(e.stamp().eta_ms, None, "too short a span to tell");
+    // Stalled: no progress over the span.
+    let (mut stalled, mut t2) = (entry(100), Instant::now());
+    stalled.sample(0, t2);
+    t2 += Duration::from_secs(2);
+    stalled.sample(0, t2);
+    assert_eq!(stalled.stamp().eta_ms, None);
+    // Unknown total: a rate but no ETA.
+    let (mut open, mut t3) = (entry(0), Instant::now());
+    open.sample(0, t3);
+    run(&mut open, &mut t3, 20.0, 1.0);
+    let i = open.stamp();
+    assert!(i.rate.is_some() && i.eta_ms.is_none());
+    e.info.done = 100;
+    assert_eq!(e.stamp().eta_ms, Some(0));
+  }
+}
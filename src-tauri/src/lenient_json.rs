@@ -0,0 +1,68 @@
+// Tolerant pre-pass for hand-edited JSON (bank files): drops a UTF-8 BOM,
+// `//` and `/* */` comments and trailing commas, then re-serializes through
+// serde_json so callers always get strict JSON back.
+
+/// Strict, pretty-printed JSON for `text` if the lenient reading parses.
+pub fn repair(text: &str) -> Option<String> {
+  let cleaned = strip_trailing_commas(&strip_comments(text.trim_start_matches('\u{feff}')));
+  let v: serde_json::Value = serde_json::from_str(&cleaned).ok()?;
+  serde_json::to_string_pretty(&v).ok()
+}
+
+fn strip_comments(s: &str) -> String {
+  let mut out = String::with_capacity(s.len());
+  let mut chars = s.chars().peekable();
+  let (mut in_str, mut escaped) = (false, false);
+  while let Some(c) = chars.next() {
+    if in_str {
+      out.push(c);
+      match c {
+        _ if escaped => escaped = false,
+        '\\' => escaped = true,
+        '"' => in_str = false,
+        _ => {}
+      }
+      continue;
+    }
+    match (c, chars.peek()) {
+      ('"', _) => { in_str = true; out.push(c); }
+      ('/', Some('/')) => {
+        for n in chars.by_ref() { if n == '\n' { out.push('\n'); break; } }
+      }
+      ('/', Some('*')) => {
+        chars.next();
+        let mut prev = '\0';
+        for n in chars.by_ref() {
+          if prev == '*' && n == '/' { break; }
+          prev = n;
+        }
+        out.push(' ');
+      }
+      _ => out.push(c),
+    }
+  }
+  out
+}
+
+/// Comments must already be gone.
+fn strip_trailing_commas(s: &str) -> String {
+  let chars: Vec<char> = s.chars().collect();
+  let mut out = String::with_capacity(s.len());
+  let (mut in_str, mut escaped) = (false, false);
+  for (i, &c) in chars.iter().enumerate() {
+    if in_str {
+      match c {
+        _ if escaped => escaped = false,
+        '\\' => escaped = true,
+        '"' => in_str = false,
+        _ => {}
+      }
+    } else if c == '"' {
+      in_str = true;
+    } else if c == ',' && chars[i + 1..].iter().find(|n| !n.is_whitespace()).is_some_and(|n| matches!(n, '}' | ']')) {
+      continue;
+    }
+    out.push(c);
+  }
+  out
+}
@@ -0,0 +1,176 @@
+// Multi-root library: several crate folders scanned as one list, plus named
+// workspaces (root sets) persisted in prefs. Walks honour `.taggerignore`
+// files and the settings excludes (see `ignore_files`).
+
+use std::{collections::HashSet, fs, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  command_span, error::CmdError, ext_lower, file_health, formats, ignore_files::{self, Rules, Skipped}, load_prefs, log_line, long_paths, meta_cache, natural_sort, payload_guard::{self, Guarded}, portable, save_prefs,
+  supported_ext, volumes,
+};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ScanOptions {
+  /// Descend into dot-directories (skipped by default, e.g. `.Trashes`).
+  include_hidden: bool,
+  /// Depth limit for recursive scans; `None` is unlimited.
+  max_depth: Option<usize>,
+  /// Also list other media files (video, disabled formats) with `supported: false`.
+  include_unsupported: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryFile {
+  pub path: String,
+  pub file_name: String,
+  /// The scanned root this file was found under, for grouping in the UI.
+  pub root: String,
+  /// `false` for files listed by `include_unsupported`: read-only, see `probe::probe_file`.
+  pub supported: bool,
+  #[serde(flatten)]
+  pub health: file_health::Health,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Workspace {
+  pub name: String,
+  pub roots: Vec<String>,
+}
+
+fn is_hidden(p: &Path) -> bool {
+  p.file_name().map(|n| n.to_string_lossy().starts_with('.')).unwrap_or(false)
+}
+
+/// What one walk collects.
+#[derive(Default)]
+struct Walk {
+  files: Vec<PathBuf>,
+  skipped: Skipped,
+}
+
+fn walk(dir: &Path, depth: usize, recursive: bool, opts: ScanOptions, rules: &mut Rules, out: &mut Walk) -> Result<(), CmdError> {
+  let rd = fs::read_dir(long_paths::extended(dir)).map_err(|e| CmdError::from_io(dir, &e))?;
+  for entry in rd.flatten() {
+    let p = long_paths::simplified(&entry.path()).into_owned();
+    let Ok(ft) = entry.file_type() else { continue };
+    if ft.is_file() && (supported_ext(&p) || opts.include_unsupported && formats::is_media(&ext_lower(&p))) {
+      if !rules.skip(&p, false, &mut out.skipped) { out.files.push(p); }
+    } else if ft.is_dir() && recursive && (opts.include_hidden || !is_hidden(&p)) && opts.max_depth.is_none_or(|m| depth < m) {
+      if rules.skip(&p, true, &mut out.skipped) { continue; }
+      let entered = rules.enter(&p);
+      // Unreadable subfolders are skipped; only the root itself is fatal.
+      let _ = walk(&p, depth + 1, recursive, opts, rules, out);
+      rules.leave(entered);
+    }
+  }
+  Ok(())
+}
+
+fn walk_root(root: &Path, recursive: bool, opts: ScanOptions) -> Result<Walk, CmdError> {
+  let mut out = Walk::default();
+  walk(root, 0, recursive, opts, &mut Rules::for_root(root), &mut out)?;
+  Ok(out)
+}
+
+/// Every supported file under `root`, recursively (hidden folders skipped).
+pub fn audio_files_under(root: &Path) -> Result<Vec<PathBuf>, CmdError> { audio_files(root, true) }
+
+pub fn audio_files(root: &Path, recursive: bool) -> Result<Vec<PathBuf>, CmdError> {
+  let mut out = walk_root(root, recursive, ScanOptions::default())?.files;
+  out.sort_by(|a, b| natural_sort::compare_paths(a, b));
+  Ok(out)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanResult {
+  pub files: Vec<LibraryFile>,
+  /// Entries left out by each ignore file, for all roots together.
+  pub ignored: Vec<ignore_files::IgnoreHits>,
+}
+
+fn scan_roots(roots: &[String], recursive: bool, opts: ScanOptions) -> Result<ScanResult, CmdError> {
+  // One thread per root: roots are usually on different disks.
+  let per_root: Vec<Result<Walk, CmdError>> = std::thread::scope(|s| {
+    let handles: Vec<_> = roots
+      .iter()
+      .map(|r| s.spawn(move || {
+        let root = PathBuf::from(r);
+        volumes::register_root(&root);
+        portable::register(&root);
+        walk_root(&root, recursive, opts)
+      }))
+      .collect();
+    handles.into_iter().map(|h| h.join().unwrap_or_else(|_| Err(CmdError::from("scan thread panicked".to_string())))).collect()
+  });
+
+  // Roots are processed in the order given, so a file under two roots is
+  // attributed to the first one.
+  let mut seen = HashSet::new();
+  let mut files = Vec::new();
+  let mut skipped = Skipped::new();
+  for (root, res) in roots.iter().zip(per_root) {
+    let walked = res?;
+    for (source, n) in walked.skipped { *skipped.entry(source).or_default() += n; }
+    for p in walked.files {
+      let canon = fs::canonicalize(&p).unwrap_or_else(|_| p.clone());
+      if !seen.insert(canon) { continue; }
+      let supported = supported_ext(&p);
+      files.push(LibraryFile {
+        file_name: p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        path: p.to_string_lossy().to_string(),
+        root: root.clone(),
+        supported,
+        health: if supported { file_health::check(&p) } else { file_health::Health::ok() },
+      });
+    }
+  }
+  files.sort_by_cached_key(|f| (natural_sort::sort_key(&f.file_name), f.path.clone()));
+  Ok(ScanResult { files, ignored: ignore_files::report(skipped) })
+}
+
+#[tauri::command]
+pub async fn scan_folders(paths: Vec<String>, recursive: bool, opts: Option<ScanOptions>) -> Result<Guarded<ScanResult>, CmdError> {
+  let _span = command_span("scan_folders");
+  let opts = opts.unwrap_or_default();
+  let res = tauri::async_runtime::spawn_blocking(move || scan_roots(&paths, recursive, opts).map(|r| (r, paths)))
+    .await
+    .map_err(|e| CmdError::from(e.to_string()))?;
+  let (res, paths) = res?;
+  meta_cache::refresh_in_background(res.files.iter().filter(|f| f.supported).map(|f| PathBuf::from(&f.path)).collect());
+  let ignored: usize = res.ignored.iter().map(|h| h.entries).sum();
+  log_line(&format!("scan_folders roots={} files={} ignored={}", paths.len(), res.files.len(), ignored));
+  Ok(payload_guard::guard("scan_folders", res)?)
+}
+
+/// Save (or replace) a named root set; it becomes the most recently used one.
+#[tauri::command]
+pub fn save_workspace(name: String, roots: Vec<String>) -> Result<Workspace, String> {
+  let name = name.trim().to_string();
+  if name.is_empty() { return Err("workspace name is empty".into()); }
+  let mut seen = HashSet::new();
+  let roots: Vec<String> = roots.into_iter().filter(|r| !r.trim().is_empty() && seen.insert(r.clone())).collect();
+  for r in &roots { portable::register(Path::new(r)); }
+  let ws = Workspace { name, roots };
+  let mut p = load_prefs();
+  p.workspaces.retain(|w| w.name != ws.name);
+  p.workspaces.insert(0, ws.clone());
+  save_prefs(&p)?;
+  Ok(ws)
+}
+
+/// Most recently used first.
+#[tauri::command]
+pub fn list_workspaces() -> Vec<Workspace> { load_prefs().workspaces }
+
+#[tauri::command]
+pub fn delete_workspace(name: String) -> Result<(), String> {
+  let mut p = load_prefs();
+  p.workspaces.retain(|w| w.name != name);
+  save_prefs(&p)
+}
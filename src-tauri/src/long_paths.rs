@@ -0,0 +1,138 @@
+// Windows paths past MAX_PATH (deep OneDrive libraries). Win32 file calls
+// fail on them with OS error 3 unless they carry the `\\?\` extended-length
+// prefix, which also turns off the usual normalization, so separators have
+// to be `\` and `.` / `..` resolved first. `extended` makes that form for a
+// long path right where a file is opened, listed, read or saved by lofty,
+// renamed or written atomically; paths kept in caches, logs and results stay
+// plain (`simplified` undoes the prefix on what `read_dir` hands back).
+// Elsewhere both are the identity.
+
+use std::{borrow::Cow, path::Path};
+#[cfg(windows)]
+use std::path::{Component, PathBuf, Prefix};
+
+/// Directories get MAX_PATH less room for an 8.3 file name; prefix from there.
+#[cfg(windows)]
+const LIMIT: usize = 260 - 12;
+
+#[cfg(windows)]
+pub fn extended(p: &Path) -> Cow<'_, Path> {
+  let Some(s) = p.to_str() else { return Cow::Borrowed(p) };
+  if s.encode_utf16().count() < LIMIT || s.starts_with(r"\\?\") { return Cow::Borrowed(p); }
+  let abs = if p.is_absolute() {
+    p.to_path_buf()
+  } else {
+    match std::env::current_dir() { Ok(d) => d.join(p), Err(_) => return Cow::Borrowed(p) }
+  };
+  let mut head = String::new();
+  let mut parts: Vec<String> = Vec::new();
+  for c in abs.components() {
+    match c {
+      Component::Prefix(pre) => match pre.kind() {
+        Prefix::Disk(d) => head = format!(r"\\?\{}:", d as char),
+        Prefix::UNC(server, share) => head = format!(r"\\?\UNC\{}\{}", server.to_string_lossy(), share.to_string_lossy()),
+        // Already verbatim, or a device path.
+        _ => return Cow::Borrowed(p),
+      },
+      Component::RootDir | Component::CurDir => {}
+      Component::ParentDir => { parts.pop(); }
+      Component::Normal(n) => parts.push(n.to_string_lossy().to_string()),
+    }
+  }
+  if head.is_empty() { return Cow::Borrowed(p); }
+  Cow::Owned(PathBuf::from(format!(r"{}\{}", head, parts.join(r"\"))))
+}
+
+#[cfg(not(windows))]
+pub fn extended(p: &Path) -> Cow<'_, Path> { Cow::Borrowed(p) }
+
+/// `p` without an extended-length prefix, for paths that leave this module's callers.
+#[cfg(windows)]
+pub fn simplified(p: &Path) -> Cow<'_, Path> {
+  let Some(s) = p.to_str() else { return Cow::Borrowed(p) };
+  if let Some(rest) = s.strip_prefix(r"\\?\UNC\") { return Cow::Owned(PathBuf::from(format!(r"\\{}", rest))); }
+  match s.strip_prefix(r"\\?\") {
+    Some(rest) if rest.as_bytes().get(1) == Some(&b':') => Cow::Owned(PathBuf::from(rest)),
+    _ => Cow::Borrowed(p),
+  }
+}
+
+#[cfg(not(windows))]
+pub fn simplified(p: &Path) -> Cow<'_, Path> { Cow::Borrowed(p) }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::ItemKey;
+  use crate::{file_health, test_support};
+
+  /// A folder whose path is well past MAX_PATH.
+  fn deep_dir() -> std::path::PathBuf {
+    let mut dir = test_support::scratch("long-paths");
+    while dir.to_string_lossy().len() < 300 { dir = dir.join("a folder name of forty characters, give or take"); }
+    std::fs::create_dir_all(extended(&dir)).unwrap();
+    dir
+  }
+
+  #[test]
+  fn deep_files_are_listed_and_checked() {
+    let dir = deep_dir();
+    let p = dir.join("track.wav");
+    std::fs::copy(test_support::audio(&test_support::scratch("long-src"), "track.wav"), extended(&p)).unwrap();
+    let listed = crate::read_folder(&dir.to_string_lossy(), false).unwrap();
+    assert_eq!(listed.len(), 1);
+    // Results carry the plain form.
+    assert_eq!(listed[0].path, p.to_string_lossy());
+    assert_eq!(file_health::check(&p).status, file_health::FileStatus::Ok);
+
+    // Read, edit, save and read back through the usual write path.
+    let outcome = crate::edit_tags(&p, |tag| { tag.insert_text(ItemKey::Comment, "#deep;".into()); }).unwrap();
+    assert!(!outcome.no_op);
+    let meta = crate::read_metadata(p.to_string_lossy().to_string()).unwrap();
+    assert_eq!(meta.comment, "#deep;");
+
+    // And streamed to the player.
+    let _streams = test_support::MEDIA_SERVER.lock();
+    let url = crate::urls::path_url("http://127.0.0.1:1", "audio", &p.to_string_lossy());
+    let req = hyper::Request::builder().uri(url).body(hyper::Body::empty()).unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let resp = rt.block_on(crate::audio_response(&req));
+    assert_eq!(resp.status(), hyper::StatusCode::OK);
+    let body = rt.block_on(hyper::body::to_bytes(resp.into_body())).unwrap();
+    assert_eq!(body, std::fs::read(extended(&p)).unwrap());
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn long_paths_get_the_verbatim_prefix() {
+    let long = format!(r"C:\Music\{}\track.flac", "x".repeat(260));
+    let ext = extended(Path::new(&long));
+    assert_eq!(ext.to_str().unwrap(), format!(r"\\?\{}", long));
+    assert_eq!(simplified(&ext).to_str().unwrap(), long);
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn dots_and_separators_are_resolved_first() {
+    let tail = "y".repeat(260);
+    let long = format!(r"C:/Music/./old/../{}/track.flac", tail);
+    assert_eq!(extended(Path::new(&long)).to_str().unwrap(), format!(r"\\?\C:\Music\{}\track.flac", tail));
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn unc_shares_round_trip() {
+    let long = format!(r"\\nas\music\{}\track.mp3", "z".repeat(260));
+    let ext = extended(Path::new(&long));
+    assert_eq!(ext.to_str().unwrap(), format!(r"\\?\UNC\nas\music\{}\track.mp3", "z".repeat(260)));
+    assert_eq!(simplified(&ext).to_str().unwrap(), long);
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn short_and_verbatim_paths_are_left_alone() {
+    for p in [r"C:\Music\track.flac", r"\\?\C:\already\verbatim.flac"] {
+      assert!(matches!(extended(Path::new(p)), Cow::Borrowed(_)), "{}", p);
+    }
+  }
+}
@@ -1,8 +1,8 @@
 #![cfg_attr(all(not(debug_assertions), target_os = "windows"), windows_subsystem = "windows")]
 
-use tauri::{api::{dialog::blocking::FileDialogBuilder, path::app_data_dir}};
+use tauri::api::dialog::blocking::FileDialogBuilder;
 use lofty::{Accessor, ItemKey, PictureType, TaggedFileExt, TagType, Tag};
-use std::{fs, path::{Path, PathBuf}, io::Write};
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, io::Write};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use chrono::Local;
@@ -13,30 +13,136 @@ use std::{convert::Infallible, io};
 use hyper::{Body, Request, Response, Server, StatusCode, header, Method};
 use hyper::header::{HeaderName, HeaderValue};
 use hyper::service::{make_service_fn, service_fn};
-use tokio::io::{AsyncSeekExt, AsyncReadExt};
-use tokio_util::io::ReaderStream;
+use tokio::io::{AsyncRead, AsyncSeekExt, ReadBuf};
+use std::{pin::Pin, sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering}, task::{Context, Poll}, time::Instant};
 
 
-use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use tauri::Manager;
 
 use serde_json::json;
-use tauri::api::path::document_dir;
 
 use serde::{Deserialize, Serialize};
 
-
-
-static TAGS_SCHEMA_VERSION: u32 = 1; // also update in src/lib/tags.ts if changed
+mod aiff_chunks;
+mod already_owned;
+mod api;
+mod ape;
+mod archive;
+mod artwork;
+mod audit;
+mod audit_export;
+mod autocomplete;
+mod bank_store;
+mod bank_sync;
+mod banks;
+mod cli;
+mod color_label;
+mod comment_precedence;
+mod comment_template;
+mod compilation;
+mod convert;
+mod dates;
+mod decode;
+mod deep_read;
+mod encoder_info;
+mod error;
+mod export;
+mod export_check;
+mod extension_check;
+mod field_limits;
+mod field_locks;
+mod file_health;
+mod folder_compare;
+mod folder_watch;
+mod formats;
+mod full_text;
+mod handshake;
+mod id3_padding;
+mod ignore_files;
+mod inbox;
+mod inspect;
+mod jobs;
+mod lenient_json;
+mod library;
+mod long_paths;
+mod maintenance;
+mod manifest;
+mod media_streams;
+mod meta_cache;
+mod name_hints;
+mod natural_sort;
+mod notifications;
+mod now_showing;
+mod palette;
+mod payload_guard;
+mod peaks;
+mod perf;
+mod picture_budget;
+mod portable;
+mod preflight;
+mod presets;
+mod preview_gain;
+mod preview_cues;
+mod preview_history;
+mod probe;
+mod profile;
+mod removed_tags;
+mod retry_queue;
+mod session_state;
+mod session_writes;
+mod shadow;
+mod snapshots;
+mod startup_scan;
+mod support_bundle;
+mod tag_conflicts;
+mod tag_import;
+mod tag_ops;
+mod tag_policy;
+mod tag_rename;
+mod tag_size;
+mod tag_storage;
+mod tag_suggest;
+mod tag_targets;
+mod tagged_at;
+#[cfg(test)]
+mod test_support;
+mod track_updates;
+mod text_cleanup;
+mod text_fold;
+mod touched;
+mod track_numbers;
+mod tracklist;
+mod urls;
+mod volume_health;
+mod volumes;
+mod watcher;
+mod write_verify;
+mod years;
+mod waveform_image;
+mod workspace_stats;
+mod xattrs;
+mod zip_export;
+
+use error::CmdError;
+
+
+static TAGS_SCHEMA_VERSION: u32 = 4; // src/lib/tags.ts sends its own in `handshake`
 
 static LOG_PATH: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
 static WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct SimpleFile { path: String, file_name: String }
+struct SimpleFile {
+  path: String,
+  file_name: String,
+  /// `false` for files listed by `include_unsupported`: read-only, see `probe::probe_file`.
+  supported: bool,
+  #[serde(flatten)]
+  health: file_health::Health,
+}
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct TrackMeta {
   path: String,
@@ -47,38 +153,129 @@ struct TrackMeta {
   comment: String,
   picture_data_url: Option<String>,
   format: Option<String>,
+  /// ISO yyyy-mm-dd, or yyyy-mm / yyyy when that's all the file knows.
+  release_date: Option<String>,
+  original_date: Option<String>,
+  /// Last successful write from this app (read_metadata only).
+  last_touched_by_app: Option<String>,
+  /// Our managed fields changed on disk since that write.
+  externally_modified_since: bool,
+  /// Another tag type holds a different non-empty comment (see `comment_precedence`).
+  comment_conflicts: bool,
+  /// TAGGED_AT stamp in the file, RFC 3339 (see `tagged_at`).
+  tagged_at: Option<String>,
+  /// Fields no write will change (see `field_locks`).
+  locked_fields: Vec<String>,
+  /// "#RRGGBB", Serato's track colour else ours (see `color_label`).
+  color_label: Option<String>,
+  /// TCMP / cpil / COMPILATION; `None` when the file has no flag.
+  compilation: Option<bool>,
+  /// "LAME 3.100", "FhG", a FLAC vendor string (see `encoder_info`).
+  encoder: Option<String>,
+  /// MP3 only; `None` when it can't be told.
+  vbr: Option<bool>,
+  /// Embedded pictures past `picture_budget_mb` weren't read; the cover may be missing.
+  pictures_truncated: bool,
+}
+
+enum MediaBase {
+  Starting,
+  Ready(String), // e.g. "http://127.0.0.1:12123"
+  Failed(String),
 }
 
 struct AppState {
-  media_base: String, // e.g. "http://127.0.0.1:12123"
+  // Filled in by the startup task once the media server is bound.
+  media_base: parking_lot::RwLock<MediaBase>,
+  banks: bank_store::BankStore,
+  /// Track on the second-screen page (see `now_showing`).
+  now_showing: parking_lot::RwLock<now_showing::NowShowing>,
+  /// Command and job timings (see `perf`).
+  perf: perf::PerfLog,
+}
+
+// Startup timing, relative to process start. Lines recorded before the
+// frontend opens the session log are written when `init_session` runs.
+static STARTED_AT: Lazy<std::time::Instant> = Lazy::new(std::time::Instant::now);
+static EARLY_LOG: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn startup_mark(what: &str) {
+  let line = format!("startup {} ms={}", what, STARTED_AT.elapsed().as_millis());
+  if LOG_PATH.lock().is_some() { log_line(&line); } else { EARLY_LOG.lock().push(line); }
 }
 
 
-fn data_dir() -> PathBuf { app_data_dir(&tauri::Config::default()).unwrap_or(std::env::current_dir().unwrap()) }
+#[cfg(not(test))]
+fn data_dir() -> PathBuf { tauri::api::path::app_data_dir(&tauri::Config::default()).unwrap_or(std::env::current_dir().unwrap()) }
+#[cfg(test)]
+fn data_dir() -> PathBuf { test_support::data_dir() }
 fn tags_file_path() -> PathBuf { let mut p = data_dir(); p.push("tags.json"); p }
 fn logs_dir() -> PathBuf { let mut p = data_dir(); p.push("logs"); p }
 fn banks_dir() -> PathBuf {
   // ~/Documents/AudioTagger/Banks
-  let base = document_dir()
-    .unwrap_or_else(|| std::env::current_dir().unwrap())
-    .join("AudioTagger")
-    .join("Banks");
+  let base = documents_root().join("Banks");
   let _ = fs::create_dir_all(&base);
   base
 }
 
+/// Write via a sibling temp file + rename so readers never see a half-written file.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+  // The temp file sits next to the target, so it's as long; both get the prefix.
+  let path = &*long_paths::extended(path);
+  let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  let tmp = path.with_file_name(format!(".{}.tmp", name));
+  let attrs = xattrs::capture(path);
+  {
+    let mut f = fs::File::create(&tmp).map_err(|e| e.to_string())?;
+    f.write_all(bytes).map_err(|e| e.to_string())?;
+    f.sync_all().map_err(|e| e.to_string())?;
+  }
+  xattrs::restore(&tmp, &attrs);
+  rename_over(&tmp, path).inspect_err(|_| { let _ = fs::remove_file(&tmp); })
+}
+
+#[cfg(not(windows))]
+fn rename_over(tmp: &Path, path: &Path) -> Result<(), String> { fs::rename(tmp, path).map_err(|e| e.to_string()) }
+
+/// Windows refuses to replace a file another process has open (a player, the
+/// indexer, antivirus); those usually let go within moments, so retry first.
+#[cfg(windows)]
+fn rename_over(tmp: &Path, path: &Path) -> Result<(), String> {
+  const ATTEMPTS: u32 = 8;
+  let mut delay = std::time::Duration::from_millis(25);
+  let mut attempt = 1;
+  loop {
+    match fs::rename(tmp, path) {
+      Ok(()) => return Ok(()),
+      // ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION
+      Err(e) if matches!(e.raw_os_error(), Some(5 | 32)) => {
+        if attempt == ATTEMPTS {
+          return Err(format!("couldn't replace {}: another program still has it open ({})", path.display(), e));
+        }
+        std::thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+      }
+      Err(e) => return Err(e.to_string()),
+    }
+  }
+}
+
 fn default_tags_json() -> String {
   format!(r#"{{ "version": {}, "tags": [] }}"#, TAGS_SCHEMA_VERSION)
 }
 
+#[cfg(not(test))]
 fn documents_root() -> PathBuf {
   // ~/Documents/AudioTagger
-  let base = document_dir()
+  let base = tauri::api::path::document_dir()
     .unwrap_or(std::env::current_dir().unwrap())
     .join("AudioTagger");
   let _ = std::fs::create_dir_all(&base);
   base
 }
+#[cfg(test)]
+fn documents_root() -> PathBuf { test_support::documents_root() }
 
 
 fn sanitize_bank(name: &str) -> String {
@@ -111,6 +308,63 @@ struct Settings {
   show_genre: bool,
   show_comment: bool,
   instant_playback: bool,
+  /// Simultaneous `/audio` streams before the media server answers 429.
+  max_media_streams: usize,
+  /// Streams of a file being saved: "wait" briefly, then close them, or "close" at once (see `media_streams`).
+  stream_write_policy: media_streams::StreamWritePolicy,
+  /// Serve the token-guarded `/api/*` endpoints for external tools.
+  api_enabled: bool,
+  /// Applied to tags added through the merge commands (see `tag_policy`).
+  tag_policy: tag_policy::TagPolicy,
+  /// Numeric- and accent-aware file ordering; off = plain lowercase order.
+  sort_locale_natural: bool,
+  /// Searches and suggestions match "senorita" to "Señorita"; off keeps accents distinct (see `text_fold`).
+  accent_folding: bool,
+  /// Extensions listed by scans and the watcher (see `formats`).
+  extensions: Vec<String>,
+  /// Gitignore-style patterns left out of every scan, under `.taggerignore` files (see `ignore_files`).
+  scan_excludes: Vec<String>,
+  /// Batches over more files than this snapshot their targets first (see `snapshots`).
+  snapshot_threshold: usize,
+  snapshot_retention_days: u32,
+  /// MP3 saves keep a small fixed ID3 padding and rewrite in place when they can (see `id3_padding`).
+  compact_padding: bool,
+  /// Session log verbosity; `debug` adds per-command timings.
+  log_level: LogLevel,
+  /// Scans sniff each file's content and flag extensions that don't match it.
+  verify_extensions_on_scan: bool,
+  /// Read back saved fields: "network" (network mounts only), "always" or "off".
+  verify_writes: write_verify::VerifyWrites,
+  /// Scan the last opened folder when the app starts.
+  reopen_last_folder: bool,
+  /// Extension -> tag types to read the comment from, first wins (see `comment_precedence`).
+  comment_precedence: HashMap<String, Vec<String>>,
+  /// Extension -> tag types every write goes to, in order, instead of the built-in ones (see `tag_targets`).
+  write_targets: HashMap<String, Vec<String>>,
+  /// Every edit also writes a TAGGED_AT time to the file (see `tagged_at`).
+  embed_tagging_timestamp: bool,
+  /// "light" turns off background fills and slows disk churn (see `profile`).
+  operation_profile: profile::OperationProfile,
+  /// Values too long for a tag type: "reject", "truncate" or "skip" (see `field_limits`).
+  field_limit_strategy: field_limits::LimitStrategy,
+  /// Where `reconcile_tag_storage` puts a file's tags: "comment" or "field" (see `tag_storage`).
+  tag_storage: tag_storage::TagStorage,
+  /// OS notification when a job that ran `notify_after_secs` or longer ends in the background.
+  notifications_enabled: bool,
+  notify_after_secs: u32,
+  /// Embedded pictures read per file before the rest are skipped (see `picture_budget`).
+  picture_budget_mb: u64,
+  /// Idle time before caches are pruned and trimmed to `cache_budgets`; 0 = never (see `maintenance`).
+  maintenance_idle_minutes: u32,
+  cache_budgets: maintenance::CacheBudgets,
+  /// Saves through a temp file keep the original's extended attributes (see `xattrs`).
+  preserve_xattrs: bool,
+  /// Tag edits keep the Finder tags of the hashtags in `finder_tag_colors` in step (macOS).
+  sync_finder_tags: bool,
+  /// Hashtag -> Finder colour, 0-7 (see `xattrs`).
+  finder_tag_colors: HashMap<String, u8>,
+  /// Answers bigger than this are read back in chunks (see `payload_guard`).
+  ipc_payload_cap_mb: u64,
 }
 
 impl Default for Settings {
@@ -121,6 +375,36 @@ impl Default for Settings {
       show_genre: true,
       show_comment: true,
       instant_playback: false,
+      max_media_streams: 4,
+      stream_write_policy: media_streams::StreamWritePolicy::Wait,
+      api_enabled: false,
+      tag_policy: tag_policy::TagPolicy::default(),
+      sort_locale_natural: true,
+      accent_folding: true,
+      extensions: formats::default_extensions(),
+      scan_excludes: Vec::new(),
+      snapshot_threshold: 20,
+      snapshot_retention_days: 30,
+      compact_padding: false,
+      log_level: LogLevel::Info,
+      verify_extensions_on_scan: false,
+      verify_writes: write_verify::VerifyWrites::Network,
+      reopen_last_folder: true,
+      comment_precedence: HashMap::new(),
+      write_targets: HashMap::new(),
+      embed_tagging_timestamp: false,
+      operation_profile: profile::OperationProfile::Full,
+      field_limit_strategy: field_limits::LimitStrategy::Truncate,
+      tag_storage: tag_storage::TagStorage::Comment,
+      notifications_enabled: true,
+      notify_after_secs: 30,
+      picture_budget_mb: picture_budget::DEFAULT_BUDGET_MB,
+      maintenance_idle_minutes: maintenance::DEFAULT_IDLE_MINUTES,
+      cache_budgets: maintenance::CacheBudgets::default(),
+      preserve_xattrs: cfg!(target_os = "macos"),
+      sync_finder_tags: false,
+      finder_tag_colors: HashMap::new(),
+      ipc_payload_cap_mb: payload_guard::DEFAULT_CAP_MB,
     }
   }
 }
@@ -130,6 +414,19 @@ impl Default for Settings {
 struct Prefs {
   last_used_bank: Option<String>,
   settings: Option<Settings>,
+  // Folders the user explicitly (re)granted access to via the picker.
+  #[serde(default)]
+  granted_folders: Vec<String>,
+  #[serde(default)]
+  inbox_rules: Vec<inbox::InboxRule>,
+  #[serde(default)]
+  comment_templates: Vec<comment_template::SavedTemplate>,
+  /// Named root sets, most recently used first.
+  #[serde(default)]
+  workspaces: Vec<library::Workspace>,
+  /// Folder reopened on launch (see startup_scan.rs).
+  #[serde(default)]
+  last_folder: Option<String>,
 }
 
 
@@ -143,7 +440,7 @@ fn load_prefs() -> Prefs {
       if s.trim_start().starts_with('{') {
         serde_json::from_str::<Prefs>(&s).unwrap_or_default()
       } else {
-        Prefs { last_used_bank: Some(s.trim().to_string()), settings: None }
+        Prefs { last_used_bank: Some(s.trim().to_string()), ..Default::default() }
       }
     }
     Err(_) => Prefs::default(),
@@ -189,12 +486,47 @@ fn read_settings() -> Result<Settings, String> {
 }
 
 #[tauri::command]
-fn write_settings(settings: Settings) -> Result<(), String> {
+fn write_settings(mut settings: Settings) -> Result<(), String> {
+  settings.extensions = formats::validate(&settings.extensions)?;
+  settings.scan_excludes = ignore_files::validate(&settings.scan_excludes)?;
+  settings.comment_precedence = comment_precedence::validate(&settings.comment_precedence)?;
+  settings.write_targets = tag_targets::validate(&settings.write_targets)?;
+  apply_runtime_settings(&settings);
   let mut p = load_prefs();
   p.settings = Some(settings);
   save_prefs(&p)
 }
 
+/// Push settings that background subsystems read into their live config.
+fn apply_runtime_settings(s: &Settings) {
+  MAX_STREAMS.store(s.max_media_streams.max(1), Ordering::Relaxed);
+  media_streams::POLICY.store(s.stream_write_policy as u8, Ordering::Relaxed);
+  api::API_ENABLED.store(s.api_enabled, Ordering::Relaxed);
+  tag_policy::set_policy(&s.tag_policy);
+  natural_sort::NATURAL.store(s.sort_locale_natural, Ordering::Relaxed);
+  meta_cache::set_accent_folding(s.accent_folding);
+  snapshots::THRESHOLD.store(s.snapshot_threshold, Ordering::Relaxed);
+  snapshots::RETENTION_DAYS.store(s.snapshot_retention_days, Ordering::Relaxed);
+  id3_padding::COMPACT.store(s.compact_padding, Ordering::Relaxed);
+  LOG_LEVEL.store(s.log_level as u8, Ordering::Relaxed);
+  extension_check::ON_SCAN.store(s.verify_extensions_on_scan, Ordering::Relaxed);
+  write_verify::MODE.store(s.verify_writes as u8, Ordering::Relaxed);
+  comment_precedence::set(&s.comment_precedence);
+  tag_targets::set(&s.write_targets);
+  tagged_at::EMBED.store(s.embed_tagging_timestamp, Ordering::Relaxed);
+  profile::PROFILE.store(s.operation_profile as u8, Ordering::Relaxed);
+  field_limits::STRATEGY.store(s.field_limit_strategy as u8, Ordering::Relaxed);
+  tag_storage::CANONICAL.store(s.tag_storage as u8, Ordering::Relaxed);
+  notifications::set_enabled(s.notifications_enabled);
+  notifications::AFTER_SECS.store(s.notify_after_secs, Ordering::Relaxed);
+  formats::set_enabled(&formats::validate(&s.extensions).unwrap_or_else(|_| formats::default_extensions()));
+  ignore_files::set_excludes(&ignore_files::validate(&s.scan_excludes).unwrap_or_default());
+  picture_budget::BUDGET.store(s.picture_budget_mb.max(1) * 1024 * 1024, Ordering::Relaxed);
+  maintenance::configure(s.maintenance_idle_minutes, s.cache_budgets);
+  xattrs::configure(s.preserve_xattrs, s.sync_finder_tags, &s.finder_tag_colors);
+  payload_guard::CAP.store(s.ipc_payload_cap_mb.max(1) * 1024 * 1024, Ordering::Relaxed);
+}
+
 
 #[tauri::command]
 fn init_session() -> Result<(), String> {
@@ -204,72 +536,225 @@ fn init_session() -> Result<(), String> {
   *LOG_PATH.lock() = Some(p.clone());
   let mut f = fs::File::create(&p).map_err(|e| e.to_string())?;
   writeln!(f, "session_start {}", Local::now().to_rfc3339()).map_err(|e| e.to_string())?;
+  for line in EARLY_LOG.lock().drain(..) { log_line(&line); }
+  log_line(&format!("operation_profile {}", profile::current().token()));
   Ok(())
 }
 
-fn log_line(s: &str) {
-  if let Some(p) = LOG_PATH.lock().clone() { if let Ok(mut f) = fs::OpenOptions::new().append(true).open(p) { let _ = writeln!(f, "{} {}", Local::now().format("%H:%M:%S"), s); } }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LogLevel { Error, Warn, #[default] Info, Debug }
+
+impl LogLevel {
+  fn token(self) -> &'static str {
+    match self { LogLevel::Error => "ERROR", LogLevel::Warn => "WARN", LogLevel::Info => "INFO", LogLevel::Debug => "DEBUG" }
+  }
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+fn log_enabled(level: LogLevel) -> bool { level as u8 <= LOG_LEVEL.load(Ordering::Relaxed) }
+
+/// One session-log line, `HH:MM:SS LEVEL message`, if `level` is enabled.
+fn log(level: LogLevel, s: &str) {
+  if !log_enabled(level) { return; }
+  if cli::ACTIVE.load(Ordering::Relaxed) { eprintln!("{} {}", level.token(), s); return; }
+  if let Some(p) = LOG_PATH.lock().clone() { if let Ok(mut f) = fs::OpenOptions::new().append(true).open(p) { let _ = writeln!(f, "{} {} {}", Local::now().format("%H:%M:%S"), level.token(), s); } }
 }
 
+fn log_line(s: &str) { log(LogLevel::Info, s); }
+
+/// Times one command into `perf`, with debug-level `cmd_start` / `cmd_end … ms=`
+/// lines around it when that level is on.
+struct CmdSpan { name: String, started: std::time::Instant, logged: bool }
+
+fn command_span(name: &str) -> CmdSpan {
+  maintenance::touch();
+  let logged = log_enabled(LogLevel::Debug);
+  if logged { log(LogLevel::Debug, &format!("cmd_start {}", name)); }
+  CmdSpan { name: name.to_string(), started: std::time::Instant::now(), logged }
+}
+
+impl Drop for CmdSpan {
+  fn drop(&mut self) {
+    let took = self.started.elapsed();
+    perf::record_command(&self.name, took, !std::thread::panicking());
+    if self.logged { log(LogLevel::Debug, &format!("cmd_end {} ms={}", self.name, took.as_millis())); }
+  }
+}
+
+/// Async commands return to the invoke handler before they run, so they open
+/// their own span; every other command is timed by the handler wrapper.
+const ASYNC_COMMANDS: &[&str] = &[
+  "open_folder_verified", "verify_folder_unchanged", "tag_usage_stats", "convert_files", "export_json", "quarantine_bad_files",
+  "scan_folders", "media_url_for_path", "preload_app_state", "export_tag_manifest", "apply_tag_manifest", "restore_snapshot",
+  "find_tag_filename_conflicts", "toggle_tag_smart", "normalize_existing_tags", "assign_track_numbers", "retry_volume",
+  "render_waveform_image", "export_selection_zip", "verify_extensions", "fix_extension",
+  "find_year_issues", "fix_years", "suggest_artwork", "apply_folder_artwork", "find_already_owned", "retry_now", "verify_session_writes", "reapply_session_writes", "folder_tag_size_report",
+  "remove_tags_soft", "restore_removed_tag", "export_audit_log", "prune_audit_log", "full_text_search", "read_metadata_deep", "compare_folders", "probe_file", "workspace_stats", "preview_cue_points",
+  "create_support_bundle",
+  "encoding_info",
+  "suggest_tags",
+  "scan_embedded_pictures",
+  "keep_first_front_cover",
+  "apply_preset",
+  "import_tracklist",
+  "tag_matched_tracks",
+  "run_maintenance_now",
+  "audit_tag_storage",
+  "reconcile_tag_storage",
+  "get_preview_history",
+  "validate_export",
+  "rename_tag_in_folder",
+  "count_tag_occurrences",
+  "remove_mp3gain_undo",
+];
+
 #[tauri::command]
-fn log_event(message: String) { log_line(&message); }
+fn log_event(message: String, level: Option<LogLevel>) { log(level.unwrap_or_default(), &message); }
 
+/// Change the session log level now and keep it in Settings.
 #[tauri::command]
-fn choose_folder() -> Option<String> { FileDialogBuilder::new().pick_folder().map(|p| p.to_string_lossy().to_string()) }
+fn set_log_level(level: LogLevel) -> Result<(), String> {
+  let mut p = load_prefs();
+  p.settings.get_or_insert_with(Settings::default).log_level = level;
+  save_prefs(&p)?;
+  LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+  log_line(&format!("log_level {}", level.token()));
+  Ok(())
+}
 
-fn supported_ext(p: &Path) -> bool {
-  if let Some(ext) = p.extension().and_then(|e| e.to_str()) { matches!(ext.to_lowercase().as_str(), "mp3"|"flac"|"wav"|"aiff"|"aif"|"m4a") } else { false }
+/// Switch profiles live. Running background fills stop at their next file;
+/// batch reads and store flushes pick it up on their next start.
+#[tauri::command]
+fn set_operation_profile(profile: profile::OperationProfile) -> Result<(), String> {
+  let mut p = load_prefs();
+  p.settings.get_or_insert_with(Settings::default).operation_profile = profile;
+  save_prefs(&p)?;
+  profile::PROFILE.store(profile as u8, Ordering::Relaxed);
+  log_line(&format!("operation_profile {}", profile.token()));
+  Ok(())
 }
 
 #[tauri::command]
-fn scan_folder(path: String) -> Result<Vec<SimpleFile>, String> {
-  let mut out = vec![];
-  for entry in fs::read_dir(PathBuf::from(&path)).map_err(|e| e.to_string())? { let e = entry.map_err(|e| e.to_string())?; let p = e.path(); if p.is_file() && supported_ext(&p) { out.push(SimpleFile{ path: p.to_string_lossy().to_string(), file_name: p.file_name().unwrap().to_string_lossy().to_string() }) } }
-  out.sort_by(|a,b| a.file_name.to_lowercase().cmp(&b.file_name.to_lowercase()));
+fn choose_folder() -> Option<String> { FileDialogBuilder::new().pick_folder().map(|p| p.to_string_lossy().to_string()) }
+
+fn supported_ext(p: &Path) -> bool { formats::is_enabled(&ext_lower(p)) }
+
+#[tauri::command]
+fn reauthorize_folder(path: String) -> Result<Option<String>, String> {
+  // Re-prompt anchored at the old location; picking the folder in the system
+  // dialog is what restores access on macOS. Security-scoped bookmarks would be
+  // stored next to this record once we ship sandboxed builds.
+  let anchor = PathBuf::from(&path);
+  let start = if anchor.is_dir() { anchor } else { anchor.parent().map(|p| p.to_path_buf()).unwrap_or(anchor) };
+  let picked = FileDialogBuilder::new().set_directory(&start).pick_folder();
+  let Some(picked) = picked else { return Ok(None) };
+  let picked = picked.to_string_lossy().to_string();
+  let mut p = load_prefs();
+  p.granted_folders.retain(|f| f != &picked);
+  p.granted_folders.insert(0, picked.clone());
+  p.granted_folders.truncate(20);
+  save_prefs(&p)?;
+  log_line(&format!("reauthorize_folder {}", picked));
+  Ok(Some(picked))
+}
+
+fn simple_file(p: &Path) -> SimpleFile {
+  let supported = supported_ext(p);
+  SimpleFile {
+    path: p.to_string_lossy().to_string(),
+    file_name: p.file_name().unwrap().to_string_lossy().to_string(),
+    supported,
+    health: if supported { file_health::check(p) } else { file_health::Health::ok() },
+  }
+}
+
+/// Supported files directly in `path`, in display order; warms the metadata cache.
+fn list_folder(path: String, include_unsupported: bool) -> Result<Vec<SimpleFile>, CmdError> {
+  let out = read_folder(&path, include_unsupported)?;
+  meta_cache::refresh_in_background(out.iter().filter(|f| f.supported).map(|f| PathBuf::from(&f.path)).collect());
   Ok(out)
 }
 
-fn read_picture_data_url(tf: &lofty::TaggedFile) -> Option<String> {
-  if let Some(tag) = tf.primary_tag() {
-    for pic in tag.pictures() {
-      if pic.pic_type() == PictureType::CoverFront || pic.pic_type() == PictureType::Other {
-        let mime = pic.mime_type().map(|m| m.to_string()).unwrap_or("image/jpeg".into());
-        let b64 = general_purpose::STANDARD.encode(pic.data());
-        return Some(format!("data:{};base64,{}", mime, b64)); 
-      } 
-    }
+/// `list_folder` without the cache warm-up, for one-shot callers (CLI mode).
+fn read_folder(path: &str, include_unsupported: bool) -> Result<Vec<SimpleFile>, CmdError> {
+  let dir = PathBuf::from(path);
+  volumes::register_root(&dir);
+  portable::register(&dir);
+  let mut out: Vec<SimpleFile> = folder_entries(&dir, include_unsupported)?.iter().map(|p| simple_file(p)).collect();
+  out.sort_by_cached_key(|f| natural_sort::sort_key(&f.file_name));
+  Ok(out)
+}
+
+/// The files a listing of `dir` shows, unsorted: supported ones not kept out
+/// by `ignore_files`; `include_unsupported` adds other media files (see
+/// `formats::is_media`).
+fn folder_entries(dir: &Path, include_unsupported: bool) -> Result<Vec<PathBuf>, CmdError> {
+  let rules = ignore_files::Rules::for_root(dir);
+  let listed = |p: &Path| (supported_ext(p) || include_unsupported && formats::is_media(&ext_lower(p))) && rules.ignored_by(p, false).is_none();
+  let mut out = vec![];
+  for entry in fs::read_dir(long_paths::extended(dir)).map_err(|e| CmdError::from_io(dir, &e))? {
+    let e = entry.map_err(|e| CmdError::from_io(dir, &e))?;
+    let p = long_paths::simplified(&e.path()).into_owned();
+    if e.file_type().is_ok_and(|t| t.is_file()) && listed(&p) { out.push(p) }
   }
-  None
+  Ok(out)
 }
 
+/// The folder the user opened; it becomes the one reopened on launch.
 #[tauri::command]
-fn read_metadata(path: String) -> Result<TrackMeta, String> {
-  use lofty::{ItemKey, TagType};
-  use std::path::PathBuf;
+fn scan_folder(path: String, include_unsupported: Option<bool>) -> Result<payload_guard::Guarded<Vec<SimpleFile>>, CmdError> {
+  startup_scan::supersede(&path);
+  let out = list_folder(path.clone(), include_unsupported.unwrap_or(false))?;
+  folder_watch::watch(&path);
+  startup_scan::remember(&path);
+  Ok(payload_guard::guard("scan_folder", out)?)
+}
 
+fn front_cover(tf: &lofty::TaggedFile) -> Option<&lofty::Picture> {
+  tf.primary_tag()?.pictures().iter().find(|pic| pic.pic_type() == PictureType::CoverFront || pic.pic_type() == PictureType::Other)
+}
+
+fn read_picture_data_url(tf: &lofty::TaggedFile) -> Option<String> {
+  let pic = front_cover(tf)?;
+  let mime = pic.mime_type().map(|m| m.to_string()).unwrap_or("image/jpeg".into());
+  let b64 = general_purpose::STANDARD.encode(pic.data());
+  Some(format!("data:{};base64,{}", mime, b64))
+}
+
+#[tauri::command]
+fn read_metadata(path: String) -> Result<TrackMeta, CmdError> {
   let p = PathBuf::from(&path);
-  let tf = lofty::read_from_path(&p).map_err(|e| e.to_string())?;
+  let (tf, truncated) = picture_budget::read(&p).map_err(|e| CmdError::from_lofty(&p, &e))?;
+  let mut meta = track_meta_from(&path, &tf, true);
+  meta.pictures_truncated = truncated;
+  (meta.last_touched_by_app, meta.externally_modified_since) = touched::status(&p, &tf);
+  meta_cache::store(&p, &tf);
+  Ok(meta)
+}
 
-  // Prefer the tag types that Rekordbox/Engine DJ use for each format.
-  let ext = p
-    .extension()
-    .and_then(|s| s.to_str())
-    .unwrap_or("")
-    .to_ascii_lowercase();
+/// Comment: try the comment precedence order; if missing, fall back to primary.
+fn read_comment(tf: &lofty::TaggedFile, p: &Path) -> String {
+  for tt in comment_precedence::order_for(p) {
+    if let Some(tag) = tf.tag(tt) {
+      if let Some(s) = tag.get_string(&ItemKey::Comment) {
+        return s.to_string();
+      }
+    }
+  }
+  tf.primary_tag()
+    .and_then(|tag| tag.get_string(&ItemKey::Comment))
+    .map(|s| s.to_string())
+    .unwrap_or_default()
+}
 
-  let order: &[TagType] = match ext.as_str() {
-    "mp3" | "aif" | "aiff" => &[TagType::Id3v2],
-    "flac" => &[TagType::VorbisComments],
-    "m4a" | "mp4" | "alac" => &[TagType::Mp4Ilst],
-    "wav" => &[TagType::RiffInfo, TagType::Id3v2],
-    _ => &[],
-  };
+/// Build TrackMeta from an already-parsed file.
+fn track_meta_from(path: &str, tf: &lofty::TaggedFile, with_picture: bool) -> TrackMeta {
+  let p = Path::new(path);
 
-  // Helper: get the first available tag in our preferred order, else primary.
-  let preferred_tag = order
-    .iter()
-    .find_map(|tt| tf.tag(*tt))
-    .or_else(|| tf.primary_tag());
+  // First available tag in our preferred order, else primary.
+  let preferred_tag = preferred_tag(tf, p);
 
   // Fields from the preferred tag (with graceful fallback).
   let title = preferred_tag
@@ -285,72 +770,119 @@ fn read_metadata(path: String) -> Result<TrackMeta, String> {
   let genre = preferred_tag
     .and_then(|t| t.genre().map(|s| s.to_string()));
 
-  // Comment: try preferred order; if missing, fall back to primary.
-  let mut comment: Option<String> = None;
-  for tt in order {
-    if let Some(tag) = tf.tag(*tt) {
-      if let Some(s) = tag.get_string(&ItemKey::Comment) {
-        comment = Some(s.to_string());
-        break;
-      }
-    }
-  }
-  if comment.is_none() {
-    if let Some(tag) = tf.primary_tag() {
-      if let Some(s) = tag.get_string(&ItemKey::Comment) {
-        comment = Some(s.to_string());
-      }
-    }
-  }
-  let comment = comment.unwrap_or_default();
+  let release_date = preferred_tag.and_then(dates::release_date);
+  let original_date = preferred_tag.and_then(dates::original_date);
+
+  let comment = read_comment(tf, p);
+  let comment_conflicts = comment_precedence::conflicts(tf, p, &comment);
 
   // Picture & format
-  let pic = read_picture_data_url(&tf);
+  let pic = if with_picture { read_picture_data_url(tf) } else { None };
   let format = p
     .extension()
     .and_then(|e| e.to_str())
     .map(|s| s.to_uppercase());
 
-  Ok(TrackMeta {
-    path: path.clone(),
+  let (encoder, vbr) = meta_cache::encoder(p, tf);
+
+  TrackMeta {
+    path: path.to_string(),
     file_name: p
       .file_name()
       .map(|s| s.to_string_lossy().to_string())
-      .unwrap_or_else(|| path.clone()),
+      .unwrap_or_else(|| path.to_string()),
     title,
     artists,
     genre,
     comment,
     picture_data_url: pic,
     format,
-  })
+    release_date,
+    original_date,
+    last_touched_by_app: None,
+    externally_modified_since: false,
+    comment_conflicts,
+    tagged_at: tagged_at::of_file(tf),
+    locked_fields: field_locks::locked(p).into_iter().map(|f| f.name()).collect(),
+    color_label: color_label::of_file(tf),
+    compilation: preferred_tag.and_then(compilation::of_tag),
+    encoder,
+    vbr,
+    pictures_truncated: false,
+  }
 }
 
-#[inline]
-fn save_tagged_file_to_path(tf: &lofty::TaggedFile, path: &std::path::Path) -> Result<(), String> {
-  <lofty::TaggedFile as lofty::AudioFile>::save_to_path(tf, path)
-    .map_err(|e| e.to_string())
+/// `track_meta_from` from a metadata cache entry, without opening the file.
+/// No picture, and no touched record.
+fn track_meta_cached(path: &str, m: &meta_cache::CachedMeta) -> TrackMeta {
+  let p = Path::new(path);
+  TrackMeta {
+    path: path.to_string(),
+    file_name: p.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| path.to_string()),
+    title: m.title.clone(),
+    artists: m.artist.iter().cloned().collect(),
+    genre: m.genre.clone(),
+    comment: m.comment.clone(),
+    picture_data_url: None,
+    format: p.extension().and_then(|e| e.to_str()).map(|s| s.to_uppercase()),
+    release_date: m.release_date.clone(),
+    original_date: m.original_date.clone(),
+    last_touched_by_app: None,
+    externally_modified_since: false,
+    comment_conflicts: m.comment_conflicts,
+    tagged_at: m.tagged_at.clone(),
+    locked_fields: field_locks::locked(p).into_iter().map(|f| f.name()).collect(),
+    color_label: m.color_label.clone(),
+    compilation: m.compilation,
+    encoder: m.encoder.clone(),
+    vbr: m.vbr,
+    pictures_truncated: false,
+  }
 }
 
+/// Semicolon-separated tokens of a comment, as the frontend's `splitTokens`.
+fn split_comment_tokens(comment: &str) -> Vec<String> {
+  comment.split(';').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string()).collect()
+}
 
+#[inline]
+fn save_tagged_file_to_path(tf: &lofty::TaggedFile, path: &std::path::Path) -> Result<(), String> {
+  formats::ensure_writable(path)?;
+  archive::guard(path).map_err(|e| e.to_string())?;
+  media_streams::before_replace(path);
+  let path = &*long_paths::extended(path);
+  let started = Instant::now();
+  let res = (|| {
+    if let Some(res) = id3_padding::save_compact(tf, path) { return res; }
+    // Reading and rewriting the file: a failure is the drive's.
+    let original = aiff_chunks::prepare_write(path).map_err(io::Error::other)?;
+    <lofty::TaggedFile as lofty::AudioFile>::save_to_path(tf, path).inspect_err(|_| {
+      // prepare_write already rewrote the file; put the original back.
+      if let Some(bytes) = &original { let _ = write_atomic(path, bytes); }
+    })
+  })();
+  volume_health::note(path, volume_health::Op::Write, started.elapsed(), &res);
+  res.map_err(|e| e.to_string())
+}
 
+/// `lofty::read_from_path`, plus AIFF files with chunk quirks (see `aiff_chunks`).
+fn read_tagged(p: impl AsRef<Path>) -> lofty::error::Result<lofty::TaggedFile> {
+  // In shadow mode a file that has a copy reads as the copy.
+  let copy = shadow::copy_of(p.as_ref());
+  let p = &*long_paths::extended(copy.as_deref().unwrap_or(p.as_ref()));
+  let started = Instant::now();
+  let res = aiff_chunks::read_repaired(p).unwrap_or_else(|| lofty::read_from_path(p));
+  volume_health::note(p, volume_health::Op::Read, started.elapsed(), &res);
+  res
+}
 
-#[tauri::command]
-fn write_comment(path: String, comment: String) -> Result<(), String> {
-  use std::path::PathBuf;
-
-  let p = PathBuf::from(&path);
-  let mut tf: lofty::TaggedFile = lofty::read_from_path(&p).map_err(|e| e.to_string())?;
 
 
-  // choose tag types by format
-  let ext = p
-    .extension()
-    .and_then(|s| s.to_str())
-    .unwrap_or("")
-    .to_ascii_lowercase();
 
-  let targets: &[TagType] = match ext.as_str() {
+/// Tag types we write to per extension (what Rekordbox/Engine DJ use).
+/// Empty means "whatever the file's primary tag type is".
+fn tag_types_for_ext(ext: &str) -> &'static [TagType] {
+  match ext {
     // MP3 / AIFF -> ID3v2 COMM
     "mp3" | "aif" | "aiff" => &[TagType::Id3v2],
     // FLAC -> Vorbis COMMENT=
@@ -359,48 +891,276 @@ fn write_comment(path: String, comment: String) -> Result<(), String> {
     "m4a" | "mp4" | "alac" => &[TagType::Mp4Ilst],
     // WAV -> RIFF INFO ICMT and ID3v2 (write both)
     "wav" => &[TagType::RiffInfo, TagType::Id3v2],
-    // fallback to the file’s primary tag type (no unwrap_or here)
+    // Musepack -> APEv2
+    "mpc" => &[TagType::Ape],
     _ => &[],
-  };
+  }
+}
 
-  let mut wrote_any = false;
+/// Read precedence per extension. Older MP3 rips (foobar2000 era) may carry
+/// APEv2 alongside or instead of ID3v2, so it goes before the ID3v1 fallback.
+/// User write targets go first (see `tag_targets`).
+fn read_order_for_ext(ext: &str) -> Vec<TagType> {
+  let builtin = match ext {
+    "mp3" => &[TagType::Id3v2, TagType::Ape, TagType::Id3v1],
+    other => tag_types_for_ext(other),
+  };
+  let mut order = tag_targets::custom(ext).unwrap_or_default();
+  for tt in builtin { if !order.contains(tt) { order.push(*tt); } }
+  order
+}
 
-  // write to all targeted tag types (creating if absent)
-  for tt in targets {
-    if tf.tag(*tt).is_none() {
-      tf.insert_tag(Tag::new(*tt));
-    }
-    if let Some(tag) = tf.tag_mut(*tt) {
-      tag.insert_text(ItemKey::Comment, comment.clone());
-      wrote_any = true;
+/// Tag types a write to this file will touch, plus a note when something
+/// other than the per-extension default applies: the `write_targets`
+/// setting, or that we never create APE on MP3 (players get confused), but
+/// an APE-only MP3 gets its APE tag updated.
+fn write_strategy(tf: &lofty::TaggedFile, p: &Path) -> (Vec<TagType>, Option<String>) {
+  let ext = ext_lower(p);
+  if let Some(mut targets) = tag_targets::custom(&ext) {
+    let names: Vec<String> = targets.iter().map(|tt| inspect::tag_type_name(*tt)).collect();
+    let mut reason = format!("write targets setting for .{}: {}", ext, names.join(", "));
+    // lofty 0.18 panics adding text chunks to an AIFF that has none.
+    if tf.tag(TagType::AiffText).is_none() && targets.contains(&TagType::AiffText) {
+      targets.retain(|tt| *tt != TagType::AiffText);
+      reason.push_str(" (AIFF text chunks are only updated, not created)");
     }
+    if !targets.is_empty() { return (targets, Some(reason)); }
   }
-
+  if ext == "mp3" && tf.tag(TagType::Ape).is_some() && tf.tag(TagType::Id3v2).is_none() {
+    return (vec![TagType::Ape], Some("APE-only MP3: updating the existing APEv2 tag instead of creating ID3v2".into()));
+  }
+  let targets = tag_types_for_ext(&ext).to_vec();
   // if the format branch didn't match, write to the primary tag type
-  if !wrote_any {
-    let tt = tf.primary_tag_type(); // returns TagType directly
+  if targets.is_empty() {
+    return (vec![tf.primary_tag_type()], Some("no mapping for this extension: writing the file's primary tag type".into()));
+  }
+  (targets, None)
+}
+
+fn write_targets(tf: &lofty::TaggedFile, p: &Path) -> Vec<TagType> { write_strategy(tf, p).0 }
+
+fn ext_lower(p: &Path) -> String {
+  p.extension().and_then(|s| s.to_str()).unwrap_or("").to_ascii_lowercase()
+}
+
+/// First tag present in our preferred order for this file, else the primary tag.
+fn preferred_tag<'a>(tf: &'a lofty::TaggedFile, p: &Path) -> Option<&'a Tag> {
+  read_order_for_ext(&ext_lower(p))
+    .iter()
+    .find_map(|tt| tf.tag(*tt))
+    .or_else(|| tf.primary_tag())
+}
+
+/// What a tracked edit did. `no_op` when every field already held the new
+/// value and the save was skipped; `limited` lists values cut or left out
+/// per tag type (see `field_limits`); `skipped_locked` the fields the edit
+/// would have changed but are locked (see `field_locks`); `shadowed` when
+/// shadow mode sent the write to a copy; `revision` is the file's touched
+/// revision after the edit, as sent with `track-updated` (0 for copies).
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteOutcome {
+  no_op: bool,
+  revision: u64,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<field_limits::FieldLimitHit>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<field_locks::LockedField>,
+  /// Written to a copy instead (see `shadow`).
+  #[serde(flatten)]
+  shadow: shadow::Mark,
+}
+
+/// The single write path for tag edits: read, apply `f` to every targeted tag
+/// (creating missing ones), save. Serialized by WRITE_LOCK. An edit that
+/// changes nothing skips the save (and the TAGGED_AT stamp) but still
+/// announces `track-updated`, so a UI that raced two identical writes settles.
+/// In shadow mode the copy is edited and none of the bookkeeping happens.
+fn edit_tags<F: FnMut(&mut Tag)>(p: &Path, f: F) -> Result<WriteOutcome, CmdError> {
+  edit_tags_with(p, |tf| Ok(write_targets(tf, p)), f)
+}
+
+/// `edit_tags` over the tag types `pick` chooses from the file as read
+/// (under WRITE_LOCK) instead of `write_targets`; missing ones are created.
+/// For the edits that reach past the usual targets: making every comment
+/// agree, the APE migration. A tag the edit leaves empty is stripped.
+fn edit_tags_with<P, F>(p: &Path, pick: P, f: F) -> Result<WriteOutcome, CmdError>
+where
+  P: FnOnce(&lofty::TaggedFile) -> Result<Vec<TagType>, CmdError>,
+  F: FnMut(&mut Tag),
+{
+  if let Some(copy) = shadow::target(p)? {
+    let (_, mut outcome) = edit_tags_inner(p, &copy, pick, f, tagged_at::now().as_deref(), false)?;
+    outcome.shadow = shadow::Mark::of(Some(&copy));
+    return Ok(outcome);
+  }
+  let (tf, outcome) = edit_tags_inner(p, p, pick, f, tagged_at::now().as_deref(), true)?;
+  meta_cache::store(p, &tf);
+  Ok(outcome)
+}
+
+/// `edit_tags` without the touched record, for scratch copies (exports).
+/// Saves are read back when `write_verify` applies to `p`.
+fn edit_tags_untracked<F: FnMut(&mut Tag)>(p: &Path, f: F) -> Result<WriteOutcome, CmdError> {
+  edit_tags_inner(p, p, |tf| Ok(write_targets(tf, p)), f, None, false).map(|(_, outcome)| outcome)
+}
+
+/// Same items and pictures, in any order (lofty moves replaced items to the end).
+fn same_fields(a: &Tag, b: &Tag) -> bool {
+  a.item_count() == b.item_count()
+    && a.picture_count() == b.picture_count()
+    && a.items().all(|i| b.items().any(|j| i == j))
+    && a.pictures().iter().all(|pic| b.pictures().contains(pic))
+}
+
+/// Returns the file as edited and what the edit did. `at` is where the file
+/// is read and saved: `p`, or its shadow copy. With `track` the revision is
+/// taken and `track-updated` queued before WRITE_LOCK is released, so
+/// revisions follow the order the saves happened in.
+fn edit_tags_inner<P, F>(p: &Path, at: &Path, pick: P, mut f: F, stamp: Option<&str>, track: bool) -> Result<(lofty::TaggedFile, WriteOutcome), CmdError>
+where
+  P: FnOnce(&lofty::TaggedFile) -> Result<Vec<TagType>, CmdError>,
+  F: FnMut(&mut Tag),
+{
+  formats::ensure_writable(p)?;
+  // Outside the lock: a save waiting out a player doesn't hold up the others.
+  media_streams::wait_for(at);
+  let _guard = WRITE_LOCK.lock();
+  let mut tf: lofty::TaggedFile = read_tagged(at).map_err(|e| e.to_string())?;
+  let verify = write_verify::applies(p);
+  let locks = field_locks::locked(p);
+  let mut expected = Vec::new();
+  // Each changed tag type, as it was before the edit.
+  let mut edited: Vec<(TagType, Tag)> = Vec::new();
+  let mut out = WriteOutcome::default();
+  let path = p.to_string_lossy();
+
+  for tt in pick(&tf)? {
     if tf.tag(tt).is_none() {
       tf.insert_tag(Tag::new(tt));
     }
     if let Some(tag) = tf.tag_mut(tt) {
-      tag.insert_text(ItemKey::Comment, comment.clone());
+      let before = tag.clone();
+      f(tag);
+      for field in field_locks::enforce(&before, tag, &locks) {
+        if !out.skipped_locked.contains(&field) { out.skipped_locked.push(field); }
+      }
+      out.limited.extend(field_limits::enforce(tt, &before, tag, &path)?);
+      if same_fields(&before, tag) { continue; }
+      // Saved as ID3v2.4 (see `dates`).
+      if tt == TagType::Id3v2 { dates::fold_tdat(tag); }
+      edited.push((tt, before));
     }
   }
+  // The stamp goes on the tags the file is normally written through, not on
+  // others an edit reached into (or emptied).
+  let usual = write_targets(&tf, p);
+  for (tt, before) in &edited {
+    let Some(tag) = tf.tag_mut(*tt) else { continue };
+    if let Some(at) = stamp.filter(|_| usual.contains(tt)) { tagged_at::stamp(tag, at); }
+    if verify { expected.extend(write_verify::changed(before, tag)); }
+  }
+  let changed = !edited.is_empty();
+  out.no_op = !changed;
+  if changed {
+    // save the file (TaggedFile::save_to takes a path; needs AudioFile trait in scope)
+    save_tagged_file_to_path(&tf, at)?;
+    write_verify::verify(at, &expected, || save_tagged_file_to_path(&tf, at))?;
+  }
+  if track {
+    out.revision = if changed {
+      xattrs::sync_finder(p, &tf);
+      touched::record(p, &tf)
+    } else { touched::revision(p) };
+    track_updates::emit(p, &tf, out.revision);
+  }
+  Ok((tf, out))
+}
 
-  // save the file (TaggedFile::save_to takes a path; needs AudioFile trait in scope)
-  save_tagged_file_to_path(&tf, p.as_path())
+/// The standard comment write: every target tag, then an audit entry.
+/// Writes to a removed drive are queued (see volumes.rs) and reported as
+/// `VolumeUnavailable`.
+fn write_comment_as(path: &str, comment: &str, source: audit::Source) -> Result<WriteOutcome, CmdError> {
+  let p = Path::new(path);
+  formats::ensure_writable(p)?;
+  archive::guard(p)?;
+  if let Some(root) = volumes::lost_root(p) { return Err(volumes::queue_comment(path, comment, source, &root)); }
+  let mut old: Option<String> = None;
+  let res = edit_tags(p, |tag| {
+    if old.is_none() { old = tag.get_string(&ItemKey::Comment).map(|s| s.to_string()); }
+    tag.insert_text(ItemKey::Comment, comment.to_string());
+  });
+  let outcome = match res {
+    Ok(o) => o,
+    Err(e) => return Err(match volumes::detect(p) {
+      Some(root) => volumes::queue_comment(path, comment, source, &root),
+      None => e,
+    }),
+  };
+  // A repeated write of the same value isn't a change worth auditing.
+  if !outcome.no_op { audit::record_comment(path, old.as_deref(), Some(comment), source); }
+  Ok(outcome)
+}
 
+#[tauri::command]
+fn write_comment(path: String, comment: String) -> Result<WriteOutcome, CmdError> {
+  write_comment_as(&path, &comment, audit::Source::Manual)
+}
 
+/// Partial metadata update; `None` leaves a field untouched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+struct MetaPatch {
+  title: Option<String>,
+  artist: Option<String>,
+  genre: Option<String>,
+  /// Loose date input, normalized on write; "" clears the field.
+  release_date: Option<String>,
+  original_date: Option<String>,
+  compilation: Option<bool>,
+}
 
+impl MetaPatch {
+  fn is_empty(&self) -> bool {
+    self.title.is_none() && self.artist.is_none() && self.genre.is_none() && self.release_date.is_none() && self.original_date.is_none()
+      && self.compilation.is_none()
+  }
 }
 
+fn apply_meta_patch(p: &Path, patch: &MetaPatch) -> Result<WriteOutcome, String> {
+  if patch.is_empty() { return Ok(WriteOutcome { no_op: true, revision: touched::revision(p), ..Default::default() }); }
+  edit_tags(p, meta_patch_editor(patch)?).map_err(String::from)
+}
 
+/// Tag edit for `patch`. Dates are validated up front so a bad one doesn't
+/// leave a half-applied patch.
+fn meta_patch_editor(patch: &MetaPatch) -> Result<impl FnMut(&mut Tag) + '_, String> {
+  let release = patch.release_date.as_deref().map(dates::date_for_write).transpose()?;
+  let original = patch.original_date.as_deref().map(dates::date_for_write).transpose()?;
+  Ok(move |tag: &mut Tag| {
+    if let Some(v) = &patch.title { tag.set_title(v.clone()); }
+    if let Some(v) = &patch.artist { tag.set_artist(v.clone()); }
+    if let Some(v) = &patch.genre { tag.set_genre(v.clone()); }
+    if let Some(v) = &release { dates::set_date(tag, ItemKey::RecordingDate, v.as_deref()); }
+    if let Some(v) = &original { dates::set_date(tag, ItemKey::OriginalReleaseDate, v.as_deref()); }
+    if let Some(v) = patch.compilation { compilation::set(tag, v); }
+  })
+}
 
 #[tauri::command]
-fn write_tags_file(json: String) -> Result<(), String> {
-  let p = tags_file_path();
-  fs::create_dir_all(p.parent().unwrap()).map_err(|e| e.to_string())?;
-  fs::write(&p, json).map_err(|e| e.to_string())?;
+fn write_metadata(path: String, patch: MetaPatch) -> Result<WriteOutcome, String> {
+  let outcome = apply_meta_patch(Path::new(&path), &patch)?;
+  log_line(&format!("write_metadata path=\"{}\"", path));
+  Ok(outcome)
+}
+
+
+
+#[tauri::command]
+fn write_tags_file(state: tauri::State<'_, AppState>, json: String) -> Result<(), CmdError> {
+  handshake::check_bank_write()?;
+  state.banks.write(&tags_file_path(), &json)?;
   log_line("write_tags_file");
   Ok(())
 }
@@ -424,27 +1184,21 @@ fn list_tag_banks() -> Result<Vec<String>, String> {
   if !out.iter().any(|s| s == "default") {
     out.push("default".into());
   }
-  out.sort();
+  out.sort_by(|a, b| natural_sort::compare_names(a, b));
   Ok(out)
 }
 
 #[tauri::command]
-fn read_tags_file_bank(bank: String) -> Result<String, String> {
-  let path = bank_path(&bank);
-  match fs::read_to_string(&path) {
-    Ok(s) => Ok(s),
-    Err(_) => {
-      let empty = default_tags_json();
-      let _ = fs::write(&path, &empty);
-      Ok(empty)
-    }
-  }
+fn read_tags_file_bank(app: tauri::AppHandle, state: tauri::State<'_, AppState>, bank: String) -> Result<String, CmdError> {
+  let s = state.banks.read_or_create(&bank, &default_tags_json())?;
+  banks::warn_duplicates(&app, &bank, &s);
+  Ok(s)
 }
 
 #[tauri::command]
-fn write_tags_file_bank(bank: String, json: String) -> Result<(), String> {
-  let path = bank_path(&bank);
-  std::fs::write(&path, json).map_err(|e| e.to_string())?;
+fn write_tags_file_bank(state: tauri::State<'_, AppState>, bank: String, json: String) -> Result<(), CmdError> {
+  handshake::check_bank_write()?;
+  state.banks.write_bank(&bank, &json)?;
   // also add to registry if new
   let mut all = read_banks_registry();
   let s = sanitize_bank(&bank);
@@ -490,16 +1244,96 @@ fn add_cors_headers(headers: &mut hyper::HeaderMap) {
 }
 
 
-async fn media_response(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-  let not_found = || {
-    let mut resp = Response::builder()
-      .status(StatusCode::NOT_FOUND)
-      .body(Body::empty())
-      .unwrap();
-    add_cors_headers(resp.headers_mut());
-    resp
-  };
+// ---- stream accounting ----
+static MAX_STREAMS: AtomicUsize = AtomicUsize::new(4);
+static ACTIVE_STREAMS: AtomicUsize = AtomicUsize::new(0);
+static STREAMS_SERVED: AtomicU64 = AtomicU64::new(0);
+static STREAMS_REJECTED: AtomicU64 = AtomicU64::new(0);
+const STREAM_CHUNK: usize = 256 * 1024;
+
+/// One slot of the stream cap; released when the response body is dropped,
+/// which hyper does as soon as the client goes away.
+struct StreamSlot;
+
+impl StreamSlot {
+  fn try_acquire() -> Option<Self> {
+    let max = MAX_STREAMS.load(Ordering::Relaxed);
+    ACTIVE_STREAMS
+      .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| if n < max { Some(n + 1) } else { None })
+      .ok()
+      .map(|_| StreamSlot)
+  }
+}
+
+impl Drop for StreamSlot {
+  fn drop(&mut self) { ACTIVE_STREAMS.fetch_sub(1, Ordering::AcqRel); }
+}
+
+/// Reader that holds a StreamSlot for as long as the body (and file handle)
+/// lives, and ends with an error once a save asks for the file. The stream's
+/// volume health is noted once: failed on a read error, otherwise worked when
+/// it reaches the end or the client goes away.
+struct SlotReader<R> {
+  inner: R,
+  _slot: StreamSlot,
+  stream: media_streams::Registration,
+  /// The file and how long opening it took, until noted.
+  health: Option<(PathBuf, std::time::Duration)>,
+}
 
+impl<R> SlotReader<R> {
+  fn note(&mut self, ok: bool) {
+    if let Some((p, took)) = self.health.take() { volume_health::record(&p, volume_health::Op::Stream, took, ok); }
+  }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SlotReader<R> {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    if self.stream.aborted() { return Poll::Ready(Err(io::Error::other("file is being saved"))); }
+    let before = buf.filled().len();
+    let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+    match &res {
+      Poll::Ready(Err(e)) if volume_health::counts(e) => self.note(false),
+      Poll::Ready(Ok(())) if buf.filled().len() == before && buf.remaining() > 0 => self.note(true),
+      _ => {}
+    }
+    res
+  }
+}
+
+impl<R> Drop for SlotReader<R> {
+  fn drop(&mut self) { self.note(true); }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaServerStats {
+  active_streams: usize,
+  max_streams: usize,
+  streams_served: u64,
+  streams_rejected: u64,
+}
+
+#[tauri::command]
+fn media_server_stats() -> MediaServerStats {
+  MediaServerStats {
+    active_streams: ACTIVE_STREAMS.load(Ordering::Relaxed),
+    max_streams: MAX_STREAMS.load(Ordering::Relaxed),
+    streams_served: STREAMS_SERVED.load(Ordering::Relaxed),
+    streams_rejected: STREAMS_REJECTED.load(Ordering::Relaxed),
+  }
+}
+
+fn not_found() -> Response<Body> {
+  let mut resp = Response::builder()
+    .status(StatusCode::NOT_FOUND)
+    .body(Body::empty())
+    .unwrap();
+  add_cors_headers(resp.headers_mut());
+  resp
+}
+
+async fn media_response(app: tauri::AppHandle, req: Request<Body>) -> Result<Response<Body>, Infallible> {
   // CORS preflight
   if req.method() == Method::OPTIONS {
     let mut resp = Response::builder()
@@ -510,39 +1344,87 @@ async fn media_response(req: Request<Body>) -> Result<Response<Body>, Infallible
     return Ok(resp);
   }
 
-  let uri = req.uri();
-  if uri.path() != "/audio" {
-    return Ok(not_found());
+  if matches!(req.uri().path(), "/now" | "/now.json") {
+    return Ok(now_showing::handle(app, req).await);
+  }
+  if req.uri().path().starts_with("/api/") {
+    return Ok(api::handle(req).await);
+  }
+  if req.uri().path() == "/peaks" {
+    return Ok(peaks::handle(req).await);
   }
 
-  // Extract and decode ?path=...
-  let path = uri
-    .query()
-    .and_then(|q| q.split('&').find(|p| p.starts_with("path=")))
-    .and_then(|kv| kv.split_once('=').map(|(_, v)| v.to_string()))
-    .and_then(|enc| {
-      percent_encoding::percent_decode_str(&enc)
-        .decode_utf8()
-        .ok()
-        .map(|s| s.into_owned())
-    });
+  if req.uri().path() != "/audio" {
+    return Ok(not_found());
+  }
+  Ok(audio_response(&req).await)
+}
 
-  let path = match path {
+/// `/audio?path=`: the file, or the range of it the request asks for.
+async fn audio_response(req: &Request<Body>) -> Response<Body> {
+  let path = match urls::query_param(req.uri(), "path") {
     Some(p) => p,
-    None => return Ok(not_found()),
+    None => return not_found(),
   };
+  let disk_path = long_paths::extended(Path::new(&path)).into_owned();
+
+  if !disk_path.exists() {
+    // Yanked drive: answer 503 right away instead of letting the player retry a 404.
+    if volumes::detect(Path::new(&path)).is_some() {
+      let mut resp = Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::RETRY_AFTER, "5")
+        .body(Body::empty())
+        .unwrap();
+      add_cors_headers(resp.headers_mut());
+      return resp;
+    }
+    return not_found();
+  }
 
-  if !std::path::Path::new(&path).exists() {
-    return Ok(not_found());
+  // A 0-byte file (aborted download): say why instead of handing the player
+  // nothing. Anything else `check` flags may still play, so it is streamed.
+  let health = file_health::check(Path::new(&path));
+  if health.status == file_health::FileStatus::Empty {
+    let mut resp = Response::builder()
+      .status(StatusCode::UNPROCESSABLE_ENTITY)
+      .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+      .body(Body::from(health.reason().to_string()))
+      .unwrap();
+    add_cors_headers(resp.headers_mut());
+    return resp;
   }
 
-  let mut file = match tokio::fs::File::open(&path).await {
+  // Rapid scrubbing opens lots of ranged GETs; past the cap, ask the client to back off.
+  let slot = if req.method() == Method::GET {
+    match StreamSlot::try_acquire() {
+      Some(s) => Some(s),
+      None => {
+        STREAMS_REJECTED.fetch_add(1, Ordering::Relaxed);
+        let mut resp = Response::builder()
+          .status(StatusCode::TOO_MANY_REQUESTS)
+          .header(header::RETRY_AFTER, "1")
+          .body(Body::empty())
+          .unwrap();
+        add_cors_headers(resp.headers_mut());
+        return resp;
+      }
+    }
+  } else {
+    None
+  };
+
+  let opened = Instant::now();
+  let mut file = match tokio::fs::File::open(&disk_path).await {
     Ok(f) => f,
-    Err(_) => return Ok(not_found()),
+    Err(e) => {
+      if volume_health::counts(&e) { volume_health::record(&disk_path, volume_health::Op::Stream, opened.elapsed(), false); }
+      return not_found();
+    }
   };
-  let meta = match tokio::fs::metadata(&path).await {
+  let meta = match tokio::fs::metadata(&disk_path).await {
     Ok(m) => m,
-    Err(_) => return Ok(not_found()),
+    Err(_) => return not_found(),
   };
   let file_len = meta.len();
   let mime = mime_guess::from_path(&path).first_or_octet_stream();
@@ -568,7 +1450,7 @@ async fn media_response(req: Request<Body>) -> Result<Response<Body>, Infallible
           .body(Body::empty())
           .unwrap();
         add_cors_headers(resp.headers_mut());
-        return Ok(resp);
+        return resp;
       }
     }
   }
@@ -585,17 +1467,21 @@ async fn media_response(req: Request<Body>) -> Result<Response<Body>, Infallible
       let cr = format!("bytes {}-{}/{}", start, end, file_len);
       headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&cr).unwrap());
     }
-    return Ok(resp);
+    return resp;
   }
 
   // GET: stream the requested range
   if (file.seek(std::io::SeekFrom::Start(start)).await).is_err() {
-    return Ok(not_found());
+    return not_found();
   }
   let to_read = end - start + 1;
   let reader = tokio::io::AsyncReadExt::take(file, to_read);
-  let stream = tokio_util::io::ReaderStream::new(reader);
+  let Some(slot) = slot else { return not_found() };
+  let health = Some((disk_path.clone(), opened.elapsed()));
+  let reader = SlotReader { inner: reader, _slot: slot, stream: media_streams::register(Path::new(&path)), health };
+  let stream = tokio_util::io::ReaderStream::with_capacity(reader, STREAM_CHUNK);
   let body = Body::wrap_stream(stream);
+  STREAMS_SERVED.fetch_add(1, Ordering::Relaxed);
 
   let mut resp = Response::new(body);
   *resp.status_mut() = status;
@@ -607,22 +1493,28 @@ async fn media_response(req: Request<Body>) -> Result<Response<Body>, Infallible
     let cr = format!("bytes {}-{}/{}", start, end, file_len);
     headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&cr).unwrap());
   }
-  Ok(resp)
+  resp
 }
 
 
 
-async fn start_media_server() -> io::Result<u16> {
+async fn start_media_server(app: tauri::AppHandle) -> io::Result<u16> {
   let std_listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
   let port = std_listener.local_addr()?.port();
   std_listener.set_nonblocking(true)?;
 
-  let make = make_service_fn(|_conn| async {
-    Ok::<_, Infallible>(service_fn(media_response))
+  let make = make_service_fn(move |_conn| {
+    let app = app.clone();
+    async move { Ok::<_, Infallible>(service_fn(move |req| media_response(app.clone(), req))) }
   });
 
+  // Keep connections alive between the webview's ranged requests, but drop
+  // idle/half-dead sockets quickly so their stream slots get released.
   let server = Server::from_tcp(std_listener)
     .map_err(io::Error::other)?
+    .http1_keepalive(true)
+    .tcp_nodelay(true)
+    .tcp_keepalive(Some(std::time::Duration::from_secs(15)))
     .serve(make);
 
   tauri::async_runtime::spawn(async move {
@@ -635,10 +1527,46 @@ async fn start_media_server() -> io::Result<u16> {
 }
 
 
+/// Waits up to ~2 s for the media server during startup; after that the
+/// frontend gets "media server not ready" and retries.
+#[tauri::command]
+async fn media_url_for_path(path: String, state: tauri::State<'_, AppState>) -> Result<String, String> {
+  let _span = command_span("media_url_for_path");
+  for _ in 0..80 {
+    match &*state.media_base.read() {
+      MediaBase::Ready(base) => return Ok(urls::path_url(base, "audio", &path)),
+      MediaBase::Failed(e) => return Err(format!("media server failed to start: {}", e)),
+      MediaBase::Starting => {}
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+  }
+  Err("media server not ready".into())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PreloadedState {
+  settings: Settings,
+  banks: Vec<String>,
+  last_used_bank: Option<String>,
+}
+
+/// Prefs and bank list in one call, read off the main thread after the UI mounts.
 #[tauri::command]
-fn media_url_for_path(path: String, state: tauri::State<AppState>) -> String {
-  let enc = utf8_percent_encode(&path, NON_ALPHANUMERIC).to_string();
-  format!("{}/audio?path={}", state.media_base, enc)
+async fn preload_app_state() -> Result<PreloadedState, String> {
+  let _span = command_span("preload_app_state");
+  tauri::async_runtime::spawn_blocking(|| {
+    let prefs = load_prefs();
+    let state = PreloadedState {
+      settings: prefs.settings.unwrap_or_default(),
+      banks: list_tag_banks()?,
+      last_used_bank: prefs.last_used_bank,
+    };
+    startup_mark("preloaded");
+    Ok(state)
+  })
+  .await
+  .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
@@ -656,32 +1584,185 @@ fn check_bank_available(name: String) -> Result<bool, String> {
 
 
 pub fn main() {
-  tauri::Builder::default()
-    .invoke_handler(tauri::generate_handler![
-      init_session, log_event, choose_folder, scan_folder, read_metadata, write_comment, write_tags_file, media_url_for_path, list_tag_banks, read_tags_file_bank, write_tags_file_bank, read_settings,
+  Lazy::force(&STARTED_AT);
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if args.first().map(String::as_str) == Some("--cli") { std::process::exit(cli::run(&args[1..])); }
+  let handler: fn(tauri::Invoke) = tauri::generate_handler![
+      init_session, preload_app_state, startup_scan::frontend_ready, startup_scan::get_last_folder, api::get_api_info, now_showing::set_now_showing, now_showing::now_page_url, perf::get_perf_metrics, perf::reset_perf_metrics, perf::record_command_failure, log_event, set_log_level, set_operation_profile, choose_folder, reauthorize_folder, scan_folder, library::scan_folders, library::save_workspace, library::list_workspaces, library::delete_workspace, bank_sync::export_bank_changes, bank_sync::apply_bank_changes, read_metadata, write_comment, write_metadata, text_cleanup::cleanup_text_fields, export::export_json, tag_ops::merge_tags, tag_policy::validate_tag, tag_policy::normalize_existing_tags, tag_conflicts::find_tag_filename_conflicts, tag_conflicts::resolve_conflicts, years::find_year_issues, years::fix_years, comment_precedence::resolve_comment_conflict, artwork::suggest_artwork, artwork::apply_folder_artwork, palette::artwork_palette, palette::warm_artwork_palettes, already_owned::find_already_owned, zip_export::export_selection_zip, convert::convert_files, track_numbers::assign_track_numbers, id3_padding::rewrite_with_minimal_padding, touched::forget_touched, tag_ops::toggle_tag_smart, autocomplete::autocomplete, file_health::quarantine_bad_files, extension_check::verify_extensions, extension_check::fix_extension, archive::open_folder_verified, archive::verify_folder_unchanged, archive::close_folder_verified, preview_gain::preview_info_for_path, formats::supported_formats, snapshots::list_snapshots, snapshots::restore_snapshot, inspect::inspect_tags, tag_size::tag_size_report, tag_size::folder_tag_size_report, inspect::write_plan, ape::convert_ape_to_id3, ape::remove_mp3gain_undo,
+  inbox::set_inbox_rule, inbox::set_inbox_rule_enabled, inbox::remove_inbox_rule, inbox::list_inbox_rules, write_tags_file, media_url_for_path, list_tag_banks, read_tags_file_bank, write_tags_file_bank, read_settings,
    write_settings,
    get_last_used_bank,
    set_last_used_bank,
   get_last_used_bank, set_last_used_bank,
-  get_known_banks, check_bank_available,
-
-    ])
+  get_known_banks, check_bank_available, media_server_stats,
+  banks::tag_usage_stats, banks::dedupe_bank, banks::reorder_bank_tags, banks::import_bank_tags, volumes::retry_volume, volumes::pending_writes, portable::list_known_roots, portable::rebase_references, retry_queue::list_retry_queue, retry_queue::retry_now, retry_queue::drop_retry_item,
+  manifest::export_tag_manifest, manifest::apply_tag_manifest,
+  jobs::cancel_job, jobs::list_jobs, waveform_image::render_waveform_image,
+  comment_template::apply_comment_template, comment_template::save_comment_template, comment_template::delete_comment_template, comment_template::list_comment_templates,
+  session_state::save_session_state, session_state::load_session_state, session_state::clear_session_state,
+  session_writes::verify_session_writes, session_writes::reapply_session_writes, session_writes::accept_external_changes,
+  removed_tags::remove_tags_soft, removed_tags::restore_removed_tag, removed_tags::purge_removed_history, removed_tags::list_removed_tags,
+  audit_export::export_audit_log, audit_export::prune_audit_log, full_text::full_text_search, deep_read::read_metadata_deep, folder_compare::compare_folders,
+  jobs::set_job_notify, notifications::notification_status, probe::probe_file, workspace_stats::workspace_stats, preview_cues::preview_cue_points,
+  handshake::handshake, handshake::force_compatibility,
+  field_locks::lock_fields, field_locks::unlock_fields, color_label::write_color_label,
+  support_bundle::create_support_bundle, encoder_info::encoding_info, shadow::set_shadow_mode, shadow::disable_shadow_mode,
+  tag_suggest::suggest_tags, picture_budget::scan_embedded_pictures, picture_budget::keep_first_front_cover,
+  presets::list_presets, presets::save_preset, presets::delete_preset, presets::apply_preset,
+  tracklist::import_tracklist, tracklist::tag_matched_tracks,
+  maintenance::get_cache_stats, maintenance::run_maintenance_now,
+  tag_storage::audit_tag_storage, tag_storage::reconcile_tag_storage,
+  preview_history::note_preview, preview_history::get_preview_history, preview_history::preview_counts,
+  export_check::validate_export,
+  tag_rename::count_tag_occurrences, tag_rename::rename_tag_in_folder,
+  xattrs::read_finder_tags, xattrs::write_finder_tags,
+  payload_guard::read_response_chunk, payload_guard::release_response,
+  volume_health::volume_health_report,
+  tag_import::import_tags_from_text,
+  track_updates::get_track_revision,
+
+  ];
+  tauri::Builder::default()
+    .invoke_handler(move |invoke: tauri::Invoke| {
+      let name = invoke.message.command().to_string();
+      // A failure report isn't a command worth timing.
+      let own_span = !ASYNC_COMMANDS.contains(&name.as_str()) && name != "record_command_failure";
+      let _span = own_span.then(|| command_span(&name));
+      handler(invoke)
+    })
     .setup(|app| {
-    tauri::async_runtime::block_on(async {
-      match start_media_server().await {
-        Ok(port) => {
-          let base = format!("http://127.0.0.1:{}", port);
-          app.manage(AppState { media_base: base });
-        }
+    // Nothing here may block on disk or sockets: the window waits for setup.
+    app.manage(AppState {
+      media_base: parking_lot::RwLock::new(MediaBase::Starting),
+      banks: bank_store::BankStore::default(),
+      now_showing: Default::default(),
+      perf: Default::default(),
+    });
+    perf::init(app.handle());
+    volumes::init(app.handle());
+    volume_health::start(app.handle());
+    track_updates::init(app.handle());
+    inbox::start(app.handle());
+    folder_watch::start(app.handle());
+    retry_queue::start();
+    maintenance::start();
+    let handle = app.handle();
+    tauri::async_runtime::spawn(async move {
+      let _ = tauri::async_runtime::spawn_blocking(|| apply_runtime_settings(&load_prefs().settings.unwrap_or_default())).await;
+      let next = match start_media_server(handle.clone()).await {
+        Ok(port) => MediaBase::Ready(format!("http://127.0.0.1:{}", port)),
         Err(e) => {
           eprintln!("Failed to start media server: {}", e);
-          app.manage(AppState { media_base: "http://127.0.0.1:0".into() });
+          MediaBase::Failed(e.to_string())
         }
-      }
+      };
+      *handle.state::<AppState>().media_base.write() = next;
+      startup_mark("media_server_ready");
     });
     Ok(())
   })
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
-    
+    .build(tauri::generate_context!())
+    .expect("error while running tauri application")
+    .run(|app, event| match event {
+      tauri::RunEvent::Ready => startup_mark("window_ready"),
+      tauri::RunEvent::WindowEvent { event: tauri::WindowEvent::Focused(focused), .. } => notifications::focus_changed(app, focused),
+      tauri::RunEvent::Exit => { session_state::flush(); touched::flush(); meta_cache::flush(); volume_health::flush(); }
+      _ => {}
+    });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hyper::body::HttpBody;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  fn get(p: &Path, method: Method) -> Request<Body> {
+    Request::builder().method(method).uri(urls::path_url("http://127.0.0.1:1", "audio", &p.to_string_lossy())).body(Body::empty()).unwrap()
+  }
+
+  /// Many STREAM_CHUNKs long, more than loopback socket buffers take in,
+  /// so a stream of it stays open while the client holds off reading.
+  fn big_file(name: &str) -> PathBuf {
+    let p = test_support::scratch(name).join("big.mp3");
+    fs::write(&p, (0..STREAM_CHUNK * 64).map(|i| (i % 251) as u8).collect::<Vec<_>>()).unwrap();
+    p
+  }
+
+  /// Descriptors of this process open on `p`.
+  #[cfg(target_os = "linux")]
+  fn open_handles(p: &Path) -> usize {
+    fs::read_dir("/proc/self/fd").unwrap().filter_map(|e| fs::read_link(e.ok()?.path()).ok()).filter(|l| l == p).count()
+  }
+
+  #[test]
+  fn streams_past_the_cap_get_429_until_one_closes() {
+    let _streams = test_support::MEDIA_SERVER.lock();
+    let p = big_file("stream-cap");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let max = MAX_STREAMS.swap(2, Ordering::Relaxed);
+    let rejected = STREAMS_REJECTED.load(Ordering::Relaxed);
+
+    let first = rt.block_on(audio_response(&get(&p, Method::GET)));
+    let second = rt.block_on(audio_response(&get(&p, Method::GET)));
+    assert_eq!((first.status(), second.status()), (StatusCode::OK, StatusCode::OK));
+    assert_eq!(media_server_stats().active_streams, 2);
+    let over = rt.block_on(audio_response(&get(&p, Method::GET)));
+    assert_eq!(over.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(over.headers()[header::RETRY_AFTER], "1");
+    assert_eq!(STREAMS_REJECTED.load(Ordering::Relaxed), rejected + 1);
+    // Probes take no slot.
+    assert_eq!(rt.block_on(audio_response(&get(&p, Method::HEAD))).status(), StatusCode::OK);
+
+    drop(first);
+    assert_eq!(media_server_stats().active_streams, 1);
+    assert_eq!(rt.block_on(audio_response(&get(&p, Method::GET))).status(), StatusCode::OK);
+    drop(second);
+    MAX_STREAMS.store(max, Ordering::Relaxed);
+    assert_eq!(media_server_stats().active_streams, 0);
+  }
+
+  #[test]
+  fn bodies_come_in_stream_chunks() {
+    let _streams = test_support::MEDIA_SERVER.lock();
+    let p = big_file("stream-chunks");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut body = rt.block_on(audio_response(&get(&p, Method::GET))).into_body();
+    let (mut got, mut sizes) = (Vec::new(), Vec::new());
+    rt.block_on(async { while let Some(c) = body.data().await { let c = c.unwrap(); sizes.push(c.len()); got.extend_from_slice(&c); } });
+    assert_eq!(got, fs::read(&p).unwrap());
+    assert_eq!(sizes[0], STREAM_CHUNK, "{:?}", sizes);
+  }
+
+  #[test]
+  fn a_client_that_hangs_up_mid_transfer_frees_its_slot_and_file() {
+    let _streams = test_support::MEDIA_SERVER.lock();
+    let p = big_file("stream-hangup");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+      let make = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(|req| async move { Ok::<_, Infallible>(audio_response(&req).await) })) });
+      let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+      let base = format!("http://{}", server.local_addr());
+      tokio::spawn(server);
+
+      let url = urls::path_url(&base, "audio", &p.to_string_lossy());
+      let mut client = tokio::net::TcpStream::connect(&base["http://".len()..]).await.unwrap();
+      client.write_all(format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", &url[base.len()..]).as_bytes()).await.unwrap();
+      let mut buf = vec![0u8; 64 * 1024];
+      let mut read = 0;
+      while read < buf.len() { read += client.read(&mut buf[read..]).await.unwrap(); }
+      assert_eq!(media_server_stats().active_streams, 1);
+      #[cfg(target_os = "linux")]
+      assert_eq!(open_handles(&p), 1);
+
+      drop(client);
+      let started = Instant::now();
+      while media_server_stats().active_streams > 0 {
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "the slot outlived its client");
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+      }
+      #[cfg(target_os = "linux")]
+      assert_eq!(open_handles(&p), 0, "the file is closed with the stream");
+    });
+  }
 }
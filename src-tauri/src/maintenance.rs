@@ -0,0 +1,165 @@
+// Upkeep of the stores that otherwise only grow: the metadata cache,
+// waveform peaks, cover palettes, audio hashes and the preview history. A
+// pass drops the entries of files that are gone (not ones on a volume that is
+// merely unplugged), evicts the least recently used past each cache's budget
+// (`CacheBudgets` in Settings) and rewrites the metadata cache file
+// compactly. It runs by itself once the app has gone
+// `maintenance_idle_minutes` without a command and no job is running, and
+// stops at the next command or job; `run_maintenance_now` runs one on
+// request, to the end. There is no thumbnail cache to keep: covers are
+// shrunk when asked for.
+
+use std::{path::Path, sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}, time::{Duration, Instant}};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{already_owned::HashCache, command_span, jobs, log_line, meta_cache, palette, peaks, preview_history, profile, volumes};
+
+pub const DEFAULT_IDLE_MINUTES: u32 = 10;
+/// How often the idle thread looks.
+const CHECK_EVERY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CacheBudgets {
+  pub metadata_entries: usize,
+  pub peaks_mb: u64,
+  pub palette_entries: usize,
+}
+
+impl Default for CacheBudgets {
+  fn default() -> Self { Self { metadata_entries: 250_000, peaks_mb: 500, palette_entries: 5_000 } }
+}
+
+static BUDGETS: Lazy<Mutex<CacheBudgets>> = Lazy::new(|| Mutex::new(CacheBudgets::default()));
+/// 0 turns idle runs off.
+static IDLE_MINUTES: AtomicU32 = AtomicU32::new(DEFAULT_IDLE_MINUTES);
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+/// Milliseconds since EPOCH of the last command.
+static LAST_ACTIVITY: AtomicU64 = AtomicU64::new(0);
+/// LAST_ACTIVITY when the last idle pass started; one pass per idle stretch.
+static LAST_PASS: AtomicU64 = AtomicU64::new(u64::MAX);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn now_ms() -> u64 { EPOCH.elapsed().as_millis() as u64 }
+
+/// Every command calls this (see `command_span`); an idle pass stops at it.
+pub fn touch() { LAST_ACTIVITY.store(now_ms(), Ordering::Relaxed); }
+
+pub fn configure(idle_minutes: u32, budgets: CacheBudgets) {
+  IDLE_MINUTES.store(idle_minutes, Ordering::Relaxed);
+  *BUDGETS.lock() = budgets;
+}
+
+/// `p` no longer exists and its volume is still there to say so.
+pub fn source_gone(p: &Path) -> bool { !p.exists() && volumes::detect(p).is_none() }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+  name: &'static str,
+  entries: usize,
+  /// On disk; `None` for the in-memory palette cache.
+  bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachePass {
+  name: &'static str,
+  /// Entries of files that are gone.
+  pruned: usize,
+  /// Least recently used, past the budget.
+  evicted: usize,
+  bytes_before: Option<u64>,
+  bytes_after: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceReport {
+  caches: Vec<CachePass>,
+  /// Stopped for user activity; the caches not listed weren't touched.
+  interrupted: bool,
+  manual: bool,
+  took_ms: u64,
+}
+
+fn stats() -> Vec<CacheStats> {
+  let (meta_n, meta_b) = meta_cache::stats();
+  let (peaks_n, peaks_b) = peaks::stats();
+  let (hash_n, hash_b) = HashCache::stats();
+  let (previews_n, previews_b) = preview_history::stats();
+  vec![
+    CacheStats { name: "metadata", entries: meta_n, bytes: Some(meta_b) },
+    CacheStats { name: "peaks", entries: peaks_n, bytes: Some(peaks_b) },
+    CacheStats { name: "palettes", entries: palette::cache_len(), bytes: None },
+    CacheStats { name: "audioHashes", entries: hash_n, bytes: Some(hash_b) },
+    CacheStats { name: "previews", entries: previews_n, bytes: Some(previews_b) },
+  ]
+}
+
+fn run(manual: bool, stop: &dyn Fn() -> bool) -> MaintenanceReport {
+  let started = Instant::now();
+  let b = *BUDGETS.lock();
+  let mut caches = Vec::new();
+  // Cache by cache; a `None` means `stop` cut the pass short.
+  let interrupted = (|| {
+    let before = meta_cache::stats().1;
+    let (pruned, evicted) = meta_cache::maintain(b.metadata_entries, stop)?;
+    caches.push(CachePass { name: "metadata", pruned, evicted, bytes_before: Some(before), bytes_after: Some(meta_cache::stats().1) });
+    let (pruned, evicted, before, after) = peaks::maintain(b.peaks_mb.saturating_mul(1024 * 1024), stop)?;
+    caches.push(CachePass { name: "peaks", pruned, evicted, bytes_before: Some(before), bytes_after: Some(after) });
+    let (pruned, evicted) = palette::maintain(b.palette_entries, stop)?;
+    caches.push(CachePass { name: "palettes", pruned, evicted, ..Default::default() });
+    let (pruned, before, after) = HashCache::prune(stop)?;
+    caches.push(CachePass { name: "audioHashes", pruned, evicted: 0, bytes_before: Some(before), bytes_after: Some(after) });
+    let (pruned, before, after) = preview_history::prune(stop)?;
+    caches.push(CachePass { name: "previews", pruned, evicted: 0, bytes_before: Some(before), bytes_after: Some(after) });
+    Some(())
+  })()
+  .is_none();
+
+  let reclaimed: u64 = caches.iter().map(|c| c.bytes_before.unwrap_or(0).saturating_sub(c.bytes_after.unwrap_or(0))).sum();
+  let summary: Vec<String> = caches.iter().map(|c| format!("{}={}+{}", c.name, c.pruned, c.evicted)).collect();
+  let took_ms = started.elapsed().as_millis() as u64;
+  log_line(&format!("maintenance manual={} interrupted={} pruned+evicted {} reclaimed_bytes={} ms={}", manual, interrupted, summary.join(" "), reclaimed, took_ms));
+  MaintenanceReport { caches, interrupted, manual, took_ms }
+}
+
+/// The idle thread: one pass per idle stretch, under the full profile only.
+pub fn start() {
+  Lazy::force(&EPOCH);
+  std::thread::spawn(|| loop {
+    std::thread::sleep(CHECK_EVERY);
+    let minutes = IDLE_MINUTES.load(Ordering::Relaxed) as u64;
+    let last = LAST_ACTIVITY.load(Ordering::Relaxed);
+    if minutes == 0 || LAST_PASS.load(Ordering::Relaxed) == last || !profile::background_allowed() { continue; }
+    if now_ms().saturating_sub(last) < minutes * 60_000 || !jobs::is_idle() { continue; }
+    if RUNNING.swap(true, Ordering::SeqCst) { continue; }
+    LAST_PASS.store(last, Ordering::Relaxed);
+    let stop = move || LAST_ACTIVITY.load(Ordering::Relaxed) != last || !jobs::is_idle();
+    run(false, &stop);
+    RUNNING.store(false, Ordering::SeqCst);
+  });
+}
+
+/// Entries and on-disk size of each cache.
+#[tauri::command]
+pub fn get_cache_stats() -> Vec<CacheStats> { stats() }
+
+/// A maintenance pass now (see the header), not stopped by activity.
+#[tauri::command]
+pub async fn run_maintenance_now() -> Result<MaintenanceReport, String> {
+  let _span = command_span("run_maintenance_now");
+  tauri::async_runtime::spawn_blocking(|| {
+    if RUNNING.swap(true, Ordering::SeqCst) { return Err("maintenance is already running".to_string()); }
+    let report = run(true, &|| false);
+    RUNNING.store(false, Ordering::SeqCst);
+    Ok(report)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,248 @@
+// Sidecar tag manifests: snapshot comment/title/artist for a folder before
+// files go through tools that strip tags (mastering, stem exports), then put
+// them back. Entries carry an audio hash so files can be matched after a
+// rename as long as the audio itself is untouched, and the file's field locks,
+// which applying puts back too.
+
+use std::{collections::{BTreeMap, HashMap}, fs, path::{Path, PathBuf}};
+use chrono::Local;
+use lofty::Accessor;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  command_span, decode, error::CmdError, field_locks::{self, LockedField}, inspect::{plan_for_path, WritePlan}, jobs::JobHandle, library::audio_files_under, log_line, preferred_tag,
+  preflight::{self, Preflight}, read_comment, read_tagged, retry_queue, snapshots, split_comment_tokens, write_atomic, MetaPatch,
+};
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+  pub comment: String,
+  pub tags: Vec<String>,
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  /// See `decode::audio_hash`; `None` when the file couldn't be decoded.
+  pub hash: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagManifest {
+  pub version: u32,
+  pub created_at: String,
+  pub root: String,
+  /// Keyed by `/`-separated path relative to `root`.
+  pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchBy { Path, Hash }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestExportSummary {
+  dest: String,
+  files: usize,
+  unhashed: usize,
+  cancelled: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestApplyResult {
+  path: String,
+  /// Manifest key this file was matched to.
+  matched: Option<String>,
+  changed: bool,
+  warning: Option<String>,
+  error: Option<String>,
+  /// Dry runs only: the tag types the write would go to.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  plan: Option<WritePlan>,
+  /// Fields the manifest would change but the file has locked; left as they are.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestApplyReport {
+  results: Vec<ManifestApplyResult>,
+  /// Manifest keys no file was matched to.
+  unmatched_entries: Vec<String>,
+  cancelled: bool,
+  /// Set when the batch was large enough to snapshot first (see `snapshots`).
+  snapshot_id: Option<String>,
+  /// Space and permission check run before writing (see `preflight`).
+  preflight: Preflight,
+}
+
+fn rel_key(root: &Path, p: &Path) -> String {
+  p.strip_prefix(root).unwrap_or(p).to_string_lossy().replace('\\', "/")
+}
+
+fn snapshot(p: &Path, job: &JobHandle) -> Result<ManifestEntry, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let comment = read_comment(&tf, p);
+  let tag = preferred_tag(&tf, p);
+  Ok(ManifestEntry {
+    tags: split_comment_tokens(&comment).into_iter().filter(|t| !t.starts_with("TagB:")).collect(),
+    title: tag.and_then(|t| t.title().map(|s| s.to_string())),
+    artist: tag.and_then(|t| t.artist().map(|s| s.to_string())),
+    hash: decode::audio_hash(p, Some(job.cancel_flag())).ok(),
+    locked: field_locks::locked(p),
+    comment,
+  })
+}
+
+fn export_blocking(job: &JobHandle, folder: &str, dest: &str) -> Result<ManifestExportSummary, String> {
+  let root = PathBuf::from(folder);
+  job.begin_phase("scan", 0);
+  let paths = audio_files_under(&root).map_err(|e| e.to_string())?;
+  let mut files = BTreeMap::new();
+  let mut cancelled = false;
+  job.begin_phase("hash", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    match snapshot(p, job) {
+      Ok(e) => { files.insert(rel_key(&root, p), e); }
+      Err(e) => log_line(&format!("export_tag_manifest skip \"{}\": {}", p.display(), e)),
+    }
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  // A partial manifest would look complete later; don't write one.
+  if cancelled { return Ok(ManifestExportSummary { dest: dest.to_string(), files: 0, unhashed: 0, cancelled }); }
+
+  let unhashed = files.values().filter(|e| e.hash.is_none()).count();
+  let manifest = TagManifest { version: MANIFEST_VERSION, created_at: Local::now().to_rfc3339(), root: folder.to_string(), files };
+  let json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+  write_atomic(Path::new(dest), &json)?;
+  log_line(&format!("export_tag_manifest folder=\"{}\" dest=\"{}\" files={}", folder, dest, manifest.files.len()));
+  Ok(ManifestExportSummary { dest: dest.to_string(), files: manifest.files.len(), unhashed, cancelled })
+}
+
+fn apply_entry(p: &Path, entry: &ManifestEntry, dry_run: bool, res: &mut ManifestApplyResult) -> Result<(), String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let comment = read_comment(&tf, p);
+  let tag = preferred_tag(&tf, p);
+  let title = tag.and_then(|t| t.title().map(|s| s.to_string()));
+  let artist = tag.and_then(|t| t.artist().map(|s| s.to_string()));
+  drop(tf);
+
+  // Only restore what the manifest has; never blank a field the file gained since.
+  let locked = field_locks::locked(p);
+  let mut unlocked = |field: LockedField, changed: bool| {
+    if changed && locked.contains(&field) { res.skipped_locked.push(field); return false; }
+    changed
+  };
+  let comment_changed = unlocked(LockedField::Comment, !entry.comment.is_empty() && entry.comment != comment);
+  let patch = MetaPatch {
+    title: entry.title.clone().filter(|t| unlocked(LockedField::Title, Some(t) != title.as_ref())),
+    artist: entry.artist.clone().filter(|a| unlocked(LockedField::Artist, Some(a) != artist.as_ref())),
+    ..Default::default()
+  };
+  res.changed = comment_changed || !patch.is_empty();
+  if dry_run && res.changed { res.plan = plan_for_path(p).ok(); }
+  if dry_run { return Ok(()); }
+  if comment_changed { retry_queue::write_comment(&res.path, &entry.comment).map_err(|e| e.to_string())?; }
+  if !patch.is_empty() { retry_queue::apply_patch(p, &patch)?; }
+  // After the writes: a lock in the manifest covers the value it restored.
+  if !entry.locked.is_empty() { field_locks::set(p, &entry.locked, true)?; }
+  Ok(())
+}
+
+fn apply_blocking(job: &JobHandle, folder: &str, manifest_path: &str, match_by: MatchBy, dry_run: bool) -> Result<ManifestApplyReport, CmdError> {
+  let raw = fs::read(manifest_path).map_err(|e| e.to_string())?;
+  let manifest: TagManifest = serde_json::from_slice(&raw).map_err(|e| format!("not a tag manifest: {}", e))?;
+  if manifest.version > MANIFEST_VERSION {
+    return Err(format!("manifest version {} is newer than supported ({})", manifest.version, MANIFEST_VERSION).into());
+  }
+  let by_hash: HashMap<&str, &str> = manifest.files.iter()
+    .filter_map(|(k, e)| e.hash.as_deref().map(|h| (h, k.as_str())))
+    .collect();
+
+  let root = PathBuf::from(folder);
+  job.begin_phase("scan", 0);
+  let paths = audio_files_under(&root).map_err(|e| e.to_string())?;
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(&paths));
+  if !dry_run { preflight::ensure(&preflight)?; }
+  let snapshot_id = if dry_run { None } else { snapshots::before_batch(job.app(), "apply_tag_manifest", &paths) };
+  let mut results = Vec::new();
+  let mut used: Vec<String> = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("write", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let mut res = ManifestApplyResult { path: p.to_string_lossy().to_string(), ..Default::default() };
+    let key = rel_key(&root, p);
+    let hash = decode::audio_hash(p, Some(job.cancel_flag())).ok();
+    let matched = match match_by {
+      MatchBy::Path => manifest.files.get_key_value(&key).map(|(k, e)| {
+        if let (Some(old), Some(new)) = (&e.hash, &hash) {
+          if old != new { res.warning = Some("audio differs from the manifest snapshot (re-rendered?)".into()); }
+        }
+        (k.clone(), e)
+      }),
+      MatchBy::Hash => hash.as_deref()
+        .and_then(|h| by_hash.get(h))
+        .and_then(|k| manifest.files.get_key_value(*k))
+        .map(|(k, e)| (k.clone(), e)),
+    };
+    match matched {
+      Some((k, entry)) => {
+        if let Err(e) = apply_entry(p, entry, dry_run, &mut res) { res.error = Some(e); }
+        used.push(k.clone());
+        res.matched = Some(k);
+      }
+      None => res.warning = Some("no manifest entry matches this file".into()),
+    }
+    results.push(res);
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  let unmatched_entries = manifest.files.keys().filter(|k| !used.contains(k)).cloned().collect();
+  if !dry_run {
+    let changed = results.iter().filter(|r| r.changed && r.error.is_none()).count();
+    log_line(&format!("apply_tag_manifest folder=\"{}\" manifest=\"{}\" changed={} cancelled={}", folder, manifest_path, changed, cancelled));
+  }
+  Ok(ManifestApplyReport { results, unmatched_entries, cancelled, snapshot_id, preflight })
+}
+
+#[tauri::command]
+pub async fn export_tag_manifest(app: tauri::AppHandle, folder: String, dest: String) -> Result<ManifestExportSummary, String> {
+  let _span = command_span("export_tag_manifest");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "tag-manifest-export", &folder);
+    let res = export_blocking(&job, &folder, &dest);
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Re-apply a manifest to `folder`. Cancelling keeps the files already done
+/// and returns their results with `cancelled: true`.
+#[tauri::command]
+pub async fn apply_tag_manifest(
+  app: tauri::AppHandle,
+  folder: String,
+  manifest_path: String,
+  match_by: MatchBy,
+  dry_run: bool,
+) -> Result<ManifestApplyReport, CmdError> {
+  let _span = command_span("apply_tag_manifest");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "tag-manifest-apply", &folder);
+    let res = apply_blocking(&job, &folder, &manifest_path, match_by, dry_run);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,178 @@
+// Which files the media server is streaming. `/audio` bodies register their
+// file for as long as they live, and tag saves call `wait_for` (before
+// WRITE_LOCK) and `before_replace` (right before saving) so a save doesn't
+// pull the file out from under a player: an in-place save would feed it new
+// bytes at old offsets, a rename leaves it reading the old file (and fails
+// outright on Windows while the file is open).
+// `stream_write_policy` in Settings: "wait" (default) gives the file's
+// streams up to WAIT to end, then aborts the rest; "close" aborts them right
+// away. An aborted body ends with an error and the player's next request
+// gets the new file. A body the client has stopped reading can't be made to
+// notice; the save goes ahead after CLOSE_GRACE and says so in the log.
+
+use std::{collections::HashMap, fs, path::Path, sync::{atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering}, Arc}, time::{Duration, Instant}};
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+
+use crate::{log, LogLevel};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamWritePolicy { #[default] Wait, Close }
+
+pub static POLICY: AtomicU8 = AtomicU8::new(StreamWritePolicy::Wait as u8);
+
+const WAIT: Duration = Duration::from_secs(2);
+/// After aborting, how long bodies get to drop their file handles.
+const CLOSE_GRACE: Duration = Duration::from_millis(500);
+
+struct Stream {
+  id: u64,
+  abort: Arc<AtomicBool>,
+}
+
+/// Canonical path -> open streams on it.
+static STREAMS: Lazy<Mutex<HashMap<String, Vec<Stream>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static ENDED: Condvar = Condvar::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn key(p: &Path) -> String {
+  fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()).to_string_lossy().to_string()
+}
+
+/// Held by a streaming body; unregisters when the body is dropped.
+pub struct Registration {
+  key: String,
+  id: u64,
+  abort: Arc<AtomicBool>,
+}
+
+impl Registration {
+  /// A save wants the file: end the body.
+  pub fn aborted(&self) -> bool { self.abort.load(Ordering::Relaxed) }
+}
+
+impl Drop for Registration {
+  fn drop(&mut self) {
+    let mut streams = STREAMS.lock();
+    if let Some(v) = streams.get_mut(&self.key) {
+      v.retain(|s| s.id != self.id);
+      if v.is_empty() { streams.remove(&self.key); }
+    }
+    ENDED.notify_all();
+  }
+}
+
+pub fn register(p: &Path) -> Registration {
+  let reg = Registration { key: key(p), id: NEXT_ID.fetch_add(1, Ordering::Relaxed), abort: Arc::new(AtomicBool::new(false)) };
+  STREAMS.lock().entry(reg.key.clone()).or_default().push(Stream { id: reg.id, abort: reg.abort.clone() });
+  reg
+}
+
+type Held<'a> = parking_lot::MutexGuard<'a, HashMap<String, Vec<Stream>>>;
+
+fn open(streams: &HashMap<String, Vec<Stream>>, k: &str) -> usize { streams.get(k).map_or(0, Vec::len) }
+
+/// Wait until `until` for the streams on `k` to end; how many are left.
+fn wait(streams: &mut Held<'_>, k: &str, until: Instant) -> usize {
+  while open(streams, k) > 0 {
+    if ENDED.wait_until(streams, until).timed_out() { break; }
+  }
+  open(streams, k)
+}
+
+/// Under the "wait" policy, give the streams on `p` up to WAIT to end.
+/// Saves call it before taking WRITE_LOCK, so waiting out a player doesn't
+/// hold up saves of other files; `before_replace` closes what's left.
+pub fn wait_for(p: &Path) {
+  if POLICY.load(Ordering::Relaxed) != StreamWritePolicy::Wait as u8 { return; }
+  let mut streams = STREAMS.lock();
+  if streams.is_empty() { return; }
+  let k = key(p);
+  if open(&streams, &k) == 0 { return; }
+  let started = Instant::now();
+  let left = wait(&mut streams, &k, started + WAIT);
+  log(LogLevel::Info, &format!("streams before save path=\"{}\" waited_ms={} still_open={}", p.display(), started.elapsed().as_millis(), left));
+}
+
+/// Abort the streams still open on `p` (see the header) right before it's
+/// saved. Returns how many outlived CLOSE_GRACE.
+pub fn before_replace(p: &Path) -> usize {
+  let mut streams = STREAMS.lock();
+  // Nothing playing: skip the canonicalize on every save.
+  if streams.is_empty() { return 0; }
+  let k = key(p);
+  let found = open(&streams, &k);
+  if found == 0 { return 0; }
+
+  let started = Instant::now();
+  if let Some(v) = streams.get(&k) {
+    for s in v { s.abort.store(true, Ordering::Relaxed); }
+  }
+  let left = wait(&mut streams, &k, started + CLOSE_GRACE);
+  let level = if left > 0 { LogLevel::Warn } else { LogLevel::Info };
+  log(level, &format!("streams closed for save path=\"{}\" open={} still_open={} waited_ms={}", p.display(), found, left, started.elapsed().as_millis()));
+  left
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+  use hyper::{body::HttpBody, header, Body, Request, StatusCode};
+  use crate::{audio_response, audit, test_support, urls, write_comment_as};
+
+  fn get(p: &Path, range: Option<&str>) -> Request<Body> {
+    let mut req = Request::get(urls::path_url("http://127.0.0.1:1", "audio", &p.to_string_lossy()));
+    if let Some(r) = range { req = req.header(header::RANGE, r); }
+    req.body(Body::empty()).unwrap()
+  }
+
+  async fn drain(body: &mut Body, into: &mut Vec<u8>) {
+    while let Some(chunk) = body.data().await { into.extend_from_slice(&chunk.unwrap()); }
+  }
+
+  #[test]
+  fn a_save_mid_stream_waits_outside_the_write_lock() {
+    let _streams = test_support::MEDIA_SERVER.lock();
+    let dir = test_support::scratch("media-streams");
+    // Bigger than one STREAM_CHUNK, so the body is still open after the first.
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    let a = dir.join("a.mp3");
+    fs::write(&a, frame.repeat(1000)).unwrap();
+    let other = test_support::audio(&dir, "b.mp3");
+    let original = fs::read(&a).unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let resp = rt.block_on(audio_response(&get(&a, Some("bytes=0-"))));
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    let mut body = resp.into_body();
+    let mut streamed = Vec::new();
+    rt.block_on(async { streamed.extend_from_slice(&body.data().await.unwrap().unwrap()) });
+    assert!(streamed.len() < original.len());
+
+    let path = a.to_string_lossy().to_string();
+    let save = thread::spawn(move || write_comment_as(&path, "#mid-stream;", audit::Source::Manual));
+    thread::sleep(Duration::from_millis(100));
+    assert!(!save.is_finished(), "the save waits for the stream");
+    // Another file's save isn't held up meanwhile.
+    let started = Instant::now();
+    write_comment_as(&other.to_string_lossy(), "#other;", audit::Source::Manual).unwrap();
+    assert!(started.elapsed() < WAIT / 2, "{:?}", started.elapsed());
+
+    rt.block_on(drain(&mut body, &mut streamed));
+    drop(body);
+    assert_eq!(streamed, original, "the stream got the file it opened, whole");
+    let written = save.join().unwrap().unwrap();
+    assert!(!written.no_op);
+
+    let mut fresh = Vec::new();
+    let mut body = rt.block_on(audio_response(&get(&a, None))).into_body();
+    rt.block_on(drain(&mut body, &mut fresh));
+    assert_eq!(fresh, fs::read(&a).unwrap());
+    assert_ne!(fresh, original);
+    assert!(fresh.windows(12).any(|w| w == b"#mid-stream;"));
+  }
+}
@@ -0,0 +1,388 @@
+// Metadata cache: the text fields of every file we've read or written, keyed
+// by canonical path and valid while size + mtime match (data dir
+// `metadata_cache.json`). Library-wide features read from here instead of
+// opening thousands of files. Entries also keep the technical properties
+// (duration, bitrate, sample rate, encoder), so exports and stats on a warm
+// cache open no audio file at all; entries written by an older build carry a
+// lower `version` and are re-read the next time they're asked for, one file
+// at a time rather than as a rebuild. The autocomplete index and the neighbour index
+// behind tag suggestions are derived from the entries and kept in step with
+// them under the same lock. Idle-time `maintenance` drops the entries of
+// deleted files and keeps the count within budget.
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::atomic::Ordering, time::{Duration, Instant, UNIX_EPOCH}};
+use lofty::{Accessor, AudioFile, ItemKey};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{autocomplete, color_label, comment_precedence, compilation, data_dir, dates, encoder_info, log, log_line, LogLevel, maintenance, preferred_tag, profile, read_comment, read_tagged, shadow, tag_suggest, tagged_at, text_fold, write_atomic};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+/// Bump when `CachedMeta` gains a field read from the file; older entries
+/// count as stale and are re-read.
+const ENTRY_VERSION: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedMeta {
+  #[serde(default)]
+  pub version: u8,
+  pub len: u64,
+  pub mtime_ms: u64,
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub album: Option<String>,
+  pub genre: Option<String>,
+  pub comment: String,
+  pub release_date: Option<String>,
+  pub original_date: Option<String>,
+  /// TAGGED_AT stamp, for "tagged more than N months ago" queries.
+  #[serde(default)]
+  pub tagged_at: Option<String>,
+  /// Unsynchronized lyrics, for full-text search.
+  #[serde(default)]
+  pub lyrics: Option<String>,
+  /// `None` when the container doesn't say (or says 0).
+  #[serde(default)]
+  pub duration_ms: Option<u64>,
+  #[serde(default)]
+  pub bitrate_kbps: Option<u32>,
+  #[serde(default)]
+  pub compilation: Option<bool>,
+  #[serde(default)]
+  pub bpm: Option<f64>,
+  /// Initial key as written in the tag.
+  #[serde(default)]
+  pub key: Option<String>,
+  #[serde(default)]
+  pub sample_rate: Option<u32>,
+  #[serde(default)]
+  pub channels: Option<u8>,
+  /// See `encoder_info`.
+  #[serde(default)]
+  pub encoder: Option<String>,
+  #[serde(default)]
+  pub vbr: Option<bool>,
+  #[serde(default)]
+  pub color_label: Option<String>,
+  #[serde(default)]
+  pub comment_conflicts: bool,
+}
+
+impl CachedMeta {
+  /// Read from the file as it is now: same size and mtime, current version.
+  fn current(&self, len: u64, mtime_ms: u64) -> bool { self.version == ENTRY_VERSION && self.len == len && self.mtime_ms == mtime_ms }
+}
+
+#[derive(Default)]
+struct Store {
+  entries: Option<HashMap<String, CachedMeta>>,
+  index: autocomplete::Index,
+  /// Not persisted; rebuilt from the entries on load.
+  neighbors: tag_suggest::Index,
+  dirty: bool,
+  last_flush: Option<Instant>,
+  flush_scheduled: bool,
+  /// Last `lookup` or store this session, for eviction by `maintain`.
+  used: HashMap<String, Instant>,
+}
+
+static STORE: Lazy<Mutex<Store>> = Lazy::new(|| Mutex::new(Store::default()));
+
+fn cache_path() -> PathBuf { data_dir().join("metadata_cache.json") }
+
+fn key(p: &Path) -> String {
+  fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()).to_string_lossy().to_string()
+}
+
+fn stamp(p: &Path) -> Option<(u64, u64)> {
+  let m = fs::metadata(p).ok()?;
+  Some((m.len(), m.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64))
+}
+
+/// Loads entries and index on first use. An index that is missing or doesn't
+/// parse is rebuilt from the entries.
+fn loaded(s: &mut Store) -> &mut HashMap<String, CachedMeta> {
+  if s.entries.is_none() {
+    let entries: HashMap<String, CachedMeta> =
+      fs::read_to_string(cache_path()).ok().and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default();
+    s.index = autocomplete::load().unwrap_or_else(|| {
+      let mut idx = autocomplete::Index::default();
+      for m in entries.values() { idx.add(m); }
+      idx
+    });
+    for (k, m) in &entries { s.neighbors.add(k, m); }
+    s.entries = Some(entries);
+  }
+  s.entries.get_or_insert_with(HashMap::new)
+}
+
+fn flush_locked(s: &mut Store) {
+  if s.dirty {
+    let res = serde_json::to_vec(loaded(s)).map_err(|e| e.to_string()).and_then(|bytes| {
+      let p = cache_path();
+      if let Some(dir) = p.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+      write_atomic(&p, &bytes)?;
+      autocomplete::save(&s.index)
+    });
+    if let Err(e) = res { log(LogLevel::Warn, &format!("metadata cache flush failed: {}", e)); }
+    s.dirty = false;
+  }
+  s.last_flush = Some(Instant::now());
+}
+
+/// Same coalescing as the touched store: batches update thousands of entries.
+fn mark_dirty(mut s: parking_lot::MutexGuard<'_, Store>) {
+  s.dirty = true;
+  let interval = profile::flush_interval(FLUSH_INTERVAL);
+  match s.last_flush.map(|t| t.elapsed()) {
+    Some(el) if el < interval => {
+      if !s.flush_scheduled {
+        s.flush_scheduled = true;
+        let wait = interval - el;
+        std::thread::spawn(move || {
+          std::thread::sleep(wait);
+          let mut s = STORE.lock();
+          s.flush_scheduled = false;
+          flush_locked(&mut s);
+        });
+      }
+    }
+    _ => flush_locked(&mut s),
+  }
+}
+
+/// Write pending entries right away. Called on app exit.
+pub fn flush() { flush_locked(&mut STORE.lock()); }
+
+fn insert(s: &mut Store, k: String, meta: CachedMeta) {
+  let old = loaded(s).insert(k.clone(), meta.clone());
+  if let Some(old) = &old {
+    s.index.remove(old);
+    s.neighbors.remove(&k, old);
+  }
+  s.index.add(&meta);
+  s.neighbors.add(&k, &meta);
+  s.used.insert(k, Instant::now());
+}
+
+fn remove(s: &mut Store, k: &str) -> bool {
+  s.used.remove(k);
+  let Some(old) = loaded(s).remove(k) else { return false };
+  s.index.remove(&old);
+  s.neighbors.remove(k, &old);
+  true
+}
+
+/// The encoder and VBR flag of the entry for `p`, when it is current.
+fn cached_encoder(p: &Path) -> Option<(Option<String>, Option<bool>)> {
+  let (len, mtime_ms) = stamp(p)?;
+  let mut s = STORE.lock();
+  loaded(&mut s).get(&key(p)).filter(|m| m.current(len, mtime_ms)).map(|m| (m.encoder.clone(), m.vbr))
+}
+
+/// `encoder_info::summary` for `p`, from its entry when that is current. On a
+/// miss the headers are read once and the entry is filed from `tf`.
+pub fn encoder(p: &Path, tf: &lofty::TaggedFile) -> (Option<String>, Option<bool>) {
+  if let Some(hit) = cached_encoder(p) { return hit; }
+  store(p, tf);
+  cached_encoder(p).unwrap_or_else(|| encoder_info::summary(p, tf))
+}
+
+/// Update the entry for `p` from an already-parsed file (after a read or a
+/// save). Not while `p` has a shadow copy: `tf` was read from the copy.
+pub fn store(p: &Path, tf: &lofty::TaggedFile) {
+  if shadow::copy_of(p).is_some() { return; }
+  let Some(meta) = entry(p, tf) else { return };
+  let mut s = STORE.lock();
+  let k = key(p);
+  if loaded(&mut s).get(&k) == Some(&meta) { return; }
+  insert(&mut s, k, meta);
+  mark_dirty(s);
+}
+
+/// The entry `tf` makes for `p`, stamped with `p` as it is now.
+fn entry(p: &Path, tf: &lofty::TaggedFile) -> Option<CachedMeta> {
+  let (len, mtime_ms) = stamp(p)?;
+  let tag = preferred_tag(tf, p);
+  let text = |v: Option<std::borrow::Cow<'_, str>>| v.map(|s| s.to_string());
+  let comment = read_comment(tf, p);
+  // The MP3 headers are read only when the entry isn't current already.
+  let (encoder, vbr) = cached_encoder(p).unwrap_or_else(|| encoder_info::summary(p, tf));
+  Some(CachedMeta {
+    version: ENTRY_VERSION,
+    len,
+    mtime_ms,
+    title: text(tag.and_then(|t| t.title())),
+    artist: text(tag.and_then(|t| t.artist())),
+    album: text(tag.and_then(|t| t.album())),
+    genre: text(tag.and_then(|t| t.genre())),
+    comment_conflicts: comment_precedence::conflicts(tf, p, &comment),
+    comment,
+    release_date: tag.and_then(dates::release_date),
+    original_date: tag.and_then(dates::original_date),
+    tagged_at: tagged_at::of_file(tf),
+    lyrics: tag.and_then(|t| t.get_string(&ItemKey::Lyrics)).map(|s| s.to_string()),
+    duration_ms: Some(tf.properties().duration().as_millis() as u64).filter(|ms| *ms > 0),
+    bitrate_kbps: tf.properties().audio_bitrate().filter(|b| *b > 0),
+    compilation: tag.and_then(compilation::of_tag),
+    bpm: tag.and_then(|t| t.get_string(&ItemKey::Bpm).or_else(|| t.get_string(&ItemKey::IntegerBpm))).and_then(|b| b.trim().parse().ok()).filter(|b: &f64| *b > 0.0),
+    key: tag.and_then(|t| t.get_string(&ItemKey::InitialKey)).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+    sample_rate: tf.properties().sample_rate().filter(|r| *r > 0),
+    channels: tf.properties().channels().filter(|c| *c > 0),
+    encoder,
+    vbr,
+    color_label: color_label::of_file(tf),
+  })
+}
+
+/// Switch `text_fold::ACCENTS` and rebuild the indexes built under the old
+/// setting: the neighbours key artists by their folded form, and the
+/// autocomplete counts leave out the artists `compilation::is_various`
+/// (folded) recognizes. Under the store lock, so no entry change is filed
+/// under one setting and taken out under the other.
+pub fn set_accent_folding(on: bool) {
+  let mut guard = STORE.lock();
+  if text_fold::ACCENTS.swap(on, Ordering::Relaxed) == on { return; }
+  let s = &mut *guard;
+  // Loading now also replaces an autocomplete.json saved under the old setting.
+  loaded(s);
+  let Some(entries) = s.entries.as_ref() else { return };
+  (s.index, s.neighbors) = Default::default();
+  for (k, m) in entries {
+    s.index.add(m);
+    s.neighbors.add(k, m);
+  }
+  mark_dirty(guard);
+}
+
+/// Cached fields for `p` if the file hasn't changed since; re-read otherwise.
+pub fn get(p: &Path) -> Result<CachedMeta, String> { lookup(p).map(|(m, _)| m) }
+
+/// `get`, and whether the file had to be opened (no entry, a stale one or
+/// one from an older `ENTRY_VERSION`). A file with a shadow copy is read
+/// from the copy every time and its entry left as it was.
+pub fn lookup(p: &Path) -> Result<(CachedMeta, bool), String> {
+  if let Some(copy) = shadow::copy_of(p) {
+    let tf = read_tagged(&copy).map_err(|e| e.to_string())?;
+    return entry(&copy, &tf).map(|m| (m, true)).ok_or_else(|| format!("file vanished while reading: {}", copy.display()));
+  }
+  let (len, mtime_ms) = stamp(p).ok_or_else(|| format!("file not found: {}", p.display()))?;
+  let k = key(p);
+  {
+    let mut s = STORE.lock();
+    let hit = loaded(&mut s).get(&k).filter(|m| m.current(len, mtime_ms)).cloned();
+    if let Some(m) = hit {
+      s.used.insert(k, Instant::now());
+      return Ok((m, false));
+    }
+  }
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  store(p, &tf);
+  let m = loaded(&mut STORE.lock()).get(&k).cloned().ok_or_else(|| format!("file vanished while reading: {}", p.display()))?;
+  Ok((m, true))
+}
+
+/// Fill in missing or stale entries for `paths`, quietly skipping files that
+/// can't be read. Returns how many were (re)read.
+pub fn refresh(paths: &[PathBuf]) -> usize {
+  // Stat outside the lock; scans hand us whole libraries.
+  let current: Vec<_> = paths.iter().map(|p| (p, key(p), stamp(p))).collect();
+  let stale: Vec<&PathBuf> = {
+    let mut s = STORE.lock();
+    let entries = loaded(&mut s);
+    current.into_iter().filter(|(_, k, cur)| entries.get(k).is_none_or(|m| !cur.is_some_and(|(len, mtime_ms)| m.current(len, mtime_ms)))).map(|(p, ..)| p).collect()
+  };
+  stale.iter().filter(|p| read_tagged(p).map(|tf| store(p, &tf)).is_ok()).count()
+}
+
+/// Entries for those of `paths` that are cached, as they are (no staleness
+/// check; `refresh` first). One lock for the whole list.
+pub fn cached_many(paths: &[PathBuf]) -> Vec<(PathBuf, CachedMeta)> {
+  let keys: Vec<String> = paths.iter().map(|p| key(p)).collect();
+  let mut s = STORE.lock();
+  let entries = loaded(&mut s);
+  paths.iter().zip(keys).filter_map(|(p, k)| entries.get(&k).map(|m| (p.clone(), m.clone()))).collect()
+}
+
+/// `f` over the cached entries of `paths` in place, under one lock: for
+/// aggregates over whole libraries, where `cached_many` would copy them all.
+pub fn visit(paths: &[PathBuf], mut f: impl FnMut(&Path, &CachedMeta)) {
+  let keys: Vec<String> = paths.iter().map(|p| key(p)).collect();
+  let mut s = STORE.lock();
+  let entries = loaded(&mut s);
+  for (p, k) in paths.iter().zip(keys) {
+    if let Some(m) = entries.get(&k) { f(p, m); }
+  }
+}
+
+/// Entries, and the size of the cache file as last written.
+pub fn stats() -> (usize, u64) {
+  let n = loaded(&mut STORE.lock()).len();
+  (n, fs::metadata(cache_path()).map(|m| m.len()).unwrap_or(0))
+}
+
+/// Drop the entries of files that are gone (see `maintenance::source_gone`),
+/// then the least recently used past `max_entries`: first those unused since
+/// launch, older files first. The file is rewritten either way, which also
+/// compacts it. `None` when `stop` cut the pass short; nothing is removed then.
+pub fn maintain(max_entries: usize, stop: &dyn Fn() -> bool) -> Option<(usize, usize)> {
+  let keys: Vec<String> = loaded(&mut STORE.lock()).keys().cloned().collect();
+  let mut gone = Vec::new();
+  for (i, k) in keys.iter().enumerate() {
+    if i % 64 == 0 && stop() { return None; }
+    if maintenance::source_gone(Path::new(k)) { gone.push(k); }
+  }
+  let mut guard = STORE.lock();
+  let s = &mut *guard;
+  let pruned = gone.into_iter().filter(|k| remove(s, k)).count();
+  let over = loaded(s).len().saturating_sub(max_entries);
+  if over > 0 {
+    let mut order: Vec<(Option<Instant>, u64, String)> = s.entries.iter().flatten().map(|(k, m)| (s.used.get(k).copied(), m.mtime_ms, k.clone())).collect();
+    order.sort_unstable();
+    for (.., k) in order.into_iter().take(over) { remove(s, &k); }
+  }
+  s.dirty = true;
+  flush_locked(s);
+  Some((pruned, over))
+}
+
+/// Move the entry of a renamed file. `from` is its canonical path from
+/// before the rename; size and mtime survive a rename, so it stays valid.
+pub fn rename(from: &Path, to: &Path) {
+  let mut s = STORE.lock();
+  let from = from.to_string_lossy().to_string();
+  let Some(meta) = loaded(&mut s).remove(&from) else { return };
+  let to = key(to);
+  s.neighbors.remove(&from, &meta);
+  s.neighbors.add(&to, &meta);
+  loaded(&mut s).insert(to, meta);
+  mark_dirty(s);
+}
+
+/// Run `f` over the index with the cache loaded.
+pub fn with_index<T>(f: impl FnOnce(&autocomplete::Index) -> T) -> T {
+  let mut s = STORE.lock();
+  loaded(&mut s);
+  f(&s.index)
+}
+
+/// Run `f` over the neighbour index and the entries it points into.
+pub fn with_neighbors<T>(f: impl FnOnce(&tag_suggest::Index, &HashMap<String, CachedMeta>) -> T) -> T {
+  let mut s = STORE.lock();
+  loaded(&mut s);
+  let Store { entries, neighbors, .. } = &mut *s;
+  f(neighbors, entries.get_or_insert_with(HashMap::new))
+}
+
+/// `refresh` on a background thread, so listing a folder also keeps the
+/// cache (and autocomplete) covering the whole library.
+pub fn refresh_in_background(paths: Vec<PathBuf>) {
+  // Files still get cached as they are read; only the library-wide fill is skipped.
+  if !profile::background_allowed() { return; }
+  std::thread::spawn(move || {
+    let n = refresh(&paths);
+    if n > 0 { log_line(&format!("metadata cache refreshed files={}", n)); }
+  });
+}
@@ -0,0 +1,352 @@
+// BPM / key hints in file and folder names ("Track (124, 8A).mp3",
+// "124 BPM/", "Artist - Title [Am 128]"). Hand-rolled tokenizer rather than
+// regexes so each accepted shape is explicit: brackets holding nothing but
+// BPM/key words, "124bpm" / "124 BPM", and a bare BPM next to a Camelot key.
+
+/// Key as a Camelot wheel position; enharmonic spellings compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Camelot { pub num: u8, pub minor: bool }
+
+impl std::fmt::Display for Camelot {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}{}", self.num, if self.minor { 'A' } else { 'B' })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyNotation { Camelot, OpenKey, Musical }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParsedKey { pub camelot: Camelot, pub notation: KeyNotation, explicit_mode: bool }
+
+fn camelot_from_pc(pc: u8, minor: bool) -> Camelot {
+  let major_pc = if minor { (pc + 3) % 12 } else { pc };
+  Camelot { num: ((major_pc as u32 * 7 % 12 + 7) % 12 + 1) as u8, minor }
+}
+
+fn split_num(t: &str) -> Option<(u8, &str)> {
+  let n = t.find(|c: char| !c.is_ascii_digit())?;
+  if n == 0 || n > 2 { return None; }
+  Some((t[..n].parse().ok()?, &t[n..]))
+}
+
+fn parse_camelot(t: &str) -> Option<Camelot> {
+  let (num, rest) = split_num(t)?;
+  if !(1..=12).contains(&num) { return None; }
+  match rest { "a" | "A" => Some(Camelot { num, minor: true }), "b" | "B" => Some(Camelot { num, minor: false }), _ => None }
+}
+
+fn parse_open_key(t: &str) -> Option<Camelot> {
+  let (num, rest) = split_num(t)?;
+  if !(1..=12).contains(&num) { return None; }
+  let minor = match rest { "m" | "M" => true, "d" | "D" => false, _ => return None };
+  Some(Camelot { num: (num + 6) % 12 + 1, minor })
+}
+
+fn parse_musical(t: &str) -> Option<(Camelot, bool)> {
+  let lower = t.to_lowercase();
+  let mut chars = lower.chars();
+  let base: u8 = match chars.next()? { 'c' => 0, 'd' => 2, 'e' => 4, 'f' => 5, 'g' => 7, 'a' => 9, 'b' => 11, _ => return None };
+  let mut rest = chars.as_str();
+  let mut pc = base;
+  if let Some(r) = rest.strip_prefix(['#', '♯']) { pc = (pc + 1) % 12; rest = r; }
+  else if let Some(r) = rest.strip_prefix(['b', '♭']) { pc = (pc + 11) % 12; rest = r; }
+  let (minor, explicit) = match rest.trim_start_matches([' ', '-', '_']) {
+    "" => (false, false),
+    "m" | "min" | "minor" => (true, true),
+    "maj" | "major" => (false, true),
+    _ => return None,
+  };
+  Some((camelot_from_pc(pc, minor), explicit))
+}
+
+/// Camelot ("8A"), Open Key ("1m") or musical ("Am", "F# minor", "Bbmaj").
+pub fn parse_key(t: &str) -> Option<ParsedKey> {
+  let t = t.trim();
+  if let Some(c) = parse_camelot(t) { return Some(ParsedKey { camelot: c, notation: KeyNotation::Camelot, explicit_mode: true }); }
+  if let Some(c) = parse_open_key(t) { return Some(ParsedKey { camelot: c, notation: KeyNotation::OpenKey, explicit_mode: true }); }
+  parse_musical(t).map(|(c, explicit)| ParsedKey { camelot: c, notation: KeyNotation::Musical, explicit_mode: explicit })
+}
+
+/// Key as stored in a tag; takes the first of "A#m/Bbm" style alternatives.
+pub fn parse_tag_key(s: &str) -> Option<Camelot> {
+  parse_key(s.split('/').next()?).map(|k| k.camelot)
+}
+
+pub fn parse_bpm(t: &str, lo: f64, hi: f64) -> Option<f64> {
+  let t = t.trim_end_matches('.');
+  if t.is_empty() || !t.chars().all(|c| c.is_ascii_digit() || c == '.') { return None; }
+  t.parse::<f64>().ok().filter(|v| (lo..=hi).contains(v))
+}
+
+const BARE_BPM: (f64, f64) = (50.0, 220.0);
+const LABELED_BPM: (f64, f64) = (20.0, 300.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint<T> {
+  pub value: T,
+  /// Exact text in the name, for renaming.
+  pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NameHints {
+  pub bpm: Option<Hint<f64>>,
+  pub key: Option<Hint<ParsedKey>>,
+}
+
+impl NameHints {
+  fn merge(&mut self, other: NameHints) {
+    if self.bpm.is_none() { self.bpm = other.bpm; }
+    if self.key.is_none() { self.key = other.key; }
+  }
+}
+
+/// Words with their original spelling; a key followed by a mode word
+/// ("A minor", "F# maj") is joined into one word.
+fn words(s: &str) -> Vec<&str> {
+  let is_word = |c: char| c.is_alphanumeric() || matches!(c, '#' | '♯' | '♭' | '.');
+  let mut raw: Vec<(usize, &str)> = Vec::new();
+  let mut start = None;
+  for (i, c) in s.char_indices().chain(std::iter::once((s.len(), ' '))) {
+    match (is_word(c), start) {
+      (true, None) => start = Some(i),
+      (false, Some(a)) => {
+        let w = s[a..i].trim_matches('.');
+        if !w.is_empty() { raw.push((a + s[a..i].find(w).unwrap_or(0), w)); }
+        start = None;
+      }
+      _ => {}
+    }
+  }
+  let mut out: Vec<&str> = Vec::new();
+  let mut i = 0;
+  while i < raw.len() {
+    let (start, w) = raw[i];
+    if let Some(&(mstart, m)) = raw.get(i + 1) {
+      let mode = matches!(m.to_lowercase().as_str(), "m" | "min" | "minor" | "maj" | "major");
+      if mode && parse_musical(w).is_some_and(|(_, explicit)| !explicit) {
+        out.push(&s[start..mstart + m.len()]);
+        i += 2;
+        continue;
+      }
+    }
+    out.push(w);
+    i += 1;
+  }
+  out
+}
+
+/// "124bpm" -> (124, "124"): the hint text is just the number so a rename keeps the suffix.
+fn labeled_bpm(w: &str) -> Option<(f64, &str)> {
+  let num = w.get(..w.len().checked_sub(3)?)?;
+  if !w[num.len()..].eq_ignore_ascii_case("bpm") { return None; }
+  parse_bpm(num, LABELED_BPM.0, LABELED_BPM.1).map(|v| (v, num))
+}
+
+/// Bracket group: every word must be a BPM, a key or a filler word.
+fn scan_group(g: &str) -> Option<NameHints> {
+  let ws = words(g);
+  let mut h = NameHints::default();
+  let mut musical_implicit = None;
+  for (i, w) in ws.iter().enumerate() {
+    let lower = w.to_lowercase();
+    if lower == "bpm" || lower == "key" { continue; }
+    let next_is_bpm = ws.get(i + 1).is_some_and(|n| n.eq_ignore_ascii_case("bpm"));
+    let (lo, hi) = if next_is_bpm { LABELED_BPM } else { BARE_BPM };
+    let bpm = labeled_bpm(w).or_else(|| parse_bpm(w, lo, hi).map(|v| (v, *w)));
+    if let Some((v, text)) = bpm {
+      if h.bpm.is_none() { h.bpm = Some(Hint { value: v, text: text.to_string() }); }
+    } else if let Some(k) = parse_key(w) {
+      let hint = Hint { value: k, text: w.to_string() };
+      if !k.explicit_mode { musical_implicit = Some(hint); } else if h.key.is_none() { h.key = Some(hint); }
+    } else {
+      return None;
+    }
+  }
+  // "(A)" alone is too likely a word; a bare note only counts next to a BPM.
+  if h.key.is_none() && h.bpm.is_some() { h.key = musical_implicit; }
+  (h.bpm.is_some() || h.key.is_some()).then_some(h)
+}
+
+fn scan_plain(s: &str) -> NameHints {
+  let ws = words(s);
+  let mut h = NameHints::default();
+  for (i, w) in ws.iter().enumerate() {
+    let next = ws.get(i + 1).copied();
+    let prev = i.checked_sub(1).map(|j| ws[j]);
+    if h.bpm.is_none() {
+      let labeled_word = [prev, next].iter().flatten().any(|o| o.eq_ignore_ascii_case("bpm"));
+      // A bare number only counts right next to a Camelot key ("124_8A").
+      let camelot_near = [prev, next].iter().flatten().any(|o| parse_camelot(o).is_some());
+      let v = labeled_bpm(w)
+        .or_else(|| labeled_word.then(|| parse_bpm(w, LABELED_BPM.0, LABELED_BPM.1)).flatten().map(|v| (v, *w)))
+        .or_else(|| camelot_near.then(|| parse_bpm(w, BARE_BPM.0, BARE_BPM.1)).flatten().map(|v| (v, *w)));
+      if let Some((v, text)) = v { h.bpm = Some(Hint { value: v, text: text.to_string() }); continue; }
+    }
+    if h.key.is_none() {
+      let after_key_word = prev.is_some_and(|p| p.eq_ignore_ascii_case("key"));
+      let next_to_bpm = [prev, next].iter().flatten()
+        .any(|o| o.eq_ignore_ascii_case("bpm") || labeled_bpm(o).is_some() || parse_bpm(o, BARE_BPM.0, BARE_BPM.1).is_some());
+      let k = (after_key_word || next_to_bpm).then(|| parse_key(w).filter(|k| k.explicit_mode)).flatten();
+      if let Some(k) = k { h.key = Some(Hint { value: k, text: w.to_string() }); }
+    }
+  }
+  h
+}
+
+/// Hints in a file stem; bracket groups win over the plain text around them.
+pub fn scan_name(name: &str) -> NameHints {
+  let mut h = NameHints::default();
+  let mut plain = String::new();
+  let mut rest = name;
+  while let Some(open) = rest.find(['(', '[', '{']) {
+    let close_ch = match &rest[open..open + 1] { "(" => ')', "[" => ']', _ => '}' };
+    plain.push_str(&rest[..open]);
+    plain.push(' ');
+    let after = &rest[open + 1..];
+    let Some(close) = after.find(close_ch) else { rest = after; continue };
+    if let Some(g) = scan_group(&after[..close]) { h.merge(g); }
+    rest = &after[close + 1..];
+  }
+  plain.push_str(rest);
+  h.merge(scan_plain(&plain));
+  h
+}
+
+/// Folder names may be nothing but the hint ("124", "8A", "A minor").
+pub fn scan_folder_name(name: &str) -> NameHints {
+  let t = name.trim();
+  if let Some(v) = parse_bpm(t, BARE_BPM.0, BARE_BPM.1) {
+    return NameHints { bpm: Some(Hint { value: v, text: t.to_string() }), key: None };
+  }
+  if let Some(k) = parse_key(t).filter(|k| k.explicit_mode) {
+    return NameHints { bpm: None, key: Some(Hint { value: k, text: t.to_string() }) };
+  }
+  scan_name(t)
+}
+
+/// Render `key` in the notation a name already uses.
+pub fn format_key(key: Camelot, notation: KeyNotation) -> String {
+  const MAJOR: [&str; 12] = ["B", "F#", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E"];
+  const MINOR: [&str; 12] = ["G#m", "D#m", "Bbm", "Fm", "Cm", "Gm", "Dm", "Am", "Em", "Bm", "F#m", "C#m"];
+  let i = (key.num - 1) as usize;
+  match notation {
+    KeyNotation::Camelot => key.to_string(),
+    KeyNotation::OpenKey => format!("{}{}", (key.num + 4) % 12 + 1, if key.minor { 'm' } else { 'd' }),
+    KeyNotation::Musical => if key.minor { MINOR[i] } else { MAJOR[i] }.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// (BPM, Camelot key) found in a file stem.
+  fn found(h: NameHints) -> (Option<f64>, Option<String>) { (h.bpm.map(|b| b.value), h.key.map(|k| k.value.camelot.to_string())) }
+
+  #[test]
+  fn file_names_as_promos_and_stores_write_them() {
+    let some = |b: Option<f64>, k: Option<&str>| (b, k.map(str::to_string));
+    for (name, want) in [
+      ("Track (124, 8A)", some(Some(124.0), Some("8A"))),
+      ("Artist - Title [Am 128]", some(Some(128.0), Some("8A"))),
+      ("Artist - Title (F# minor, 126 BPM)", some(Some(126.0), Some("11A"))),
+      ("Artist - Title [Dbm 120bpm]", some(Some(120.0), Some("12A"))),
+      ("Artist - Title [Bbmaj]", some(None, Some("6B"))),
+      ("Artist - Title [1m 124]", some(Some(124.0), Some("8A"))),
+      ("Artist - Title (A 124)", some(Some(124.0), Some("11B"))),
+      ("Artist - Title (128.00 BPM)", some(Some(128.0), None)),
+      ("Artist - Title {174}", some(Some(174.0), None)),
+      ("124bpm Artist - Title", some(Some(124.0), None)),
+      ("Artist - Title 75 BPM", some(Some(75.0), None)),
+      ("Artist - Title 8A 124", some(Some(124.0), Some("8A"))),
+      ("Artist_-_Title_124_8A", some(Some(124.0), Some("8A"))),
+      ("07 - Artist - Title - 10B - 122", some(Some(122.0), Some("10B"))),
+      ("Key Am - Artist - Title", some(None, Some("8A"))),
+      // Brackets win over the plain text around them.
+      ("125 BPM Title (128, 9A)", some(Some(128.0), Some("9A"))),
+    ] {
+      assert_eq!(found(scan_name(name)), want, "{:?}", name);
+    }
+  }
+
+  #[test]
+  fn ordinary_names_carry_no_hints() {
+    for name in [
+      "Artist - Title (Original Mix)",
+      "Artist - Title (A)",
+      "Artist - Title (99 Problems)",
+      "Artist - Title (Remix 2019)",
+      "808 State - Pacific 202",
+      "2 Unlimited - Get Ready",
+      "Artist - Am I Wrong",
+      "Blink-182 - Adam's Song",
+      "Title [Bonus Track]",
+      "Title (Part 2)",
+      "Title (",
+      "",
+    ] {
+      assert_eq!(found(scan_name(name)), (None, None), "{:?}", name);
+    }
+  }
+
+  #[test]
+  fn hint_text_is_what_a_rename_replaces() {
+    let h = scan_name("Title (124bpm, F# min)");
+    assert_eq!((h.bpm.unwrap().text, h.key.unwrap().text), ("124".to_string(), "F# min".to_string()));
+    let k = scan_name("Title [8a]").key.unwrap();
+    assert_eq!((k.text.as_str(), k.value.notation), ("8a", KeyNotation::Camelot));
+  }
+
+  #[test]
+  fn folder_names_may_be_just_the_hint() {
+    for (name, want) in [
+      ("124", (Some(124.0), None)),
+      (" 124 BPM ", (Some(124.0), None)),
+      ("8A", (None, Some("8A".to_string()))),
+      ("A minor", (None, Some("8A".to_string()))),
+      ("Am", (None, Some("8A".to_string()))),
+      ("House 124", (None, None)),
+      ("2019", (None, None)),
+      ("A", (None, None)),
+    ] {
+      assert_eq!(found(scan_folder_name(name)), want, "{:?}", name);
+    }
+  }
+
+  #[test]
+  fn enharmonic_spellings_share_a_wheel_position() {
+    let wheel = |s: &str| parse_key(s).map(|k| k.camelot);
+    assert_eq!(wheel("F#"), wheel("Gb"));
+    assert_eq!(wheel("A#m"), wheel("Bbm"));
+    assert_eq!(wheel("C#m"), wheel("Dbm"));
+    assert_eq!(wheel("D♭"), wheel("C#"));
+    assert_eq!(wheel("Am"), wheel("8A"));
+    assert_eq!(wheel("Am"), wheel("1m"));
+    assert_eq!(wheel("C major"), wheel("8B"));
+    assert_eq!(parse_tag_key("A#m/Bbm"), wheel("3A"));
+    assert_eq!(parse_key("13A"), None);
+    assert_eq!(parse_key("H"), None);
+    for num in 1..=12 {
+      for minor in [true, false] {
+        let c = Camelot { num, minor };
+        for n in [KeyNotation::Camelot, KeyNotation::OpenKey, KeyNotation::Musical] {
+          let text = format_key(c, n);
+          assert_eq!(parse_key(&text).map(|k| (k.camelot, k.notation)), Some((c, n)), "{}", text);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn bpm_ranges_depend_on_the_label() {
+    assert_eq!(parse_bpm("124.5", BARE_BPM.0, BARE_BPM.1), Some(124.5));
+    assert_eq!(parse_bpm("124.", BARE_BPM.0, BARE_BPM.1), Some(124.0));
+    assert_eq!(parse_bpm("1.2.3", BARE_BPM.0, BARE_BPM.1), None);
+    assert_eq!(found(scan_name("Title (40, 8A)")).0, None, "too slow without a label");
+    assert_eq!(found(scan_name("Title (40 BPM)")).0, Some(40.0));
+    assert_eq!(found(scan_name("Title 280bpm")).0, Some(280.0));
+    assert_eq!(found(scan_name("Title 320bpm")).0, None);
+  }
+}
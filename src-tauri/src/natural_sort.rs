@@ -0,0 +1,160 @@
+// File-name ordering shared by every listing the backend sorts: digit runs
+// compare as numbers ("Track 2" < "Track 10") and letters compare case- and
+// accent-folded ("Édith" sorts with "E", by `text_fold`'s letters whatever
+// `accent_folding` says). `sortLocaleNatural: false` in Settings restores the
+// old plain lowercase order.
+
+use std::{cmp::Ordering, path::Path, sync::atomic::{AtomicBool, Ordering as AtomicOrdering}};
+
+use crate::text_fold;
+
+pub static NATURAL: AtomicBool = AtomicBool::new(true);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Chunk {
+  // Declared first so numbers sort before words, as in Finder/Explorer.
+  Num { len: usize, digits: String, zeros: usize },
+  Text(String),
+}
+
+fn natural_key(s: &str) -> Vec<Chunk> {
+  let mut out = Vec::new();
+  let mut text = String::new();
+  let mut digits = String::new();
+  let flush_digits = |digits: &mut String, out: &mut Vec<Chunk>| {
+    if digits.is_empty() { return; }
+    let trimmed = digits.trim_start_matches('0');
+    let trimmed = if trimmed.is_empty() { "0" } else { trimmed };
+    out.push(Chunk::Num { len: trimmed.len(), digits: trimmed.to_string(), zeros: digits.len() - trimmed.len() });
+    digits.clear();
+  };
+  for c in s.chars() {
+    if c.is_ascii_digit() {
+      if !text.is_empty() { out.push(Chunk::Text(std::mem::take(&mut text))); }
+      digits.push(c);
+      continue;
+    }
+    flush_digits(&mut digits, &mut out);
+    text_fold::push_folded(c, true, &mut text);
+  }
+  flush_digits(&mut digits, &mut out);
+  if !text.is_empty() { out.push(Chunk::Text(text)); }
+  out
+}
+
+/// Sort key for a file name under the current setting. Ties between names that
+/// fold to the same key are broken by the raw name, so ordering is stable.
+pub fn sort_key(name: &str) -> (Vec<Chunk>, String) { key_for(name, NATURAL.load(AtomicOrdering::Relaxed)) }
+
+fn key_for(name: &str, natural: bool) -> (Vec<Chunk>, String) {
+  let key = if natural { natural_key(name) } else { vec![Chunk::Text(name.to_lowercase())] };
+  (key, name.to_string())
+}
+
+pub fn compare_names(a: &str, b: &str) -> Ordering { sort_key(a).cmp(&sort_key(b)) }
+
+/// Paths compared one component at a time.
+pub fn compare_paths(a: &Path, b: &Path) -> Ordering {
+  let mut ac = a.components();
+  let mut bc = b.components();
+  loop {
+    match (ac.next(), bc.next()) {
+      (None, None) => return Ordering::Equal,
+      (None, Some(_)) => return Ordering::Less,
+      (Some(_), None) => return Ordering::Greater,
+      (Some(x), Some(y)) => {
+        let o = compare_names(&x.as_os_str().to_string_lossy(), &y.as_os_str().to_string_lossy());
+        if o != Ordering::Equal { return o; }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Ascending under the natural order.
+  const ORDERED: &[&str] = &[
+    "2 Intro", "10 Intro", "Alpha", "Alpha 2", "Alpha 10", "Ångström", "Eagle", "Edith", "Édith", "Emma",
+    "Track 1", "Track 2", "Track 02", "Track 10", "Track 10b", "Track 100", "track 100",
+    "Track 99999999999999999999", "Track 100000000000000000000", "Zebra", "Zebra 🎵", "🎵 Intro",
+  ];
+
+  /// Deterministic xorshift, so a failure reproduces.
+  struct Rng(u64);
+  impl Rng {
+    fn next(&mut self) -> u64 { self.0 ^= self.0 << 13; self.0 ^= self.0 >> 7; self.0 ^= self.0 << 17; self.0 }
+    fn below(&mut self, n: usize) -> usize { (self.next() % n as u64) as usize }
+  }
+
+  fn sorted(names: &[String]) -> Vec<String> {
+    let mut v = names.to_vec();
+    v.sort_by_key(|a| key_for(a, true));
+    v
+  }
+
+  #[test]
+  fn mixed_digit_accented_and_emoji_names_sort_naturally() {
+    let want: Vec<String> = ORDERED.iter().map(|s| s.to_string()).collect();
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    for _ in 0..200 {
+      let mut shuffled = want.clone();
+      for i in (1..shuffled.len()).rev() { shuffled.swap(i, rng.below(i + 1)); }
+      assert_eq!(sorted(&shuffled), want);
+    }
+  }
+
+  /// Names built from the pieces that trip comparators up.
+  fn random_name(rng: &mut Rng) -> String {
+    const PIECES: &[&str] = &["track", "Track", "TRACK", " ", "-", "_", "é", "E", "e", "Ø", "🎵", "ß", "0", "00", "7", "10", "9", "123456789012345678901234", "a", "B"];
+    (0..1 + rng.below(5)).map(|_| PIECES[rng.below(PIECES.len())]).collect()
+  }
+
+  #[test]
+  fn the_order_is_total_and_consistent() {
+    let mut rng = Rng(42);
+    let names: Vec<String> = (0..400).map(|_| random_name(&mut rng)).collect();
+    for (a, b) in names.iter().zip(names.iter().rev()) {
+      assert_eq!(key_for(a, true).cmp(&key_for(b, true)), key_for(b, true).cmp(&key_for(a, true)).reverse(), "{:?} {:?}", a, b);
+      assert_eq!(key_for(a, true) == key_for(b, true), a == b, "only equal names tie: {:?} {:?}", a, b);
+    }
+    let v = sorted(&names);
+    for w in v.windows(2) { assert_ne!(key_for(&w[0], true).cmp(&key_for(&w[1], true)), Ordering::Greater); }
+    // Transitivity over random triples of the sorted list.
+    for _ in 0..2000 {
+      let (mut i, mut j, mut k) = (rng.below(v.len()), rng.below(v.len()), rng.below(v.len()));
+      if i > j { std::mem::swap(&mut i, &mut j); }
+      if j > k { std::mem::swap(&mut j, &mut k); }
+      if i > j { std::mem::swap(&mut i, &mut j); }
+      assert_ne!(key_for(&v[i], true).cmp(&key_for(&v[k], true)), Ordering::Greater, "{:?} {:?} {:?}", v[i], v[j], v[k]);
+    }
+  }
+
+  #[test]
+  fn numbers_compare_by_value_and_letters_by_folded_form() {
+    let mut rng = Rng(7);
+    for _ in 0..1000 {
+      let (a, b) = (rng.next() % 100_000, rng.next() % 100_000);
+      let (x, y) = (format!("Set {} end", a), format!("Set {} end", b));
+      assert_eq!(key_for(&x, true).0.cmp(&key_for(&y, true).0), a.cmp(&b), "{} {}", x, y);
+    }
+    for (a, b) in [("abc", "ABC"), ("Édith", "edith"), ("STRASSE", "straße"), ("Øre", "ore")] {
+      assert_eq!(key_for(a, true).0, key_for(b, true).0, "{} {}", a, b);
+    }
+  }
+
+  #[test]
+  fn the_fallback_is_plain_lowercase_order() {
+    let mut v = vec!["Track 2", "track 10", "Édith", "Zed"];
+    v.sort_by_key(|a| key_for(a, false));
+    assert_eq!(v, ["track 10", "Track 2", "Zed", "Édith"]);
+  }
+
+  #[test]
+  fn paths_compare_a_component_at_a_time() {
+    assert_eq!(compare_paths(Path::new("a/Disc 2/x"), Path::new("a/Disc 10/a")), Ordering::Less);
+    assert_eq!(compare_paths(Path::new("a"), Path::new("a/b")), Ordering::Less);
+    assert_eq!(compare_paths(Path::new("a b/z"), Path::new("a/b")), Ordering::Greater);
+  }
+}
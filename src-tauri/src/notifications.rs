@@ -0,0 +1,117 @@
+// OS notifications for long jobs that end while the window is in the
+// background. `JobHandle::finish` hands over every job; those shorter than
+// the threshold, opted out (`set_job_notify`) or finishing while the window
+// has focus are dropped here.
+//
+// Tauri v1 doesn't report clicks on desktop notifications, but clicking one
+// brings the app forward on macOS and Windows. So the first focus after a
+// notification (within CLICK_WINDOW) counts as its click: `job-notification-
+// opened` goes out with the job id so the UI can open that job's result.
+
+use std::{sync::atomic::{AtomicBool, AtomicU32, Ordering}, time::{Duration, Instant}};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{jobs::{JobInfo, JobStatus}, log_line};
+
+pub static ENABLED: AtomicBool = AtomicBool::new(true);
+pub static AFTER_SECS: AtomicU32 = AtomicU32::new(30);
+static FOCUSED: AtomicBool = AtomicBool::new(true);
+
+const CLICK_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Opened {
+  job_id: String,
+  kind: String,
+}
+
+/// The last notification shown, until the window is focused.
+static LAST: Lazy<Mutex<Option<(Opened, Instant)>>> = Lazy::new(|| Mutex::new(None));
+/// Set when showing one failed; cleared when notifications are re-enabled.
+static UNAVAILABLE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Why notifications won't show here, when that's known before trying. macOS
+/// only delivers them to a bundled .app (a bare dev binary's are dropped
+/// without an error) and asks the user on the first one; Windows shows them
+/// for any binary, under PowerShell's name unless the installer registered
+/// ours; Linux needs a notification daemon, which only a failed `show` tells.
+fn blocked() -> Option<String> {
+  if let Some(r) = UNAVAILABLE.lock().clone() { return Some(r); }
+  #[cfg(target_os = "macos")]
+  {
+    let bundled = std::env::current_exe().ok().is_some_and(|p| p.to_string_lossy().contains(".app/Contents/MacOS/"));
+    if !bundled { return Some("macOS only shows notifications from the bundled app".into()); }
+  }
+  None
+}
+
+fn show(app: &tauri::AppHandle, title: &str, body: &str) -> Result<(), String> {
+  tauri::api::notification::Notification::new(&app.config().tauri.bundle.identifier)
+    .title(title)
+    .body(body)
+    .show()
+    .map_err(|e| e.to_string())
+}
+
+fn took_text(d: Duration) -> String {
+  let s = d.as_secs();
+  match (s / 3600, s / 60 % 60, s % 60) {
+    (0, 0, s) => format!("{}s", s),
+    (0, m, s) => format!("{}m {:02}s", m, s),
+    (h, m, _) => format!("{}h {:02}m", h, m),
+  }
+}
+
+/// Called by `JobHandle::finish` for every job.
+pub fn job_finished(app: &tauri::AppHandle, job: &JobInfo, status: JobStatus, took: Duration, error: Option<&str>) {
+  if !ENABLED.load(Ordering::Relaxed) || FOCUSED.load(Ordering::Relaxed) { return; }
+  if took < Duration::from_secs(AFTER_SECS.load(Ordering::Relaxed) as u64) { return; }
+  if blocked().is_some() { return; }
+  let counts = if job.total > 0 { format!("{}/{} · ", job.done, job.total) } else { String::new() };
+  let kind = &job.kind;
+  let (title, body) = match status {
+    JobStatus::Done => (format!("{} finished", kind), format!("{}{}", counts, took_text(took))),
+    JobStatus::Cancelled => (format!("{} cancelled", kind), format!("{}{}", counts, took_text(took))),
+    JobStatus::Failed => (format!("{} failed", kind), format!("{}{} · {}", counts, took_text(took), error.unwrap_or("unknown error"))),
+  };
+  match show(app, &title, &body) {
+    Ok(()) => *LAST.lock() = Some((Opened { job_id: job.job_id.clone(), kind: kind.clone() }, Instant::now())),
+    Err(e) => {
+      log_line(&format!("notifications unavailable: {}", e));
+      *UNAVAILABLE.lock() = Some(e);
+    }
+  }
+}
+
+/// Window focus changes, from the run loop.
+pub fn focus_changed(app: &tauri::AppHandle, focused: bool) {
+  FOCUSED.store(focused, Ordering::Relaxed);
+  if !focused { return; }
+  if let Some((opened, at)) = LAST.lock().take() {
+    if at.elapsed() <= CLICK_WINDOW { let _ = app.emit_all("job-notification-opened", opened); }
+  }
+}
+
+/// Settings changed: a re-enable gets another try after a failed `show`.
+pub fn set_enabled(enabled: bool) {
+  if enabled && !ENABLED.load(Ordering::Relaxed) { *UNAVAILABLE.lock() = None; }
+  ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationStatus {
+  enabled: bool,
+  after_secs: u32,
+  /// Why they won't show on this system, if known.
+  unavailable: Option<String>,
+}
+
+#[tauri::command]
+pub fn notification_status() -> NotificationStatus {
+  NotificationStatus { enabled: ENABLED.load(Ordering::Relaxed), after_secs: AFTER_SECS.load(Ordering::Relaxed), unavailable: blocked() }
+}
@@ -0,0 +1,244 @@
+// "Now tagging" page for a second screen on the media server. The frontend
+// reports the selected track with `set_now_showing`; `/now` renders it as a
+// small HTML page and `/now.json` serves the same view, which the page polls
+// with `?since=` so unchanged state costs a 204. Edits to the track shown
+// (every `track_updates::emit` for its path) count as changes too. Both need the session API
+// token as `?token=` (a browser can't send X-Api-Token on navigation) and
+// only answer to a localhost Host header, which keeps DNS-rebinding pages
+// out even though the server already listens on 127.0.0.1 only.
+
+use std::path::Path;
+use hyper::{Body, Request, Response, StatusCode, header};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{api, artwork, front_cover, read_tagged, split_comment_tokens, track_meta_from, urls, AppState, MediaBase};
+
+const ART_PX: u32 = 320;
+const POLL_MS: u32 = 1500;
+
+#[derive(Debug, Default)]
+pub struct NowShowing {
+  /// Bumped on every change; `/now.json?since=` compares against it.
+  seq: u64,
+  path: Option<String>,
+}
+
+impl NowShowing {
+  /// Show `path`; the new `seq`, or `None` when it already was.
+  fn show(&mut self, path: Option<String>) -> Option<u64> {
+    if self.path == path { return None; }
+    self.path = path;
+    self.seq += 1;
+    Some(self.seq)
+  }
+
+  /// `path` was edited; the new `seq` when it is the one shown.
+  fn edited(&mut self, path: &str) -> Option<u64> {
+    if self.path.as_deref() != Some(path) { return None; }
+    self.seq += 1;
+    Some(self.seq)
+  }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NowShowingChanged {
+  seq: u64,
+  path: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NowView {
+  seq: u64,
+  path: Option<String>,
+  file_name: Option<String>,
+  title: Option<String>,
+  artist: Option<String>,
+  /// Comment tokens, bank markers left out.
+  tags: Vec<String>,
+  /// Downsized front cover as a data URL.
+  artwork: Option<String>,
+  error: Option<String>,
+}
+
+/// Track the second-screen page shows; `None` clears it. Emits
+/// `now-showing-changed` ({ seq, path }) when it actually changes.
+#[tauri::command]
+pub fn set_now_showing(app: tauri::AppHandle, state: tauri::State<'_, AppState>, path: Option<String>) {
+  let Some(seq) = state.now_showing.write().show(path.clone()) else { return };
+  let _ = app.emit_all("now-showing-changed", NowShowingChanged { seq, path });
+}
+
+/// From `track_updates::emit`: bumps `seq` when `path` is the track shown, so
+/// the page's next poll picks up the new tags.
+pub fn edited(app: &tauri::AppHandle, path: &str) {
+  let Some(seq) = app.state::<AppState>().now_showing.write().edited(path) else { return };
+  let _ = app.emit_all("now-showing-changed", NowShowingChanged { seq, path: Some(path.to_string()) });
+}
+
+/// Address of the page, token included; `None` until the media server is up.
+#[tauri::command]
+pub fn now_page_url(state: tauri::State<'_, AppState>) -> Option<String> {
+  match &*state.media_base.read() {
+    MediaBase::Ready(base) => Some(format!("{}/now?token={}", base, urls::encode_value(api::token()))),
+    _ => None,
+  }
+}
+
+fn view(seq: u64, path: Option<String>) -> NowView {
+  let Some(path) = path else { return NowView { seq, ..Default::default() } };
+  let p = Path::new(&path);
+  let mut v = NowView {
+    seq,
+    file_name: p.file_name().map(|n| n.to_string_lossy().to_string()),
+    path: Some(path.clone()),
+    ..Default::default()
+  };
+  match read_tagged(p) {
+    Ok(tf) => {
+      let meta = track_meta_from(&path, &tf, false);
+      v.title = meta.title;
+      v.artist = (!meta.artists.is_empty()).then(|| meta.artists.join(", "));
+      v.tags = split_comment_tokens(&meta.comment).into_iter().filter(|t| !t.starts_with("TagB:")).collect();
+      v.artwork = front_cover(&tf).and_then(|pic| artwork::thumbnail(pic.data(), ART_PX));
+    }
+    Err(e) => v.error = Some(e.to_string()),
+  }
+  v
+}
+
+fn escape(s: &str) -> String {
+  s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;").replace('{', "&#123;")
+}
+
+fn render(v: &NowView) -> String {
+  let title = v.title.as_deref().or(v.file_name.as_deref()).unwrap_or("Nothing selected");
+  let tags: String = v.tags.iter().map(|t| format!("<span class=\"tag\">{}</span>", escape(t))).collect();
+  PAGE
+    .replace("{{title}}", &escape(title))
+    .replace("{{artist}}", &escape(v.artist.as_deref().unwrap_or_default()))
+    .replace("{{tags}}", &tags)
+    .replace("{{art}}", v.artwork.as_deref().unwrap_or_default())
+    .replace("{{art_hidden}}", if v.artwork.is_some() { "" } else { "hidden" })
+    .replace("{{seq}}", &v.seq.to_string())
+    .replace("{{poll_ms}}", &POLL_MS.to_string())
+}
+
+/// Host header names this machine (`localhost`, `127.0.0.1`, `[::1]`).
+fn local_host(req: &Request<Body>) -> bool {
+  let Some(host) = req.headers().get(header::HOST).and_then(|v| v.to_str().ok()) else { return false };
+  let name = match host.rsplit_once(':') {
+    Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+    _ => host,
+  };
+  matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+fn respond(status: StatusCode, ctype: &str, body: impl Into<Body>) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, ctype)
+    .header(header::CACHE_CONTROL, "no-store")
+    .body(body.into())
+    .unwrap()
+}
+
+/// Entry point for `/now` and `/now.json` on the media server.
+pub async fn handle(app: tauri::AppHandle, req: Request<Body>) -> Response<Body> {
+  let text = "text/plain; charset=utf-8";
+  if !local_host(&req) { return respond(StatusCode::FORBIDDEN, text, "localhost only"); }
+  if !urls::query_param(req.uri(), "token").is_some_and(|t| api::token_matches(&t)) {
+    return respond(StatusCode::UNAUTHORIZED, text, "missing or invalid ?token=");
+  }
+  let state = app.state::<AppState>();
+  let (seq, path) = {
+    let now = state.now_showing.read();
+    (now.seq, now.path.clone())
+  };
+  let json = req.uri().path() == "/now.json";
+  if json && urls::query_param(req.uri(), "since").and_then(|s| s.parse::<u64>().ok()) == Some(seq) {
+    return respond(StatusCode::NO_CONTENT, text, Body::empty());
+  }
+  let v = match tauri::async_runtime::spawn_blocking(move || view(seq, path)).await {
+    Ok(v) => v,
+    Err(e) => return respond(StatusCode::INTERNAL_SERVER_ERROR, text, e.to_string()),
+  };
+  if json {
+    respond(StatusCode::OK, "application/json", serde_json::to_vec(&v).unwrap_or_default())
+  } else {
+    respond(StatusCode::OK, "text/html; charset=utf-8", render(&v))
+  }
+}
+
+const PAGE: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Now tagging</title>
+<style>
+  body { margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center; background: #111; color: #eee; font: 18px system-ui, sans-serif; }
+  main { display: flex; gap: 2rem; align-items: center; padding: 2rem; max-width: 60rem; }
+  img { width: 320px; height: 320px; object-fit: cover; border-radius: 6px; background: #222; }
+  h1 { margin: 0 0 .25em; font-size: 2.2em; }
+  #artist { margin: 0 0 1em; color: #aaa; font-size: 1.3em; }
+  .tag { display: inline-block; margin: 0 .4em .4em 0; padding: .2em .6em; border-radius: 1em; background: #2a4d6e; }
+</style>
+</head>
+<body>
+<main>
+  <img id="art" src="{{art}}" alt="" {{art_hidden}}>
+  <div>
+    <h1 id="title">{{title}}</h1>
+    <p id="artist">{{artist}}</p>
+    <div id="tags">{{tags}}</div>
+  </div>
+</main>
+<script>
+  const token = new URLSearchParams(location.search).get("token") || "";
+  let seq = {{seq}};
+  function show(v) {
+    document.getElementById("title").textContent = v.title || v.fileName || "Nothing selected";
+    document.getElementById("artist").textContent = v.artist || "";
+    document.getElementById("tags").replaceChildren(...v.tags.map((t) => {
+      const s = document.createElement("span");
+      s.className = "tag";
+      s.textContent = t;
+      return s;
+    }));
+    const art = document.getElementById("art");
+    art.hidden = !v.artwork;
+    if (v.artwork) art.src = v.artwork;
+  }
+  async function poll() {
+    try {
+      const r = await fetch(`/now.json?token=${encodeURIComponent(token)}&since=${seq}`, { cache: "no-store" });
+      if (r.status === 200) { const v = await r.json(); seq = v.seq; show(v); }
+    } catch (e) {
+      // App closed or restarting; keep trying.
+    }
+    setTimeout(poll, {{poll_ms}});
+  }
+  setTimeout(poll, {{poll_ms}});
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn edits_to_the_shown_track_bump_seq() {
+    let mut now = NowShowing::default();
+    assert_eq!(now.show(Some("/a.mp3".into())), Some(1));
+    assert_eq!(now.show(Some("/a.mp3".into())), None);
+    assert_eq!(now.edited("/a.mp3"), Some(2));
+    assert_eq!(now.edited("/b.mp3"), None);
+    assert_eq!(now.show(None), Some(3));
+    assert_eq!(now.edited("/a.mp3"), None);
+  }
+}
@@ -0,0 +1,176 @@
+// Cover colors for tinting list rows. The embedded front cover is shrunk to
+// SAMPLE_PX and clustered into PALETTE_SIZE colors with a few rounds of
+// k-means (seeded at luminance quantiles, so the result is deterministic);
+// the biggest cluster is the dominant color. Tracks without art, or whose
+// art can't be decoded, get the fixed NEUTRAL palette; grayscale covers keep
+// their own grays and are flagged `neutral` too. Cached in memory by
+// path + size + mtime, like preview gains; idle `maintenance` drops entries
+// of deleted files and the least recently used past the budget.
+
+use std::{collections::HashMap, path::{Path, PathBuf}, time::Instant};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{front_cover, jobs::JobHandle, log_line, maintenance, profile, read_folder, read_tagged, watcher::{stamp_of, FileStamp}};
+
+const SAMPLE_PX: u32 = 64;
+const PALETTE_SIZE: usize = 4;
+const ROUNDS: usize = 8;
+/// HSV saturation below which every cluster counts as gray.
+const GRAY_SATURATION: f32 = 0.08;
+const NEUTRAL: [&str; PALETTE_SIZE] = ["#3a3a3a", "#5c5c5c", "#7f7f7f", "#a3a3a3"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Palette {
+  path: String,
+  dominant: String,
+  /// Biggest cluster first.
+  colors: Vec<String>,
+  /// No usable color: missing or undecodable art, or a grayscale cover.
+  neutral: bool,
+  has_art: bool,
+}
+
+/// Stamp, palette, last use.
+type Cached = (FileStamp, Palette, Instant);
+static CACHE: Lazy<Mutex<HashMap<PathBuf, Cached>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+type Rgb = [f32; 3];
+
+fn hex(c: Rgb) -> String {
+  let b = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+  format!("#{:02x}{:02x}{:02x}", b(c[0]), b(c[1]), b(c[2]))
+}
+
+fn luma(c: &Rgb) -> f32 { 0.299 * c[0] + 0.587 * c[1] + 0.114 * c[2] }
+
+fn saturation(c: &Rgb) -> f32 {
+  let max = c[0].max(c[1]).max(c[2]);
+  let min = c[0].min(c[1]).min(c[2]);
+  if max <= 0.0 { 0.0 } else { (max - min) / max }
+}
+
+fn dist2(a: &Rgb, b: &Rgb) -> f32 { (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum() }
+
+/// Cluster centers with their pixel counts, biggest first.
+fn kmeans(pixels: &[Rgb]) -> Vec<(Rgb, usize)> {
+  let mut by_luma = pixels.to_vec();
+  by_luma.sort_by(|a, b| luma(a).total_cmp(&luma(b)));
+  let mut centers: Vec<Rgb> = (0..PALETTE_SIZE).map(|k| by_luma[(2 * k + 1) * by_luma.len() / (2 * PALETTE_SIZE)]).collect();
+  let mut counts = [0usize; PALETTE_SIZE];
+  for _ in 0..ROUNDS {
+    let mut sums = [[0f32; 3]; PALETTE_SIZE];
+    counts.iter_mut().for_each(|c| *c = 0);
+    for px in pixels {
+      let k = (0..PALETTE_SIZE).min_by(|&a, &b| dist2(px, &centers[a]).total_cmp(&dist2(px, &centers[b]))).unwrap_or(0);
+      (0..3).for_each(|i| sums[k][i] += px[i]);
+      counts[k] += 1;
+    }
+    for k in 0..PALETTE_SIZE {
+      // An empty cluster keeps its seed.
+      if counts[k] > 0 { centers[k] = sums[k].map(|s| s / counts[k] as f32); }
+    }
+  }
+  let mut out: Vec<(Rgb, usize)> = centers.into_iter().zip(counts).collect();
+  out.sort_by_key(|c| std::cmp::Reverse(c.1));
+  out
+}
+
+fn neutral(path: &str, has_art: bool) -> Palette {
+  Palette { path: path.to_string(), dominant: NEUTRAL[0].into(), colors: NEUTRAL.iter().map(|c| c.to_string()).collect(), neutral: true, has_art }
+}
+
+fn compute(path: &str) -> Palette {
+  let Ok(tf) = read_tagged(path) else { return neutral(path, false) };
+  let Some(pic) = front_cover(&tf) else { return neutral(path, false) };
+  let Ok(img) = image::load_from_memory(pic.data()) else { return neutral(path, true) };
+  let small = img.thumbnail(SAMPLE_PX, SAMPLE_PX).to_rgb8();
+  let pixels: Vec<Rgb> = small.pixels().map(|p| p.0.map(|v| v as f32)).collect();
+  if pixels.len() < PALETTE_SIZE { return neutral(path, true); }
+  let clusters = kmeans(&pixels);
+  let colors: Vec<String> = clusters.iter().map(|(c, _)| hex(*c)).collect();
+  Palette {
+    path: path.to_string(),
+    dominant: colors[0].clone(),
+    neutral: clusters.iter().all(|(c, _)| saturation(c) < GRAY_SATURATION),
+    colors,
+    has_art: true,
+  }
+}
+
+fn cached(p: &Path, stamp: FileStamp) -> Option<Palette> {
+  let mut cache = CACHE.lock();
+  let (_, pal, used) = cache.get_mut(p).filter(|(s, ..)| *s == stamp)?;
+  *used = Instant::now();
+  Some(pal.clone())
+}
+
+pub fn cache_len() -> usize { CACHE.lock().len() }
+
+/// Drop entries of files that are gone, then the least recently used past
+/// `max_entries`. Returns (pruned, evicted); `None` when `stop` cut it short.
+pub fn maintain(max_entries: usize, stop: &dyn Fn() -> bool) -> Option<(usize, usize)> {
+  let paths: Vec<PathBuf> = CACHE.lock().keys().cloned().collect();
+  let mut gone = Vec::new();
+  for (i, p) in paths.into_iter().enumerate() {
+    if i % 64 == 0 && stop() { return None; }
+    if maintenance::source_gone(&p) { gone.push(p); }
+  }
+  let mut cache = CACHE.lock();
+  let pruned = gone.iter().filter(|p| cache.remove(*p).is_some()).count();
+  let over = cache.len().saturating_sub(max_entries);
+  if over > 0 {
+    let mut order: Vec<(Instant, PathBuf)> = cache.iter().map(|(p, (.., used))| (*used, p.clone())).collect();
+    order.sort_unstable();
+    for (_, p) in order.into_iter().take(over) { cache.remove(&p); }
+  }
+  Some((pruned, over))
+}
+
+fn palette_for(path: &str) -> Result<Palette, String> {
+  let p = PathBuf::from(path);
+  let stamp = stamp_of(&p).ok_or_else(|| format!("file not found: {}", path))?;
+  if let Some(pal) = cached(&p, stamp) { return Ok(pal); }
+  let pal = compute(path);
+  CACHE.lock().insert(p, (stamp, pal.clone(), Instant::now()));
+  Ok(pal)
+}
+
+/// Dominant color and palette of the track's front cover, as "#rrggbb".
+/// Missing or broken art gives the neutral palette, not an error.
+#[tauri::command]
+pub fn artwork_palette(path: String) -> Result<Palette, String> { palette_for(&path) }
+
+/// Fill the cache for every file in `folder` on a background "palettes" job
+/// (call after a scan). Each computed entry arrives as an `artwork-palette`
+/// event with the `Palette` shape; already cached files are skipped. Under
+/// the light profile the job stops without computing anything.
+#[tauri::command]
+pub fn warm_artwork_palettes(app: tauri::AppHandle, folder: String) -> String {
+  let job = JobHandle::start(&app, "palettes", &folder);
+  let job_id = job.id().to_string();
+  std::thread::spawn(move || {
+    let res = read_folder(&folder, false).map_err(String::from).map(|files| {
+      let mut computed = 0usize;
+      for (i, f) in files.iter().enumerate() {
+        if job.is_cancelled() || !profile::background_allowed() { break; }
+        let p = Path::new(&f.path);
+        let fresh = stamp_of(p).is_some_and(|s| cached(p, s).is_some());
+        if !fresh {
+          if let Ok(pal) = job.timed("parse", || palette_for(&f.path)) {
+            let _ = app.emit_all("artwork-palette", pal);
+            computed += 1;
+          }
+        }
+        job.progress(i as u64 + 1, files.len() as u64);
+      }
+      computed
+    });
+    if let Ok(n) = &res { log_line(&format!("warm_artwork_palettes folder=\"{}\" computed={}", folder, n)); }
+    job.finish(&res);
+  });
+  job_id
+}
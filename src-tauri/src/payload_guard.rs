@@ -0,0 +1,169 @@
+// Cap on the size of a command's answer. The webview takes a reply as one JSON
+// string, and a few hundred megabytes of it (full-size art in a deep read, the
+// listing of a huge folder) stalls or crashes it. Commands that can answer
+// that big return `guard(name, value)`: the value itself when its JSON is
+// within `ipc_payload_cap_mb` (Settings, default 8), else the JSON is written
+// to data dir `responses/` and the answer is a `Chunked` descriptor the
+// frontend reads back with `read_response_chunk` (`resolveChunked` in
+// src/tauri.ts). Handles expire RESPONSE_TTL after they were made, or when
+// `release_response` says the frontend is done.
+
+use std::{collections::HashMap, fs, io::{self, BufWriter, Read, Seek, SeekFrom, Write}, path::PathBuf, sync::atomic::{AtomicU64, Ordering}, time::{Duration, Instant}};
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{data_dir, log_line};
+
+pub const DEFAULT_CAP_MB: u64 = 8;
+const RESPONSE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Largest chunk `read_response_chunk` hands out.
+const MAX_CHUNK: usize = 4 * 1024 * 1024;
+
+pub static CAP: AtomicU64 = AtomicU64::new(DEFAULT_CAP_MB * 1024 * 1024);
+
+struct Stored {
+  path: PathBuf,
+  total: u64,
+  at: Instant,
+}
+
+/// Created on first use, dropping whatever an earlier run left behind.
+static STORED: Lazy<Mutex<HashMap<String, Stored>>> = Lazy::new(|| {
+  let _ = fs::remove_dir_all(dir());
+  Mutex::new(HashMap::new())
+});
+
+fn dir() -> PathBuf { data_dir().join("responses") }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunked {
+  chunked: bool,
+  handle: String,
+  total_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Guarded<T> {
+  Inline(T),
+  Chunked(Chunked),
+}
+
+/// Counts what is written; fails once past `cap` so a big value stops early.
+struct Counter { n: u64, cap: u64 }
+
+impl Write for Counter {
+  fn write(&mut self, b: &[u8]) -> io::Result<usize> {
+    self.n += b.len() as u64;
+    if self.n > self.cap { return Err(io::ErrorKind::OutOfMemory.into()); }
+    Ok(b.len())
+  }
+  fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+fn sweep(stored: &mut HashMap<String, Stored>) {
+  stored.retain(|_, s| {
+    let keep = s.at.elapsed() < RESPONSE_TTL;
+    if !keep { let _ = fs::remove_file(&s.path); }
+    keep
+  });
+}
+
+fn new_handle() -> String {
+  static SEQ: AtomicU64 = AtomicU64::new(0);
+  format!("{}-{}", chrono::Local::now().format("%Y%m%d%H%M%S"), SEQ.fetch_add(1, Ordering::Relaxed))
+}
+
+/// `value` as it is when its JSON fits the cap, else stored for chunked reads
+/// (see the header). `command` is for the log.
+pub fn guard<T: Serialize>(command: &str, value: T) -> Result<Guarded<T>, String> {
+  guard_at(command, value, CAP.load(Ordering::Relaxed))
+}
+
+fn guard_at<T: Serialize>(command: &str, value: T, cap: u64) -> Result<Guarded<T>, String> {
+  if serde_json::to_writer(Counter { n: 0, cap }, &value).is_ok() { return Ok(Guarded::Inline(value)); }
+
+  let mut stored = STORED.lock();
+  sweep(&mut stored);
+  fs::create_dir_all(dir()).map_err(|e| e.to_string())?;
+  let handle = new_handle();
+  let path = dir().join(format!("{}.json", handle));
+  let mut w = BufWriter::new(fs::File::create(&path).map_err(|e| e.to_string())?);
+  let written = serde_json::to_writer(&mut w, &value).map_err(|e| e.to_string()).and_then(|_| w.flush().map_err(|e| e.to_string()));
+  drop(w);
+  if let Err(e) = written { let _ = fs::remove_file(&path); return Err(e); }
+  let total = fs::metadata(&path).map(|m| m.len()).map_err(|e| e.to_string())?;
+  log_line(&format!("{} answer of {} bytes is over the {} byte cap; chunked as {}", command, total, cap, handle));
+  stored.insert(handle.clone(), Stored { path, total, at: Instant::now() });
+  Ok(Guarded::Chunked(Chunked { chunked: true, handle, total_bytes: total }))
+}
+
+/// Up to `len` bytes (at most MAX_CHUNK) of a stored answer from `offset`,
+/// base64; empty at the end.
+#[tauri::command]
+pub fn read_response_chunk(handle: String, offset: u64, len: usize) -> Result<String, String> {
+  let mut stored = STORED.lock();
+  sweep(&mut stored);
+  let s = stored.get(&handle).ok_or_else(|| format!("response {} has expired or was never made", handle))?;
+  let len = len.min(MAX_CHUNK) as u64;
+  let len = len.min(s.total.saturating_sub(offset)) as usize;
+  let mut f = fs::File::open(&s.path).map_err(|e| e.to_string())?;
+  f.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+  let mut buf = vec![0u8; len];
+  f.read_exact(&mut buf).map_err(|e| e.to_string())?;
+  Ok(general_purpose::STANDARD.encode(buf))
+}
+
+/// Drop a stored answer once it has been read.
+#[tauri::command]
+pub fn release_response(handle: String) {
+  if let Some(s) = STORED.lock().remove(&handle) { let _ = fs::remove_file(&s.path); }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The stored answer read back `step` bytes at a time, as `resolveChunked` does.
+  fn reassemble(c: &Chunked, step: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+      let part = general_purpose::STANDARD.decode(read_response_chunk(c.handle.clone(), out.len() as u64, step).unwrap()).unwrap();
+      if part.is_empty() { break; }
+      out.extend(part);
+    }
+    out
+  }
+
+  #[derive(Serialize)]
+  struct Big { names: Vec<String>, art: Vec<u8> }
+
+  #[test]
+  fn an_answer_over_the_cap_reads_back_byte_for_byte() {
+    let value = Big {
+      names: (0..500).map(|i| format!("Ünïcödé \"{}\" \u{1F3B5}.mp3", i)).collect(),
+      art: (0..40_000u32).map(|i| (i * 7919 % 251) as u8).collect(),
+    };
+    let want = serde_json::to_vec(&value).unwrap();
+    let Guarded::Chunked(c) = guard_at("test", &value, 4096).unwrap() else { panic!("expected the chunked path") };
+    assert!(c.chunked);
+    assert_eq!(c.total_bytes, want.len() as u64);
+    // Odd steps split multi-byte characters across chunks; a huge one is clamped to MAX_CHUNK.
+    for step in [1000, 4093, usize::MAX] { assert!(reassemble(&c, step) == want, "step {}", step); }
+
+    release_response(c.handle.clone());
+    assert!(read_response_chunk(c.handle, 0, 10).is_err());
+  }
+
+  #[test]
+  fn an_answer_within_the_cap_stays_inline() {
+    let value = vec![1u8; 100];
+    let json = serde_json::to_string(&guard_at("test", &value, 4096).unwrap()).unwrap();
+    assert_eq!(json, serde_json::to_string(&value).unwrap());
+    let Guarded::Chunked(c) = guard_at("test", &value, 10).unwrap() else { panic!("expected the chunked path") };
+    release_response(c.handle);
+  }
+}
@@ -0,0 +1,246 @@
+// Waveform peaks: per-bucket min/max of the mono-mixed signal, cached in the
+// data dir keyed by path + size + mtime so an edited file gets recomputed.
+// Cache files hold raw linear data; display transforms happen at use time.
+// Served as JSON on the media server at `/peaks?path=…[&normalize=1][&log=1]`.
+// `sources.json` next to the cache files names the audio file of each, so
+// idle `maintenance` can drop the ones of deleted or since-edited files; a
+// cache hit bumps the file's mtime, which orders eviction past the budget.
+
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}, sync::atomic::AtomicBool, time::{SystemTime, UNIX_EPOCH}};
+use hyper::{Body, Request, Response, StatusCode, header};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{add_cors_headers, data_dir, decode, log, maintenance, LogLevel, urls, watcher::stamp_of, write_atomic};
+
+pub const PEAKS_VERSION: u32 = 1;
+/// Version of the `/peaks` response schema (the frontend keeps its own copies).
+pub const PEAKS_RESPONSE_VERSION: u32 = 1;
+/// Bottom of the `log` display range; quieter samples map to 0.
+const LOG_FLOOR_DB: f32 = -60.0;
+/// Bucket count when the container declares its length; enough for a 4K-wide render.
+const TARGET_BUCKETS: u64 = 4000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Peaks {
+  pub version: u32,
+  pub sample_rate: u32,
+  pub frames_per_bucket: u64,
+  pub duration_secs: f64,
+  pub min: Vec<f32>,
+  pub max: Vec<f32>,
+}
+
+/// Cache file name -> audio path; loaded on first use.
+static SOURCES: Lazy<Mutex<Option<HashMap<String, String>>>> = Lazy::new(|| Mutex::new(None));
+
+fn peaks_dir() -> PathBuf { data_dir().join("peaks") }
+
+fn sources_path() -> PathBuf { peaks_dir().join("sources.json") }
+
+fn sources(s: &mut Option<HashMap<String, String>>) -> &mut HashMap<String, String> {
+  s.get_or_insert_with(|| fs::read(sources_path()).ok().and_then(|b| serde_json::from_slice(&b).ok()).unwrap_or_default())
+}
+
+fn save_sources(map: &HashMap<String, String>) -> Result<(), String> {
+  write_atomic(&sources_path(), &serde_json::to_vec(map).map_err(|e| e.to_string())?)
+}
+
+fn cache_path(p: &Path) -> Option<PathBuf> {
+  let stamp = stamp_of(p)?;
+  let mtime = stamp.modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_millis()).unwrap_or(0);
+  let mut h = Sha256::new();
+  h.update(p.to_string_lossy().as_bytes());
+  h.update(format!("|{}|{}", stamp.len, mtime).as_bytes());
+  let hex: String = h.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+  Some(peaks_dir().join(format!("{}.json", hex)))
+}
+
+pub fn cached_peaks(p: &Path) -> Option<Peaks> {
+  let cp = cache_path(p)?;
+  let raw = fs::read(&cp).ok()?;
+  let pk = serde_json::from_slice::<Peaks>(&raw).ok().filter(|pk| pk.version == PEAKS_VERSION)?;
+  let _ = fs::File::options().write(true).open(&cp).and_then(|f| f.set_modified(SystemTime::now()));
+  Some(pk)
+}
+
+/// Decode and bucket. `on_progress(done_frames, total_frames)` fires per block
+/// (total is 0 when the container doesn't declare a length).
+pub fn compute_peaks(p: &Path, cancel: Option<&AtomicBool>, mut on_progress: impl FnMut(u64, u64)) -> Result<Peaks, String> {
+  let mut fpb: u64 = 0;
+  let (mut lo, mut hi, mut n) = (0f32, 0f32, 0u64);
+  let mut frames: u64 = 0;
+  let (mut min, mut max) = (Vec::new(), Vec::new());
+
+  let info = decode::decode_f32(p, cancel, |info, samples| {
+    if fpb == 0 {
+      fpb = match info.n_frames {
+        Some(total) if total > 0 => total.div_ceil(TARGET_BUCKETS).max(1),
+        // Unknown length: 10 ms buckets.
+        _ => (info.sample_rate as u64 / 100).max(1),
+      };
+    }
+    for frame in samples.chunks(info.channels) {
+      let v = frame.iter().sum::<f32>() / frame.len() as f32;
+      if n == 0 { lo = v; hi = v; } else { lo = lo.min(v); hi = hi.max(v); }
+      n += 1;
+      if n == fpb {
+        min.push(lo);
+        max.push(hi);
+        n = 0;
+      }
+    }
+    frames += (samples.len() / info.channels) as u64;
+    on_progress(frames, info.n_frames.unwrap_or(0));
+    true
+  })?;
+  if n > 0 { min.push(lo); max.push(hi); }
+
+  Ok(Peaks {
+    version: PEAKS_VERSION,
+    sample_rate: info.sample_rate,
+    frames_per_bucket: fpb.max(1),
+    duration_secs: frames as f64 / info.sample_rate.max(1) as f64,
+    min,
+    max,
+  })
+}
+
+/// Cached peaks if fresh, otherwise compute and store them.
+pub fn get_or_compute_peaks(p: &Path, cancel: Option<&AtomicBool>, on_progress: impl FnMut(u64, u64)) -> Result<Peaks, String> {
+  if let Some(pk) = cached_peaks(p) { return Ok(pk); }
+  let pk = compute_peaks(p, cancel, on_progress)?;
+  if let Some(cp) = cache_path(p) {
+    let res = fs::create_dir_all(peaks_dir()).map_err(|e| e.to_string())
+      .and_then(|_| serde_json::to_vec(&pk).map_err(|e| e.to_string()))
+      .and_then(|bytes| write_atomic(&cp, &bytes))
+      .and_then(|_| {
+        let mut s = SOURCES.lock();
+        let map = sources(&mut s);
+        map.insert(cp.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(), p.to_string_lossy().to_string());
+        save_sources(map)
+      });
+    if let Err(e) = res { log(LogLevel::Warn, &format!("peaks cache write failed path=\"{}\": {}", p.display(), e)); }
+  }
+  Ok(pk)
+}
+
+/// Cache files (`sources.json` aside) as (path, bytes, mtime).
+fn cache_files() -> Vec<(PathBuf, u64, SystemTime)> {
+  let Ok(rd) = fs::read_dir(peaks_dir()) else { return Vec::new() };
+  rd.flatten()
+    .filter(|e| e.file_name() != "sources.json")
+    .filter_map(|e| { let m = e.metadata().ok()?; Some((e.path(), m.len(), m.modified().ok()?)) })
+    .collect()
+}
+
+/// Cache files and their total size.
+pub fn stats() -> (usize, u64) {
+  let files = cache_files();
+  (files.len(), files.iter().map(|f| f.1).sum())
+}
+
+/// Delete the peaks of files that are gone or have changed since, then the
+/// least recently used past `max_bytes`. Returns (pruned, evicted, bytes
+/// before, bytes after); `None` when `stop` cut the pass short.
+pub fn maintain(max_bytes: u64, stop: &dyn Fn() -> bool) -> Option<(usize, usize, u64, u64)> {
+  // Held throughout, so peaks stored meanwhile wait instead of losing their entry.
+  let mut guard = SOURCES.lock();
+  let known = sources(&mut guard);
+  let mut files = cache_files();
+  let before: u64 = files.iter().map(|f| f.1).sum();
+  let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  let mut pruned = 0;
+  for (i, (path, ..)) in files.iter().enumerate() {
+    if i % 64 == 0 && stop() { return None; }
+    let Some(src) = known.get(&name(path)).map(PathBuf::from) else { continue };
+    let stale = maintenance::source_gone(&src) || (src.exists() && cache_path(&src).as_deref() != Some(path.as_path()));
+    if stale && fs::remove_file(path).is_ok() { pruned += 1; }
+  }
+  files.retain(|f| f.0.exists());
+  let mut total: u64 = files.iter().map(|f| f.1).sum();
+  let mut evicted = 0;
+  files.sort_by_key(|f| f.2);
+  for (path, len, _) in &files {
+    if total <= max_bytes { break; }
+    if fs::remove_file(path).is_ok() { total -= len; evicted += 1; }
+  }
+  let left: HashSet<String> = cache_files().iter().map(|f| name(&f.0)).collect();
+  known.retain(|n, _| left.contains(n));
+  if let Err(e) = save_sources(known) { log(LogLevel::Warn, &format!("peaks sources save failed: {}", e)); }
+  Some((pruned, evicted, before, total))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeaksView {
+  pub version: u32,
+  pub sample_rate: u32,
+  pub frames_per_bucket: u64,
+  pub duration_secs: f64,
+  pub min: Vec<f32>,
+  pub max: Vec<f32>,
+  pub normalized: bool,
+  /// Factor applied by `normalize` (1 when off or for silent files).
+  pub scale: f32,
+  pub log: bool,
+}
+
+/// Display transform of cached peaks. `normalize` scales the loudest bucket to
+/// full scale; `log` maps magnitudes onto a dB scale from LOG_FLOOR_DB to 0,
+/// keeping the sign. Normalization is applied first.
+pub fn view(pk: &Peaks, normalize: bool, log: bool) -> PeaksView {
+  let loudest = pk.min.iter().chain(&pk.max).fold(0f32, |m, v| m.max(v.abs()));
+  let scale = if normalize && loudest > 0.0 { 1.0 / loudest } else { 1.0 };
+  let map = |v: f32| {
+    let v = (v * scale).clamp(-1.0, 1.0);
+    if !log || v == 0.0 { return v; }
+    let db = 20.0 * v.abs().log10();
+    v.signum() * (1.0 - db / LOG_FLOOR_DB).max(0.0)
+  };
+  PeaksView {
+    version: PEAKS_RESPONSE_VERSION,
+    sample_rate: pk.sample_rate,
+    frames_per_bucket: pk.frames_per_bucket,
+    duration_secs: pk.duration_secs,
+    min: pk.min.iter().map(|&v| map(v)).collect(),
+    max: pk.max.iter().map(|&v| map(v)).collect(),
+    normalized: normalize,
+    scale,
+    log,
+  }
+}
+
+fn flag(uri: &hyper::Uri, name: &str) -> bool {
+  urls::query_param(uri, name).is_some_and(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+}
+
+/// `/peaks` on the media server. Uncached files are decoded first, which can
+/// take a few seconds for long tracks.
+pub async fn handle(req: Request<Body>) -> Response<Body> {
+  let uri = req.uri();
+  let (normalize, log) = (flag(uri, "normalize"), flag(uri, "log"));
+  let respond = |status: StatusCode, ctype: &str, body: Vec<u8>| {
+    let mut resp = Response::builder()
+      .status(status)
+      .header(header::CONTENT_TYPE, ctype)
+      .body(Body::from(body))
+      .unwrap();
+    add_cors_headers(resp.headers_mut());
+    resp
+  };
+  let Some(path) = urls::query_param(uri, "path").filter(|p| Path::new(p).is_file()) else {
+    return respond(StatusCode::NOT_FOUND, "text/plain; charset=utf-8", Vec::new());
+  };
+  let res = tauri::async_runtime::spawn_blocking(move || get_or_compute_peaks(Path::new(&path), None, |_, _| {}))
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r);
+  match res {
+    Ok(pk) => respond(StatusCode::OK, "application/json", serde_json::to_vec(&view(&pk, normalize, log)).unwrap_or_default()),
+    Err(e) => respond(StatusCode::UNPROCESSABLE_ENTITY, "text/plain; charset=utf-8", e.into_bytes()),
+  }
+}
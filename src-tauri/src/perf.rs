@@ -0,0 +1,217 @@
+// In-memory timings for a debug panel and release-to-release comparisons:
+// the last RECENT_CAP command invocations (every `CmdSpan`) and the last
+// JOB_CAP jobs with their phase totals (`JobHandle::timed`), kept in
+// `AppState`. Async commands close their spans on the runtime with no app
+// handle in reach, so recording goes through the handle `init` keeps;
+// before that (and in the CLI) nothing is recorded. Nothing is written to disk.
+//
+// Outcomes: job runs always know theirs. Tauri gives the invoke wrapper no
+// view of a command's result, so an entry is `ok` unless the command
+// panicked on the invoking thread or the frontend's invoke wrapper reports
+// the call rejected (`record_command_failure`, which marks the newest `ok`
+// entry of that command).
+
+use std::{collections::{HashMap, VecDeque}, time::Duration};
+use chrono::Local;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{jobs::JobStatus, profile::{self, OperationProfile}, AppState};
+
+const RECENT_CAP: usize = 500;
+const JOB_CAP: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandTiming {
+  command: String,
+  ms: f64,
+  ok: bool,
+  at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTiming {
+  phase: String,
+  ms: f64,
+  /// How many times the phase ran (per file, usually).
+  count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobTiming {
+  kind: String,
+  label: String,
+  ms: f64,
+  status: JobStatus,
+  phases: Vec<PhaseTiming>,
+  at: String,
+}
+
+#[derive(Default)]
+struct Rings {
+  commands: VecDeque<CommandTiming>,
+  jobs: VecDeque<JobTiming>,
+}
+
+/// `AppState::perf`.
+#[derive(Default)]
+pub struct PerfLog(Mutex<Rings>);
+
+static APP: OnceCell<tauri::AppHandle> = OnceCell::new();
+
+pub fn init(app: tauri::AppHandle) { let _ = APP.set(app); }
+
+fn with_log(f: impl FnOnce(&PerfLog)) {
+  if let Some(app) = APP.get() { f(&app.state::<AppState>().perf); }
+}
+
+fn ms(d: Duration) -> f64 { d.as_secs_f64() * 1000.0 }
+
+fn push<T>(q: &mut VecDeque<T>, cap: usize, v: T) {
+  if q.len() == cap { q.pop_front(); }
+  q.push_back(v);
+}
+
+pub fn record_command(command: &str, took: Duration, ok: bool) {
+  with_log(|log| log.command(command, took, ok));
+}
+
+/// `phases`: (name, total time, runs), in first-use order.
+pub fn record_job(kind: &str, label: &str, took: Duration, status: JobStatus, phases: &[(String, Duration, u64)]) {
+  with_log(|log| log.job(kind, label, took, status, phases));
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandStats {
+  command: String,
+  count: usize,
+  /// Rejected or panicked invocations; see the header note on outcomes.
+  failures: usize,
+  p50_ms: f64,
+  p95_ms: f64,
+  max_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerfMetrics {
+  /// Over the entries in `recent`, slowest p95 first.
+  commands: Vec<CommandStats>,
+  /// Oldest first.
+  recent: Vec<CommandTiming>,
+  jobs: Vec<JobTiming>,
+  operation_profile: OperationProfile,
+}
+
+/// Nearest-rank percentile of sorted `v`.
+fn percentile(v: &[f64], p: f64) -> f64 {
+  if v.is_empty() { return 0.0; }
+  let rank = ((p / 100.0) * v.len() as f64).ceil() as usize;
+  v[rank.clamp(1, v.len()) - 1]
+}
+
+impl PerfLog {
+  fn command(&self, command: &str, took: Duration, ok: bool) {
+    let t = CommandTiming { command: command.to_string(), ms: ms(took), ok, at: Local::now().to_rfc3339() };
+    push(&mut self.0.lock().commands, RECENT_CAP, t);
+  }
+
+  fn job(&self, kind: &str, label: &str, took: Duration, status: JobStatus, phases: &[(String, Duration, u64)]) {
+    let t = JobTiming {
+      kind: kind.to_string(),
+      label: label.to_string(),
+      ms: ms(took),
+      status,
+      phases: phases.iter().map(|(phase, d, count)| PhaseTiming { phase: phase.clone(), ms: ms(*d), count: *count }).collect(),
+      at: Local::now().to_rfc3339(),
+    };
+    push(&mut self.0.lock().jobs, JOB_CAP, t);
+  }
+
+  /// Mark the newest `ok` entry of `command` failed; false when there is none.
+  fn fail(&self, command: &str) -> bool {
+    let mut log = self.0.lock();
+    match log.commands.iter_mut().rev().find(|t| t.command == command && t.ok) {
+      Some(t) => { t.ok = false; true }
+      None => false,
+    }
+  }
+
+  fn metrics(&self) -> PerfMetrics {
+    let log = self.0.lock();
+    let mut by_cmd: HashMap<&str, (Vec<f64>, usize)> = HashMap::new();
+    for t in &log.commands {
+      let e = by_cmd.entry(t.command.as_str()).or_default();
+      e.0.push(t.ms);
+      if !t.ok { e.1 += 1; }
+    }
+    let mut commands: Vec<CommandStats> = by_cmd
+      .into_iter()
+      .map(|(command, (mut v, failures))| {
+        v.sort_by(f64::total_cmp);
+        CommandStats {
+          command: command.to_string(),
+          count: v.len(),
+          failures,
+          p50_ms: percentile(&v, 50.0),
+          p95_ms: percentile(&v, 95.0),
+          max_ms: v.last().copied().unwrap_or_default(),
+        }
+      })
+      .collect();
+    commands.sort_by(|a, b| b.p95_ms.total_cmp(&a.p95_ms).then_with(|| a.command.cmp(&b.command)));
+    PerfMetrics {
+      commands,
+      recent: log.commands.iter().cloned().collect(),
+      jobs: log.jobs.iter().cloned().collect(),
+      operation_profile: profile::current(),
+    }
+  }
+
+  fn reset(&self) {
+    let mut log = self.0.lock();
+    log.commands.clear();
+    log.jobs.clear();
+  }
+}
+
+/// What `get_perf_metrics` answers, for callers without the state at hand.
+pub fn metrics() -> PerfMetrics {
+  APP.get().map(|app| app.state::<AppState>().perf.metrics()).unwrap_or_else(|| PerfLog::default().metrics())
+}
+
+#[tauri::command]
+pub fn get_perf_metrics(state: tauri::State<'_, AppState>) -> PerfMetrics { state.perf.metrics() }
+
+#[tauri::command]
+pub fn reset_perf_metrics(state: tauri::State<'_, AppState>) { state.perf.reset() }
+
+/// The frontend's invoke wrapper saw `command` rejected (see the header).
+#[tauri::command]
+pub fn record_command_failure(state: tauri::State<'_, AppState>, command: String) { state.perf.fail(&command); }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn a_reported_rejection_counts_as_a_failure() {
+    let log = PerfLog::default();
+    for ms in [5, 10, 20] { log.command("write_comment", Duration::from_millis(ms), true); }
+    log.command("scan_folder", Duration::from_millis(1), true);
+    assert!(log.fail("write_comment"));
+    assert!(log.fail("write_comment"));
+    assert!(!log.fail("read_metadata"));
+    let m = log.metrics();
+    let oks: Vec<bool> = m.recent.iter().map(|t| t.ok).collect();
+    assert_eq!(oks, [true, false, false, true], "the newest entries are marked");
+    let w = m.commands.iter().find(|c| c.command == "write_comment").unwrap();
+    assert_eq!((w.count, w.failures, w.p50_ms, w.max_ms), (3, 2, 10.0, 20.0));
+  }
+}
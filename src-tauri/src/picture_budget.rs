@@ -0,0 +1,510 @@
+// Files that carry dozens of embedded pictures (bad tagger output leaves 20+
+// and hundreds of MB) blow up memory on every read, because lofty parses
+// every picture. The read-only paths (the list's `read_metadata`, the detail
+// panel) go through `read`. It first lists the pictures from the tag headers
+// alone: ID3v2 APIC/PIC frames at the front of an MP3, and PICTURE blocks in
+// FLAC. When they add up to more than `picture_budget_mb` (Settings, default
+// 50), lofty is handed a view of the file with the pictures past the budget
+// cut out, so they are never loaded. Front covers are kept first, then the
+// rest in file order, while they fit, and the result says it's truncated.
+// Other formats, and tags this scan can't splice (unsynchronised v2.3, an
+// extended header), are read as usual.
+//
+// A truncated TaggedFile must never be saved: the missing pictures would be
+// dropped. Writes keep reading the whole file. `keep_first_front_cover` is
+// the remedy; it rewrites the tag with a single picture and reads the file in
+// full once to do it.
+
+use std::{fs, io::{self, BufReader, Read, Seek, SeekFrom}, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}};
+use lofty::{FileType, PictureType, Probe, Tag};
+use serde::Serialize;
+
+use crate::{audit, command_span, edit_tags, ext_lower, id3_padding::{syncsafe, to_syncsafe}, log_line, long_paths, read_tagged, CmdError, WriteOutcome};
+
+pub const DEFAULT_BUDGET_MB: u64 = 50;
+pub static BUDGET: AtomicU64 = AtomicU64::new(DEFAULT_BUDGET_MB * 1024 * 1024);
+
+/// ID3v2 / FLAC picture type of a front cover.
+const FRONT_COVER: u8 = 3;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddedPicture {
+  /// ID3v2 / FLAC picture type (3 is the front cover); `None` for a
+  /// compressed or encrypted frame.
+  pic_type: Option<u8>,
+  /// Frame or block, header included.
+  bytes: u64,
+  #[serde(skip)]
+  offset: u64,
+}
+
+enum Layout { Id3 { version: u8, tag_end: u64 }, Flac { meta_end: u64 } }
+
+struct Scan {
+  layout: Layout,
+  pictures: Vec<EmbeddedPicture>,
+}
+
+fn read_at(f: &mut fs::File, at: u64, buf: &mut [u8]) -> io::Result<()> {
+  f.seek(SeekFrom::Start(at))?;
+  f.read_exact(buf)
+}
+
+/// Picture frames of a leading ID3v2 tag, from frame headers and the first
+/// bytes of each picture frame.
+fn scan_id3(f: &mut fs::File) -> Option<Scan> {
+  let mut head = [0u8; 10];
+  read_at(f, 0, &mut head).ok()?;
+  if &head[..3] != b"ID3" { return None; }
+  let (version, flags) = (head[3], head[5]);
+  // Unsynchronisation over the whole tag, an extended header or a footer:
+  // the frames can't be cut out byte for byte.
+  if flags & 0x80 != 0 && version < 4 || flags & 0x50 != 0 { return None; }
+  let tag_end = 10 + syncsafe(&head[6..10]);
+  let (id_len, head_len) = if version == 2 { (3usize, 6u64) } else { (4, 10) };
+  let mut pictures = Vec::new();
+  let mut at = 10u64;
+  let mut fh = [0u8; 10];
+  while at + head_len <= tag_end {
+    read_at(f, at, &mut fh[..head_len as usize]).ok()?;
+    if fh[0] == 0 { break; }
+    let s = &fh[id_len..id_len + if version == 2 { 3 } else { 4 }];
+    let size = match version {
+      2 => ((s[0] as u64) << 16) | ((s[1] as u64) << 8) | s[2] as u64,
+      4 => syncsafe(s),
+      _ => u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as u64,
+    };
+    let id = &fh[..id_len];
+    if id == b"APIC" || id == b"PIC" {
+      pictures.push(EmbeddedPicture { pic_type: id3_pic_type(f, version, &fh, at + head_len, size), bytes: head_len + size, offset: at });
+    }
+    at += head_len + size;
+  }
+  Some(Scan { layout: Layout::Id3 { version, tag_end }, pictures })
+}
+
+/// The type byte after the encoding and MIME type (or v2.2 image format).
+fn id3_pic_type(f: &mut fs::File, version: u8, head: &[u8], body: u64, size: u64) -> Option<u8> {
+  let mut body = body;
+  if version >= 3 {
+    let fmt = head[9];
+    let opaque = if version == 4 { fmt & 0x0c != 0 } else { fmt & 0xc0 != 0 };
+    if opaque { return None; }
+    // v2.4 data length indicator.
+    if version == 4 && fmt & 0x01 != 0 { body += 4; }
+  }
+  let mut buf = [0u8; 128];
+  let n = (size as usize).min(buf.len());
+  read_at(f, body, &mut buf[..n]).ok()?;
+  if version == 2 { return buf.get(4).copied(); }
+  let mime_end = buf[1..n].iter().position(|&b| b == 0)? + 1;
+  buf.get(mime_end + 1).copied().filter(|_| mime_end + 1 < n)
+}
+
+fn scan_flac(f: &mut fs::File) -> Option<Scan> {
+  let mut magic = [0u8; 4];
+  read_at(f, 0, &mut magic).ok()?;
+  if &magic != b"fLaC" { return None; }
+  let mut pictures = Vec::new();
+  let mut at = 4u64;
+  let mut head = [0u8; 4];
+  loop {
+    read_at(f, at, &mut head).ok()?;
+    let len = u32::from_be_bytes([0, head[1], head[2], head[3]]) as u64;
+    if head[0] & 0x7f == 6 {
+      let mut t = [0u8; 4];
+      let pic_type = read_at(f, at + 4, &mut t).ok().and_then(|_| u8::try_from(u32::from_be_bytes(t)).ok());
+      pictures.push(EmbeddedPicture { pic_type, bytes: 4 + len, offset: at });
+    }
+    at += 4 + len;
+    if head[0] & 0x80 != 0 { break; }
+  }
+  Some(Scan { layout: Layout::Flac { meta_end: at }, pictures })
+}
+
+fn scan(p: &Path) -> Option<Scan> {
+  let mut f = fs::File::open(long_paths::extended(p)).ok()?;
+  match ext_lower(p).as_str() {
+    "mp3" => scan_id3(&mut f),
+    "flac" => scan_flac(&mut f),
+    _ => None,
+  }
+}
+
+/// Offsets of the pictures that fit `budget`: front covers first, then the
+/// rest in file order.
+fn fitting(pictures: &[EmbeddedPicture], budget: u64) -> Vec<u64> {
+  let mut order: Vec<&EmbeddedPicture> = pictures.iter().filter(|x| x.pic_type == Some(FRONT_COVER)).collect();
+  order.extend(pictures.iter().filter(|x| x.pic_type != Some(FRONT_COVER)));
+  let mut used = 0u64;
+  let mut keep = Vec::new();
+  for x in order {
+    if used + x.bytes > budget { continue; }
+    used += x.bytes;
+    keep.push(x.offset);
+  }
+  keep
+}
+
+/// The file with its tag region replaced by `head`: reads below `head.len()`
+/// come from `head`, the rest from the file past `skip_to`.
+struct Spliced {
+  file: BufReader<fs::File>,
+  head: Vec<u8>,
+  skip_to: u64,
+  len: u64,
+  pos: u64,
+  /// Where `file` stands, so sequential reads keep its buffer.
+  file_pos: Option<u64>,
+}
+
+impl Read for Spliced {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let head_len = self.head.len() as u64;
+    let n = if self.pos < head_len {
+      let from = &self.head[self.pos as usize..];
+      let n = from.len().min(buf.len());
+      buf[..n].copy_from_slice(&from[..n]);
+      n
+    } else {
+      let at = self.skip_to + self.pos - head_len;
+      if self.file_pos != Some(at) { self.file.seek(SeekFrom::Start(at))?; }
+      let n = self.file.read(buf)?;
+      self.file_pos = Some(at + n as u64);
+      n
+    };
+    self.pos += n as u64;
+    Ok(n)
+  }
+}
+
+impl Seek for Spliced {
+  fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+    let pos = match to {
+      SeekFrom::Start(n) => n as i64,
+      SeekFrom::End(n) => self.len as i64 + n,
+      SeekFrom::Current(n) => self.pos as i64 + n,
+    };
+    if pos < 0 { return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start")); }
+    self.pos = pos as u64;
+    Ok(self.pos)
+  }
+}
+
+fn copy_span(f: &mut fs::File, out: &mut Vec<u8>, from: u64, to: u64) -> io::Result<()> {
+  let start = out.len();
+  out.resize(start + (to - from) as usize, 0);
+  read_at(f, from, &mut out[start..])
+}
+
+/// The tag region rebuilt with only the kept pictures (and no padding).
+fn spliced_head(f: &mut fs::File, scan: &Scan, keep: &[u64]) -> io::Result<(Vec<u8>, u64)> {
+  let dropped = |x: &EmbeddedPicture| !keep.contains(&x.offset);
+  match scan.layout {
+    Layout::Id3 { tag_end, .. } => {
+      let mut out = vec![0u8; 10];
+      read_at(f, 0, &mut out)?;
+      let mut at = 10;
+      for x in scan.pictures.iter().filter(|x| dropped(x)) {
+        copy_span(f, &mut out, at, x.offset)?;
+        at = x.offset + x.bytes;
+      }
+      // Up to the end of the frames; the padding is left out.
+      let frames_end = scan_frames_end(f, scan, at, tag_end)?;
+      copy_span(f, &mut out, at, frames_end)?;
+      let body = out.len() as u64 - 10;
+      out[6..10].copy_from_slice(&to_syncsafe(body));
+      Ok((out, tag_end))
+    }
+    Layout::Flac { meta_end } => {
+      let mut out = b"fLaC".to_vec();
+      let mut at = 4;
+      let mut last_block = 4usize;
+      let mut head = [0u8; 4];
+      while at < meta_end {
+        read_at(f, at, &mut head)?;
+        let len = 4 + u32::from_be_bytes([0, head[1], head[2], head[3]]) as u64;
+        let skip = head[0] & 0x7f == 1 || scan.pictures.iter().any(|x| x.offset == at && dropped(x));
+        if !skip {
+          last_block = out.len();
+          copy_span(f, &mut out, at, at + len)?;
+          out[last_block] &= 0x7f;
+        }
+        at += len;
+      }
+      out[last_block] |= 0x80;
+      Ok((out, meta_end))
+    }
+  }
+}
+
+/// Where the frames after `from` end and padding begins.
+fn scan_frames_end(f: &mut fs::File, scan: &Scan, from: u64, tag_end: u64) -> io::Result<u64> {
+  let Layout::Id3 { version, .. } = scan.layout else { return Ok(tag_end) };
+  let (id_len, head_len) = if version == 2 { (3usize, 6u64) } else { (4, 10) };
+  let mut at = from;
+  let mut fh = [0u8; 10];
+  while at + head_len <= tag_end {
+    read_at(f, at, &mut fh[..head_len as usize])?;
+    if fh[0] == 0 { break; }
+    let s = &fh[id_len..id_len + if version == 2 { 3 } else { 4 }];
+    at += head_len + match version {
+      2 => ((s[0] as u64) << 16) | ((s[1] as u64) << 8) | s[2] as u64,
+      4 => syncsafe(s),
+      _ => u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as u64,
+    };
+  }
+  Ok(at.min(tag_end))
+}
+
+fn read_spliced(p: &Path, scan: &Scan, keep: &[u64]) -> lofty::error::Result<lofty::TaggedFile> {
+  let mut f = fs::File::open(long_paths::extended(p))?;
+  let len = f.metadata()?.len();
+  let (head, skip_to) = spliced_head(&mut f, scan, keep)?;
+  let file_type = match scan.layout { Layout::Id3 { .. } => FileType::Mpeg, Layout::Flac { .. } => FileType::Flac };
+  let view = Spliced { len: head.len() as u64 + len.saturating_sub(skip_to), file: BufReader::new(f), head, skip_to, pos: 0, file_pos: None };
+  Probe::with_file_type(view, file_type).read()
+}
+
+/// `read_tagged` for reads that are never saved back, holding embedded
+/// pictures to the budget (see the header). The flag says pictures were left out.
+pub fn read(p: &Path) -> lofty::error::Result<(lofty::TaggedFile, bool)> { read_with(p, BUDGET.load(Ordering::Relaxed)) }
+
+fn read_with(p: &Path, budget: u64) -> lofty::error::Result<(lofty::TaggedFile, bool)> {
+  if let Some(scan) = scan(p) {
+    let total: u64 = scan.pictures.iter().map(|x| x.bytes).sum();
+    if total > budget {
+      let keep = fitting(&scan.pictures, budget);
+      log_line(&format!("picture budget path=\"{}\" pictures={} bytes={} kept={}", p.display(), scan.pictures.len(), total, keep.len()));
+      return read_spliced(p, &scan, &keep).map(|tf| (tf, true));
+    }
+  }
+  read_tagged(p).map(|tf| (tf, false))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PictureScan {
+  path: String,
+  /// Empty for formats the header scan doesn't cover.
+  pictures: Vec<EmbeddedPicture>,
+  total_bytes: u64,
+  budget_bytes: u64,
+  over_budget: bool,
+}
+
+/// Embedded pictures of `path` and their sizes, from the tag headers only.
+#[tauri::command]
+pub async fn scan_embedded_pictures(path: String) -> Result<PictureScan, String> {
+  let _span = command_span("scan_embedded_pictures");
+  tauri::async_runtime::spawn_blocking(move || {
+    let pictures = scan(&PathBuf::from(&path)).map(|s| s.pictures).unwrap_or_default();
+    let total_bytes = pictures.iter().map(|x| x.bytes).sum();
+    let budget_bytes = BUDGET.load(Ordering::Relaxed);
+    Ok(PictureScan { path, pictures, total_bytes, budget_bytes, over_budget: total_bytes > budget_bytes })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Every picture of `tag` but the first front cover (the first picture when
+/// there's no front cover). Returns how many went.
+fn keep_first(tag: &mut Tag) -> usize {
+  let pics = tag.pictures();
+  let Some(keep) = pics.iter().position(|x| x.pic_type() == PictureType::CoverFront).or((!pics.is_empty()).then_some(0)) else { return 0 };
+  let removed = pics.len() - 1;
+  let pic = pics[keep].clone();
+  while tag.picture_count() > 0 { tag.remove_picture(0); }
+  tag.push_picture(pic);
+  removed
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverCleanup {
+  path: String,
+  removed: usize,
+  #[serde(flatten)]
+  outcome: WriteOutcome,
+}
+
+/// Rewrite `path` keeping only its first front cover. A locked artwork field
+/// is left alone and reported in `skippedLocked`.
+#[tauri::command]
+pub async fn keep_first_front_cover(path: String) -> Result<CoverCleanup, CmdError> {
+  let _span = command_span("keep_first_front_cover");
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = PathBuf::from(&path);
+    let mut removed = 0;
+    let outcome = edit_tags(&p, |tag| removed += keep_first(tag))?;
+    if !outcome.skipped_locked.is_empty() { removed = 0; }
+    if !outcome.no_op && outcome.skipped_locked.is_empty() {
+      audit::record(&path, "artwork", Some(&format!("{} extra pictures", removed)), Some("first front cover"), audit::Source::Manual);
+    }
+    log_line(&format!("keep_first_front_cover path=\"{}\" removed={}", path, removed));
+    Ok(CoverCleanup { path, removed, outcome })
+  })
+  .await
+  .map_err(|e| CmdError::from(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::{Accessor, AudioFile, TaggedFileExt};
+  use crate::test_support;
+
+  /// A JPEG-looking picture of `len` bytes, tagged with `seed` so each is distinct.
+  fn jpeg(len: usize, seed: u8) -> Vec<u8> {
+    let mut out = vec![0xFF, 0xD8, 0xFF, 0xE0, seed];
+    out.resize(len, seed);
+    out
+  }
+
+  fn id3_frame(version: u8, id: &str, body: &[u8]) -> Vec<u8> {
+    let len = body.len() as u64;
+    let size = match version {
+      2 => (len as u32).to_be_bytes()[1..].to_vec(),
+      3 => (len as u32).to_be_bytes().to_vec(),
+      _ => to_syncsafe(len).to_vec(),
+    };
+    let flags: &[u8] = if version == 2 { &[] } else { &[0, 0] };
+    [id.as_bytes(), &size, flags, body].concat()
+  }
+
+  /// APIC (PIC in v2.2) of `pic_type` holding `data`.
+  fn picture_frame(version: u8, pic_type: u8, data: &[u8]) -> Vec<u8> {
+    match version {
+      2 => id3_frame(2, "PIC", &[&[0u8][..], b"JPG", &[pic_type, 0], data].concat()),
+      v => id3_frame(v, "APIC", &[&[0u8][..], b"image/jpeg\0", &[pic_type, 0], data].concat()),
+    }
+  }
+
+  /// A silent MP3 with an ID3v2 tag of `frames` and some padding.
+  fn mp3(dir: &Path, name: &str, version: u8, frames: &[Vec<u8>]) -> PathBuf {
+    let mut body = frames.concat();
+    body.resize(body.len() + 256, 0);
+    let mut tag = vec![b'I', b'D', b'3', version, 0, 0];
+    tag.extend_from_slice(&to_syncsafe(body.len() as u64));
+    let p = dir.join(name);
+    fs::write(&p, [tag, body, test_support::mpeg_frames(20)].concat()).unwrap();
+    p
+  }
+
+  fn flac_block(kind: u8, body: &[u8]) -> Vec<u8> { [&[kind][..], &(body.len() as u32).to_be_bytes()[1..], body].concat() }
+
+  fn flac_picture(pic_type: u32, data: &[u8]) -> Vec<u8> {
+    let mime = b"image/jpeg";
+    let body = [
+      &pic_type.to_be_bytes()[..], &(mime.len() as u32).to_be_bytes(), mime, &0u32.to_be_bytes(),
+      &640u32.to_be_bytes(), &640u32.to_be_bytes(), &24u32.to_be_bytes(), &0u32.to_be_bytes(), &(data.len() as u32).to_be_bytes(), data,
+    ].concat();
+    flac_block(6, &body)
+  }
+
+  /// STREAMINFO (44.1 kHz stereo), `pictures`, a Vorbis comment after them, padding.
+  fn flac(dir: &Path, name: &str, pictures: &[Vec<u8>]) -> PathBuf {
+    let mut info = Vec::new();
+    info.extend_from_slice(&[0x10, 0, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+    info.extend_from_slice(&((44100u64 << 44) | (1 << 41) | (15 << 36) | 4410).to_be_bytes());
+    info.extend_from_slice(&[0; 16]);
+    let vendor = b"test";
+    let comment = b"TITLE=After the pictures";
+    let vorbis = [&(vendor.len() as u32).to_le_bytes()[..], vendor, &1u32.to_le_bytes(), &(comment.len() as u32).to_le_bytes(), comment].concat();
+    let blocks = [vec![flac_block(0, &info)], pictures.to_vec(), vec![flac_block(4, &vorbis), flac_block(0x81, &[0; 512])]].concat();
+    let p = dir.join(name);
+    fs::write(&p, [b"fLaC".to_vec(), blocks.concat()].concat()).unwrap();
+    p
+  }
+
+  /// Types of the pictures lofty loaded, in tag order.
+  fn loaded(tf: &lofty::TaggedFile) -> Vec<PictureType> {
+    tf.primary_tag().map(|t| t.pictures().iter().map(|x| x.pic_type()).collect()).unwrap_or_default()
+  }
+
+  #[test]
+  fn pictures_are_listed_from_the_headers() {
+    let dir = test_support::scratch("picture-scan");
+    for version in [2, 3, 4] {
+      let frames = [id3_frame(version, if version == 2 { "TT2" } else { "TIT2" }, b"\0Title"), picture_frame(version, 0, &jpeg(1000, 1)), picture_frame(version, 3, &jpeg(2000, 2))];
+      let p = mp3(&dir, &format!("v2{}.mp3", version), version, &frames);
+      let s = scan(&p).unwrap();
+      let got: Vec<(Option<u8>, u64)> = s.pictures.iter().map(|x| (x.pic_type, x.bytes)).collect();
+      assert_eq!(got, [(Some(0), frames[1].len() as u64), (Some(3), frames[2].len() as u64)], "v2.{}", version);
+    }
+    let pics = [flac_picture(3, &jpeg(3000, 1)), flac_picture(8, &jpeg(500, 2))];
+    let s = scan(&flac(&dir, "a.flac", &pics)).unwrap();
+    assert_eq!(s.pictures.iter().map(|x| (x.pic_type, x.bytes)).collect::<Vec<_>>(), [(Some(3), pics[0].len() as u64), (Some(8), pics[1].len() as u64)]);
+
+    // An extended header can't be spliced: read as usual.
+    let p = mp3(&dir, "ext.mp3", 4, &[picture_frame(4, 3, &jpeg(100, 1))]);
+    let mut bytes = fs::read(&p).unwrap();
+    bytes[5] = 0x40;
+    fs::write(&p, bytes).unwrap();
+    assert!(scan(&p).is_none());
+    assert!(scan(&test_support::audio(&dir, "a.wav")).is_none());
+  }
+
+  #[test]
+  fn front_covers_go_first_then_file_order() {
+    let pic = |pic_type, bytes, offset| EmbeddedPicture { pic_type: Some(pic_type), bytes, offset };
+    let pictures = [pic(0, 40, 10), pic(3, 50, 50), pic(8, 30, 100), pic(3, 60, 130), pic(4, 20, 190)];
+    assert_eq!(fitting(&pictures, 200), [50, 130, 10, 100, 190]);
+    assert_eq!(fitting(&pictures, 170), [50, 130, 10, 190], "what doesn't fit is skipped, later ones still tried");
+    assert_eq!(fitting(&pictures, 110), [50, 130]);
+    assert_eq!(fitting(&pictures, 10), Vec::<u64>::new());
+  }
+
+  #[test]
+  fn many_large_pictures_are_read_to_the_budget() {
+    let dir = test_support::scratch("picture-budget");
+    let size = 64 * 1024;
+    // 24 pictures, the front cover twelfth, the title frame after all of them.
+    let mut frames: Vec<Vec<u8>> = (0..24u8).map(|i| picture_frame(4, if i == 11 { 3 } else { 0 }, &jpeg(size, i))).collect();
+    frames.push(id3_frame(4, "TIT2", b"\0After the pictures"));
+    let p = mp3(&dir, "many.mp3", 4, &frames);
+    let budget = 3 * frames[0].len() as u64;
+    let (tf, truncated) = read_with(&p, budget).unwrap();
+    assert!(truncated);
+    assert_eq!(loaded(&tf), [PictureType::Other, PictureType::Other, PictureType::CoverFront], "the cover, then the first two that fit");
+    assert_eq!(tf.primary_tag().unwrap().title().as_deref(), Some("After the pictures"));
+    assert_eq!(tf.properties().sample_rate(), Some(44100), "the audio is found after the shortened tag");
+    let (tf, truncated) = read_with(&p, u64::MAX).unwrap();
+    assert_eq!((loaded(&tf).len(), truncated), (24, false));
+
+    let pics: Vec<Vec<u8>> = (0..20u8).map(|i| flac_picture(if i == 0 { 3 } else { 5 }, &jpeg(size, i))).collect();
+    let p = flac(&dir, "many.flac", &pics);
+    let (tf, truncated) = read_with(&p, 2 * pics[0].len() as u64).unwrap();
+    assert!(truncated);
+    assert_eq!(loaded(&tf), [PictureType::CoverFront, PictureType::Leaflet]);
+    assert_eq!(tf.primary_tag().unwrap().title().as_deref(), Some("After the pictures"));
+    assert_eq!((tf.properties().sample_rate(), tf.properties().channels()), (Some(44100), Some(2)));
+  }
+
+  #[test]
+  fn the_default_budget_holds_back_hundreds_of_megabytes() {
+    let dir = test_support::scratch("picture-default");
+    // 22 pictures of 2.5 MB: 55 MB of art against the 50 MiB default.
+    let frames: Vec<Vec<u8>> = (0..22u8).map(|i| picture_frame(3, i, &jpeg(2_500_000, i))).collect();
+    let p = mp3(&dir, "huge.mp3", 3, &frames);
+    assert_eq!(BUDGET.load(Ordering::Relaxed), DEFAULT_BUDGET_MB * 1024 * 1024);
+    let (tf, truncated) = read(&p).unwrap();
+    assert_eq!((loaded(&tf).len(), truncated), (20, true));
+    let _ = fs::remove_file(&p);
+  }
+
+  #[test]
+  fn keep_first_front_cover_rewrites_with_one_picture() {
+    let dir = test_support::scratch("picture-cleanup");
+    let frames: Vec<Vec<u8>> = [0u8, 4, 3, 3, 8].iter().enumerate().map(|(i, t)| picture_frame(4, *t, &jpeg(4096, i as u8))).collect();
+    let p = mp3(&dir, "a.mp3", 4, &frames);
+    let mut removed = 0;
+    edit_tags(&p, |tag| removed += keep_first(tag)).unwrap();
+    assert_eq!(removed, 4);
+    let tf = read_tagged(&p).unwrap();
+    let pics = tf.primary_tag().unwrap().pictures();
+    assert_eq!((pics.len(), pics[0].pic_type(), pics[0].data()[4]), (1, PictureType::CoverFront, 2), "the first of the two covers");
+    assert_eq!(scan(&p).unwrap().pictures.len(), 1);
+  }
+}
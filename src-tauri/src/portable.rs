@@ -0,0 +1,198 @@
+// Portable file references. Workspace roots get a stable id in data dir
+// `roots.json`, and stores that remember individual files (touched records,
+// the retry queue) keep a root id + `/`-separated relative path next to the
+// absolute one. When the absolute path stops resolving (the crate moved to
+// another drive or mount point), the relative part is tried under the file's
+// own root, wherever that root is now; never under another root, where the
+// same name is some other file. `rebase_references` rewrites everything
+// stored under one root at once when the new location is known. Tag
+// manifests are already relative to their folder and need none of this.
+
+use std::{fs, path::{Path, PathBuf}};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{data_dir, load_prefs, log_line, retry_queue, save_prefs, touched, volumes, write_atomic};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownRoot {
+  pub id: String,
+  pub path: String,
+  pub registered_at: String,
+}
+
+/// Where a file sits relative to a known root.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootRel {
+  pub root_id: String,
+  pub rel: String,
+}
+
+static ROOTS: Lazy<Mutex<Vec<KnownRoot>>> = Lazy::new(|| {
+  Mutex::new(fs::read_to_string(roots_path()).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default())
+});
+
+fn roots_path() -> PathBuf { data_dir().join("roots.json") }
+
+fn save(roots: &[KnownRoot]) {
+  let res = serde_json::to_vec_pretty(roots).map_err(|e| e.to_string()).and_then(|json| write_atomic(&roots_path(), &json));
+  if let Err(e) = res { log_line(&format!("roots save failed: {}", e)); }
+}
+
+fn canonical(p: &Path) -> PathBuf { fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()) }
+
+fn rel_string(rel: &Path) -> String {
+  rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<_>>().join("/")
+}
+
+/// Give `root` an id if it has none yet; returns the id.
+pub fn register(root: &Path) -> String {
+  let root = canonical(root);
+  let path = root.to_string_lossy().to_string();
+  let mut roots = ROOTS.lock();
+  if let Some(r) = roots.iter().find(|r| r.path == path) { return r.id.clone(); }
+  let registered_at = Local::now().to_rfc3339();
+  let digest = Sha256::digest(format!("{}\0{}", path, registered_at).as_bytes());
+  let id: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+  roots.push(KnownRoot { id: id.clone(), path, registered_at });
+  save(&roots);
+  id
+}
+
+/// Place of `p` under the deepest known root containing it.
+pub fn place(p: &Path) -> Option<RootRel> {
+  let roots = ROOTS.lock();
+  let root = roots.iter().filter(|r| p.starts_with(&r.path)).max_by_key(|r| Path::new(&r.path).components().count())?;
+  let rel = p.strip_prefix(&root.path).ok()?;
+  Some(RootRel { root_id: root.id.clone(), rel: rel_string(rel) })
+}
+
+/// Every (root, relative path) reading of `p`, deepest root first; what a
+/// stored reference would have to say to mean this file.
+pub fn readings(p: &Path) -> Vec<RootRel> {
+  let roots = ROOTS.lock();
+  let mut out: Vec<(usize, RootRel)> = roots
+    .iter()
+    .filter_map(|r| {
+      let rel = p.strip_prefix(&r.path).ok()?;
+      Some((Path::new(&r.path).components().count(), RootRel { root_id: r.id.clone(), rel: rel_string(rel) }))
+    })
+    .collect();
+  out.sort_by_key(|r| std::cmp::Reverse(r.0));
+  out.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Current location of a stored reference: `path` if it exists, else its
+/// root's current path + `rel`. `None` when neither exists, including when
+/// the root is unknown or not mounted.
+pub fn resolve(path: &str, place: Option<&RootRel>) -> Option<PathBuf> {
+  let abs = PathBuf::from(path);
+  if abs.exists() { return Some(abs); }
+  let place = place?;
+  let roots = ROOTS.lock();
+  let root = roots.iter().find(|r| r.id == place.root_id)?;
+  Some(place.rel.split('/').fold(PathBuf::from(&root.path), |p, part| p.join(part))).filter(|p| p.exists())
+}
+
+/// `place`'s root is known and its folder is there.
+pub fn root_present(place: &RootRel) -> bool {
+  ROOTS.lock().iter().any(|r| r.id == place.root_id && Path::new(&r.path).is_dir())
+}
+
+/// `p` moved from under `old_root` to under `new_root`; `None` when it wasn't
+/// under it. `old_root` itself becomes `new_root` exactly (joining an empty
+/// path would add a trailing separator).
+pub fn rebased(p: &Path, old_root: &Path, new_root: &Path) -> Option<PathBuf> {
+  let rel = p.strip_prefix(old_root).ok()?;
+  Some(if rel.as_os_str().is_empty() { new_root.to_path_buf() } else { new_root.join(rel) })
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebaseReport {
+  roots: usize,
+  workspace_roots: usize,
+  touched: usize,
+  retry_items: usize,
+  pending_writes: usize,
+}
+
+#[tauri::command]
+pub fn list_known_roots() -> Vec<KnownRoot> { ROOTS.lock().clone() }
+
+/// Point every stored reference under `old_root` at `new_root` (a drive
+/// letter or mount point changed). Root ids are kept, so references saved
+/// elsewhere with only the relative part keep resolving.
+#[tauri::command]
+pub fn rebase_references(old_root: String, new_root: String) -> Result<RebaseReport, String> {
+  let new = canonical(Path::new(&new_root));
+  if !new.is_dir() { return Err(format!("{} is not a folder", new_root)); }
+  // The old location is usually gone; when it isn't, match its canonical form too.
+  let mut olds = vec![PathBuf::from(&old_root)];
+  let canon = canonical(Path::new(&old_root));
+  if canon != olds[0] { olds.push(canon); }
+
+  let mut report = RebaseReport::default();
+  {
+    let mut roots = ROOTS.lock();
+    for r in roots.iter_mut() {
+      if let Some(p) = olds.iter().find_map(|o| rebased(Path::new(&r.path), o, &new)) {
+        r.path = p.to_string_lossy().to_string();
+        report.roots += 1;
+      }
+    }
+    if report.roots > 0 { save(&roots); }
+  }
+  let mut prefs = load_prefs();
+  for root in prefs.workspaces.iter_mut().flat_map(|w| w.roots.iter_mut()) {
+    if let Some(p) = olds.iter().find_map(|o| rebased(Path::new(root.as_str()), o, &new)) {
+      *root = p.to_string_lossy().to_string();
+      report.workspace_roots += 1;
+    }
+  }
+  if report.workspace_roots > 0 { save_prefs(&prefs)?; }
+  for old in &olds {
+    report.touched += touched::rebase(old, &new);
+    report.retry_items += retry_queue::rebase(old, &new);
+    report.pending_writes += volumes::rebase(old, &new);
+  }
+  log_line(&format!(
+    "rebase_references old=\"{}\" new=\"{}\" roots={} touched={} retry={} pending={}",
+    old_root, new.display(), report.roots, report.touched, report.retry_items, report.pending_writes
+  ));
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{read_tagged, test_support};
+
+  #[test]
+  fn references_follow_their_root_when_the_tree_moves() {
+    let dir = test_support::scratch("portable-move");
+    let (old_root, new_root) = (dir.join("crate"), dir.join("moved"));
+    fs::create_dir_all(old_root.join("house")).unwrap();
+    let id = register(&old_root);
+    let p = test_support::audio(&old_root.join("house"), "a.mp3");
+    touched::record(&p, &read_tagged(&p).unwrap());
+    // What a store keeps for a file: the absolute path plus its place.
+    let (abs, at) = (p.to_string_lossy().to_string(), place(&p).unwrap());
+    assert_eq!(at, RootRel { root_id: id.clone(), rel: "house/a.mp3".into() });
+
+    fs::rename(&old_root, &new_root).unwrap();
+    assert_eq!(resolve(&abs, Some(&at)), None, "the root still points at the old folder");
+    rebase_references(old_root.to_string_lossy().to_string(), new_root.to_string_lossy().to_string()).unwrap();
+
+    let moved = new_root.join("house").join("a.mp3");
+    assert_eq!(resolve(&abs, Some(&at)), Some(moved.clone()));
+    assert_eq!(register(&new_root), id, "the root keeps its id");
+    let (touched_at, external) = touched::status(&moved, &read_tagged(&moved).unwrap());
+    assert!(touched_at.is_some() && !external);
+  }
+}
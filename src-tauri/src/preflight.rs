@@ -0,0 +1,224 @@
+// Pre-flight checks for big batches. Before the first file is touched, the
+// bytes each volume will need are estimated, compared with its free space,
+// and a sample of target folders gets a probe file created and removed, so a
+// full disk or a read-only folder fails the whole batch up front instead of
+// at file 250. Dry runs carry the same report so the UI can warn early.
+//
+// Rewrites save one file at a time and each temp copy replaces its original,
+// so their need per volume is the largest file there (plus MARGIN), not the
+// sum. Converted copies and zips keep everything they write.
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use serde::Serialize;
+
+use crate::error::CmdError;
+
+/// Headroom on every estimate: tag growth, padding, file system overhead.
+const MARGIN_PERCENT: u64 = 10;
+const PROBE_DIRS: usize = 8;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeSpace {
+  /// A folder on the volume (the first target seen there).
+  pub folder: String,
+  pub required: u64,
+  /// `None` when the free space can't be read; such volumes aren't blocked.
+  pub available: Option<u64>,
+  pub shortfall: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preflight {
+  pub required_bytes: u64,
+  pub volumes: Vec<VolumeSpace>,
+  /// Target folders a probe file was written to.
+  pub checked_folders: Vec<String>,
+  pub no_write_access: Vec<String>,
+  pub ok: bool,
+}
+
+fn with_margin(bytes: u64) -> u64 { bytes + bytes * MARGIN_PERCENT / 100 }
+
+#[cfg(unix)]
+fn volume_key(dir: &Path) -> String {
+  use std::os::unix::fs::MetadataExt;
+  fs::metadata(dir).map(|m| m.dev().to_string()).unwrap_or_else(|_| dir.to_string_lossy().to_string())
+}
+
+#[cfg(windows)]
+fn volume_key(dir: &Path) -> String {
+  dir.components().next().map(|c| c.as_os_str().to_string_lossy().to_uppercase()).unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+  use std::{ffi::CString, os::unix::ffi::OsStrExt};
+  let c = CString::new(dir.as_os_str().as_bytes()).ok()?;
+  let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statvfs(c.as_ptr(), &mut st) } != 0 { return None; }
+  Some(st.f_bavail as u64 * st.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn free_space(dir: &Path) -> Option<u64> {
+  use std::os::windows::ffi::OsStrExt;
+  #[link(name = "kernel32")]
+  extern "system" {
+    fn GetDiskFreeSpaceExW(dir: *const u16, avail: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+  }
+  let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+  let mut avail = 0u64;
+  let ok = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut avail, std::ptr::null_mut(), std::ptr::null_mut()) };
+  (ok != 0).then_some(avail)
+}
+
+/// Create and remove a probe file in `dir`.
+fn writable(dir: &Path) -> bool {
+  let probe = dir.join(format!(".audio-tagger-preflight-{}", std::process::id()));
+  let ok = fs::File::create(&probe).is_ok();
+  let _ = fs::remove_file(&probe);
+  ok
+}
+
+/// Existing folder a write to `p` lands in: `p` itself, else its nearest existing ancestor.
+fn landing_dir(p: &Path) -> PathBuf {
+  p.ancestors().find(|a| a.is_dir()).unwrap_or(p).to_path_buf()
+}
+
+/// Check `needs` (folder, bytes without margin) and probe a sample of `folders`.
+fn check(needs: &[(PathBuf, u64)], folders: &[PathBuf]) -> Preflight {
+  let mut by_volume: HashMap<String, usize> = HashMap::new();
+  let mut volumes: Vec<VolumeSpace> = Vec::new();
+  for (dir, bytes) in needs {
+    let dir = landing_dir(dir);
+    let i = *by_volume.entry(volume_key(&dir)).or_insert_with(|| {
+      volumes.push(VolumeSpace { folder: dir.to_string_lossy().to_string(), required: 0, available: free_space(&dir), shortfall: 0 });
+      volumes.len() - 1
+    });
+    volumes[i].required += with_margin(*bytes);
+  }
+  for v in &mut volumes {
+    v.shortfall = v.available.map_or(0, |a| v.required.saturating_sub(a));
+  }
+
+  let mut sample: Vec<PathBuf> = Vec::new();
+  for f in folders.iter().map(|f| landing_dir(f)) {
+    if sample.len() == PROBE_DIRS { break; }
+    if !sample.contains(&f) { sample.push(f); }
+  }
+  let no_write_access: Vec<String> = sample.iter().filter(|d| !writable(d)).map(|d| d.to_string_lossy().to_string()).collect();
+
+  Preflight {
+    required_bytes: volumes.iter().map(|v| v.required).sum(),
+    ok: no_write_access.is_empty() && volumes.iter().all(|v| v.shortfall == 0),
+    checked_folders: sample.iter().map(|d| d.to_string_lossy().to_string()).collect(),
+    volumes,
+    no_write_access,
+  }
+}
+
+/// In-place tag rewrites of `paths`.
+pub fn rewrite<P: AsRef<Path>>(paths: &[P]) -> Preflight {
+  let mut largest: HashMap<String, (PathBuf, u64)> = HashMap::new();
+  let mut folders = Vec::new();
+  for p in paths {
+    let p = p.as_ref();
+    let Some(dir) = p.parent() else { continue };
+    let len = fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+    let e = largest.entry(volume_key(dir)).or_insert_with(|| (dir.to_path_buf(), 0));
+    e.1 = e.1.max(len);
+    folders.push(dir.to_path_buf());
+  }
+  let needs: Vec<(PathBuf, u64)> = largest.into_values().collect();
+  check(&needs, &folders)
+}
+
+/// New files: `outputs` bytes in `dest_dir`, plus an optional scratch need
+/// (folder, bytes) elsewhere, e.g. temp copies.
+pub fn copies(dest_dir: &Path, outputs: u64, scratch: Option<(PathBuf, u64)>) -> Preflight {
+  let mut needs = vec![(dest_dir.to_path_buf(), outputs)];
+  let mut folders = vec![dest_dir.to_path_buf()];
+  if let Some((dir, bytes)) = scratch {
+    folders.push(dir.clone());
+    needs.push((dir, bytes));
+  }
+  check(&needs, &folders)
+}
+
+fn mb(bytes: u64) -> String { format!("{:.1} MB", bytes as f64 / 1_048_576.0) }
+
+/// The report as an error when the batch can't go ahead.
+pub fn ensure(p: &Preflight) -> Result<(), CmdError> {
+  if let Some(v) = p.volumes.iter().find(|v| v.shortfall > 0) {
+    let available = v.available.unwrap_or(0);
+    return Err(CmdError::InsufficientSpace {
+      folder: v.folder.clone(),
+      required: v.required,
+      available,
+      shortfall: v.shortfall,
+      message: format!(
+        "Not enough free space on the volume of {}: needs {}, {} free ({} short). Nothing was written.",
+        v.folder, mb(v.required), mb(available), mb(v.shortfall)
+      ),
+    });
+  }
+  if !p.no_write_access.is_empty() {
+    return Err(CmdError::NoWriteAccess {
+      folders: p.no_write_access.clone(),
+      message: format!("Can't write to {}. Nothing was written.", p.no_write_access.join(", ")),
+    });
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support;
+
+  #[test]
+  fn rewrites_need_the_largest_file_per_volume() {
+    let dir = test_support::scratch("preflight");
+    let small = test_support::audio(&dir, "a.wav");
+    let big = test_support::audio(&dir, "b.flac");
+    fs::write(&big, vec![0u8; 50_000]).unwrap();
+    let p = rewrite(&[&small, &big]);
+    assert_eq!(p.volumes.len(), 1);
+    assert_eq!(p.required_bytes, with_margin(50_000));
+    assert_eq!(p.checked_folders, vec![dir.to_string_lossy().to_string()]);
+    assert!(p.ok && ensure(&p).is_ok());
+  }
+
+  #[test]
+  fn shortfall_and_unwritable_folders_are_typed_errors() {
+    let short = Preflight {
+      volumes: vec![VolumeSpace { folder: "/music".into(), required: 200, available: Some(50), shortfall: 150 }],
+      ..Default::default()
+    };
+    assert!(matches!(ensure(&short), Err(CmdError::InsufficientSpace { shortfall: 150, .. })));
+    let locked = Preflight { no_write_access: vec!["/music/ro".into()], ..Default::default() };
+    assert!(matches!(ensure(&locked), Err(CmdError::NoWriteAccess { folders, .. }) if folders == ["/music/ro"]));
+  }
+
+  #[test]
+  fn every_batch_snapshot_follows_a_preflight() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut unchecked = Vec::new();
+    for entry in fs::read_dir(&src).unwrap() {
+      let p = entry.unwrap().path();
+      if p.extension().is_none_or(|e| e != "rs") || p.ends_with("snapshots.rs") { continue; }
+      let text = fs::read_to_string(&p).unwrap();
+      let code = text.split("#[cfg(test)]").next().unwrap_or("");
+      for body in code.split("\nfn ").flat_map(|s| s.split("\npub fn ")).flat_map(|s| s.split("\npub async fn ")).skip(1) {
+        if let Some(at) = body.find("snapshots::before_batch(") {
+          if !body[..at].contains("preflight::ensure(") {
+            unchecked.push(format!("{}: {}", p.file_name().unwrap().to_string_lossy(), body.split('(').next().unwrap_or("")));
+          }
+        }
+      }
+    }
+    assert!(unchecked.is_empty(), "batch writers without a preflight: {:?}", unchecked);
+  }
+}
@@ -0,0 +1,461 @@
+// Bank presets: named lists of actions stored in the bank (`presets`, schema
+// 3), e.g. "Club Ready" = add #club #tested, set grouping to "A", require key
+// and BPM. Actions are `add_tags` / `remove_tags` (comment tokens, through the
+// tag policy), `set_field` (an empty value clears it) and `require_field`,
+// which only warns when the field is empty once the earlier actions ran.
+//
+// `apply_preset` is all or nothing per file: every action is worked out
+// against the file as read first, and a failure (a tag the policy rejects, a
+// locked field, a bad value, an unknown field) leaves the file untouched and
+// marks the other actions rolled back. Tags are added once per
+// `banks::dedupe_key`, so "#house" doesn't join a "#House" already there.
+// The plan is made from the file as read under WRITE_LOCK, and the fields
+// and the comment go into that one `edit_tags` save, so a file gets one
+// revision per preset and a save that fails leaves it as it was. Actions
+// are kept in the bank as raw JSON, so a preset saved by a newer build keeps
+// action types this one doesn't know; such a preset is refused as a whole
+// rather than applied in part.
+
+use std::{cell::OnceCell, path::Path};
+use lofty::{ItemKey, Tag};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::Manager;
+
+use crate::{
+  audit, banks::{dedupe_key, BankDocument}, command_span, edit_tags_with, error::CmdError, field_locks::{self, LockedField}, handshake, log_line, preferred_tag,
+  preflight::{self, Preflight}, read_comment, read_tagged, shadow, snapshots, split_comment_tokens, tag_ops::join_tokens, tag_policy, write_targets, AppState,
+  WriteOutcome,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+  pub name: String,
+  /// `Action`s; raw so unknown ones survive a round trip.
+  #[serde(default)]
+  pub actions: Vec<Value>,
+  #[serde(flatten)]
+  pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Action {
+  AddTags { tags: Vec<String> },
+  RemoveTags { tags: Vec<String> },
+  SetField { field: String, value: String },
+  RequireField { field: String },
+}
+
+impl Action {
+  fn kind(&self) -> &'static str {
+    match self {
+      Action::AddTags { .. } => "add_tags",
+      Action::RemoveTags { .. } => "remove_tags",
+      Action::SetField { .. } => "set_field",
+      Action::RequireField { .. } => "require_field",
+    }
+  }
+}
+
+/// Tag item and lock of a field name `set_field` / `require_field` accept.
+fn field(name: &str) -> Option<(ItemKey, Option<LockedField>)> {
+  Some(match name {
+    "title" => (ItemKey::TrackTitle, Some(LockedField::Title)),
+    "artist" => (ItemKey::TrackArtist, Some(LockedField::Artist)),
+    "album" => (ItemKey::AlbumTitle, Some(LockedField::Album)),
+    "genre" => (ItemKey::Genre, Some(LockedField::Genre)),
+    "grouping" => (ItemKey::ContentGroup, None),
+    "bpm" => (ItemKey::Bpm, Some(LockedField::Bpm)),
+    "key" => (ItemKey::InitialKey, Some(LockedField::Key)),
+    "isrc" => (ItemKey::Isrc, Some(LockedField::Isrc)),
+    "label" => (ItemKey::Label, None),
+    "composer" => (ItemKey::Composer, None),
+    _ => return None,
+  })
+}
+
+/// Every action parsed, or why the preset can't run: unknown action types
+/// are named, then the first malformed action.
+fn parse_actions(p: &Preset) -> Result<Vec<Action>, String> {
+  let known = ["add_tags", "remove_tags", "set_field", "require_field"];
+  let unknown: Vec<String> = p.actions.iter()
+    .map(|a| a.get("type").and_then(Value::as_str).unwrap_or("").to_string())
+    .filter(|t| !known.contains(&t.as_str()))
+    .collect();
+  if !unknown.is_empty() {
+    return Err(format!("preset \"{}\" uses actions this version doesn't support: {}", p.name, unknown.join(", ")));
+  }
+  p.actions.iter().enumerate().map(|(i, a)| {
+    let action: Action = serde_json::from_value(a.clone()).map_err(|e| format!("preset \"{}\" action {}: {}", p.name, i + 1, e))?;
+    match &action {
+      Action::SetField { field: f, .. } | Action::RequireField { field: f } if field(f).is_none() => {
+        Err(format!("preset \"{}\" action {}: unknown field \"{}\"", p.name, i + 1, f))
+      }
+      _ => Ok(action),
+    }
+  }).collect()
+}
+
+/// A preset as it may be saved: a name, and actions this build can run.
+fn validate(p: &Preset) -> Result<(), String> {
+  if p.name.trim().is_empty() { return Err("preset name required".into()); }
+  parse_actions(p).map(|_| ())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ActionStatus {
+  Applied,
+  /// Nothing to do: tags already there (or gone), the field already holds it.
+  Unchanged,
+  /// `require_field` on an empty field. A warning; the rest still applies.
+  Missing,
+  Failed,
+  /// Would have applied, but another action on the file failed.
+  RolledBack,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionOutcome {
+  action: &'static str,
+  status: ActionStatus,
+  detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetFileResult {
+  path: String,
+  /// The file was (or on a dry run would be) written.
+  changed: bool,
+  /// File-level error (unreadable, save failed); per-action ones are in `actions`.
+  error: Option<String>,
+  actions: Vec<ActionOutcome>,
+  #[serde(flatten)]
+  shadow: shadow::Mark,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetReport {
+  preset: String,
+  dry_run: bool,
+  results: Vec<PresetFileResult>,
+  changed: usize,
+  failed: usize,
+  /// Files with an unmet `require_field`.
+  warned: usize,
+  /// Taken before writing (see `snapshots`); None on a dry run.
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+struct FieldWrite {
+  name: String,
+  key: ItemKey,
+  lock: Option<LockedField>,
+  old: String,
+  /// "" clears the field.
+  value: String,
+}
+
+/// A file's values as the actions see them, updated as they run.
+struct Plan {
+  /// The comment as read, for the audit.
+  comment: String,
+  tokens: Vec<String>,
+  comment_changed: bool,
+  /// One per field, the last `set_field` winning.
+  fields: Vec<FieldWrite>,
+}
+
+/// A plan and an outcome per action.
+type Planned = (Plan, Vec<ActionOutcome>);
+
+fn outcome(action: &Action, status: ActionStatus, detail: Option<String>) -> ActionOutcome {
+  ActionOutcome { action: action.kind(), status, detail }
+}
+
+/// Run `actions` against `p` as read into `tf`, in memory; the plan to write
+/// and an outcome per action.
+fn plan(tf: &lofty::TaggedFile, p: &Path, actions: &[Action]) -> Planned {
+  let tag = preferred_tag(tf, p);
+  let current = |k: &ItemKey| tag.and_then(|t| t.get_string(k)).map(|s| s.trim().to_string()).unwrap_or_default();
+  let locks = field_locks::locked(p);
+  let policy = tag_policy::policy();
+  let comment = read_comment(tf, p);
+  let mut plan = Plan { tokens: split_comment_tokens(&comment), comment, comment_changed: false, fields: Vec::new() };
+  let value_of = |plan: &Plan, k: &ItemKey| plan.fields.iter().find(|f| f.key == *k).map(|f| f.value.clone()).unwrap_or_else(|| current(k));
+  let comment_locked = locks.contains(&LockedField::Comment);
+
+  let mut out = Vec::new();
+  for a in actions {
+    let res: Result<(ActionStatus, Option<String>), String> = match a {
+      Action::AddTags { tags } => tag_policy::normalize_for_add(tags).and_then(|tags| {
+        let mut seen: Vec<String> = plan.tokens.iter().map(|t| dedupe_key(t, &policy)).collect();
+        let new: Vec<String> = tags.into_iter().filter(|t| {
+          let key = dedupe_key(t, &policy);
+          !seen.contains(&key) && { seen.push(key); true }
+        }).collect();
+        if new.is_empty() { return Ok((ActionStatus::Unchanged, None)); }
+        if comment_locked { return Err("comment is locked".into()); }
+        // Before the trailing bank marker, as `merge_tokens` does.
+        let at = plan.tokens.iter().position(|t| t.starts_with("TagB:")).unwrap_or(plan.tokens.len());
+        plan.tokens.splice(at..at, new);
+        plan.comment_changed = true;
+        Ok((ActionStatus::Applied, None))
+      }),
+      Action::RemoveTags { tags } => {
+        let normalized: Vec<String> = tags.iter().filter_map(|t| tag_policy::normalize_tag(t, &policy).ok()).collect();
+        let before = plan.tokens.len();
+        let keep: Vec<String> = plan.tokens.iter().filter(|t| !tags.contains(t) && !normalized.contains(t)).cloned().collect();
+        if keep.len() == before { Ok((ActionStatus::Unchanged, None)) }
+        else if comment_locked { Err("comment is locked".into()) }
+        else { plan.tokens = keep; plan.comment_changed = true; Ok((ActionStatus::Applied, None)) }
+      }
+      Action::SetField { field: name, value } => match (field(name), value.trim()) {
+        (None, _) => Err(format!("unknown field \"{}\"", name)),
+        (Some((key, _)), v) if key == ItemKey::Bpm && !v.is_empty() && !v.parse::<f64>().is_ok_and(|b| b > 0.0) => {
+          Err(format!("bpm \"{}\" is not a positive number", v))
+        }
+        (Some((key, _)), v) if value_of(&plan, &key) == v => Ok((ActionStatus::Unchanged, None)),
+        (Some((_, Some(lock))), _) if locks.contains(&lock) => Err(format!("{} is locked", name)),
+        (Some((key, lock)), v) => {
+          plan.fields.retain(|f| f.key != key);
+          let old = current(&key);
+          if old != v { plan.fields.push(FieldWrite { name: name.clone(), key, lock, old, value: v.to_string() }); }
+          Ok((ActionStatus::Applied, None))
+        }
+      },
+      Action::RequireField { field: name } => match field(name) {
+        None => Err(format!("unknown field \"{}\"", name)),
+        Some((key, _)) if value_of(&plan, &key).is_empty() => Ok((ActionStatus::Missing, Some(format!("{} is empty", name)))),
+        Some(_) => Ok((ActionStatus::Unchanged, None)),
+      },
+    };
+    out.push(match res {
+      Ok((status, detail)) => outcome(a, status, detail),
+      Err(e) => outcome(a, ActionStatus::Failed, Some(e)),
+    });
+  }
+  (plan, out)
+}
+
+impl Plan {
+  fn changes(&self) -> bool { self.comment_changed || !self.fields.is_empty() }
+
+  fn put(&self, tag: &mut Tag) {
+    for f in &self.fields {
+      if f.value.is_empty() { tag.remove_key(&f.key); } else { tag.insert_text(f.key.clone(), f.value.clone()); }
+    }
+    if self.comment_changed { tag.insert_text(ItemKey::Comment, join_tokens(&self.tokens)); }
+  }
+}
+
+fn failed(outcomes: &[ActionOutcome]) -> bool { outcomes.iter().any(|o| o.status == ActionStatus::Failed) }
+
+/// Plan against the file as read under WRITE_LOCK and save the fields and the
+/// comment together, in one `edit_tags` save; nothing is saved when an action
+/// failed. The save's outcome is `None` when the file couldn't be read.
+fn write(p: &Path, actions: &[Action]) -> (Result<Planned, String>, Option<Result<WriteOutcome, String>>) {
+  let planned: OnceCell<Planned> = OnceCell::new();
+  let saved = edit_tags_with(p, |tf| {
+    let (plan, outcomes) = plan(tf, p, actions);
+    let targets = if plan.changes() && !failed(&outcomes) { write_targets(tf, p) } else { Vec::new() };
+    let _ = planned.set((plan, outcomes));
+    Ok(targets)
+  }, |tag: &mut Tag| {
+    if let Some((plan, _)) = planned.get() { plan.put(tag); }
+  }).map_err(String::from);
+  match planned.into_inner() {
+    Some(planned) => (Ok(planned), Some(saved)),
+    None => (Err(saved.err().unwrap_or_default()), None),
+  }
+}
+
+fn apply_file(path: &str, actions: &[Action], dry_run: bool) -> PresetFileResult {
+  let p = Path::new(path);
+  let mut res = PresetFileResult { path: path.to_string(), changed: false, error: None, actions: Vec::new(), shadow: shadow::Mark::default() };
+  let (planned, saved) = if dry_run {
+    (read_tagged(p).map(|tf| plan(&tf, p, actions)).map_err(|e| e.to_string()), None)
+  } else {
+    write(p, actions)
+  };
+  let (plan, mut outcomes) = match planned {
+    Ok(v) => v,
+    Err(e) => { res.error = Some(e); return res; }
+  };
+  let roll_back = |outcomes: &mut [ActionOutcome]| {
+    for o in outcomes.iter_mut().filter(|o| o.status == ActionStatus::Applied) { o.status = ActionStatus::RolledBack; }
+  };
+  if failed(&outcomes) {
+    roll_back(&mut outcomes);
+    res.actions = outcomes;
+    return res;
+  }
+  res.changed = plan.changes();
+  match saved {
+    None => {}
+    Some(Ok(o)) => {
+      res.shadow = o.shadow;
+      res.changed = !o.no_op;
+      // Locks were checked in `plan` under the same WRITE_LOCK; one only
+      // `edit_tags` saw (lifted in between) kept its field as it was.
+      if !o.skipped_locked.is_empty() {
+        res.error = Some(format!("left as they were, locked: {}", o.skipped_locked.iter().map(|f| f.name()).collect::<Vec<_>>().join(", ")));
+      }
+      let kept = |lock: Option<LockedField>| lock.is_some_and(|l| o.skipped_locked.contains(&l));
+      let opt = |s: &str| (!s.is_empty()).then(|| s.to_string());
+      for f in plan.fields.iter().filter(|f| res.changed && !kept(f.lock)) {
+        audit::record(path, &f.name, opt(&f.old).as_deref(), opt(&f.value).as_deref(), audit::Source::Batch);
+      }
+      if res.changed && plan.comment_changed && !kept(Some(LockedField::Comment)) {
+        audit::record_comment(path, Some(&plan.comment), Some(&join_tokens(&plan.tokens)), audit::Source::Batch);
+      }
+    }
+    Some(Err(e)) => {
+      res.changed = false;
+      res.error = Some(e);
+      roll_back(&mut outcomes);
+    }
+  }
+  res.actions = outcomes;
+  res
+}
+
+fn find<'a>(doc: &'a BankDocument, name: &str) -> Option<&'a Preset> {
+  doc.presets.as_deref().unwrap_or_default().iter().find(|p| p.name == name)
+}
+
+#[tauri::command]
+pub fn list_presets(state: tauri::State<'_, AppState>, bank: String) -> Result<Vec<Preset>, String> {
+  Ok(state.banks.load(&bank)?.presets.unwrap_or_default())
+}
+
+/// Add `preset` to the bank, replacing one of the same name.
+#[tauri::command]
+pub fn save_preset(state: tauri::State<'_, AppState>, bank: String, preset: Preset) -> Result<Vec<Preset>, CmdError> {
+  handshake::check_bank_write()?;
+  validate(&preset)?;
+  let presets = state.banks.update(&bank, |doc| {
+    let list = doc.presets.get_or_insert_with(Vec::new);
+    match list.iter_mut().find(|p| p.name == preset.name) {
+      Some(p) => *p = preset.clone(),
+      None => list.push(preset.clone()),
+    }
+    (list.clone(), true)
+  })?;
+  log_line(&format!("save_preset bank=\"{}\" name=\"{}\" actions={}", bank, preset.name, preset.actions.len()));
+  Ok(presets)
+}
+
+#[tauri::command]
+pub fn delete_preset(state: tauri::State<'_, AppState>, bank: String, name: String) -> Result<Vec<Preset>, CmdError> {
+  handshake::check_bank_write()?;
+  Ok(state.banks.update(&bank, |doc| {
+    let list = doc.presets.get_or_insert_with(Vec::new);
+    let before = list.len();
+    list.retain(|p| p.name != name);
+    (list.clone(), list.len() != before)
+  })?)
+}
+
+/// Run the bank preset `preset_name` on every file in `paths` (see the
+/// header). A failing file doesn't stop the others; `dry_run` reports what
+/// each file would get without writing.
+#[tauri::command]
+pub async fn apply_preset(app: tauri::AppHandle, paths: Vec<String>, bank: String, preset_name: String, dry_run: bool) -> Result<PresetReport, CmdError> {
+  let _span = command_span("apply_preset");
+  tauri::async_runtime::spawn_blocking(move || {
+    let doc = app.state::<AppState>().banks.load(&bank)?;
+    let preset = find(&doc, &preset_name).ok_or_else(|| format!("bank \"{}\" has no preset \"{}\"", bank, preset_name))?;
+    let actions = parse_actions(preset)?;
+    let preflight = preflight::rewrite(&paths);
+    let mut snapshot_id = None;
+    if !dry_run {
+      preflight::ensure(&preflight)?;
+      snapshot_id = snapshots::before_batch(&app, "apply_preset", &paths);
+    }
+    let results: Vec<PresetFileResult> = paths.iter().map(|p| apply_file(p, &actions, dry_run)).collect();
+    let failed = results.iter().filter(|r| r.error.is_some() || r.actions.iter().any(|a| a.status == ActionStatus::Failed)).count();
+    let changed = results.iter().filter(|r| r.changed).count();
+    let warned = results.iter().filter(|r| r.actions.iter().any(|a| a.status == ActionStatus::Missing)).count();
+    log_line(&format!("apply_preset bank=\"{}\" preset=\"{}\" dry_run={} files={} changed={} failed={} warned={}", bank, preset_name, dry_run, results.len(), changed, failed, warned));
+    Ok(PresetReport { preset: preset_name, dry_run, results, changed, failed, warned, snapshot_id, preflight })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::TagType;
+  use crate::{test_support, touched};
+
+  fn add(tags: &[&str]) -> Action { Action::AddTags { tags: tags.iter().map(|t| t.to_string()).collect() } }
+  fn set(field: &str, value: &str) -> Action { Action::SetField { field: field.into(), value: value.into() } }
+  fn statuses(r: &PresetFileResult) -> Vec<ActionStatus> { r.actions.iter().map(|a| a.status).collect() }
+  fn read(p: &Path, k: ItemKey) -> Option<String> { test_support::text(p, TagType::Id3v2, &k) }
+
+  #[test]
+  fn a_failed_action_rolls_the_file_back() {
+    let dir = test_support::scratch("presets-rollback");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#House;"), (ItemKey::TrackTitle, "Old")]);
+    let path = p.to_string_lossy().to_string();
+    let r = apply_file(&path, &[add(&["#club"]), set("title", "New"), set("bpm", "abc")], false);
+    assert_eq!(statuses(&r), [ActionStatus::RolledBack, ActionStatus::RolledBack, ActionStatus::Failed]);
+    assert!(!r.changed && r.error.is_none());
+    assert_eq!((read(&p, ItemKey::Comment).as_deref(), read(&p, ItemKey::TrackTitle).as_deref()), (Some("#House;"), Some("Old")));
+
+    // An unknown field is that action's failure, not the file's.
+    let r = apply_file(&path, &[set("title", "New"), set("mood", "dark"), Action::RequireField { field: "mood".into() }], false);
+    assert_eq!(statuses(&r), [ActionStatus::RolledBack, ActionStatus::Failed, ActionStatus::Failed]);
+    assert!(r.error.is_none());
+  }
+
+  #[test]
+  fn tags_already_there_in_another_case_are_not_added() {
+    let dir = test_support::scratch("presets-dedupe");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#House;")]);
+    let r = apply_file(&p.to_string_lossy(), &[add(&["#house"]), add(&["#club", "#Club"])], false);
+    assert_eq!(statuses(&r), [ActionStatus::Unchanged, ActionStatus::Applied]);
+    assert_eq!(read(&p, ItemKey::Comment).as_deref(), Some("#House;#club;"));
+  }
+
+  #[test]
+  fn a_lock_set_before_the_save_leaves_the_file_untouched() {
+    let dir = test_support::scratch("presets-late-lock");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#a;"), (ItemKey::TrackTitle, "Old")]);
+    let path = p.to_string_lossy().to_string();
+    let actions = [add(&["#b"]), set("title", "New"), set("grouping", "A")];
+    let planned = apply_file(&path, &actions, true);
+    assert!(planned.changed && planned.error.is_none());
+    field_locks::set(&p, &[LockedField::Comment], true).unwrap();
+    let r = apply_file(&path, &actions, false);
+    assert_eq!(statuses(&r), [ActionStatus::Failed, ActionStatus::RolledBack, ActionStatus::RolledBack]);
+    assert!(!r.changed);
+    assert_eq!(read(&p, ItemKey::TrackTitle).as_deref(), Some("Old"));
+    assert_eq!(read(&p, ItemKey::ContentGroup), None);
+    assert_eq!(read(&p, ItemKey::Comment).as_deref(), Some("#a;"));
+  }
+
+  #[test]
+  fn fields_and_comment_land_in_one_save() {
+    let dir = test_support::scratch("presets-one-save");
+    let p = test_support::audio(&dir, "a.wav");
+    test_support::add_tag(&p, TagType::RiffInfo, &[(ItemKey::TrackTitle, "Riff"), (ItemKey::Comment, "#a;")]);
+    test_support::add_tag(&p, TagType::Id3v2, &[(ItemKey::TrackTitle, "Id3"), (ItemKey::Comment, "#a;")]);
+    let before = touched::revision(&p);
+    let r = apply_file(&p.to_string_lossy(), &[add(&["#b"]), set("title", "New")], false);
+    assert!(r.changed && r.error.is_none(), "{:?}", r);
+    assert_eq!(touched::revision(&p), before + 1);
+    for tt in [TagType::RiffInfo, TagType::Id3v2] {
+      assert_eq!(test_support::text(&p, tt, &ItemKey::TrackTitle).as_deref(), Some("New"), "{:?}", tt);
+      assert_eq!(test_support::text(&p, tt, &ItemKey::Comment).as_deref(), Some("#a;#b;"), "{:?}", tt);
+    }
+  }
+}
@@ -0,0 +1,128 @@
+// Cue points for hopping through long tracks in preview. The audible span is
+// found from the peaks (leading and trailing buckets under SILENCE_DBFS are
+// cut), split into `count` equal segments, and each segment's loudest
+// DROP_IN_SECS window is suggested as where to drop in. Peaks come from the
+// disk cache when present, else from a decode run as a "preview-cues" job.
+// Files that can't be decoded fall back to an even split of the tagged
+// duration with no drop-ins. Results are cached in memory by path + size +
+// mtime + count.
+
+use std::{collections::HashMap, path::{Path, PathBuf}};
+use lofty::AudioFile;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{command_span, jobs::JobHandle, log, peaks, read_tagged, watcher::{stamp_of, FileStamp}, LogLevel};
+
+const SILENCE_DBFS: f32 = -50.0;
+const DROP_IN_SECS: f64 = 10.0;
+const MAX_CUES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CueSource { Peaks, Even }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CueSegment {
+  /// The cue: where this segment starts.
+  start_secs: f64,
+  end_secs: f64,
+  /// Start of the loudest DROP_IN_SECS window in the segment; `None` without peaks.
+  drop_in_secs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CuePoints {
+  path: String,
+  duration_secs: f64,
+  /// The audible span the segments cover (the whole track for `even`).
+  audio_start_secs: f64,
+  audio_end_secs: f64,
+  source: CueSource,
+  segments: Vec<CueSegment>,
+}
+
+/// (path, count) -> cues, with the stamp they were computed for.
+type CueCache = HashMap<(PathBuf, usize), (FileStamp, CuePoints)>;
+
+static CACHE: Lazy<Mutex<CueCache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn even(path: &str, duration: f64, count: usize) -> CuePoints {
+  let step = duration / count as f64;
+  let segments = (0..count).map(|i| CueSegment { start_secs: i as f64 * step, end_secs: (i + 1) as f64 * step, drop_in_secs: None }).collect();
+  CuePoints { path: path.to_string(), duration_secs: duration, audio_start_secs: 0.0, audio_end_secs: duration, source: CueSource::Even, segments }
+}
+
+fn from_peaks(path: &str, pk: &peaks::Peaks, count: usize) -> CuePoints {
+  let mag: Vec<f32> = pk.min.iter().zip(&pk.max).map(|(lo, hi)| lo.abs().max(hi.abs())).collect();
+  let bucket = pk.frames_per_bucket as f64 / pk.sample_rate.max(1) as f64;
+  let floor = 10f32.powf(SILENCE_DBFS / 20.0);
+  let (Some(first), Some(last)) = (mag.iter().position(|m| *m > floor), mag.iter().rposition(|m| *m > floor)) else {
+    // All silence: nothing to skip, nothing louder than anything else.
+    return even(path, pk.duration_secs, count);
+  };
+  // Energy prefix sums, for any window's loudness in O(1).
+  let mut prefix = vec![0f64; mag.len() + 1];
+  for (i, m) in mag.iter().enumerate() { prefix[i + 1] = prefix[i] + (*m as f64) * (*m as f64); }
+  let window = ((DROP_IN_SECS / bucket).ceil() as usize).max(1);
+
+  let span = last + 1 - first;
+  let segments = (0..count)
+    .map(|i| {
+      let (a, b) = (first + span * i / count, first + span * (i + 1) / count);
+      let drop_in = if b - a <= window {
+        a
+      } else {
+        (a..=b - window).max_by(|x, y| (prefix[x + window] - prefix[*x]).total_cmp(&(prefix[y + window] - prefix[*y]))).unwrap_or(a)
+      };
+      let secs = |bk: usize| (bk as f64 * bucket).min(pk.duration_secs);
+      CueSegment { start_secs: secs(a), end_secs: secs(b), drop_in_secs: Some(secs(drop_in)) }
+    })
+    .collect();
+  CuePoints {
+    path: path.to_string(),
+    duration_secs: pk.duration_secs,
+    audio_start_secs: (first as f64 * bucket).min(pk.duration_secs),
+    audio_end_secs: ((last + 1) as f64 * bucket).min(pk.duration_secs),
+    source: CueSource::Peaks,
+    segments,
+  }
+}
+
+fn compute(app: &tauri::AppHandle, path: &str, count: usize) -> Result<CuePoints, String> {
+  let p = Path::new(path);
+  if let Some(pk) = peaks::cached_peaks(p) { return Ok(from_peaks(path, &pk, count)); }
+  let job = JobHandle::start(app, "preview-cues", path);
+  let res = job.timed("decode", || peaks::get_or_compute_peaks(p, Some(job.cancel_flag()), |done, total| job.progress(done, total)));
+  job.finish(&res);
+  match res {
+    Ok(pk) => Ok(from_peaks(path, &pk, count)),
+    Err(e) => {
+      log(LogLevel::Warn, &format!("preview cues fall back to even spacing path=\"{}\": {}", path, e));
+      let tf = read_tagged(p).map_err(|e| e.to_string())?;
+      Ok(even(path, tf.properties().duration().as_secs_f64(), count))
+    }
+  }
+}
+
+/// `count` (1..=64) evenly spaced cues over the audible part of `path`, each
+/// with a suggested drop-in point.
+#[tauri::command]
+pub async fn preview_cue_points(app: tauri::AppHandle, path: String, count: usize) -> Result<CuePoints, String> {
+  let _span = command_span("preview_cue_points");
+  let count = count.clamp(1, MAX_CUES);
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = PathBuf::from(&path);
+    let stamp = stamp_of(&p).ok_or_else(|| format!("file not found: {}", path))?;
+    let key = (p, count);
+    if let Some((_, cues)) = CACHE.lock().get(&key).filter(|(s, _)| *s == stamp) { return Ok(cues.clone()); }
+    let cues = compute(&app, &path, count)?;
+    CACHE.lock().insert(key, (stamp, cues.clone()));
+    Ok(cues)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,117 @@
+// Per-track preview gain so auditioning a folder doesn't jump in volume.
+// REPLAYGAIN_TRACK_GAIN wins when a tag has it; otherwise a single RMS pass
+// over the first minute is run on a background thread and delivered as a
+// `preview-gain` event. The webview applies the value through WebAudio; we
+// only compute it. Results are cached in memory by path + size + mtime.
+
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}};
+use lofty::{ItemKey, TaggedFileExt};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{decode, log, LogLevel, profile, read_tagged, watcher::{stamp_of, FileStamp}};
+
+const MAX_GAIN_DB: f64 = 12.0;
+const RMS_SECONDS: u64 = 60;
+/// RMS level (dBFS) the estimate normalizes to; close to where ReplayGain
+/// lands on typical club masters.
+const RMS_TARGET_DBFS: f64 = -18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GainSource { ReplayGain, Rms }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewInfo {
+  pub path: String,
+  /// Clamped to ±12 dB; `None` while an estimate is pending or when the file
+  /// can't be decoded.
+  pub preview_gain_db: Option<f64>,
+  pub gain_source: Option<GainSource>,
+  /// An RMS estimate is running; a `preview-gain` event follows.
+  pub pending: bool,
+}
+
+/// Gain and where it came from, valid while the file's stamp matches.
+type Entry = (FileStamp, f64, GainSource);
+
+static CACHE: Lazy<Mutex<HashMap<PathBuf, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static IN_FLIGHT: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+fn clamp(db: f64) -> f64 { db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB) }
+
+/// "-6.54 dB", "+1.2", "-3,1 dB" (some taggers write a decimal comma).
+fn parse_gain(s: &str) -> Option<f64> {
+  let t = s.trim();
+  let t = t.strip_suffix("dB").or_else(|| t.strip_suffix("db")).or_else(|| t.strip_suffix("DB")).unwrap_or(t);
+  t.trim().replace(',', ".").parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+fn replaygain_db(p: &Path) -> Option<f64> {
+  let tf = read_tagged(p).ok()?;
+  tf.tags().iter().find_map(|t| t.get_string(&ItemKey::ReplayGainTrackGain).and_then(parse_gain))
+}
+
+/// Mono-mixed RMS over the first minute, as a gain towards RMS_TARGET_DBFS.
+fn rms_gain_db(p: &Path) -> Result<f64, String> {
+  let (mut sum, mut frames) = (0f64, 0u64);
+  let mut limit = 0u64;
+  decode::decode_f32(p, None, |info, samples| {
+    if limit == 0 { limit = info.sample_rate as u64 * RMS_SECONDS; }
+    for frame in samples.chunks(info.channels) {
+      let v = (frame.iter().sum::<f32>() / frame.len() as f32) as f64;
+      sum += v * v;
+      frames += 1;
+      if frames >= limit { return false; }
+    }
+    true
+  })?;
+  if frames == 0 { return Err("no audio decoded".into()); }
+  let rms = (sum / frames as f64).sqrt();
+  // Digital silence: nothing sensible to normalize, leave it alone.
+  if rms < 1e-6 { return Ok(0.0); }
+  Ok(RMS_TARGET_DBFS - 20.0 * rms.log10())
+}
+
+fn cached(p: &Path, stamp: FileStamp) -> Option<(f64, GainSource)> {
+  CACHE.lock().get(p).filter(|(s, _, _)| *s == stamp).map(|(_, db, src)| (*db, *src))
+}
+
+/// Preview gain for `path`. Cached and tagged values come back directly;
+/// otherwise the RMS estimate is started and the result arrives as a
+/// `preview-gain` event carrying the same `PreviewInfo` shape. The light
+/// profile skips the estimate: no gain, not pending.
+#[tauri::command]
+pub fn preview_info_for_path(app: tauri::AppHandle, path: String) -> Result<PreviewInfo, String> {
+  let p = PathBuf::from(&path);
+  let stamp = stamp_of(&p).ok_or_else(|| format!("file not found: {}", path))?;
+  let info = |db: Option<f64>, src: Option<GainSource>, pending: bool| PreviewInfo { path: path.clone(), preview_gain_db: db, gain_source: src, pending };
+
+  if let Some((db, src)) = cached(&p, stamp) { return Ok(info(Some(db), Some(src), false)); }
+  if let Some(db) = replaygain_db(&p).map(clamp) {
+    CACHE.lock().insert(p, (stamp, db, GainSource::ReplayGain));
+    return Ok(info(Some(db), Some(GainSource::ReplayGain), false));
+  }
+
+  if !profile::background_allowed() { return Ok(info(None, None, false)); }
+  if !IN_FLIGHT.lock().insert(p.clone()) { return Ok(info(None, None, true)); }
+  std::thread::spawn(move || {
+    let res = rms_gain_db(&p).map(clamp);
+    IN_FLIGHT.lock().remove(&p);
+    let ev = match res {
+      Ok(db) => {
+        CACHE.lock().insert(p.clone(), (stamp, db, GainSource::Rms));
+        PreviewInfo { path: p.to_string_lossy().to_string(), preview_gain_db: Some(db), gain_source: Some(GainSource::Rms), pending: false }
+      }
+      Err(e) => {
+        log(LogLevel::Warn, &format!("preview gain estimate failed path=\"{}\": {}", p.display(), e));
+        PreviewInfo { path: p.to_string_lossy().to_string(), preview_gain_db: None, gain_source: None, pending: false }
+      }
+    };
+    let _ = app.emit_all("preview-gain", ev);
+  });
+  Ok(info(None, None, true))
+}
@@ -0,0 +1,153 @@
+// What was listened to. The frontend calls `note_preview` when a preview
+// stops; the last MAX_ENTRIES plays (path, time listened, when) are kept in
+// data dir `preview_history.json`, oldest dropped first. The history list
+// joins them with the cached metadata, and `preview_counts` gives the list
+// view a play count per track. Maintenance also drops plays older than
+// MAX_AGE_DAYS and those of files that are gone.
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use chrono::{DateTime, Duration, Local};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{command_span, data_dir, log_line, maintenance, meta_cache, portable::{self, RootRel}, track_meta_cached, write_atomic, TrackMeta};
+
+const MAX_ENTRIES: usize = 500;
+const MAX_AGE_DAYS: i64 = 180;
+const DEFAULT_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewEntry {
+  pub path: String,
+  pub listened_ms: u64,
+  /// RFC 3339, local time.
+  pub at: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub place: Option<RootRel>,
+}
+
+static HISTORY: Lazy<Mutex<Vec<PreviewEntry>>> = Lazy::new(|| Mutex::new(load()));
+
+fn history_path() -> PathBuf { data_dir().join("preview_history.json") }
+
+fn load() -> Vec<PreviewEntry> {
+  fs::read_to_string(history_path()).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+fn save(h: &[PreviewEntry]) {
+  let res = serde_json::to_vec(h).map_err(|e| e.to_string()).and_then(|json| write_atomic(&history_path(), &json));
+  if let Err(e) = res { log_line(&format!("preview_history save failed: {}", e)); }
+}
+
+fn parse_time(at: &str) -> Option<DateTime<chrono::FixedOffset>> { DateTime::parse_from_rfc3339(at).ok() }
+
+/// Keep plays of `from` attached to the file after a rename.
+pub fn rename(from: &Path, to: &Path) {
+  let (from, to_s) = (from.to_string_lossy(), to.to_string_lossy().to_string());
+  let mut h = HISTORY.lock();
+  let mut hit = false;
+  for e in h.iter_mut().filter(|e| e.path == from) {
+    e.path = to_s.clone();
+    e.place = portable::place(to);
+    hit = true;
+  }
+  if hit { save(&h); }
+}
+
+/// Entries and size on disk.
+pub fn stats() -> (usize, u64) {
+  (HISTORY.lock().len(), fs::metadata(history_path()).map(|m| m.len()).unwrap_or(0))
+}
+
+/// Drop plays older than MAX_AGE_DAYS and of files that are gone. `None` when
+/// `stop` cut it short; otherwise (dropped, bytes before, bytes after).
+pub fn prune(stop: &dyn Fn() -> bool) -> Option<(usize, u64, u64)> {
+  let before = stats().1;
+  let cutoff = Local::now() - Duration::days(MAX_AGE_DAYS);
+  let mut h = HISTORY.lock();
+  let mut gone: HashMap<String, bool> = HashMap::new();
+  for e in h.iter() {
+    if stop() { return None; }
+    if !gone.contains_key(&e.path) { gone.insert(e.path.clone(), maintenance::source_gone(Path::new(&e.path))); }
+  }
+  let n = h.len();
+  h.retain(|e| !gone[&e.path] && parse_time(&e.at).is_some_and(|t| t >= cutoff));
+  let dropped = n - h.len();
+  if dropped > 0 { save(&h); }
+  drop(h);
+  Some((dropped, before, stats().1))
+}
+
+/// Record one preview of `path` that played for `duration_listened_ms`.
+#[tauri::command]
+pub fn note_preview(path: String, duration_listened_ms: u64) {
+  let place = portable::place(Path::new(&path));
+  let mut h = HISTORY.lock();
+  h.push(PreviewEntry { path, listened_ms: duration_listened_ms, at: Local::now().to_rfc3339(), place });
+  let over = h.len().saturating_sub(MAX_ENTRIES);
+  if over > 0 { h.drain(..over); }
+  save(&h);
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewItem {
+  #[serde(flatten)]
+  entry: PreviewEntry,
+  /// From the metadata cache; `None` when the file can't be read any more.
+  meta: Option<TrackMeta>,
+}
+
+/// Plays newest first, at most `limit` (DEFAULT_LIMIT), only those at or
+/// after `since` (RFC 3339) when given.
+#[tauri::command]
+pub async fn get_preview_history(limit: Option<usize>, since: Option<String>) -> Result<Vec<PreviewItem>, String> {
+  let _span = command_span("get_preview_history");
+  tauri::async_runtime::spawn_blocking(move || {
+    let since = match since.as_deref() {
+      Some(s) => Some(parse_time(s).ok_or_else(|| format!("not an RFC 3339 time: {}", s))?),
+      None => None,
+    };
+    let entries: Vec<PreviewEntry> = HISTORY.lock()
+      .iter()
+      .rev()
+      .filter(|e| since.is_none_or(|s| parse_time(&e.at).is_some_and(|t| t >= s)))
+      .take(limit.unwrap_or(DEFAULT_LIMIT))
+      .cloned()
+      .collect();
+    Ok(entries.into_iter().map(|entry| {
+      let meta = meta_cache::get(Path::new(&entry.path)).ok().map(|m| track_meta_cached(&entry.path, &m));
+      PreviewItem { entry, meta }
+    }).collect())
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewCount {
+  path: String,
+  plays: usize,
+  listened_ms: u64,
+  last_at: String,
+}
+
+/// Play counts for the previewed files under `folder`, for the list view's
+/// "listened" mark. Files never previewed aren't listed.
+#[tauri::command]
+pub fn preview_counts(folder: String) -> Vec<PreviewCount> {
+  let root = Path::new(&folder);
+  let mut by_path: HashMap<String, PreviewCount> = HashMap::new();
+  for e in HISTORY.lock().iter().filter(|e| Path::new(&e.path).starts_with(root)) {
+    let c = by_path.entry(e.path.clone()).or_insert_with(|| PreviewCount { path: e.path.clone(), plays: 0, listened_ms: 0, last_at: String::new() });
+    c.plays += 1;
+    c.listened_ms += e.listened_ms;
+    c.last_at = e.at.clone();
+  }
+  let mut out: Vec<PreviewCount> = by_path.into_values().collect();
+  out.sort_by(|a, b| a.path.cmp(&b.path));
+  out
+}
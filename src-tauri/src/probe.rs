@@ -0,0 +1,64 @@
+// Read-only look at files listed by `include_unsupported` scans, for the
+// greyed-out rows: container and codec from the file's own headers when
+// symphonia knows the format, otherwise just size and modification time.
+// Nothing here writes; tag writes refuse these files in `formats::ensure_writable`.
+
+use std::{fs, io::Read, path::Path};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::{command_span, decode, ext_lower, extension_check, supported_ext};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeInfo {
+  path: String,
+  /// Listed as a normal, taggable file.
+  supported: bool,
+  size: u64,
+  /// RFC 3339.
+  modified: Option<String>,
+  /// From the content where recognized ("matroska", "m4a", ...), else the extension.
+  container: String,
+  codec: Option<String>,
+  sample_rate: Option<u32>,
+  channels: Option<usize>,
+  duration_secs: Option<f64>,
+  /// Why the stream fields are empty, when the probe failed.
+  probe_error: Option<String>,
+}
+
+/// EBML header: Matroska and WebM, which `extension_check` doesn't sniff.
+fn ebml(p: &Path) -> bool {
+  let mut head = [0u8; 4];
+  fs::File::open(p).and_then(|mut f| f.read_exact(&mut head)).is_ok() && head == [0x1A, 0x45, 0xDF, 0xA3]
+}
+
+#[tauri::command]
+pub async fn probe_file(path: String) -> Result<ProbeInfo, String> {
+  let _span = command_span("probe_file");
+  tauri::async_runtime::spawn_blocking(move || {
+    let p = Path::new(&path);
+    let meta = fs::metadata(p).map_err(|e| e.to_string())?;
+    let modified = meta.modified().ok().map(|t| DateTime::<Local>::from(t).to_rfc3339());
+    let container = extension_check::sniff(p).map(str::to_string).unwrap_or_else(|| if ebml(p) { "matroska".into() } else { ext_lower(p) });
+    let (s, probe_error) = match decode::probe_stream(p) {
+      Ok(s) => (s, None),
+      Err(e) => (Default::default(), Some(e)),
+    };
+    Ok(ProbeInfo {
+      supported: supported_ext(p),
+      path,
+      size: meta.len(),
+      modified,
+      container,
+      codec: s.codec,
+      sample_rate: s.sample_rate,
+      channels: s.channels,
+      duration_secs: s.duration_secs,
+      probe_error,
+    })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
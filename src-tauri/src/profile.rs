@@ -0,0 +1,47 @@
+// Operation profile: how much background work the app does on its own.
+// "full" is the normal behavior. "light" is for weak machines: background
+// fills (artwork palettes, preview gain estimates, the post-scan metadata
+// cache refresh) don't run, batch readers use LIGHT_WORKERS threads, the
+// debounced stores flush less often, and pre-batch snapshots are captured but
+// only written out once no job is running. Everything here reads the live
+// value, so `set_operation_profile` takes effect on the next file.
+
+use std::{sync::atomic::{AtomicU8, Ordering}, time::Duration};
+use serde::{Deserialize, Serialize};
+
+const LIGHT_WORKERS: usize = 2;
+const FULL_MAX_WORKERS: usize = 8;
+/// Debounce intervals are stretched by this much in light mode.
+const LIGHT_FLUSH_FACTOR: u32 = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationProfile { #[default] Full, Light }
+
+impl OperationProfile {
+  pub fn token(self) -> &'static str {
+    match self { OperationProfile::Full => "full", OperationProfile::Light => "light" }
+  }
+}
+
+pub static PROFILE: AtomicU8 = AtomicU8::new(OperationProfile::Full as u8);
+
+pub fn current() -> OperationProfile {
+  if PROFILE.load(Ordering::Relaxed) == OperationProfile::Light as u8 { OperationProfile::Light } else { OperationProfile::Full }
+}
+
+pub fn is_light() -> bool { current() == OperationProfile::Light }
+
+/// Whether optional background fills should start (or keep going).
+pub fn background_allowed() -> bool { !is_light() }
+
+/// Threads for a parallel batch read.
+pub fn workers() -> usize {
+  if is_light() { return LIGHT_WORKERS; }
+  std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(FULL_MAX_WORKERS)
+}
+
+/// `base` debounce interval under the current profile.
+pub fn flush_interval(base: Duration) -> Duration {
+  if is_light() { base * LIGHT_FLUSH_FACTOR } else { base }
+}
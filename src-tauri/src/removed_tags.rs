@@ -0,0 +1,264 @@
+// Undoable tag removal. `remove_tags_soft` drops tags from comments like a
+// normal batch remove, but keeps each removed token (file, tag, original
+// position) in data dir `removed_tags.json` until it is restored or purged.
+// The history is capped at MAX_ENTRIES; the oldest removals go first.
+
+use std::{fs, path::{Path, PathBuf}};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  audit, command_span, data_dir, error::CmdError, field_locks::LockedField, jobs::JobHandle, log_line, portable::{self, RootRel}, preflight::{self, Preflight},
+  read_comment, read_tagged, retry_queue, snapshots, split_comment_tokens, tag_ops::join_tokens, tag_policy, write_atomic,
+};
+
+const MAX_ENTRIES: usize = 20_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemovedTag {
+  pub path: String,
+  /// The token as it was written in the file.
+  pub tag: String,
+  /// Index among the comment's tokens before the removal.
+  pub position: usize,
+  pub removed_at: String,
+  pub session: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub place: Option<RootRel>,
+}
+
+static HISTORY: Lazy<Mutex<Vec<RemovedTag>>> = Lazy::new(|| Mutex::new(load()));
+
+fn history_path() -> PathBuf { data_dir().join("removed_tags.json") }
+
+fn load() -> Vec<RemovedTag> {
+  fs::read_to_string(history_path()).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+fn save(h: &[RemovedTag]) {
+  let res = serde_json::to_vec_pretty(h).map_err(|e| e.to_string()).and_then(|json| write_atomic(&history_path(), &json));
+  if let Err(e) = res { log_line(&format!("removed_tags save failed: {}", e)); }
+}
+
+fn push(entries: Vec<RemovedTag>) {
+  if entries.is_empty() { return; }
+  let mut h = HISTORY.lock();
+  h.extend(entries);
+  let over = h.len().saturating_sub(MAX_ENTRIES);
+  if over > 0 { h.drain(..over); }
+  save(&h);
+}
+
+/// Keep history entries pointing at `from` attached to the file after a rename.
+pub fn rename(from: &Path, to: &Path) {
+  let (from, to_s) = (from.to_string_lossy(), to.to_string_lossy().to_string());
+  let mut h = HISTORY.lock();
+  let mut hit = false;
+  for e in h.iter_mut().filter(|e| e.path == from) {
+    e.path = to_s.clone();
+    e.place = portable::place(to);
+    hit = true;
+  }
+  if hit { save(&h); }
+}
+
+/// `tag` matches `token` as written or after the tag policy ("#Melodic" removes "#melodic").
+pub fn matches(token: &str, tag: &str, policy: &tag_policy::TagPolicy) -> bool {
+  token == tag || tag_policy::normalize_tag(tag, policy).is_ok_and(|n| n == token)
+}
+
+/// Put `tag` back at `position`, but never after the `TagB:` bank marker.
+fn reinsert(comment: &str, tag: &str, position: usize) -> String {
+  let mut tokens = split_comment_tokens(comment);
+  if tokens.iter().any(|t| t == tag) { return join_tokens(&tokens); }
+  let bank_ix = tokens.iter().position(|t| t.starts_with("TagB:")).unwrap_or(tokens.len());
+  tokens.insert(position.min(bank_ix), tag.to_string());
+  join_tokens(&tokens)
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftTagResult {
+  path: String,
+  /// Tokens removed or restored, as written.
+  tags: Vec<String>,
+  /// Set alongside `tags` when a removal was queued for retry; it is in the
+  /// history and can be restored like one that was written.
+  error: Option<String>,
+  /// `["comment"]` when the comment is locked; nothing was changed.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftTagReport {
+  results: Vec<SoftTagResult>,
+  /// Files whose comment changed.
+  changed: usize,
+  cancelled: bool,
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+fn remove_blocking(job: &JobHandle, paths: &[String], tags: &[String]) -> Result<SoftTagReport, CmdError> {
+  let policy = tag_policy::policy();
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(paths));
+  preflight::ensure(&preflight)?;
+  let snapshot_id = snapshots::before_batch(job.app(), "remove_tags_soft", paths);
+  let (mut results, mut changed, mut cancelled) = (Vec::new(), 0, false);
+  job.begin_phase("write", paths.len() as u64);
+  for (i, path) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let mut res = SoftTagResult { path: path.clone(), ..Default::default() };
+    let p = Path::new(path);
+    match job.timed("parse", || read_tagged(p).map(|tf| read_comment(&tf, p))) {
+      Ok(old) => {
+        let tokens = split_comment_tokens(&old);
+        let (gone, kept): (Vec<_>, Vec<_>) = tokens.into_iter().enumerate().partition(|(_, t)| {
+          !t.starts_with("TagB:") && tags.iter().any(|tag| matches(t, tag, &policy))
+        });
+        if !gone.is_empty() {
+          let new = join_tokens(&kept.into_iter().map(|(_, t)| t).collect::<Vec<_>>());
+          let written = match job.timed("write", || retry_queue::write_comment(path, &new)) {
+            Ok(o) if !o.skipped_locked.is_empty() => { res.skipped_locked = o.skipped_locked; false }
+            Ok(o) if o.no_op => false,
+            Ok(_) => { changed += 1; true }
+            // Queued removals happen later; keep them undoable too.
+            Err(e) => { let queued = retry_queue::is_queued(&e); res.error = Some(e.to_string()); queued }
+          };
+          if written {
+            let (at, session) = (Local::now().to_rfc3339(), audit::session_id().to_string());
+            let place = portable::place(p);
+            push(gone.iter().map(|(ix, t)| RemovedTag {
+              path: path.clone(), tag: t.clone(), position: *ix, removed_at: at.clone(), session: session.clone(), place: place.clone(),
+            }).collect());
+            res.tags = gone.into_iter().map(|(_, t)| t).collect();
+          }
+        }
+      }
+      Err(e) => res.error = Some(e.to_string()),
+    }
+    results.push(res);
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  log_line(&format!("remove_tags_soft tags={:?} files={} changed={} cancelled={}", tags, paths.len(), changed, cancelled));
+  Ok(SoftTagReport { results, changed, cancelled, snapshot_id, preflight })
+}
+
+fn restore_blocking(job: &JobHandle, paths: &[String], tag: &str) -> Result<SoftTagReport, CmdError> {
+  let policy = tag_policy::policy();
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(paths));
+  preflight::ensure(&preflight)?;
+  let snapshot_id = snapshots::before_batch(job.app(), "restore_removed_tag", paths);
+  let (mut results, mut changed, mut cancelled) = (Vec::new(), 0, false);
+  job.begin_phase("write", paths.len() as u64);
+  for (i, path) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let mut res = SoftTagResult { path: path.clone(), ..Default::default() };
+    let p = Path::new(path);
+    // Latest removal of this tag from this file.
+    let entry = HISTORY.lock().iter().rev().find(|e| e.path == *path && matches(&e.tag, tag, &policy)).cloned();
+    if let Some(entry) = entry {
+      let res_write = job.timed("parse", || read_tagged(p).map(|tf| read_comment(&tf, p))).map_err(|e| e.to_string()).and_then(|old| {
+        let new = reinsert(&old, &entry.tag, entry.position);
+        if new == old { return Ok(false); }
+        job.timed("write", || retry_queue::write_comment(path, &new)).map(|o| { res.skipped_locked = o.skipped_locked; !o.no_op }).map_err(|e| e.to_string())
+      });
+      match res_write {
+        // Locked: not put back, so it stays in the history.
+        Ok(_) if !res.skipped_locked.is_empty() => {}
+        Ok(wrote) => {
+          let mut h = HISTORY.lock();
+          h.retain(|e| !(e.path == *path && e.tag == entry.tag));
+          save(&h);
+          if wrote { changed += 1; }
+          res.tags.push(entry.tag);
+        }
+        Err(e) => res.error = Some(e),
+      }
+    }
+    results.push(res);
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  log_line(&format!("restore_removed_tag tag=\"{}\" files={} changed={} cancelled={}", tag, paths.len(), changed, cancelled));
+  Ok(SoftTagReport { results, changed, cancelled, snapshot_id, preflight })
+}
+
+/// Remove `tags` from every file in `paths`, keeping what was removed so
+/// `restore_removed_tag` can put it back.
+#[tauri::command]
+pub async fn remove_tags_soft(app: tauri::AppHandle, paths: Vec<String>, tags: Vec<String>) -> Result<SoftTagReport, CmdError> {
+  let _span = command_span("remove_tags_soft");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "soft-remove-tags", &tags.join(", "));
+    let res = remove_blocking(&job, &paths, &tags);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Re-add the most recent soft removal of `tag` to each file in `paths`, at
+/// its original position where possible. Files without one are skipped.
+#[tauri::command]
+pub async fn restore_removed_tag(app: tauri::AppHandle, paths: Vec<String>, tag: String) -> Result<SoftTagReport, CmdError> {
+  let _span = command_span("restore_removed_tag");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "restore-removed-tag", &tag);
+    let res = restore_blocking(&job, &paths, &tag);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Forget removals older than `older_than_days` (0 = all). Returns how many went.
+#[tauri::command]
+pub fn purge_removed_history(older_than_days: u32) -> usize {
+  let cutoff = Local::now() - chrono::Duration::days(older_than_days as i64);
+  let mut h = HISTORY.lock();
+  let before = h.len();
+  h.retain(|e| chrono::DateTime::parse_from_rfc3339(&e.removed_at).is_ok_and(|t| t > cutoff));
+  let purged = before - h.len();
+  if purged > 0 {
+    save(&h);
+    log_line(&format!("purge_removed_history older_than_days={} purged={}", older_than_days, purged));
+  }
+  purged
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemovedTagGroup {
+  tag: String,
+  paths: Vec<String>,
+  /// Latest removal in the group.
+  removed_at: String,
+}
+
+/// Removals still undoable, grouped by tag, for the session summary
+/// ("removed #wip from 12 files"). `session_only` limits it to this session.
+#[tauri::command]
+pub fn list_removed_tags(session_only: bool) -> Vec<RemovedTagGroup> {
+  let session = audit::session_id();
+  let mut groups: Vec<RemovedTagGroup> = Vec::new();
+  for e in HISTORY.lock().iter().filter(|e| !session_only || e.session == session) {
+    match groups.iter_mut().find(|g| g.tag == e.tag) {
+      Some(g) => {
+        if !g.paths.contains(&e.path) { g.paths.push(e.path.clone()); }
+        if e.removed_at > g.removed_at { g.removed_at = e.removed_at.clone(); }
+      }
+      None => groups.push(RemovedTagGroup { tag: e.tag.clone(), paths: vec![e.path.clone()], removed_at: e.removed_at.clone() }),
+    }
+  }
+  groups.sort_by(|a, b| b.removed_at.cmp(&a.removed_at));
+  groups
+}
@@ -0,0 +1,353 @@
+// Writes from batch jobs that failed for a reason that usually passes: the
+// file is locked or held open by another program (DJ software, a sync
+// client), or its volume is unavailable. They are kept in data dir
+// `retry_queue.json` and retried in the background with exponential backoff
+// while the app runs. Comment writes to a lost volume already wait in the
+// volumes.rs queue and aren't duplicated here. Items that keep failing, or
+// are older than a week, are marked dead and stay listed until dropped.
+
+use std::{collections::BTreeMap, fs, io, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use chrono::{DateTime, Local};
+use lofty::Accessor;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  apply_meta_patch, audit, command_span, data_dir, dates, error::CmdError, field_limits::FieldLimitHit, log_line, portable::{self, RootRel}, preferred_tag, read_tagged,
+  volumes, write_atomic, write_comment_as, MetaPatch, WriteOutcome,
+};
+
+const TICK: Duration = Duration::from_secs(5);
+const FIRST_DELAY_SECS: i64 = 15;
+const MAX_DELAY_SECS: i64 = 30 * 60;
+/// Counting the original failure.
+const MAX_ATTEMPTS: u32 = 12;
+const MAX_AGE_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum RetryOp {
+  Comment { comment: String },
+  Metadata { patch: MetaPatch },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryClass { Locked, SharingViolation, VolumeUnavailable }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryItem {
+  id: String,
+  path: String,
+  /// Lets the item find its file again after the crate moved (portable.rs).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  place: Option<RootRel>,
+  #[serde(flatten)]
+  op: RetryOp,
+  source: audit::Source,
+  class: RetryClass,
+  attempts: u32,
+  last_error: String,
+  queued_at: String,
+  /// `None` once dead.
+  next_attempt_at: Option<String>,
+  dead: bool,
+  dead_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryRunReport {
+  /// Item ids.
+  succeeded: Vec<String>,
+  failed: Vec<String>,
+  died: Vec<String>,
+  /// Succeeded ids whose values were cut or left out for a length limit (see `field_limits`).
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  limited: BTreeMap<String, Vec<FieldLimitHit>>,
+}
+
+static QUEUE: Lazy<Mutex<Vec<RetryItem>>> = Lazy::new(|| Mutex::new(load()));
+// One pass at a time, whether from the timer or `retry_now`.
+static RUNNING: Mutex<()> = parking_lot::const_mutex(());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn queue_path() -> PathBuf { data_dir().join("retry_queue.json") }
+
+fn load() -> Vec<RetryItem> {
+  fs::read_to_string(queue_path()).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+fn save(q: &[RetryItem]) {
+  let res = serde_json::to_vec_pretty(q).map_err(|e| e.to_string()).and_then(|json| write_atomic(&queue_path(), &json));
+  if let Err(e) = res { log_line(&format!("retry_queue save failed: {}", e)); }
+}
+
+fn next_attempt(attempts: u32) -> String {
+  let secs = (FIRST_DELAY_SECS << attempts.saturating_sub(1).min(16)).min(MAX_DELAY_SECS);
+  (Local::now() + chrono::Duration::seconds(secs)).to_rfc3339()
+}
+
+/// EBUSY / ETXTBSY / EAGAIN, or Windows ERROR_SHARING_VIOLATION / ERROR_LOCK_VIOLATION.
+fn lock_class(e: &io::Error) -> Option<RetryClass> {
+  #[cfg(windows)]
+  match e.raw_os_error() {
+    Some(32) => return Some(RetryClass::SharingViolation),
+    Some(33) => return Some(RetryClass::Locked),
+    _ => {}
+  }
+  #[cfg(unix)]
+  if matches!(e.raw_os_error(), Some(16) | Some(26)) { return Some(RetryClass::Locked); }
+  (e.kind() == io::ErrorKind::WouldBlock).then_some(RetryClass::Locked)
+}
+
+/// Why writing `p` fails right now, if it's a reason worth waiting out. Write
+/// errors reach us as text, so the file is probed instead of the message parsed.
+fn classify(p: &Path) -> Option<RetryClass> {
+  if volumes::detect(p).is_some() { return Some(RetryClass::VolumeUnavailable); }
+  fs::OpenOptions::new().read(true).write(true).open(p).err().as_ref().and_then(lock_class)
+}
+
+/// Queue `op` if `error` on `path` is transient; returns whether it was.
+fn offer(path: &str, op: RetryOp, source: audit::Source, error: &str) -> bool {
+  let Some(class) = classify(Path::new(path)) else { return false };
+  let mut q = QUEUE.lock();
+  // A newer comment for the same file supersedes the queued one.
+  if matches!(op, RetryOp::Comment { .. }) {
+    q.retain(|i| i.dead || i.path != path || !matches!(i.op, RetryOp::Comment { .. }));
+  }
+  q.push(RetryItem {
+    id: format!("{}-{}", Local::now().timestamp_millis(), NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+    path: path.to_string(),
+    place: portable::place(Path::new(path)),
+    op,
+    source,
+    class,
+    attempts: 1,
+    last_error: error.to_string(),
+    queued_at: Local::now().to_rfc3339(),
+    next_attempt_at: Some(next_attempt(1)),
+    dead: false,
+    dead_reason: None,
+  });
+  save(&q);
+  drop(q);
+  log_line(&format!("retry_queue queued path=\"{}\" class={:?}", path, class));
+  true
+}
+
+const QUEUED_NOTE: &str = " (queued for retry)";
+
+fn queued_note(e: impl std::fmt::Display) -> String { format!("{}{}", e, QUEUED_NOTE) }
+
+/// `e` from `write_comment` means the write is waiting in a queue (here or
+/// volumes.rs) rather than lost.
+pub fn is_queued(e: &CmdError) -> bool {
+  match e {
+    CmdError::VolumeUnavailable { queued, .. } => *queued,
+    CmdError::Other { message } => message.ends_with(QUEUED_NOTE),
+    _ => false,
+  }
+}
+
+/// `write_comment_as` for batch jobs; transient failures are queued.
+pub fn write_comment(path: &str, comment: &str) -> Result<WriteOutcome, CmdError> {
+  write_comment_as(path, comment, audit::Source::Batch).map_err(|e| match e {
+    CmdError::VolumeUnavailable { queued: true, .. } => e,
+    e if offer(path, RetryOp::Comment { comment: comment.to_string() }, audit::Source::Batch, &e.to_string()) => {
+      CmdError::Other { message: queued_note(&e) }
+    }
+    e => e,
+  })
+}
+
+/// `apply_meta_patch` for batch jobs; transient failures are queued.
+pub fn apply_patch(p: &Path, patch: &MetaPatch) -> Result<WriteOutcome, String> {
+  apply_meta_patch(p, patch).map_err(|e| offer_patch(p, patch, e))
+}
+
+/// Queue `patch` after a batch write of it to `p` failed with `e`, if that's
+/// transient; `e`, noted when queued. For jobs that write more than `apply_patch`.
+pub fn offer_patch(p: &Path, patch: &MetaPatch, e: String) -> String {
+  if offer(&p.to_string_lossy(), RetryOp::Metadata { patch: patch.clone() }, audit::Source::Batch, &e) { queued_note(e) } else { e }
+}
+
+fn patch_fields(patch: &MetaPatch) -> [(&'static str, Option<&String>); 5] {
+  [
+    ("title", patch.title.as_ref()),
+    ("artist", patch.artist.as_ref()),
+    ("genre", patch.genre.as_ref()),
+    ("release_date", patch.release_date.as_ref()),
+    ("original_date", patch.original_date.as_ref()),
+  ]
+}
+
+fn tag_field(tag: &lofty::Tag, field: &str) -> Option<String> {
+  match field {
+    "title" => tag.title().map(|s| s.to_string()),
+    "artist" => tag.artist().map(|s| s.to_string()),
+    "genre" => tag.genre().map(|s| s.to_string()),
+    "release_date" => dates::release_date(tag),
+    _ => dates::original_date(tag),
+  }
+}
+
+/// The length limits the write hit.
+fn attempt(item: &mut RetryItem) -> Result<Vec<FieldLimitHit>, String> {
+  if let Some(moved) = portable::resolve(&item.path, item.place.as_ref()) { item.path = moved.to_string_lossy().to_string(); }
+  let p = Path::new(&item.path);
+  if !p.is_file() && volumes::detect(p).is_none() {
+    // Its root isn't mounted yet: keep waiting rather than guess another root.
+    if item.place.as_ref().is_some_and(|pl| !portable::root_present(pl)) { return Err("the file's root folder is not available".into()); }
+    return Err("file no longer exists at this path".into());
+  }
+  match &item.op {
+    RetryOp::Comment { comment } => write_comment_as(&item.path, comment, item.source).map(|o| o.limited).map_err(String::from),
+    RetryOp::Metadata { patch } => {
+      let old: Vec<Option<String>> = {
+        let tf = read_tagged(p).map_err(|e| e.to_string())?;
+        let tag = preferred_tag(&tf, p);
+        patch_fields(patch).iter().map(|(f, _)| tag.and_then(|t| tag_field(t, f))).collect()
+      };
+      let outcome = apply_meta_patch(p, patch)?;
+      for ((field, new), old) in patch_fields(patch).into_iter().zip(old) {
+        let Some(new) = new else { continue };
+        if outcome.skipped_locked.iter().any(|f| f.audit_name() == field) { continue; }
+        audit::record(&item.path, field, old.as_deref(), Some(new).filter(|v| !v.is_empty()).map(|v| v.as_str()), item.source);
+      }
+      Ok(outcome.limited)
+    }
+  }
+}
+
+fn kill(item: &mut RetryItem, reason: String) {
+  item.dead = true;
+  item.dead_reason = Some(reason);
+  item.next_attempt_at = None;
+}
+
+/// Retry live items (only those due unless `all`), then retire the hopeless.
+fn run(all: bool) -> RetryRunReport {
+  let _running = RUNNING.lock();
+  let now = Local::now();
+  let due = |i: &RetryItem| {
+    !i.dead && (all || i.next_attempt_at.as_deref().and_then(|t| DateTime::parse_from_rfc3339(t).ok()).is_none_or(|t| t <= now))
+  };
+  let batch: Vec<RetryItem> = QUEUE.lock().iter().filter(|i| due(i)).cloned().collect();
+  let mut report = RetryRunReport::default();
+  let mut outcomes = Vec::new();
+  for mut item in batch {
+    let res = attempt(&mut item);
+    outcomes.push((item.id, item.path, res));
+  }
+
+  let mut q = QUEUE.lock();
+  for (id, path, res) in outcomes {
+    let Some(i) = q.iter().position(|i| i.id == id) else { continue };
+    q[i].path = path;
+    match res {
+      Ok(limited) => {
+        q.remove(i);
+        if !limited.is_empty() { report.limited.insert(id.clone(), limited); }
+        report.succeeded.push(id);
+      }
+      Err(e) => {
+        let item = &mut q[i];
+        item.attempts += 1;
+        let root_gone = item.place.as_ref().is_some_and(|pl| !portable::root_present(pl));
+        match classify(Path::new(&item.path)).or(root_gone.then_some(RetryClass::VolumeUnavailable)) {
+          Some(class) => {
+            item.class = class;
+            item.next_attempt_at = Some(next_attempt(item.attempts));
+            report.failed.push(id);
+          }
+          None => {
+            kill(item, format!("no longer a transient failure: {}", e));
+            report.died.push(id);
+          }
+        }
+        item.last_error = e;
+      }
+    }
+  }
+  for item in q.iter_mut().filter(|i| !i.dead) {
+    let age = DateTime::parse_from_rfc3339(&item.queued_at).map(|t| now.signed_duration_since(t)).unwrap_or_default();
+    if item.attempts >= MAX_ATTEMPTS {
+      kill(item, format!("gave up after {} attempts", item.attempts));
+      report.died.push(item.id.clone());
+    } else if age > chrono::Duration::days(MAX_AGE_DAYS) {
+      kill(item, format!("not written within {} days", MAX_AGE_DAYS));
+      report.died.push(item.id.clone());
+    }
+  }
+  let changed = !report.succeeded.is_empty() || !report.failed.is_empty() || !report.died.is_empty();
+  if changed { save(&q); }
+  drop(q);
+  if changed {
+    log_line(&format!("retry_queue run succeeded={} failed={} died={}", report.succeeded.len(), report.failed.len(), report.died.len()));
+  }
+  report
+}
+
+/// Point queued items under `old_root` at `new_root`; see `portable::rebase_references`.
+pub fn rebase(old_root: &Path, new_root: &Path) -> usize {
+  let mut q = QUEUE.lock();
+  let mut n = 0;
+  for item in q.iter_mut() {
+    let Some(to) = portable::rebased(Path::new(&item.path), old_root, new_root) else { continue };
+    item.path = to.to_string_lossy().to_string();
+    n += 1;
+  }
+  if n > 0 { save(&q); }
+  n
+}
+
+/// Follow a rename seen by the folder watcher.
+pub fn rename(from: &Path, to: &Path) {
+  let mut q = QUEUE.lock();
+  let mut n = 0;
+  for item in q.iter_mut().filter(|i| Path::new(&i.path) == from) {
+    item.path = to.to_string_lossy().to_string();
+    item.place = portable::place(to);
+    n += 1;
+  }
+  if n > 0 { save(&q); }
+}
+
+/// Background retry loop; started once from setup.
+pub fn start() {
+  std::thread::spawn(|| loop {
+    std::thread::sleep(TICK);
+    if QUEUE.lock().iter().any(|i| !i.dead) { run(false); }
+  });
+}
+
+/// Latest live queued comment for `path` and when it was queued.
+pub fn pending_comment(path: &str) -> Option<(String, String)> {
+  QUEUE.lock().iter().rev().filter(|i| !i.dead && i.path == path).find_map(|i| match &i.op {
+    RetryOp::Comment { comment } => Some((comment.clone(), i.queued_at.clone())),
+    RetryOp::Metadata { .. } => None,
+  })
+}
+
+#[tauri::command]
+pub fn list_retry_queue() -> Vec<RetryItem> { QUEUE.lock().clone() }
+
+/// Retry every live item now, ignoring backoff. Dead items stay dead.
+#[tauri::command]
+pub async fn retry_now() -> Result<RetryRunReport, String> {
+  let _span = command_span("retry_now");
+  tauri::async_runtime::spawn_blocking(|| run(true)).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn drop_retry_item(id: String) -> bool {
+  let mut q = QUEUE.lock();
+  let before = q.len();
+  q.retain(|i| i.id != id);
+  let dropped = q.len() != before;
+  if dropped { save(&q); }
+  dropped
+}
@@ -0,0 +1,109 @@
+// Frontend session state (open folder, selected track, scroll position, ...).
+// The blob is opaque to Rust; we only cap its size, stamp it and debounce writes
+// so the UI can call save on every selection change without churning the disk.
+
+use std::{fs, path::PathBuf, time::{Duration, Instant}};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{data_dir, log, LogLevel, profile, write_atomic};
+
+const SESSION_STATE_SCHEMA: u8 = 1;
+const MAX_STATE_BYTES: usize = 256 * 1024;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSnapshot {
+  schema: u8,
+  saved_at: String,
+  state: serde_json::Value,
+}
+
+#[derive(Default)]
+struct Debouncer {
+  pending: Option<SessionSnapshot>,
+  last_flush: Option<Instant>,
+  flush_scheduled: bool,
+}
+
+static DEBOUNCER: Lazy<Mutex<Debouncer>> = Lazy::new(|| Mutex::new(Debouncer::default()));
+
+fn session_state_path() -> PathBuf { data_dir().join("session_state.json") }
+
+fn flush_locked(d: &mut Debouncer) {
+  if let Some(snap) = d.pending.take() {
+    let res = serde_json::to_vec(&snap)
+      .map_err(|e| e.to_string())
+      .and_then(|bytes| {
+        let p = session_state_path();
+        if let Some(dir) = p.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+        write_atomic(&p, &bytes)
+      });
+    if let Err(e) = res { log(LogLevel::Warn, &format!("session_state flush failed: {}", e)); }
+  }
+  d.last_flush = Some(Instant::now());
+}
+
+/// Write any pending state right away. Called on app exit.
+pub fn flush() {
+  flush_locked(&mut DEBOUNCER.lock());
+}
+
+#[tauri::command]
+pub fn save_session_state(state_json: String) -> Result<(), String> {
+  if state_json.len() > MAX_STATE_BYTES {
+    return Err(format!("session state too large ({} bytes, max {})", state_json.len(), MAX_STATE_BYTES));
+  }
+  let state: serde_json::Value = serde_json::from_str(&state_json).map_err(|e| e.to_string())?;
+  let snap = SessionSnapshot { schema: SESSION_STATE_SCHEMA, saved_at: Local::now().to_rfc3339(), state };
+
+  let mut d = DEBOUNCER.lock();
+  d.pending = Some(snap);
+  let interval = profile::flush_interval(FLUSH_INTERVAL);
+  let since = d.last_flush.map(|t| t.elapsed());
+  match since {
+    Some(el) if el < interval => {
+      if !d.flush_scheduled {
+        d.flush_scheduled = true;
+        let wait = interval - el;
+        std::thread::spawn(move || {
+          std::thread::sleep(wait);
+          let mut d = DEBOUNCER.lock();
+          d.flush_scheduled = false;
+          flush_locked(&mut d);
+        });
+      }
+    }
+    _ => flush_locked(&mut d),
+  }
+  Ok(())
+}
+
+#[tauri::command]
+pub fn load_session_state() -> Result<Option<SessionSnapshot>, String> {
+  if let Some(snap) = DEBOUNCER.lock().pending.clone() {
+    return Ok(Some(snap));
+  }
+  let s = match fs::read_to_string(session_state_path()) {
+    Ok(s) => s,
+    Err(_) => return Ok(None),
+  };
+  // Unknown schema or garbage: behave like a fresh start rather than erroring.
+  match serde_json::from_str::<SessionSnapshot>(&s) {
+    Ok(snap) if snap.schema == SESSION_STATE_SCHEMA => Ok(Some(snap)),
+    _ => Ok(None),
+  }
+}
+
+#[tauri::command]
+pub fn clear_session_state() -> Result<(), String> {
+  DEBOUNCER.lock().pending = None;
+  match fs::remove_file(session_state_path()) {
+    Ok(()) => Ok(()),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(e.to_string()),
+  }
+}
@@ -0,0 +1,166 @@
+// Has what this session wrote stayed written? `verify_session_writes` takes
+// the last comment each file got in this session's audit entries (the value
+// as saved, else as requested) and compares it with the file now. Drift
+// means a write silently failed or another tool reverted the file; the UI
+// then re-applies ours through the batch writer or accepts theirs, which
+// audits the file's value as the new expectation (source "external").
+
+use std::{collections::HashMap, path::Path};
+use serde::Serialize;
+
+use crate::{
+  audit, command_span, error::CmdError, field_locks::LockedField, preflight::{self, Preflight}, read_comment, read_tagged, retry_queue, snapshots, touched,
+};
+
+#[derive(Debug, Clone)]
+struct Expected {
+  comment: String,
+  at: String,
+  source: audit::Source,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteDrift {
+  path: String,
+  expected: String,
+  actual: String,
+  written_at: String,
+  source: audit::Source,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Unreadable {
+  path: String,
+  error: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionWriteReport {
+  session: String,
+  /// Files with a comment write in this session.
+  checked: usize,
+  mismatches: Vec<WriteDrift>,
+  unreadable: Vec<Unreadable>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReapplyResult {
+  path: String,
+  /// Nothing to do: the file already matches, or has no write this session.
+  skipped: bool,
+  error: Option<String>,
+  /// `["comment"]` when the comment is locked; nothing was written.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReapplyReport {
+  results: Vec<ReapplyResult>,
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+/// Last comment per file written this session.
+fn expected() -> HashMap<String, Expected> {
+  let session = audit::session_id();
+  let mut out = HashMap::new();
+  for e in audit::read_entries() {
+    if e.field != "comment" || e.session.as_deref() != Some(session) { continue; }
+    let comment = e.written.or(e.new).unwrap_or_default();
+    out.insert(e.path, Expected { comment, at: e.timestamp, source: e.source });
+  }
+  out
+}
+
+fn current_comment(path: &str) -> Result<String, String> {
+  let p = Path::new(path);
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  Ok(read_comment(&tf, p))
+}
+
+/// Files whose comment on disk differs from what this session last wrote.
+#[tauri::command]
+pub async fn verify_session_writes() -> Result<SessionWriteReport, String> {
+  let _span = command_span("verify_session_writes");
+  tauri::async_runtime::spawn_blocking(|| {
+    let want = expected();
+    let mut paths: Vec<&String> = want.keys().collect();
+    paths.sort();
+    let mut mismatches = Vec::new();
+    let mut unreadable = Vec::new();
+    for path in paths {
+      let w = &want[path];
+      match current_comment(path) {
+        Ok(actual) if actual != w.comment => mismatches.push(WriteDrift {
+          path: path.clone(),
+          expected: w.comment.clone(),
+          actual,
+          written_at: w.at.clone(),
+          source: w.source,
+        }),
+        Ok(_) => {}
+        Err(error) => unreadable.push(Unreadable { path: path.clone(), error }),
+      }
+    }
+    Ok(SessionWriteReport { session: audit::session_id().to_string(), checked: want.len(), mismatches, unreadable })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Write this session's expected comment back to each of `paths`, as a batch.
+#[tauri::command]
+pub async fn reapply_session_writes(app: tauri::AppHandle, paths: Vec<String>) -> Result<ReapplyReport, CmdError> {
+  let _span = command_span("reapply_session_writes");
+  tauri::async_runtime::spawn_blocking(move || {
+    let want = expected();
+    let todo: Vec<(&String, &Expected)> = paths
+      .iter()
+      .filter_map(|p| want.get(p).map(|w| (p, w)))
+      .filter(|(p, w)| current_comment(p).map_or(true, |c| c != w.comment))
+      .collect();
+    let targets: Vec<&String> = todo.iter().map(|(p, _)| *p).collect();
+    let preflight = preflight::rewrite(&targets);
+    preflight::ensure(&preflight)?;
+    let snapshot_id = snapshots::before_batch(&app, "reapply_session_writes", &targets);
+    let results = paths
+      .iter()
+      .map(|p| match todo.iter().find(|(t, _)| *t == p) {
+        Some((_, w)) => match retry_queue::write_comment(p, &w.comment) {
+          Ok(o) => ReapplyResult { path: p.clone(), skipped: o.no_op, error: None, skipped_locked: o.skipped_locked },
+          Err(e) => ReapplyResult { path: p.clone(), skipped: false, error: Some(e.into()), skipped_locked: Vec::new() },
+        },
+        None => ReapplyResult { path: p.clone(), skipped: true, error: None, skipped_locked: Vec::new() },
+      })
+      .collect();
+    Ok(ReapplyReport { results, snapshot_id, preflight })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Take each file's current comment as the expected value from now on.
+/// Returns how many files were accepted.
+#[tauri::command]
+pub fn accept_external_changes(paths: Vec<String>) -> Result<usize, String> {
+  let want = expected();
+  let mut accepted = 0;
+  for path in &paths {
+    let p = Path::new(path);
+    let tf = read_tagged(p).map_err(|e| format!("{}: {}", path, e))?;
+    let actual = read_comment(&tf, p);
+    let before = want.get(path).map(|w| w.comment.as_str());
+    if before == Some(actual.as_str()) { continue; }
+    audit::record_comment(path, before, Some(&actual), audit::Source::External);
+    // The "changed outside the app" badge should clear too.
+    touched::record(p, &tf);
+    accepted += 1;
+  }
+  Ok(accepted)
+}
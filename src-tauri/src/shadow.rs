@@ -0,0 +1,197 @@
+// Shadow mode, for trying a bulk operation on copies before trusting it with
+// the library. While `set_shadow_mode(dest_dir)` is on, writes to audio files
+// go to a mirror of the file under `dest_dir` instead: `<root name>/<path
+// below the workspace root>`, or OUTSIDE/<full path> for a file under no
+// known root. The first write copies the original there; later writes keep
+// editing the copy, and renames move it. Originals are never opened for
+// writing.
+//
+// Reads of a file that has a copy see the copy (`read_tagged`, metadata
+// cache lookups), so a second batch builds on what the first one wrote
+// instead of recomputing from the original and undoing it. The audit log,
+// touched records and the metadata cache entries keep describing the real
+// library. Write results carry `shadowed: true` and the copy's path (`Mark`).
+// The mode lasts until `disable_shadow_mode` or quit.
+
+use std::{fs, path::{Component, Path, PathBuf}, sync::atomic::{AtomicUsize, Ordering}};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{log_line, long_paths, portable};
+
+/// Under `dest_dir`, for files outside every known root.
+const OUTSIDE: &str = "_outside";
+
+/// The shadow directory, canonical. Held while a copy is made, so two writes
+/// to one file don't both copy it.
+#[cfg(not(test))]
+static DEST: Mutex<Option<PathBuf>> = Mutex::new(None);
+static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(test))]
+fn shadow_dir() -> &'static Mutex<Option<PathBuf>> { &DEST }
+
+// One per test thread, so a test in shadow mode doesn't redirect the writes
+// of the tests running alongside it.
+#[cfg(test)]
+fn shadow_dir() -> &'static Mutex<Option<PathBuf>> {
+  thread_local! { static DEST: &'static Mutex<Option<PathBuf>> = Box::leak(Box::new(Mutex::new(None))); }
+  DEST.with(|d| *d)
+}
+
+/// Flattened into write results.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mark {
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub shadowed: bool,
+  /// The copy that was written instead of the original.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub shadow_path: Option<String>,
+}
+
+impl Mark {
+  pub fn of(copy: Option<&Path>) -> Mark {
+    Mark { shadowed: copy.is_some(), shadow_path: copy.map(|c| c.to_string_lossy().to_string()) }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowStatus {
+  enabled: bool,
+  dest_dir: Option<String>,
+  /// Originals copied since shadow mode was turned on.
+  copies: usize,
+}
+
+pub fn active() -> bool { shadow_dir().lock().is_some() }
+
+/// Canonical `p`, or for a path that doesn't exist yet (a rename target) its
+/// canonical parent joined with the name.
+fn canonical(p: &Path) -> PathBuf {
+  fs::canonicalize(p).unwrap_or_else(|_| match (p.parent().and_then(|d| fs::canonicalize(d).ok()), p.file_name()) {
+    (Some(dir), Some(name)) => dir.join(name),
+    _ => p.to_path_buf(),
+  })
+}
+
+/// Mirror of `p` under `dest`; a path already under `dest` (a copy being
+/// written again) is its own.
+fn mirrored(dest: &Path, p: &Path) -> PathBuf {
+  let p = canonical(p);
+  if p.starts_with(dest) { return p; }
+  let root = portable::place(&p).and_then(|place| {
+    let root = portable::list_known_roots().into_iter().find(|r| r.id == place.root_id)?;
+    let name = Path::new(&root.path).file_name()?.to_os_string();
+    Some(place.rel.split('/').fold(dest.join(name), |acc, part| acc.join(part)))
+  });
+  root.unwrap_or_else(|| {
+    let parts = p.components().filter_map(|c| match c { Component::Normal(s) => Some(s), _ => None });
+    parts.fold(dest.join(OUTSIDE), |acc, part| acc.join(part))
+  })
+}
+
+/// Where a write to `p` goes in shadow mode, copying the original there on
+/// first use; `None` when shadow mode is off and `p` itself is written.
+pub fn target(p: &Path) -> Result<Option<PathBuf>, String> {
+  let dest = shadow_dir().lock();
+  let Some(dest) = dest.as_ref() else { return Ok(None) };
+  let copy = mirrored(dest, p);
+  if !copy.exists() {
+    if let Some(dir) = copy.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+    if let Err(e) = fs::copy(long_paths::extended(p), long_paths::extended(&copy)) {
+      let _ = fs::remove_file(&copy);
+      return Err(format!("shadow copy of {} failed: {}", p.display(), e));
+    }
+    COPIES.fetch_add(1, Ordering::Relaxed);
+    log_line(&format!("shadow copy path=\"{}\" to=\"{}\"", p.display(), copy.display()));
+  }
+  Ok(Some(copy))
+}
+
+/// `p`'s copy when shadow mode is on and has made one; reads of `p` see it.
+pub fn copy_of(p: &Path) -> Option<PathBuf> {
+  let dest = shadow_dir().lock();
+  let copy = mirrored(dest.as_ref()?, p);
+  copy.exists().then_some(copy)
+}
+
+/// Rename `from` to `to`, or in shadow mode its copy to the mirror of `to`.
+/// Returns the copy's new path when shadowed.
+pub fn rename(from: &Path, to: &Path) -> Result<Option<PathBuf>, String> {
+  let Some(copy) = target(from)? else {
+    fs::rename(long_paths::extended(from), long_paths::extended(to)).map_err(|e| e.to_string())?;
+    return Ok(None);
+  };
+  let Some(dest) = shadow_dir().lock().clone() else { return Err("shadow mode was turned off during the rename".into()) };
+  let moved = mirrored(&dest, to);
+  if moved.exists() { return Err(format!("rename target exists: {}", moved.display())); }
+  if let Some(dir) = moved.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+  fs::rename(long_paths::extended(&copy), long_paths::extended(&moved)).map_err(|e| e.to_string())?;
+  Ok(Some(moved))
+}
+
+fn status() -> ShadowStatus {
+  let dest = shadow_dir().lock();
+  ShadowStatus { enabled: dest.is_some(), dest_dir: dest.as_ref().map(|d| d.to_string_lossy().to_string()), copies: COPIES.load(Ordering::Relaxed) }
+}
+
+/// Send every write to copies under `dest_dir` (see the header). The folder
+/// is created if needed and may not sit inside a known root, where scans
+/// would pick the copies up.
+#[tauri::command]
+pub fn set_shadow_mode(dest_dir: String) -> Result<ShadowStatus, String> {
+  fs::create_dir_all(&dest_dir).map_err(|e| e.to_string())?;
+  let dest = fs::canonicalize(&dest_dir).map_err(|e| e.to_string())?;
+  if let Some(root) = portable::list_known_roots().into_iter().find(|r| dest.starts_with(&r.path)) {
+    return Err(format!("shadow folder is inside the library root {}", root.path));
+  }
+  *shadow_dir().lock() = Some(dest.clone());
+  COPIES.store(0, Ordering::Relaxed);
+  log_line(&format!("set_shadow_mode dest=\"{}\"", dest.display()));
+  Ok(status())
+}
+
+/// Back to writing originals. Copies made so far stay where they are.
+#[tauri::command]
+pub fn disable_shadow_mode() -> ShadowStatus {
+  let was = shadow_dir().lock().take();
+  if let Some(d) = was { log_line(&format!("disable_shadow_mode dest=\"{}\" copies={}", d.display(), COPIES.load(Ordering::Relaxed))); }
+  status()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::{ItemKey, TagType};
+  use crate::{edit_tags, meta_cache, read_comment, read_tagged, retry_queue, test_support};
+
+  #[test]
+  fn originals_stay_byte_identical_while_batches_build_on_the_copies() {
+    let (lib, out) = (test_support::scratch("shadow-lib"), test_support::scratch("shadow-out"));
+    fs::create_dir(lib.join("Set")).unwrap();
+    let p = test_support::tagged(&lib.join("Set"), "a.mp3", &[(ItemKey::Comment, "#a;")]);
+    let original = fs::read(&p).unwrap();
+    portable::register(&lib);
+    set_shadow_mode(out.to_string_lossy().to_string()).unwrap();
+
+    let first = edit_tags(&p, |t| { t.insert_text(ItemKey::Comment, "#a;#b;".into()); }).unwrap();
+    let copy = out.join(lib.file_name().unwrap()).join("Set").join("a.mp3");
+    assert_eq!(first.shadow, Mark::of(Some(&copy)));
+    // A second batch computes its comment from what it reads, as the batch writers do.
+    let before = read_comment(&read_tagged(&p).unwrap(), &p);
+    assert_eq!(before, "#a;#b;");
+    retry_queue::write_comment(&p.to_string_lossy(), &format!("{}#c;", before)).unwrap();
+    assert_eq!(meta_cache::get(&p).unwrap().comment, "#a;#b;#c;");
+    let moved = rename(&p, &p.with_file_name("b.mp3")).unwrap().unwrap();
+    assert_eq!(moved, copy.with_file_name("b.mp3"));
+    assert_eq!(status().copies, 1);
+
+    disable_shadow_mode();
+    assert_eq!(fs::read(&p).unwrap(), original);
+    assert!(!p.with_file_name("b.mp3").exists());
+    assert_eq!(test_support::text(&moved, TagType::Id3v2, &ItemKey::Comment).as_deref(), Some("#a;#b;#c;"));
+    assert_eq!(meta_cache::get(&p).unwrap().comment, "#a;", "the cache still describes the original");
+  }
+}
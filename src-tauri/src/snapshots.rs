@@ -0,0 +1,214 @@
+// Coarse safety net for batch writes: before a batch touches more than
+// `snapshot_threshold` files, the comment and metadata of every target go to
+// data dir `snapshots/<timestamp>.json`. `restore_snapshot` puts them back
+// wholesale or for chosen paths. Independent of the audit log, so it still
+// works when the log has rotated away. Old snapshots are pruned by age.
+// Under the light profile (see `profile`) the fields are still read before
+// the batch starts, but the file is written once no job is running; until
+// then the snapshot is listed and restored from memory. Each file's field
+// locks are kept too; a restore re-adds them, and fields locked now stay as
+// they are.
+
+use std::{fs, path::{Path, PathBuf}, sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering}, time::{Duration, SystemTime}};
+use chrono::Local;
+use lofty::Accessor;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::{audit, command_span, data_dir, dates, edit_tags, field_locks::{self, LockedField}, jobs, log, log_line, LogLevel, preferred_tag, profile, read_comment, read_tagged, write_atomic};
+
+pub static THRESHOLD: AtomicUsize = AtomicUsize::new(20);
+pub static RETENTION_DAYS: AtomicU32 = AtomicU32::new(30);
+const IDLE_POLL: Duration = Duration::from_secs(5);
+
+/// Light-profile snapshots not written yet, oldest first.
+static DEFERRED: Lazy<Mutex<Vec<Snapshot>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static WRITER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileFields {
+  pub path: String,
+  pub comment: String,
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub genre: Option<String>,
+  pub release_date: Option<String>,
+  pub original_date: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+  id: String,
+  created_at: String,
+  /// Command that took the snapshot, e.g. "apply_comment_template".
+  operation: String,
+  files: Vec<FileFields>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSummary {
+  pub id: String,
+  pub created_at: String,
+  pub operation: String,
+  pub files: usize,
+}
+
+fn snapshots_dir() -> PathBuf { data_dir().join("snapshots") }
+
+fn snapshot_file(id: &str) -> Result<PathBuf, String> {
+  // Ids are our own timestamps; anything else could escape the directory.
+  if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit() || c == '-') { return Err(format!("invalid snapshot id \"{}\"", id)); }
+  Ok(snapshots_dir().join(format!("{}.json", id)))
+}
+
+fn read_fields(p: &Path) -> Result<FileFields, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let tag = preferred_tag(&tf, p);
+  Ok(FileFields {
+    path: p.to_string_lossy().to_string(),
+    comment: read_comment(&tf, p),
+    title: tag.and_then(|t| t.title().map(|s| s.to_string())),
+    artist: tag.and_then(|t| t.artist().map(|s| s.to_string())),
+    genre: tag.and_then(|t| t.genre().map(|s| s.to_string())),
+    release_date: tag.and_then(dates::release_date),
+    original_date: tag.and_then(dates::original_date),
+    locked: field_locks::locked(p),
+  })
+}
+
+fn prune() {
+  let max_age = Duration::from_secs(RETENTION_DAYS.load(Ordering::Relaxed) as u64 * 86_400);
+  let now = SystemTime::now();
+  for e in fs::read_dir(snapshots_dir()).into_iter().flatten().flatten() {
+    let old = e.metadata().ok().and_then(|m| m.modified().ok()).and_then(|m| now.duration_since(m).ok()).is_some_and(|age| age > max_age);
+    if old { let _ = fs::remove_file(e.path()); }
+  }
+}
+
+fn write_snapshot(snap: &Snapshot) -> Result<(), String> {
+  fs::create_dir_all(snapshots_dir()).map_err(|e| e.to_string())?;
+  let bytes = serde_json::to_vec(snap).map_err(|e| e.to_string())?;
+  write_atomic(&snapshot_file(&snap.id)?, &bytes)?;
+  prune();
+  Ok(())
+}
+
+/// Write the deferred snapshots once no job is running (one writer thread at a time).
+fn write_when_idle() {
+  if WRITER_RUNNING.swap(true, Ordering::AcqRel) { return; }
+  std::thread::spawn(|| {
+    while !jobs::is_idle() { std::thread::sleep(IDLE_POLL); }
+    WRITER_RUNNING.store(false, Ordering::Release);
+    let pending: Vec<Snapshot> = std::mem::take(&mut *DEFERRED.lock());
+    for snap in &pending {
+      match write_snapshot(snap) {
+        Ok(()) => log_line(&format!("snapshot id={} written", snap.id)),
+        Err(e) => log(LogLevel::Warn, &format!("deferred snapshot {} failed: {}", snap.id, e)),
+      }
+    }
+  });
+}
+
+/// Snapshot `paths` if there are more than the threshold. Returns the id (also
+/// emitted as `snapshot-created`). A failed snapshot is logged and the batch
+/// goes ahead: the per-file audit log still covers it.
+pub fn before_batch<P: AsRef<Path>>(app: &tauri::AppHandle, operation: &str, paths: &[P]) -> Option<String> {
+  if paths.len() <= THRESHOLD.load(Ordering::Relaxed) { return None; }
+  let files: Vec<FileFields> = paths.iter().filter_map(|p| read_fields(p.as_ref()).ok()).collect();
+  let id = Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+  let snap = Snapshot { id: id.clone(), created_at: Local::now().to_rfc3339(), operation: operation.to_string(), files };
+  if profile::is_light() {
+    log_line(&format!("snapshot id={} operation={} files={} deferred", id, operation, snap.files.len()));
+    DEFERRED.lock().push(snap.clone());
+    write_when_idle();
+  } else {
+    if let Err(e) = write_snapshot(&snap) {
+      log(LogLevel::Warn, &format!("snapshot before {} failed: {}", operation, e));
+      return None;
+    }
+    log_line(&format!("snapshot id={} operation={} files={}", id, operation, snap.files.len()));
+  }
+  let summary = SnapshotSummary { id: id.clone(), created_at: snap.created_at, operation: snap.operation, files: snap.files.len() };
+  let _ = app.emit_all("snapshot-created", summary);
+  Some(id)
+}
+
+/// Newest first.
+#[tauri::command]
+pub fn list_snapshots() -> Vec<SnapshotSummary> {
+  let deferred = DEFERRED.lock().clone();
+  let mut out: Vec<SnapshotSummary> = fs::read_dir(snapshots_dir()).into_iter().flatten().flatten()
+    .filter_map(|e| fs::read(e.path()).ok())
+    .filter_map(|raw| serde_json::from_slice::<Snapshot>(&raw).ok())
+    .chain(deferred)
+    .map(|s| SnapshotSummary { files: s.files.len(), id: s.id, created_at: s.created_at, operation: s.operation })
+    .collect();
+  out.sort_by(|a, b| b.id.cmp(&a.id));
+  out
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+  path: String,
+  changed: bool,
+  error: Option<String>,
+  /// Fields locked now that the snapshot would have changed.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+fn restore_one(want: &FileFields) -> Result<(bool, Vec<LockedField>), String> {
+  let p = Path::new(&want.path);
+  let now = read_fields(p)?;
+  if &now == want { return Ok((false, Vec::new())); }
+  let outcome = edit_tags(p, |tag| {
+    tag.insert_text(lofty::ItemKey::Comment, want.comment.clone());
+    match &want.title { Some(v) => tag.set_title(v.clone()), None => tag.remove_title() }
+    match &want.artist { Some(v) => tag.set_artist(v.clone()), None => tag.remove_artist() }
+    match &want.genre { Some(v) => tag.set_genre(v.clone()), None => tag.remove_genre() }
+    dates::set_date(tag, lofty::ItemKey::RecordingDate, want.release_date.as_deref());
+    dates::set_date(tag, lofty::ItemKey::OriginalReleaseDate, want.original_date.as_deref());
+  })?;
+  let comment_restored = now.comment != want.comment && !outcome.skipped_locked.contains(&LockedField::Comment);
+  if comment_restored { audit::record_comment(&want.path, Some(&now.comment), Some(&want.comment), audit::Source::Batch); }
+  let relock = want.locked.iter().any(|f| !now.locked.contains(f));
+  if relock { field_locks::set(p, &want.locked, true)?; }
+  Ok((!outcome.no_op || relock, outcome.skipped_locked))
+}
+
+/// Put back the values in snapshot `id`, for every file or only `paths`.
+/// Fields that were empty at snapshot time are cleared again.
+#[tauri::command]
+pub async fn restore_snapshot(id: String, paths: Option<Vec<String>>) -> Result<Vec<RestoreResult>, String> {
+  let _span = command_span("restore_snapshot");
+  tauri::async_runtime::spawn_blocking(move || {
+    let deferred = DEFERRED.lock().iter().find(|s| s.id == id).cloned();
+    let snap: Snapshot = match deferred {
+      Some(snap) => snap,
+      None => {
+        let raw = fs::read(snapshot_file(&id)?).map_err(|e| format!("snapshot {}: {}", id, e))?;
+        serde_json::from_slice(&raw).map_err(|e| e.to_string())?
+      }
+    };
+    let results: Vec<RestoreResult> = snap.files.iter()
+      .filter(|f| paths.as_ref().is_none_or(|ps| ps.contains(&f.path)))
+      .map(|f| match restore_one(f) {
+        Ok((changed, skipped_locked)) => RestoreResult { path: f.path.clone(), changed, error: None, skipped_locked },
+        Err(e) => RestoreResult { path: f.path.clone(), changed: false, error: Some(e), skipped_locked: Vec::new() },
+      })
+      .collect();
+    let changed = results.iter().filter(|r| r.changed).count();
+    log_line(&format!("restore_snapshot id={} files={} changed={}", id, results.len(), changed));
+    Ok(results)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
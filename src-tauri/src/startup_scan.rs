@@ -0,0 +1,114 @@
+// Reopening the last folder on launch. The scan waits for `frontend_ready`
+// (so the window is up and listening), runs as a "startup-scan" job with
+// progress and cancel, and delivers the listing as `startup-scan-result`.
+// Opening another folder cancels it. When the folder's volume is gone the
+// scan doesn't start; `volume-missing` lets the UI offer to relocate it.
+
+use std::{path::{Path, PathBuf}, sync::atomic::{AtomicBool, Ordering}};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{folder_entries, folder_watch, jobs::{self, JobHandle}, load_prefs, log_line, meta_cache, natural_sort, save_prefs, simple_file, volumes, SimpleFile};
+
+static READY_SEEN: AtomicBool = AtomicBool::new(false);
+/// (job id, folder) of the running startup scan.
+static RUNNING: Lazy<Mutex<Option<(String, String)>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupScan {
+  job_id: String,
+  folder: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VolumeMissing {
+  folder: String,
+  /// The missing volume's mount point; `None` when the volume is there but
+  /// the folder itself is gone.
+  root: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartupScanResult {
+  job_id: String,
+  folder: String,
+  files: Vec<SimpleFile>,
+}
+
+/// Persist `folder` as the one to reopen; no write when it's unchanged.
+pub fn remember(folder: &str) {
+  let mut p = load_prefs();
+  if p.last_folder.as_deref() == Some(folder) { return; }
+  p.last_folder = Some(folder.to_string());
+  if let Err(e) = save_prefs(&p) { log_line(&format!("last_folder save failed: {}", e)); }
+}
+
+/// Cancel the startup scan unless it is already listing `folder`.
+pub fn supersede(folder: &str) {
+  let running = RUNNING.lock().clone();
+  if let Some((job_id, f)) = running.filter(|(_, f)| f != folder) {
+    if jobs::cancel_job(job_id) { log_line(&format!("startup scan of \"{}\" superseded by \"{}\"", f, folder)); }
+  }
+}
+
+/// `read_folder`, one file at a time so the job shows progress and can stop.
+fn scan(job: &JobHandle, folder: &str) -> Result<Vec<SimpleFile>, String> {
+  job.begin_phase("scan", 0);
+  let paths = job.timed("walk", || folder_entries(Path::new(folder), false))?;
+  let mut out = Vec::with_capacity(paths.len());
+  job.begin_phase("read", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { return Ok(Vec::new()); }
+    out.push(job.timed("parse", || simple_file(p)));
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  out.sort_by_cached_key(|f| natural_sort::sort_key(&f.file_name));
+  Ok(out)
+}
+
+/// The frontend has mounted and subscribed to events. Starts reopening the
+/// last folder once per launch (when `reopen_last_folder` is on) and returns
+/// the job to watch or cancel; `None` when there's nothing to scan.
+#[tauri::command]
+pub fn frontend_ready(app: tauri::AppHandle) -> Option<StartupScan> {
+  if READY_SEEN.swap(true, Ordering::SeqCst) { return None; }
+  let prefs = load_prefs();
+  if !prefs.settings.unwrap_or_default().reopen_last_folder { return None; }
+  let folder = prefs.last_folder?;
+  let dir = Path::new(&folder);
+  if !dir.is_dir() {
+    let root = volumes::detect(dir).map(|r| r.to_string_lossy().to_string());
+    log_line(&format!("startup scan skipped folder=\"{}\" volume_missing={}", folder, root.is_some()));
+    let _ = app.emit_all("volume-missing", VolumeMissing { folder, root });
+    return None;
+  }
+  volumes::register_root(dir);
+
+  let job = JobHandle::start(&app, "startup-scan", &folder);
+  let started = StartupScan { job_id: job.id().to_string(), folder: folder.clone() };
+  *RUNNING.lock() = Some((started.job_id.clone(), folder.clone()));
+  std::thread::spawn(move || {
+    let res = scan(&job, &folder);
+    RUNNING.lock().take();
+    let outcome = res.as_ref().map(|_| ()).map_err(String::clone);
+    match res {
+      Ok(files) if !job.is_cancelled() => {
+        log_line(&format!("startup scan folder=\"{}\" files={}", folder, files.len()));
+        meta_cache::refresh_in_background(files.iter().map(|f| PathBuf::from(&f.path)).collect());
+        folder_watch::watch(&folder);
+        let _ = app.emit_all("startup-scan-result", StartupScanResult { job_id: job.id().to_string(), folder: folder.clone(), files });
+      }
+      _ => {}
+    }
+    job.finish(&outcome);
+  });
+  Some(started)
+}
+
+#[tauri::command]
+pub fn get_last_folder() -> Option<String> { load_prefs().last_folder }
@@ -0,0 +1,172 @@
+// One zip to attach to a bug report, written to Downloads (Documents when
+// there's none). `include` picks the parts; empty means everything but bank
+// contents, redacted:
+//   "session_log"   the current session log
+//   "recent_logs"   the RECENT_LOGS logs before it
+//   "diagnostics"   versions, platform, profile, formats, jobs, queues
+//   "prefs"         prefs.json
+//   "banks"         bank file names and sizes
+//   "bank_contents" the bank files themselves
+//   "perf"          the perf metrics snapshot
+//   "redact_paths"  home, documents, downloads and data dir prefixes in every
+//                   text entry become <HOME>, <DOCUMENTS>, <DOWNLOADS>, <APPDATA>
+// Nothing is uploaded anywhere. The bundle's own log line lists what went in.
+
+use std::{fs, io::Write, path::{Path, PathBuf}};
+use chrono::Local;
+use serde::Serialize;
+use tauri::api::path::{document_dir, download_dir, home_dir};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+  banks_dir, command_span, data_dir, documents_root, formats, handshake, jobs, log_line, logs_dir, media_server_stats,
+  perf, prefs_path, profile, retry_queue, volumes, LOG_PATH, TAGS_SCHEMA_VERSION,
+};
+
+const RECENT_LOGS: usize = 5;
+const PARTS: &[&str] = &["session_log", "recent_logs", "diagnostics", "prefs", "banks", "bank_contents", "perf", "redact_paths"];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Diagnostics {
+  app_version: &'static str,
+  schema_version: u32,
+  os: &'static str,
+  arch: &'static str,
+  profile: &'static str,
+  capabilities: &'static [&'static str],
+  media_server: crate::MediaServerStats,
+  formats: Vec<formats::FormatInfo>,
+  jobs: Vec<jobs::JobInfo>,
+  pending_volume_writes: usize,
+  retry_queue: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BankFile {
+  name: String,
+  bytes: u64,
+}
+
+/// (prefix, placeholder), longest prefix first so the data dir wins over home.
+fn redactions() -> Vec<(String, &'static str)> {
+  let mut out: Vec<(String, &'static str)> = [(Some(data_dir()), "<APPDATA>"), (document_dir(), "<DOCUMENTS>"), (download_dir(), "<DOWNLOADS>"), (home_dir(), "<HOME>")]
+    .into_iter()
+    .filter_map(|(p, name)| Some((p?.to_string_lossy().trim_end_matches(['/', '\\']).to_string(), name)))
+    .filter(|(p, _)| !p.is_empty())
+    .collect();
+  out.sort_by_key(|(p, _)| std::cmp::Reverse(p.len()));
+  out
+}
+
+fn redact(text: &str, rules: &[(String, &'static str)]) -> String {
+  let mut s = text.to_string();
+  for (prefix, name) in rules {
+    s = s.replace(prefix.as_str(), name);
+    // Windows paths inside JSON have their backslashes doubled.
+    if prefix.contains('\\') { s = s.replace(&prefix.replace('\\', "\\\\"), name); }
+  }
+  s
+}
+
+/// Logs in `logs_dir`, oldest first.
+fn log_files() -> Vec<PathBuf> {
+  let mut v: Vec<PathBuf> = fs::read_dir(logs_dir())
+    .map(|rd| rd.flatten().map(|e| e.path()).filter(|p| p.extension().is_some_and(|x| x == "log")).collect())
+    .unwrap_or_default();
+  v.sort();
+  v
+}
+
+fn diagnostics() -> Diagnostics {
+  Diagnostics {
+    app_version: env!("CARGO_PKG_VERSION"),
+    schema_version: TAGS_SCHEMA_VERSION,
+    os: std::env::consts::OS,
+    arch: std::env::consts::ARCH,
+    profile: profile::current().token(),
+    capabilities: handshake::CAPABILITIES,
+    media_server: media_server_stats(),
+    formats: formats::supported_formats(),
+    jobs: jobs::list_jobs(),
+    pending_volume_writes: volumes::pending_writes().len(),
+    retry_queue: retry_queue::list_retry_queue().len(),
+  }
+}
+
+fn json<T: Serialize>(v: &T) -> Result<String, String> { serde_json::to_string_pretty(v).map_err(|e| e.to_string()) }
+
+fn bundle_blocking(include: &[String]) -> Result<String, String> {
+  if let Some(bad) = include.iter().find(|p| !PARTS.contains(&p.as_str())) {
+    return Err(format!("unknown bundle part \"{}\" (expected one of {})", bad, PARTS.join(", ")));
+  }
+  let all: Vec<String> = PARTS.iter().filter(|p| **p != "bank_contents").map(|p| p.to_string()).collect();
+  let include = if include.is_empty() { &all[..] } else { include };
+  let has = |part: &str| include.iter().any(|p| p == part);
+  let rules = if has("redact_paths") { redactions() } else { Vec::new() };
+
+  // (entry name, bytes); text goes through `redact`.
+  let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+  let mut text = |name: String, s: &str| entries.push((name, redact(s, &rules).into_bytes()));
+  let current = LOG_PATH.lock().clone();
+  let logs = log_files();
+  if has("session_log") {
+    if let Some(p) = &current { text(format!("logs/{}", file_name(p)), &fs::read_to_string(p).map_err(|e| e.to_string())?); }
+  }
+  if has("recent_logs") {
+    let older: Vec<&PathBuf> = logs.iter().filter(|p| Some(*p) != current.as_ref()).collect();
+    for p in &older[older.len().saturating_sub(RECENT_LOGS)..] {
+      // Other sessions' logs come and go with pruning; skip what's gone.
+      if let Ok(s) = fs::read_to_string(p) { text(format!("logs/{}", file_name(p)), &s); }
+    }
+  }
+  if has("diagnostics") { text("diagnostics.json".into(), &json(&diagnostics())?); }
+  if has("prefs") {
+    if let Ok(s) = fs::read_to_string(prefs_path()) { text("prefs.json".into(), &s); }
+  }
+  if has("perf") { text("perf.json".into(), &json(&perf::metrics())?); }
+  let mut bank_files: Vec<PathBuf> = fs::read_dir(banks_dir()).map(|rd| rd.flatten().map(|e| e.path()).filter(|p| p.is_file()).collect()).unwrap_or_default();
+  bank_files.sort();
+  if has("banks") {
+    let list: Vec<BankFile> = bank_files.iter().map(|p| BankFile { name: file_name(p), bytes: fs::metadata(p).map(|m| m.len()).unwrap_or(0) }).collect();
+    text("banks.json".into(), &json(&list)?);
+  }
+  if has("bank_contents") {
+    for p in &bank_files { text(format!("banks/{}", file_name(p)), &fs::read_to_string(p).map_err(|e| e.to_string())?); }
+  }
+
+  let dir = download_dir().unwrap_or_else(documents_root);
+  fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+  let dest = dir.join(format!("AudioTagger-support-{}.zip", Local::now().format("%Y%m%d_%H%M%S")));
+  let partial = dest.with_extension("zip.partial");
+  let written = (|| {
+    let mut zip = ZipWriter::new(fs::File::create(&partial).map_err(|e| e.to_string())?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, bytes) in &entries {
+      zip.start_file(name.clone(), options).map_err(|e| e.to_string())?;
+      zip.write_all(bytes).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    fs::rename(&partial, &dest).map_err(|e| e.to_string())
+  })();
+  if let Err(e) = written {
+    let _ = fs::remove_file(&partial);
+    return Err(e);
+  }
+  let names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+  log_line(&format!(
+    "create_support_bundle dest=\"{}\" parts={} redacted={} entries=[{}]",
+    redact(&dest.to_string_lossy(), &rules), include.join(","), !rules.is_empty(), names.join(", ")
+  ));
+  Ok(dest.to_string_lossy().to_string())
+}
+
+fn file_name(p: &Path) -> String { p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default() }
+
+/// Zip the parts in `include` (see the header) for a bug report; returns the bundle's path.
+#[tauri::command]
+pub async fn create_support_bundle(include: Vec<String>) -> Result<String, String> {
+  let _span = command_span("create_support_bundle");
+  tauri::async_runtime::spawn_blocking(move || bundle_blocking(&include)).await.map_err(|e| e.to_string())?
+}
@@ -0,0 +1,281 @@
+// BPM / key disagreements between embedded tags and what the file or folder
+// name says (see `name_hints`). Resolving either writes the name's value into
+// the tags or renames the file to match the tags.
+
+use std::path::{Path, PathBuf};
+use lofty::ItemKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  archive, audit, command_span, edit_tags, error::CmdError, field_locks::LockedField, jobs::JobHandle, library::audio_files, log_line, preferred_tag,
+  preflight::{self, Preflight}, read_tagged, name_hints::{self, format_key, parse_tag_key, NameHints}, shadow, snapshots,
+};
+
+/// Allowed drift between a tag's BPM and the name's.
+const BPM_TOLERANCE: f64 = 0.05;
+/// How many parent folders up (within the scanned folder) to look for hints.
+const FOLDER_LEVELS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field { Bpm, Key }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HintSource { Filename, Folder }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+  pub path: String,
+  pub field: Field,
+  /// `None` when the tag is missing.
+  pub tag_value: Option<String>,
+  /// Normalized value from the name ("124", "Am").
+  pub name_value: String,
+  pub source: HintSource,
+  /// File stem or folder name the value came from.
+  pub source_name: String,
+  /// Exact text in `source_name` that was matched.
+  pub source_text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictReport {
+  conflicts: Vec<Conflict>,
+  scanned: usize,
+  cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Prefer { Tag, Filename }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveResult {
+  path: String,
+  field: Field,
+  applied: bool,
+  renamed_to: Option<String>,
+  error: Option<String>,
+  /// The tag field is locked: nothing was written.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveReport {
+  results: Vec<ResolveResult>,
+  /// Taken before tag writes (see `snapshots`); None when only renaming.
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+fn tidy_bpm(v: f64) -> String {
+  if v.fract() == 0.0 { format!("{}", v as i64) } else { format!("{}", (v * 100.0).round() / 100.0) }
+}
+
+/// Filename hints first, then the nearest folder that has one.
+fn hints_for(p: &Path, root: &Path) -> Vec<(HintSource, String, NameHints)> {
+  let mut out = Vec::new();
+  if let Some(stem) = p.file_stem() {
+    let stem = stem.to_string_lossy().to_string();
+    out.push((HintSource::Filename, stem.clone(), name_hints::scan_name(&stem)));
+  }
+  for dir in p.ancestors().skip(1).take(FOLDER_LEVELS) {
+    if !dir.starts_with(root) { break; }
+    let Some(name) = dir.file_name() else { break };
+    let name = name.to_string_lossy().to_string();
+    out.push((HintSource::Folder, name.clone(), name_hints::scan_folder_name(&name)));
+  }
+  out
+}
+
+fn conflicts_for(p: &Path, root: &Path) -> Result<Vec<Conflict>, String> {
+  let hints = hints_for(p, root);
+  let bpm_hint = hints.iter().find_map(|(s, n, h)| h.bpm.as_ref().map(|b| (*s, n, b)));
+  let key_hint = hints.iter().find_map(|(s, n, h)| h.key.as_ref().map(|k| (*s, n, k)));
+  if bpm_hint.is_none() && key_hint.is_none() { return Ok(Vec::new()); }
+
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let tag = preferred_tag(&tf, p);
+  let text = |k: ItemKey| tag.and_then(|t| t.get_string(&k)).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+  let path = p.to_string_lossy().to_string();
+  let mut out = Vec::new();
+
+  if let Some((source, name, hint)) = bpm_hint {
+    let tag_bpm = text(ItemKey::Bpm).or_else(|| text(ItemKey::IntegerBpm));
+    let agrees = tag_bpm.as_deref().and_then(|b| b.parse::<f64>().ok()).is_some_and(|b| (b - hint.value).abs() <= BPM_TOLERANCE);
+    if !agrees {
+      out.push(Conflict { path: path.clone(), field: Field::Bpm, tag_value: tag_bpm, name_value: tidy_bpm(hint.value), source, source_name: name.clone(), source_text: hint.text.clone() });
+    }
+  }
+  if let Some((source, name, hint)) = key_hint {
+    let tag_key = text(ItemKey::InitialKey);
+    let agrees = tag_key.as_deref().and_then(parse_tag_key).is_some_and(|k| k == hint.value.camelot);
+    if !agrees {
+      let name_value = format_key(hint.value.camelot, hint.value.notation);
+      out.push(Conflict { path, field: Field::Key, tag_value: tag_key, name_value, source, source_name: name.clone(), source_text: hint.text.clone() });
+    }
+  }
+  Ok(out)
+}
+
+fn find_blocking(job: &JobHandle, folder: &str, recursive: bool) -> Result<ConflictReport, String> {
+  let root = PathBuf::from(folder);
+  job.begin_phase("scan", 0);
+  let paths = audio_files(&root, recursive).map_err(|e| e.to_string())?;
+  let mut conflicts = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("analyze", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    match conflicts_for(p, &root) {
+      Ok(c) => conflicts.extend(c),
+      Err(e) => log_line(&format!("find_tag_filename_conflicts skip \"{}\": {}", p.display(), e)),
+    }
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  Ok(ConflictReport { conflicts, scanned: paths.len(), cancelled })
+}
+
+/// Files under `folder` whose BPM/key tags disagree with (or are missing but
+/// present in) the file or folder name. Keys compare on the Camelot wheel, so
+/// "G#m", "Abm" and "1A" agree.
+#[tauri::command]
+pub async fn find_tag_filename_conflicts(app: tauri::AppHandle, folder: String, recursive: bool) -> Result<ConflictReport, String> {
+  let _span = command_span("find_tag_filename_conflicts");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "tag-conflicts", &folder);
+    let res = find_blocking(&job, &folder, recursive);
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Returns the locked fields that kept the write from happening.
+fn write_name_value(c: &Conflict) -> Result<Vec<LockedField>, String> {
+  let p = Path::new(&c.path);
+  let old = c.tag_value.clone();
+  let (outcome, field) = match c.field {
+    Field::Bpm => {
+      let v: f64 = c.name_value.parse().map_err(|_| format!("not a BPM: {}", c.name_value))?;
+      let o = edit_tags(p, |tag| {
+        tag.insert_text(ItemKey::Bpm, tidy_bpm(v));
+        tag.insert_text(ItemKey::IntegerBpm, format!("{}", v.round() as i64));
+      })?;
+      (o, "bpm")
+    }
+    Field::Key => (edit_tags(p, |tag| { tag.insert_text(ItemKey::InitialKey, c.name_value.clone()); })?, "key"),
+  };
+  if outcome.skipped_locked.is_empty() && !outcome.no_op { audit::record(&c.path, field, old.as_deref(), Some(&c.name_value), audit::Source::Batch); }
+  Ok(outcome.skipped_locked)
+}
+
+/// Rename `current` so the matched text carries the tag's value.
+fn rename_to_tag_value(c: &Conflict, current: &Path) -> Result<PathBuf, String> {
+  if c.source == HintSource::Folder { return Err("folder names are not renamed".into()); }
+  let tag_value = c.tag_value.as_deref().ok_or("the tag has no value to rename to")?;
+  let new_text = match c.field {
+    Field::Bpm => tidy_bpm(tag_value.parse().map_err(|_| format!("tag BPM is not a number: {}", tag_value))?),
+    Field::Key => {
+      let key = parse_tag_key(tag_value).ok_or_else(|| format!("tag key not recognized: {}", tag_value))?;
+      // Keep the name's notation: a Camelot name stays Camelot.
+      let notation = name_hints::parse_key(&c.source_text).map(|k| k.notation).ok_or("matched text is no longer a key")?;
+      format_key(key, notation)
+    }
+  };
+  let stem = current.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let at = stem.rfind(&c.source_text).ok_or("file was renamed since the scan")?;
+  let new_stem = format!("{}{}{}", &stem[..at], new_text, &stem[at + c.source_text.len()..]);
+  let ext = current.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+  let target = current.with_file_name(format!("{}{}", new_stem, ext));
+  if target == current { return Ok(target); }
+  if target.exists() { return Err(format!("rename target exists: {}", target.display())); }
+  archive::guard(current).map_err(|e| e.to_string())?;
+  Ok(shadow::rename(current, &target)?.unwrap_or(target))
+}
+
+/// Write the chosen side for each conflict. With `prefer: "tag"` files are
+/// renamed (several conflicts on one file are applied in turn).
+#[tauri::command]
+pub fn resolve_conflicts(app: tauri::AppHandle, items: Vec<Conflict>, prefer: Prefer) -> Result<ResolveReport, CmdError> {
+  let mut paths: Vec<&str> = items.iter().map(|c| c.path.as_str()).collect();
+  paths.sort_unstable();
+  paths.dedup();
+  let preflight = preflight::rewrite(&paths);
+  preflight::ensure(&preflight)?;
+  // Renames leave the fields alone; only tag writes are worth a snapshot.
+  let snapshot_id = if prefer == Prefer::Filename { snapshots::before_batch(&app, "resolve_conflicts", &paths) } else { None };
+  let mut renamed: Vec<(String, PathBuf)> = Vec::new();
+  let results: Vec<ResolveResult> = items.iter().map(|c| {
+    let mut res = ResolveResult { path: c.path.clone(), field: c.field, applied: false, renamed_to: None, error: None, skipped_locked: Vec::new() };
+    let outcome = match prefer {
+      Prefer::Filename => write_name_value(c).map(|locked| res.skipped_locked = locked),
+      Prefer::Tag => {
+        let current = renamed.iter().rev().find(|(orig, _)| orig == &c.path).map(|(_, p)| p.clone()).unwrap_or_else(|| PathBuf::from(&c.path));
+        rename_to_tag_value(c, &current).map(|target| {
+          if target != current {
+            let (from, to) = (current.to_string_lossy().to_string(), target.to_string_lossy().to_string());
+            audit::record(&from, "path", Some(&from), Some(&to), audit::Source::Batch);
+            res.renamed_to = Some(to);
+            renamed.push((c.path.clone(), target));
+          }
+        })
+      }
+    };
+    match outcome {
+      Ok(()) => res.applied = res.skipped_locked.is_empty(),
+      Err(e) => res.error = Some(e),
+    }
+    res
+  }).collect();
+  let applied = results.iter().filter(|r| r.applied).count();
+  log_line(&format!("resolve_conflicts items={} applied={} prefer={:?}", results.len(), applied, prefer));
+  Ok(ResolveReport { results, snapshot_id, preflight })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use crate::test_support;
+
+  fn found(c: &[Conflict]) -> Vec<(Field, Option<&str>, &str, HintSource)> {
+    c.iter().map(|c| (c.field, c.tag_value.as_deref(), c.name_value.as_str(), c.source)).collect()
+  }
+
+  #[test]
+  fn tags_agreeing_within_tolerance_or_enharmonically_are_no_conflict() {
+    let root = test_support::scratch("tag-conflicts");
+    let dir = root.join("124 BPM");
+    fs::create_dir(&dir).unwrap();
+    // Fractional BPMs need a format with a BPM text field; ID3v2 only has TBPM.
+    let agree = test_support::tagged(&dir, "Track (8A).flac", &[(ItemKey::Bpm, "124.04"), (ItemKey::InitialKey, "Am")]);
+    assert!(conflicts_for(&agree, &root).unwrap().is_empty());
+    let enharmonic = test_support::tagged(&dir, "Track (A#m).mp3", &[(ItemKey::IntegerBpm, "124"), (ItemKey::InitialKey, "Bbm")]);
+    assert!(conflicts_for(&enharmonic, &root).unwrap().is_empty());
+
+    let off = test_support::tagged(&dir, "Track (Bbm).flac", &[(ItemKey::Bpm, "124.1"), (ItemKey::InitialKey, "8A")]);
+    assert_eq!(found(&conflicts_for(&off, &root).unwrap()), [
+      (Field::Bpm, Some("124.1"), "124", HintSource::Folder),
+      (Field::Key, Some("8A"), "Bbm", HintSource::Filename),
+    ]);
+    // The file name beats the folder; a missing tag is a conflict too.
+    let bare = test_support::audio(&dir, "Track (126, 5A).mp3");
+    let c = conflicts_for(&bare, &root).unwrap();
+    assert_eq!(found(&c), [(Field::Bpm, None, "126", HintSource::Filename), (Field::Key, None, "5A", HintSource::Filename)]);
+    assert_eq!((c[0].source_name.as_str(), c[0].source_text.as_str()), ("Track (126, 5A)", "126"));
+    // Folders above the scanned one don't count.
+    let set = dir.join("Set");
+    fs::create_dir(&set).unwrap();
+    let plain = test_support::audio(&set, "Plain.mp3");
+    assert!(conflicts_for(&plain, &set).unwrap().is_empty());
+    assert_eq!(found(&conflicts_for(&plain, &root).unwrap()), [(Field::Bpm, None, "124", HintSource::Folder)]);
+  }
+}
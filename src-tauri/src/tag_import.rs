@@ -0,0 +1,374 @@
+// Tag lists from elsewhere into a bank. People share taxonomies as plain
+// text (one tag per line, or a `a;b;c` comment), spreadsheets saved as CSV
+// (name, colour, group columns; comma, semicolon or tab separated, quoted
+// fields, an Excel BOM or `sep=` line) or JSON (an array of names or of TagDef-like
+// objects, or a whole bank file). Each row goes through the tag policy and
+// the rules of `validateTagName` in src/lib/tags.ts, is compared with the
+// bank's entries as `dedupe_bank` compares names, and gets a fresh id; the
+// report sorts the rows into added, skipped and invalid, with a reason for
+// each of the latter two. Nothing is written with `dryRun`.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{banks::{dedupe_key, new_id, BankTag}, error::CmdError, handshake, log_line, tag_policy, AppState};
+
+const KINDS: &[&str] = &["main", "mandatory", "optional"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat { #[default] Auto, Text, Csv, Json }
+
+/// A CSV column by 0-based position or by header name (case-insensitive).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef { Index(usize), Name(String) }
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ImportOptions {
+  /// Defaults to a header named "name" or "tag", else the first column.
+  name_column: Option<ColumnRef>,
+  /// Default to a header named "color" and "group" (or "category"); none without a header.
+  color_column: Option<ColumnRef>,
+  group_column: Option<ColumnRef>,
+  description_column: Option<ColumnRef>,
+  /// `None`: the first row is a header when it names a name column.
+  has_header: Option<bool>,
+  /// `None`: whichever of `,` `;` tab the first row has most of.
+  delimiter: Option<char>,
+  /// Type of the new entries when a row doesn't say; "optional" by default.
+  kind: Option<String>,
+  dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportRow {
+  /// Line (text, CSV) or 1-based position (JSON) of the row.
+  line: usize,
+  raw: String,
+  /// After the tag policy.
+  name: Option<String>,
+  /// The new entry's id; for skipped rows, the entry it matched.
+  id: Option<String>,
+  reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextImportReport {
+  bank: String,
+  dry_run: bool,
+  /// What `auto` was read as.
+  format: ImportFormat,
+  added: Vec<ImportRow>,
+  skipped: Vec<ImportRow>,
+  invalid: Vec<ImportRow>,
+}
+
+/// One entry as read, before any checks.
+#[derive(Debug, Default)]
+struct RawRow {
+  line: usize,
+  raw: String,
+  name: String,
+  color: Option<String>,
+  group: Option<String>,
+  description: Option<String>,
+  kind: Option<String>,
+}
+
+// ---- parsing ----
+
+fn detect(text: &str) -> ImportFormat {
+  let t = text.trim_start();
+  if t.starts_with('[') || t.starts_with('{') { return ImportFormat::Json; }
+  if sep_line(t).is_some() { return ImportFormat::Csv; }
+  let first = t.lines().next().unwrap_or("");
+  if first.contains(',') || first.contains('\t') { return ImportFormat::Csv; }
+  // A header row means a spreadsheet: "Name;Colour" from a European Excel,
+  // which as text would import "Name" and "Colour", or a lone "Tag" column.
+  let header = csv_records(first, sniff_delimiter(first)).into_iter().next();
+  if header.is_some_and(|(_, r)| header_index(&r, NAME_HEADERS).is_some()) { ImportFormat::Csv } else { ImportFormat::Text }
+}
+
+/// Excel's `sep=;` first line: the delimiter it names and the text after it.
+fn sep_line(text: &str) -> Option<(char, &str)> {
+  let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+  let mut d = first.trim_end_matches('\r').strip_prefix("sep=")?.chars();
+  match (d.next(), d.next()) { (Some(c), None) => Some((c, rest)), _ => None }
+}
+
+fn text_rows(text: &str) -> Vec<RawRow> {
+  let mut out = Vec::new();
+  for (i, l) in text.lines().enumerate() {
+    let l = l.trim();
+    // Bulleted lists pasted from notes.
+    let body = ["- ", "* ", "• "].iter().find_map(|b| l.strip_prefix(b)).unwrap_or(l);
+    for tok in body.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+      out.push(RawRow { line: i + 1, raw: l.to_string(), name: tok.to_string(), ..Default::default() });
+    }
+  }
+  out
+}
+
+/// The delimiter the first record has most of outside quotes; `,` on a tie.
+fn sniff_delimiter(text: &str) -> char {
+  let mut counts = [(',', 0), (';', 0), ('\t', 0)];
+  let mut quoted = false;
+  for c in text.chars() {
+    if c == '"' { quoted = !quoted; continue; }
+    if quoted { continue; }
+    if c == '\n' { break; }
+    if let Some(slot) = counts.iter_mut().find(|(d, _)| *d == c) { slot.1 += 1; }
+  }
+  counts.iter().fold((',', 0), |best, &(d, n)| if n > best.1 { (d, n) } else { best }).0
+}
+
+/// Records of delimited text with the line each starts on. A field that
+/// opens with a quote may hold the delimiter, line breaks and `""` for a
+/// quote; a stray quote elsewhere is kept as it is.
+fn csv_records(text: &str, delim: char) -> Vec<(usize, Vec<String>)> {
+  let mut out = Vec::new();
+  let (mut row, mut field): (Vec<String>, String) = (Vec::new(), String::new());
+  let (mut quoted, mut line, mut start) = (false, 1, 1);
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); }
+      '"' if quoted => quoted = false,
+      '"' if field.trim().is_empty() => { field.clear(); quoted = true; }
+      c if quoted => { if c == '\n' { line += 1; } field.push(c); }
+      '\r' => {}
+      '\n' => {
+        row.push(std::mem::take(&mut field));
+        out.push((start, std::mem::take(&mut row)));
+        line += 1;
+        start = line;
+      }
+      c if c == delim => row.push(std::mem::take(&mut field)),
+      c => field.push(c),
+    }
+  }
+  if !field.is_empty() || !row.is_empty() { row.push(field); out.push((start, row)); }
+  out.into_iter()
+    .map(|(n, r)| (n, r.into_iter().map(|f| f.trim().to_string()).collect::<Vec<_>>()))
+    .filter(|(_, r)| r.iter().any(|f| !f.is_empty()))
+    .collect()
+}
+
+fn header_index(header: &[String], names: &[&str]) -> Option<usize> {
+  header.iter().position(|h| names.contains(&h.to_lowercase().as_str()))
+}
+
+fn resolve(col: &Option<ColumnRef>, header: Option<&[String]>, names: &[&str]) -> Result<Option<usize>, String> {
+  match col {
+    Some(ColumnRef::Index(i)) => Ok(Some(*i)),
+    Some(ColumnRef::Name(n)) => {
+      let h = header.ok_or_else(|| format!("column \"{}\" given by name, but the file has no header row", n))?;
+      header_index(h, &[n.to_lowercase().as_str()]).map(Some).ok_or_else(|| format!("no column named \"{}\"", n))
+    }
+    None => Ok(header.and_then(|h| header_index(h, names))),
+  }
+}
+
+const NAME_HEADERS: &[&str] = &["name", "tag", "tag name", "hashtag"];
+
+fn csv_rows(text: &str, opts: &ImportOptions) -> Result<Vec<RawRow>, String> {
+  let (hint, text, skipped) = match sep_line(text) { Some((d, rest)) => (Some(d), rest, 1), None => (None, text, 0) };
+  let delim = opts.delimiter.or(hint).unwrap_or_else(|| sniff_delimiter(text));
+  let mut records = csv_records(text, delim).into_iter().map(|(line, r)| (line + skipped, r)).peekable();
+  let has_header = opts.has_header.unwrap_or_else(|| records.peek().is_some_and(|(_, r)| header_index(r, NAME_HEADERS).is_some()));
+  let header = if has_header { records.next().map(|(_, r)| r) } else { None };
+  let h = header.as_deref();
+  let name = resolve(&opts.name_column, h, NAME_HEADERS)?.unwrap_or(0);
+  let color = resolve(&opts.color_column, h, &["color", "colour"])?;
+  let group = resolve(&opts.group_column, h, &["group", "category"])?;
+  let description = resolve(&opts.description_column, h, &["description", "notes"])?;
+  let kind = h.and_then(|h| header_index(h, &["type", "kind"]));
+  let cell = |r: &[String], i: Option<usize>| i.and_then(|i| r.get(i)).filter(|v| !v.is_empty()).cloned();
+  Ok(records.map(|(line, r)| RawRow {
+    line,
+    raw: r.join(&delim.to_string()),
+    name: r.get(name).cloned().unwrap_or_default(),
+    color: cell(&r, color),
+    group: cell(&r, group),
+    description: cell(&r, description),
+    kind: cell(&r, kind),
+  }).collect())
+}
+
+fn json_rows(text: &str) -> Result<Vec<RawRow>, String> {
+  let v: Value = serde_json::from_str(text).map_err(|e| format!("not valid JSON: {}", e))?;
+  let items = match v {
+    Value::Array(a) => a,
+    Value::Object(mut o) => match o.remove("tags") {
+      Some(Value::Array(a)) => a,
+      _ => return Err("a JSON object needs a \"tags\" array".into()),
+    },
+    _ => return Err("expected a JSON array of tags".into()),
+  };
+  let field = |o: &Map<String, Value>, k: &str| o.get(k).and_then(Value::as_str).map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+  Ok(items.into_iter().enumerate().map(|(i, item)| {
+    let raw = item.to_string();
+    match &item {
+      Value::String(s) => RawRow { line: i + 1, raw, name: s.clone(), ..Default::default() },
+      Value::Object(o) => RawRow {
+        line: i + 1,
+        name: field(o, "name").unwrap_or_default(),
+        color: field(o, "color"),
+        group: field(o, "group"),
+        description: field(o, "description"),
+        kind: field(o, "type"),
+        raw,
+      },
+      _ => RawRow { line: i + 1, raw, ..Default::default() },
+    }
+  }).collect())
+}
+
+// ---- checks ----
+
+/// `#abc`, `#aabbcc`, `#aabbccdd` or a CSS colour name.
+fn valid_color(c: &str) -> bool {
+  match c.strip_prefix('#') {
+    Some(hex) => matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|ch| ch.is_ascii_hexdigit()),
+    None => !c.is_empty() && c.chars().all(|ch| ch.is_ascii_alphabetic()),
+  }
+}
+
+/// The row as a new entry, or why it can't be one.
+fn entry(r: &RawRow, policy: &tag_policy::TagPolicy, default_kind: &str) -> Result<BankTag, String> {
+  if r.name.trim().is_empty() { return Err("no tag name".into()); }
+  let name = tag_policy::normalize_tag(&r.name, policy)?;
+  if name.chars().any(char::is_whitespace) { return Err("contains spaces (use dashes or CamelCase)".into()); }
+  if let Some(c) = r.color.as_deref().filter(|c| !valid_color(c)) { return Err(format!("\"{}\" is not a colour", c)); }
+  let kind = r.kind.as_deref().map(str::to_lowercase).unwrap_or_else(|| default_kind.to_string());
+  if !KINDS.contains(&kind.as_str()) { return Err(format!("type \"{}\" is not main, mandatory or optional", kind)); }
+  Ok(BankTag {
+    id: String::new(),
+    name,
+    kind: Some(kind),
+    parent: None,
+    amount_range: None,
+    color: r.color.clone(),
+    group: r.group.clone(),
+    description: r.description.clone(),
+    modified_at: None,
+    extra: Map::new(),
+  })
+}
+
+/// Read `content` as `format` and merge the valid, new rows into `bank`
+/// (see the header). With `options.dryRun` only the report comes back.
+#[tauri::command]
+pub fn import_tags_from_text(
+  state: tauri::State<'_, AppState>,
+  bank: String,
+  content: String,
+  format: Option<ImportFormat>,
+  options: Option<ImportOptions>,
+) -> Result<TextImportReport, CmdError> {
+  let opts = options.unwrap_or_default();
+  if !opts.dry_run { handshake::check_bank_write()?; }
+  let text = content.strip_prefix('\u{feff}').unwrap_or(&content);
+  let format = match format.unwrap_or_default() { ImportFormat::Auto => detect(text), f => f };
+  let rows = match format {
+    ImportFormat::Json => json_rows(text)?,
+    ImportFormat::Csv => csv_rows(text, &opts)?,
+    _ => text_rows(text),
+  };
+  let default_kind = opts.kind.as_deref().map(str::to_lowercase).unwrap_or_else(|| "optional".into());
+  if !KINDS.contains(&default_kind.as_str()) { return Err(format!("type \"{}\" is not main, mandatory or optional", default_kind).into()); }
+  let policy = tag_policy::policy();
+
+  let report = state.banks.update(&bank, |doc| {
+    let mut report = TextImportReport { bank: bank.clone(), dry_run: opts.dry_run, format, added: Vec::new(), skipped: Vec::new(), invalid: Vec::new() };
+    let existing: HashMap<String, &BankTag> = doc.tags.iter().map(|t| (dedupe_key(&t.name, &policy), t)).collect();
+    // Key -> line of the row that brought it.
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut new_tags = Vec::new();
+    for r in &rows {
+      let row = |name: Option<String>, id: Option<String>, reason: Option<String>| ImportRow { line: r.line, raw: r.raw.clone(), name, id, reason };
+      let mut t = match entry(r, &policy, &default_kind) {
+        Ok(t) => t,
+        Err(e) => { report.invalid.push(row(None, None, Some(e))); continue; }
+      };
+      let key = dedupe_key(&t.name, &policy);
+      if let Some(cur) = existing.get(&key) {
+        report.skipped.push(row(Some(t.name), Some(cur.id.clone()), Some(format!("already in the bank as \"{}\"", cur.name))));
+        continue;
+      }
+      if let Some(first) = seen.get(&key) {
+        report.skipped.push(row(Some(t.name), None, Some(format!("repeats line {}", first))));
+        continue;
+      }
+      seen.insert(key, r.line);
+      t.id = new_id();
+      report.added.push(row(Some(t.name.clone()), Some(t.id.clone()), None));
+      new_tags.push(t);
+    }
+    let changed = !opts.dry_run && !new_tags.is_empty();
+    if changed { doc.tags.extend(new_tags); }
+    (report, changed)
+  })?;
+  if !opts.dry_run {
+    log_line(&format!(
+      "import_tags_from_text bank=\"{}\" format={:?} added={} skipped={} invalid={}",
+      bank, report.format, report.added.len(), report.skipped.len(), report.invalid.len()
+    ));
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn names(text: &str) -> Vec<(usize, String)> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let rows = match detect(text) {
+      ImportFormat::Csv => csv_rows(text, &ImportOptions::default()).unwrap(),
+      _ => text_rows(text),
+    };
+    rows.into_iter().map(|r| (r.line, r.name)).collect()
+  }
+
+  #[test]
+  fn semicolon_excel_exports_are_csv() {
+    let excel = "\u{feff}\"Name\";\"Colour\";\"Group\"\r\nDeepHouse;#ff0000;Genre\r\n\"Peak;Time\";red;\r\n;;\r\n";
+    assert_eq!(detect(excel.trim_start_matches('\u{feff}')), ImportFormat::Csv);
+    assert_eq!(names(excel), [(2, "DeepHouse".to_string()), (3, "Peak;Time".to_string())]);
+    let rows = csv_rows(excel.trim_start_matches('\u{feff}'), &ImportOptions::default()).unwrap();
+    assert_eq!(rows[0].color.as_deref(), Some("#ff0000"));
+    assert_eq!(rows[1].group, None);
+  }
+
+  #[test]
+  fn sep_lines_set_the_delimiter_and_keep_line_numbers() {
+    let text = "sep=;\nTag Name;Notes\nwarmup;first hour, kept low\n";
+    assert_eq!(detect(text), ImportFormat::Csv);
+    let rows = csv_rows(text, &ImportOptions::default()).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!((rows[0].line, rows[0].name.as_str()), (3, "warmup"));
+    assert_eq!(rows[0].description.as_deref(), Some("first hour, kept low"));
+  }
+
+  #[test]
+  fn a_lone_header_column_is_not_a_tag() {
+    assert_eq!(names("Hashtag\n#vocal\n#dub\n"), [(2, "#vocal".to_string()), (3, "#dub".to_string())]);
+    assert_eq!(names("  TAG  \nvocal"), [(2, "vocal".to_string())]);
+  }
+
+  #[test]
+  fn tag_lists_stay_text() {
+    assert_eq!(detect("house;techno;TagB:Main;\n"), ImportFormat::Text);
+    assert_eq!(detect("- warmup\n- peak\n"), ImportFormat::Text);
+    // Looks like a header only when a whole field is one.
+    assert_eq!(detect("names;tagging\n"), ImportFormat::Text);
+    assert_eq!(names("house;techno;\n"), [(1, "house".to_string()), (1, "techno".to_string())]);
+  }
+}
@@ -0,0 +1,252 @@
+// Comment-token edits shared by commands and background rules. Tokens are
+// the semicolon-separated entries the frontend writes ("deep;melodic;TagB:x;").
+
+use std::{cell::OnceCell, path::Path};
+use lofty::ItemKey;
+use serde::Serialize;
+
+use crate::{
+  audit, banks::dedupe_key, command_span, edit_tags_with, error::CmdError, field_locks::LockedField, log_line, meta_cache, preflight::{self, Preflight}, read_comment, shadow,
+  snapshots, split_comment_tokens, tag_policy, write_targets,
+};
+
+/// Canonical comment form, matching the frontend: `a;b;c;` (empty when no tokens).
+pub fn join_tokens(tokens: &[String]) -> String {
+  if tokens.is_empty() { String::new() } else { format!("{};", tokens.join(";")) }
+}
+
+/// Add `add` (keeping existing order, new ones before the trailing `TagB:` bank
+/// marker) and drop everything in `remove`. A tag is added once per
+/// `dedupe_key`, so "#house" doesn't join a "#House" already there.
+pub fn merge_tokens(comment: &str, add: &[String], remove: &[String]) -> String {
+  let policy = tag_policy::policy();
+  let mut tokens: Vec<String> = split_comment_tokens(comment)
+    .into_iter()
+    .filter(|t| !remove.iter().any(|r| r == t))
+    .collect();
+  let bank_ix = tokens.iter().position(|t| t.starts_with("TagB:"));
+  let mut insert_at = bank_ix.unwrap_or(tokens.len());
+  for a in add {
+    let a = a.trim();
+    if a.is_empty() || a.contains(';') || tokens.iter().any(|t| dedupe_key(t, &policy) == dedupe_key(a, &policy)) { continue; }
+    tokens.insert(insert_at, a.to_string());
+    insert_at += 1;
+  }
+  join_tokens(&tokens)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeOutcome {
+  pub path: String,
+  pub old_comment: String,
+  pub new_comment: String,
+  pub changed: bool,
+  /// `["comment"]` when the comment is locked; `new_comment` wasn't written.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub skipped_locked: Vec<LockedField>,
+  #[serde(flatten)]
+  pub shadow: shadow::Mark,
+}
+
+/// Read-merge-write one file. Unchanged comments are not rewritten. `add` goes
+/// through the tag policy first; any rejected tag fails the whole merge.
+pub fn merge_file_tags(path: &str, add: &[String], remove: &[String], source: audit::Source) -> Result<MergeOutcome, String> {
+  let add = tag_policy::normalize_for_add(add)?;
+  // Removing "#Melodic" should also remove the "#melodic" the policy would have written.
+  let policy = tag_policy::policy();
+  let normalized: Vec<String> = remove.iter().filter_map(|r| tag_policy::normalize_tag(r, &policy).ok()).collect();
+  let remove = [remove, &normalized[..]].concat();
+  let p = Path::new(path);
+  // The comment is read and merged under WRITE_LOCK, from the file the edit
+  // is about to save, so two merges into one file can't drop each other's tokens.
+  let merged: OnceCell<(String, String)> = OnceCell::new();
+  let outcome = edit_tags_with(p, |tf| {
+    let old = read_comment(tf, p);
+    let new = merge_tokens(&old, &add, &remove);
+    let _ = merged.set((old, new));
+    Ok(write_targets(tf, p))
+  }, |tag| {
+    if let Some((old, new)) = merged.get() {
+      if new != old { tag.insert_text(ItemKey::Comment, new.clone()); }
+    }
+  })?;
+  let (old, new) = merged.into_inner().unwrap_or_default();
+  let changed = new != old && outcome.skipped_locked.is_empty() && !outcome.no_op;
+  if changed { audit::record_comment(path, Some(&old), Some(&new), source); }
+  let (skipped_locked, shadow) = (outcome.skipped_locked, outcome.shadow);
+  Ok(MergeOutcome { path: path.to_string(), old_comment: old, new_comment: new, changed, skipped_locked, shadow })
+}
+
+#[tauri::command]
+pub fn merge_tags(path: String, add: Vec<String>, remove: Vec<String>) -> Result<MergeOutcome, String> {
+  let out = merge_file_tags(&path, &add, &remove, audit::Source::Manual)?;
+  if out.changed { log_line(&format!("merge_tags path=\"{}\" -> \"{}\"", path, out.new_comment)); }
+  Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToggleDirection { Add, Remove }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToggleFileResult {
+  pub path: String,
+  /// `None` when the file couldn't be read or written.
+  pub outcome: Option<MergeOutcome>,
+  pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToggleReport {
+  /// The tag as written, after the tag policy.
+  pub tag: String,
+  pub direction: ToggleDirection,
+  pub results: Vec<ToggleFileResult>,
+  /// Taken before writing (see `snapshots`).
+  pub snapshot_id: Option<String>,
+  pub preflight: Preflight,
+}
+
+/// Remove only if every readable file already has the tag; any file without
+/// it (and an empty or unreadable selection) means add. Unreadable files don't
+/// vote, so one broken file can't flip the direction for the rest.
+fn toggle_direction(has_tag: &[Option<bool>]) -> ToggleDirection {
+  let readable: Vec<bool> = has_tag.iter().flatten().copied().collect();
+  if !readable.is_empty() && readable.iter().all(|h| *h) { ToggleDirection::Remove } else { ToggleDirection::Add }
+}
+
+/// Per file, the tokens that count as the tag (removed as written), or why
+/// it couldn't be read.
+type ToggleState = Vec<Result<Vec<String>, String>>;
+
+/// The tag after the policy, the direction, and the files' state.
+fn toggle_plan(paths: &[String], tag: &str) -> Result<(String, ToggleDirection, ToggleState), String> {
+  let policy = tag_policy::policy();
+  let tag = tag_policy::normalize_tag(tag, &policy)?;
+  // "#PeakTime" written before the policy existed counts as having "#peaktime".
+  let key = dedupe_key(&tag, &policy);
+  let state: ToggleState = paths
+    .iter()
+    .map(|p| meta_cache::get(Path::new(p)).map(|m| split_comment_tokens(&m.comment).into_iter().filter(|t| dedupe_key(t, &policy) == key).collect()))
+    .collect();
+  let direction = toggle_direction(&state.iter().map(|s| s.as_ref().ok().map(|m| !m.is_empty())).collect::<Vec<_>>());
+  Ok((tag, direction, state))
+}
+
+fn toggle_apply(paths: &[String], tag: &str, direction: ToggleDirection, state: ToggleState) -> Vec<ToggleFileResult> {
+  let one = [tag.to_string()];
+  paths
+    .iter()
+    .zip(state)
+    .map(|(p, s)| {
+      let res = s.and_then(|matches| match direction {
+        ToggleDirection::Add => merge_file_tags(p, &one, &[], audit::Source::Batch),
+        ToggleDirection::Remove => merge_file_tags(p, &[], &matches, audit::Source::Batch),
+      });
+      match res {
+        Ok(o) => ToggleFileResult { path: p.clone(), outcome: Some(o), error: None },
+        Err(e) => ToggleFileResult { path: p.clone(), outcome: None, error: Some(e) },
+      }
+    })
+    .collect()
+}
+
+/// Drop-onto-a-chip toggle for several files at once: add `tag` where it is
+/// missing, or remove it everywhere when every file already has it. Current
+/// comments come from the metadata cache; writes go through `merge_file_tags`,
+/// one file at a time, and a failing file doesn't stop the others.
+#[tauri::command]
+pub async fn toggle_tag_smart(app: tauri::AppHandle, paths: Vec<String>, tag: String) -> Result<ToggleReport, CmdError> {
+  let _span = command_span("toggle_tag_smart");
+  tauri::async_runtime::spawn_blocking(move || {
+    let (tag, direction, state) = toggle_plan(&paths, &tag)?;
+    let targets: Vec<&String> = paths.iter().zip(&state).filter(|(_, s)| s.is_ok()).map(|(p, _)| p).collect();
+    let preflight = preflight::rewrite(&targets);
+    preflight::ensure(&preflight)?;
+    let snapshot_id = snapshots::before_batch(&app, "toggle_tag_smart", &targets);
+    let results = toggle_apply(&paths, &tag, direction, state);
+    let changed = results.iter().filter(|r| r.outcome.as_ref().is_some_and(|o| o.changed)).count();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    log_line(&format!("toggle_tag_smart tag=\"{}\" direction={:?} files={} changed={} failed={}", tag, direction, results.len(), changed, failed));
+    Ok(ToggleReport { tag, direction, results, snapshot_id, preflight })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use lofty::TagType;
+  use crate::test_support;
+
+  fn s(v: &[&str]) -> Vec<String> { v.iter().map(|t| t.to_string()).collect() }
+
+  #[test]
+  fn merge_adds_each_tag_once_in_any_case() {
+    assert_eq!(merge_tokens("#House;TagB:x;", &s(&["#house", "#club", "#Club"]), &[]), "#House;#club;TagB:x;");
+    assert_eq!(merge_tokens("#a;#b;", &s(&["#c"]), &s(&["#a"])), "#b;#c;");
+  }
+
+  #[test]
+  fn the_direction_follows_the_readable_files() {
+    use ToggleDirection::*;
+    assert_eq!(toggle_direction(&[Some(true), Some(true)]), Remove);
+    assert_eq!(toggle_direction(&[Some(false), Some(false)]), Add);
+    assert_eq!(toggle_direction(&[Some(true), Some(false)]), Add);
+    assert_eq!(toggle_direction(&[Some(true), None]), Remove);
+    assert_eq!(toggle_direction(&[None]), Add);
+  }
+
+  fn comment(path: &str) -> Option<String> { test_support::text(Path::new(path), TagType::Id3v2, &ItemKey::Comment) }
+
+  #[test]
+  fn toggling_adds_where_missing_and_keeps_going_past_broken_files() {
+    let dir = test_support::scratch("toggle-mixed");
+    let file = |name: &str, c: &str| test_support::tagged(&dir, name, &[(ItemKey::Comment, c)]).to_string_lossy().to_string();
+    let paths = vec![file("a.mp3", "#PeakTime;"), dir.join("missing.mp3").to_string_lossy().to_string(), file("b.mp3", "#deep;"), file("c.mp3", "#x;")];
+    let (tag, direction, state) = toggle_plan(&paths, "#PeakTime").unwrap();
+    assert_eq!(direction, ToggleDirection::Add, "b.mp3 doesn't have it; the missing file doesn't vote");
+    // Gone between the read and the write.
+    fs::remove_file(&paths[3]).unwrap();
+    let results = toggle_apply(&paths, &tag, direction, state);
+    let errors: Vec<bool> = results.iter().map(|r| r.error.is_some()).collect();
+    assert_eq!(errors, [false, true, false, true]);
+    assert!(!results[0].outcome.as_ref().unwrap().changed);
+    assert_eq!(comment(&paths[0]).as_deref(), Some("#PeakTime;"));
+    assert_eq!(comment(&paths[2]), Some(format!("#deep;{};", tag)));
+  }
+
+  #[test]
+  fn toggling_removes_when_every_file_has_it() {
+    let dir = test_support::scratch("toggle-all");
+    let a = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#PeakTime;#x;")]).to_string_lossy().to_string();
+    let b = test_support::tagged(&dir, "b.mp3", &[(ItemKey::Comment, "#peaktime;")]).to_string_lossy().to_string();
+    let paths = vec![a, b];
+    let (tag, direction, state) = toggle_plan(&paths, "#peaktime").unwrap();
+    assert_eq!(direction, ToggleDirection::Remove);
+    let results = toggle_apply(&paths, &tag, direction, state);
+    assert!(results.iter().all(|r| r.outcome.as_ref().is_some_and(|o| o.changed)));
+    assert_eq!(comment(&paths[0]).as_deref(), Some("#x;"));
+    assert_eq!(comment(&paths[1]).as_deref(), Some(""));
+  }
+
+  #[test]
+  fn concurrent_merges_into_one_file_keep_every_token() {
+    let dir = test_support::scratch("merge-race");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#base;")]).to_string_lossy().to_string();
+    std::thread::scope(|sc| {
+      for i in 0..8 {
+        let p = &p;
+        sc.spawn(move || merge_file_tags(p, &[format!("#t{}", i)], &[], audit::Source::Manual).unwrap());
+      }
+    });
+    let tokens = split_comment_tokens(&comment(&p).unwrap());
+    assert_eq!(tokens.len(), 9, "{:?}", tokens);
+    assert!((0..8).all(|i| tokens.contains(&format!("#t{}", i))), "{:?}", tokens);
+  }
+}
@@ -0,0 +1,208 @@
+// Normalization rules for comment tags ("#Melodic" / "#melodic." -> "#melodic").
+// The live policy comes from Settings and is applied by the merge commands;
+// files already on disk only change through `normalize_existing_tags`.
+
+use std::path::PathBuf;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  command_span, error::CmdError, field_limits::FieldLimitHit, field_locks::{self, LockedField}, inspect::{plan_for_path, WritePlan}, jobs::JobHandle, library::audio_files_under, log_line,
+  preflight::{self, Preflight}, read_comment, read_tagged, retry_queue, snapshots, split_comment_tokens, tag_ops::join_tokens,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct TagPolicy {
+  pub lowercase: bool,
+  /// Drop trailing `.,:;!?` and quotes ("#melodic." -> "#melodic").
+  pub strip_trailing_punctuation: bool,
+  /// "deep house" -> "deep-house".
+  pub spaces_to_dashes: bool,
+  /// Characters, 0 = unlimited. Longer tags are rejected, not truncated.
+  pub max_length: usize,
+  /// Characters allowed besides letters and digits (e.g. "-_"); `None` allows anything.
+  /// A leading `#` is always allowed.
+  pub allowed_chars: Option<String>,
+}
+
+static POLICY: Lazy<RwLock<TagPolicy>> = Lazy::new(|| RwLock::new(TagPolicy::default()));
+
+pub fn set_policy(p: &TagPolicy) { *POLICY.write() = p.clone(); }
+pub fn policy() -> TagPolicy { POLICY.read().clone() }
+
+const TRAILING_PUNCT: &[char] = &['.', ',', ':', ';', '!', '?', '\'', '"', '…'];
+
+/// Normalized form of `raw`, or the reason it can't be a tag.
+pub fn normalize_tag(raw: &str, policy: &TagPolicy) -> Result<String, String> {
+  let mut t = raw.trim().to_string();
+  if policy.strip_trailing_punctuation { t = t.trim_end_matches(TRAILING_PUNCT).trim_end().to_string(); }
+  if policy.spaces_to_dashes { t = t.split_whitespace().collect::<Vec<_>>().join("-"); }
+  if policy.lowercase { t = t.to_lowercase(); }
+
+  if t.is_empty() || t == "#" { return Err("empty tag".into()); }
+  if t.contains(';') { return Err("';' separates tags and can't be part of one".into()); }
+  if t.starts_with("TagB:") { return Err("\"TagB:\" is reserved for the bank marker".into()); }
+  if policy.max_length > 0 && t.chars().count() > policy.max_length {
+    return Err(format!("longer than {} characters", policy.max_length));
+  }
+  if let Some(allowed) = &policy.allowed_chars {
+    let body = t.strip_prefix('#').unwrap_or(&t);
+    if let Some(bad) = body.chars().find(|c| !c.is_alphanumeric() && !allowed.contains(*c)) {
+      return Err(format!("character '{}' is not allowed", bad));
+    }
+  }
+  Ok(t)
+}
+
+/// Normalize a list of tags to add, failing on the first rejected one.
+pub fn normalize_for_add(tags: &[String]) -> Result<Vec<String>, String> {
+  let p = policy();
+  tags.iter().map(|t| normalize_tag(t, &p).map_err(|e| format!("tag \"{}\" rejected: {}", t.trim(), e))).collect()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagValidation {
+  input: String,
+  normalized: Option<String>,
+  /// The normalized form differs from the input.
+  changed: bool,
+  error: Option<String>,
+}
+
+/// Live check for the bank editor.
+#[tauri::command]
+pub fn validate_tag(tag: String) -> TagValidation {
+  match normalize_tag(&tag, &policy()) {
+    Ok(n) => TagValidation { changed: n != tag, normalized: Some(n), input: tag, error: None },
+    Err(e) => TagValidation { input: tag, normalized: None, changed: false, error: Some(e) },
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagChange {
+  from: String,
+  /// `None`: dropped as a duplicate of another tag's normalized form.
+  to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagRejection {
+  tag: String,
+  reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizeResult {
+  path: String,
+  before: String,
+  after: String,
+  changed: bool,
+  changes: Vec<TagChange>,
+  /// Left in place as they are.
+  rejected: Vec<TagRejection>,
+  error: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  plan: Option<WritePlan>,
+  /// `["comment"]` when the comment is locked; nothing was written.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+  /// Values cut or left out for a tag type's length limit (see `field_limits`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<FieldLimitHit>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizeReport {
+  results: Vec<NormalizeResult>,
+  cancelled: bool,
+  /// Set when the batch was large enough to snapshot first (see `snapshots`).
+  snapshot_id: Option<String>,
+  /// Space and permission check run before writing (see `preflight`).
+  preflight: Preflight,
+}
+
+/// Policy applied to one comment. The bank marker and free-text notes (tokens
+/// with spaces that don't start with `#`) are kept as they are.
+fn normalize_comment(comment: &str, policy: &TagPolicy, res: &mut NormalizeResult) -> String {
+  let mut out: Vec<String> = Vec::new();
+  for tok in split_comment_tokens(comment) {
+    let is_note = tok.contains(char::is_whitespace) && !tok.starts_with('#');
+    if tok.starts_with("TagB:") || is_note { out.push(tok); continue; }
+    match normalize_tag(&tok, policy) {
+      Ok(n) if out.contains(&n) => res.changes.push(TagChange { from: tok, to: None }),
+      Ok(n) => {
+        if n != tok { res.changes.push(TagChange { from: tok, to: Some(n.clone()) }); }
+        out.push(n);
+      }
+      Err(reason) => { res.rejected.push(TagRejection { tag: tok.clone(), reason }); out.push(tok); }
+    }
+  }
+  // Keep the bank marker last even if a duplicate got dropped before it.
+  if let Some(i) = out.iter().position(|t| t.starts_with("TagB:")) { let b = out.remove(i); out.push(b); }
+  join_tokens(&out)
+}
+
+fn normalize_blocking(job: &JobHandle, folder: &str, dry_run: bool) -> Result<NormalizeReport, CmdError> {
+  let policy = policy();
+  job.begin_phase("scan", 0);
+  let paths = job.timed("walk", || audio_files_under(&PathBuf::from(folder))).map_err(|e| e.to_string())?;
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(&paths));
+  if !dry_run { preflight::ensure(&preflight)?; }
+  let snapshot_id = if dry_run { None } else { snapshots::before_batch(job.app(), "normalize_existing_tags", &paths) };
+  let mut results = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("write", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let mut res = NormalizeResult { path: p.to_string_lossy().to_string(), ..Default::default() };
+    match job.timed("parse", || read_tagged(p).map(|tf| read_comment(&tf, p))) {
+      Ok(before) => {
+        res.after = normalize_comment(&before, &policy, &mut res);
+        res.before = before;
+        res.changed = res.after != res.before;
+        if res.changed && dry_run {
+          res.plan = plan_for_path(p).ok();
+          res.skipped_locked = field_locks::hits(p, &[LockedField::Comment]);
+        }
+        if res.changed && !dry_run {
+          match job.timed("write", || retry_queue::write_comment(&res.path, &res.after)) {
+            Ok(o) => { res.changed = !o.no_op; (res.skipped_locked, res.limited) = (o.skipped_locked, o.limited); }
+            Err(e) => res.error = Some(e.to_string()),
+          }
+        }
+      }
+      Err(e) => res.error = Some(e.to_string()),
+    }
+    results.push(res);
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  if !dry_run {
+    let changed = results.iter().filter(|r| r.changed && r.error.is_none()).count();
+    log_line(&format!("normalize_existing_tags folder=\"{}\" changed={} cancelled={}", folder, changed, cancelled));
+  }
+  Ok(NormalizeReport { results, cancelled, snapshot_id, preflight })
+}
+
+/// Apply the current policy to every file under `folder`, reporting each
+/// rename, merged duplicate and rejection.
+#[tauri::command]
+pub async fn normalize_existing_tags(app: tauri::AppHandle, folder: String, dry_run: bool) -> Result<NormalizeReport, CmdError> {
+  let _span = command_span("normalize_existing_tags");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "normalize-tags", &folder);
+    let res = normalize_blocking(&job, &folder, dry_run);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,225 @@
+// Renaming a tag within one folder. `count_tag_occurrences` answers "how many
+// files would change" for the audio files in the folder as listed now, through
+// the metadata cache: only files that are new or changed since they were
+// cached are opened, so it stays cheap enough for every keystroke of a rename
+// box. `rename_tag_in_folder` picks its files the same way, so the preview
+// count and the files touched agree. Both match a
+// token as written or after the tag policy ("#Melodic" finds "#melodic", as
+// removals do). A dry run previews each new comment from the cache; the real
+// run re-reads every file and writes through the batch writer.
+
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+
+use crate::{
+  command_span, error::CmdError, field_limits::FieldLimitHit, field_locks::LockedField, jobs::JobHandle, library, log_line, meta_cache, preflight::{self, Preflight}, read_comment, read_tagged,
+  removed_tags, retry_queue, snapshots, split_comment_tokens, tag_ops::join_tokens, tag_policy::{self, TagPolicy},
+};
+
+/// How many tokens of `comment` are `tag`.
+fn occurrences(comment: &str, tag: &str, policy: &TagPolicy) -> usize {
+  split_comment_tokens(comment).iter().filter(|t| removed_tags::matches(t, tag, policy)).count()
+}
+
+/// `comment` with every `old` token replaced by one `new` where the first
+/// was (none added when the comment already has `new`).
+fn renamed(comment: &str, old: &str, new: &str, policy: &TagPolicy) -> String {
+  let tokens = split_comment_tokens(comment);
+  let mut placed = tokens.iter().any(|t| t == new && !removed_tags::matches(t, old, policy));
+  let mut out = Vec::with_capacity(tokens.len());
+  for t in tokens {
+    if !removed_tags::matches(&t, old, policy) { out.push(t); continue; }
+    if !placed { out.push(new.to_string()); placed = true; }
+  }
+  join_tokens(&out)
+}
+
+/// What `carriers` found.
+struct Found {
+  /// (path, comment, occurrences) of the files carrying the tag, in listing order.
+  files: Vec<(String, String, usize)>,
+  scanned: usize,
+  read: usize,
+}
+
+/// The audio files under `folder` carrying `tag`, by their current comment
+/// (see the header). Files that can't be read don't carry it.
+fn carriers(folder: &str, tag: &str, recursive: bool, policy: &TagPolicy) -> Result<Found, CmdError> {
+  let paths = library::audio_files(Path::new(folder), recursive)?;
+  let mut found = Found { files: Vec::new(), scanned: paths.len(), read: 0 };
+  for p in &paths {
+    let Ok((m, opened)) = meta_cache::lookup(p) else { continue };
+    if opened { found.read += 1; }
+    let n = occurrences(&m.comment, tag, policy);
+    if n > 0 { found.files.push((p.to_string_lossy().to_string(), m.comment, n)); }
+  }
+  Ok(found)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+  /// Files carrying the tag.
+  files: usize,
+  /// Tokens, counting a tag repeated in one comment each time.
+  occurrences: usize,
+  /// Audio files looked at.
+  scanned: usize,
+  /// Of those, the ones opened because the cache had nothing current.
+  read: usize,
+}
+
+/// Files under `folder` whose comment has `tag` (see the header).
+#[tauri::command]
+pub async fn count_tag_occurrences(folder: String, tag: String, recursive: bool) -> Result<TagCount, CmdError> {
+  let _span = command_span("count_tag_occurrences");
+  tauri::async_runtime::spawn_blocking(move || {
+    let found = carriers(&folder, tag.trim(), recursive, &tag_policy::policy())?;
+    Ok(TagCount { files: found.files.len(), occurrences: found.files.iter().map(|(.., n)| n).sum(), scanned: found.scanned, read: found.read })
+  })
+  .await
+  .map_err(|e| CmdError::from(e.to_string()))?
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameResult {
+  path: String,
+  before: String,
+  after: String,
+  /// False when the file no longer had the tag, or the rename only
+  /// respelled it to what it was.
+  changed: bool,
+  error: Option<String>,
+  /// `["comment"]` when the comment is locked; nothing was written.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+  /// Values cut or left out for a tag type's length limit (see `field_limits`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<FieldLimitHit>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameReport {
+  /// `new` after the tag policy.
+  tag: String,
+  /// One per file `count_tag_occurrences` counts.
+  results: Vec<RenameResult>,
+  cancelled: bool,
+  snapshot_id: Option<String>,
+  preflight: Option<Preflight>,
+}
+
+fn rename_blocking(job: &JobHandle, folder: &str, old: &str, new: &str, recursive: bool, dry_run: bool) -> Result<RenameReport, CmdError> {
+  let policy = tag_policy::policy();
+  let new = tag_policy::normalize_tag(new, &policy).map_err(|e| format!("tag \"{}\" rejected: {}", new.trim(), e))?;
+  let old = old.trim();
+  if old.is_empty() { return Err(String::from("no tag to rename").into()); }
+  let found = carriers(folder, old, recursive, &policy)?.files;
+  if dry_run {
+    let results = found.into_iter().map(|(path, before, _)| {
+      let after = renamed(&before, old, &new, &policy);
+      RenameResult { path, changed: after != before, before, after, ..Default::default() }
+    }).collect();
+    return Ok(RenameReport { tag: new, results, cancelled: false, snapshot_id: None, preflight: None });
+  }
+
+  let paths: Vec<PathBuf> = found.iter().map(|(p, ..)| PathBuf::from(p)).collect();
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(&paths));
+  preflight::ensure(&preflight)?;
+  let snapshot_id = snapshots::before_batch(job.app(), "rename_tag_in_folder", &paths);
+  let (mut results, mut cancelled) = (Vec::new(), false);
+  job.begin_phase("write", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let mut res = RenameResult { path: p.to_string_lossy().to_string(), ..Default::default() };
+    match job.timed("parse", || read_tagged(p).map(|tf| read_comment(&tf, p))) {
+      Ok(before) => {
+        res.after = renamed(&before, old, &new, &policy);
+        res.before = before;
+        res.changed = res.after != res.before;
+        if res.changed {
+          match job.timed("write", || retry_queue::write_comment(&res.path, &res.after)) {
+            Ok(o) => { res.changed = o.skipped_locked.is_empty() && !o.no_op; (res.skipped_locked, res.limited) = (o.skipped_locked, o.limited); }
+            Err(e) => { res.changed = false; res.error = Some(e.to_string()); }
+          }
+        }
+      }
+      Err(e) => res.error = Some(e.to_string()),
+    }
+    results.push(res);
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  let changed = results.iter().filter(|r| r.changed).count();
+  log_line(&format!("rename_tag_in_folder folder=\"{}\" old=\"{}\" new=\"{}\" files={} changed={} cancelled={}", folder, old, new, results.len(), changed, cancelled));
+  Ok(RenameReport { tag: new, results, cancelled, snapshot_id, preflight: Some(preflight) })
+}
+
+/// Rename `old` to `new` in the files of `folder` (and below with
+/// `recursive`) that `count_tag_occurrences` counts. With `dry_run` nothing is
+/// written and each result previews the new comment.
+#[tauri::command]
+pub async fn rename_tag_in_folder(
+  app: tauri::AppHandle,
+  folder: String,
+  old: String,
+  new: String,
+  dry_run: bool,
+  recursive: Option<bool>,
+) -> Result<RenameReport, CmdError> {
+  let _span = command_span("rename_tag_in_folder");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "rename-tag", &folder);
+    let res = rename_blocking(&job, &folder, &old, &new, recursive.unwrap_or(false), dry_run);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{fs, time::{Duration, SystemTime}};
+  use lofty::ItemKey;
+  use crate::test_support;
+
+  fn names(found: &Found) -> Vec<String> {
+    found.files.iter().map(|(p, ..)| Path::new(p).file_name().unwrap().to_string_lossy().to_string()).collect()
+  }
+
+  #[test]
+  fn count_and_rename_see_the_files_as_they_are_now() {
+    let dir = test_support::scratch("tag-rename");
+    let policy = tag_policy::policy();
+    let stale = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#old;#x;")]);
+    meta_cache::get(&stale).unwrap();
+    // Edited outside the app after it was cached.
+    test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#x;")]);
+    fs::File::options().write(true).open(&stale).unwrap().set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+    test_support::tagged(&dir, "b.mp3", &[(ItemKey::Comment, "#old;#old;")]);
+    meta_cache::get(&test_support::tagged(&dir, "c.mp3", &[(ItemKey::Comment, "#keep;")])).unwrap();
+    fs::create_dir(dir.join("sub")).unwrap();
+    test_support::tagged(&dir.join("sub"), "d.mp3", &[(ItemKey::Comment, "#old;")]);
+
+    let found = carriers(&dir.to_string_lossy(), "#old", false, &policy).unwrap();
+    assert_eq!(names(&found), ["b.mp3"]);
+    assert_eq!(found.files[0].2, 2);
+    assert_eq!((found.scanned, found.read), (3, 2), "the stale and the uncached file are opened");
+    let again = carriers(&dir.to_string_lossy(), "#old", false, &policy).unwrap();
+    assert_eq!((names(&again), again.read), (names(&found), 0));
+
+    let deep = carriers(&dir.to_string_lossy(), "#old", true, &policy).unwrap();
+    assert_eq!(names(&deep), ["b.mp3", "d.mp3"]);
+  }
+
+  #[test]
+  fn renamed_keeps_one_new_token_where_the_old_was() {
+    let policy = tag_policy::policy();
+    assert_eq!(renamed("#a;#old;#b;#old;", "#old", "#new", &policy), "#a;#new;#b;");
+    assert_eq!(renamed("#new;#old;", "#old", "#new", &policy), "#new;");
+  }
+}
@@ -0,0 +1,402 @@
+// How much of a file is tags. lofty doesn't say where its tags sit or how big
+// they are on disk, so the blocks are found by hand: a leading ID3v2 tag
+// (header-declared size, padding included), a trailing APEv2 tag and ID3v1,
+// FLAC metadata blocks, MP4 `ilst` and the `free` atoms beside it, and RIFF
+// INFO / ID3 chunks in WAV and AIFF. Everything else counts as audio, which
+// is close enough: stream headers and seek tables are small. Ogg comments
+// live inside pages and aren't measured. The folder aggregate flags files
+// whose tags pass a threshold; that's usually huge embedded art or Serato
+// overview frames.
+
+use std::{fs, io::{Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+use serde::Serialize;
+
+use crate::{command_span, ext_lower, id3_padding, jobs::JobHandle, library::audio_files, log_line};
+
+const HEAVY_DEFAULT: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagBlockSize {
+  /// "Id3v2", "Id3v1", "Ape", "VorbisComments", "FlacPictures", "FlacPadding",
+  /// "Mp4Ilst", "Mp4Free", "RiffInfo", or "Id3v2Chunk" (ID3 inside WAV/AIFF).
+  block: String,
+  bytes: u64,
+  /// Reserved space inside the block, where the format has it.
+  padding_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSizeReport {
+  path: String,
+  file_bytes: u64,
+  tag_bytes: u64,
+  audio_bytes: u64,
+  audio_percent: f64,
+  blocks: Vec<TagBlockSize>,
+}
+
+fn block(name: &str, bytes: u64, padding_bytes: Option<u64>) -> TagBlockSize {
+  TagBlockSize { block: name.to_string(), bytes, padding_bytes }
+}
+
+fn read_at<R: Read + Seek>(r: &mut R, at: u64, buf: &mut [u8]) -> Option<()> {
+  r.seek(SeekFrom::Start(at)).ok()?;
+  r.read_exact(buf).ok()
+}
+
+/// ID3v1 (and its 227-byte "TAG+" extension) and an APEv2 tag before it.
+fn trailing<R: Read + Seek>(r: &mut R, len: u64, out: &mut Vec<TagBlockSize>) {
+  let mut end = len;
+  let mut magic = [0u8; 4];
+  if len >= 128 && read_at(r, len - 128, &mut magic[..3]).is_some() && &magic[..3] == b"TAG" {
+    let mut ext = 0;
+    if len >= 128 + 227 && read_at(r, len - 128 - 227, &mut magic).is_some() && &magic == b"TAG+" { ext = 227; }
+    out.push(block("Id3v1", 128 + ext, None));
+    end -= 128 + ext;
+  }
+  let mut footer = [0u8; 32];
+  if end >= 32 && read_at(r, end - 32, &mut footer).is_some() && &footer[..8] == b"APETAGEX" {
+    // Size covers items + footer; bit 31 of the flags says a 32-byte header precedes them.
+    let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+    let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+    let total = size + if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+    if total <= end { out.push(block("Ape", total, None)); }
+  }
+}
+
+/// Metadata blocks after "fLaC" (which may follow a leading ID3v2 tag).
+fn flac<R: Read + Seek>(r: &mut R, start: u64, len: u64, out: &mut Vec<TagBlockSize>) {
+  let mut magic = [0u8; 4];
+  if read_at(r, start, &mut magic).is_none() || &magic != b"fLaC" { return; }
+  let (mut comments, mut pictures, mut padding) = (None, 0u64, 0u64);
+  let mut at = start + 4;
+  let mut head = [0u8; 4];
+  while at + 4 <= len && read_at(r, at, &mut head).is_some() {
+    let size = 4 + (((head[1] as u64) << 16) | ((head[2] as u64) << 8) | head[3] as u64);
+    match head[0] & 0x7f {
+      1 => padding += size,
+      4 => comments = Some(comments.unwrap_or(0) + size),
+      6 => pictures += size,
+      _ => {}
+    }
+    at += size;
+    if head[0] & 0x80 != 0 { break; }
+  }
+  if let Some(c) = comments { out.push(block("VorbisComments", c, None)); }
+  if pictures > 0 { out.push(block("FlacPictures", pictures, None)); }
+  if padding > 0 { out.push(block("FlacPadding", padding, Some(padding))); }
+}
+
+/// Atoms in [start, end): (type, offset, total size).
+fn atoms<R: Read + Seek>(r: &mut R, start: u64, end: u64) -> Vec<([u8; 4], u64, u64)> {
+  let mut out = Vec::new();
+  let mut at = start;
+  let mut head = [0u8; 8];
+  while at + 8 <= end && read_at(r, at, &mut head).is_some() {
+    let kind = [head[4], head[5], head[6], head[7]];
+    let size = match u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as u64 {
+      0 => end - at,
+      1 => {
+        let mut large = [0u8; 8];
+        if read_at(r, at + 8, &mut large).is_none() { break; }
+        u64::from_be_bytes(large)
+      }
+      s => s,
+    };
+    if size < 8 || at + size > end { break; }
+    out.push((kind, at, size));
+    at += size;
+  }
+  out
+}
+
+fn child<'a>(list: &'a [([u8; 4], u64, u64)], kind: &[u8; 4]) -> Option<&'a ([u8; 4], u64, u64)> {
+  list.iter().find(|a| &a.0 == kind)
+}
+
+/// moov/udta/meta/ilst, with `free` atoms around it counted as padding.
+fn mp4<R: Read + Seek>(r: &mut R, len: u64, out: &mut Vec<TagBlockSize>) {
+  let top = atoms(r, 0, len);
+  let Some(&(_, moov, moov_len)) = child(&top, b"moov") else { return };
+  let Some(&(_, udta, udta_len)) = child(&atoms(r, moov + 8, moov + moov_len), b"udta") else { return };
+  let udta_kids = atoms(r, udta + 8, udta + udta_len);
+  let Some(&(_, meta, meta_len)) = child(&udta_kids, b"meta") else { return };
+  // ISO `meta` is a full box (4 bytes of version/flags); QuickTime's starts with `hdlr` right away.
+  let mut peek = [0u8; 4];
+  let quicktime = read_at(r, meta + 12, &mut peek).is_some() && &peek == b"hdlr";
+  let meta_kids = atoms(r, meta + if quicktime { 8 } else { 12 }, meta + meta_len);
+  if let Some(&(_, _, ilst_len)) = child(&meta_kids, b"ilst") { out.push(block("Mp4Ilst", ilst_len, None)); }
+  let free: u64 = meta_kids.iter().chain(udta_kids.iter()).filter(|a| &a.0 == b"free").map(|a| a.2).sum();
+  if free > 0 { out.push(block("Mp4Free", free, Some(free))); }
+}
+
+/// RIFF (little-endian) or FORM (big-endian) chunks: LIST/INFO and ID3 chunks.
+fn chunks<R: Read + Seek>(r: &mut R, len: u64, big_endian: bool, out: &mut Vec<TagBlockSize>) {
+  let (mut info, mut id3) = (0u64, 0u64);
+  let mut at = 12u64;
+  let mut head = [0u8; 8];
+  let mut list_type = [0u8; 4];
+  while at + 8 <= len && read_at(r, at, &mut head).is_some() {
+    let raw = [head[4], head[5], head[6], head[7]];
+    let size = if big_endian { u32::from_be_bytes(raw) } else { u32::from_le_bytes(raw) } as u64;
+    let total = 8 + size + size % 2;
+    let id = &head[..4];
+    if id == b"LIST" && read_at(r, at + 8, &mut list_type).is_some() && &list_type == b"INFO" {
+      info += total;
+    } else if id.eq_ignore_ascii_case(b"id3 ") {
+      id3 += total;
+    }
+    at += total;
+  }
+  if info > 0 { out.push(block("RiffInfo", info, None)); }
+  if id3 > 0 { out.push(block("Id3v2Chunk", id3, None)); }
+}
+
+fn measure(p: &Path) -> Result<TagSizeReport, String> {
+  let mut f = fs::File::open(p).map_err(|e| e.to_string())?;
+  let len = f.metadata().map_err(|e| e.to_string())?.len();
+  let mut blocks = Vec::new();
+  let mut head = [0u8; 12];
+  let _ = read_at(&mut f, 0, &mut head);
+  let id3 = id3_padding::measure(p);
+  if let Some(t) = id3 { blocks.push(block("Id3v2", t.tag_bytes, Some(t.padding_bytes))); }
+  match (&head[..4], &head[8..12]) {
+    (b"RIFF", b"WAVE") => chunks(&mut f, len, false, &mut blocks),
+    (b"FORM", b"AIFF" | b"AIFC") => chunks(&mut f, len, true, &mut blocks),
+    _ if &head[4..8] == b"ftyp" => mp4(&mut f, len, &mut blocks),
+    _ => {
+      flac(&mut f, id3.map_or(0, |t| t.tag_bytes), len, &mut blocks);
+      if ext_lower(p) != "ogg" { trailing(&mut f, len, &mut blocks); }
+    }
+  }
+  let tag_bytes = blocks.iter().map(|b| b.bytes).sum::<u64>().min(len);
+  let audio_bytes = len - tag_bytes;
+  Ok(TagSizeReport {
+    path: p.to_string_lossy().to_string(),
+    file_bytes: len,
+    tag_bytes,
+    audio_bytes,
+    audio_percent: if len == 0 { 0.0 } else { (audio_bytes as f64 * 1000.0 / len as f64).round() / 10.0 },
+    blocks,
+  })
+}
+
+/// Byte size of every tag block in the file, and the share left for audio.
+#[tauri::command]
+pub fn tag_size_report(path: String) -> Result<TagSizeReport, String> { measure(Path::new(&path)) }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTotal {
+  block: String,
+  bytes: u64,
+  files: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderTagSizeReport {
+  scanned: usize,
+  file_bytes: u64,
+  tag_bytes: u64,
+  by_block: Vec<BlockTotal>,
+  threshold_bytes: u64,
+  /// Files whose tags exceed the threshold, biggest first.
+  heavy: Vec<TagSizeReport>,
+  cancelled: bool,
+}
+
+fn folder_blocking(job: &JobHandle, folder: &str, recursive: bool, threshold: u64) -> Result<FolderTagSizeReport, String> {
+  job.begin_phase("scan", 0);
+  let paths: Vec<PathBuf> = job.timed("walk", || audio_files(&PathBuf::from(folder), recursive)).map_err(|e| e.to_string())?;
+  let mut report = FolderTagSizeReport { scanned: 0, file_bytes: 0, tag_bytes: 0, by_block: Vec::new(), threshold_bytes: threshold, heavy: Vec::new(), cancelled: false };
+  job.begin_phase("analyze", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { report.cancelled = true; break; }
+    match job.timed("parse", || measure(p)) {
+      Ok(r) => {
+        report.scanned += 1;
+        report.file_bytes += r.file_bytes;
+        report.tag_bytes += r.tag_bytes;
+        for b in &r.blocks {
+          match report.by_block.iter_mut().find(|t| t.block == b.block) {
+            Some(t) => { t.bytes += b.bytes; t.files += 1; }
+            None => report.by_block.push(BlockTotal { block: b.block.clone(), bytes: b.bytes, files: 1 }),
+          }
+        }
+        if r.tag_bytes > threshold { report.heavy.push(r); }
+      }
+      Err(e) => log_line(&format!("tag_size_report skip \"{}\": {}", p.display(), e)),
+    }
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  report.by_block.sort_by_key(|t| std::cmp::Reverse(t.bytes));
+  report.heavy.sort_by_key(|r| std::cmp::Reverse(r.tag_bytes));
+  Ok(report)
+}
+
+/// Tag sizes summed over `folder`; job kind "tag-sizes". Files with more
+/// than `threshold_bytes` of tags (default 1 MiB) are listed individually.
+#[tauri::command]
+pub async fn folder_tag_size_report(app: tauri::AppHandle, folder: String, recursive: bool, threshold_bytes: Option<u64>) -> Result<FolderTagSizeReport, String> {
+  let _span = command_span("folder_tag_size_report");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "tag-sizes", &folder);
+    let res = folder_blocking(&job, &folder, recursive, threshold_bytes.unwrap_or(HEAVY_DEFAULT));
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{id3_padding::to_syncsafe, test_support};
+
+  /// ID3v2.4 with Latin-1 text `frames` and `padding` zero bytes.
+  fn id3(frames: &[(&str, &str)], padding: usize) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (id, value) in frames {
+      body.extend_from_slice(id.as_bytes());
+      body.extend_from_slice(&to_syncsafe(value.len() as u64 + 1));
+      body.extend_from_slice(&[0, 0, 0]);
+      body.extend_from_slice(value.as_bytes());
+    }
+    body.resize(body.len() + padding, 0);
+    let mut out = b"ID3\x04\0\0".to_vec();
+    out.extend_from_slice(&to_syncsafe(body.len() as u64));
+    out.extend_from_slice(&body);
+    out
+  }
+
+  /// APEv2 with a header, `items` and a footer.
+  fn ape(items: &[(&str, &str)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (k, v) in items {
+      body.extend_from_slice(&(v.len() as u32).to_le_bytes());
+      body.extend_from_slice(&0u32.to_le_bytes());
+      body.extend_from_slice(k.as_bytes());
+      body.push(0);
+      body.extend_from_slice(v.as_bytes());
+    }
+    let frame = |flags: u32| {
+      let mut f = b"APETAGEX".to_vec();
+      f.extend_from_slice(&2000u32.to_le_bytes());
+      f.extend_from_slice(&(body.len() as u32 + 32).to_le_bytes());
+      f.extend_from_slice(&(items.len() as u32).to_le_bytes());
+      f.extend_from_slice(&flags.to_le_bytes());
+      f.extend_from_slice(&[0u8; 8]);
+      f
+    };
+    [frame(0xA000_0000), body.clone(), frame(0x8000_0000)].concat()
+  }
+
+  fn flac_block(kind: u8, len: usize, last: bool) -> Vec<u8> {
+    let mut out = vec![kind | if last { 0x80 } else { 0 }];
+    out.extend_from_slice(&(len as u32).to_be_bytes()[1..]);
+    out.resize(4 + len, 0);
+    out
+  }
+
+  fn riff_chunk(id: &[u8; 4], body: &[u8], big_endian: bool) -> Vec<u8> {
+    let mut out = id.to_vec();
+    let len = body.len() as u32;
+    out.extend_from_slice(&if big_endian { len.to_be_bytes() } else { len.to_le_bytes() });
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 { out.push(0); }
+    out
+  }
+
+  fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = (body.len() as u32 + 8).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+  }
+
+  fn sizes(bytes: &[u8], name: &str) -> TagSizeReport {
+    let p = test_support::scratch("tag-size").join(name);
+    fs::write(&p, bytes).unwrap();
+    measure(&p).unwrap()
+  }
+
+  fn blocks(r: &TagSizeReport) -> Vec<(&str, u64, Option<u64>)> {
+    r.blocks.iter().map(|b| (b.block.as_str(), b.bytes, b.padding_bytes)).collect()
+  }
+
+  #[test]
+  fn mp3_id3v2_with_padding_ape_and_id3v1() {
+    let head = id3(&[("TIT2", "Title"), ("TPE1", "Artist")], 300);
+    let tail = ape(&[("Title", "Title"), ("Artist", "Artist")]);
+    let mut plus = b"TAG+".to_vec();
+    plus.resize(227, 0);
+    let mut v1 = b"TAG".to_vec();
+    v1.resize(128, 0);
+    let r = sizes(&[head.clone(), test_support::mpeg_frames(10), tail.clone(), plus, v1].concat(), "a.mp3");
+    assert_eq!(blocks(&r), [("Id3v2", head.len() as u64, Some(300)), ("Id3v1", 128 + 227, None), ("Ape", tail.len() as u64, None)]);
+    assert_eq!((r.audio_bytes, r.tag_bytes + r.audio_bytes), (4170, r.file_bytes));
+
+    // A footer-only APE tag (APEv1 style), no ID3v1.
+    let mut footer_only = tail[tail.len() - 32..].to_vec();
+    footer_only[23] = 0;
+    let items = &tail[32..tail.len() - 32];
+    let r = sizes(&[test_support::mpeg_frames(4), items.to_vec(), footer_only].concat(), "b.mp3");
+    assert_eq!(blocks(&r), [("Ape", items.len() as u64 + 32, None)]);
+    assert_eq!(sizes(&test_support::mpeg_frames(4), "c.mp3").blocks.len(), 0, "untagged");
+  }
+
+  #[test]
+  fn flac_metadata_blocks_behind_an_id3_tag() {
+    let meta = [b"fLaC".to_vec(), flac_block(0, 34, false), flac_block(4, 120, false), flac_block(6, 5000, false), flac_block(4, 10, false), flac_block(1, 800, true)].concat();
+    let r = sizes(&[meta.clone(), vec![0xFF; 2000]].concat(), "a.flac");
+    assert_eq!(blocks(&r), [("VorbisComments", 138, None), ("FlacPictures", 5004, None), ("FlacPadding", 804, Some(804))]);
+    assert_eq!(r.audio_bytes, 4 + 38 + 2000, "the marker and STREAMINFO count as audio");
+
+    let head = id3(&[("TIT2", "T")], 0);
+    let r = sizes(&[head.clone(), meta, vec![0xFF; 2000]].concat(), "b.flac");
+    assert_eq!(blocks(&r)[0], ("Id3v2", head.len() as u64, Some(0)));
+    assert_eq!(blocks(&r)[1], ("VorbisComments", 138, None));
+  }
+
+  #[test]
+  fn riff_info_and_id3_chunks_in_wav_and_aiff() {
+    // INFO with an odd-sized item: the pad byte counts.
+    let info = [b"INFO".to_vec(), riff_chunk(b"INAM", b"Title", false)].concat();
+    let id3_body = id3(&[("TIT2", "Title")], 0);
+    let chunks = [riff_chunk(b"fmt ", &[0; 16], false), riff_chunk(b"data", &[0; 1000], false), riff_chunk(b"LIST", &info, false), riff_chunk(b"id3 ", &id3_body, false)].concat();
+    let mut wav = b"RIFF".to_vec();
+    wav.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(&chunks);
+    let r = sizes(&wav, "a.wav");
+    let id3_chunk = 8 + id3_body.len() as u64 + id3_body.len() as u64 % 2;
+    assert_eq!(blocks(&r), [("RiffInfo", 8 + 4 + 8 + 5 + 1, None), ("Id3v2Chunk", id3_chunk, None)]);
+
+    let chunks = [riff_chunk(b"COMM", &[0; 18], true), riff_chunk(b"SSND", &[0; 1000], true), riff_chunk(b"ID3 ", &id3_body, true)].concat();
+    let mut aiff = b"FORM".to_vec();
+    aiff.extend_from_slice(&(chunks.len() as u32 + 4).to_be_bytes());
+    aiff.extend_from_slice(b"AIFF");
+    aiff.extend_from_slice(&chunks);
+    assert_eq!(blocks(&sizes(&aiff, "a.aiff")), [("Id3v2Chunk", id3_chunk, None)]);
+  }
+
+  #[test]
+  fn mp4_ilst_and_free_in_iso_and_quicktime_meta() {
+    let ilst = atom(b"ilst", &[0; 300]);
+    let hdlr = atom(b"hdlr", &[0; 25]);
+    let free = atom(b"free", &[0; 64]);
+    for quicktime in [false, true] {
+      let version = if quicktime { vec![] } else { vec![0; 4] };
+      let meta = atom(b"meta", &[version, hdlr.clone(), ilst.clone(), free.clone()].concat());
+      let udta = atom(b"udta", &[meta, atom(b"free", &[0; 16])].concat());
+      let moov = atom(b"moov", &[atom(b"mvhd", &[0; 100]), udta].concat());
+      let file = [atom(b"ftyp", b"M4A \0\0\0\0"), moov, atom(b"mdat", &[0; 3000])].concat();
+      let r = sizes(&file, "a.m4a");
+      assert_eq!(blocks(&r), [("Mp4Ilst", 308, None), ("Mp4Free", 72 + 24, Some(96))], "quicktime={}", quicktime);
+    }
+  }
+}
@@ -0,0 +1,273 @@
+// Where a file's tags live: in the comment (what DJ software shows) or in our
+// own AUDIOTAGGER_TAGS field (TXXX in ID3v2, a plain key in Vorbis comments
+// and APE, the iTunes freeform atom in MP4), both as `a;b;c;`. Libraries end
+// up with some files of each and some with both, not always agreeing.
+// `audit_tag_storage` sorts the files of a folder by that;
+// `reconcile_tag_storage` writes one set to the `tag_storage` location of
+// Settings and clears the other location or keeps it in step. Files whose
+// two sets differ are left alone unless a policy says which wins. Tag edits
+// still go to the comment; `tag_storage` only decides where reconciliation
+// puts them. Tags are the comment tokens `tag_suggest` counts, so notes and
+// the bank marker stay in the comment.
+
+use std::{path::{Path, PathBuf}, sync::atomic::{AtomicU8, Ordering}};
+use lofty::{ItemKey, ItemValue, Tag, TagItem, TagType, TaggedFileExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  audit, command_span, edit_tags, error::CmdError, field_locks::LockedField, jobs::JobHandle, library::audio_files, log_line, preflight::{self, Preflight},
+  read_comment, read_tagged, shadow, snapshots, split_comment_tokens, tag_ops::{join_tokens, merge_tokens}, tag_suggest,
+};
+
+const NAME: &str = "AUDIOTAGGER_TAGS";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagStorage { #[default] Comment, Field }
+
+pub static CANONICAL: AtomicU8 = AtomicU8::new(TagStorage::Comment as u8);
+
+fn canonical() -> TagStorage {
+  if CANONICAL.load(Ordering::Relaxed) == TagStorage::Field as u8 { TagStorage::Field } else { TagStorage::Comment }
+}
+
+fn key(tt: TagType) -> Option<ItemKey> {
+  match tt {
+    TagType::Id3v2 | TagType::VorbisComments | TagType::Ape => Some(ItemKey::Unknown(NAME.into())),
+    TagType::Mp4Ilst => Some(ItemKey::Unknown(format!("----:com.apple.iTunes:{}", NAME))),
+    _ => None,
+  }
+}
+
+fn read_field(tag: &Tag) -> Option<String> { tag.get_string(&key(tag.tag_type())?).map(str::to_string) }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageState { CommentOnly, FieldOnly, BothConsistent, BothConflicting, Neither }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageEntry {
+  path: String,
+  state: StorageState,
+  comment_tags: Vec<String>,
+  field_tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageAudit {
+  files: Vec<StorageEntry>,
+  scanned: usize,
+  cancelled: bool,
+}
+
+/// Comment, its tags and the field's tags, as on disk.
+struct Stored {
+  comment: String,
+  comment_tags: Vec<String>,
+  field_tags: Vec<String>,
+}
+
+fn read_stored(p: &Path) -> Result<Stored, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let comment = read_comment(&tf, p);
+  let field = tf.tags().iter().find_map(read_field).unwrap_or_default();
+  Ok(Stored { comment_tags: tag_suggest::tags(&comment), field_tags: split_comment_tokens(&field), comment })
+}
+
+fn same_set(a: &[String], b: &[String]) -> bool { a.iter().all(|t| b.contains(t)) && b.iter().all(|t| a.contains(t)) }
+
+fn state_of(s: &Stored) -> StorageState {
+  match (s.comment_tags.is_empty(), s.field_tags.is_empty()) {
+    (true, true) => StorageState::Neither,
+    (false, true) => StorageState::CommentOnly,
+    (true, false) => StorageState::FieldOnly,
+    _ if same_set(&s.comment_tags, &s.field_tags) => StorageState::BothConsistent,
+    _ => StorageState::BothConflicting,
+  }
+}
+
+fn audit_blocking(job: &JobHandle, folder: &str) -> Result<StorageAudit, String> {
+  job.begin_phase("scan", 0);
+  let paths = audio_files(&PathBuf::from(folder), true).map_err(|e| e.to_string())?;
+  let mut files = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("read", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    match read_stored(p) {
+      Ok(s) => files.push(StorageEntry { path: p.to_string_lossy().to_string(), state: state_of(&s), comment_tags: s.comment_tags, field_tags: s.field_tags }),
+      Err(e) => log_line(&format!("audit_tag_storage skip \"{}\": {}", p.display(), e)),
+    }
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  Ok(StorageAudit { files, scanned: paths.len(), cancelled })
+}
+
+/// Every audio file under `folder` (recursively) by where its tags are
+/// stored and whether the two locations agree.
+#[tauri::command]
+pub async fn audit_tag_storage(app: tauri::AppHandle, folder: String) -> Result<StorageAudit, String> {
+  let _span = command_span("audit_tag_storage");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "tag-storage-audit", &folder);
+    let res = audit_blocking(&job, &folder);
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Which set wins when the comment and the field disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReconcilePolicy { Union, PreferComment, PreferField }
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDiff {
+  added: Vec<String>,
+  removed: Vec<String>,
+}
+
+fn diff(old: &[String], new: &[String]) -> SetDiff {
+  SetDiff {
+    added: new.iter().filter(|t| !old.contains(t)).cloned().collect(),
+    removed: old.iter().filter(|t| !new.contains(t)).cloned().collect(),
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileResult {
+  path: String,
+  /// As found, before this call.
+  state: Option<StorageState>,
+  /// The reconciled set.
+  tags: Vec<String>,
+  comment: SetDiff,
+  field: SetDiff,
+  /// Something was (or, in a dry run, would be) written.
+  changed: bool,
+  /// Conflicting and no policy given: left as it is.
+  needs_policy: bool,
+  error: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+  #[serde(flatten)]
+  shadow: shadow::Mark,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileReport {
+  results: Vec<ReconcileResult>,
+  /// Taken before writing (see `snapshots`); None on a dry run.
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+/// The one set for a file, or `None` when it conflicts and there's no policy.
+fn reconciled(s: &Stored, state: StorageState, policy: Option<ReconcilePolicy>) -> Option<Vec<String>> {
+  match state {
+    StorageState::Neither => Some(Vec::new()),
+    StorageState::CommentOnly | StorageState::BothConsistent => Some(s.comment_tags.clone()),
+    StorageState::FieldOnly => Some(s.field_tags.clone()),
+    StorageState::BothConflicting => match policy? {
+      ReconcilePolicy::PreferComment => Some(s.comment_tags.clone()),
+      ReconcilePolicy::PreferField => Some(s.field_tags.clone()),
+      ReconcilePolicy::Union => {
+        let mut all = s.comment_tags.clone();
+        all.extend(s.field_tags.iter().filter(|t| !s.comment_tags.contains(t)).cloned());
+        Some(all)
+      }
+    },
+  }
+}
+
+fn reconcile_one(path: &str, policy: Option<ReconcilePolicy>, clear_other: bool, dry_run: bool) -> ReconcileResult {
+  let mut res = ReconcileResult {
+    path: path.to_string(), state: None, tags: Vec::new(), comment: SetDiff::default(), field: SetDiff::default(),
+    changed: false, needs_policy: false, error: None, skipped_locked: Vec::new(), shadow: shadow::Mark::default(),
+  };
+  let p = Path::new(path);
+  let s = match read_stored(p) { Ok(s) => s, Err(e) => { res.error = Some(e); return res; } };
+  let state = state_of(&s);
+  res.state = Some(state);
+  let Some(tags) = reconciled(&s, state, policy) else { res.needs_policy = true; return res };
+
+  // The other location is cleared, or kept in step where it had tags.
+  let (in_comment, in_field) = match canonical() {
+    TagStorage::Comment => (true, !clear_other && !s.field_tags.is_empty()),
+    TagStorage::Field => (!clear_other && !s.comment_tags.is_empty(), true),
+  };
+  let comment_tags = if in_comment { tags.clone() } else { Vec::new() };
+  let field_tags = if in_field { tags.clone() } else { Vec::new() };
+  res.comment = diff(&s.comment_tags, &comment_tags);
+  res.field = diff(&s.field_tags, &field_tags);
+  let new_comment = merge_tokens(&s.comment, &res.comment.added, &res.comment.removed);
+  let comment_changed = !res.comment.added.is_empty() || !res.comment.removed.is_empty();
+  // Same members in another order still gets the field rewritten as the set.
+  let field_changed = field_tags != s.field_tags;
+  res.tags = tags;
+  res.changed = comment_changed || field_changed;
+  if dry_run || !res.changed { return res; }
+
+  let field_value = join_tokens(&field_tags);
+  let outcome = edit_tags(p, |tag| {
+    if comment_changed {
+      if new_comment.is_empty() { tag.remove_key(&ItemKey::Comment); } else { tag.insert_text(ItemKey::Comment, new_comment.clone()); }
+    }
+    if let Some(k) = key(tag.tag_type()).filter(|_| field_changed) {
+      if field_value.is_empty() { tag.remove_key(&k); } else { tag.insert_unchecked(TagItem::new(k, ItemValue::Text(field_value.clone()))); }
+    }
+  });
+  match outcome {
+    Ok(o) => {
+      res.changed = !o.no_op && o.skipped_locked.is_empty();
+      (res.skipped_locked, res.shadow) = (o.skipped_locked, o.shadow);
+      if res.changed {
+        if comment_changed { audit::record_comment(path, Some(&s.comment), Some(&new_comment), audit::Source::Batch); }
+        if field_changed { audit::record(path, "tags", Some(&join_tokens(&s.field_tags)), Some(&field_value), audit::Source::Batch); }
+      }
+    }
+    Err(e) => { res.changed = false; res.error = Some(e.to_string()); }
+  }
+  res
+}
+
+/// Write each of `items` (paths) to a single tag set in the `tag_storage`
+/// location (see the header). Conflicting files need `policy`; without one
+/// they come back with `needs_policy` and untouched. With `dry_run` nothing is
+/// written and the results show what would change.
+#[tauri::command]
+pub async fn reconcile_tag_storage(
+  app: tauri::AppHandle,
+  items: Vec<String>,
+  policy: Option<ReconcilePolicy>,
+  clear_other: bool,
+  dry_run: bool,
+) -> Result<ReconcileReport, CmdError> {
+  let _span = command_span("reconcile_tag_storage");
+  tauri::async_runtime::spawn_blocking(move || {
+    let preflight = preflight::rewrite(&items);
+    let mut snapshot_id = None;
+    if !dry_run {
+      preflight::ensure(&preflight)?;
+      snapshot_id = snapshots::before_batch(&app, "reconcile_tag_storage", &items);
+    }
+    let results: Vec<ReconcileResult> = items.iter().map(|p| reconcile_one(p, policy, clear_other, dry_run)).collect();
+    let changed = results.iter().filter(|r| r.changed).count();
+    let needs_policy = results.iter().filter(|r| r.needs_policy).count();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    log_line(&format!(
+      "reconcile_tag_storage files={} changed={} needs_policy={} failed={} policy={:?} canonical={:?} clear_other={} dry_run={}",
+      results.len(), changed, needs_policy, failed, policy, canonical(), clear_other, dry_run
+    ));
+    Ok(ReconcileReport { results, snapshot_id, preflight })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,225 @@
+// Tag suggestions for a track, from similar tracks in the metadata cache:
+// the same artist, a BPM within BPM_WINDOW and the same key (as a Camelot
+// position, so "Am" matches "8A"), all three; a track missing one of them
+// has no neighbours. A neighbour's similarity is 1 at the same BPM, falling
+// off linearly to nothing just past BPM_WINDOW. Each tag the neighbours carry
+// scores the sum of their similarities and lists the closest of them as
+// evidence. Tags are comment tokens, notes and the bank marker aside; ones
+// the track already has aren't suggested. Nothing leaves the machine.
+//
+// `Index` maps artist, whole BPM and key to cache keys and is kept in step
+// with the entries by `meta_cache`, so a query looks at the candidates only,
+// however large the library.
+
+use std::{collections::{HashMap, HashSet}, fs, hash::Hash, path::{Path, PathBuf}};
+use serde::Serialize;
+
+use crate::{command_span, compilation, meta_cache::{self, CachedMeta}, name_hints::parse_tag_key, split_comment_tokens, text_fold::fold_str};
+
+const DEFAULT_LIMIT: usize = 10;
+const BPM_WINDOW: f64 = 3.0;
+/// Neighbours listed per suggestion.
+const EVIDENCE: usize = 5;
+
+/// Feature -> cache keys (canonical paths) of the entries that have it.
+#[derive(Debug, Default)]
+pub struct Index {
+  artist: HashMap<String, HashSet<String>>,
+  bpm: HashMap<u32, HashSet<String>>,
+  key: HashMap<String, HashSet<String>>,
+}
+
+struct Features {
+  /// Folded; the "Various Artists" of a compilation doesn't count.
+  artist: Option<String>,
+  bpm: Option<f64>,
+  /// Camelot, e.g. "8A".
+  key: Option<String>,
+}
+
+fn features(m: &CachedMeta) -> Features {
+  Features {
+    artist: compilation::artist_for_counts(m.artist.as_deref(), m.compilation).map(|a| fold_str(a.trim())).filter(|a| !a.is_empty()),
+    bpm: m.bpm.filter(|b| b.is_finite() && *b > 0.0),
+    key: m.key.as_deref().and_then(parse_tag_key).map(|c| c.to_string()),
+  }
+}
+
+fn bucket(bpm: f64) -> u32 { bpm.round() as u32 }
+
+fn take<K: Eq + Hash>(map: &mut HashMap<K, HashSet<String>>, k: K, path: &str) {
+  if let Some(set) = map.get_mut(&k) {
+    set.remove(path);
+    if set.is_empty() { map.remove(&k); }
+  }
+}
+
+impl Index {
+  pub fn add(&mut self, path: &str, m: &CachedMeta) {
+    let f = features(m);
+    if let Some(a) = f.artist { self.artist.entry(a).or_default().insert(path.to_string()); }
+    if let Some(b) = f.bpm { self.bpm.entry(bucket(b)).or_default().insert(path.to_string()); }
+    if let Some(k) = f.key { self.key.entry(k).or_default().insert(path.to_string()); }
+  }
+
+  pub fn remove(&mut self, path: &str, m: &CachedMeta) {
+    let f = features(m);
+    if let Some(a) = f.artist { take(&mut self.artist, a, path); }
+    if let Some(b) = f.bpm { take(&mut self.bpm, bucket(b), path); }
+    if let Some(k) = f.key { take(&mut self.key, k, path); }
+  }
+
+  /// Entries with `f`'s artist and key and a BPM bucket in the window; the
+  /// exact BPM is `similarity`'s to check.
+  fn candidates(&self, f: &Features) -> HashSet<&str> {
+    let (Some(artist), Some(bpm), Some(key)) = (&f.artist, f.bpm, &f.key) else { return HashSet::new() };
+    let (Some(by_artist), Some(by_key)) = (self.artist.get(artist), self.key.get(key)) else { return HashSet::new() };
+    let (small, large) = if by_artist.len() <= by_key.len() { (by_artist, by_key) } else { (by_key, by_artist) };
+    let buckets = bucket(bpm - BPM_WINDOW)..=bucket(bpm + BPM_WINDOW);
+    small.iter()
+      .filter(|k| large.contains(*k) && buckets.clone().any(|n| self.bpm.get(&n).is_some_and(|s| s.contains(*k))))
+      .map(String::as_str)
+      .collect()
+  }
+}
+
+/// Tags in a comment: tokens other than free-text notes and the bank marker.
+pub fn tags(comment: &str) -> Vec<String> {
+  split_comment_tokens(comment).into_iter().filter(|t| !t.starts_with("TagB:") && (t.starts_with('#') || !t.contains(char::is_whitespace))).collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Neighbor {
+  path: String,
+  similarity: f64,
+  /// Its BPM less the track's.
+  bpm_delta: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSuggestion {
+  tag: String,
+  /// Sum of the similarities of the neighbours carrying the tag.
+  score: f64,
+  /// How many neighbours carry it.
+  count: usize,
+  /// The most similar of them, at most EVIDENCE.
+  evidence: Vec<Neighbor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSuggestions {
+  path: String,
+  /// Neighbours found (see the header).
+  neighbors: usize,
+  suggestions: Vec<TagSuggestion>,
+}
+
+/// (similarity, BPM delta) of a neighbour of `want`; None for anything else.
+fn similarity(want: &Features, m: &CachedMeta) -> Option<(f64, f64)> {
+  let f = features(m);
+  if want.artist.is_none() || f.artist != want.artist || want.key.is_none() || f.key != want.key { return None; }
+  let delta = f.bpm? - want.bpm?;
+  (delta.abs() <= BPM_WINDOW).then(|| (1.0 - delta.abs() / (BPM_WINDOW + 1.0), delta))
+}
+
+fn suggest_blocking(path: &str, limit: usize, roots: &[PathBuf]) -> Result<TagSuggestions, String> {
+  let p = Path::new(path);
+  let me = meta_cache::get(p)?;
+  let own: HashSet<String> = tags(&me.comment).iter().map(|t| fold_str(t)).collect();
+  let self_key = fs::canonicalize(p).map(|c| c.to_string_lossy().to_string()).unwrap_or_else(|_| path.to_string());
+  let want = features(&me);
+
+  let mut neighbors: Vec<(Neighbor, Vec<String>)> = meta_cache::with_neighbors(|idx, entries| {
+    idx.candidates(&want)
+      .into_iter()
+      .filter(|k| *k != self_key && (roots.is_empty() || roots.iter().any(|r| Path::new(k).starts_with(r))))
+      .filter_map(|k| {
+        let m = entries.get(k)?;
+        let (similarity, bpm_delta) = similarity(&want, m)?;
+        Some((Neighbor { path: k.to_string(), similarity, bpm_delta }, tags(&m.comment)))
+      })
+      .collect()
+  });
+  neighbors.sort_by(|a, b| b.0.similarity.total_cmp(&a.0.similarity).then_with(|| a.0.path.cmp(&b.0.path)));
+
+  // Folded tag -> (first spelling seen, score, carriers); neighbours come most similar first.
+  let mut by_tag: HashMap<String, (String, f64, Vec<&Neighbor>)> = HashMap::new();
+  for (n, tags) in &neighbors {
+    for t in tags {
+      let folded = fold_str(t);
+      if own.contains(&folded) { continue; }
+      let e = by_tag.entry(folded).or_insert_with(|| (t.clone(), 0.0, Vec::new()));
+      if e.2.iter().any(|c| c.path == n.path) { continue; }
+      e.1 += n.similarity;
+      e.2.push(n);
+    }
+  }
+  let mut suggestions: Vec<TagSuggestion> = by_tag
+    .into_values()
+    .map(|(tag, score, carriers)| TagSuggestion { tag, score, count: carriers.len(), evidence: carriers.into_iter().take(EVIDENCE).cloned().collect() })
+    .collect();
+  suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.tag.cmp(&b.tag)));
+  suggestions.truncate(limit);
+  Ok(TagSuggestions { path: path.to_string(), neighbors: neighbors.len(), suggestions })
+}
+
+/// Tags to suggest for `path` from similar cached tracks (see the header),
+/// best first. `roots` keeps the neighbours to a workspace; none means the
+/// whole cache.
+#[tauri::command]
+pub async fn suggest_tags(path: String, limit: Option<usize>, roots: Option<Vec<String>>) -> Result<TagSuggestions, String> {
+  let _span = command_span("suggest_tags");
+  tauri::async_runtime::spawn_blocking(move || {
+    let roots: Vec<PathBuf> = roots.unwrap_or_default().iter().map(|r| fs::canonicalize(r).unwrap_or_else(|_| PathBuf::from(r))).collect();
+    suggest_blocking(&path, limit.unwrap_or(DEFAULT_LIMIT), &roots)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::ItemKey;
+  use crate::test_support;
+
+  fn track(dir: &Path, name: &str, artist: &str, bpm: &str, key: &str, comment: &str) -> String {
+    let p = test_support::tagged(dir, name, &[(ItemKey::TrackArtist, artist), (ItemKey::IntegerBpm, bpm), (ItemKey::InitialKey, key), (ItemKey::Comment, comment)]);
+    meta_cache::get(&p).unwrap();
+    p.to_string_lossy().to_string()
+  }
+
+  #[test]
+  fn neighbours_share_artist_bpm_and_key() {
+    let dir = test_support::scratch("tag-suggest");
+    let me = track(&dir, "me.mp3", "Solomun", "124", "Am", "");
+    track(&dir, "close.mp3", "Solomun", "124", "8A", "#deep;#peak;");
+    track(&dir, "nearer.mp3", "solomun", "126", "Am", "#deep;");
+    // One criterion off each: none of these count.
+    track(&dir, "other-artist.mp3", "Dixon", "124", "Am", "#wrong;");
+    track(&dir, "far-bpm.mp3", "Solomun", "130", "Am", "#wrong;");
+    track(&dir, "other-key.mp3", "Solomun", "124", "9A", "#wrong;");
+
+    let roots = [fs::canonicalize(&dir).unwrap()];
+    let s = suggest_blocking(&me, 10, &roots).unwrap();
+    assert_eq!(s.neighbors, 2);
+    let ranked: Vec<(&str, usize)> = s.suggestions.iter().map(|t| (t.tag.as_str(), t.count)).collect();
+    assert_eq!(ranked, [("#deep", 2), ("#peak", 1)]);
+    let deep = &s.suggestions[0];
+    assert_eq!(deep.evidence[0].similarity, 1.0);
+    assert_eq!(deep.evidence[1].bpm_delta, 2.0);
+  }
+
+  #[test]
+  fn a_track_missing_a_criterion_has_no_neighbours() {
+    let dir = test_support::scratch("tag-suggest-missing");
+    let me = track(&dir, "me.mp3", "Solomun", "124", "", "");
+    track(&dir, "close.mp3", "Solomun", "124", "8A", "#deep;");
+    let s = suggest_blocking(&me, 10, &[fs::canonicalize(&dir).unwrap()]).unwrap();
+    assert_eq!((s.neighbors, s.suggestions.len()), (0, 0));
+  }
+}
@@ -0,0 +1,55 @@
+// User write targets: `write_targets` in Settings maps an extension to the
+// tag types every comment/metadata write goes to, in order (names as
+// `inspect_tags` prints them), instead of the built-in ones of
+// `tag_types_for_ext`; e.g. wav -> ["Id3v2"] for players that ignore RIFF
+// INFO. Reads follow: the chosen types come first, then the built-in read
+// order. Each type has to be one the format can hold, and AIFF text chunks
+// are only written where the file has some. `write_plan` names the setting
+// when it applies.
+
+use std::collections::HashMap;
+use lofty::{FileType, TagType};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::comment_precedence::{self, parse_tag_type};
+
+static TARGETS: Lazy<RwLock<HashMap<String, Vec<TagType>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Normalized copy of a `write_targets` setting (as `comment_precedence`
+/// normalizes its own), or what's wrong with it.
+pub fn validate(raw: &HashMap<String, Vec<String>>) -> Result<HashMap<String, Vec<String>>, String> {
+  let out = comment_precedence::validate(raw)?;
+  for (ext, names) in &out {
+    let ft = FileType::from_ext(ext).ok_or_else(|| format!("write targets: no known format for .{}", ext))?;
+    if let Some(n) = names.iter().find(|n| !parse_tag_type(n).is_some_and(|tt| ft.supports_tag_type(tt))) {
+      return Err(format!("write targets: .{} files can't hold {}", ext, n));
+    }
+  }
+  Ok(out)
+}
+
+pub fn set(raw: &HashMap<String, Vec<String>>) {
+  let parsed = raw.iter()
+    .map(|(ext, names)| (ext.to_ascii_lowercase(), names.iter().filter_map(|n| parse_tag_type(n)).collect::<Vec<_>>()))
+    .filter(|(_, types)| !types.is_empty())
+    .collect();
+  *TARGETS.write() = parsed;
+}
+
+/// The user's write targets for `ext`, if the setting has them.
+pub fn custom(ext: &str) -> Option<Vec<TagType>> { TARGETS.read().get(ext).cloned() }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn targets_a_format_cannot_hold_are_refused() {
+    let bad = |ext: &str, tt: &str| validate(&HashMap::from([(ext.to_string(), vec![tt.to_string()])]));
+    assert!(bad("mp3", "Mp4Ilst").unwrap_err().contains("can't hold"));
+    assert!(bad("xyz", "Id3v2").is_err());
+    assert!(bad("wav", "Id3v9").is_err());
+    assert!(bad("wav", "Id3v2").is_ok());
+  }
+}
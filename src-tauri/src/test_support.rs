@@ -0,0 +1,182 @@
+// Shared pieces for the unit tests: a data dir of their own, so the stores
+// under data_dir() never touch the real one, scratch folders, and tiny but
+// valid audio files written from code. The files are silent and a fraction
+// of a second long; lofty reads them like any other.
+
+use std::{fs, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}};
+use lofty::{AudioFile, ItemKey, ItemValue, Tag, TagItem, TagType, TaggedFileExt};
+use once_cell::sync::Lazy;
+
+static ROOT: Lazy<PathBuf> = Lazy::new(|| {
+  let root = std::env::temp_dir().join(format!("audio-tagger-tests-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&root);
+  fs::create_dir_all(root.join("data")).expect("test data dir");
+  root
+});
+
+/// Held by tests that open `/audio` streams: the stream cap is process-wide.
+pub static MEDIA_SERVER: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+/// What `data_dir()` returns under test.
+pub fn data_dir() -> PathBuf { ROOT.join("data") }
+
+/// What `documents_root()` (banks, prefs) returns under test.
+pub fn documents_root() -> PathBuf {
+  let dir = ROOT.join("documents");
+  fs::create_dir_all(&dir).expect("test documents dir");
+  dir
+}
+
+/// A fresh, empty folder for one test.
+pub fn scratch(name: &str) -> PathBuf {
+  static SEQ: AtomicU64 = AtomicU64::new(0);
+  let dir = ROOT.join(format!("{}-{}", name, SEQ.fetch_add(1, Ordering::Relaxed)));
+  fs::create_dir_all(&dir).expect("scratch dir");
+  // Canonical, like the paths the stores key by.
+  fs::canonicalize(&dir).expect("canonical scratch dir")
+}
+
+/// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo: 417-byte frames.
+pub fn mpeg_frames(n: usize) -> Vec<u8> {
+  let mut frame = vec![0u8; 417];
+  frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+  frame.repeat(n)
+}
+
+/// Silent 16-bit stereo samples.
+fn pcm(frames: u32) -> Vec<u8> { vec![0u8; frames as usize * 4] }
+
+fn wav_bytes() -> Vec<u8> {
+  let data = pcm(4410);
+  let mut out = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+  out.extend_from_slice(&16u32.to_le_bytes());
+  out.extend_from_slice(&1u16.to_le_bytes());
+  out.extend_from_slice(&2u16.to_le_bytes());
+  out.extend_from_slice(&44100u32.to_le_bytes());
+  out.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+  out.extend_from_slice(&4u16.to_le_bytes());
+  out.extend_from_slice(&16u16.to_le_bytes());
+  out.extend_from_slice(b"data");
+  out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+  out.extend_from_slice(&data);
+  let size = (out.len() - 8) as u32;
+  out[4..8].copy_from_slice(&size.to_le_bytes());
+  out
+}
+
+fn aiff_bytes() -> Vec<u8> {
+  let frames = 4410u32;
+  let mut comm = Vec::new();
+  comm.extend_from_slice(&2u16.to_be_bytes());
+  comm.extend_from_slice(&frames.to_be_bytes());
+  comm.extend_from_slice(&16u16.to_be_bytes());
+  // 44100 as an 80-bit extended float.
+  comm.extend_from_slice(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+  let mut ssnd = vec![0u8; 8];
+  ssnd.extend_from_slice(&pcm(frames));
+  let mut out = b"FORM\0\0\0\0AIFF".to_vec();
+  for (id, data) in [(b"COMM", comm), (b"SSND", ssnd)] {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&data);
+  }
+  let size = (out.len() - 8) as u32;
+  out[4..8].copy_from_slice(&size.to_be_bytes());
+  out
+}
+
+/// FLAC with a STREAMINFO block (44.1 kHz, stereo, 16-bit, 4410 samples),
+/// padding and no audio frames.
+fn flac_bytes() -> Vec<u8> {
+  let mut info = Vec::new();
+  info.extend_from_slice(&4096u16.to_be_bytes());
+  info.extend_from_slice(&4096u16.to_be_bytes());
+  info.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+  // 20 bits rate, 3 bits channels - 1, 5 bits bps - 1, 36 bits samples.
+  let packed: u64 = (44100u64 << 44) | (1 << 41) | (15 << 36) | 4410;
+  info.extend_from_slice(&packed.to_be_bytes());
+  info.extend_from_slice(&[0u8; 16]);
+  let mut out = b"fLaC".to_vec();
+  out.push(0);
+  out.extend_from_slice(&(info.len() as u32).to_be_bytes()[1..]);
+  out.extend_from_slice(&info);
+  out.extend_from_slice(&[0x81, 0, 4, 0]);
+  out.extend_from_slice(&[0u8; 1024]);
+  out
+}
+
+/// A silent file named `name` in `dir`; the extension picks the format
+/// (mp3, wav, aif/aiff, flac).
+pub fn audio(dir: &Path, name: &str) -> PathBuf {
+  let p = dir.join(name);
+  let bytes = match p.extension().and_then(|e| e.to_str()).unwrap_or("") {
+    "mp3" => mpeg_frames(40),
+    "wav" => wav_bytes(),
+    "aif" | "aiff" => aiff_bytes(),
+    "flac" => flac_bytes(),
+    ext => panic!("no fixture for .{}", ext),
+  };
+  fs::write(&p, bytes).expect("write fixture");
+  p
+}
+
+/// `audio` with `items` set in its primary tag kind (ID3v2, Vorbis comments).
+pub fn tagged(dir: &Path, name: &str, items: &[(ItemKey, &str)]) -> PathBuf {
+  let p = audio(dir, name);
+  let mut tf = lofty::read_from_path(&p).expect("read fixture");
+  let tt = tf.primary_tag_type();
+  let mut tag = Tag::new(tt);
+  for (k, v) in items { tag.insert_text(k.clone(), v.to_string()); }
+  tf.insert_tag(tag);
+  tf.save_to_path(&p).expect("tag fixture");
+  p
+}
+
+/// Give `p` a tag of kind `tt` holding `items` (replacing one it has), for
+/// files with several tags, e.g. ID3v2 + APE on an MP3.
+pub fn add_tag(p: &Path, tt: TagType, items: &[(ItemKey, &str)]) {
+  let mut tf = lofty::read_from_path(p).expect("read fixture");
+  let mut tag = Tag::new(tt);
+  for (k, v) in items { tag.insert_unchecked(TagItem::new(k.clone(), ItemValue::Text(v.to_string()))); }
+  tf.insert_tag(tag);
+  tf.save_to_path(p).expect("tag fixture");
+}
+
+/// Put an ID3v2.3 tag of text `frames` ((id, value), Latin-1) in front of
+/// `p`, an untagged MP3; lofty itself only writes v2.4.
+pub fn id3v23(p: &Path, frames: &[(&str, &str)]) {
+  let mut body = Vec::new();
+  for (id, value) in frames {
+    body.extend_from_slice(id.as_bytes());
+    body.extend_from_slice(&(value.len() as u32 + 1).to_be_bytes());
+    body.extend_from_slice(&[0, 0, 0]);
+    body.extend_from_slice(value.as_bytes());
+  }
+  let size = body.len() as u32;
+  let mut out = b"ID3\x03\0\0".to_vec();
+  out.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7F) as u8));
+  out.extend_from_slice(&body);
+  out.extend_from_slice(&fs::read(p).expect("read fixture"));
+  fs::write(p, out).expect("write fixture");
+}
+
+/// The text of `key` in `p`'s tag of kind `tt`.
+pub fn text(p: &Path, tt: TagType, key: &ItemKey) -> Option<String> {
+  let tf = lofty::read_from_path(p).expect("read back");
+  tf.tag(tt).and_then(|t| t.get_string(key)).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fixtures_read_and_tag() {
+    let dir = scratch("fixtures");
+    for name in ["a.mp3", "a.wav", "a.aiff", "a.flac"] {
+      let p = tagged(&dir, name, &[(ItemKey::TrackTitle, "Title")]);
+      let tf = lofty::read_from_path(&p).unwrap_or_else(|e| panic!("{}: {}", name, e));
+      assert_eq!(text(&p, tf.primary_tag_type(), &ItemKey::TrackTitle).as_deref(), Some("Title"), "{}", name);
+    }
+  }
+}
@@ -0,0 +1,482 @@
+// Bulk cleanup of title/artist text ("TRACK NAME (ORIGINAL MIX)",
+// "  artist- title feat.X ", "... [www.site.com]"). Each rule is a pure
+// string transform so the engine can run in dry-run over a whole folder.
+
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use lofty::Accessor;
+
+use crate::{
+  error::CmdError, field_limits::FieldLimitHit, field_locks::{self, LockedField}, inspect::{plan_for_path, WritePlan}, log_line, preferred_tag, preflight::{self, Preflight}, read_tagged,
+  retry_queue, snapshots, MetaPatch,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BracketStyle { Round, Square }
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CleanupRules {
+  collapse_whitespace: bool,
+  title_case: bool,
+  /// Lowercased in title case unless first/last word or opening a phrase.
+  small_words: Vec<String>,
+  /// Always written upper case ("DJ", "VIP").
+  keep_upper: Vec<String>,
+  /// Target form for feat./ft./featuring, e.g. "feat.". `None` leaves them alone.
+  feat_form: Option<String>,
+  /// Bracket style for mix names like "(Original Mix)".
+  mix_brackets: Option<BracketStyle>,
+  /// Drop trailing "[www.site.com]"-style junk.
+  strip_junk: bool,
+}
+
+impl Default for CleanupRules {
+  fn default() -> Self {
+    let words = |l: &[&str]| l.iter().map(|s| s.to_string()).collect();
+    Self {
+      collapse_whitespace: true,
+      title_case: false,
+      small_words: words(&["a", "an", "and", "as", "at", "but", "by", "for", "in", "of", "on", "or", "the", "to", "vs", "vs.", "with"]),
+      keep_upper: words(&["DJ", "MC", "VIP", "UK", "USA", "EP", "LP", "II", "III", "IV"]),
+      feat_form: None,
+      mix_brackets: None,
+      strip_junk: true,
+    }
+  }
+}
+
+const FEAT_WORDS: &[&str] = &["featuring", "feat.", "feat", "ft.", "ft"];
+const MIX_WORDS: &[&str] = &["mix", "remix", "edit", "dub", "version", "rework", "bootleg", "vip", "remaster", "remastered", "instrumental"];
+const JUNK_TLDS: &[&str] = &["com", "net", "org", "info", "biz", "ru", "io", "co", "to", "cc", "me", "fm", "club", "xyz", "us", "eu", "de"];
+
+pub fn clean(s: &str, rules: &CleanupRules) -> String {
+  let mut out = s.to_string();
+  if rules.strip_junk { out = strip_junk(&out); }
+  if rules.collapse_whitespace { out = collapse_whitespace(&out); }
+  if let Some(form) = &rules.feat_form { out = normalize_feat(&out, form); }
+  if let Some(style) = rules.mix_brackets { out = normalize_mix_brackets(&out, style); }
+  if rules.title_case { out = title_case(&out, rules); }
+  if rules.collapse_whitespace { out = collapse_whitespace(&out); }
+  out
+}
+
+/// Trim, collapse runs of whitespace, and space out dangling dashes
+/// ("artist- title" -> "artist - title") without touching "hip-hop".
+pub fn collapse_whitespace(s: &str) -> String {
+  let words: Vec<&str> = s.split_whitespace().collect();
+  let mut out: Vec<&str> = Vec::with_capacity(words.len());
+  for (i, w) in words.iter().enumerate() {
+    let last = i + 1 == words.len();
+    if w.len() > 1 && w.ends_with('-') && !w.ends_with("--") && !last {
+      out.push(&w[..w.len() - 1]);
+      out.push("-");
+    } else if w.len() > 1 && w.starts_with('-') && !w.starts_with("--") && i > 0 {
+      out.push("-");
+      out.push(&w[1..]);
+    } else {
+      out.push(w);
+    }
+  }
+  out.join(" ")
+}
+
+fn looks_like_url(s: &str) -> bool {
+  let l = s.trim().to_lowercase();
+  if l.is_empty() || l.contains(' ') { return false; }
+  if l.contains("www.") || l.contains("://") { return true; }
+  let host = l.split('/').next().unwrap_or("");
+  match host.rsplit_once('.') {
+    Some((name, tld)) => !name.is_empty() && JUNK_TLDS.contains(&tld) && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '.'),
+    None => false,
+  }
+}
+
+/// Remove trailing URL-ish groups ("[www.site.com]", "(site.ru)", " - site.net").
+pub fn strip_junk(s: &str) -> String {
+  let mut out = s.trim_end().to_string();
+  loop {
+    let before = out.clone();
+    let closing = out.chars().last();
+    let opening = match closing { Some(']') => Some('['), Some(')') => Some('('), Some('}') => Some('{'), _ => None };
+    if let Some(open) = opening {
+      if let Some(ix) = out.rfind(open) {
+        let inner = &out[ix + 1..out.len() - 1];
+        if looks_like_url(inner) { out.truncate(ix); }
+      }
+    } else if let Some((head, tail)) = out.rsplit_once(char::is_whitespace) {
+      if looks_like_url(tail) { out = head.to_string(); }
+    } else if looks_like_url(&out) {
+      // whole value is junk; keep it rather than blanking the field
+      break;
+    }
+    out = out.trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '_' | '|' | '~' | ',')).to_string();
+    if out == before || out.is_empty() { break; }
+  }
+  if out.is_empty() { s.trim().to_string() } else { out }
+}
+
+fn is_boundary_before(s: &str, ix: usize) -> bool {
+  s[..ix].chars().last().is_none_or(|c| c.is_whitespace() || c == '(' || c == '[')
+}
+
+/// Rewrite feat./ft./featuring (any case, with or without a following space)
+/// to `form`, always followed by a single space.
+pub fn normalize_feat(s: &str, form: &str) -> String {
+  let lower = s.to_lowercase();
+  // to_lowercase can change byte lengths for some scripts; bail out rather than mis-slice
+  if lower.len() != s.len() { return s.to_string(); }
+  let mut out = String::with_capacity(s.len() + 8);
+  let mut i = 0;
+  while i < s.len() {
+    if is_boundary_before(s, i) {
+      let rest = &lower[i..];
+      let hit = FEAT_WORDS.iter().find(|w| {
+        if !rest.starts_with(*w) { return false; }
+        let next = rest[w.len()..].chars().next();
+        // "feat.X" is fine when dotted; bare "ft"/"feat" must end the word
+        w.ends_with('.') || next.is_none_or(|c| c.is_whitespace())
+      });
+      if let Some(w) = hit {
+        out.push_str(form);
+        i += w.len();
+        let skipped = s[i..].len() - s[i..].trim_start().len();
+        i += skipped;
+        if i < s.len() { out.push(' '); }
+        continue;
+      }
+    }
+    let ch = s[i..].chars().next().unwrap();
+    out.push(ch);
+    i += ch.len_utf8();
+  }
+  out
+}
+
+/// Switch "[Original Mix]" <-> "(Original Mix)" for bracket groups that name a mix.
+pub fn normalize_mix_brackets(s: &str, style: BracketStyle) -> String {
+  let (from_open, from_close, to_open, to_close) = match style {
+    BracketStyle::Round => ('[', ']', '(', ')'),
+    BracketStyle::Square => ('(', ')', '[', ']'),
+  };
+  let mut out = String::with_capacity(s.len());
+  let mut rest = s;
+  while let Some(start) = rest.find(from_open) {
+    let Some(len) = rest[start + 1..].find(from_close) else { break };
+    let inner = &rest[start + 1..start + 1 + len];
+    out.push_str(&rest[..start]);
+    let last_word = inner.split_whitespace().last().unwrap_or("").to_lowercase();
+    if MIX_WORDS.contains(&last_word.as_str()) {
+      out.push(to_open);
+      out.push_str(inner);
+      out.push(to_close);
+    } else {
+      out.push(from_open);
+      out.push_str(inner);
+      out.push(from_close);
+    }
+    rest = &rest[start + len + 2..];
+  }
+  out.push_str(rest);
+  out
+}
+
+fn capitalize_segment(seg: &str) -> String {
+  let mut out = String::with_capacity(seg.len());
+  let mut done = false;
+  for ch in seg.chars() {
+    if !done && ch.is_alphabetic() {
+      out.extend(ch.to_uppercase());
+      done = true;
+    } else {
+      out.extend(ch.to_lowercase());
+    }
+  }
+  out
+}
+
+pub fn title_case(s: &str, rules: &CleanupRules) -> String {
+  let words: Vec<&str> = s.split(' ').collect();
+  let feat = rules.feat_form.as_deref().map(|f| f.to_lowercase());
+  let mut phrase_start = true;
+  let mut out: Vec<String> = Vec::with_capacity(words.len());
+  for (i, w) in words.iter().enumerate() {
+    let lead = w.len() - w.trim_start_matches(|c: char| !c.is_alphanumeric()).len();
+    let core_end = w.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '.').len().max(lead);
+    let (pre, core, post) = (&w[..lead], &w[lead..core_end], &w[core_end..]);
+    let last = i + 1 == words.len();
+    let opens = phrase_start || pre.contains('(') || pre.contains('[');
+
+    let lower = core.to_lowercase();
+    let has_lower = core.chars().any(|c| c.is_lowercase());
+    let has_upper = core.chars().any(|c| c.is_uppercase());
+    let fixed = if core.is_empty() {
+      core.to_string()
+    } else if rules.keep_upper.iter().any(|k| k.eq_ignore_ascii_case(core)) {
+      core.to_uppercase()
+    } else if FEAT_WORDS.contains(&lower.as_str()) || feat.as_deref() == Some(lower.as_str()) {
+      lower
+    } else if core.chars().any(|c| c.is_ascii_digit()) || (has_lower && has_upper) {
+      // "8A", "deadmau5", "McCoy", "iPhone": someone spelled these on purpose
+      core.to_string()
+    } else if !opens && !last && rules.small_words.iter().any(|sw| sw.eq_ignore_ascii_case(core)) {
+      lower
+    } else {
+      core.split('-').map(capitalize_segment).collect::<Vec<_>>().join("-")
+    };
+    out.push(format!("{}{}{}", pre, fixed, post));
+    phrase_start = *w == "-" || post.ends_with(':');
+  }
+  out.join(" ")
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextFields {
+  title: Option<String>,
+  artist: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupResult {
+  path: String,
+  before: TextFields,
+  after: TextFields,
+  changed: bool,
+  error: Option<String>,
+  /// Dry runs only: the tag types the write would go to.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  plan: Option<WritePlan>,
+  /// Changed fields left alone because they're locked (see `field_locks`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+  /// Values cut or left out for a tag type's length limit (see `field_limits`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<FieldLimitHit>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupReport {
+  results: Vec<CleanupResult>,
+  /// Taken before writing (see `snapshots`); None on a dry run.
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+fn read_text_fields(p: &Path) -> Result<TextFields, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let tag = preferred_tag(&tf, p);
+  Ok(TextFields {
+    title: tag.and_then(|t| t.title().map(|s| s.to_string())),
+    artist: tag.and_then(|t| t.artist().map(|s| s.to_string())),
+  })
+}
+
+fn cleanup_one(path: &str, rules: &CleanupRules, dry_run: bool) -> CleanupResult {
+  let p = Path::new(path);
+  let mut res = CleanupResult {
+    path: path.to_string(), before: TextFields::default(), after: TextFields::default(), changed: false, error: None, plan: None, skipped_locked: Vec::new(), limited: Vec::new(),
+  };
+  let before = match read_text_fields(p) {
+    Ok(b) => b,
+    Err(e) => { res.error = Some(e); return res; }
+  };
+  let after = TextFields {
+    title: before.title.as_deref().map(|s| clean(s, rules)),
+    artist: before.artist.as_deref().map(|s| clean(s, rules)),
+  };
+  res.changed = after != before;
+  if res.changed && dry_run {
+    res.plan = plan_for_path(p).ok();
+    let changed: Vec<LockedField> = [(LockedField::Title, after.title != before.title), (LockedField::Artist, after.artist != before.artist)]
+      .into_iter().filter_map(|(f, c)| c.then_some(f)).collect();
+    res.skipped_locked = field_locks::hits(p, &changed);
+  }
+  if res.changed && !dry_run {
+    let patch = MetaPatch {
+      title: after.title.clone().filter(|_| after.title != before.title),
+      artist: after.artist.clone().filter(|_| after.artist != before.artist),
+      ..Default::default()
+    };
+    match retry_queue::apply_patch(p, &patch) {
+      Ok(o) => (res.skipped_locked, res.limited) = (o.skipped_locked, o.limited),
+      Err(e) => res.error = Some(e),
+    }
+  }
+  res.before = before;
+  res.after = after;
+  res
+}
+
+#[tauri::command]
+pub fn cleanup_text_fields(app: tauri::AppHandle, paths: Vec<String>, rules: CleanupRules, dry_run: bool) -> Result<CleanupReport, CmdError> {
+  let preflight = preflight::rewrite(&paths);
+  let mut snapshot_id = None;
+  if !dry_run {
+    preflight::ensure(&preflight)?;
+    snapshot_id = snapshots::before_batch(&app, "cleanup_text_fields", &paths);
+  }
+  let results: Vec<CleanupResult> = paths.iter().map(|p| cleanup_one(p, &rules, dry_run)).collect();
+  if !dry_run {
+    let changed = results.iter().filter(|r| r.changed && r.error.is_none()).count();
+    log_line(&format!("cleanup_text_fields files={} changed={}", results.len(), changed));
+  }
+  Ok(CleanupReport { results, snapshot_id, preflight })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::{ItemKey, TagType};
+  use crate::test_support;
+
+  fn all_rules() -> CleanupRules {
+    CleanupRules { title_case: true, feat_form: Some("feat.".into()), mix_brackets: Some(BracketStyle::Round), ..Default::default() }
+  }
+
+  #[test]
+  fn whitespace_collapses_and_dangling_dashes_get_spaced() {
+    for (input, want) in [
+      ("  artist-  title  ", "artist - title"),
+      ("artist -title", "artist - title"),
+      ("a\t\tb\n c", "a b c"),
+      ("hip-hop", "hip-hop"),
+      ("drum -- bass", "drum -- bass"),
+      ("ends with-", "ends with-"),
+      ("-starts", "-starts"),
+      ("", ""),
+    ] {
+      assert_eq!(collapse_whitespace(input), want, "{:?}", input);
+    }
+  }
+
+  #[test]
+  fn trailing_url_junk_is_stripped_and_nothing_else() {
+    for (input, want) in [
+      ("Track [www.site.com]", "Track"),
+      ("Track (site.ru)", "Track"),
+      ("Track - promo-site.net", "Track"),
+      ("Track {http://x.io/a}", "Track"),
+      ("Track [Original Mix] [www.x.com] (y.org)", "Track [Original Mix]"),
+      ("Track (Original Mix)", "Track (Original Mix)"),
+      ("Track (feat. Someone)", "Track (feat. Someone)"),
+      ("Dot.com Song", "Dot.com Song"),
+      ("Song Part 2.1", "Song Part 2.1"),
+      // The whole value looks like a URL: kept rather than blanked.
+      ("www.site.com", "www.site.com"),
+      ("[www.site.com]", "[www.site.com]"),
+    ] {
+      assert_eq!(strip_junk(input), want, "{:?}", input);
+    }
+  }
+
+  #[test]
+  fn feat_spellings_become_the_configured_form() {
+    for (input, want) in [
+      ("Artist ft.X", "Artist feat. X"),
+      ("Artist Featuring  Y", "Artist feat. Y"),
+      ("Artist FT Y", "Artist feat. Y"),
+      ("Artist feat.   Z", "Artist feat. Z"),
+      ("Song (ft. Z)", "Song (feat. Z)"),
+      ("Artist feat", "Artist feat."),
+      // Not a feat word: inside a word, or only starting with one.
+      ("Soft Cell", "Soft Cell"),
+      ("Aftermath", "Aftermath"),
+      ("Feather", "Feather"),
+      ("Ftp Song", "Ftp Song"),
+    ] {
+      assert_eq!(normalize_feat(input, "feat."), want, "{:?}", input);
+    }
+    assert_eq!(normalize_feat("A ft. B", "ft."), "A ft. B");
+    assert_eq!(normalize_feat("A feat. B", "&"), "A & B");
+    // Lowercasing changes the byte length here, so the value is left alone.
+    assert_eq!(normalize_feat("İstanbul ft. X", "feat."), "İstanbul ft. X");
+  }
+
+  #[test]
+  fn only_mix_names_switch_brackets() {
+    assert_eq!(normalize_mix_brackets("Song [Original Mix]", BracketStyle::Round), "Song (Original Mix)");
+    assert_eq!(normalize_mix_brackets("Song [Live] [Dub]", BracketStyle::Round), "Song [Live] (Dub)");
+    assert_eq!(normalize_mix_brackets("Song (Extended Mix) (feat. X)", BracketStyle::Square), "Song [Extended Mix] (feat. X)");
+    assert_eq!(normalize_mix_brackets("Song (Acoustic)", BracketStyle::Square), "Song (Acoustic)");
+    assert_eq!(normalize_mix_brackets("Song (2019 Remastered)", BracketStyle::Square), "Song [2019 Remastered]");
+    assert_eq!(normalize_mix_brackets("Song [Mix", BracketStyle::Round), "Song [Mix");
+    assert_eq!(normalize_mix_brackets("Song (Club Mix)", BracketStyle::Round), "Song (Club Mix)");
+  }
+
+  #[test]
+  fn title_case_keeps_small_words_and_deliberate_spellings() {
+    let rules = all_rules();
+    for (input, want) in [
+      ("TRACK NAME (ORIGINAL MIX)", "Track Name (Original Mix)"),
+      ("the end of the world", "The End of the World"),
+      ("return to the sky", "Return to the Sky"),
+      ("dj snake - in the end", "DJ Snake - In the End"),
+      ("love (in the club)", "Love (In the Club)"),
+      ("part one: the return", "Part One: The Return"),
+      ("what it's for", "What It's For"),
+      ("don't stop", "Don't Stop"),
+      ("hip-hop anthem", "Hip-Hop Anthem"),
+      ("deadmau5 - strobe", "deadmau5 - Strobe"),
+      ("McCoy in 8A", "McCoy in 8A"),
+      ("ARTIST FEAT. GUEST", "Artist feat. Guest"),
+      ("vip mix by mc x", "VIP Mix by MC X"),
+      ("élan vital", "Élan Vital"),
+    ] {
+      assert_eq!(title_case(input, &rules), want, "{:?}", input);
+    }
+  }
+
+  #[test]
+  fn the_whole_pipeline_cleans_promo_titles() {
+    let rules = all_rules();
+    assert_eq!(clean("  TRACK NAME   [ORIGINAL MIX] [www.promo.com] ", &rules), "Track Name (Original Mix)");
+    assert_eq!(clean("  artist- title ft.X ", &rules), "Artist - Title feat. X");
+    // Defaults only tidy whitespace and junk, never the casing.
+    let defaults = CleanupRules::default();
+    assert_eq!(clean("  artist- title feat.X ", &defaults), "artist - title feat.X");
+    assert_eq!(clean("TRACK [www.x.com]", &defaults), "TRACK");
+  }
+
+  #[test]
+  fn cleaning_is_idempotent_and_leaves_clean_values_alone() {
+    let rules = all_rules();
+    for input in [
+      "  TRACK NAME   [ORIGINAL MIX] [www.promo.com] ",
+      "  artist- title feat.X ",
+      "dj snake - in the end (ft. lil jon) [Club Mix]",
+      "the a-team of the 8A - bass (vs. the world)",
+      "Beyoncé – Halo",
+      "",
+    ] {
+      let once = clean(input, &rules);
+      assert_eq!(clean(&once, &rules), once, "{:?}", input);
+    }
+    for tidy in ["Track Name (Original Mix)", "Beyoncé – Halo", "DJ Snake feat. Lil Jon"] {
+      assert_eq!(clean(tidy, &rules), tidy);
+    }
+  }
+
+  #[test]
+  fn a_dry_run_reports_without_writing() {
+    let dir = test_support::scratch("text-cleanup");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::TrackTitle, "TRACK NAME [www.x.com]"), (ItemKey::TrackArtist, "  dj  snake ")]);
+    let path = p.to_string_lossy().to_string();
+    let rules = all_rules();
+
+    let preview = cleanup_one(&path, &rules, true);
+    assert!(preview.changed && preview.error.is_none());
+    assert_eq!(preview.after, TextFields { title: Some("Track Name".into()), artist: Some("DJ Snake".into()) });
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackTitle).as_deref(), Some("TRACK NAME [www.x.com]"));
+
+    let done = cleanup_one(&path, &rules, false);
+    assert_eq!((done.changed, done.error, done.after), (true, None, preview.after));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackTitle).as_deref(), Some("Track Name"));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackArtist).as_deref(), Some("DJ Snake"));
+    assert!(!cleanup_one(&path, &rules, false).changed);
+  }
+}
@@ -0,0 +1,204 @@
+// Text folding shared by every matching path: autocomplete, full-text
+// search, tag suggestions and the already-owned and compilation checks all
+// compare `fold_str` forms, and file-name sorting uses the same letters.
+// Folding is Unicode lowercasing plus the case-folding cases lowercasing
+// misses (ß and ẞ to "ss", final sigma, long s, İ to a plain "i"). Unless
+// `accent_folding` is off in Settings, accents go too: Latin letters with
+// diacritics become their base letters and combining marks are dropped, so
+// decomposed text (macOS file names, some taggers) folds like precomposed.
+// With it off, "Señorita" and "Senorita" stay apart, but a base letter
+// followed by its combining mark is composed first (NFC, for the letters in
+// COMPOSE), so a decomposed "Señorita" still matches a precomposed one; the
+// dot of a decomposed "İ" is still dropped, since "i" carries it anyway.
+//
+// There's no Unicode normalization crate in this build, hence the tables;
+// they cover Latin-script letters, which is what music metadata mostly carries.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub static ACCENTS: AtomicBool = AtomicBool::new(true);
+
+/// Combining mark -> (base, precomposed) pairs, Latin-1 and Latin Extended-A.
+const COMPOSE: &[(char, &str)] = &[
+  ('\u{0300}', "AÀEÈIÌOÒUÙaàeèiìoòuù"),
+  ('\u{0301}', "AÁEÉIÍOÓUÚYÝaáeéiíoóuúyýCĆcćLĹlĺNŃnńRŔrŕSŚsśZŹzź"),
+  ('\u{0302}', "AÂEÊIÎOÔUÛaâeêiîoôuûCĈcĉGĜgĝHĤhĥJĴjĵSŜsŝWŴwŵYŶyŷ"),
+  ('\u{0303}', "AÃNÑOÕaãnñoõIĨiĩUŨuũ"),
+  ('\u{0304}', "AĀaāEĒeēIĪiīOŌoōUŪuū"),
+  ('\u{0306}', "AĂaăEĔeĕGĞgğIĬiĭOŎoŏUŬuŭ"),
+  ('\u{0307}', "CĊcċEĖeėGĠgġIİZŻzż"),
+  ('\u{0308}', "AÄEËIÏOÖUÜaäeëiïoöuüyÿYŸ"),
+  ('\u{030a}', "AÅaåUŮuů"),
+  ('\u{030b}', "OŐoőUŰuű"),
+  ('\u{030c}', "CČcčDĎdďEĚeěLĽlľNŇnňRŘrřSŠsšTŤtťZŽzž"),
+  ('\u{0327}', "CÇcçGĢgģKĶkķLĻlļNŅnņRŖrŗSŞsşTŢtţ"),
+  ('\u{0328}', "AĄaąEĘeęIĮiįUŲuų"),
+];
+
+fn compose(base: char, mark: char) -> Option<char> {
+  let (_, pairs) = COMPOSE.iter().find(|(m, _)| *m == mark)?;
+  let mut it = pairs.chars();
+  while let (Some(b), Some(c)) = (it.next(), it.next()) {
+    if b == base { return Some(c); }
+  }
+  None
+}
+
+/// The chars of `s` with their byte offsets, base + mark composed where
+/// COMPOSE has the pair (the composed char takes the base's offset).
+fn composed(s: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+  let mut it = s.char_indices().peekable();
+  std::iter::from_fn(move || {
+    let (i, mut c) = it.next()?;
+    if let Some(k) = it.peek().and_then(|&(_, m)| compose(c, m)) {
+      c = k;
+      it.next();
+    }
+    Some((i, c))
+  })
+}
+
+/// Latin accents to their base letters; everything else passes through as-is.
+fn base_letter(c: char) -> Option<&'static str> {
+  Some(match c {
+    'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+    'æ' => "ae",
+    'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+    'ď' | 'đ' | 'ð' => "d",
+    'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+    'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+    'ĥ' | 'ħ' => "h",
+    'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+    'ĵ' => "j",
+    'ķ' => "k",
+    'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+    'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+    'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+    'œ' => "oe",
+    'ŕ' | 'ŗ' | 'ř' => "r",
+    'ś' | 'ŝ' | 'ş' | 'š' => "s",
+    'ţ' | 'ť' | 'ŧ' => "t",
+    'þ' => "th",
+    'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+    'ŵ' => "w",
+    'ý' | 'ÿ' | 'ŷ' => "y",
+    'ź' | 'ż' | 'ž' => "z",
+    _ => return None,
+  })
+}
+
+fn combining(c: char) -> bool {
+  matches!(c, '\u{0300}'..='\u{036f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}' | '\u{fe20}'..='\u{fe2f}')
+}
+
+/// Append the folded form of `c` to `out`.
+pub fn push_folded(c: char, accents: bool, out: &mut String) {
+  match c {
+    'ß' | 'ẞ' => return out.push_str("ss"),
+    'İ' => return out.push('i'),
+    'ς' => return out.push('σ'),
+    'ſ' => return out.push('s'),
+    _ => {}
+  }
+  for lc in c.to_lowercase() {
+    if combining(lc) {
+      if accents || lc == '\u{0307}' && out.ends_with(['i', 'j']) { continue; }
+      out.push(lc);
+    } else {
+      match base_letter(lc).filter(|_| accents) { Some(f) => out.push_str(f), None => out.push(lc) }
+    }
+  }
+}
+
+/// Folded form for matching ("Beyoncé" -> "beyonce"), per `accent_folding`.
+pub fn fold_str(s: &str) -> String { fold_with(s, ACCENTS.load(Ordering::Relaxed)) }
+
+fn fold_with(s: &str, accents: bool) -> String {
+  let mut out = String::with_capacity(s.len());
+  for (_, c) in composed(s) { push_folded(c, accents, &mut out); }
+  out
+}
+
+/// `fold_str`, with the byte offset in `s` of every byte of the result, for
+/// mapping matches back onto the original text.
+pub fn fold_mapped(s: &str) -> (String, Vec<usize>) {
+  let accents = ACCENTS.load(Ordering::Relaxed);
+  let (mut out, mut map) = (String::with_capacity(s.len()), Vec::with_capacity(s.len()));
+  for (i, c) in composed(s) {
+    push_folded(c, accents, &mut out);
+    map.resize(out.len(), i);
+  }
+  (out, map)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Every precomposed letter in COMPOSE, with its base and mark.
+  fn letters() -> Vec<(char, char, char)> {
+    COMPOSE.iter().flat_map(|(mark, pairs)| {
+      let chars: Vec<char> = pairs.chars().collect();
+      chars.chunks(2).map(|p| (p[0], *mark, p[1])).collect::<Vec<_>>()
+    }).collect()
+  }
+
+  #[test]
+  fn decomposed_folds_like_precomposed() {
+    for (base, mark, pre) in letters() {
+      for accents in [true, false] {
+        let nfd = format!("x{}{}y", base, mark);
+        assert_eq!(fold_with(&nfd, accents), fold_with(&format!("x{}y", pre), accents), "{:?} accents={}", pre, accents);
+      }
+    }
+  }
+
+  #[test]
+  fn accent_folding_reaches_the_base_letter() {
+    for (base, _, pre) in letters() {
+      let plain = fold_with(&base.to_string(), true);
+      assert_eq!(fold_with(&pre.to_string(), true), plain, "{:?}", pre);
+      // With it off the accent stays, whatever form it came in.
+      if pre != 'İ' { assert_ne!(fold_with(&pre.to_string(), false), plain, "{:?}", pre); }
+    }
+    assert_eq!(fold_with("Señorita", false), fold_with("Sen\u{303}orita", false));
+    assert_ne!(fold_with("Señorita", false), fold_with("Senorita", false));
+  }
+
+  #[test]
+  fn case_folding_specials() {
+    for accents in [true, false] {
+      assert_eq!(fold_with("Straße", accents), "strasse");
+      assert_eq!(fold_with("STRAẞE", accents), "strasse");
+      assert_eq!(fold_with("İstanbul", accents), "istanbul");
+      assert_eq!(fold_with("I\u{307}stanbul", accents), "istanbul");
+      // Dotless ı is its own letter unless accents are folded.
+      assert_eq!(fold_with("ıstanbul", accents) == "istanbul", accents);
+      assert_eq!(fold_with("ΟΔΟΣ", accents), fold_with("οδος", accents));
+    }
+  }
+
+  #[test]
+  fn folding_is_idempotent() {
+    let samples = ["Beyoncé", "Ben Böhmer", "Sigur Ro\u{301}s", "Ǆemal", "Straße", "İZMİR", "ﬁre", "Mötley Crüe", "Ĳsselmeer", "Œuvre"];
+    for s in samples {
+      for accents in [true, false] {
+        let once = fold_with(s, accents);
+        assert_eq!(fold_with(&once, accents), once, "{} accents={}", s, accents);
+      }
+    }
+  }
+
+  #[test]
+  fn mapped_offsets_point_into_the_original() {
+    for s in ["Sen\u{303}orita", "Straße", "Beyoncé feat. İlkay"] {
+      let (out, map) = fold_mapped(s);
+      assert_eq!(map.len(), out.len());
+      assert!(map.iter().all(|&i| s.is_char_boundary(i) && i < s.len()), "{}", s);
+      assert!(map.windows(2).all(|w| w[0] <= w[1]), "{}", s);
+    }
+    let (out, map) = fold_mapped("Sen\u{303}orita");
+    let at = out.find('o').unwrap();
+    assert_eq!(&"Sen\u{303}orita"[map[at]..], "orita");
+  }
+}
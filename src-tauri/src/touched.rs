@@ -0,0 +1,263 @@
+// Which files this app has written, for the "touched" badge in the list.
+// Every successful `edit_tags` records the file's mtime and a hash of the
+// fields we manage (data dir `touched.json`, keyed by canonical path). A newer
+// mtime alone doesn't mean someone else changed our fields: Rekordbox rewrites
+// files without touching them, so the hash is re-checked on the next read.
+// Records also keep the file's place under a known root (portable.rs), so a
+// crate moved to another drive is picked up again by relative path.
+// Each written file also has a revision, bumped on every save that changed
+// something and sent with `track-updated` (track_updates.rs). Revisions live
+// next to the records in `touched_revisions.json`, flushed with them, and
+// outlive `forget_touched` so they never go backwards for a path.
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, time::{Duration, Instant, UNIX_EPOCH}};
+use chrono::Local;
+use lofty::Accessor;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{data_dir, dates, log, LogLevel, portable::{self, RootRel}, preferred_tag, profile, read_comment, write_atomic};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TouchRecord {
+  pub touched_at: String,
+  pub mtime_ms: u64,
+  pub fields_hash: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub place: Option<RootRel>,
+}
+
+#[derive(Default)]
+struct Store {
+  records: Option<HashMap<String, TouchRecord>>,
+  /// Relative path -> record keys; rebuilt after any change.
+  by_rel: Option<HashMap<String, Vec<String>>>,
+  revisions: Option<HashMap<String, u64>>,
+  dirty: bool,
+  last_flush: Option<Instant>,
+  flush_scheduled: bool,
+}
+
+static STORE: Lazy<Mutex<Store>> = Lazy::new(|| Mutex::new(Store::default()));
+
+pub fn touched_path() -> PathBuf { data_dir().join("touched.json") }
+
+fn revisions_path() -> PathBuf { data_dir().join("touched_revisions.json") }
+
+fn key(p: &Path) -> String {
+  fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()).to_string_lossy().to_string()
+}
+
+fn mtime_ms(p: &Path) -> Option<u64> {
+  let m = fs::metadata(p).ok()?.modified().ok()?;
+  Some(m.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+/// Hash of the fields this app writes, as read back from the file.
+pub fn fields_hash(tf: &lofty::TaggedFile, p: &Path) -> String {
+  let tag = preferred_tag(tf, p);
+  let text = |v: Option<std::borrow::Cow<'_, str>>| v.map(|s| s.to_string()).unwrap_or_default();
+  let mut h = Sha256::new();
+  for field in [
+    read_comment(tf, p),
+    text(tag.and_then(|t| t.title())),
+    text(tag.and_then(|t| t.artist())),
+    text(tag.and_then(|t| t.genre())),
+    tag.and_then(dates::release_date).unwrap_or_default(),
+    tag.and_then(dates::original_date).unwrap_or_default(),
+  ] {
+    h.update(field.as_bytes());
+    h.update([0u8]);
+  }
+  h.finalize()[..12].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn records(s: &mut Store) -> &mut HashMap<String, TouchRecord> {
+  s.records.get_or_insert_with(|| {
+    fs::read_to_string(touched_path()).ok().and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default()
+  })
+}
+
+fn revisions(s: &mut Store) -> &mut HashMap<String, u64> {
+  s.revisions.get_or_insert_with(|| {
+    fs::read_to_string(revisions_path()).ok().and_then(|j| serde_json::from_str(&j).ok()).unwrap_or_default()
+  })
+}
+
+fn save_json<T: Serialize>(p: &Path, value: &T) -> Result<(), String> {
+  let bytes = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+  if let Some(dir) = p.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+  write_atomic(p, &bytes)
+}
+
+fn flush_locked(s: &mut Store) {
+  if s.dirty {
+    let mut res = save_json(&touched_path(), records(s));
+    // Untouched unless something was saved this run.
+    if let Some(revs) = &s.revisions { res = res.and(save_json(&revisions_path(), revs)); }
+    if let Err(e) = res { log(LogLevel::Warn, &format!("touched flush failed: {}", e)); }
+    s.dirty = false;
+  }
+  s.last_flush = Some(Instant::now());
+}
+
+/// Batches write thousands of files; coalesce the store rewrites.
+fn mark_dirty(mut s: parking_lot::MutexGuard<'_, Store>) {
+  s.dirty = true;
+  s.by_rel = None;
+  let interval = profile::flush_interval(FLUSH_INTERVAL);
+  match s.last_flush.map(|t| t.elapsed()) {
+    Some(el) if el < interval => {
+      if !s.flush_scheduled {
+        s.flush_scheduled = true;
+        let wait = interval - el;
+        std::thread::spawn(move || {
+          std::thread::sleep(wait);
+          let mut s = STORE.lock();
+          s.flush_scheduled = false;
+          flush_locked(&mut s);
+        });
+      }
+    }
+    _ => flush_locked(&mut s),
+  }
+}
+
+/// Write pending records right away. Called on app exit.
+pub fn flush() { flush_locked(&mut STORE.lock()); }
+
+/// Called after a successful save; `tf` is the file as written. Returns the
+/// file's new revision.
+pub fn record(p: &Path, tf: &lofty::TaggedFile) -> u64 {
+  let k = key(p);
+  let rec = mtime_ms(p).map(|mtime_ms| TouchRecord { touched_at: Local::now().to_rfc3339(), mtime_ms, fields_hash: fields_hash(tf, p), place: portable::place(Path::new(&k)) });
+  let mut s = STORE.lock();
+  let rev = {
+    let r = revisions(&mut s).entry(k.clone()).or_insert(0);
+    *r += 1;
+    *r
+  };
+  if let Some(rec) = rec { records(&mut s).insert(k, rec); }
+  mark_dirty(s);
+  rev
+}
+
+/// The revision of the last change this app saved to `p`; 0 for none.
+pub fn revision(p: &Path) -> u64 {
+  let k = key(p);
+  revisions(&mut STORE.lock()).get(&k).copied().unwrap_or(0)
+}
+
+/// `(last_touched_by_app, externally_modified_since)` for a file just read.
+/// A newer mtime with unchanged fields adopts the new mtime instead of
+/// reporting an outside edit.
+pub fn status(p: &Path, tf: &lofty::TaggedFile) -> (Option<String>, bool) {
+  let k = key(p);
+  let mut s = STORE.lock();
+  let Some(rec) = records(&mut s).get(&k).cloned().or_else(|| adopt(&mut s, &k)) else { return (None, false) };
+  let Some(now_ms) = mtime_ms(p) else { return (Some(rec.touched_at), false) };
+  if now_ms <= rec.mtime_ms { return (Some(rec.touched_at), false); }
+  if fields_hash(tf, p) != rec.fields_hash { return (Some(rec.touched_at), true); }
+  if let Some(r) = records(&mut s).get_mut(&k) { r.mtime_ms = now_ms; }
+  mark_dirty(s);
+  (Some(rec.touched_at), false)
+}
+
+/// A record left behind at a path that no longer exists whose root-relative
+/// place is one of `k`'s: same root id and relative path. The same relative
+/// path under another root is some other file and is never adopted.
+/// Re-keyed to `k` when found.
+fn adopt(s: &mut Store, k: &str) -> Option<TouchRecord> {
+  let readings = portable::readings(Path::new(k));
+  if readings.is_empty() { return None; }
+  if s.by_rel.is_none() {
+    let mut idx: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, rec) in records(s).iter() {
+      if let Some(pl) = &rec.place { idx.entry(pl.rel.clone()).or_default().push(key.clone()); }
+    }
+    s.by_rel = Some(idx);
+  }
+  let idx = s.by_rel.as_ref()?;
+  let recs = s.records.as_ref()?;
+  let orphans = |want: &RootRel| -> Vec<&String> {
+    idx.get(&want.rel).into_iter().flatten().filter(|key| !Path::new(key.as_str()).exists()).collect()
+  };
+  let old = readings.iter().find_map(|want| orphans(want).into_iter().find(|key| recs[key.as_str()].place.as_ref() == Some(want)).cloned())?;
+  let mut rec = records(s).remove(&old)?;
+  rec.place = portable::place(Path::new(k));
+  records(s).insert(k.to_string(), rec.clone());
+  s.dirty = true;
+  s.by_rel = None;
+  Some(rec)
+}
+
+/// Move every record under `old_root` to the same place under `new_root`.
+pub fn rebase(old_root: &Path, new_root: &Path) -> usize {
+  let mut s = STORE.lock();
+  let recs = records(&mut s);
+  let moved: Vec<(String, PathBuf)> =
+    recs.keys().filter_map(|k| portable::rebased(Path::new(k), old_root, new_root).map(|to| (k.clone(), to))).collect();
+  for (from, to) in &moved {
+    let Some(mut rec) = recs.remove(from) else { continue };
+    rec.place = portable::place(to);
+    recs.insert(to.to_string_lossy().to_string(), rec);
+  }
+  let revs = revisions(&mut s);
+  for (from, to) in &moved {
+    if let Some(r) = revs.remove(from) { revs.insert(to.to_string_lossy().to_string(), r); }
+  }
+  if !moved.is_empty() { mark_dirty(s); }
+  moved.len()
+}
+
+/// Carry a record over a rename; `from` is the canonical path from before it.
+pub fn rename(from: &Path, to: &Path) {
+  let mut s = STORE.lock();
+  let recs = records(&mut s);
+  let Some(mut rec) = recs.remove(&from.to_string_lossy().to_string()) else { return };
+  let k = key(to);
+  rec.place = portable::place(Path::new(&k));
+  recs.insert(k.clone(), rec);
+  let revs = revisions(&mut s);
+  if let Some(r) = revs.remove(&from.to_string_lossy().to_string()) { revs.insert(k, r); }
+  mark_dirty(s);
+}
+
+/// Drop records for `paths`; returns how many existed. Revisions stay.
+#[tauri::command]
+pub fn forget_touched(paths: Vec<String>) -> usize {
+  let mut s = STORE.lock();
+  let recs = records(&mut s);
+  // Deleted files can't be canonicalized any more; try the path as given too.
+  let removed = paths.iter().filter(|p| recs.remove(&key(Path::new(p))).or_else(|| recs.remove(p.as_str())).is_some()).count();
+  if removed > 0 { mark_dirty(s); }
+  removed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{read_tagged, test_support};
+
+  #[test]
+  fn a_same_named_file_under_another_root_is_not_adopted() {
+    let dir = test_support::scratch("touched-adopt");
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    fs::create_dir_all(&a).unwrap();
+    fs::create_dir_all(&b).unwrap();
+    portable::register(&a);
+    portable::register(&b);
+    let gone = test_support::audio(&a, "t.mp3");
+    record(&gone, &read_tagged(&gone).unwrap());
+    fs::remove_file(&gone).unwrap();
+
+    let other = test_support::audio(&b, "t.mp3");
+    assert_eq!(status(&other, &read_tagged(&other).unwrap()), (None, false));
+    assert!(records(&mut STORE.lock()).contains_key(&key(&gone)), "the orphan stays where it was");
+  }
+}
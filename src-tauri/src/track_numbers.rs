@@ -0,0 +1,205 @@
+// Track numbers for set-order folders: 1..N in the order the UI lists the
+// files, optionally with the total ("3/24", as tags store it) and a
+// zero-padded "03 - " file-name prefix. The tag is written first and the rename only follows a successful
+// write, so a failure leaves each file either untouched, numbered, or
+// numbered and renamed, and the result row says which.
+
+use std::path::{Path, PathBuf};
+use lofty::Accessor;
+use serde::{Deserialize, Serialize};
+
+use crate::{archive, audit, command_span, edit_tags, error::CmdError, field_locks::{self, LockedField}, folder_watch, jobs::JobHandle, log_line, preferred_tag, preflight::{self, Preflight}, read_tagged, shadow, snapshots};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct TrackNumberOptions {
+  /// Replace track numbers files already have; otherwise they are skipped.
+  overwrite: bool,
+  /// Rename to "03 - Name.ext" in the same pass.
+  prefix_filenames: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackNumberResult {
+  path: String,
+  number: u32,
+  /// As the tag will read, e.g. "3/24".
+  display: String,
+  old_number: Option<u32>,
+  old_total: Option<u32>,
+  /// Had a number and `overwrite` was off: neither tag nor name changed.
+  skipped: bool,
+  tag_written: bool,
+  /// New path after the prefix rename (planned one in dry runs).
+  renamed_to: Option<String>,
+  error: Option<String>,
+  /// `["trackNumber"]` when it's locked: the tag is left alone, the rename still happens.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackNumberReport {
+  results: Vec<TrackNumberResult>,
+  dry_run: bool,
+  cancelled: bool,
+  snapshot_id: Option<String>,
+  /// Space and permission check run before writing (see `preflight`).
+  preflight: Preflight,
+}
+
+/// A prefix we wrote earlier ("03 - "), so re-numbering replaces it instead
+/// of stacking. Names that merely start with digits ("808 State") are kept.
+pub fn strip_own_prefix(stem: &str) -> &str {
+  let digits = stem.chars().take_while(|c| c.is_ascii_digit()).count();
+  match stem[digits..].strip_prefix(" - ") {
+    Some(rest) if (2..=4).contains(&digits) && !rest.is_empty() => rest,
+    _ => stem,
+  }
+}
+
+fn prefixed_path(p: &Path, number: &str) -> PathBuf {
+  let stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let ext = p.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+  p.with_file_name(format!("{} - {}{}", number, strip_own_prefix(&stem), ext))
+}
+
+/// Returns where the file went: `to`, or the shadow copy's new path.
+fn rename(from: &Path, to: &Path) -> Result<PathBuf, String> {
+  if to.exists() { return Err(format!("rename target exists: {}", to.display())); }
+  archive::guard(from).map_err(|e| e.to_string())?;
+  match shadow::rename(from, to)? {
+    Some(copy) => {
+      let (f, t) = (from.to_string_lossy().to_string(), copy.to_string_lossy().to_string());
+      audit::record(&f, "path", Some(&f), Some(&t), audit::Source::Batch);
+      Ok(copy)
+    }
+    None => {
+      folder_watch::migrate(from, to, audit::Source::Batch);
+      Ok(to.to_path_buf())
+    }
+  }
+}
+
+fn number_one(path: &str, number: u32, total: Option<u32>, width: usize, opts: TrackNumberOptions, dry_run: bool) -> TrackNumberResult {
+  let p = Path::new(path);
+  let padded = format!("{:0w$}", number, w = width);
+  let display = match total { Some(t) => format!("{}/{}", number, t), None => number.to_string() };
+  let mut r = TrackNumberResult { path: path.to_string(), number, display, old_number: None, old_total: None, skipped: false, tag_written: false, renamed_to: None, error: None, skipped_locked: Vec::new() };
+  let tf = match read_tagged(p) { Ok(tf) => tf, Err(e) => { r.error = Some(e.to_string()); return r; } };
+  let tag = preferred_tag(&tf, p);
+  (r.old_number, r.old_total) = (tag.and_then(|t| t.track()), tag.and_then(|t| t.track_total()));
+  drop(tf);
+  if r.old_number.is_some() && !opts.overwrite { r.skipped = true; return r; }
+  let target = opts.prefix_filenames.then(|| prefixed_path(p, &padded)).filter(|t| t != p);
+  if dry_run {
+    r.renamed_to = target.map(|t| t.to_string_lossy().to_string());
+    r.skipped_locked = field_locks::hits(p, &[LockedField::TrackNumber]);
+    return r;
+  }
+
+  let unchanged = r.old_number == Some(number) && (total.is_none() || r.old_total == total);
+  if !unchanged {
+    let res = edit_tags(p, |tag| {
+      tag.set_track(number);
+      match total { Some(t) => tag.set_track_total(t), None => tag.remove_track_total() }
+    });
+    match res {
+      Err(e) => { r.error = Some(e.to_string()); return r; }
+      Ok(o) if !o.skipped_locked.is_empty() => r.skipped_locked = o.skipped_locked,
+      Ok(o) if o.no_op => {}
+      Ok(_) => {
+        let old = r.old_number.map(|n| n.to_string());
+        audit::record(path, "track", old.as_deref(), Some(&r.display), audit::Source::Batch);
+        r.tag_written = true;
+      }
+    }
+  }
+  if let Some(t) = target {
+    match rename(p, &t) {
+      Ok(moved) => r.renamed_to = Some(moved.to_string_lossy().to_string()),
+      Err(e) if r.tag_written => r.error = Some(format!("track number written, rename failed: {}", e)),
+      Err(e) => r.error = Some(format!("rename failed: {}", e)),
+    }
+  }
+  r
+}
+
+fn assign_blocking(job: &JobHandle, paths: &[String], start: u32, write_total: bool, opts: TrackNumberOptions, dry_run: bool) -> Result<TrackNumberReport, CmdError> {
+  let last = start + paths.len().saturating_sub(1) as u32;
+  let width = last.to_string().len().max(2);
+  let total = write_total.then_some(last);
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(paths));
+  if !dry_run { preflight::ensure(&preflight)?; }
+  let snapshot_id = if dry_run { None } else { snapshots::before_batch(job.app(), "assign_track_numbers", paths) };
+  let mut results = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("write", paths.len() as u64);
+  for (i, path) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    results.push(number_one(path, start + i as u32, total, width, opts, dry_run));
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  if !dry_run {
+    let written = results.iter().filter(|r| r.tag_written).count();
+    let renamed = results.iter().filter(|r| r.renamed_to.is_some()).count();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    log_line(&format!("assign_track_numbers files={} written={} renamed={} failed={}", results.len(), written, renamed, failed));
+  }
+  Ok(TrackNumberReport { results, dry_run, cancelled, snapshot_id, preflight })
+}
+
+/// Number `paths_in_order` sequentially from `start` (default 1); job kind
+/// "track-numbers". `dry_run` returns the same rows without writing.
+#[tauri::command]
+pub async fn assign_track_numbers(
+  app: tauri::AppHandle,
+  paths_in_order: Vec<String>,
+  start: Option<u32>,
+  write_total: bool,
+  dry_run: bool,
+  options: Option<TrackNumberOptions>,
+) -> Result<TrackNumberReport, CmdError> {
+  let _span = command_span("assign_track_numbers");
+  let start = start.unwrap_or(1);
+  let last = (paths_in_order.len() as u64).saturating_sub(1) + start as u64;
+  if start == 0 || last > u32::MAX as u64 { return Err(format!("track numbers from {} don't fit", start).into()); }
+  let opts = options.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "track-numbers", &format!("{} files", paths_in_order.len()));
+    let res = assign_blocking(&job, &paths_in_order, start, write_total, opts, dry_run);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{borrow::Cow, fs};
+  use lofty::{id3::v2::FrameId, mpeg::MpegFile, AudioFile, ParseOptions};
+  use crate::test_support;
+
+  #[test]
+  fn the_preview_reads_like_the_tag() {
+    let dir = test_support::scratch("track-numbers");
+    let opts = TrackNumberOptions { overwrite: true, prefix_filenames: true };
+    let p = test_support::audio(&dir, "Intro.mp3");
+    let path = p.to_string_lossy().to_string();
+    let preview = number_one(&path, 3, Some(24), 2, opts, true);
+    assert_eq!(preview.display, "3/24");
+    assert_eq!(preview.renamed_to, Some(dir.join("03 - Intro.mp3").to_string_lossy().to_string()));
+
+    let done = number_one(&path, 3, Some(24), 2, opts, false);
+    assert_eq!((done.tag_written, done.error, &done.renamed_to), (true, None, &preview.renamed_to));
+    let mut f = fs::File::open(dir.join("03 - Intro.mp3")).unwrap();
+    let mpeg = MpegFile::read_from(&mut f, ParseOptions::new()).unwrap();
+    assert_eq!(mpeg.id3v2().unwrap().get_text(&FrameId::Valid(Cow::Borrowed("TRCK"))), Some(preview.display.as_str()));
+  }
+}
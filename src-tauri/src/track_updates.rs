@@ -0,0 +1,193 @@
+// `track-updated {path, revision, meta}`: sent after every tracked edit through
+// `edit_tags`, including ones skipped because nothing changed, and so also
+// for background retries and rule writes the UI didn't start. `meta` has no
+// picture, like the metadata cache. `revision` is the file's touched revision
+// (touched.rs): it goes up with every save that changed something, so a
+// skipped edit repeats the last one. Updates are held FLUSH_EVERY and sent
+// in the order they were made, one per path; a newer one for the same path
+// replaces the held one. Each one also refreshes the second-screen page
+// when it shows that path (now_showing.rs). `get_track_revision` lets the
+// frontend drop data older than what it already applied.
+
+use std::{collections::HashMap, path::Path, time::Duration};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{now_showing, touched, track_meta_from, TrackMeta};
+
+const FLUSH_EVERY: Duration = Duration::from_millis(50);
+
+static APP: OnceCell<tauri::AppHandle> = OnceCell::new();
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TrackUpdated {
+  path: String,
+  revision: u64,
+  meta: TrackMeta,
+}
+
+#[derive(Default)]
+struct Pending {
+  /// Path -> (order it was made in, update).
+  updates: HashMap<String, (u64, TrackUpdated)>,
+  seq: u64,
+  scheduled: bool,
+}
+
+static PENDING: Lazy<Mutex<Pending>> = Lazy::new(|| Mutex::new(Pending::default()));
+
+pub fn init(app: tauri::AppHandle) { let _ = APP.set(app); }
+
+impl Pending {
+  /// Hold `u`, replacing an older update for its path. Returns whether a
+  /// flush has to be scheduled.
+  fn hold(&mut self, u: TrackUpdated) -> bool {
+    self.seq += 1;
+    // An edit whose emit lost the race to a later one mustn't replace it.
+    if self.updates.get(&u.path).is_some_and(|(_, held)| held.revision > u.revision) { return false; }
+    self.updates.insert(u.path.clone(), (self.seq, u));
+    !std::mem::replace(&mut self.scheduled, true)
+  }
+
+  /// The held updates in the order they were made.
+  fn take(&mut self) -> Vec<TrackUpdated> {
+    self.scheduled = false;
+    let mut held: Vec<(u64, TrackUpdated)> = self.updates.drain().map(|(_, u)| u).collect();
+    held.sort_by_key(|(seq, _)| *seq);
+    held.into_iter().map(|(_, u)| u).collect()
+  }
+}
+
+fn flush() {
+  let Some(app) = APP.get() else { return };
+  let held = PENDING.lock().take();
+  for u in held { let _ = app.emit_all("track-updated", u); }
+}
+
+/// Queue `track-updated` for `p` at `revision` (see the header).
+pub fn emit(p: &Path, tf: &lofty::TaggedFile, revision: u64) {
+  // Not set in CLI mode.
+  let Some(app) = APP.get() else { return };
+  let path = p.to_string_lossy().to_string();
+  now_showing::edited(app, &path);
+  let meta = track_meta_from(&path, tf, false);
+  if PENDING.lock().hold(TrackUpdated { path, revision, meta }) {
+    std::thread::spawn(|| {
+      std::thread::sleep(FLUSH_EVERY);
+      flush();
+    });
+  }
+}
+
+/// The revision of the last change saved to `path`; 0 when the app never
+/// changed it.
+#[tauri::command]
+pub fn get_track_revision(path: String) -> u64 { touched::revision(Path::new(&path)) }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::{ItemKey, TagType};
+  use std::fs;
+  use crate::{apply_meta_patch, audit, edit_tags, test_support, write_comment_as, MetaPatch};
+
+  fn update(path: &Path, revision: u64) -> TrackUpdated {
+    let tf = lofty::read_from_path(path).unwrap();
+    let path = path.to_string_lossy().to_string();
+    TrackUpdated { meta: track_meta_from(&path, &tf, false), path, revision }
+  }
+
+  #[test]
+  fn held_updates_coalesce_per_path_in_order() {
+    let dir = test_support::scratch("coalesce");
+    let (a, b) = (test_support::audio(&dir, "a.mp3"), test_support::audio(&dir, "b.mp3"));
+    let mut pending = Pending::default();
+    assert!(pending.hold(update(&a, 1)));
+    assert!(!pending.hold(update(&b, 1)), "one flush per interval");
+    assert!(!pending.hold(update(&a, 3)));
+    // Lost the race to revision 3.
+    assert!(!pending.hold(update(&a, 2)));
+    let sent: Vec<(String, u64)> = pending.take().into_iter().map(|u| (u.path, u.revision)).collect();
+    assert_eq!(sent, vec![(b.to_string_lossy().to_string(), 1), (a.to_string_lossy().to_string(), 3)]);
+    assert!(pending.hold(update(&b, 2)), "a new interval after a flush");
+  }
+
+  #[test]
+  fn revisions_follow_the_order_of_the_saves() {
+    let dir = test_support::scratch("revisions");
+    let p = test_support::audio(&dir, "a.mp3");
+    let base = touched::revision(&p);
+    let results: Vec<(u64, String)> = std::thread::scope(|s| {
+      let handles: Vec<_> = (0..8).map(|i| {
+        let p = &p;
+        s.spawn(move || {
+          let title = format!("take {}", i);
+          let out = edit_tags(p, |tag| { tag.insert_text(ItemKey::TrackTitle, title.clone()); }).unwrap();
+          (out.revision, title)
+        })
+      }).collect();
+      handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut revisions: Vec<u64> = results.iter().map(|(r, _)| *r).collect();
+    revisions.sort_unstable();
+    assert_eq!(revisions, (base + 1..=base + 8).collect::<Vec<_>>());
+    let (last, title) = results.iter().max_by_key(|(r, _)| *r).unwrap();
+    assert_eq!(touched::revision(&p), *last);
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackTitle).as_deref(), Some(title.as_str()));
+  }
+
+  #[test]
+  fn an_unchanged_write_repeats_the_revision() {
+    let dir = test_support::scratch("repeat");
+    let p = test_support::audio(&dir, "a.mp3");
+    let set = |tag: &mut lofty::Tag| { tag.insert_text(ItemKey::TrackTitle, "same".into()); };
+    let first = edit_tags(&p, set).unwrap();
+    let again = edit_tags(&p, set).unwrap();
+    assert!(again.no_op);
+    assert_eq!(again.revision, first.revision);
+    assert_eq!(get_track_revision(p.to_string_lossy().to_string()), first.revision);
+  }
+
+  fn audited(p: &Path) -> usize {
+    let path = p.to_string_lossy();
+    audit::read_entries().iter().filter(|e| e.path == path).count()
+  }
+
+  #[test]
+  fn a_repeated_comment_is_neither_saved_nor_audited() {
+    let dir = test_support::scratch("repeat-comment");
+    let p = test_support::audio(&dir, "a.mp3");
+    let path = p.to_string_lossy().to_string();
+    let first = write_comment_as(&path, "#deep;", audit::Source::Manual).unwrap();
+    assert!(!first.no_op);
+    let (bytes, modified) = (fs::read(&p).unwrap(), fs::metadata(&p).unwrap().modified().unwrap());
+    let again = write_comment_as(&path, "#deep;", audit::Source::Batch).unwrap();
+    assert!(again.no_op);
+    assert_eq!(again.revision, first.revision);
+    assert_eq!((fs::read(&p).unwrap(), fs::metadata(&p).unwrap().modified().unwrap()), (bytes, modified));
+    assert_eq!(audited(&p), 1);
+
+    let changed = write_comment_as(&path, "#deep;#dub;", audit::Source::Manual).unwrap();
+    assert!(!changed.no_op);
+    assert_eq!(changed.revision, first.revision + 1);
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::Comment).as_deref(), Some("#deep;#dub;"));
+    assert_eq!(audited(&p), 2);
+  }
+
+  #[test]
+  fn a_repeated_patch_skips_the_save() {
+    let dir = test_support::scratch("repeat-patch");
+    let p = test_support::audio(&dir, "a.mp3");
+    let patch = MetaPatch { title: Some("Intro".into()), release_date: Some("2021-03".into()), ..Default::default() };
+    assert!(!apply_meta_patch(&p, &patch).unwrap().no_op);
+    let bytes = fs::read(&p).unwrap();
+    assert!(apply_meta_patch(&p, &patch).unwrap().no_op);
+    assert_eq!(fs::read(&p).unwrap(), bytes);
+    let retitled = MetaPatch { title: Some("Outro".into()), ..patch };
+    assert!(!apply_meta_patch(&p, &retitled).unwrap().no_op);
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::TrackTitle).as_deref(), Some("Outro"));
+  }
+}
@@ -0,0 +1,546 @@
+// Tracklists of a recorded set, matched against the library so the tracks
+// that were played can be tagged in one go ("#PlayedAtX"). Three shapes are
+// read: plain text ("01. Artist - Title", with or without a "[00:12:34]"
+// timestamp), CSV with artist / title (and optionally time) columns, and
+// Rekordbox's history export (tab-separated text, UTF-16 or UTF-8, with a
+// "Track Title" and an "Artist" column). Rows are one per line; quoted
+// fields may not span lines.
+//
+// Entries are normalized like `already_owned` does (accents and case folded,
+// featured artists dropped, neutral mix names ignored) and scored against
+// the cached tags of the library: a typo-tolerant title ratio and a
+// token-set ratio of the artists (so "A & B" matches "B"), with a penalty
+// when the mix names differ. Matches under MIN_CONFIDENCE come back with
+// the unparseable lines as unmatched, with the best guess for picking by
+// hand. Nothing is written until `tag_matched_tracks`.
+
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  already_owned::{meta_key, MetaKey},
+  audit, command_span, error::CmdError, library, log_line, meta_cache, portable,
+  preflight::{self, Preflight}, snapshots,
+  tag_ops::{merge_file_tags, ToggleFileResult},
+  tag_policy, text_fold::fold_str,
+};
+
+const TITLE_WEIGHT: f32 = 0.6;
+const ARTIST_WEIGHT: f32 = 0.4;
+/// Factor when one side names a mix the other doesn't have.
+const MIX_PENALTY: f32 = 0.85;
+const MIN_CONFIDENCE: f32 = 0.75;
+/// Other candidates over MIN_CONFIDENCE listed with a match.
+const ALTERNATIVES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TracklistFormat { Auto, Text, Csv, Rekordbox }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracklistEntry {
+  /// 1-based place in the set, counting parsed entries only.
+  position: usize,
+  /// 1-based line of the input.
+  line: usize,
+  raw: String,
+  time: Option<String>,
+  artist: String,
+  title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+  path: String,
+  artist: Option<String>,
+  title: Option<String>,
+  confidence: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracklistMatch {
+  entry: TracklistEntry,
+  #[serde(flatten)]
+  best: Candidate,
+  alternatives: Vec<Candidate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmatchedLine {
+  line: usize,
+  raw: String,
+  /// Set when the line parsed but nothing scored MIN_CONFIDENCE.
+  entry: Option<TracklistEntry>,
+  reason: String,
+  /// The closest candidate, if any shared a word with the entry.
+  best: Option<Candidate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracklistImport {
+  /// As detected when `auto` was asked for.
+  format: TracklistFormat,
+  matches: Vec<TracklistMatch>,
+  unmatched: Vec<UnmatchedLine>,
+  /// Library files with artist and title that were matched against.
+  searched: usize,
+  refreshed: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagMatchedReport {
+  /// As written, after the tag policy.
+  tags: Vec<String>,
+  results: Vec<ToggleFileResult>,
+  /// Taken before writing (see `snapshots`).
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+//////////////////// parsing ////////////////////
+
+/// `[00:12:34]`, `12:34` and the like.
+fn is_time(s: &str) -> bool {
+  let parts: Vec<&str> = s.split(':').collect();
+  (2..=3).contains(&parts.len())
+    && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+    && parts[1..].iter().all(|p| p.len() == 2)
+}
+
+/// A leading timestamp, bracketed or not, and the rest of `s`.
+fn take_time(s: &str) -> (Option<String>, &str) {
+  let s = s.trim_start();
+  if let Some(close) = s.strip_prefix(['[', '(']).and_then(|r| r.find([']', ')'])) {
+    let inner = &s[1..close + 1];
+    if is_time(inner.trim()) { return (Some(inner.trim().to_string()), s[close + 2..].trim_start()); }
+  }
+  let end = s.find(|c: char| !(c.is_ascii_digit() || c == ':')).unwrap_or(s.len());
+  if is_time(&s[..end]) { return (Some(s[..end].to_string()), s[end..].trim_start_matches([' ', '-', '\t'])); }
+  (None, s)
+}
+
+/// `s` without a "01." / "1)" / "01 - " track number. A bare "808 State"
+/// keeps its digits.
+fn strip_number(s: &str) -> &str {
+  let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+  if end == 0 || end > 3 { return s; }
+  let rest = &s[end..];
+  if let Some(r) = rest.strip_prefix(['.', ')', ':']) { return r.trim_start(); }
+  match rest.strip_prefix(" - ") {
+    Some(r) if split_dash(r).is_some() => r,
+    _ => s,
+  }
+}
+
+fn split_dash(s: &str) -> Option<(&str, &str)> {
+  [" - ", " – ", " — "].iter().filter_map(|d| s.find(d).map(|i| (i, d.len()))).min().map(|(i, n)| (&s[..i], &s[i + n..]))
+}
+
+/// "Title [Label]": the trailing bracket goes unless it names a mix.
+fn strip_label(title: &str) -> &str {
+  let t = title.trim_end();
+  if !t.ends_with(']') { return t; }
+  match t.rfind('[') {
+    Some(open) if open > 0 && meta_key("x", &t[open..]).is_none_or(|k| k.mix_raw.is_none()) => t[..open].trim_end(),
+    _ => t,
+  }
+}
+
+fn parse_text_line(line: &str) -> Option<(Option<String>, String, String)> {
+  let (mut time, rest) = take_time(line.trim());
+  let rest = strip_number(rest);
+  let rest = if time.is_none() {
+    let (t, r) = take_time(rest);
+    time = t;
+    r
+  } else {
+    rest
+  };
+  let (artist, title) = split_dash(rest)?;
+  let (artist, title) = (artist.trim(), strip_label(title).trim());
+  (!artist.is_empty() && !title.is_empty()).then(|| (time, artist.to_string(), title.to_string()))
+}
+
+/// One delimited row; `""` inside quotes is a quote.
+fn split_row(line: &str, delim: char) -> Vec<String> {
+  let mut out = vec![String::new()];
+  let mut quoted = false;
+  let mut chars = line.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); out.last_mut().unwrap().push('"'); }
+      '"' => quoted = !quoted,
+      c if c == delim && !quoted => out.push(String::new()),
+      c => out.last_mut().unwrap().push(c),
+    }
+  }
+  out.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+struct Columns { artist: usize, title: usize, time: Option<usize> }
+
+fn header_columns(row: &[String]) -> Option<Columns> {
+  let find = |names: &[&str]| row.iter().position(|f| names.contains(&fold_str(f).as_str()));
+  Some(Columns {
+    artist: find(&["artist", "artists"])?,
+    title: find(&["title", "track title", "track", "name"])?,
+    time: find(&["time", "start", "start time", "timestamp"]),
+  })
+}
+
+fn decode(bytes: &[u8]) -> String {
+  let utf16 = |be: bool| {
+    let units: Vec<u16> = bytes[2..].chunks_exact(2).map(|c| if be { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) }).collect();
+    String::from_utf16_lossy(&units)
+  };
+  match bytes {
+    [0xFF, 0xFE, ..] => utf16(false),
+    [0xFE, 0xFF, ..] => utf16(true),
+    [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+    _ => String::from_utf8_lossy(bytes).to_string(),
+  }
+}
+
+fn detect(text: &str) -> TracklistFormat {
+  let first = text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+  if first.contains('\t') { return TracklistFormat::Rekordbox; }
+  if header_columns(&split_row(first, ',')).is_some() { return TracklistFormat::Csv; }
+  let sample: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).take(3).collect();
+  if !sample.is_empty() && sample.iter().all(|l| l.contains(',') && split_dash(l).is_none()) { TracklistFormat::Csv } else { TracklistFormat::Text }
+}
+
+/// Parsed entries, and (line, raw, reason) for the lines that didn't parse.
+fn parse(text: &str, format: TracklistFormat) -> (Vec<TracklistEntry>, Vec<(usize, String, String)>) {
+  let mut entries = Vec::new();
+  let mut bad = Vec::new();
+  let lines: Vec<(usize, &str)> = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim_end_matches('\r'))).filter(|(_, l)| !l.trim().is_empty()).collect();
+  let mut push = |line: usize, raw: &str, time: Option<String>, artist: String, title: String| {
+    entries.push(TracklistEntry { position: entries.len() + 1, line, raw: raw.trim().to_string(), time, artist, title });
+  };
+  if format == TracklistFormat::Text {
+    for (n, l) in lines {
+      match parse_text_line(l) {
+        Some((time, artist, title)) => push(n, l, time, artist, title),
+        None => bad.push((n, l.trim().to_string(), "not an \"Artist - Title\" line".to_string())),
+      }
+    }
+    return (entries, bad);
+  }
+
+  let delim = if format == TracklistFormat::Rekordbox { '\t' } else { ',' };
+  let mut rows = lines.into_iter().map(|(n, l)| (n, l, split_row(l, delim))).peekable();
+  let header = rows.peek().and_then(|(_, _, r)| header_columns(r));
+  let cols = match header {
+    Some(c) => { rows.next(); c }
+    None if rows.peek().is_some_and(|(_, _, r)| r.len() >= 3 && is_time(&r[0])) => Columns { artist: 1, title: 2, time: Some(0) },
+    None => Columns { artist: 0, title: 1, time: None },
+  };
+  for (n, l, r) in rows {
+    let get = |i: usize| r.get(i).map(String::as_str).unwrap_or("");
+    let (artist, title) = (get(cols.artist), get(cols.title));
+    if artist.is_empty() || title.is_empty() {
+      bad.push((n, l.trim().to_string(), "no artist or title column".to_string()));
+      continue;
+    }
+    let time = cols.time.map(get).filter(|t| !t.is_empty()).map(str::to_string);
+    push(n, l, time, artist.to_string(), title.to_string());
+  }
+  (entries, bad)
+}
+
+//////////////////// scoring ////////////////////
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+  let mut prev: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.iter().enumerate() {
+    let mut cur = vec![i + 1; b.len() + 1];
+    for (j, cb) in b.iter().enumerate() {
+      cur[j + 1] = (prev[j] + usize::from(ca != cb)).min(prev[j + 1] + 1).min(cur[j] + 1);
+    }
+    prev = cur;
+  }
+  prev[b.len()]
+}
+
+/// 1.0 for equal strings, falling with the edit distance.
+fn ratio(a: &str, b: &str) -> f32 {
+  if a == b { return 1.0; }
+  let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+  let n = a.len().max(b.len());
+  1.0 - levenshtein(&a, &b) as f32 / n as f32
+}
+
+fn sorted_tokens<'a>(words: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+  let mut v: Vec<&str> = words.collect();
+  v.sort_unstable();
+  v.dedup();
+  v
+}
+
+/// The best of comparing the shared words to each side and the sides to
+/// each other, so a list that contains the other ("A B" vs "A") scores 1.0.
+fn token_set_ratio(a: &[&str], b: &[&str]) -> f32 {
+  let common: Vec<&str> = a.iter().filter(|w| b.contains(w)).copied().collect();
+  let with = |side: &[&str]| {
+    let rest: Vec<&str> = side.iter().filter(|w| !common.contains(w)).copied().collect();
+    [common.join(" "), rest.join(" ")].join(" ").trim().to_string()
+  };
+  let (base, da, db) = (common.join(" "), with(a), with(b));
+  let vs_common = if base.is_empty() { 0.0 } else { ratio(&base, &da).max(ratio(&base, &db)) };
+  vs_common.max(ratio(&da, &db))
+}
+
+fn artist_words(k: &MetaKey) -> Vec<&str> { sorted_tokens(k.artists.iter().flat_map(|a| a.split_whitespace())) }
+
+fn confidence(a: &MetaKey, b: &MetaKey) -> f32 {
+  let title = ratio(&sorted_tokens(a.title.split_whitespace()).join(" "), &sorted_tokens(b.title.split_whitespace()).join(" "));
+  let artist = token_set_ratio(&artist_words(a), &artist_words(b));
+  let score = TITLE_WEIGHT * title + ARTIST_WEIGHT * artist;
+  if a.mix != b.mix { score * MIX_PENALTY } else { score }
+}
+
+struct LibraryTrack { path: String, artist: Option<String>, title: Option<String>, key: MetaKey }
+
+fn words(k: &MetaKey) -> impl Iterator<Item = String> + '_ {
+  k.title.split_whitespace().chain(k.artists.iter().flat_map(|a| a.split_whitespace())).map(str::to_string)
+}
+
+/// Candidates for `key` best first: the tracks sharing a title or artist word with it.
+fn rank(key: &MetaKey, tracks: &[LibraryTrack], by_word: &HashMap<String, Vec<usize>>) -> Vec<Candidate> {
+  let ids: HashSet<usize> = words(key).filter_map(|w| by_word.get(&w)).flatten().copied().collect();
+  let mut out: Vec<Candidate> = ids
+    .into_iter()
+    .map(|i| {
+      let t = &tracks[i];
+      Candidate { path: t.path.clone(), artist: t.artist.clone(), title: t.title.clone(), confidence: confidence(key, &t.key) }
+    })
+    .collect();
+  out.sort_by(|a, b| b.confidence.total_cmp(&a.confidence).then_with(|| a.path.cmp(&b.path)));
+  out
+}
+
+fn library_tracks(roots: &[PathBuf]) -> Result<(Vec<LibraryTrack>, usize), String> {
+  let mut paths: Vec<PathBuf> = Vec::new();
+  for r in roots { paths.extend(library::audio_files_under(r).map_err(String::from)?); }
+  let mut unique = HashSet::new();
+  paths.retain(|p| unique.insert(p.clone()));
+  let refreshed = meta_cache::refresh(&paths);
+  let mut tracks = Vec::new();
+  meta_cache::visit(&paths, |p, m| {
+    let (Some(a), Some(t)) = (&m.artist, &m.title) else { return };
+    if let Some(key) = meta_key(a, t) {
+      tracks.push(LibraryTrack { path: p.to_string_lossy().to_string(), artist: m.artist.clone(), title: m.title.clone(), key });
+    }
+  });
+  Ok((tracks, refreshed))
+}
+
+fn import_blocking(text_or_path: &str, format: TracklistFormat, roots: Option<Vec<String>>) -> Result<TracklistImport, String> {
+  let p = Path::new(text_or_path.trim());
+  let text = if !text_or_path.contains('\n') && p.is_file() { decode(&fs::read(p).map_err(|e| e.to_string())?) } else { text_or_path.to_string() };
+  let format = if format == TracklistFormat::Auto { detect(&text) } else { format };
+  let (entries, bad) = parse(&text, format);
+
+  let roots: Vec<PathBuf> = match roots {
+    Some(r) if !r.is_empty() => r.into_iter().map(PathBuf::from).collect(),
+    _ => portable::list_known_roots().into_iter().map(|r| PathBuf::from(r.path)).collect(),
+  };
+  if roots.is_empty() { return Err("no library folders to match against".into()); }
+  let (tracks, refreshed) = library_tracks(&roots)?;
+  let mut by_word: HashMap<String, Vec<usize>> = HashMap::new();
+  for (i, t) in tracks.iter().enumerate() {
+    for w in words(&t.key).collect::<HashSet<_>>() { by_word.entry(w).or_default().push(i); }
+  }
+
+  let mut matches = Vec::new();
+  let mut unmatched: Vec<UnmatchedLine> = bad.into_iter().map(|(line, raw, reason)| UnmatchedLine { line, raw, entry: None, reason, best: None }).collect();
+  for entry in entries {
+    let Some(key) = meta_key(&entry.artist, &entry.title) else {
+      unmatched.push(UnmatchedLine { line: entry.line, raw: entry.raw.clone(), entry: Some(entry), reason: "nothing left of artist or title after normalization".into(), best: None });
+      continue;
+    };
+    let mut ranked = rank(&key, &tracks, &by_word).into_iter();
+    match ranked.next() {
+      Some(best) if best.confidence >= MIN_CONFIDENCE => {
+        let alternatives = ranked.take_while(|c| c.confidence >= MIN_CONFIDENCE).take(ALTERNATIVES).collect();
+        matches.push(TracklistMatch { entry, best, alternatives });
+      }
+      best => {
+        let reason = if best.is_some() { "no confident match" } else { "no library track shares a word with it" };
+        unmatched.push(UnmatchedLine { line: entry.line, raw: entry.raw.clone(), entry: Some(entry), reason: reason.into(), best });
+      }
+    }
+  }
+  unmatched.sort_by_key(|u| u.line);
+  log_line(&format!("import_tracklist format={:?} matched={} unmatched={} searched={} refreshed={}", format, matches.len(), unmatched.len(), tracks.len(), refreshed));
+  Ok(TracklistImport { format, matches, unmatched, searched: tracks.len(), refreshed })
+}
+
+/// Parse a tracklist (pasted text, or the path of a file) and match its
+/// entries against the library under `roots`, or every known root (see the
+/// header). `format` defaults to detecting it.
+#[tauri::command]
+pub async fn import_tracklist(text_or_path: String, format: Option<TracklistFormat>, roots: Option<Vec<String>>) -> Result<TracklistImport, String> {
+  let _span = command_span("import_tracklist");
+  tauri::async_runtime::spawn_blocking(move || import_blocking(&text_or_path, format.unwrap_or(TracklistFormat::Auto), roots))
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Add `tags` to the comments of the matched files the user kept, through
+/// the batch merge: one snapshot first, then file by file, and a failing
+/// file doesn't stop the others.
+#[tauri::command]
+pub async fn tag_matched_tracks(app: tauri::AppHandle, matches: Vec<String>, tags: Vec<String>) -> Result<TagMatchedReport, CmdError> {
+  let _span = command_span("tag_matched_tracks");
+  tauri::async_runtime::spawn_blocking(move || {
+    let tags = tag_policy::normalize_for_add(&tags)?;
+    if tags.is_empty() { return Err(String::from("no tags to add").into()); }
+    let mut unique = HashSet::new();
+    let paths: Vec<String> = matches.into_iter().filter(|p| unique.insert(p.clone())).collect();
+    let preflight = preflight::rewrite(&paths);
+    preflight::ensure(&preflight)?;
+    let snapshot_id = snapshots::before_batch(&app, "tag_matched_tracks", &paths);
+    let results: Vec<ToggleFileResult> = paths
+      .iter()
+      .map(|p| match merge_file_tags(p, &tags, &[], audit::Source::Batch) {
+        Ok(o) => ToggleFileResult { path: p.clone(), outcome: Some(o), error: None },
+        Err(e) => ToggleFileResult { path: p.clone(), outcome: None, error: Some(e) },
+      })
+      .collect();
+    let changed = results.iter().filter(|r| r.outcome.as_ref().is_some_and(|o| o.changed)).count();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    log_line(&format!("tag_matched_tracks tags=\"{}\" files={} changed={} failed={}", tags.join(" "), results.len(), changed, failed));
+    Ok(TagMatchedReport { tags, results, snapshot_id, preflight })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::ItemKey;
+  use crate::test_support;
+
+  fn entries(text: &str, format: TracklistFormat) -> Vec<(Option<String>, String, String)> {
+    parse(text, format).0.into_iter().map(|e| (e.time, e.artist, e.title)).collect()
+  }
+
+  fn e(time: Option<&str>, artist: &str, title: &str) -> (Option<String>, String, String) { (time.map(str::to_string), artist.into(), title.into()) }
+
+  /// Pasted from a set's description, as they come.
+  const MESSY: &str = "Tracklist:\n\
+    01. Deadmau5 - Strobe (Original Mix)\n\
+    [00:07:30] Eric Prydz - Opus (Four Tet Rmx) [Astralwerks]\n\
+    3) Guy & Gal – Song\n\
+    12:45 Daft Punk — One More Time (Radio Edit)\n\
+    04 - 808 State - Pacific State\n\
+    1:02:03 - Bicep - Glu\n\
+    ???\n\
+    ID - ID\n";
+
+  #[test]
+  fn messy_text_lines_parse() {
+    assert_eq!(detect(MESSY), TracklistFormat::Text);
+    let (got, bad) = parse(MESSY, TracklistFormat::Text);
+    let got: Vec<_> = got.into_iter().map(|e| (e.time, e.artist, e.title)).collect();
+    assert_eq!(got, [
+      e(None, "Deadmau5", "Strobe (Original Mix)"),
+      e(Some("00:07:30"), "Eric Prydz", "Opus (Four Tet Rmx)"),
+      e(None, "Guy & Gal", "Song"),
+      e(Some("12:45"), "Daft Punk", "One More Time (Radio Edit)"),
+      e(None, "808 State", "Pacific State"),
+      e(Some("1:02:03"), "Bicep", "Glu"),
+      e(None, "ID", "ID"),
+    ]);
+    assert_eq!(bad.iter().map(|(n, raw, _)| (*n, raw.as_str())).collect::<Vec<_>>(), [(1, "Tracklist:"), (8, "???")]);
+  }
+
+  #[test]
+  fn csv_and_rekordbox_exports_parse() {
+    let csv = "Time,Artist,Title\n00:00,\"Prydz, Eric\",Opus\n05:10,Bicep,\"Glue (Extended Mix)\"\n,,\n";
+    assert_eq!(detect(csv), TracklistFormat::Csv);
+    assert_eq!(entries(csv, TracklistFormat::Csv), [e(Some("00:00"), "Prydz, Eric", "Opus"), e(Some("05:10"), "Bicep", "Glue (Extended Mix)")]);
+    assert_eq!(entries("00:00,Bicep,Glue\n", TracklistFormat::Csv), [e(Some("00:00"), "Bicep", "Glue")], "no header: time, artist, title");
+
+    // Rekordbox's history export: UTF-16LE with a BOM, tab-separated.
+    let history = "#\tTrack Title\tArtist\tAlbum\tBPM\r\n1\tStrobe\tdeadmau5\tFor Lack of a Better Name\t128.00\r\n2\tEple\tRöyksopp\tMelody A.M.\t110.00\r\n";
+    let bytes: Vec<u8> = [0xFF, 0xFE].into_iter().chain(history.encode_utf16().flat_map(u16::to_le_bytes)).collect();
+    let text = decode(&bytes);
+    assert_eq!(detect(&text), TracklistFormat::Rekordbox);
+    assert_eq!(entries(&text, TracklistFormat::Rekordbox), [e(None, "deadmau5", "Strobe"), e(None, "Röyksopp", "Eple")]);
+  }
+
+  fn library() -> PathBuf {
+    let dir = test_support::scratch("tracklist");
+    for (i, (artist, title)) in [
+      ("deadmau5", "Strobe"), ("Eric Prydz", "Opus"), ("Eric Prydz", "Opus (Four Tet Remix)"), ("Gal", "Song"),
+      ("Daft Punk", "One More Time"), ("Bicep", "Glue"), ("Bicep", "Apricots"), ("Fred again..", "Delilah (pull me out of this)"), ("Röyksopp", "Eple"),
+    ].iter().enumerate() {
+      test_support::tagged(&dir, &format!("{:02}.mp3", i), &[(ItemKey::TrackArtist, artist), (ItemKey::TrackTitle, title)]);
+    }
+    dir
+  }
+
+  fn file_title(path: &str) -> String {
+    meta_cache::get(Path::new(path)).unwrap().title.unwrap_or_default()
+  }
+
+  #[test]
+  fn remix_names_and_typos_match_the_right_track() {
+    let dir = library();
+    let text = [
+      MESSY,
+      "Deadmaus - Strobe\nDeadmau5 - Strboe\nEric Pryds - Opus\nRoyksopp - Eple\nBicep - Glue (Extended Mix)\n",
+      "Fred Again - Delilah\nDaft Punk - Around The World\nSomeone - Something Else\n",
+    ].concat();
+    let r = import_blocking(&text, TracklistFormat::Auto, Some(vec![dir.to_string_lossy().to_string()])).unwrap();
+    assert_eq!((r.format, r.searched), (TracklistFormat::Text, 9));
+    let matched: Vec<(String, String)> = r.matches.iter().map(|m| (m.entry.raw.clone(), file_title(&m.best.path))).collect();
+    let want = [
+      ("01. Deadmau5 - Strobe (Original Mix)", "Strobe"),
+      ("[00:07:30] Eric Prydz - Opus (Four Tet Rmx) [Astralwerks]", "Opus (Four Tet Remix)"),
+      ("3) Guy & Gal – Song", "Song"),
+      ("12:45 Daft Punk — One More Time (Radio Edit)", "One More Time"),
+      ("1:02:03 - Bicep - Glu", "Glue"),
+      ("Deadmaus - Strobe", "Strobe"),
+      ("Deadmau5 - Strboe", "Strobe"),
+      ("Eric Pryds - Opus", "Opus"),
+      ("Royksopp - Eple", "Eple"),
+      ("Bicep - Glue (Extended Mix)", "Glue"),
+    ];
+    assert_eq!(matched, want.map(|(a, b)| (a.to_string(), b.to_string())));
+    let opus = &r.matches[1];
+    assert_eq!(opus.alternatives.iter().map(|c| file_title(&c.path)).collect::<Vec<_>>(), ["Opus"], "the original is offered too");
+    assert!(r.matches.iter().all(|m| m.best.confidence >= MIN_CONFIDENCE));
+
+    let unmatched: Vec<(&str, Option<String>)> = r.unmatched.iter().map(|u| (u.raw.as_str(), u.best.as_ref().map(|b| file_title(&b.path)))).collect();
+    assert_eq!(unmatched, [
+      ("Tracklist:", None),
+      ("04 - 808 State - Pacific State", None),
+      ("???", None),
+      ("ID - ID", None),
+      // A shortened title or another track by the same artist: a guess to confirm by hand.
+      ("Fred Again - Delilah", Some("Delilah (pull me out of this)".to_string())),
+      ("Daft Punk - Around The World", Some("One More Time".to_string())),
+      ("Someone - Something Else", None),
+    ]);
+  }
+
+  #[test]
+  fn scores_fall_with_edits_and_mix_mismatches() {
+    let k = |a: &str, t: &str| meta_key(a, t).unwrap();
+    assert_eq!(confidence(&k("Bicep", "Glue"), &k("bicep", "GLUE")), 1.0);
+    assert_eq!(confidence(&k("Bicep", "Glue (Dub)"), &k("Bicep", "Glue")), MIX_PENALTY);
+    assert_eq!(token_set_ratio(&["a", "b"], &["b"]), 1.0);
+    assert_eq!(ratio("strobe", "strboe"), 1.0 - 2.0 / 6.0);
+    assert!(confidence(&k("Artist", "Title"), &k("Other", "Words")) < 0.3);
+  }
+}
@@ -0,0 +1,315 @@
+// Per-volume health, to catch a drive that is starting to fail before a save
+// does. Tag reads, tag saves and media-server streams note whether they
+// worked and how long they took (a stream when it ends or fails, with the
+// time its open took), under the volume they hit: the drive letter or
+// share on Windows, the mount point elsewhere. Samples are folded into hourly
+// buckets (counts and a latency histogram) kept HOURS_KEPT hours and flushed
+// to data dir `volume_health.json` every FLUSH_EVERY and on exit, so a
+// restart keeps the picture. `volume_health_report` sums the last 24 hours
+// per volume. When a volume's current hour crosses ERROR_RATE or SLOW_P95_MS
+// (over MIN_OPS operations or more) `volume-degraded` is emitted, once an hour
+// per volume. Only I/O errors count as failures, and not a file that isn't
+// there: a file that doesn't parse was still read, a tag lofty won't encode
+// says nothing about the drive.
+
+use std::{collections::HashMap, fs, io, path::{Component, Path, PathBuf, Prefix}, time::Duration};
+use chrono::Utc;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use crate::{data_dir, log_line, write_atomic, write_verify};
+
+/// Upper bounds of the latency buckets; past the last is one more bucket.
+const BOUNDS_MS: [u64; 13] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000, 10000];
+const HOURS_KEPT: i64 = 24;
+const FLUSH_EVERY: Duration = Duration::from_secs(60);
+const MIN_OPS: u64 = 20;
+const ERROR_RATE: f64 = 0.05;
+const SLOW_P95_MS: u64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op { Read, Write, Stream }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bucket {
+  ok: u64,
+  failed: u64,
+  /// Operations per BOUNDS_MS bucket, plus one past the last.
+  hist: Vec<u64>,
+}
+
+impl Bucket {
+  fn add(&mut self, other: &Bucket) {
+    self.ok += other.ok;
+    self.failed += other.failed;
+    if self.hist.len() < other.hist.len() { self.hist.resize(other.hist.len(), 0); }
+    for (a, b) in self.hist.iter_mut().zip(&other.hist) { *a += b; }
+  }
+}
+
+/// One bucket in `volume_health.json`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredBucket {
+  volume: String,
+  op: Op,
+  /// Hours since the Unix epoch, UTC.
+  hour: i64,
+  #[serde(flatten)]
+  bucket: Bucket,
+}
+
+type BucketKey = (String, Op, i64);
+
+struct State {
+  buckets: HashMap<BucketKey, Bucket>,
+  dirty: bool,
+  /// Volume -> hour `volume-degraded` was last emitted for it.
+  warned: HashMap<String, i64>,
+}
+
+static APP: OnceCell<tauri::AppHandle> = OnceCell::new();
+static STATE: Lazy<Mutex<State>> = Lazy::new(|| Mutex::new(State { buckets: load(), dirty: false, warned: HashMap::new() }));
+
+fn store_path() -> PathBuf { data_dir().join("volume_health.json") }
+
+fn hour_now() -> i64 { Utc::now().timestamp() / 3600 }
+
+fn load() -> HashMap<BucketKey, Bucket> {
+  let stored: Vec<StoredBucket> = fs::read_to_string(store_path()).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default();
+  let oldest = hour_now() - HOURS_KEPT;
+  stored.into_iter().filter(|b| b.hour > oldest).map(|b| ((b.volume, b.op, b.hour), b.bucket)).collect()
+}
+
+/// Write the buckets out when something changed, dropping expired ones.
+pub fn flush() {
+  let mut st = STATE.lock();
+  let oldest = hour_now() - HOURS_KEPT;
+  st.buckets.retain(|(_, _, hour), _| *hour > oldest);
+  if !st.dirty { return; }
+  let stored: Vec<StoredBucket> = st.buckets.iter().map(|((volume, op, hour), b)| StoredBucket { volume: volume.clone(), op: *op, hour: *hour, bucket: b.clone() }).collect();
+  match serde_json::to_vec(&stored).map_err(|e| e.to_string()).and_then(|json| write_atomic(&store_path(), &json)) {
+    Ok(()) => st.dirty = false,
+    Err(e) => log_line(&format!("volume_health flush failed: {}", e)),
+  }
+}
+
+pub fn start(app: tauri::AppHandle) {
+  let _ = APP.set(app);
+  std::thread::spawn(|| loop {
+    std::thread::sleep(FLUSH_EVERY);
+    flush();
+  });
+}
+
+/// The drive or share on Windows, the innermost mount point elsewhere.
+pub fn volume_of(p: &Path) -> String {
+  if let Some(Component::Prefix(pre)) = p.components().next() {
+    return match pre.kind() {
+      Prefix::Disk(d) | Prefix::VerbatimDisk(d) => format!("{}:", (d as char).to_ascii_uppercase()),
+      Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => format!(r"\\{}\{}", server.to_string_lossy(), share.to_string_lossy()),
+      _ => pre.as_os_str().to_string_lossy().to_string(),
+    };
+  }
+  write_verify::mount_point(p).map(|m| m.to_string_lossy().to_string()).unwrap_or_else(|| "/".into())
+}
+
+/// `e` says something about the drive (a missing file doesn't).
+pub fn counts(e: &io::Error) -> bool { e.kind() != io::ErrorKind::NotFound }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpHealth {
+  ops: u64,
+  failures: u64,
+  error_rate: f64,
+  /// Upper bound of the latency bucket the 95th percentile falls in; `None`
+  /// with no operations, and 10000 also stands for anything slower.
+  p95_ms: Option<u64>,
+}
+
+fn health(b: &Bucket) -> OpHealth {
+  let ops = b.ok + b.failed;
+  let mut seen = 0;
+  let p95_ms = (ops > 0).then(|| {
+    let rank = (ops * 95).div_ceil(100);
+    let i = b.hist.iter().position(|n| { seen += n; seen >= rank }).unwrap_or(BOUNDS_MS.len());
+    BOUNDS_MS[i.min(BOUNDS_MS.len() - 1)]
+  });
+  OpHealth { ops, failures: b.failed, error_rate: if ops == 0 { 0.0 } else { b.failed as f64 / ops as f64 }, p95_ms }
+}
+
+fn over_threshold(h: &OpHealth) -> bool {
+  h.ops >= MIN_OPS && (h.error_rate >= ERROR_RATE || h.p95_ms.is_some_and(|p| p >= SLOW_P95_MS))
+}
+
+/// `volume`'s operations in `hour`, all kinds together.
+fn hour_total(st: &State, volume: &str, hour: i64) -> Bucket {
+  let mut total = Bucket::default();
+  for op in [Op::Read, Op::Write, Op::Stream] {
+    if let Some(b) = st.buckets.get(&(volume.to_string(), op, hour)) { total.add(b); }
+  }
+  total
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Degraded {
+  volume: String,
+  #[serde(flatten)]
+  last_hour: OpHealth,
+}
+
+/// One operation on `p` that took `took`.
+pub fn record(p: &Path, op: Op, took: Duration, ok: bool) {
+  let volume = volume_of(p);
+  let hour = hour_now();
+  let ms = took.as_millis() as u64;
+  let slot = BOUNDS_MS.iter().position(|&b| ms <= b).unwrap_or(BOUNDS_MS.len());
+  let alert = {
+    let mut st = STATE.lock();
+    let b = st.buckets.entry((volume.clone(), op, hour)).or_default();
+    if b.hist.len() <= BOUNDS_MS.len() { b.hist.resize(BOUNDS_MS.len() + 1, 0); }
+    if ok { b.ok += 1; } else { b.failed += 1; }
+    b.hist[slot] += 1;
+    st.dirty = true;
+    let last_hour = health(&hour_total(&st, &volume, hour));
+    let fresh = over_threshold(&last_hour) && st.warned.get(&volume) != Some(&hour);
+    if fresh { st.warned.insert(volume.clone(), hour); }
+    fresh.then_some(Degraded { volume, last_hour })
+  };
+  if let Some(d) = alert {
+    log_line(&format!("volume degraded volume=\"{}\" ops={} failures={} p95_ms={:?}", d.volume, d.last_hour.ops, d.last_hour.failures, d.last_hour.p95_ms));
+    if let Some(app) = APP.get() { let _ = app.emit_all("volume-degraded", d); }
+  }
+}
+
+/// How a lofty result counts: `Some(false)` only for an I/O error other than
+/// a missing file, `None` for a missing file. A file that doesn't parse or a
+/// tag that won't encode still reached the drive.
+fn outcome<T>(res: &lofty::error::Result<T>) -> Option<bool> {
+  match res.as_ref().err().map(|e| e.kind()) {
+    Some(lofty::error::ErrorKind::Io(e)) if !counts(e) => None,
+    Some(lofty::error::ErrorKind::Io(_)) => Some(false),
+    _ => Some(true),
+  }
+}
+
+/// A tag read or save of `p` through lofty (see `outcome`).
+pub fn note<T>(p: &Path, op: Op, took: Duration, res: &lofty::error::Result<T>) {
+  if let Some(ok) = outcome(res) { record(p, op, took, ok); }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeHealth {
+  volume: String,
+  reads: OpHealth,
+  writes: OpHealth,
+  streams: OpHealth,
+  /// The current hour is over a threshold (see the header).
+  degraded: bool,
+}
+
+/// Error rates and p95 latencies per volume over the last 24 hours, worst
+/// error rate first.
+#[tauri::command]
+pub fn volume_health_report() -> Vec<VolumeHealth> {
+  let st = STATE.lock();
+  let (hour, oldest) = (hour_now(), hour_now() - 24);
+  let mut by_volume: HashMap<&str, [Bucket; 3]> = HashMap::new();
+  for ((volume, op, _), b) in st.buckets.iter().filter(|((_, _, h), _)| *h > oldest) {
+    by_volume.entry(volume.as_str()).or_default()[*op as usize].add(b);
+  }
+  let mut out: Vec<VolumeHealth> = by_volume.into_iter().map(|(volume, [r, w, s])| VolumeHealth {
+    volume: volume.to_string(),
+    reads: health(&r),
+    writes: health(&w),
+    streams: health(&s),
+    degraded: over_threshold(&health(&hour_total(&st, volume, hour))),
+  }).collect();
+  let rate = |v: &VolumeHealth| (v.reads.failures + v.writes.failures + v.streams.failures) as f64 / (v.reads.ops + v.writes.ops + v.streams.ops).max(1) as f64;
+  out.sort_by(|a, b| rate(b).total_cmp(&rate(a)).then_with(|| a.volume.cmp(&b.volume)));
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::error::{ErrorKind, LoftyError};
+  use crate::test_support;
+
+  #[cfg(windows)]
+  #[test]
+  fn windows_paths_go_to_their_drive_or_share() {
+    assert_eq!(volume_of(Path::new(r"d:\Crates\a.mp3")), "D:");
+    assert_eq!(volume_of(Path::new(r"\\?\E:\Crates\a.mp3")), "E:");
+    assert_eq!(volume_of(Path::new(r"\\nas\music\a.mp3")), r"\\nas\music");
+    assert_eq!(volume_of(Path::new(r"\\?\UNC\nas\music\a.mp3")), r"\\nas\music");
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn linux_paths_go_to_their_mount_point() {
+    assert_eq!(volume_of(Path::new("/proc/self/status")), "/proc");
+    assert_eq!(volume_of(Path::new("/")), "/");
+  }
+
+  #[cfg(target_os = "macos")]
+  #[test]
+  fn macos_paths_go_to_their_mount_point() {
+    assert_eq!(volume_of(Path::new("/dev/null")), "/dev");
+    assert_eq!(volume_of(Path::new("/")), "/");
+  }
+
+  #[test]
+  fn only_io_errors_count_against_the_drive() {
+    let io = |kind| Err::<(), _>(LoftyError::from(io::Error::from(kind)));
+    assert_eq!(outcome(&io(io::ErrorKind::PermissionDenied)), Some(false));
+    assert_eq!(outcome(&io(io::ErrorKind::NotFound)), None);
+    assert_eq!(outcome(&Err::<(), _>(LoftyError::new(ErrorKind::UnknownFormat))), Some(true));
+    assert_eq!(outcome(&Err::<(), _>(LoftyError::new(ErrorKind::TextDecode("bad UTF-16")))), Some(true));
+    assert_eq!(outcome(&Ok(())), Some(true));
+  }
+
+  #[test]
+  fn the_buckets_survive_a_restart() {
+    let dir = test_support::scratch("volume-health");
+    let p = dir.join("a.mp3");
+    record(&p, Op::Write, Duration::from_millis(3), false);
+    flush();
+    let k = (volume_of(&p), Op::Write, hour_now());
+    let reloaded = load();
+    assert!(reloaded.get(&k).is_some_and(|b| b.failed >= 1 && b.hist[2] >= 1), "{:?}", reloaded.get(&k));
+    assert!(volume_health_report().iter().any(|v| v.volume == k.0 && v.writes.failures >= 1));
+  }
+
+  /// Fails every read, like a drive dropping out mid-stream.
+  struct Failing;
+
+  impl tokio::io::AsyncRead for Failing {
+    fn poll_read(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, _: &mut tokio::io::ReadBuf<'_>) -> std::task::Poll<io::Result<()>> {
+      std::task::Poll::Ready(Err(io::Error::other("I/O error")))
+    }
+  }
+
+  #[test]
+  fn a_read_error_mid_stream_is_a_failed_stream() {
+    use tokio::io::AsyncReadExt;
+    let dir = test_support::scratch("volume-health-stream");
+    let p = test_support::audio(&dir, "a.mp3");
+    let k = (volume_of(&p), Op::Stream, hour_now());
+    let failed = || STATE.lock().buckets.get(&k).map_or(0, |b| b.failed);
+    let before = failed();
+    let Some(slot) = crate::StreamSlot::try_acquire() else { panic!("no stream slot") };
+    let mut reader = crate::SlotReader { inner: Failing, _slot: slot, stream: crate::media_streams::register(&p), health: Some((p.clone(), Duration::from_millis(1))) };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    assert!(rt.block_on(reader.read(&mut [0u8; 16])).is_err());
+    drop(reader);
+    assert!(failed() > before, "noted once, as failed");
+  }
+}
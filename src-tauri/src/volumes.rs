@@ -0,0 +1,203 @@
+// Removable volumes (USB sticks, SD cards) disappearing mid-session. A path
+// is on a lost volume when the root it lives under no longer exists: the
+// mount point for /Volumes, /media, /run/media and /mnt paths, otherwise a
+// folder the user scanned. Lost roots emit `volume-lost` once, comment writes
+// aimed at them are queued instead of failing, and `retry_volume` flushes
+// the queue when the root is back.
+
+use std::{collections::HashSet, io, path::{Component, Path, PathBuf}};
+use chrono::Local;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{audit, command_span, error::CmdError, log_line, portable, write_comment_as};
+
+static APP: OnceCell<tauri::AppHandle> = OnceCell::new();
+static ROOTS: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static LOST: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+static QUEUE: Lazy<Mutex<Vec<PendingWrite>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingWrite {
+  pub path: String,
+  pub root: String,
+  pub comment: String,
+  pub source: audit::Source,
+  pub queued_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VolumeEvent {
+  root: String,
+  pending_writes: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteFailure {
+  path: String,
+  error: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryReport {
+  root: String,
+  available: bool,
+  flushed: Vec<String>,
+  failed: Vec<WriteFailure>,
+  still_queued: usize,
+}
+
+pub fn init(app: tauri::AppHandle) { let _ = APP.set(app); }
+
+/// Remember a scanned folder as the root for paths under it.
+pub fn register_root(root: &Path) {
+  let mut roots = ROOTS.lock();
+  if !roots.iter().any(|r| r == root) { roots.push(root.to_path_buf()); }
+}
+
+/// Mount point for the usual removable-media locations, or the drive root on Windows.
+fn mount_root(p: &Path) -> Option<PathBuf> {
+  let parts: Vec<Component> = p.components().collect();
+  if let Some(Component::Prefix(_)) = parts.first() {
+    return Some(parts.iter().take(2).collect());
+  }
+  let names: Vec<String> = parts.iter().skip(1).map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+  let depth = match names.first().map(|s| s.as_str()) {
+    Some("Volumes") | Some("mnt") => 2,
+    Some("media") => 3,
+    Some("run") if names.get(1).map(|s| s.as_str()) == Some("media") => 4,
+    _ => return None,
+  };
+  if names.len() < depth { return None; }
+  Some(parts.iter().take(depth + 1).collect())
+}
+
+fn root_for(p: &Path) -> Option<PathBuf> {
+  mount_root(p).or_else(|| {
+    ROOTS.lock().iter().filter(|r| p.starts_with(r)).max_by_key(|r| r.components().count()).cloned()
+  })
+}
+
+/// ENOENT / ENODEV / ENXIO (and Windows "device not ready"): what a yanked drive looks like.
+pub fn is_volume_error(e: &io::Error) -> bool {
+  if e.kind() == io::ErrorKind::NotFound { return true; }
+  #[cfg(unix)]
+  { matches!(e.raw_os_error(), Some(6) | Some(19)) }
+  #[cfg(windows)]
+  { matches!(e.raw_os_error(), Some(21)) }
+  #[cfg(not(any(unix, windows)))]
+  { false }
+}
+
+/// Already-known lost root containing `p`.
+pub fn lost_root(p: &Path) -> Option<PathBuf> {
+  LOST.lock().iter().find(|r| p.starts_with(r)).cloned()
+}
+
+fn emit(event: &str, root: &Path) {
+  let pending = QUEUE.lock().iter().filter(|w| Path::new(&w.path).starts_with(root)).count();
+  if let Some(app) = APP.get() {
+    let _ = app.emit_all(event, VolumeEvent { root: root.to_string_lossy().to_string(), pending_writes: pending });
+  }
+}
+
+/// Check whether `p` sits on a volume that has gone away; marks and announces it the first time.
+pub fn detect(p: &Path) -> Option<PathBuf> {
+  if let Some(r) = lost_root(p) { return Some(r); }
+  let root = root_for(p)?;
+  if root.exists() { return None; }
+  if LOST.lock().insert(root.clone()) {
+    log_line(&format!("volume lost root=\"{}\"", root.display()));
+    emit("volume-lost", &root);
+  }
+  Some(root)
+}
+
+pub fn unavailable(p: &Path, root: &Path, queued: bool) -> CmdError {
+  CmdError::VolumeUnavailable {
+    path: p.to_string_lossy().to_string(),
+    root: root.to_string_lossy().to_string(),
+    message: format!(
+      "{} is not available (drive removed?).{}",
+      root.display(),
+      if queued { " The change is queued and will be written when it is back." } else { "" }
+    ),
+    queued,
+  }
+}
+
+/// Queue a comment write for a lost root; a newer write to the same file replaces the older one.
+pub fn queue_comment(path: &str, comment: &str, source: audit::Source, root: &Path) -> CmdError {
+  let mut q = QUEUE.lock();
+  q.retain(|w| w.path != path);
+  q.push(PendingWrite {
+    path: path.to_string(),
+    root: root.to_string_lossy().to_string(),
+    comment: comment.to_string(),
+    source,
+    queued_at: Local::now().to_rfc3339(),
+  });
+  drop(q);
+  unavailable(Path::new(path), root, true)
+}
+
+/// Point queued writes under `old_root` at `new_root`; see `portable::rebase_references`.
+pub fn rebase(old_root: &Path, new_root: &Path) -> usize {
+  let mut q = QUEUE.lock();
+  let mut n = 0;
+  for w in q.iter_mut() {
+    let Some(to) = portable::rebased(Path::new(&w.path), old_root, new_root) else { continue };
+    w.path = to.to_string_lossy().to_string();
+    if let Some(root) = portable::rebased(Path::new(&w.root), old_root, new_root) { w.root = root.to_string_lossy().to_string(); }
+    n += 1;
+  }
+  n
+}
+
+#[tauri::command]
+pub fn pending_writes() -> Vec<PendingWrite> { QUEUE.lock().clone() }
+
+/// If `root` is mounted again: clear the lost state, re-validate queued paths and write them.
+#[tauri::command]
+pub async fn retry_volume(root: String) -> Result<RetryReport, String> {
+  let _span = command_span("retry_volume");
+  tauri::async_runtime::spawn_blocking(move || {
+    let r = PathBuf::from(&root);
+    if !r.exists() {
+      let still_queued = QUEUE.lock().iter().filter(|w| Path::new(&w.path).starts_with(&r)).count();
+      return Ok(RetryReport { root, available: false, flushed: vec![], failed: vec![], still_queued });
+    }
+    LOST.lock().retain(|l| !l.starts_with(&r) && !r.starts_with(l));
+    let batch: Vec<PendingWrite> = {
+      let mut q = QUEUE.lock();
+      let (mine, rest): (Vec<_>, Vec<_>) = q.drain(..).partition(|w| Path::new(&w.path).starts_with(&r));
+      *q = rest;
+      mine
+    };
+    let mut flushed = Vec::new();
+    let mut failed = Vec::new();
+    for w in batch {
+      if !Path::new(&w.path).is_file() {
+        failed.push(WriteFailure { path: w.path, error: "file no longer exists at this path".into() });
+        continue;
+      }
+      // Another failure here re-queues through write_comment_as if the drive went away again.
+      match write_comment_as(&w.path, &w.comment, w.source) {
+        Ok(_) => flushed.push(w.path),
+        Err(e) => failed.push(WriteFailure { path: w.path, error: e.to_string() }),
+      }
+    }
+    let still_queued = QUEUE.lock().iter().filter(|w| Path::new(&w.path).starts_with(&r)).count();
+    log_line(&format!("retry_volume root=\"{}\" flushed={} failed={}", root, flushed.len(), failed.len()));
+    emit("volume-restored", &r);
+    Ok(RetryReport { root, available: true, flushed, failed, still_queued })
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,268 @@
+// Polling folder watcher. Cheap directory listings every few seconds are
+// plenty for DJ folders and behave the same on every OS and on network or
+// removable volumes, where native notifications are unreliable.
+//
+// With `track_renames`, a removal is held back for a short window and paired
+// with a file that appears (in any watched root) with the same size and the
+// same fingerprint: a hash of one block from the middle of the file, which is
+// audio data rather than tags for any real track. A pair is one `Renamed`.
+
+use std::{collections::HashMap, fs, io::{Read, Seek, SeekFrom}, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+use sha2::{Digest, Sha256};
+
+use crate::{ignore_files::Rules, long_paths, supported_ext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+  pub len: u64,
+  pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsEvent {
+  /// New file whose size/mtime stayed put for the settle period (not half-copied).
+  Added(PathBuf),
+  Removed(PathBuf),
+  Modified(PathBuf),
+  /// Only with `track_renames`; replaces the `Removed` + `Added` pair.
+  Renamed { from: PathBuf, to: PathBuf },
+}
+
+#[derive(Default)]
+struct RootState {
+  known: HashMap<PathBuf, FileStamp>,
+  settling: HashMap<PathBuf, (FileStamp, Instant)>,
+}
+
+pub struct PollWatcher {
+  roots: HashMap<PathBuf, RootState>,
+  settle: Duration,
+  /// Rename window; `None` reports plain removals and additions.
+  renames: Option<Duration>,
+  /// Fingerprints of known files, kept only when tracking renames.
+  prints: HashMap<PathBuf, (FileStamp, u64)>,
+  /// Removed files waiting for a partner: (path, size, fingerprint, since).
+  vanished: Vec<(PathBuf, u64, u64, Instant)>,
+}
+
+const PRINT_BLOCK: u64 = 64 * 1024;
+
+pub fn stamp_of(p: &Path) -> Option<FileStamp> {
+  let m = fs::metadata(long_paths::extended(p)).ok()?;
+  Some(FileStamp { len: m.len(), modified: m.modified().ok() })
+}
+
+/// Hash of the PRINT_BLOCK bytes in the middle of the file.
+fn fingerprint(p: &Path, len: u64) -> Option<u64> {
+  let mut f = fs::File::open(long_paths::extended(p)).ok()?;
+  let start = len.saturating_sub(PRINT_BLOCK) / 2;
+  f.seek(SeekFrom::Start(start)).ok()?;
+  let mut buf = Vec::with_capacity(PRINT_BLOCK as usize);
+  f.take(PRINT_BLOCK).read_to_end(&mut buf).ok()?;
+  let d = Sha256::digest(&buf);
+  Some(u64::from_le_bytes(d[..8].try_into().ok()?))
+}
+
+fn list(root: &Path) -> Option<HashMap<PathBuf, FileStamp>> {
+  let rd = fs::read_dir(long_paths::extended(root)).ok()?;
+  let rules = Rules::for_root(root);
+  let mut out = HashMap::new();
+  for e in rd.flatten() {
+    let p = long_paths::simplified(&e.path()).into_owned();
+    if e.file_type().is_ok_and(|t| t.is_file()) && supported_ext(&p) && rules.ignored_by(&p, false).is_none() {
+      if let Some(s) = stamp_of(&p) { out.insert(p, s); }
+    }
+  }
+  Some(out)
+}
+
+impl PollWatcher {
+  pub fn new(settle: Duration) -> Self {
+    Self { roots: HashMap::new(), settle, renames: None, prints: HashMap::new(), vanished: Vec::new() }
+  }
+
+  /// Pair removals with additions that show up within `window`. A removal is
+  /// reported that much later when nothing matches.
+  pub fn track_renames(mut self, window: Duration) -> Self {
+    self.renames = Some(window);
+    self
+  }
+
+  /// Fingerprint for `p` at `stamp`, hashing again only when the stamp moved.
+  fn remember_print(&mut self, p: &Path, stamp: FileStamp) {
+    if self.renames.is_none() { return; }
+    if self.prints.get(p).is_some_and(|(s, _)| *s == stamp) { return; }
+    match fingerprint(p, stamp.len) {
+      Some(h) => { self.prints.insert(p.to_path_buf(), (stamp, h)); }
+      None => { self.prints.remove(p); }
+    }
+  }
+
+  /// Replace the watched set. Newly added roots are snapshotted so files that
+  /// already exist don't show up as `Added`.
+  pub fn set_roots(&mut self, roots: &[PathBuf]) {
+    self.roots.retain(|r, _| roots.contains(r));
+    for r in roots {
+      if !self.roots.contains_key(r) {
+        let known = list(r).unwrap_or_default();
+        for (p, s) in &known { self.remember_print(p, *s); }
+        self.roots.insert(r.clone(), RootState { known, settling: HashMap::new() });
+      }
+    }
+    let roots = &self.roots;
+    self.prints.retain(|p, _| roots.values().any(|st| st.known.contains_key(p)));
+  }
+
+  pub fn poll(&mut self) -> Vec<FsEvent> {
+    let mut events = Vec::new();
+    let now = Instant::now();
+    let mut added = Vec::new();
+    for (root, st) in self.roots.iter_mut() {
+      // Unreadable root (unplugged, permissions): report nothing rather than "all removed".
+      let Some(current) = list(root) else { continue };
+      for (p, stamp) in &current {
+        if let Some(prev) = st.known.get_mut(p) {
+          if prev != stamp {
+            *prev = *stamp;
+            events.push(FsEvent::Modified(p.clone()));
+          }
+          continue;
+        }
+        match st.settling.get(p) {
+          Some((s, since)) if s == stamp => {
+            if now.duration_since(*since) >= self.settle {
+              st.settling.remove(p);
+              st.known.insert(p.clone(), *stamp);
+              added.push((p.clone(), *stamp));
+            }
+          }
+          _ => { st.settling.insert(p.clone(), (*stamp, now)); }
+        }
+      }
+      st.known.retain(|p, _| {
+        let keep = current.contains_key(p);
+        if !keep {
+          match (self.renames, self.prints.remove(p)) {
+            (Some(_), Some((s, h))) => self.vanished.push((p.clone(), s.len, h, now)),
+            _ => events.push(FsEvent::Removed(p.clone())),
+          }
+        }
+        keep
+      });
+      st.settling.retain(|p, _| current.contains_key(p));
+    }
+    for ev in &events {
+      if let FsEvent::Modified(p) = ev {
+        if let Some(stamp) = stamp_of(p) { self.remember_print(p, stamp); }
+      }
+    }
+    for (p, stamp) in added {
+      self.remember_print(&p, stamp);
+      let print = self.prints.get(&p).map(|(_, h)| *h);
+      let partner = self.vanished.iter().position(|(_, len, h, _)| *len == stamp.len && Some(*h) == print);
+      match partner {
+        Some(i) => events.push(FsEvent::Renamed { from: self.vanished.remove(i).0, to: p }),
+        None => events.push(FsEvent::Added(p)),
+      }
+    }
+    if let Some(window) = self.renames {
+      // Outlive the settle period too: the new name only counts once it settled.
+      let wait = window + self.settle;
+      self.vanished.retain(|(p, _, _, since)| {
+        let keep = now.duration_since(*since) < wait;
+        if !keep { events.push(FsEvent::Removed(p.clone())); }
+        keep
+      });
+    }
+    events
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+  use crate::test_support;
+
+  const WINDOW: Duration = Duration::from_millis(100);
+
+  /// `len` bytes that differ with `seed`, like two different recordings.
+  fn audio_like(p: &Path, len: usize, seed: u8) {
+    fs::write(p, (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect::<Vec<_>>()).unwrap();
+  }
+
+  fn watching(roots: &[PathBuf]) -> PollWatcher {
+    let mut w = PollWatcher::new(Duration::ZERO).track_renames(WINDOW);
+    w.set_roots(roots);
+    w
+  }
+
+  /// Polls until nothing is settling or held back any more.
+  fn settle(w: &mut PollWatcher) -> Vec<FsEvent> {
+    let mut events = w.poll();
+    events.extend(w.poll());
+    thread::sleep(WINDOW);
+    events.extend(w.poll());
+    events
+  }
+
+  #[test]
+  fn a_rename_is_one_event() {
+    let dir = test_support::scratch("watch-rename");
+    let old = dir.join("01 Intro.mp3");
+    audio_like(&old, 200_000, 1);
+    let mut w = watching(std::slice::from_ref(&dir));
+    let new = dir.join("Intro (relocated).mp3");
+    fs::rename(&old, &new).unwrap();
+    assert_eq!(settle(&mut w), [FsEvent::Renamed { from: old, to: new }]);
+  }
+
+  #[test]
+  fn a_move_between_watched_folders_is_a_rename_even_with_new_tags() {
+    let dir = test_support::scratch("watch-move");
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    fs::create_dir_all(&a).unwrap();
+    fs::create_dir_all(&b).unwrap();
+    let old = a.join("track.mp3");
+    audio_like(&old, 200_000, 2);
+    let mut w = watching(&[a.clone(), b.clone()]);
+    let new = b.join("track.mp3");
+    fs::rename(&old, &new).unwrap();
+    // A tag edit on the way changes the head, not the middle of the file.
+    let mut bytes = fs::read(&new).unwrap();
+    bytes[..16].copy_from_slice(b"ID3 retagged....");
+    fs::write(&new, bytes).unwrap();
+    assert_eq!(settle(&mut w), [FsEvent::Renamed { from: old, to: new }]);
+  }
+
+  #[test]
+  fn a_delete_and_an_unrelated_add_stay_apart() {
+    let dir = test_support::scratch("watch-delete-add");
+    let (gone, other) = (dir.join("gone.mp3"), dir.join("other.mp3"));
+    audio_like(&gone, 200_000, 3);
+    audio_like(&other, 150_000, 3);
+    let mut w = watching(std::slice::from_ref(&dir));
+    fs::remove_file(&gone).unwrap();
+    fs::remove_file(&other).unwrap();
+    // Same size as the deleted file, other audio; and the same audio cut shorter.
+    let (same_size, same_audio) = (dir.join("new.mp3"), dir.join("edit.mp3"));
+    audio_like(&same_size, 200_000, 4);
+    audio_like(&same_audio, 149_000, 3);
+    let mut events = settle(&mut w);
+    events.sort_by_key(|e| format!("{:?}", e));
+    assert_eq!(events, [FsEvent::Added(same_audio), FsEvent::Added(same_size), FsEvent::Removed(gone), FsEvent::Removed(other)]);
+  }
+
+  #[test]
+  fn without_tracking_a_rename_is_a_remove_and_an_add() {
+    let dir = test_support::scratch("watch-plain");
+    let old = dir.join("a.mp3");
+    audio_like(&old, 10_000, 5);
+    let mut w = PollWatcher::new(Duration::ZERO);
+    w.set_roots(std::slice::from_ref(&dir));
+    let new = dir.join("b.mp3");
+    fs::rename(&old, &new).unwrap();
+    assert_eq!(w.poll(), [FsEvent::Removed(old)]);
+    assert_eq!(w.poll(), [FsEvent::Added(new)]);
+  }
+}
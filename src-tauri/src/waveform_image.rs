@@ -0,0 +1,173 @@
+// Server-side waveform PNGs (social posts, exports): min/max columns from the
+// peaks cache, optional gradient, optional embedded cover in a corner.
+
+use std::{io::Cursor, path::{Path, PathBuf}};
+use base64::{engine::general_purpose, Engine as _};
+use image::{imageops, DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{command_span, front_cover, jobs::JobHandle, log_line, peaks::{self, Peaks}, read_tagged, write_atomic};
+
+/// Data URLs past this go through IPC as one string; ask for a file instead.
+const DATA_URL_CAP: usize = 4 * 1024 * 1024;
+const MAX_SIDE: u32 = 8192;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Corner { TopLeft, TopRight, BottomLeft, BottomRight }
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct WaveformStyle {
+  /// `#rgb`, `#rrggbb` or `#rrggbbaa`; default near-black.
+  background: Option<String>,
+  fill: Option<String>,
+  /// When set, the fill fades from `fill` (left) to `fill_end` (right).
+  fill_end: Option<String>,
+  cover_corner: Option<Corner>,
+  /// Cover edge in px; defaults to half the image height.
+  cover_size: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedWaveform {
+  width: u32,
+  height: u32,
+  bytes: usize,
+  dest: Option<String>,
+  data_url: Option<String>,
+}
+
+fn parse_hex(s: &str) -> Result<Rgba<u8>, String> {
+  let h = s.trim().trim_start_matches('#');
+  let nib = |i: usize| u8::from_str_radix(&h[i..i + 1], 16).map(|v| v * 17);
+  let byte = |i: usize| u8::from_str_radix(&h[i..i + 2], 16);
+  let bad = |_| format!("invalid color: {}", s);
+  if !h.is_ascii() { return Err(format!("invalid color: {}", s)); }
+  match h.len() {
+    3 => Ok(Rgba([nib(0).map_err(bad)?, nib(1).map_err(bad)?, nib(2).map_err(bad)?, 255])),
+    6 | 8 => {
+      let a = if h.len() == 8 { byte(6).map_err(bad)? } else { 255 };
+      Ok(Rgba([byte(0).map_err(bad)?, byte(2).map_err(bad)?, byte(4).map_err(bad)?, a]))
+    }
+    _ => Err(format!("invalid color: {}", s)),
+  }
+}
+
+fn color_or(s: &Option<String>, default: Rgba<u8>) -> Result<Rgba<u8>, String> {
+  s.as_deref().map(parse_hex).unwrap_or(Ok(default))
+}
+
+fn lerp(a: Rgba<u8>, b: Rgba<u8>, t: f32) -> Rgba<u8> {
+  let mut out = [0u8; 4];
+  for (i, o) in out.iter_mut().enumerate() {
+    *o = (a.0[i] as f32 + (b.0[i] as f32 - a.0[i] as f32) * t).round() as u8;
+  }
+  Rgba(out)
+}
+
+fn draw_peaks(img: &mut RgbaImage, pk: &Peaks, fill: Rgba<u8>, fill_end: Option<Rgba<u8>>) {
+  let (w, h) = (img.width(), img.height());
+  let buckets = pk.min.len().min(pk.max.len());
+  if buckets == 0 { return; }
+  let mid = (h as f32 - 1.0) / 2.0;
+  for x in 0..w {
+    let start = x as usize * buckets / w as usize;
+    let end = ((x as usize + 1) * buckets / w as usize).clamp(start + 1, buckets);
+    let lo = pk.min[start..end].iter().copied().fold(f32::INFINITY, f32::min).clamp(-1.0, 1.0);
+    let hi = pk.max[start..end].iter().copied().fold(f32::NEG_INFINITY, f32::max).clamp(-1.0, 1.0);
+    let top = (mid - hi * mid).floor().max(0.0) as u32;
+    let bottom = (mid - lo * mid).ceil().min(h as f32 - 1.0) as u32;
+    let color = match fill_end {
+      Some(e) => lerp(fill, e, x as f32 / (w.max(2) - 1) as f32),
+      None => fill,
+    };
+    for y in top..=bottom.max(top) { img.put_pixel(x, y, color); }
+  }
+}
+
+fn composite_cover(img: &mut RgbaImage, cover_bytes: &[u8], corner: Corner, size: u32) -> Result<(), String> {
+  let (w, h) = (img.width(), img.height());
+  let size = size.min(w).min(h);
+  if size == 0 { return Ok(()); }
+  let cover = image::load_from_memory(cover_bytes).map_err(|e| format!("cover decode failed: {}", e))?;
+  let cover = cover.resize_to_fill(size, size, imageops::FilterType::Triangle).to_rgba8();
+  let margin = (size / 16) as i64;
+  let (x, y) = match corner {
+    Corner::TopLeft => (margin, margin),
+    Corner::TopRight => ((w - size) as i64 - margin, margin),
+    Corner::BottomLeft => (margin, (h - size) as i64 - margin),
+    Corner::BottomRight => ((w - size) as i64 - margin, (h - size) as i64 - margin),
+  };
+  imageops::overlay(img, &cover, x.max(0), y.max(0));
+  Ok(())
+}
+
+fn render_blocking(job: &JobHandle, path: &str, width: u32, height: u32, style: &WaveformStyle) -> Result<Vec<u8>, String> {
+  let p = Path::new(path);
+  let pk = peaks::get_or_compute_peaks(p, Some(job.cancel_flag()), |done, total| job.progress(done, total))?;
+
+  let background = color_or(&style.background, Rgba([16, 16, 20, 255]))?;
+  let fill = color_or(&style.fill, Rgba([79, 209, 197, 255]))?;
+  let fill_end = style.fill_end.as_deref().map(parse_hex).transpose()?;
+  let mut img = RgbaImage::from_pixel(width, height, background);
+  draw_peaks(&mut img, &pk, fill, fill_end);
+
+  if let Some(corner) = style.cover_corner {
+    let tf = read_tagged(p).map_err(|e| e.to_string())?;
+    // No embedded art is not an error; the waveform alone is still useful.
+    if let Some(pic) = front_cover(&tf) {
+      composite_cover(&mut img, pic.data(), corner, style.cover_size.unwrap_or(height / 2))?;
+    }
+  }
+  if job.is_cancelled() { return Err("cancelled".into()); }
+
+  let mut png = Vec::new();
+  DynamicImage::ImageRgba8(img)
+    .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+    .map_err(|e| e.to_string())?;
+  Ok(png)
+}
+
+/// Render a waveform PNG. With `dest` the file is written there; otherwise the
+/// PNG comes back as a data URL (capped, see DATA_URL_CAP). Runs as a job so
+/// long decodes can be cancelled via `cancel_job`.
+#[tauri::command]
+pub async fn render_waveform_image(
+  app: tauri::AppHandle,
+  path: String,
+  width: u32,
+  height: u32,
+  style: Option<WaveformStyle>,
+  dest: Option<String>,
+) -> Result<RenderedWaveform, String> {
+  let _span = command_span("render_waveform_image");
+  if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+    return Err(format!("image size must be 1..={} px per side", MAX_SIDE));
+  }
+  let style = style.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "waveform-image", &path);
+    let res = render_blocking(&job, &path, width, height, &style).and_then(|png| {
+      let bytes = png.len();
+      match &dest {
+        Some(d) => {
+          write_atomic(&PathBuf::from(d), &png)?;
+          Ok(RenderedWaveform { width, height, bytes, dest: Some(d.clone()), data_url: None })
+        }
+        None if bytes > DATA_URL_CAP => Err(format!("rendered PNG is {} bytes (cap {}); pass a destination file instead", bytes, DATA_URL_CAP)),
+        None => {
+          let url = format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(&png));
+          Ok(RenderedWaveform { width, height, bytes, dest: None, data_url: Some(url) })
+        }
+      }
+    });
+    log_line(&format!("render_waveform_image path=\"{}\" {}x{} job={} ok={}", path, width, height, job.id(), res.is_ok()));
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
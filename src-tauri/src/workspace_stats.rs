@@ -0,0 +1,147 @@
+// Library dashboard numbers for a set of roots, from the metadata cache. The
+// roots are walked once, then handled in chunks: each chunk's stale entries
+// are re-read and its entries folded into the totals in place
+// (`meta_cache::visit`), so a library of 100k tracks never sits in memory as
+// metadata. Tracks whose container gives no duration or bitrate are counted
+// as such and left out of the sums and averages. Top tags leave out the bank
+// marker and free-text notes (`tag_suggest::tags`).
+
+use std::{collections::{BTreeMap, HashMap, HashSet}, path::PathBuf, time::{Duration, UNIX_EPOCH}};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::{command_span, ext_lower, jobs::JobHandle, library, log_line, meta_cache, tag_suggest};
+
+const CHUNK: usize = 1000;
+const TOP_TAGS: usize = 20;
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatStats {
+  tracks: usize,
+  hours: f64,
+  avg_bitrate_kbps: Option<f64>,
+  #[serde(skip)]
+  bitrate_sum: u64,
+  #[serde(skip)]
+  with_bitrate: usize,
+}
+
+/// Tracks with each field non-empty.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCoverage {
+  title: usize,
+  artist: usize,
+  album: usize,
+  genre: usize,
+  comment: usize,
+  release_date: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+  tag: String,
+  tracks: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceStats {
+  /// Files found under the roots.
+  tracks: usize,
+  /// Files that couldn't be read; they count in `tracks` only.
+  unreadable: usize,
+  hours: f64,
+  /// Tracks with no known duration (not in `hours`).
+  missing_duration: usize,
+  avg_bitrate_kbps: Option<f64>,
+  /// Extension -> totals.
+  formats: BTreeMap<String, FormatStats>,
+  coverage: TagCoverage,
+  top_tags: Vec<TagCount>,
+  /// "YYYY-MM" (local, from file mtimes) -> tracks.
+  additions_per_month: BTreeMap<String, usize>,
+  /// Entries re-read before counting.
+  refreshed: usize,
+  cancelled: bool,
+}
+
+fn avg(sum: u64, n: usize) -> Option<f64> { (n > 0).then(|| sum as f64 / n as f64) }
+
+fn stats_blocking(job: &JobHandle, roots: &[String], recursive: bool) -> Result<WorkspaceStats, String> {
+  let mut paths: Vec<PathBuf> = Vec::new();
+  job.begin_phase("scan", 0);
+  for r in roots { paths.extend(job.timed("walk", || library::audio_files(&PathBuf::from(r), recursive)).map_err(String::from)?); }
+  // Overlapping roots list the same files twice.
+  let mut unique = HashSet::new();
+  paths.retain(|p| unique.insert(p.clone()));
+
+  let mut st = WorkspaceStats { tracks: paths.len(), ..Default::default() };
+  let (mut ms, mut bitrate_sum, mut with_bitrate, mut seen) = (0u64, 0u64, 0usize, 0usize);
+  let mut tags: HashMap<String, usize> = HashMap::new();
+  job.begin_phase("analyze", paths.len() as u64);
+  for (i, chunk) in paths.chunks(CHUNK).enumerate() {
+    if job.is_cancelled() { st.cancelled = true; break; }
+    st.refreshed += job.timed("refresh", || meta_cache::refresh(chunk));
+    job.timed("aggregate", || meta_cache::visit(chunk, |p, m| {
+      seen += 1;
+      let f = st.formats.entry(ext_lower(p)).or_default();
+      f.tracks += 1;
+      match m.duration_ms {
+        Some(d) => { ms += d; f.hours += d as f64 / 3_600_000.0; }
+        None => st.missing_duration += 1,
+      }
+      if let Some(b) = m.bitrate_kbps {
+        bitrate_sum += b as u64;
+        with_bitrate += 1;
+        f.bitrate_sum += b as u64;
+        f.with_bitrate += 1;
+      }
+      let has = |v: &Option<String>| v.as_deref().is_some_and(|s| !s.trim().is_empty());
+      let c = &mut st.coverage;
+      c.title += has(&m.title) as usize;
+      c.artist += has(&m.artist) as usize;
+      c.album += has(&m.album) as usize;
+      c.genre += has(&m.genre) as usize;
+      c.comment += (!m.comment.trim().is_empty()) as usize;
+      c.release_date += has(&m.release_date) as usize;
+      // A tag written twice in one comment counts once.
+      let mut own = tag_suggest::tags(&m.comment);
+      own.sort();
+      own.dedup();
+      for t in own { *tags.entry(t).or_default() += 1; }
+      let month = DateTime::<Local>::from(UNIX_EPOCH + Duration::from_millis(m.mtime_ms)).format("%Y-%m").to_string();
+      *st.additions_per_month.entry(month).or_default() += 1;
+    }));
+    job.progress(((i + 1) * CHUNK).min(paths.len()) as u64, paths.len() as u64);
+  }
+
+  st.unreadable = if st.cancelled { 0 } else { st.tracks - seen };
+  st.hours = ms as f64 / 3_600_000.0;
+  st.avg_bitrate_kbps = avg(bitrate_sum, with_bitrate);
+  for f in st.formats.values_mut() { f.avg_bitrate_kbps = avg(f.bitrate_sum, f.with_bitrate); }
+  let mut top: Vec<TagCount> = tags.into_iter().map(|(tag, tracks)| TagCount { tag, tracks }).collect();
+  top.sort_by(|a, b| b.tracks.cmp(&a.tracks).then_with(|| a.tag.cmp(&b.tag)));
+  top.truncate(TOP_TAGS);
+  st.top_tags = top;
+  Ok(st)
+}
+
+/// Dashboard totals for the files under `roots` (job kind "workspace-stats").
+#[tauri::command]
+pub async fn workspace_stats(app: tauri::AppHandle, roots: Vec<String>, recursive: bool) -> Result<WorkspaceStats, String> {
+  let _span = command_span("workspace_stats");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "workspace-stats", &roots.join(", "));
+    let res = stats_blocking(&job, &roots, recursive);
+    if let Ok(s) = &res {
+      log_line(&format!("workspace_stats roots={} tracks={} refreshed={} unreadable={}", roots.len(), s.tracks, s.refreshed, s.unreadable));
+    }
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
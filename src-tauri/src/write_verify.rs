@@ -0,0 +1,192 @@
+// Read-back check after tag saves. SMB and NFS clients can report a write as
+// done while a read moments later (the media server's, say) still gets the
+// old bytes, so the player and the tag list disagree. With verification on,
+// a save flushes the file, re-reads the fields it changed and compares; a
+// mismatch saves once more, then fails with `VerificationFailed`.
+// `verify_writes` in Settings: "network" (default) checks only files on
+// network mounts and UNC paths, "always" checks every save, "off" none.
+
+use std::{fs, path::{Component, Path, PathBuf, Prefix}, sync::atomic::{AtomicU8, Ordering}, time::{Duration, Instant}};
+use lofty::{ItemKey, Tag, TagType, TaggedFileExt};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::CmdError, log_line, read_tagged};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyWrites { Off, #[default] Network, Always }
+
+pub static MODE: AtomicU8 = AtomicU8::new(VerifyWrites::Network as u8);
+
+/// Mount table is re-read at most this often.
+const MOUNTS_TTL: Duration = Duration::from_secs(30);
+
+const NETWORK_FS: &[&str] = &["cifs", "smb3", "smbfs", "nfs", "nfs4", "afpfs", "webdav", "davfs", "fuse.sshfs", "9p"];
+
+/// (mount point, filesystem type).
+type Mount = (PathBuf, String);
+type MountTable = (Instant, Vec<Mount>);
+
+static MOUNTS: Lazy<Mutex<Option<MountTable>>> = Lazy::new(|| Mutex::new(None));
+
+/// /proc/mounts, which escapes spaces in mount points as \040.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_mounts(text: &str) -> Vec<Mount> {
+  text.lines()
+    .filter_map(|l| {
+      let mut f = l.split(' ');
+      let (_, point, fstype) = (f.next()?, f.next()?, f.next()?);
+      Some((PathBuf::from(point.replace("\\040", " ")), fstype.to_string()))
+    })
+    .collect()
+}
+
+/// `mount` prints "//user@nas/share on /Volumes/share (smbfs, nodev, ...)".
+#[cfg(any(target_os = "macos", test))]
+fn parse_mount_output(text: &str) -> Vec<Mount> {
+  text.lines()
+    .filter_map(|l| {
+      let (_, rest) = l.split_once(" on ")?;
+      let (point, opts) = rest.rsplit_once(" (")?;
+      Some((PathBuf::from(point), opts.split(',').next()?.trim().to_string()))
+    })
+    .collect()
+}
+
+/// Every mount, as the OS lists it.
+#[cfg(target_os = "linux")]
+fn read_mounts() -> Vec<Mount> { parse_proc_mounts(&fs::read_to_string("/proc/mounts").unwrap_or_default()) }
+
+#[cfg(target_os = "macos")]
+fn read_mounts() -> Vec<Mount> {
+  let out = std::process::Command::new("/sbin/mount").output().map(|o| String::from_utf8_lossy(&o.stdout).to_string()).unwrap_or_default();
+  parse_mount_output(&out)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn read_mounts() -> Vec<Mount> { Vec::new() }
+
+/// The innermost mount `p` is under.
+fn mount_of(p: &Path) -> Option<Mount> {
+  let mut cache = MOUNTS.lock();
+  if cache.as_ref().is_none_or(|(at, _)| at.elapsed() > MOUNTS_TTL) { *cache = Some((Instant::now(), read_mounts())); }
+  innermost(cache.as_ref().map(|(_, m)| m.as_slice()).unwrap_or_default(), p)
+}
+
+fn innermost(mounts: &[Mount], p: &Path) -> Option<Mount> {
+  mounts.iter().filter(|(point, _)| p.starts_with(point)).max_by_key(|(point, _)| point.components().count()).cloned()
+}
+
+/// Mount point of the filesystem `p` is on; `None` where the OS gives no
+/// mount table (Windows) or `p` is under none of it.
+pub fn mount_point(p: &Path) -> Option<PathBuf> { mount_of(p).map(|(point, _)| point) }
+
+fn is_network(p: &Path) -> bool {
+  if let Some(Component::Prefix(pre)) = p.components().next() {
+    return matches!(pre.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..));
+  }
+  mount_of(p).is_some_and(|(_, fstype)| NETWORK_FS.contains(&fstype.as_str()))
+}
+
+/// Whether saves to `p` are read back under the current setting.
+pub fn applies(p: &Path) -> bool {
+  match MODE.load(Ordering::Relaxed) {
+    m if m == VerifyWrites::Off as u8 => false,
+    m if m == VerifyWrites::Always as u8 => true,
+    _ => is_network(p),
+  }
+}
+
+/// A text field a save changed, with the value it should read back as.
+pub struct Expected {
+  tag_type: TagType,
+  key: ItemKey,
+  value: Option<String>,
+}
+
+fn text(tag: Option<&Tag>, key: &ItemKey) -> Option<String> {
+  tag.and_then(|t| t.get_string(key)).filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+/// Text fields that differ between `before` and `after` (one tag of a file).
+pub fn changed(before: &Tag, after: &Tag) -> Vec<Expected> {
+  let mut keys: Vec<ItemKey> = Vec::new();
+  for item in before.items().chain(after.items()) {
+    if !keys.contains(item.key()) { keys.push(item.key().clone()); }
+  }
+  keys.into_iter()
+    .filter(|k| text(Some(before), k) != text(Some(after), k))
+    .map(|key| Expected { tag_type: after.tag_type(), value: text(Some(after), &key), key })
+    .collect()
+}
+
+fn flush(p: &Path) -> Result<(), String> {
+  fs::OpenOptions::new().write(true).open(p).and_then(|f| f.sync_all()).map_err(|e| e.to_string())
+}
+
+fn first_mismatch(p: &Path, expected: &[Expected]) -> Result<Option<(String, String, String)>, String> {
+  let tf = read_tagged(p).map_err(|e| format!("re-read after write failed: {}", e))?;
+  Ok(expected.iter().find_map(|e| {
+    let actual = text(tf.tag(e.tag_type), &e.key);
+    (actual != e.value).then(|| (format!("{:?}", e.key), e.value.clone().unwrap_or_default(), actual.unwrap_or_default()))
+  }))
+}
+
+/// Flush and re-read `p` after a save; on a mismatch run `save` again and
+/// check once more.
+pub fn verify(p: &Path, expected: &[Expected], mut save: impl FnMut() -> Result<(), String>) -> Result<(), CmdError> {
+  if expected.is_empty() { return Ok(()); }
+  let started = Instant::now();
+  let mut retried = false;
+  loop {
+    flush(p)?;
+    let mismatch = first_mismatch(p, expected)?;
+    let ms = started.elapsed().as_millis();
+    match mismatch {
+      None => {
+        log_line(&format!("verify_write path=\"{}\" fields={} ms={}{}", p.display(), expected.len(), ms, if retried { " retried" } else { "" }));
+        return Ok(());
+      }
+      Some(_) if !retried => { retried = true; save()?; }
+      Some((field, expected, actual)) => {
+        log_line(&format!("verify_write failed path=\"{}\" field={} ms={}", p.display(), field, ms));
+        return Err(CmdError::VerificationFailed {
+          path: p.to_string_lossy().to_string(),
+          message: format!("{} still reads \"{}\" for {} after writing \"{}\" (twice). The volume may be caching; try again.", p.display(), actual, field, expected),
+          field,
+          expected,
+          actual,
+        });
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn mount_tables_parse_per_platform() {
+    let linux = parse_proc_mounts(concat!(
+      "/dev/nvme0n1p2 / ext4 rw,relatime 0 0\n",
+      "/dev/sda1 /media/dj/Gig\\040SSD exfat rw,nosuid 0 0\n",
+      "//nas/music /mnt/nas cifs rw 0 0\n",
+    ));
+    assert_eq!(linux[1], (PathBuf::from("/media/dj/Gig SSD"), "exfat".into()));
+    assert_eq!(innermost(&linux, Path::new("/media/dj/Gig SSD/Crates/a.mp3")).map(|m| m.0), Some(PathBuf::from("/media/dj/Gig SSD")));
+    assert_eq!(innermost(&linux, Path::new("/home/dj/a.mp3")).map(|m| m.0), Some(PathBuf::from("/")));
+    assert_eq!(innermost(&linux, Path::new("/mnt/nas/a.mp3")).map(|m| m.1).as_deref(), Some("cifs"));
+
+    let macos = parse_mount_output(concat!(
+      "/dev/disk3s1s1 on / (apfs, sealed, local, read-only, journaled)\n",
+      "/dev/disk4s1 on /Volumes/Gig SSD (exfat, local, nodev, nosuid, noowners)\n",
+      "//dj@nas._smb._tcp.local/music on /Volumes/music (smbfs, nodev, nosuid, mounted by dj)\n",
+    ));
+    assert_eq!(macos[1], (PathBuf::from("/Volumes/Gig SSD"), "exfat".into()));
+    assert_eq!(innermost(&macos, Path::new("/Volumes/music/a.mp3")).map(|m| m.1).as_deref(), Some("smbfs"));
+    assert_eq!(innermost(&macos, Path::new("/Users/dj/a.mp3")).map(|m| m.0), Some(PathBuf::from("/")));
+  }
+}
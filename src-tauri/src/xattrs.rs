@@ -0,0 +1,385 @@
+// Extended attributes of audio files. A save that goes through a temp file
+// and a rename (`write_atomic`: compact ID3 rewrites, AIFF chunk repairs)
+// would leave the new file without the original's attributes, Finder tags
+// and Spotlight comments among them; with `preserve_xattrs` (default on for
+// macOS) they are read before the save and put on the temp file before it
+// replaces the original. On Linux only the `user.` namespace is carried.
+//
+// Finder tags live in `com.apple.metadata:_kMDItemUserTags`, a binary plist
+// array of "name\ncolor" strings, colour 0-7: none, gray, green, purple, blue,
+// yellow, red, orange. `read_finder_tags` / `write_finder_tags` edit them on
+// macOS. With `sync_finder_tags` every tag edit also keeps the Finder tags of
+// the hashtags in `finder_tag_colors` in step with the comment: a mapped
+// hashtag in the comment is a Finder tag of its colour, one not in it isn't.
+// Other Finder tags are left alone.
+
+use std::{collections::HashMap, path::Path, sync::atomic::{AtomicBool, Ordering}};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::CmdError, log_line, read_comment, shadow, split_comment_tokens};
+
+pub static PRESERVE: AtomicBool = AtomicBool::new(cfg!(target_os = "macos"));
+/// Hashtag -> Finder colour while `sync_finder_tags` is on.
+static SYNC: Lazy<Mutex<Option<HashMap<String, u8>>>> = Lazy::new(|| Mutex::new(None));
+
+#[cfg(target_os = "macos")]
+const USER_TAGS: &str = "com.apple.metadata:_kMDItemUserTags";
+const MAX_COLOR: u8 = 7;
+
+pub fn configure(preserve: bool, sync: bool, colors: &HashMap<String, u8>) {
+  PRESERVE.store(preserve, Ordering::Relaxed);
+  *SYNC.lock() = sync.then(|| colors.iter().filter(|(_, &c)| c <= MAX_COLOR).map(|(h, &c)| (h.clone(), c)).collect());
+}
+
+// ---- attributes ----
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod sys {
+  use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::Path};
+
+  fn c_path(p: &Path) -> io::Result<CString> { CString::new(p.as_os_str().as_bytes()).map_err(|_| io::ErrorKind::InvalidInput.into()) }
+  fn c_name(name: &str) -> io::Result<CString> { CString::new(name).map_err(|_| io::ErrorKind::InvalidInput.into()) }
+
+  #[cfg(target_os = "macos")]
+  unsafe fn list_raw(p: *const libc::c_char, buf: *mut libc::c_char, size: usize) -> isize { libc::listxattr(p, buf, size, 0) }
+  #[cfg(target_os = "linux")]
+  unsafe fn list_raw(p: *const libc::c_char, buf: *mut libc::c_char, size: usize) -> isize { libc::listxattr(p, buf, size) }
+
+  #[cfg(target_os = "macos")]
+  unsafe fn get_raw(p: *const libc::c_char, n: *const libc::c_char, buf: *mut libc::c_void, size: usize) -> isize { libc::getxattr(p, n, buf, size, 0, 0) }
+  #[cfg(target_os = "linux")]
+  unsafe fn get_raw(p: *const libc::c_char, n: *const libc::c_char, buf: *mut libc::c_void, size: usize) -> isize { libc::getxattr(p, n, buf, size) }
+
+  #[cfg(target_os = "macos")]
+  unsafe fn set_raw(p: *const libc::c_char, n: *const libc::c_char, v: *const libc::c_void, size: usize) -> libc::c_int { libc::setxattr(p, n, v, size, 0, 0) }
+  #[cfg(target_os = "linux")]
+  unsafe fn set_raw(p: *const libc::c_char, n: *const libc::c_char, v: *const libc::c_void, size: usize) -> libc::c_int { libc::setxattr(p, n, v, size, 0) }
+
+  #[cfg(target_os = "macos")]
+  unsafe fn remove_raw(p: *const libc::c_char, n: *const libc::c_char) -> libc::c_int { libc::removexattr(p, n, 0) }
+
+  /// Size, then contents; again if it grew in between.
+  fn sized(mut call: impl FnMut(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+    for _ in 0..3 {
+      let n = call(std::ptr::null_mut(), 0);
+      if n < 0 { return Err(io::Error::last_os_error()); }
+      let mut buf = vec![0u8; n as usize];
+      let got = call(buf.as_mut_ptr(), buf.len());
+      if got >= 0 { buf.truncate(got as usize); return Ok(buf); }
+      let e = io::Error::last_os_error();
+      if e.raw_os_error() != Some(libc::ERANGE) { return Err(e); }
+    }
+    Err(io::Error::from_raw_os_error(libc::ERANGE))
+  }
+
+  pub fn names(p: &Path) -> io::Result<Vec<String>> {
+    let c = c_path(p)?;
+    let raw = sized(|buf, size| unsafe { list_raw(c.as_ptr(), buf as *mut libc::c_char, size) })?;
+    Ok(raw.split(|&b| b == 0).filter(|n| !n.is_empty()).map(|n| String::from_utf8_lossy(n).to_string()).collect())
+  }
+
+  /// `None` when `p` has no attribute `name`.
+  pub fn get(p: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let (c, n) = (c_path(p)?, c_name(name)?);
+    match sized(|buf, size| unsafe { get_raw(c.as_ptr(), n.as_ptr(), buf as *mut libc::c_void, size) }) {
+      Ok(v) => Ok(Some(v)),
+      #[cfg(target_os = "macos")]
+      Err(e) if e.raw_os_error() == Some(libc::ENOATTR) => Ok(None),
+      #[cfg(target_os = "linux")]
+      Err(e) if e.raw_os_error() == Some(libc::ENODATA) => Ok(None),
+      Err(e) => Err(e),
+    }
+  }
+
+  pub fn set(p: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    let (c, n) = (c_path(p)?, c_name(name)?);
+    if unsafe { set_raw(c.as_ptr(), n.as_ptr(), value.as_ptr() as *const libc::c_void, value.len()) } != 0 { return Err(io::Error::last_os_error()); }
+    Ok(())
+  }
+
+  #[cfg(target_os = "macos")]
+  pub fn remove(p: &Path, name: &str) -> io::Result<()> {
+    let (c, n) = (c_path(p)?, c_name(name)?);
+    if unsafe { remove_raw(c.as_ptr(), n.as_ptr()) } != 0 { return Err(io::Error::last_os_error()); }
+    Ok(())
+  }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+mod sys {
+  use std::{io, path::Path};
+
+  pub fn names(_: &Path) -> io::Result<Vec<String>> { Ok(Vec::new()) }
+  pub fn get(_: &Path, _: &str) -> io::Result<Option<Vec<u8>>> { Ok(None) }
+  pub fn set(_: &Path, _: &str, _: &[u8]) -> io::Result<()> { Err(io::ErrorKind::Unsupported.into()) }
+}
+
+/// Attributes of `p` a replacement should carry over; none when
+/// `preserve_xattrs` is off or `p` doesn't exist yet.
+pub fn capture(p: &Path) -> Vec<(String, Vec<u8>)> {
+  if !PRESERVE.load(Ordering::Relaxed) { return Vec::new(); }
+  let Ok(names) = sys::names(p) else { return Vec::new() };
+  names.into_iter()
+    .filter(|n| !cfg!(target_os = "linux") || n.starts_with("user."))
+    .filter_map(|n| sys::get(p, &n).ok().flatten().map(|v| (n, v)))
+    .collect()
+}
+
+/// Put `attrs` (from `capture`) on `p`; failures are logged, not fatal.
+pub fn restore(p: &Path, attrs: &[(String, Vec<u8>)]) {
+  for (name, value) in attrs {
+    if let Err(e) = sys::set(p, name, value) { log_line(&format!("xattr \"{}\" not carried over to \"{}\": {}", name, p.display(), e)); }
+  }
+}
+
+// ---- binary plist (an array of strings) ----
+
+#[cfg(any(target_os = "macos", test))]
+mod plist {
+  fn be(b: &[u8]) -> u64 { b.iter().fold(0, |a, &x| a << 8 | x as u64) }
+
+  fn be_bytes(v: u64, width: usize) -> Vec<u8> { v.to_be_bytes()[8 - width..].to_vec() }
+
+  /// The strings of a `bplist00` whose top object is an array of strings.
+  pub fn strings(b: &[u8]) -> Option<Vec<String>> {
+    if b.len() < 40 || &b[..8] != b"bplist00" { return None; }
+    let t = &b[b.len() - 32..];
+    let (off_size, ref_size) = (t[6] as usize, t[7] as usize);
+    let (count, top, table) = (be(&t[8..16]) as usize, be(&t[16..24]) as usize, be(&t[24..32]) as usize);
+    let offset = |i: usize| -> Option<usize> {
+      let at = table.checked_add(i.checked_mul(off_size)?)?;
+      Some(be(b.get(at..at + off_size)?) as usize)
+    };
+    // (type nibble, length, where the data starts)
+    let object = |i: usize| -> Option<(u8, usize, usize)> {
+      let at = offset(i)?;
+      let marker = *b.get(at)?;
+      if marker & 0x0f != 0x0f { return Some((marker >> 4, (marker & 0x0f) as usize, at + 1)); }
+      let int = *b.get(at + 1)?;
+      if int >> 4 != 1 { return None; }
+      let w = 1usize << (int & 0x0f);
+      Some((marker >> 4, be(b.get(at + 2..at + 2 + w)?) as usize, at + 2 + w))
+    };
+    let (kind, len, start) = object(top)?;
+    if kind != 0xa { return None; }
+    (0..len).map(|k| {
+      let r = be(b.get(start + k * ref_size..start + (k + 1) * ref_size)?) as usize;
+      if r >= count { return None; }
+      let (kind, len, s) = object(r)?;
+      match kind {
+        0x5 => Some(String::from_utf8_lossy(b.get(s..s + len)?).to_string()),
+        0x6 => {
+          let units: Vec<u16> = b.get(s..s + len * 2)?.chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+          Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+      }
+    }).collect()
+  }
+
+  fn push_marker(out: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 15 { out.push(kind << 4 | len as u8); return; }
+    out.push(kind << 4 | 0x0f);
+    out.push(0x12);
+    out.extend((len as u32).to_be_bytes());
+  }
+
+  pub fn from(strings: &[String]) -> Vec<u8> {
+    let count = strings.len() + 1;
+    let ref_size = if count < 256 { 1 } else { 2 };
+    let mut out = b"bplist00".to_vec();
+    let mut offsets = vec![out.len()];
+    push_marker(&mut out, 0xa, strings.len());
+    for i in 1..count { out.extend(be_bytes(i as u64, ref_size)); }
+    for s in strings {
+      offsets.push(out.len());
+      if s.is_ascii() {
+        push_marker(&mut out, 0x5, s.len());
+        out.extend(s.as_bytes());
+      } else {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        push_marker(&mut out, 0x6, units.len());
+        for u in units { out.extend(u.to_be_bytes()); }
+      }
+    }
+    let table = out.len();
+    let off_size = match table { 0..=0xff => 1, 0x100..=0xffff => 2, _ => 4 };
+    for o in offsets { out.extend(be_bytes(o as u64, off_size)); }
+    out.extend([0u8; 6]);
+    out.extend([off_size as u8, ref_size as u8]);
+    out.extend((count as u64).to_be_bytes());
+    out.extend(0u64.to_be_bytes());
+    out.extend((table as u64).to_be_bytes());
+    out
+  }
+}
+
+// ---- Finder tags ----
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinderTag {
+  pub name: String,
+  /// 0 none, 1 gray, 2 green, 3 purple, 4 blue, 5 yellow, 6 red, 7 orange.
+  #[serde(default)]
+  pub color: u8,
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl FinderTag {
+  fn parse(s: &str) -> Self {
+    match s.rsplit_once('\n') {
+      Some((name, c)) => FinderTag { name: name.to_string(), color: c.trim().parse().unwrap_or(0) },
+      None => FinderTag { name: s.to_string(), color: 0 },
+    }
+  }
+
+  fn encode(&self) -> String { if self.color == 0 { self.name.clone() } else { format!("{}\n{}", self.name, self.color) } }
+}
+
+#[cfg(target_os = "macos")]
+fn read_tags(p: &Path) -> Result<Vec<FinderTag>, String> {
+  let Some(raw) = sys::get(p, USER_TAGS).map_err(|e| format!("{}: {}", p.display(), e))? else { return Ok(Vec::new()) };
+  let strings = plist::strings(&raw).ok_or_else(|| format!("{}: Finder tags are not a list of names", p.display()))?;
+  Ok(strings.iter().map(|s| FinderTag::parse(s)).collect())
+}
+
+#[cfg(target_os = "macos")]
+fn write_tags(p: &Path, tags: &[FinderTag]) -> Result<(), String> {
+  let res = if tags.is_empty() {
+    sys::remove(p, USER_TAGS).or_else(|e| if e.raw_os_error() == Some(libc::ENOATTR) { Ok(()) } else { Err(e) })
+  } else {
+    let strings: Vec<String> = tags.iter().map(FinderTag::encode).collect();
+    sys::set(p, USER_TAGS, &plist::from(&strings))
+  };
+  res.map_err(|e| format!("{}: {}", p.display(), e))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn read_tags(_: &Path) -> Result<Vec<FinderTag>, String> { Err("Finder tags exist only on macOS".into()) }
+
+#[cfg(not(target_os = "macos"))]
+fn write_tags(_: &Path, _: &[FinderTag]) -> Result<(), String> { Err("Finder tags exist only on macOS".into()) }
+
+/// Bring the mapped Finder tags of `p` in line with its comment after a tag
+/// edit (see the header). Files that haven't changed aren't written.
+pub fn sync_finder(p: &Path, tf: &lofty::TaggedFile) {
+  if !cfg!(target_os = "macos") { return; }
+  let Some(map) = SYNC.lock().clone() else { return };
+  let tokens = split_comment_tokens(&read_comment(tf, p));
+  let current = match read_tags(p) {
+    Ok(t) => t,
+    Err(e) => { log_line(&format!("sync_finder_tags skip: {}", e)); return; }
+  };
+  let mut next: Vec<FinderTag> = current.iter()
+    .filter(|t| !map.contains_key(&t.name) || tokens.contains(&t.name))
+    .map(|t| FinderTag { color: map.get(&t.name).copied().unwrap_or(t.color), ..t.clone() })
+    .collect();
+  for tok in tokens.iter().filter(|t| map.contains_key(*t)) {
+    if !next.iter().any(|t| &t.name == tok) { next.push(FinderTag { name: tok.clone(), color: map[tok] }); }
+  }
+  if next == current { return; }
+  if let Err(e) = write_tags(p, &next) { log_line(&format!("sync_finder_tags failed: {}", e)); }
+}
+
+/// Finder tags of `path`, in Finder's order. macOS only.
+#[tauri::command]
+pub fn read_finder_tags(path: String) -> Result<Vec<FinderTag>, String> { read_tags(Path::new(&path)) }
+
+/// Replace the Finder tags of `path`; an empty list removes them. Under
+/// shadow mode the copy gets them. macOS only.
+#[tauri::command]
+pub fn write_finder_tags(path: String, tags: Vec<FinderTag>) -> Result<(), CmdError> {
+  if let Some(bad) = tags.iter().find(|t| t.name.trim().is_empty() || t.name.contains('\n') || t.color > MAX_COLOR) {
+    return Err(CmdError::from(format!("not a Finder tag: {:?} (colour {})", bad.name, bad.color)));
+  }
+  let p = Path::new(&path);
+  let target = shadow::target(p)?.unwrap_or_else(|| p.to_path_buf());
+  write_tags(&target, &tags)?;
+  log_line(&format!("write_finder_tags \"{}\" tags={}", target.display(), tags.len()));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use crate::test_support;
+
+  /// `["Red\n6"]` as Finder writes it.
+  const RED: &[u8] = b"bplist00\xa1\x01\x55Red\n6\x08\x0a\0\0\0\0\0\0\x01\x01\0\0\0\0\0\0\0\x02\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x10";
+
+  #[test]
+  fn finder_tag_plists_round_trip() {
+    assert_eq!(plist::from(&["Red\n6".to_string()]), RED);
+    assert_eq!(plist::strings(RED), Some(vec!["Red\n6".to_string()]));
+    let long = vec!["A name past fifteen bytes\n4".to_string(), "Ünïcødé 🎵\n2".to_string(), String::new()];
+    assert_eq!(plist::strings(&plist::from(&long)), Some(long));
+    // Past 255 objects the refs and offsets widen.
+    let many: Vec<String> = (0..300).map(|i| format!("#tag{}\n{}", i, i % 8)).collect();
+    assert_eq!(plist::strings(&plist::from(&many)), Some(many));
+    assert_eq!(plist::strings(b"bplist00 too short"), None);
+  }
+
+  #[test]
+  fn finder_tag_names_carry_their_colour() {
+    assert_eq!(FinderTag::parse("Work\n6"), FinderTag { name: "Work".into(), color: 6 });
+    assert_eq!(FinderTag::parse("Plain"), FinderTag { name: "Plain".into(), color: 0 });
+    assert_eq!(FinderTag { name: "Plain".into(), color: 0 }.encode(), "Plain");
+    assert_eq!(FinderTag { name: "Work".into(), color: 6 }.encode(), "Work\n6");
+  }
+
+  #[cfg(any(target_os = "macos", target_os = "linux"))]
+  #[test]
+  fn an_atomic_save_keeps_the_files_attributes() {
+    let dir = test_support::scratch("xattrs");
+    let p = dir.join("a.mp3");
+    fs::write(&p, b"old").unwrap();
+    let name = if cfg!(target_os = "macos") { "com.example.rating" } else { "user.rating" };
+    match sys::set(&p, name, b"5 stars") {
+      Err(e) if matches!(e.raw_os_error(), Some(libc::ENOTSUP)) => return, // no xattrs on this filesystem
+      r => r.unwrap(),
+    }
+    #[cfg(target_os = "macos")]
+    write_tags(&p, &[FinderTag { name: "Red".into(), color: 6 }]).unwrap();
+    PRESERVE.store(true, Ordering::Relaxed);
+
+    crate::write_atomic(&p, b"new").unwrap();
+    assert_eq!(fs::read(&p).unwrap(), b"new");
+    assert_eq!(sys::get(&p, name).unwrap().as_deref(), Some(&b"5 stars"[..]));
+    #[cfg(target_os = "macos")]
+    assert_eq!(read_tags(&p).unwrap(), [FinderTag { name: "Red".into(), color: 6 }]);
+  }
+
+  #[cfg(target_os = "macos")]
+  #[test]
+  fn finder_tags_round_trip_on_a_file() {
+    let dir = test_support::scratch("finder-tags");
+    let p = dir.join("a.mp3");
+    fs::write(&p, b"x").unwrap();
+    assert_eq!(read_tags(&p).unwrap(), []);
+    let tags = vec![FinderTag { name: "Red".into(), color: 6 }, FinderTag { name: "Ünïcødé long tag name".into(), color: 0 }];
+    write_tags(&p, &tags).unwrap();
+    assert_eq!(read_tags(&p).unwrap(), tags);
+    write_tags(&p, &[]).unwrap();
+    assert_eq!(sys::get(&p, USER_TAGS).unwrap(), None);
+    write_tags(&p, &[]).unwrap();
+  }
+
+  #[cfg(target_os = "macos")]
+  #[test]
+  fn a_sync_follows_the_comment_for_mapped_hashtags_only() {
+    use lofty::ItemKey;
+    let dir = test_support::scratch("finder-sync");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::Comment, "#red;#unmapped;")]);
+    write_tags(&p, &[FinderTag { name: "#gone".into(), color: 2 }, FinderTag { name: "Other".into(), color: 4 }]).unwrap();
+    let colors = HashMap::from([("#red".to_string(), 6), ("#gone".to_string(), 2)]);
+
+    configure(true, true, &colors);
+    sync_finder(&p, &lofty::read_from_path(&p).unwrap());
+    configure(true, false, &HashMap::new());
+    assert_eq!(read_tags(&p).unwrap(), [FinderTag { name: "Other".into(), color: 4 }, FinderTag { name: "#red".into(), color: 6 }]);
+  }
+}
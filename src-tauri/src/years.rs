@@ -0,0 +1,439 @@
+// Year problems across a label archive: missing years, "0000", placeholder or
+// implausible years, and tag types on one file that disagree (ID3v2 says
+// 2019, RIFF INFO says 2021). `fix_years` writes a year taken from the file
+// name, the folder name or the caller in one save to the usual write targets
+// and to every other tag that carries a different year (APE on MP3, ID3v1,
+// the only ID3v1 field the app writes), so a fixed file reads one year
+// everywhere and later scans stop flagging it.
+
+use std::{cell::OnceCell, path::{Path, PathBuf}};
+use chrono::{Datelike, Local};
+use lofty::{ItemKey, Tag, TagType, TaggedFileExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  audit, command_span, dates, edit_tags_with, error::CmdError, field_limits::FieldLimitHit, field_locks::LockedField, jobs::JobHandle, library::audio_files, log_line, preferred_tag,
+  preflight::{self, Preflight}, read_tagged, retry_queue, snapshots, write_targets, MetaPatch,
+};
+
+/// How many folders up `from-folder` looks for a year.
+const FOLDER_LEVELS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YearIssueKind { Missing, Zero, Implausible, Conflicting }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagYear {
+  /// lofty's name for the tag type ("Id3v2", "RiffInfo", ...).
+  tag_type: String,
+  raw: String,
+  /// `None` when `raw` has no readable year; 0 for "0000".
+  year: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearIssue {
+  path: String,
+  issues: Vec<YearIssueKind>,
+  /// One entry per tag type that carries a year.
+  years: Vec<TagYear>,
+  /// What the app shows: the year of the preferred tag.
+  current: Option<i32>,
+  /// What `from-filename` / `from-folder` would write.
+  filename_year: Option<i32>,
+  folder_year: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearReport {
+  issues: Vec<YearIssue>,
+  scanned: usize,
+  cancelled: bool,
+}
+
+fn max_year() -> i32 { Local::now().year() + 1 }
+
+/// After 1900 (many taggers' placeholder) and no later than next year.
+fn plausible(y: i32) -> bool { y > 1900 && y <= max_year() }
+
+/// Year of a raw date value; `Some(0)` for all-zero values like "0000".
+fn year_of(raw: &str) -> Option<i32> {
+  if let Some(d) = dates::normalize_date(raw) { return d[..4].parse().ok(); }
+  let digits: Vec<u8> = raw.bytes().filter(u8::is_ascii_digit).collect();
+  (!digits.is_empty() && digits.iter().all(|&b| b == b'0')).then_some(0)
+}
+
+fn raw_year(tag: &Tag) -> Option<&str> {
+  tag.get_string(&ItemKey::RecordingDate).or_else(|| tag.get_string(&ItemKey::Year)).map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// A plausible 19xx/20xx standing alone in `name` ("Album (2019)", "2019 -
+/// Title"; not "LBL2019"). A bracketed year wins; otherwise all candidates
+/// must agree, since "1999 - 2000 Remix" can't be told apart.
+fn year_token(name: &str) -> Option<i32> {
+  let b = name.as_bytes();
+  let mut found: Vec<(i32, bool)> = Vec::new();
+  let mut i = 0;
+  while i < b.len() {
+    if !b[i].is_ascii_digit() { i += 1; continue; }
+    let start = i;
+    while i < b.len() && b[i].is_ascii_digit() { i += 1; }
+    let standalone = (start == 0 || !b[start - 1].is_ascii_alphanumeric()) && (i == b.len() || !b[i].is_ascii_alphanumeric());
+    if i - start != 4 || !standalone { continue; }
+    let Ok(y) = name[start..i].parse::<i32>() else { continue };
+    if plausible(y) { found.push((y, start > 0 && matches!(b[start - 1], b'(' | b'['))); }
+  }
+  if let Some(&(y, _)) = found.iter().find(|(_, bracketed)| *bracketed) { return Some(y); }
+  let first = found.first()?.0;
+  found.iter().all(|(y, _)| *y == first).then_some(first)
+}
+
+fn filename_year(p: &Path) -> Option<i32> { year_token(&p.file_stem()?.to_string_lossy()) }
+
+fn folder_year(p: &Path) -> Option<i32> {
+  p.ancestors().skip(1).take(FOLDER_LEVELS).find_map(|d| year_token(&d.file_name()?.to_string_lossy()))
+}
+
+fn issue_for(p: &Path) -> Result<Option<YearIssue>, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let years: Vec<TagYear> = tf.tags().iter()
+    .filter_map(|t| raw_year(t).map(|raw| TagYear { tag_type: format!("{:?}", t.tag_type()), raw: raw.to_string(), year: year_of(raw) }))
+    .collect();
+  let mut issues = Vec::new();
+  if years.is_empty() { issues.push(YearIssueKind::Missing); }
+  if years.iter().any(|y| y.year == Some(0)) { issues.push(YearIssueKind::Zero); }
+  if years.iter().any(|y| y.year.is_none_or(|v| v != 0 && !plausible(v))) { issues.push(YearIssueKind::Implausible); }
+  let mut distinct: Vec<i32> = years.iter().filter_map(|y| y.year).filter(|&v| plausible(v)).collect();
+  distinct.sort_unstable();
+  distinct.dedup();
+  if distinct.len() > 1 { issues.push(YearIssueKind::Conflicting); }
+  if issues.is_empty() { return Ok(None); }
+  Ok(Some(YearIssue {
+    path: p.to_string_lossy().to_string(),
+    issues,
+    current: preferred_tag(&tf, p).and_then(raw_year).and_then(year_of),
+    years,
+    filename_year: filename_year(p),
+    folder_year: folder_year(p),
+  }))
+}
+
+fn find_blocking(job: &JobHandle, folder: &str, recursive: bool) -> Result<YearReport, String> {
+  job.begin_phase("scan", 0);
+  let paths = job.timed("walk", || audio_files(&PathBuf::from(folder), recursive)).map_err(|e| e.to_string())?;
+  let mut issues = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("analyze", paths.len() as u64);
+  for (i, p) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    match job.timed("parse", || issue_for(p)) {
+      Ok(issue) => issues.extend(issue),
+      Err(e) => log_line(&format!("find_year_issues skip \"{}\": {}", p.display(), e)),
+    }
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+  Ok(YearReport { issues, scanned: paths.len(), cancelled })
+}
+
+/// Files under `folder` with a missing, zero, implausible (1900 or earlier,
+/// or after next year) or conflicting year; job kind "year-issues".
+#[tauri::command]
+pub async fn find_year_issues(app: tauri::AppHandle, folder: String, recursive: bool) -> Result<YearReport, String> {
+  let _span = command_span("find_year_issues");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "year-issues", &folder);
+    let res = find_blocking(&job, &folder, recursive);
+    job.finish(&res);
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum YearStrategy { FromFilename, FromFolder, Explicit }
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearFixItem {
+  path: String,
+  /// Used by the `explicit` strategy.
+  #[serde(default)]
+  year: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearFixResult {
+  path: String,
+  /// Release date of the preferred tag before the fix.
+  old: Option<String>,
+  /// Date as written (or as it would be in a dry run).
+  new: Option<String>,
+  applied: bool,
+  error: Option<String>,
+  /// `["releaseDate"]` when the date is locked; nothing was written.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  skipped_locked: Vec<LockedField>,
+  /// Values cut or left out for a tag type's length limit (see `field_limits`).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  limited: Vec<FieldLimitHit>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearFixReport {
+  results: Vec<YearFixResult>,
+  dry_run: bool,
+  cancelled: bool,
+  snapshot_id: Option<String>,
+  preflight: Preflight,
+}
+
+/// What `fix_one` writes to: the usual targets, plus every other tag on the
+/// file carrying a year other than `year` (APE on MP3, ID3v1), so the file
+/// stops reading as conflicting. Tag types lofty can't write to this file
+/// are left out.
+fn year_targets(tf: &lofty::TaggedFile, p: &Path, year: i32) -> Vec<TagType> {
+  let mut out = write_targets(tf, p);
+  for t in tf.tags() {
+    let tt = t.tag_type();
+    if !out.contains(&tt) && raw_year(t).is_some_and(|raw| year_of(raw) != Some(year)) { out.push(tt); }
+  }
+  out.retain(|tt| tf.file_type().supports_tag_type(*tt));
+  out
+}
+
+/// `date` as the tag's recording date, or as a bare `year` in the Year field
+/// of tag types without one (APE, ID3v1).
+fn put_year(tag: &mut Tag, date: &str, year: i32) {
+  dates::set_date(tag, ItemKey::RecordingDate, Some(date));
+  if tag.get_string(&ItemKey::RecordingDate).is_none() { tag.insert_text(ItemKey::Year, format!("{:04}", year)); }
+}
+
+fn fix_one(item: &YearFixItem, strategy: YearStrategy, dry_run: bool) -> YearFixResult {
+  let p = Path::new(&item.path);
+  let mut r = YearFixResult { path: item.path.clone(), old: None, new: None, applied: false, error: None, skipped_locked: Vec::new(), limited: Vec::new() };
+  let year = match strategy {
+    YearStrategy::FromFilename => filename_year(p).ok_or("no year in the file name"),
+    YearStrategy::FromFolder => folder_year(p).ok_or("no year in the folder names"),
+    YearStrategy::Explicit => item.year.ok_or("no year given"),
+  };
+  let year = match year {
+    Ok(y) if plausible(y) => y,
+    Ok(y) => { r.error = Some(format!("{} is not a plausible year", y)); return r; }
+    Err(e) => { r.error = Some(e.to_string()); return r; }
+  };
+  // (old, new); new is `None` when every tag already reads `year`.
+  let plan = |tf: &lofty::TaggedFile| {
+    let old = preferred_tag(tf, p).and_then(dates::release_date);
+    // Keep month and day when the year was already right in the preferred tag.
+    let new = old.clone().filter(|d| d.starts_with(&format!("{:04}", year))).unwrap_or_else(|| format!("{:04}", year));
+    let settled = tf.tags().iter().all(|t| raw_year(t).and_then(year_of) == Some(year)) && !tf.tags().is_empty();
+    (old, new, settled)
+  };
+  if dry_run {
+    match read_tagged(p) {
+      Ok(tf) => { let (old, new, _) = plan(&tf); (r.old, r.new) = (old, Some(new)); }
+      Err(e) => r.error = Some(e.to_string()),
+    }
+    return r;
+  }
+  // Planned from the file as read under WRITE_LOCK, and written in one save.
+  let planned: OnceCell<(Option<String>, String)> = OnceCell::new();
+  // Each distinct date the save replaced, for the audit.
+  let mut olds: Vec<Option<String>> = Vec::new();
+  let res = edit_tags_with(p, |tf| {
+    let (old, new, settled) = plan(tf);
+    let targets = if settled { Vec::new() } else { year_targets(tf, p, year) };
+    let _ = planned.set((old, new));
+    Ok(targets)
+  }, |tag| {
+    let Some((_, new)) = planned.get() else { return };
+    let before = dates::release_date(tag);
+    put_year(tag, new, year);
+    if before.as_deref() != Some(new) && !olds.contains(&before) { olds.push(before); }
+  });
+  let Some((old, new)) = planned.into_inner() else {
+    r.error = res.err().map(String::from);
+    return r;
+  };
+  (r.old, r.new) = (old, Some(new.clone()));
+  match res {
+    Ok(o) if !o.skipped_locked.is_empty() => r.skipped_locked = o.skipped_locked,
+    Ok(o) => {
+      for old in olds.iter().filter(|_| !o.no_op) { audit::record(&item.path, "release_date", old.as_deref(), Some(&new), audit::Source::Batch); }
+      r.applied = !o.no_op;
+      r.limited = o.limited;
+    }
+    Err(e) => r.error = Some(retry_queue::offer_patch(p, &MetaPatch { release_date: Some(new), ..Default::default() }, e.into())),
+  }
+  r
+}
+
+fn fix_blocking(job: &JobHandle, items: &[YearFixItem], strategy: YearStrategy, dry_run: bool) -> Result<YearFixReport, CmdError> {
+  let paths: Vec<&str> = items.iter().map(|i| i.path.as_str()).collect();
+  job.begin_phase("preflight", 0);
+  let preflight = job.timed("preflight", || preflight::rewrite(&paths));
+  if !dry_run { preflight::ensure(&preflight)?; }
+  let snapshot_id = if dry_run { None } else { snapshots::before_batch(job.app(), "fix_years", &paths) };
+  let mut results = Vec::new();
+  let mut cancelled = false;
+  job.begin_phase("write", items.len() as u64);
+  for (i, item) in items.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    results.push(fix_one(item, strategy, dry_run));
+    job.progress(i as u64 + 1, items.len() as u64);
+  }
+  if !dry_run {
+    let applied = results.iter().filter(|r| r.applied).count();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    log_line(&format!("fix_years items={} applied={} failed={} strategy={:?} cancelled={}", results.len(), applied, failed, strategy, cancelled));
+  }
+  Ok(YearFixReport { results, dry_run, cancelled, snapshot_id, preflight })
+}
+
+/// Set the release year of each item from the chosen source; job kind
+/// "fix-years". Files whose tags already all read that year are left alone;
+/// `dry_run` reports the planned dates without writing.
+#[tauri::command]
+pub async fn fix_years(app: tauri::AppHandle, items: Vec<YearFixItem>, strategy: YearStrategy, dry_run: bool) -> Result<YearFixReport, CmdError> {
+  let _span = command_span("fix_years");
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "fix-years", &format!("{} files", items.len()));
+    let res = fix_blocking(&job, &items, strategy, dry_run);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support;
+
+  #[test]
+  fn a_fixed_file_reads_one_year_everywhere() {
+    let dir = test_support::scratch("years");
+    let p = test_support::tagged(&dir, "Track (2019).mp3", &[(ItemKey::RecordingDate, "2021")]);
+    test_support::add_tag(&p, TagType::Id3v1, &[(ItemKey::Year, "1999")]);
+    assert!(issue_for(&p).unwrap().unwrap().issues.contains(&YearIssueKind::Conflicting));
+
+    let item = YearFixItem { path: p.to_string_lossy().to_string(), year: None };
+    let r = fix_one(&item, YearStrategy::FromFilename, false);
+    assert_eq!((r.applied, r.error, r.new.as_deref()), (true, None, Some("2019")));
+    assert_eq!(test_support::text(&p, TagType::Id3v1, &ItemKey::Year).as_deref(), Some("2019"));
+    assert!(issue_for(&p).unwrap().is_none(), "nothing left to flag");
+    // Settled now: a second run writes nothing.
+    assert!(!fix_one(&item, YearStrategy::FromFilename, false).applied);
+  }
+
+  fn fix(p: &Path, strategy: YearStrategy, year: Option<i32>, dry_run: bool) -> YearFixResult {
+    fix_one(&YearFixItem { path: p.to_string_lossy().to_string(), year }, strategy, dry_run)
+  }
+
+  fn kinds(p: &Path) -> Vec<YearIssueKind> { issue_for(p).unwrap().map(|i| i.issues).unwrap_or_default() }
+
+  /// (old, new) release dates audited for `p`.
+  fn audited(p: &Path) -> Vec<(Option<String>, Option<String>)> {
+    let path = p.to_string_lossy();
+    crate::audit::read_entries().into_iter().filter(|e| e.path == path && e.field == "release_date").map(|e| (e.old, e.new)).collect()
+  }
+
+  fn date(d: &str) -> Option<String> { Some(d.to_string()) }
+
+  #[test]
+  fn a_wav_gets_one_year_in_riff_info_and_id3v2() {
+    let dir = test_support::scratch("years-wav");
+    let p = test_support::audio(&dir, "a.wav");
+    test_support::add_tag(&p, TagType::RiffInfo, &[(ItemKey::RecordingDate, "2021")]);
+    test_support::add_tag(&p, TagType::Id3v2, &[(ItemKey::RecordingDate, "2019")]);
+    assert_eq!(kinds(&p), [YearIssueKind::Conflicting]);
+
+    let r = fix(&p, YearStrategy::Explicit, Some(2020), false);
+    assert_eq!((r.applied, r.error, r.new.as_deref()), (true, None, Some("2020")));
+    for tt in [TagType::RiffInfo, TagType::Id3v2] {
+      assert_eq!(test_support::text(&p, tt, &ItemKey::RecordingDate).as_deref(), Some("2020"), "{:?}", tt);
+    }
+    assert!(kinds(&p).is_empty());
+    // One entry per value replaced.
+    assert_eq!(audited(&p), [(date("2021"), date("2020")), (date("2019"), date("2020"))]);
+  }
+
+  #[test]
+  fn a_disagreeing_ape_year_on_an_mp3_is_rewritten_too() {
+    let dir = test_support::scratch("years-ape");
+    let p = test_support::tagged(&dir, "Track [2019].mp3", &[(ItemKey::RecordingDate, "2019")]);
+    test_support::add_tag(&p, TagType::Ape, &[(ItemKey::Year, "2021")]);
+    assert_eq!(kinds(&p), [YearIssueKind::Conflicting]);
+
+    // ID3v2 already reads 2019: only the APE tag changes, and that's a change.
+    let r = fix(&p, YearStrategy::FromFilename, None, false);
+    assert_eq!((r.applied, r.error), (true, None));
+    assert_eq!(test_support::text(&p, TagType::Ape, &ItemKey::Year).as_deref(), Some("2019"));
+    assert!(kinds(&p).is_empty(), "no longer conflicting");
+    assert_eq!(audited(&p), [(date("2021"), date("2019"))]);
+  }
+
+  #[test]
+  fn an_id3v1_only_fix_counts_as_applied() {
+    let dir = test_support::scratch("years-id3v1");
+    let p = test_support::tagged(&dir, "a.mp3", &[(ItemKey::RecordingDate, "2019")]);
+    test_support::add_tag(&p, TagType::Id3v1, &[(ItemKey::Year, "1999")]);
+    let r = fix(&p, YearStrategy::Explicit, Some(2019), false);
+    assert_eq!((r.applied, r.error), (true, None));
+    assert_eq!(test_support::text(&p, TagType::Id3v1, &ItemKey::Year).as_deref(), Some("2019"));
+    assert_eq!(audited(&p), [(date("1999"), date("2019"))]);
+  }
+
+  #[test]
+  fn zero_placeholder_future_and_missing_years_are_flagged() {
+    let dir = test_support::scratch("years-kinds");
+    let with = |name: &str, raw: &str| test_support::tagged(&dir, name, &[(ItemKey::RecordingDate, raw)]);
+    assert_eq!(kinds(&with("zero.mp3", "0000")), [YearIssueKind::Zero]);
+    assert_eq!(kinds(&with("placeholder.mp3", "1900")), [YearIssueKind::Implausible]);
+    assert_eq!(kinds(&with("future.mp3", &(max_year() + 1).to_string())), [YearIssueKind::Implausible]);
+    assert_eq!(kinds(&with("next-year.mp3", &max_year().to_string())), Vec::<YearIssueKind>::new());
+    assert_eq!(kinds(&test_support::audio(&dir, "missing.mp3")), [YearIssueKind::Missing]);
+
+    let p = with("explicit.mp3", "0000");
+    for bad in [1900, max_year() + 1] {
+      let r = fix(&p, YearStrategy::Explicit, Some(bad), false);
+      assert_eq!((r.applied, r.error), (false, Some(format!("{} is not a plausible year", bad))));
+    }
+    assert_eq!(fix(&p, YearStrategy::Explicit, None, false).error.as_deref(), Some("no year given"));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::RecordingDate).as_deref(), Some("0000"));
+  }
+
+  #[test]
+  fn from_folder_takes_the_year_from_up_to_two_folders_up() {
+    let dir = test_support::scratch("years-folder");
+    let disc = dir.join("Label - Album (2017)").join("CD1");
+    std::fs::create_dir_all(&disc).unwrap();
+    let p = test_support::audio(&disc, "a.mp3");
+    assert_eq!(kinds(&p), [YearIssueKind::Missing]);
+    let r = fix(&p, YearStrategy::FromFolder, None, false);
+    assert_eq!((r.applied, r.old, r.new.as_deref()), (true, None, Some("2017")));
+    assert_eq!(test_support::text(&p, TagType::Id3v2, &ItemKey::RecordingDate).as_deref(), Some("2017"));
+    assert_eq!(fix(&test_support::audio(&dir, "b.mp3"), YearStrategy::FromFolder, None, false).error.as_deref(), Some("no year in the folder names"));
+  }
+
+  #[test]
+  fn a_dry_run_writes_nothing() {
+    let dir = test_support::scratch("years-dry");
+    let p = test_support::tagged(&dir, "2018 - Track.mp3", &[(ItemKey::RecordingDate, "2011-05-03")]);
+    test_support::add_tag(&p, TagType::Ape, &[(ItemKey::Year, "2012")]);
+    let bytes = std::fs::read(&p).unwrap();
+    let r = fix(&p, YearStrategy::FromFilename, None, true);
+    assert_eq!((r.applied, r.old.as_deref(), r.new.as_deref()), (false, Some("2011-05-03"), Some("2018")));
+    assert_eq!(std::fs::read(&p).unwrap(), bytes);
+    assert!(audited(&p).is_empty());
+  }
+}
@@ -0,0 +1,261 @@
+// Deliver a selection as a zip. Entries are stored, not deflated (audio
+// doesn't compress), and written one file at a time. Metadata changes for the
+// recipient (patch, stripped comment, smaller cover) go to a temp copy only;
+// the originals are never opened for writing.
+
+use std::{fs, io::{Read, Write}, path::{Path, PathBuf}};
+use chrono::{Datelike, Local, Timelike};
+use lofty::{Accessor, ItemKey, MimeType, Picture, PictureType};
+use serde::{Deserialize, Serialize};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+  command_span, compilation, edit_tags_untracked, error::CmdError, front_cover, jobs::JobHandle, log_line, long_paths, meta_patch_editor, preferred_tag,
+  preflight::{self, Preflight}, read_tagged, MetaPatch,
+};
+
+const CHUNK: usize = 1 << 20;
+const COVER_JPEG_QUALITY: u8 = 85;
+/// Local header, central directory record and zip64 extras per entry, with a long name.
+const ENTRY_OVERHEAD: u64 = 512;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ZipExportOptions {
+  /// Entry name without extension, see `template_base`. Default "{name}".
+  name_template: Option<String>,
+  /// Applied to the copy in the archive.
+  patch: Option<MetaPatch>,
+  /// Drop the comment (tags and private notes) from the copy.
+  strip_comment: bool,
+  /// Re-encode the front cover as JPEG fitting this many pixels.
+  cover_max_px: Option<u32>,
+  /// Only run the pre-flight space and permission check.
+  dry_run: bool,
+}
+
+impl ZipExportOptions {
+  fn needs_copy(&self) -> bool {
+    self.patch.as_ref().is_some_and(|p| !p.is_empty()) || self.strip_comment || self.cover_max_px.is_some()
+  }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipEntryStatus {
+  path: String,
+  entry_name: Option<String>,
+  bytes: u64,
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZipExportReport {
+  dest: String,
+  /// 0 when cancelled (the partial archive is deleted).
+  archive_size: u64,
+  files: Vec<ZipEntryStatus>,
+  cancelled: bool,
+  /// Archive size and temp copies against free space (see `preflight`).
+  preflight: Preflight,
+}
+
+/// `{a|b}`: the first of the fields with a value. A placeholder naming no
+/// known field stays as written.
+fn expand(template: &str, value: impl Fn(&str) -> Option<Option<String>>) -> String {
+  let mut out = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(open) = rest.find('{') {
+    let Some(close) = rest[open..].find('}').map(|c| open + c) else { break };
+    out.push_str(&rest[..open]);
+    let known: Vec<Option<String>> = rest[open + 1..close].split('|').filter_map(|f| value(f.trim())).collect();
+    if known.is_empty() { out.push_str(&rest[open..=close]); } else { out.push_str(&known.into_iter().flatten().next().unwrap_or_default()); }
+    rest = &rest[close + 1..];
+  }
+  out.push_str(rest);
+  out
+}
+
+/// File name (no extension) for `p` from a template with {name} {artist}
+/// {albumartist} {album} {title} {index}, and `|` fallbacks between them.
+/// On a compilation a "Various Artists" album artist counts as empty, so
+/// `{albumartist|artist}` names each track after its own artist. Characters
+/// that aren't valid in file names are dropped; an empty result falls back
+/// to the source name.
+pub fn template_base(p: &Path, template: &str, index: usize) -> String {
+  let stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let tf = read_tagged(p).ok();
+  let tag = tf.as_ref().and_then(|tf| preferred_tag(tf, p));
+  let is_compilation = tag.and_then(compilation::of_tag) == Some(true);
+  let text = |v: Option<&str>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+  let name = expand(template, |field| {
+    Some(match field {
+      "name" => Some(stem.clone()),
+      "artist" => text(tag.and_then(|t| t.artist()).as_deref()),
+      "albumartist" => text(tag.and_then(|t| t.get_string(&ItemKey::AlbumArtist))).filter(|a| !(is_compilation && compilation::is_various(a))),
+      "album" => text(tag.and_then(|t| t.album()).as_deref()),
+      "title" => text(tag.and_then(|t| t.title()).as_deref()),
+      "index" => Some(format!("{:02}", index + 1)),
+      _ => return None,
+    })
+  });
+  let name: String = name.chars().filter(|c| !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')).collect();
+  let name = name.trim().trim_matches(['-', '_']).trim();
+  if name.is_empty() { stem } else { name.to_string() }
+}
+
+fn entry_name(p: &Path, template: &str, index: usize, used: &mut Vec<String>) -> String {
+  let ext = p.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+  let base = template_base(p, template, index);
+  let mut candidate = format!("{}{}", base, ext);
+  let mut n = 2;
+  while used.iter().any(|u| u.eq_ignore_ascii_case(&candidate)) {
+    candidate = format!("{} ({}){}", base, n, ext);
+    n += 1;
+  }
+  used.push(candidate.clone());
+  candidate
+}
+
+fn downsized_cover(p: &Path, max_px: u32) -> Result<Option<Vec<u8>>, String> {
+  let tf = read_tagged(p).map_err(|e| e.to_string())?;
+  let Some(pic) = front_cover(&tf) else { return Ok(None) };
+  let img = image::load_from_memory(pic.data()).map_err(|e| format!("cover: {}", e))?;
+  if img.width() <= max_px && img.height() <= max_px { return Ok(None); }
+  let mut out = std::io::Cursor::new(Vec::new());
+  img.thumbnail(max_px, max_px)
+    .write_to(&mut out, image::ImageOutputFormat::Jpeg(COVER_JPEG_QUALITY))
+    .map_err(|e| format!("cover: {}", e))?;
+  Ok(Some(out.into_inner()))
+}
+
+/// Temp copy of `p` with the export edits applied.
+fn prepared_copy(p: &Path, opts: &ZipExportOptions, tmp_dir: &Path, index: usize) -> Result<PathBuf, String> {
+  let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  let copy = tmp_dir.join(format!("{}-{}", index, name));
+  fs::copy(long_paths::extended(p), long_paths::extended(&copy)).map_err(|e| e.to_string())?;
+  let cover = match opts.cover_max_px { Some(px) => downsized_cover(&copy, px.max(16))?, None => None };
+  let mut patch_edit = opts.patch.as_ref().map(meta_patch_editor).transpose()?;
+  // Scratch copy: not recorded as touched.
+  edit_tags_untracked(&copy, |tag| {
+    if let Some(f) = patch_edit.as_mut() { f(tag); }
+    if opts.strip_comment { tag.remove_key(&ItemKey::Comment); }
+    if let Some(bytes) = &cover {
+      tag.remove_picture_type(PictureType::CoverFront);
+      tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, Some(MimeType::Jpeg), None, bytes.clone()));
+    }
+  })?;
+  Ok(copy)
+}
+
+fn zip_time() -> zip::DateTime {
+  let now = Local::now();
+  zip::DateTime::from_date_and_time(now.year() as u16, now.month() as u8, now.day() as u8, now.hour() as u8, now.minute() as u8, now.second() as u8)
+    .unwrap_or_default()
+}
+
+/// Stream one file into the open entry; `Ok(None)` when cancelled mid-file.
+fn copy_entry<W: Write + std::io::Seek>(zip: &mut ZipWriter<W>, src: &Path, job: &JobHandle) -> Result<Option<u64>, String> {
+  let mut f = fs::File::open(long_paths::extended(src)).map_err(|e| e.to_string())?;
+  let mut buf = vec![0u8; CHUNK];
+  let mut written = 0u64;
+  loop {
+    if job.is_cancelled() { return Ok(None); }
+    let n = f.read(&mut buf).map_err(|e| e.to_string())?;
+    if n == 0 { break; }
+    zip.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+    written += n as u64;
+  }
+  Ok(Some(written))
+}
+
+/// Stored entries: the archive is the files plus headers. Copies for
+/// changed metadata are made one at a time in the temp folder.
+fn check_space(paths: &[String], dest_path: &Path, opts: &ZipExportOptions) -> Preflight {
+  let sizes: Vec<u64> = paths.iter().map(|p| fs::metadata(long_paths::extended(Path::new(p))).map(|m| m.len()).unwrap_or(0)).collect();
+  let archive = sizes.iter().sum::<u64>() + paths.len() as u64 * ENTRY_OVERHEAD;
+  let scratch = opts.needs_copy().then(|| (std::env::temp_dir(), sizes.iter().copied().max().unwrap_or(0)));
+  preflight::copies(dest_path.parent().unwrap_or(dest_path), archive, scratch)
+}
+
+fn export_blocking(job: &JobHandle, paths: &[String], dest: &str, opts: &ZipExportOptions) -> Result<ZipExportReport, CmdError> {
+  let dest_path = PathBuf::from(dest);
+  let preflight = job.timed("preflight", || check_space(paths, &dest_path, opts));
+  if opts.dry_run { return Ok(ZipExportReport { dest: dest.to_string(), archive_size: 0, files: Vec::new(), cancelled: false, preflight }); }
+  preflight::ensure(&preflight)?;
+  let partial = dest_path.with_extension("zip.partial");
+  let tmp_dir = std::env::temp_dir().join(format!("audio-tagger-{}", job.id()));
+  if opts.needs_copy() { fs::create_dir_all(&tmp_dir).map_err(|e| e.to_string())?; }
+  let template = opts.name_template.clone().filter(|t| !t.trim().is_empty()).unwrap_or_else(|| "{name}".into());
+
+  let mut zip = ZipWriter::new(fs::File::create(long_paths::extended(&partial)).map_err(|e| e.to_string())?);
+  let mut files = Vec::new();
+  let mut used = Vec::new();
+  let mut cancelled = false;
+  let mtime = zip_time();
+
+  job.begin_phase("write", paths.len() as u64);
+  for (i, path) in paths.iter().enumerate() {
+    if job.is_cancelled() { cancelled = true; break; }
+    let p = Path::new(path);
+    let mut st = ZipEntryStatus { path: path.clone(), entry_name: None, bytes: 0, error: None };
+    let src = if opts.needs_copy() { prepared_copy(p, opts, &tmp_dir, i) } else { Ok(p.to_path_buf()) };
+    match src.and_then(|src| fs::metadata(long_paths::extended(&src)).map(|m| (src, m.len())).map_err(|e| e.to_string())) {
+      Ok((src, expected)) => {
+        let name = entry_name(p, &template, i, &mut used);
+        let options = FileOptions::default()
+          .compression_method(CompressionMethod::Stored)
+          .last_modified_time(mtime)
+          .large_file(expected >= u32::MAX as u64);
+        let res = zip.start_file(name.clone(), options).map_err(|e| e.to_string()).and_then(|_| copy_entry(&mut zip, &src, job));
+        match res {
+          Ok(Some(n)) if n == expected => st.bytes = n,
+          Ok(Some(n)) => st.error = Some(format!("wrote {} of {} bytes", n, expected)),
+          Ok(None) => cancelled = true,
+          Err(e) => st.error = Some(e),
+        }
+        // Don't leave a truncated entry behind.
+        if st.error.is_some() { let _ = zip.abort_file(); }
+        st.entry_name = Some(name);
+        if src != p { let _ = fs::remove_file(&src); }
+      }
+      Err(e) => st.error = Some(e),
+    }
+    files.push(st);
+    if cancelled { break; }
+    job.progress(i as u64 + 1, paths.len() as u64);
+  }
+
+  let _ = fs::remove_dir_all(&tmp_dir);
+  if cancelled {
+    drop(zip);
+    let _ = fs::remove_file(long_paths::extended(&partial));
+    return Ok(ZipExportReport { dest: dest.to_string(), archive_size: 0, files, cancelled, preflight });
+  }
+  let file = zip.finish().map_err(|e| e.to_string())?;
+  file.sync_all().map_err(|e| e.to_string())?;
+  drop(file);
+  fs::rename(long_paths::extended(&partial), long_paths::extended(&dest_path)).map_err(|e| e.to_string())?;
+  let archive_size = fs::metadata(long_paths::extended(&dest_path)).map(|m| m.len()).unwrap_or(0);
+  let failed = files.iter().filter(|f| f.error.is_some()).count();
+  log_line(&format!("export_selection_zip dest=\"{}\" files={} failed={} bytes={}", dest, files.len(), failed, archive_size));
+  Ok(ZipExportReport { dest: dest.to_string(), archive_size, files, cancelled, preflight })
+}
+
+/// Zip `paths` into `dest` (job kind "zip-export"). Cancelling deletes the
+/// partial archive; per-file failures are reported and skipped.
+#[tauri::command]
+pub async fn export_selection_zip(app: tauri::AppHandle, paths: Vec<String>, dest: String, options: Option<ZipExportOptions>) -> Result<ZipExportReport, CmdError> {
+  let _span = command_span("export_selection_zip");
+  let opts = options.unwrap_or_default();
+  tauri::async_runtime::spawn_blocking(move || {
+    let job = JobHandle::start(&app, "zip-export", &dest);
+    let res = export_blocking(&job, &paths, &dest, &opts);
+    job.finish(&res.as_ref().map_err(|e| e.to_string()));
+    res
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,98 @@
+// The `--cli` mode end to end: the built binary run against fixture files in
+// a scratch folder, with HOME, the XDG dirs and the working directory pointed
+// at a scratch home so no real data dir or bank is touched. Each command
+// prints JSON on stdout; logs and errors go to stderr.
+
+use std::{fs, path::{Path, PathBuf}, process::{Command, Output}, sync::atomic::{AtomicU64, Ordering}};
+use serde_json::Value;
+
+/// A fresh (home, music) pair for one test.
+fn scratch(name: &str) -> (PathBuf, PathBuf) {
+  static SEQ: AtomicU64 = AtomicU64::new(0);
+  let root = std::env::temp_dir().join(format!("audio-tagger-cli-{}-{}-{}", std::process::id(), name, SEQ.fetch_add(1, Ordering::Relaxed)));
+  let _ = fs::remove_dir_all(&root);
+  let (home, music) = (root.join("home"), root.join("music"));
+  fs::create_dir_all(&home).unwrap();
+  fs::create_dir_all(&music).unwrap();
+  (home, fs::canonicalize(music).unwrap())
+}
+
+/// A silent, untagged MP3: MPEG-1 Layer III, 128 kbps, 44.1 kHz, 417-byte frames.
+fn mp3(dir: &Path, name: &str) -> PathBuf {
+  let mut frame = vec![0u8; 417];
+  frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+  let p = dir.join(name);
+  fs::write(&p, frame.repeat(20)).unwrap();
+  p
+}
+
+fn run(home: &Path, args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_audio-tagger"))
+    .arg("--cli")
+    .args(args)
+    .current_dir(home)
+    .env("HOME", home)
+    .env("XDG_DATA_HOME", home.join("data"))
+    .env("XDG_CONFIG_HOME", home.join("config"))
+    .env("XDG_DOCUMENTS_DIR", home.join("Documents"))
+    .output()
+    .expect("run the binary")
+}
+
+/// stdout as JSON, after checking the exit code.
+fn json(out: &Output) -> Value {
+  assert_eq!(out.status.code(), Some(0), "stderr: {}", String::from_utf8_lossy(&out.stderr));
+  serde_json::from_slice(&out.stdout).expect("stdout is JSON")
+}
+
+fn s(p: &Path) -> &str { p.to_str().unwrap() }
+
+#[test]
+fn commands_print_json_and_share_the_stores() {
+  let (home, music) = scratch("flow");
+  let a = mp3(&music, "a.mp3");
+  mp3(&music, "b.mp3");
+  fs::write(music.join("notes.txt"), "not audio").unwrap();
+
+  let scan = json(&run(&home, &["scan", s(&music)]));
+  let names: Vec<&str> = scan.as_array().unwrap().iter().filter(|f| f["supported"] == true).map(|f| f["fileName"].as_str().unwrap()).collect();
+  assert_eq!(names, ["a.mp3", "b.mp3"]);
+
+  let set = json(&run(&home, &["set-comment", s(&a), "#house;"]));
+  assert_eq!(set["comment"], "#house;");
+  let added = json(&run(&home, &["add-tags", s(&a), "#vocal", "#house"]));
+  assert_eq!((added["oldComment"].as_str(), added["newComment"].as_str(), &added["changed"]), (Some("#house;"), Some("#house;#vocal;"), &Value::Bool(true)));
+
+  let read = json(&run(&home, &["read", s(&a)]));
+  assert_eq!((read["comment"].as_str(), read["format"].as_str()), (Some("#house;#vocal;"), Some("MP3")));
+  assert!(read["lastTouchedByApp"].is_string(), "CLI writes are recorded like the GUI's");
+
+  let csv = music.join("out.csv");
+  let out = run(&home, &["export-csv", s(&music), s(&csv)]);
+  let report = json(&out);
+  assert_eq!(report["tracksWritten"], 2);
+  assert!(String::from_utf8_lossy(&out.stderr).contains("export_csv"), "log lines go to stderr");
+  let text = fs::read_to_string(&csv).unwrap();
+  assert!(text.lines().any(|l| l.starts_with("a.mp3,") && l.contains("#house;#vocal;")), "{}", text);
+}
+
+#[test]
+fn failures_exit_non_zero_with_nothing_on_stdout() {
+  let (home, music) = scratch("errors");
+  let missing = music.join("missing.mp3");
+  for (args, code) in [
+    (vec!["read", s(&missing)], 1),
+    (vec!["scan", s(&missing)], 1),
+    (vec!["bogus"], 2),
+    (vec!["add-tags", s(&missing)], 2),
+    (vec![], 2),
+  ] {
+    let out = run(&home, &args);
+    assert_eq!(out.status.code(), Some(code), "{:?}", args);
+    assert!(out.stdout.is_empty(), "{:?}", args);
+    assert!(!out.stderr.is_empty(), "{:?}", args);
+  }
+  let help = run(&home, &["help"]);
+  assert_eq!(help.status.code(), Some(0));
+  assert!(String::from_utf8_lossy(&help.stdout).starts_with("usage:"));
+}
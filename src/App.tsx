@@ -1,6 +1,8 @@
 import React, { useEffect, useState, useRef } from "react";
 import {
   initSession,
+  handshake,
+  forceCompatibility,
   chooseFolder,
   scanFolder,
   readMetadata,
@@ -10,17 +12,16 @@ import {
   readTagsFileBank,
   writeTagsFileBank,
   listTagBanks,
-  getLastUsedBank,
   setLastUsedBank,
   sanitizeBank,
-  readSettings,
+  preloadAppState,
   writeSettings,
 } from "./tauri";
 import Waveform from "./components/Waveform";
 import { TagDef, TagsFile, TrackMeta, Settings } from "./types";
 import {
   emptyTags,
-  idFromName,
+  newTagId,
   parseCommentToTags,
   enforceParentAndMandatory,
   stringifyTagsForComment,
@@ -30,7 +31,9 @@ import {
   coerceTagsFile,
   unknownTokensFromComment,
   splitTokens,
+  TAGS_SCHEMA_VERSION,
 } from "./lib/tags";
+import pkg from "../package.json";
 import { StatusViewport, pushStatus } from "./ui/Status";
 
 function useHotkeys(bindings: Record<string, (e: KeyboardEvent) => void>) {
@@ -182,7 +185,7 @@ function TagManager({
   function save() {
     try {
       validateTagName(form.name);
-      const id = editing ?? idFromName(form.name);
+      const id = editing ?? newTagId();
       const def: TagDef = {
         id,
         name: form.name,
@@ -1419,17 +1422,22 @@ export default function App() {
   useEffect(() => {
     initSession();
     (async () => {
-      // 1) Load settings first (fallback to defaults if error)
-      try {
-        const s = await readSettings();
-        setSettings({ ...defaultSettings, ...s });
-      } catch {
-        /* use defaults */
+      // 0) Schema handshake: bank writes are refused on a mismatch until confirmed
+      const hs = await handshake(TAGS_SCHEMA_VERSION, pkg.version).catch(() => null);
+      if (hs && hs.compatibility !== "equal") {
+        const ok = confirm(
+          `This window uses tag schema ${TAGS_SCHEMA_VERSION}, the backend uses ${hs.schemaVersion}. ` +
+            "Saving tag banks may lose data. Save anyway?"
+        );
+        if (ok) await forceCompatibility();
       }
-      // 2) Load banks + last used bank + tags
-      const list = await listTagBanks().catch(() => ["default"]);
+
+      // 1) Settings, banks and last used bank in one backend call (defaults on error)
+      const pre = await preloadAppState().catch(() => null);
+      if (pre) setSettings({ ...defaultSettings, ...pre.settings });
+      const list = pre?.banks ?? ["default"];
       setBanks(list.length ? list : ["default"]);
-      const last = (await getLastUsedBank()) || "default";
+      const last = pre?.lastUsedBank || "default";
       const chosen = list.includes(last) ? last : "default";
       setBank(chosen);
 
@@ -147,8 +147,9 @@ export default function Waveform({
       if (u.startsWith("http://") || u.startsWith("https://")) {
         try {
           const url = new URL(u);
+          // Already decoded; decoding again breaks names containing "%".
           const p = url.searchParams.get("path");
-          if (p) return decodeURIComponent(p);
+          if (p) return p;
         } catch {}
       }
       if (u.startsWith("/")) return u;
@@ -1,6 +1,6 @@
 import { TagDef, TagsFile } from "../types";
 
-export const TAGS_SCHEMA_VERSION = 1; // also update in src-tauri/src/main.rs if changed
+export const TAGS_SCHEMA_VERSION = 4; // checked against the backend's in `handshake`
 
 export function emptyTags(): TagsFile {
   return { version: TAGS_SCHEMA_VERSION, tags: [] };
@@ -24,11 +24,9 @@ export function validateTagName(name: string) {
     throw new Error("Semicolons are reserved as separators");
 }
 
-export function idFromName(name: string) {
-  return name
-    .toLowerCase()
-    .replace(/[^a-z0-9]+/g, "_")
-    .replace(/^_+|_+$/g, "");
+/** A fresh entry id; the backend expects UUIDs (bank schema 4). */
+export function newTagId() {
+  return crypto.randomUUID();
 }
 
 export function topoParents(
@@ -1,28 +1,140 @@
-import { invoke, convertFileSrc } from "@tauri-apps/api/tauri";
+import { invoke as rawInvoke, convertFileSrc } from "@tauri-apps/api/tauri";
 import { open } from "@tauri-apps/api/dialog";
 import type { TrackMeta } from "./types";
 import { readBinaryFile } from "@tauri-apps/api/fs";
-import type { Settings } from "./types";
+import type { OperationProfile, Preset, PresetAction, Settings, TagDef, TagType } from "./types";
+
+/** Typed error from commands returning `CmdError` (Rust `{ kind, message }`). */
+export class CommandError extends Error {
+  kind: string;
+  path?: string;
+  constructor(raw: { kind: string; message?: string; path?: string }) {
+    super(raw.message ?? raw.kind);
+    this.name = raw.kind;
+    this.kind = raw.kind;
+    this.path = raw.path;
+  }
+}
+
+/**
+ * Every binding's `invoke`: a rejection is reported to the backend's timings
+ * (`record_command_failure`, see perf.rs) and passed on unchanged.
+ */
+function invoke<T>(cmd: string, args?: Record<string, unknown>): Promise<T> {
+  return rawInvoke<T>(cmd, args).catch((e) => {
+    rawInvoke<void>("record_command_failure", { command: cmd }).catch(() => {});
+    throw e;
+  });
+}
+
+function rethrowTyped(e: unknown): never {
+  if (e && typeof e === "object" && "kind" in (e as any))
+    throw new CommandError(e as any);
+  throw e;
+}
+
+/** What a command answers instead of a payload over `Settings.ipcPayloadCapMb`. */
+export interface ChunkedResponse {
+  chunked: true;
+  handle: string;
+  totalBytes: number;
+}
+
+const RESPONSE_CHUNK = 4 * 1024 * 1024;
+
+/** The payload itself, or, for a `ChunkedResponse`, read back chunk by chunk and parsed. */
+export async function resolveChunked<T>(raw: T | ChunkedResponse): Promise<T> {
+  const r = raw as any;
+  if (!r || typeof r !== "object" || r.chunked !== true || typeof r.handle !== "string") return raw as T;
+  const bytes = new Uint8Array(r.totalBytes);
+  try {
+    for (let offset = 0; offset < r.totalBytes; ) {
+      const b64 = await invoke<string>("read_response_chunk", { handle: r.handle, offset, len: RESPONSE_CHUNK });
+      const bin = atob(b64);
+      if (bin.length === 0) throw new Error(`response ${r.handle} ended at ${offset} of ${r.totalBytes} bytes`);
+      for (let i = 0; i < bin.length; i++) bytes[offset + i] = bin.charCodeAt(i);
+      offset += bin.length;
+    }
+  } finally {
+    invoke<void>("release_response", { handle: r.handle }).catch(() => {});
+  }
+  return JSON.parse(new TextDecoder().decode(bytes)) as T;
+}
 
 export async function initSession(): Promise<void> {
   await invoke<void>("init_session");
 }
 
+export interface HandshakeInfo {
+  schemaVersion: number;
+  appVersion: string;
+  /** Feature flags of this backend build, e.g. "batch_write", "peaks", "api_mode". */
+  capabilities: string[];
+  /** Our schema relative to the backend's; bank writes are refused unless "equal". */
+  compatibility: "older" | "equal" | "newer";
+  appVersionDiffers: boolean;
+}
+
+/** Startup contract: call once, before any bank write. */
+export async function handshake(frontendSchemaVersion: number, frontendAppVersion: string): Promise<HandshakeInfo> {
+  return invoke<HandshakeInfo>("handshake", { frontendSchemaVersion, frontendAppVersion });
+}
+
+/** Accept a schema mismatch and allow bank writes until the next `handshake`. */
+export async function forceCompatibility(): Promise<void> {
+  await invoke<void>("force_compatibility");
+}
+
+export interface StartupScan {
+  jobId: string;
+  folder: string;
+}
+
+/**
+ * Call once listeners are attached. Reopens the last folder as a
+ * "startup-scan" job (cancel with `cancelJob`); the listing arrives as the
+ * `startup-scan-result` event ({ jobId, folder, files }). When the folder's
+ * volume is gone, `volume-missing` ({ folder, root }) fires instead.
+ */
+export async function frontendReady(): Promise<StartupScan | null> {
+  return invoke<StartupScan | null>("frontend_ready");
+}
+
+export async function getLastFolder(): Promise<string | null> {
+  return invoke<string | null>("get_last_folder");
+}
+
+/**
+ * "empty" = 0 bytes, "corrupt" = bad magic or truncated, "mismatched" = the
+ * content is another format than the extension says (only with
+ * `verifyExtensionsOnScan`); see `statusReason`.
+ */
+export type FileStatus = "ok" | "empty" | "corrupt" | "mismatched";
+
+/**
+ * `includeUnsupported` also lists other media files (video, disabled formats)
+ * with `supported: false`; they can't be tagged, see `probeFile`.
+ */
 export async function scanFolder(
-  path: string
-): Promise<{ path: string; fileName: string }[]> {
-  const raw = await invoke<any>("scan_folder", { path });
+  path: string,
+  includeUnsupported = false
+): Promise<{ path: string; fileName: string; supported: boolean; status: FileStatus; statusReason?: string | null; realFormat?: string }[]> {
+  const raw = await invoke<any>("scan_folder", { path, includeUnsupported }).then(resolveChunked).catch(rethrowTyped);
   const list = Array.isArray(raw) ? raw : [];
   return list
     .map((x: any) => ({
       path: x.path,
       fileName: x.fileName ?? x.file_name ?? "",
+      supported: x.supported ?? true,
+      status: (x.status ?? "ok") as FileStatus,
+      statusReason: x.statusReason ?? null,
+      realFormat: x.realFormat ?? undefined,
     }))
     .filter((x) => x.path && x.fileName);
 }
 
 export async function readMetadata(path: string): Promise<TrackMeta> {
-  const m = await invoke<any>("read_metadata", { path });
+  const m = await invoke<any>("read_metadata", { path }).catch(rethrowTyped);
   // normalize snake_case from Rust v1 to our TS interface
   return {
     path: m.path,
@@ -33,22 +145,194 @@ export async function readMetadata(path: string): Promise<TrackMeta> {
     comment: m.comment ?? "",
     pictureDataUrl: m.pictureDataUrl ?? m.picture_data_url ?? null,
     format: m.format ?? undefined,
+    releaseDate: m.releaseDate ?? null,
+    originalDate: m.originalDate ?? null,
+    lastTouchedByApp: m.lastTouchedByApp ?? null,
+    externallyModifiedSince: m.externallyModifiedSince ?? false,
+    commentConflicts: m.commentConflicts ?? false,
+    lockedFields: m.lockedFields ?? [],
+    colorLabel: m.colorLabel ?? null,
+    compilation: m.compilation ?? null,
+    encoder: m.encoder ?? null,
+    vbr: m.vbr ?? null,
+    picturesTruncated: m.picturesTruncated ?? false,
   };
 }
 
-export async function writeComment(
-  path: string,
-  comment: string
-): Promise<void> {
-  await invoke<void>("write_comment", { path, comment });
+/** A deep-read field: `value` when read, `error` when that reader failed on its own. */
+export interface Field<T> {
+  value: T | null;
+  error: string | null;
+}
+
+export interface PictureInfo {
+  picType: string;
+  mime: string | null;
+  bytes: number;
+  description: string | null;
+  dataUrl: string;
+}
+
+export interface AudioProps {
+  durationSecs: number;
+  bitrateKbps: number | null;
+  sampleRate: number | null;
+  bitDepth: number | null;
+  channels: number | null;
+}
+
+export interface TrackMetaDeep {
+  path: string;
+  meta: Field<TrackMeta>;
+  lyrics: Field<string | null>;
+  comments: Field<{ tagType: string; comment: string }[]>;
+  pictures: Field<PictureInfo[]>;
+  properties: Field<AudioProps>;
+  tagSizes: Field<TagSizeReport>;
+  /** A queued write not on disk yet; `meta.value.comment` already shows it. */
+  pending: { comment: string; onDisk: string; queue: "retry" | "volume"; queuedAt: string } | null;
+}
+
+/** Everything the detail panel shows for one track, in one call. */
+export async function readMetadataDeep(path: string): Promise<TrackMetaDeep> {
+  return invoke<TrackMetaDeep | ChunkedResponse>("read_metadata_deep", { path }).then((r) => resolveChunked(r));
+}
+
+/** Drop "touched by this app" records; returns how many existed. */
+export async function forgetTouched(paths: string[]): Promise<number> {
+  return invoke<number>("forget_touched", { paths });
+}
+
+export type AutocompleteField = "artist" | "genre" | "album";
+export type Suggestion = { value: string; count: number };
+
+/**
+ * Existing values of `field` starting with `prefix` (case- and accent-folded,
+ * "beyonce" finds "Beyoncé"), most frequent first.
+ */
+export async function autocomplete(field: AutocompleteField, prefix: string, limit?: number): Promise<Suggestion[]> {
+  return invoke<Suggestion[]>("autocomplete", { field, prefix, limit });
+}
+
+/**
+ * Payload of `track-updated`, sent after every write to a file (including
+ * background retries and skipped no-op writes); `meta` has no picture.
+ * Updates arrive in the order they were made, at most one per path every
+ * 50 ms. `revision` goes up with each save that changed the file (a skipped
+ * write repeats it); ignore one lower than the revision already applied.
+ */
+export interface TrackUpdatedEvent {
+  path: string;
+  revision: number;
+  meta: TrackMeta;
+}
+
+/** A value cut (or left out) for one tag type because of its length limit. */
+export interface FieldLimitHit {
+  tagType: string;
+  field: string;
+  limit: number;
+  length: number;
+  action: "truncated" | "dropped" | "skipped";
+  written: string | null;
+}
+
+export type LockedField =
+  | "title" | "artist" | "album" | "genre" | "comment" | "isrc" | "bpm" | "key"
+  | "releaseDate" | "originalDate" | "trackNumber" | "artwork";
+
+/** On write results while shadow mode is on; see `setShadowMode`. */
+export interface ShadowMark {
+  shadowed?: boolean;
+  /** The copy written instead of the original. */
+  shadowPath?: string;
+}
+
+export interface WriteOutcome extends ShadowMark {
+  noOp: boolean;
+  /** The file's revision after the write, as in `track-updated`; 0 for shadow copies. */
+  revision: number;
+  limited?: FieldLimitHit[];
+  /** Fields the write would have changed but are locked; left as they were. */
+  skippedLocked?: LockedField[];
+}
+
+export interface ShadowStatus {
+  enabled: boolean;
+  destDir: string | null;
+  /** Originals copied since shadow mode was turned on. */
+  copies: number;
+}
+
+/**
+ * Shadow mode: every write goes to a copy of the file mirrored under
+ * `destDir` (by workspace root and relative path) instead of the original.
+ * Reads, the audit log and the metadata cache keep describing the originals.
+ * `destDir` may not be inside a library root. Lasts until
+ * `disableShadowMode` or quit.
+ */
+export async function setShadowMode(destDir: string): Promise<ShadowStatus> {
+  return invoke<ShadowStatus>("set_shadow_mode", { destDir });
+}
+
+export async function disableShadowMode(): Promise<ShadowStatus> {
+  return invoke<ShadowStatus>("disable_shadow_mode");
+}
+
+/**
+ * Lock fields of one file against every write (single, batch, template,
+ * cleanup); writes skip them and report them as `skippedLocked`. Returns
+ * all of the file's locked fields.
+ */
+export async function lockFields(path: string, fields: LockedField[]): Promise<LockedField[]> {
+  return invoke<LockedField[]>("lock_fields", { path, fields });
+}
+
+export async function unlockFields(path: string, fields: LockedField[]): Promise<LockedField[]> {
+  return invoke<LockedField[]>("unlock_fields", { path, fields });
+}
+
+/**
+ * `noOp` when the file already held this comment: nothing was saved or
+ * audited, `track-updated` still fires. Throws CommandError
+ * "VerificationFailed" (with `field`, `expected`, `actual`) when a verified
+ * write still reads back differently after a retry, and "FieldTooLong"
+ * (`tagType`, `field`, `limit`, `length`) under the "reject" limit strategy.
+ * Every tag write throws "Unsupported" (`ext`) for a file listed with
+ * `supported: false`.
+ */
+export type ColorTarget = "neutral" | "serato";
+
+/** What happened to a file's Serato colour; anything but `written`/`unchanged` means only TRACK_COLOR changed. */
+export type SeratoWrite = "notRequested" | "written" | "unchanged" | "noMarkers" | "noColorEntry" | "unparsed" | "unsupported";
+
+export interface ColorWriteResult extends ShadowMark {
+  path: string;
+  color: string;
+  noOp: boolean;
+  serato: SeratoWrite;
+}
+
+/**
+ * Sets the colour label ("#RRGGBB"). TRACK_COLOR is always written; with
+ * "serato" in `targets` the Serato colour is patched too when its blob is
+ * one we can rewrite exactly.
+ */
+export async function writeColorLabel(path: string, colorHex: string, targets: ColorTarget[]): Promise<ColorWriteResult> {
+  return invoke<ColorWriteResult>("write_color_label", { path, colorHex, targets }).catch(rethrowTyped);
+}
+
+export async function writeComment(path: string, comment: string): Promise<WriteOutcome> {
+  return invoke<WriteOutcome>("write_comment", { path, comment }).catch(rethrowTyped);
 }
 
 export async function readTagsFile(): Promise<string> {
   return invoke<string>("read_tags_file");
 }
 
+/** Throws CommandError "SchemaMismatch" after a mismatched `handshake`, see `forceCompatibility`. */
 export async function writeTagsFile(json: string): Promise<void> {
-  await invoke<void>("write_tags_file", { json });
+  await invoke<void>("write_tags_file", { json }).catch(rethrowTyped);
 }
 
 export async function chooseFolder(): Promise<string | null> {
@@ -64,12 +348,71 @@ export async function chooseFolder(): Promise<string | null> {
   }
 }
 
+/** Re-prompts for a folder the OS no longer lets us read; null if cancelled. */
+export async function reauthorizeFolder(path: string): Promise<string | null> {
+  return invoke<string | null>("reauthorize_folder", { path });
+}
+
 export async function getMediaUrl(path: string): Promise<string> {
-  return invoke<string>("media_url_for_path", { path });
+  // The media server starts after the window; keep asking while it binds.
+  for (let attempt = 0; ; attempt++) {
+    try {
+      return await invoke<string>("media_url_for_path", { path });
+    } catch (e) {
+      if (String(e) !== "media server not ready" || attempt >= 5) throw e;
+    }
+  }
+}
+
+export interface PeaksView {
+  /** Response schema version; bump-aware callers should drop stale copies. */
+  version: number;
+  sampleRate: number;
+  framesPerBucket: number;
+  durationSecs: number;
+  min: number[];
+  max: number[];
+  normalized: boolean;
+  /** Factor applied by `normalize` (1 when off). */
+  scale: number;
+  log: boolean;
+}
+
+/** Waveform peaks from the media server's `/peaks`; one cache serves every mode. */
+export async function fetchPeaks(path: string, opts: { normalize?: boolean; log?: boolean } = {}): Promise<PeaksView> {
+  const url = new URL((await getMediaUrl(path)).replace("/audio?", "/peaks?"));
+  if (opts.normalize) url.searchParams.set("normalize", "1");
+  if (opts.log) url.searchParams.set("log", "1");
+  const resp = await fetch(url.toString());
+  if (!resp.ok) throw new Error((await resp.text()) || `peaks failed (${resp.status})`);
+  return resp.json();
+}
+
+export interface PreloadedState {
+  settings: Settings;
+  banks: string[];
+  lastUsedBank?: string | null;
 }
 
-export async function logEvent(message: string): Promise<void> {
-  await invoke<void>("log_event", { message });
+/** Settings, bank list and last bank in one call, for the first render. */
+export async function preloadAppState(): Promise<PreloadedState> {
+  return invoke<PreloadedState>("preload_app_state");
+}
+
+export type LogLevel = "error" | "warn" | "info" | "debug";
+
+export async function logEvent(message: string, level?: LogLevel): Promise<void> {
+  await invoke<void>("log_event", { message, level });
+}
+
+/** Session log verbosity; "debug" adds per-command timings. Persisted in Settings. */
+export async function setLogLevel(level: LogLevel): Promise<void> {
+  await invoke<void>("set_log_level", { level });
+}
+
+/** Apply an operation profile live; persisted in Settings. */
+export async function setOperationProfile(profile: OperationProfile): Promise<void> {
+  await invoke<void>("set_operation_profile", { profile });
 }
 
 export function fileUrl(path: string): string {
@@ -91,7 +434,13 @@ function mimeFor(path: string): string {
     case "mp3":
       return "audio/mpeg";
     case "m4a":
+    case "m4b":
       return "audio/mp4";
+    case "aac":
+      return "audio/aac";
+    case "ogg":
+    case "opus":
+      return "audio/ogg";
     case "wav":
       return "audio/wav";
     case "aif":
@@ -123,14 +472,20 @@ export async function fileBlobUrl(path: string): Promise<string> {
 export async function listTagBanks(): Promise<string[]> {
   return invoke<string[]>("list_tag_banks");
 }
+/**
+ * Always strict JSON: hand-edited banks (BOM, comments, trailing commas) are
+ * repaired on disk. Unreadable ones are moved aside and throw a
+ * CommandError of kind "Corrupt".
+ */
 export async function readTagsFileBank(bank: string): Promise<string> {
-  return invoke<string>("read_tags_file_bank", { bank });
+  return invoke<string>("read_tags_file_bank", { bank }).catch(rethrowTyped);
 }
+/** Throws CommandError "SchemaMismatch" after a mismatched `handshake`, see `forceCompatibility`. */
 export async function writeTagsFileBank(
   bank: string,
   json: string
 ): Promise<void> {
-  return invoke<void>("write_tags_file_bank", { bank, json });
+  return invoke<void>("write_tags_file_bank", { bank, json }).catch(rethrowTyped);
 }
 export async function getLastUsedBank(): Promise<string | null> {
   return invoke<string | null>("get_last_used_bank");
@@ -150,8 +505,10 @@ export function sanitizeBank(name: string): string {
 
 export async function readSettings(): Promise<Settings> {
   const s = await invoke<any>("read_settings");
-  // fields are camelCase from Rust via serde(rename_all)
+  // fields are camelCase from Rust via serde(rename_all); keep unknown
+  // fields so writeSettings round-trips backend-only settings
   return {
+    ...s,
     showTitle: !!s.showTitle,
     showAuthors: !!s.showAuthors,
     showGenre: !!s.showGenre,
@@ -171,3 +528,2115 @@ export async function getKnownBanks(): Promise<string[]> {
 export async function checkBankAvailable(name: string): Promise<boolean> {
   return invoke<boolean>("check_bank_available", { name });
 }
+
+export interface SessionSnapshot {
+  schema: number;
+  savedAt: string;
+  state: any;
+}
+
+/** Debounced on the Rust side; safe to call on every selection/scroll change. */
+export async function saveSessionState(state: unknown): Promise<void> {
+  await invoke<void>("save_session_state", { stateJson: JSON.stringify(state) });
+}
+
+export async function loadSessionState(): Promise<SessionSnapshot | null> {
+  return invoke<SessionSnapshot | null>("load_session_state");
+}
+
+export async function clearSessionState(): Promise<void> {
+  await invoke<void>("clear_session_state");
+}
+
+/** Who caused an audited change; "external" = another program's change, accepted. */
+export type AuditSource = "manual" | "batch" | "rule" | "api" | "cli" | "external";
+
+export interface AuditFilters {
+  /** RFC 3339, or YYYY-MM-DD (start of that local day). */
+  from?: string;
+  /** RFC 3339, or YYYY-MM-DD (end of that local day, inclusive). */
+  to?: string;
+  pathPrefix?: string;
+  fields?: string[];
+  sources?: AuditSource[];
+}
+
+export interface AuditExportSummary {
+  dest: string;
+  entries: number;
+  filesTouched: number;
+  fieldsChanged: Record<string, number>;
+  skippedLines: number;
+}
+
+/**
+ * Matching audit entries as JSON (`{ schemaVersion, exportedAt, filters,
+ * entries: [{ timestamp, path, field, old, new, source }], summary }`) or CSV
+ * (same columns, then a blank line and `metric,value` summary rows).
+ */
+export async function exportAuditLog(
+  dest: string,
+  format: "json" | "csv" = "json",
+  filters?: AuditFilters
+): Promise<AuditExportSummary> {
+  return invoke<AuditExportSummary>("export_audit_log", { dest, format, filters });
+}
+
+/** Drop entries older than `olderThanDays`; `archive` moves them to a dated file in the data dir instead. */
+export async function pruneAuditLog(
+  olderThanDays: number,
+  archive: boolean
+): Promise<{ pruned: number; kept: number; archive: string | null }> {
+  return invoke("prune_audit_log", { olderThanDays, archive });
+}
+
+export interface WriteDrift {
+  path: string;
+  /** Last comment this session wrote (as saved). */
+  expected: string;
+  actual: string;
+  writtenAt: string;
+  source: AuditSource;
+}
+
+export interface SessionWriteReport {
+  session: string;
+  checked: number;
+  mismatches: WriteDrift[];
+  unreadable: { path: string; error: string }[];
+}
+
+/** Files whose comment no longer matches what this session wrote. */
+export async function verifySessionWrites(): Promise<SessionWriteReport> {
+  return invoke<SessionWriteReport>("verify_session_writes");
+}
+
+/** Write the expected comments back (batch writer; may take a snapshot). */
+export async function reapplySessionWrites(
+  paths: string[]
+): Promise<{ results: { path: string; skipped: boolean; error?: string | null; skippedLocked?: LockedField[] }[]; snapshotId: string | null; preflight: Preflight }> {
+  return invoke("reapply_session_writes", { paths }).catch(rethrowTyped);
+}
+
+/** Keep the files' current comments as the expected values; returns how many changed. */
+export async function acceptExternalChanges(paths: string[]): Promise<number> {
+  return invoke<number>("accept_external_changes", { paths });
+}
+
+export interface MetaPatch {
+  title?: string;
+  artist?: string;
+  genre?: string;
+  /** Loose input ("20210305", "2021/3/5"); normalized to ISO on write, "" clears. */
+  releaseDate?: string;
+  originalDate?: string;
+  /** Compilation flag (TCMP / cpil / COMPILATION); `false` writes an explicit 0. */
+  compilation?: boolean;
+}
+
+export async function writeMetadata(
+  path: string,
+  patch: MetaPatch
+): Promise<WriteOutcome> {
+  return invoke<WriteOutcome>("write_metadata", { path, patch });
+}
+
+export interface CleanupRules {
+  collapseWhitespace?: boolean;
+  titleCase?: boolean;
+  smallWords?: string[];
+  keepUpper?: string[];
+  featForm?: string | null;
+  mixBrackets?: "round" | "square" | null;
+  stripJunk?: boolean;
+}
+
+export interface CleanupResult {
+  path: string;
+  before: { title?: string | null; artist?: string | null };
+  after: { title?: string | null; artist?: string | null };
+  changed: boolean;
+  error?: string | null;
+  /** Dry runs only. */
+  plan?: WritePlan;
+  skippedLocked?: LockedField[];
+  /** Values cut or left out for a tag type's length limit. */
+  limited?: FieldLimitHit[];
+}
+
+export async function cleanupTextFields(
+  paths: string[],
+  rules: CleanupRules,
+  dryRun: boolean
+): Promise<{ results: CleanupResult[]; snapshotId: string | null; preflight: Preflight }> {
+  return invoke<{ results: CleanupResult[]; snapshotId: string | null; preflight: Preflight }>("cleanup_text_fields", { paths, rules, dryRun }).catch(rethrowTyped);
+}
+
+export interface MediaServerStats {
+  activeStreams: number;
+  maxStreams: number;
+  streamsServed: number;
+  streamsRejected: number;
+}
+
+export async function mediaServerStats(): Promise<MediaServerStats> {
+  return invoke<MediaServerStats>("media_server_stats");
+}
+
+export interface ApiInfo {
+  enabled: boolean;
+  /** e.g. "http://127.0.0.1:12123/api"; null until the media server is up. */
+  baseUrl: string | null;
+  /** Regenerated every launch. */
+  token: string;
+  headerName: string;
+}
+
+/** Connection details for the external-tools API (see Settings.apiEnabled). */
+export async function getApiInfo(): Promise<ApiInfo> {
+  return invoke<ApiInfo>("get_api_info");
+}
+
+/**
+ * Track shown on the second-screen `/now` page; null clears it. Fires
+ * `now-showing-changed` ({ seq, path }) when the value changes.
+ */
+export async function setNowShowing(path: string | null): Promise<void> {
+  await invoke<void>("set_now_showing", { path });
+}
+
+/** Address of the `/now` page with this session's token; null until the media server is up. */
+export async function nowPageUrl(): Promise<string | null> {
+  return invoke<string | null>("now_page_url");
+}
+
+export interface ExportOptions {
+  inlineArt?: boolean;
+  pretty?: boolean;
+}
+
+export interface ExportSummary {
+  dest: string;
+  tracksWritten: number;
+  failed: { path: string; error: string }[];
+  /** Files read because the metadata cache had nothing current for them; 0 on a warm cache without `inlineArt`. */
+  filesOpened: number;
+}
+
+/** `source` is a folder path (scanned) or an explicit list of files. */
+export async function exportJson(
+  source: string | string[],
+  dest: string,
+  options?: ExportOptions
+): Promise<ExportSummary> {
+  return invoke<ExportSummary>("export_json", {
+    folderOrPaths: source,
+    dest,
+    options: options ?? null,
+  });
+}
+
+export interface ZipExportOptions {
+  /** Entry name without extension: {name} {artist} {albumartist} {album} {title} {index}; `{albumartist|artist}` takes the first with a value. */
+  nameTemplate?: string;
+  /** Applied to the archived copy only. */
+  patch?: MetaPatch;
+  stripComment?: boolean;
+  /** Re-encode the front cover as JPEG fitting this many pixels. */
+  coverMaxPx?: number;
+  /** Only run the pre-flight check; `files` comes back empty. */
+  dryRun?: boolean;
+}
+
+export interface VolumeSpace {
+  /** A target folder on the volume. */
+  folder: string;
+  /** Bytes, estimate plus margin. */
+  required: number;
+  /** null when the free space couldn't be read (not blocked). */
+  available: number | null;
+  shortfall: number;
+}
+
+/**
+ * Space and write-permission check a batch ran before touching any file.
+ * When it fails the batch throws CommandError "InsufficientSpace" (folder,
+ * required, available, shortfall) or "NoWriteAccess" (folders) instead.
+ */
+export interface Preflight {
+  requiredBytes: number;
+  volumes: VolumeSpace[];
+  checkedFolders: string[];
+  noWriteAccess: string[];
+  ok: boolean;
+}
+
+export interface ZipExportReport {
+  dest: string;
+  archiveSize: number;
+  files: { path: string; entryName: string | null; bytes: number; error: string | null }[];
+  cancelled: boolean;
+  preflight: Preflight;
+}
+
+/** Zip a selection (job kind "zip-export"); originals are never modified. */
+export async function exportSelectionZip(
+  paths: string[],
+  dest: string,
+  options?: ZipExportOptions
+): Promise<ZipExportReport> {
+  return invoke<ZipExportReport>("export_selection_zip", { paths, dest, options: options ?? null }).catch(rethrowTyped);
+}
+
+export type ConvertFormat = "aiff" | "wav" | "mp3";
+
+export interface ConvertOptions {
+  /** 16 or 24; defaults to the source's width when it is 24, else 16. PCM targets only. */
+  bitDepth?: number;
+  /** MP3 only: constant bitrate in kbps, 128, 192, 256 or 320 (default). */
+  bitrate?: number;
+  /** Output name without extension, placeholders as in `ZipExportOptions.nameTemplate`. */
+  nameTemplate?: string;
+  /** Replace outputs that already exist. */
+  overwrite?: boolean;
+  /** Only run the pre-flight check; `files` comes back empty. */
+  dryRun?: boolean;
+}
+
+export interface EncodeParams {
+  format: ConvertFormat;
+  sampleRate: number;
+  channels: number;
+  /** 16 for MP3: the width fed to the encoder. */
+  bitDepth: number;
+  /** MP3 only, kbps. */
+  bitrate?: number;
+}
+
+export interface ConvertReport {
+  destDir: string;
+  files: {
+    path: string;
+    output: string | null;
+    params: EncodeParams | null;
+    tagWarning: string | null;
+    limited?: FieldLimitHit[];
+    error: string | null;
+  }[];
+  cancelled: boolean;
+  preflight: Preflight;
+}
+
+/**
+ * Converted copies with tags and artwork carried over (job kind "convert").
+ * Sources are never modified.
+ */
+export async function convertFiles(
+  paths: string[],
+  targetFormat: ConvertFormat,
+  destDir: string,
+  options?: ConvertOptions
+): Promise<ConvertReport> {
+  return invoke<ConvertReport>("convert_files", { paths, targetFormat, options: options ?? null, destDir }).catch(rethrowTyped);
+}
+
+export interface TrackNumberOptions {
+  /** Replace existing track numbers; otherwise those files are skipped. */
+  overwrite?: boolean;
+  /** Rename to "03 - Name.ext" in the same pass. */
+  prefixFilenames?: boolean;
+}
+
+export interface TrackNumberResult {
+  path: string;
+  number: number;
+  /** As the tag will read, e.g. "3/24". */
+  display: string;
+  oldNumber: number | null;
+  oldTotal: number | null;
+  skipped: boolean;
+  tagWritten: boolean;
+  renamedTo: string | null;
+  error: string | null;
+  /** The tag is left alone; the rename still happens. */
+  skippedLocked?: LockedField[];
+}
+
+export interface TrackNumberReport {
+  results: TrackNumberResult[];
+  dryRun: boolean;
+  cancelled: boolean;
+  snapshotId: string | null;
+  preflight: Preflight;
+}
+
+/** Number files 01..N in the given order (job kind "track-numbers"). */
+export async function assignTrackNumbers(
+  pathsInOrder: string[],
+  writeTotal: boolean,
+  dryRun: boolean,
+  start = 1,
+  options?: TrackNumberOptions
+): Promise<TrackNumberReport> {
+  return invoke<TrackNumberReport>("assign_track_numbers", { pathsInOrder, start, writeTotal, dryRun, options: options ?? null }).catch(rethrowTyped);
+}
+
+export interface MergeOutcome extends ShadowMark {
+  path: string;
+  oldComment: string;
+  newComment: string;
+  changed: boolean;
+  /** ["comment"] when locked: `newComment` wasn't written. */
+  skippedLocked?: LockedField[];
+}
+
+/** Add/remove comment tokens server-side (audited, no-op if unchanged). */
+export async function mergeTags(
+  path: string,
+  add: string[],
+  remove: string[] = []
+): Promise<MergeOutcome> {
+  return invoke<MergeOutcome>("merge_tags", { path, add, remove });
+}
+
+export interface TagNeighbor {
+  path: string;
+  similarity: number;
+  /** Its BPM less the track's. */
+  bpmDelta: number;
+}
+
+export interface TagSuggestion {
+  tag: string;
+  /** Sum of the similarities of the neighbours carrying the tag. */
+  score: number;
+  count: number;
+  /** The most similar carriers, at most 5. */
+  evidence: TagNeighbor[];
+}
+
+/**
+ * Tags for `path` from cached tracks with the same artist, a BPM within 3 and
+ * the same key, best first; the track's own tags are left out. `roots`
+ * limits neighbours to a workspace. Add one with `mergeTags(path, [tag])`.
+ */
+export async function suggestTags(
+  path: string,
+  limit?: number,
+  roots?: string[]
+): Promise<{ path: string; neighbors: number; suggestions: TagSuggestion[] }> {
+  return invoke("suggest_tags", { path, limit, roots });
+}
+
+export interface ToggleReport {
+  /** The tag as written, after the tag policy. */
+  tag: string;
+  direction: "add" | "remove";
+  results: { path: string; outcome: MergeOutcome | null; error: string | null }[];
+  snapshotId: string | null;
+  preflight: Preflight;
+}
+
+/**
+ * Files dropped onto a tag chip: add the tag where missing, or remove it from
+ * all of them when every (readable) file already has it.
+ */
+export async function toggleTagSmart(paths: string[], tag: string): Promise<ToggleReport> {
+  return invoke<ToggleReport>("toggle_tag_smart", { paths, tag }).catch(rethrowTyped);
+}
+
+export interface SoftTagReport {
+  /** `tags`: tokens removed (or restored) in that file, as written; with `error` set too, the removal was queued for retry (still restorable). */
+  results: { path: string; tags: string[]; error: string | null; skippedLocked?: LockedField[] }[];
+  changed: number;
+  cancelled: boolean;
+  snapshotId?: string | null;
+  preflight: Preflight;
+}
+
+/**
+ * Batch-remove `tags` but remember what was removed (job kind
+ * "soft-remove-tags"); see `restoreRemovedTag`.
+ */
+export async function removeTagsSoft(paths: string[], tags: string[]): Promise<SoftTagReport> {
+  return invoke<SoftTagReport>("remove_tags_soft", { paths, tags }).catch(rethrowTyped);
+}
+
+/** Undo the latest soft removal of `tag` in each file (job kind "restore-removed-tag"). */
+export async function restoreRemovedTag(paths: string[], tag: string): Promise<SoftTagReport> {
+  return invoke<SoftTagReport>("restore_removed_tag", { paths, tag }).catch(rethrowTyped);
+}
+
+/** Forget soft removals older than `olderThanDays` (0 = all); returns how many. */
+export async function purgeRemovedHistory(olderThanDays: number): Promise<number> {
+  return invoke<number>("purge_removed_history", { olderThanDays });
+}
+
+export interface RemovedTagGroup {
+  tag: string;
+  paths: string[];
+  removedAt: string;
+}
+
+/** Undoable removals by tag, newest first ("removed #wip from 12 files — undoable"). */
+export async function listRemovedTags(sessionOnly: boolean): Promise<RemovedTagGroup[]> {
+  return invoke<RemovedTagGroup[]>("list_removed_tags", { sessionOnly });
+}
+
+export type SearchField = "comment" | "lyrics" | "title" | "artist" | "album";
+
+export interface FieldHit {
+  field: SearchField;
+  snippet: string;
+  /** [start, end) in `snippet` (JS string offsets). */
+  highlights: [number, number][];
+  clippedStart: boolean;
+  clippedEnd: boolean;
+}
+
+export interface SearchReport {
+  matches: { path: string; title: string | null; artist: string | null; hits: FieldHit[] }[];
+  /** Stopped at 500 matching files. */
+  truncated: boolean;
+  searched: number;
+  refreshed: number;
+  tookMs: number;
+}
+
+/**
+ * Case-insensitive search over the metadata cache (stale entries re-read first).
+ * mode "all" (default): every word somewhere in `fields`; "phrase": the whole query.
+ * `currentFolderOnly` searches the last opened folder instead of `folders`.
+ */
+export async function fullTextSearch(
+  folders: string[],
+  query: string,
+  fields: SearchField[] = ["comment"],
+  recursive = true,
+  options?: { mode?: "all" | "phrase"; currentFolderOnly?: boolean }
+): Promise<SearchReport> {
+  return invoke<SearchReport>("full_text_search", { folders, query, fields, recursive, options });
+}
+
+export interface TagValidation {
+  input: string;
+  normalized: string | null;
+  changed: boolean;
+  error: string | null;
+}
+
+/** Normalized form of a tag under the current policy, or why it's rejected. */
+export async function validateTag(tag: string): Promise<TagValidation> {
+  return invoke<TagValidation>("validate_tag", { tag });
+}
+
+export interface NormalizeResult {
+  path: string;
+  before: string;
+  after: string;
+  changed: boolean;
+  /** `to: null` means merged into a duplicate. */
+  changes: { from: string; to: string | null }[];
+  rejected: { tag: string; reason: string }[];
+  error: string | null;
+  plan?: WritePlan;
+  skippedLocked?: LockedField[];
+  /** Values cut or left out for a tag type's length limit. */
+  limited?: FieldLimitHit[];
+}
+
+export interface NormalizeReport {
+  results: NormalizeResult[];
+  cancelled: boolean;
+  /** See `listSnapshots`; set for batches above `snapshotThreshold`. */
+  snapshotId?: string | null;
+  preflight: Preflight;
+}
+
+/** Apply the tag policy to every file under `folder` (job kind "normalize-tags"). */
+export async function normalizeExistingTags(folder: string, dryRun: boolean): Promise<NormalizeReport> {
+  return invoke<NormalizeReport>("normalize_existing_tags", { folder, dryRun }).catch(rethrowTyped);
+}
+
+export interface TagConflict {
+  path: string;
+  field: "bpm" | "key";
+  /** null when the tag is missing. */
+  tagValue: string | null;
+  nameValue: string;
+  source: "filename" | "folder";
+  sourceName: string;
+  sourceText: string;
+}
+
+export interface ConflictReport {
+  conflicts: TagConflict[];
+  scanned: number;
+  cancelled: boolean;
+}
+
+/** BPM/key tags that disagree with the file or folder name (job kind "tag-conflicts"). */
+export async function findTagFilenameConflicts(folder: string, recursive: boolean): Promise<ConflictReport> {
+  return invoke<ConflictReport>("find_tag_filename_conflicts", { folder, recursive });
+}
+
+export interface ResolveResult {
+  path: string;
+  field: "bpm" | "key";
+  applied: boolean;
+  renamedTo: string | null;
+  error: string | null;
+  skippedLocked?: LockedField[];
+}
+
+/** "filename" writes the name's value into the tags; "tag" renames the file. */
+export async function resolveConflicts(items: TagConflict[], prefer: "tag" | "filename"): Promise<{ results: ResolveResult[]; snapshotId: string | null; preflight: Preflight }> {
+  return invoke<{ results: ResolveResult[]; snapshotId: string | null; preflight: Preflight }>("resolve_conflicts", { items, prefer }).catch(rethrowTyped);
+}
+
+export type YearIssueKind = "missing" | "zero" | "implausible" | "conflicting";
+
+export interface YearIssue {
+  path: string;
+  issues: YearIssueKind[];
+  /** One entry per tag type carrying a year; `year` is 0 for "0000", null when unreadable. */
+  years: { tagType: string; raw: string; year: number | null }[];
+  current: number | null;
+  filenameYear: number | null;
+  folderYear: number | null;
+}
+
+export interface YearReport {
+  issues: YearIssue[];
+  scanned: number;
+  cancelled: boolean;
+}
+
+/** Missing, zero, implausible or conflicting years under `folder` (job kind "year-issues"). */
+export async function findYearIssues(folder: string, recursive: boolean): Promise<YearReport> {
+  return invoke<YearReport>("find_year_issues", { folder, recursive });
+}
+
+export type YearStrategy = "from-filename" | "from-folder" | "explicit";
+
+export interface YearFixReport {
+  results: { path: string; old: string | null; new: string | null; applied: boolean; error: string | null; skippedLocked?: LockedField[]; limited?: FieldLimitHit[] }[];
+  dryRun: boolean;
+  cancelled: boolean;
+  snapshotId: string | null;
+  preflight: Preflight;
+}
+
+/** Write each file's year from the chosen source (job kind "fix-years"); `year` per item is used by "explicit". */
+export async function fixYears(items: { path: string; year?: number }[], strategy: YearStrategy, dryRun: boolean): Promise<YearFixReport> {
+  return invoke<YearFixReport>("fix_years", { items, strategy, dryRun }).catch(rethrowTyped);
+}
+
+export interface OwnedMatch {
+  newPath: string;
+  existingPath: string;
+  /** 1 = identical audio; meta matches are 0.95, 0.85 (original/extended mix vs none) or 0.7 (artist subset). */
+  confidence: number;
+  reason: string;
+}
+
+export interface OwnedReport {
+  matches: OwnedMatch[];
+  newFiles: number;
+  libraryFiles: number;
+  unreadable: string[];
+  cancelled: boolean;
+}
+
+/**
+ * Incoming files already in the library (job kind "already-owned"). "hash"
+ * compares audio streams (cached), "meta" normalized artist + title.
+ */
+export async function findAlreadyOwned(newFolder: string, libraryFolders: string[], method: "hash" | "meta"): Promise<OwnedReport> {
+  return invoke<OwnedReport>("find_already_owned", { newFolder, libraryFolders, method });
+}
+
+export type CompareField = "comment" | "title" | "artist" | "bpm" | "key" | "artwork";
+
+export interface ComparedPair {
+  a: string;
+  b: string;
+  /** Absent for equal pairs. */
+  diffs?: { field: CompareField; a: string | null; b: string | null }[];
+}
+
+export interface CompareReport {
+  equal: ComparedPair[];
+  different: ComparedPair[];
+  onlyInA: string[];
+  onlyInB: string[];
+  unreadable: string[];
+  fields: CompareField[];
+  cancelled: boolean;
+  exportedTo: string | null;
+}
+
+/**
+ * Pair the files of two folders by name (ignoring "03 - " number prefixes) or
+ * audio hash and compare `fields` (empty = all); job kind "compare-folders".
+ * `exportTo` also writes the report, as CSV for a ".csv" path, else JSON.
+ */
+export async function compareFolders(
+  folderA: string,
+  folderB: string,
+  matchBy: "name" | "hash",
+  fields: CompareField[] = [],
+  options: { exportTo?: string } = {},
+): Promise<CompareReport> {
+  return invoke<CompareReport>("compare_folders", { folderA, folderB, matchBy, fields, options });
+}
+
+export interface ArtworkCandidate {
+  path: string;
+  fileName: string;
+  width: number;
+  height: number;
+  bytes: number;
+  /** Named exactly cover/folder/front/album. */
+  exactName: boolean;
+  /** Small JPEG preview as a data URL. */
+  thumbnail: string | null;
+}
+
+/** Cover-like images in the folder of `path`, best first (exact names, then larger square images). */
+export async function suggestArtwork(path: string): Promise<ArtworkCandidate[]> {
+  return invoke<ArtworkCandidate[]>("suggest_artwork", { path });
+}
+
+export interface ArtworkReport {
+  /** `noOp`: the file already had this exact cover and wasn't rewritten. */
+  results: { path: string; skipped: boolean; noOp: boolean; applied: boolean; error: string | null; skippedLocked?: LockedField[] }[];
+  /** Files embedded (or that would be, in a dry run). */
+  written: number;
+  /** Files that already had this cover, e.g. "34 already had this cover". */
+  identical: number;
+  failed: number;
+  /** The image as embedded, after resizing/conversion. */
+  width: number;
+  height: number;
+  bytes: number;
+  dryRun: boolean;
+  cancelled: boolean;
+  snapshotId: string | null;
+  preflight: Preflight;
+}
+
+/**
+ * Embed `imagePath` as the front cover of `paths` (job kind "artwork"). Files
+ * that already have a cover are skipped unless `overwrite`, and ones whose
+ * cover already is this image are never rewritten; images larger
+ * than `maxPx` (default 1400) are downsized, WebP is converted to JPEG.
+ */
+export async function applyFolderArtwork(
+  paths: string[],
+  imagePath: string,
+  dryRun: boolean,
+  options?: { overwrite?: boolean; maxPx?: number }
+): Promise<ArtworkReport> {
+  return invoke<ArtworkReport>("apply_folder_artwork", { paths, imagePath, dryRun, options: options ?? null }).catch(rethrowTyped);
+}
+
+/** Cover colors as "#rrggbb"; tracks without usable art get a fixed gray set with `neutral`. */
+export interface ArtworkPalette {
+  path: string;
+  dominant: string;
+  /** Biggest cluster first. */
+  colors: string[];
+  /** Missing or undecodable art, or a grayscale cover. */
+  neutral: boolean;
+  hasArt: boolean;
+}
+
+export async function artworkPalette(path: string): Promise<ArtworkPalette> {
+  return invoke<ArtworkPalette>("artwork_palette", { path });
+}
+
+/**
+ * Compute palettes for `folder` on a "palettes" job (after a scan); returns
+ * the job id. Each result arrives as an `artwork-palette` event (ArtworkPalette).
+ */
+export async function warmArtworkPalettes(folder: string): Promise<string> {
+  return invoke<string>("warm_artwork_palettes", { folder });
+}
+
+export interface InboxRule {
+  folder: string;
+  addTags: string[];
+  renameTemplate?: string | null;
+  enabled: boolean;
+}
+
+/** Payload of the `inbox-file-processed` event. */
+export interface InboxEvent {
+  folder: string;
+  path: string;
+  renamedTo?: string | null;
+  tags: string[];
+  error?: string | null;
+}
+
+export async function setInboxRule(
+  folder: string,
+  addTags: string[],
+  renameTemplate: string | null = null
+): Promise<InboxRule> {
+  return invoke<InboxRule>("set_inbox_rule", { folder, addTags, renameTemplate });
+}
+
+export async function setInboxRuleEnabled(
+  folder: string,
+  enabled: boolean
+): Promise<void> {
+  await invoke<void>("set_inbox_rule_enabled", { folder, enabled });
+}
+
+export async function removeInboxRule(folder: string): Promise<void> {
+  await invoke<void>("remove_inbox_rule", { folder });
+}
+
+export async function listInboxRules(): Promise<InboxRule[]> {
+  return invoke<InboxRule[]>("list_inbox_rules");
+}
+
+export interface TagBlockReport {
+  tagType: string;
+  itemCount: number;
+  pictureCount: number;
+  comment?: string | null;
+}
+
+export interface InspectReport {
+  path: string;
+  fileType: string;
+  primaryTagType: string;
+  tags: TagBlockReport[];
+  hasApe: boolean;
+  readOrder: string[];
+  writeTargets: string[];
+  /** Leading ID3v2 tag of an MP3 and how much of it is padding. */
+  id3Padding: { tagBytes: number; paddingBytes: number } | null;
+  /** MP3Gain's undo fields in the APE tag of an MP3, with why they matter. */
+  mp3gainUndo: Mp3GainUndo | null;
+}
+
+/**
+ * What MP3Gain left in an MP3's APE tag to undo the gain it applied to the
+ * audio frames. `undo` is `MP3GAIN_UNDO`, e.g. "+003,+003,N".
+ */
+export interface Mp3GainUndo {
+  undo: string | null;
+  minMax: string | null;
+  albumMinMax: string | null;
+  explanation: string;
+}
+
+export async function inspectTags(path: string): Promise<InspectReport> {
+  return invoke<InspectReport>("inspect_tags", { path });
+}
+
+export interface TagBlockSize {
+  /** "Id3v2", "Id3v1", "Ape", "VorbisComments", "FlacPictures", "FlacPadding", "Mp4Ilst", "Mp4Free", "RiffInfo", "Id3v2Chunk". */
+  block: string;
+  bytes: number;
+  paddingBytes: number | null;
+}
+
+export interface TagSizeReport {
+  path: string;
+  fileBytes: number;
+  tagBytes: number;
+  audioBytes: number;
+  /** One decimal, e.g. 97.3. */
+  audioPercent: number;
+  blocks: TagBlockSize[];
+}
+
+/** On-disk size of each tag block in the file. Ogg comments aren't measured. */
+export async function tagSizeReport(path: string): Promise<TagSizeReport> {
+  return invoke<TagSizeReport>("tag_size_report", { path });
+}
+
+export interface FolderTagSizeReport {
+  scanned: number;
+  fileBytes: number;
+  tagBytes: number;
+  byBlock: { block: string; bytes: number; files: number }[];
+  thresholdBytes: number;
+  /** Files with more tag bytes than the threshold, biggest first. */
+  heavy: TagSizeReport[];
+  cancelled: boolean;
+}
+
+/** Tag sizes summed over a folder (job kind "tag-sizes"); threshold defaults to 1 MiB. */
+export async function folderTagSizeReport(folder: string, recursive: boolean, thresholdBytes?: number): Promise<FolderTagSizeReport> {
+  return invoke<FolderTagSizeReport>("folder_tag_size_report", { folder, recursive, thresholdBytes: thresholdBytes ?? null });
+}
+
+export interface CommentResolution extends WriteOutcome {
+  /** The kept tag's comment. */
+  comment: string;
+}
+
+/**
+ * Copy the comment of the `keep` tag type ("Id3v2", "Ape", "RiffInfo", ...)
+ * to every other tag on the file except ID3v1. A locked comment is left as
+ * it is (`skippedLocked`).
+ */
+export async function resolveCommentConflict(path: string, keep: string): Promise<CommentResolution> {
+  return invoke<CommentResolution>("resolve_comment_conflict", { path, keep }).catch(rethrowTyped);
+}
+
+export interface PaddingRewrite extends ShadowMark {
+  path: string;
+  sizeBefore: number;
+  sizeAfter: number;
+  paddingBefore: number;
+  paddingAfter: number;
+}
+
+/** Drop an MP3's excess ID3 padding (down to 4 KiB); frames are kept as they are. */
+export async function rewriteWithMinimalPadding(path: string): Promise<PaddingRewrite> {
+  return invoke<PaddingRewrite>("rewrite_with_minimal_padding", { path });
+}
+
+/** Folds an MP3's APEv2 fields into ID3v2 and removes the APE block. */
+/** MP3Gain undo fields are kept in an APE block of their own (`keptMp3gain`). */
+export async function convertApeToId3(
+  path: string
+): Promise<{ path: string; copiedItems: number; copiedPictures: number; keptMp3gain?: string[] } & WriteOutcome> {
+  return invoke<{ path: string; copiedItems: number; copiedPictures: number; keptMp3gain?: string[] } & WriteOutcome>("convert_ape_to_id3", { path }).catch(rethrowTyped);
+}
+
+/**
+ * Remove MP3Gain's undo fields from an MP3 after a native confirmation;
+ * `confirmed` is false when the user declined and nothing was written.
+ */
+export async function removeMp3gainUndo(
+  path: string
+): Promise<{ confirmed: boolean; path: string; removed: string[] } & WriteOutcome> {
+  return invoke("remove_mp3gain_undo", { path });
+}
+
+export interface PhaseProgress {
+  name: string;
+  done: number;
+  total: number;
+}
+
+/**
+ * Payload of `job-started` / `job-progress`, and the rows of `listJobs`.
+ * Events of one job can arrive out of order: ignore any whose `seq` isn't
+ * above the last one seen.
+ */
+export interface JobInfo {
+  jobId: string;
+  kind: string;
+  label: string;
+  startedAt: string;
+  /** Within the current phase, if any. */
+  done: number;
+  total: number;
+  phase: string | null;
+  /** Phases begun so far, the current one last. */
+  phases: PhaseProgress[];
+  /** Items per second over the last 10 s. */
+  rate: number | null;
+  /** Time left in the current phase. */
+  etaMs: number | null;
+  seq: number;
+}
+
+/** Payload of `job-finished`, sent for every job, cancelled ones included. */
+export interface JobFinished {
+  jobId: string;
+  kind: string;
+  status: "done" | "cancelled" | "failed";
+  error?: string | null;
+  seq: number;
+  tookMs: number;
+  /** How far the job got: the last counts and every phase's. */
+  done: number;
+  total: number;
+  phases: PhaseProgress[];
+}
+
+export async function cancelJob(jobId: string): Promise<boolean> {
+  return invoke<boolean>("cancel_job", { jobId });
+}
+
+export async function listJobs(): Promise<JobInfo[]> {
+  return invoke<JobInfo[]>("list_jobs");
+}
+
+/** Keep a running job from raising its completion notification (or undo that). */
+export async function setJobNotify(jobId: string, notify: boolean): Promise<boolean> {
+  return invoke<boolean>("set_job_notify", { jobId, notify });
+}
+
+/**
+ * Payload of `job-notification-opened`: the window came forward after a job
+ * notification (clicking one does that; Tauri can't report the click itself).
+ */
+export interface JobNotificationOpened {
+  jobId: string;
+  kind: string;
+}
+
+export interface NotificationStatus {
+  enabled: boolean;
+  afterSecs: number;
+  /** Why notifications won't show on this system (e.g. an unbundled macOS build). */
+  unavailable: string | null;
+}
+
+export async function notificationStatus(): Promise<NotificationStatus> {
+  return invoke<NotificationStatus>("notification_status");
+}
+
+export interface CommandTiming {
+  command: string;
+  ms: number;
+  /** `false` when the call was rejected or the command panicked. */
+  ok: boolean;
+  at: string;
+}
+
+export interface CommandStats {
+  command: string;
+  count: number;
+  failures: number;
+  p50Ms: number;
+  p95Ms: number;
+  maxMs: number;
+}
+
+export interface JobTiming {
+  kind: string;
+  label: string;
+  ms: number;
+  status: JobFinished["status"];
+  /** "walk", "parse", "write", "hash"…; `count` is how often the phase ran. */
+  phases: { phase: string; ms: number; count: number }[];
+  at: string;
+}
+
+export interface PerfMetrics {
+  /** Aggregated over `recent`, slowest p95 first. */
+  commands: CommandStats[];
+  /** Last 500 invocations, oldest first. */
+  recent: CommandTiming[];
+  /** Last 100 finished jobs. */
+  jobs: JobTiming[];
+  operationProfile: OperationProfile;
+}
+
+/** In-memory command and job timings for the debug panel. */
+export async function getPerfMetrics(): Promise<PerfMetrics> {
+  return invoke<PerfMetrics>("get_perf_metrics");
+}
+
+export async function resetPerfMetrics(): Promise<void> {
+  await invoke<void>("reset_perf_metrics");
+}
+
+export interface WaveformStyle {
+  /** "#rgb", "#rrggbb" or "#rrggbbaa" */
+  background?: string;
+  fill?: string;
+  /** Gradient end color (left → right). */
+  fillEnd?: string;
+  coverCorner?: "topLeft" | "topRight" | "bottomLeft" | "bottomRight";
+  coverSize?: number;
+}
+
+export interface RenderedWaveform {
+  width: number;
+  height: number;
+  bytes: number;
+  dest?: string | null;
+  dataUrl?: string | null;
+}
+
+/** Renders a waveform PNG to `dest`, or returns it as a data URL when `dest` is null. */
+export async function renderWaveformImage(
+  path: string,
+  width: number,
+  height: number,
+  style: WaveformStyle = {},
+  dest: string | null = null
+): Promise<RenderedWaveform> {
+  return invoke<RenderedWaveform>("render_waveform_image", { path, width, height, style, dest });
+}
+
+export interface TemplateResult {
+  path: string;
+  before: string;
+  after: string;
+  changed: boolean;
+  error?: string | null;
+  /** Dry runs only. */
+  plan?: WritePlan;
+  skippedLocked?: LockedField[];
+  /** Values cut or left out for a tag type's length limit. */
+  limited?: FieldLimitHit[];
+}
+
+/**
+ * Writes a templated comment to each file. Placeholders: {bpm} {key} {tags}
+ * {genre} {year} {existing_prose}. With `dryRun` nothing is written.
+ */
+export async function applyCommentTemplate(
+  paths: string[],
+  template: string,
+  dryRun: boolean,
+  cleanup = true
+): Promise<{ results: TemplateResult[]; snapshotId: string | null; preflight: Preflight }> {
+  return invoke<{ results: TemplateResult[]; snapshotId: string | null; preflight: Preflight }>("apply_comment_template", { paths, template, dryRun, cleanup }).catch(rethrowTyped);
+}
+
+export interface SavedTemplate {
+  name: string;
+  template: string;
+}
+
+export async function saveCommentTemplate(name: string, template: string): Promise<void> {
+  await invoke<void>("save_comment_template", { name, template });
+}
+
+export async function deleteCommentTemplate(name: string): Promise<void> {
+  await invoke<void>("delete_comment_template", { name });
+}
+
+export async function listCommentTemplates(): Promise<SavedTemplate[]> {
+  return invoke<SavedTemplate[]>("list_comment_templates");
+}
+
+export interface LibraryFile {
+  path: string;
+  fileName: string;
+  /** The root folder this file was found under. */
+  root: string;
+  /** `false` for files listed by `includeUnsupported`: read-only, see `probeFile`. */
+  supported: boolean;
+  status: FileStatus;
+  statusReason?: string | null;
+  /** Sniffed format when `status` is "mismatched". */
+  realFormat?: string;
+}
+
+export interface ScanOptions {
+  includeHidden?: boolean;
+  maxDepth?: number | null;
+  /** Also list other media files (video, disabled formats) with `supported: false`. */
+  includeUnsupported?: boolean;
+}
+
+/** Ignore file path (or "settings" for `scanExcludes`) and how many entries it kept out. */
+export interface IgnoreHits {
+  source: string;
+  entries: number;
+}
+
+export interface ScanResult {
+  files: LibraryFile[];
+  ignored: IgnoreHits[];
+}
+
+/** Scans several roots as one library; files reachable from two roots appear once.
+ *  `.taggerignore` files and `scanExcludes` leave paths out; `ignored` says which did. */
+export async function scanFolders(
+  paths: string[],
+  recursive = true,
+  opts: ScanOptions = {}
+): Promise<ScanResult> {
+  return invoke<ScanResult | ChunkedResponse>("scan_folders", { paths, recursive, opts }).then((r) => resolveChunked(r)).catch(rethrowTyped);
+}
+
+export interface ProbeInfo {
+  path: string;
+  supported: boolean;
+  size: number;
+  modified: string | null;
+  /** Sniffed from the content ("matroska", "m4a", ...), else the extension. */
+  container: string;
+  codec: string | null;
+  sampleRate: number | null;
+  channels: number | null;
+  durationSecs: number | null;
+  /** Set when the headers couldn't be read; only size and `modified` are known. */
+  probeError: string | null;
+}
+
+/** Best-effort read-only info for any file, for greyed-out unsupported rows. */
+export async function probeFile(path: string): Promise<ProbeInfo> {
+  return invoke<ProbeInfo>("probe_file", { path });
+}
+
+export interface FormatStats {
+  tracks: number;
+  hours: number;
+  avgBitrateKbps: number | null;
+}
+
+export interface WorkspaceStats {
+  tracks: number;
+  /** Couldn't be read; counted in `tracks` only. */
+  unreadable: number;
+  hours: number;
+  /** Tracks with no known duration, left out of `hours`. */
+  missingDuration: number;
+  avgBitrateKbps: number | null;
+  /** Keyed by lowercase extension. */
+  formats: Record<string, FormatStats>;
+  /** Tracks with each field non-empty. */
+  coverage: { title: number; artist: number; album: number; genre: number; comment: number; releaseDate: number };
+  topTags: { tag: string; tracks: number }[];
+  /** "YYYY-MM" from file modification times -> tracks. */
+  additionsPerMonth: Record<string, number>;
+  refreshed: number;
+  cancelled: boolean;
+}
+
+/** Dashboard totals from the metadata cache (job kind "workspace-stats"). */
+export async function workspaceStats(roots: string[], recursive = true): Promise<WorkspaceStats> {
+  return invoke<WorkspaceStats>("workspace_stats", { roots, recursive });
+}
+
+export interface CueSegment {
+  startSecs: number;
+  endSecs: number;
+  /** Start of the segment's loudest 10 s; null when spaced without peaks. */
+  dropInSecs: number | null;
+}
+
+export interface CuePoints {
+  path: string;
+  durationSecs: number;
+  /** The audible span the segments cover, leading/trailing silence cut. */
+  audioStartSecs: number;
+  audioEndSecs: number;
+  /** "even" when the file couldn't be decoded: tagged duration, split evenly. */
+  source: "peaks" | "even";
+  segments: CueSegment[];
+}
+
+/** `count` (1..64) cues spread over the audible part of a track, for preview hops. */
+export async function previewCuePoints(path: string, count: number): Promise<CuePoints> {
+  return invoke<CuePoints>("preview_cue_points", { path, count });
+}
+
+export type SupportBundlePart =
+  | "session_log" | "recent_logs" | "diagnostics" | "prefs" | "banks" | "bank_contents" | "perf" | "redact_paths";
+
+/**
+ * Zips logs, diagnostics, prefs and bank info into Downloads for a bug report
+ * and returns the zip's path. An empty `include` means every part but
+ * "bank_contents", with paths redacted. Nothing is uploaded.
+ */
+export async function createSupportBundle(include: SupportBundlePart[] = []): Promise<string> {
+  return invoke<string>("create_support_bundle", { include });
+}
+
+export interface LameTag {
+  /** As written, e.g. "LAME3.100". */
+  version: string;
+  vbrMethod: "cbr" | "abr" | "vbr-old" | "vbr-mtrh" | "vbr-mt" | "vbr-m4" | "cbr-2pass" | "abr-2pass" | "unknown";
+  lowpassHz: number | null;
+  /** "V2", "320 kbps", "extreme", ... */
+  preset: string | null;
+  bitrateKbps: number | null;
+  encoderDelay: number;
+  padding: number;
+}
+
+export interface Mp3Info {
+  version: "MPEG-1" | "MPEG-2" | "MPEG-2.5";
+  layer: number;
+  sampleRate: number;
+  channelMode: "stereo" | "joint" | "dual" | "mono";
+  bitrateKbps: number;
+  header: "Xing" | "Info" | "VBRI" | null;
+  frames: number | null;
+  bytes: number | null;
+  quality: number | null;
+  lame: LameTag | null;
+}
+
+export interface EncodingInfo {
+  path: string;
+  encoder: string | null;
+  encoderSource: "lameTag" | "vbriHeader" | "vendor" | "encoderTag" | null;
+  vbr: boolean | null;
+  /** MP3s only: the Xing/Info/VBRI and LAME headers. */
+  mp3: Mp3Info | null;
+}
+
+/** Encoder and MP3 encoding details, for choosing between duplicates. */
+export async function encodingInfo(path: string): Promise<EncodingInfo> {
+  return invoke<EncodingInfo>("encoding_info", { path });
+}
+
+export interface EmbeddedPicture {
+  /** ID3v2/FLAC picture type, 3 = front cover; null for a compressed frame. */
+  picType: number | null;
+  bytes: number;
+}
+
+export interface PictureScan {
+  path: string;
+  /** Empty for formats other than MP3 and FLAC. */
+  pictures: EmbeddedPicture[];
+  totalBytes: number;
+  budgetBytes: number;
+  overBudget: boolean;
+}
+
+/** Embedded pictures of an MP3 or FLAC and their sizes, read from the tag headers only. */
+export async function scanEmbeddedPictures(path: string): Promise<PictureScan> {
+  return invoke<PictureScan>("scan_embedded_pictures", { path });
+}
+
+export interface CoverCleanup extends WriteOutcome {
+  path: string;
+  removed: number;
+}
+
+/** Rewrite `path` with only its first front cover, for files with piles of embedded art. */
+export async function keepFirstFrontCover(path: string): Promise<CoverCleanup> {
+  return invoke<CoverCleanup>("keep_first_front_cover", { path }).catch(rethrowTyped);
+}
+
+export interface PresetActionOutcome {
+  action: PresetAction["type"];
+  /** "missing": an unmet `require_field` (a warning); "rolledBack": undone because another action on the file failed. */
+  status: "applied" | "unchanged" | "missing" | "failed" | "rolledBack";
+  detail: string | null;
+}
+
+export interface PresetFileResult extends ShadowMark {
+  path: string;
+  changed: boolean;
+  error: string | null;
+  actions: PresetActionOutcome[];
+}
+
+export interface PresetReport {
+  preset: string;
+  dryRun: boolean;
+  results: PresetFileResult[];
+  changed: number;
+  failed: number;
+  warned: number;
+  /** Null on a dry run. */
+  snapshotId: string | null;
+  preflight: Preflight;
+}
+
+export async function listPresets(bank: string): Promise<Preset[]> {
+  return invoke<Preset[]>("list_presets", { bank });
+}
+
+/**
+ * Replaces a preset of the same name; unknown action types or fields are rejected. Returns the bank's presets.
+ * Throws CommandError "SchemaMismatch" after a mismatched `handshake`.
+ */
+export async function savePreset(bank: string, preset: Preset): Promise<Preset[]> {
+  return invoke<Preset[]>("save_preset", { bank, preset }).catch(rethrowTyped);
+}
+
+/** Throws CommandError "SchemaMismatch" after a mismatched `handshake`. */
+export async function deletePreset(bank: string, name: string): Promise<Preset[]> {
+  return invoke<Preset[]>("delete_preset", { bank, name }).catch(rethrowTyped);
+}
+
+/** Apply a bank preset; per file, every action applies or none does. */
+export async function applyPreset(paths: string[], bank: string, presetName: string, dryRun = false): Promise<PresetReport> {
+  return invoke<PresetReport>("apply_preset", { paths, bank, presetName, dryRun }).catch(rethrowTyped);
+}
+
+export type TracklistFormat = "auto" | "text" | "csv" | "rekordbox";
+
+export interface TracklistEntry {
+  /** 1-based place in the set. */
+  position: number;
+  /** 1-based line of the input. */
+  line: number;
+  raw: string;
+  time: string | null;
+  artist: string;
+  title: string;
+}
+
+export interface TracklistCandidate {
+  path: string;
+  artist: string | null;
+  title: string | null;
+  /** 0..1; 0.75 and up counts as a match. */
+  confidence: number;
+}
+
+export interface TracklistMatch extends TracklistCandidate {
+  entry: TracklistEntry;
+  alternatives: TracklistCandidate[];
+}
+
+export interface UnmatchedTracklistLine {
+  line: number;
+  raw: string;
+  entry: TracklistEntry | null;
+  reason: string;
+  best: TracklistCandidate | null;
+}
+
+export interface TracklistImport {
+  format: TracklistFormat;
+  matches: TracklistMatch[];
+  unmatched: UnmatchedTracklistLine[];
+  searched: number;
+  refreshed: number;
+}
+
+/**
+ * Parse a set tracklist (pasted text or a file path; plain text, CSV or a
+ * Rekordbox history export) and match it against the library under `roots`,
+ * every known root by default. Nothing is written.
+ */
+export async function importTracklist(textOrPath: string, format: TracklistFormat = "auto", roots?: string[]): Promise<TracklistImport> {
+  return invoke<TracklistImport>("import_tracklist", { textOrPath, format, roots: roots ?? null });
+}
+
+/** Adds `tags` to each of `matches` (paths) through the batch merge, after one snapshot. */
+export async function tagMatchedTracks(matches: string[], tags: string[]): Promise<{ tags: string[]; results: ToggleReport["results"]; snapshotId: string | null; preflight: Preflight }> {
+  return invoke("tag_matched_tracks", { matches, tags }).catch(rethrowTyped);
+}
+
+export interface CacheStats {
+  name: "metadata" | "peaks" | "palettes" | "audioHashes" | "previews";
+  entries: number;
+  /** On disk; null for the in-memory palette cache. */
+  bytes: number | null;
+}
+
+export interface CachePass {
+  name: CacheStats["name"];
+  /** Entries of files that are gone. */
+  pruned: number;
+  /** Least recently used, past the budget. */
+  evicted: number;
+  bytesBefore: number | null;
+  bytesAfter: number | null;
+}
+
+export interface MaintenanceReport {
+  caches: CachePass[];
+  /** Stopped for user activity; caches not listed weren't touched. */
+  interrupted: boolean;
+  manual: boolean;
+  tookMs: number;
+}
+
+export async function getCacheStats(): Promise<CacheStats[]> {
+  return invoke<CacheStats[]>("get_cache_stats");
+}
+
+/** Prunes and trims every cache now, as the idle pass does, without stopping for activity. */
+export async function runMaintenanceNow(): Promise<MaintenanceReport> {
+  return invoke<MaintenanceReport>("run_maintenance_now");
+}
+
+export type StorageState = "commentOnly" | "fieldOnly" | "bothConsistent" | "bothConflicting" | "neither";
+
+export interface StorageEntry {
+  path: string;
+  state: StorageState;
+  commentTags: string[];
+  fieldTags: string[];
+}
+
+export interface StorageAudit {
+  files: StorageEntry[];
+  scanned: number;
+  cancelled: boolean;
+}
+
+/** Where the tags of each file under `folder` live: comment, AUDIOTAGGER_TAGS field, both or neither. */
+export async function auditTagStorage(folder: string): Promise<StorageAudit> {
+  return invoke<StorageAudit>("audit_tag_storage", { folder });
+}
+
+export type ReconcilePolicy = "union" | "preferComment" | "preferField";
+
+export interface SetDiff {
+  added: string[];
+  removed: string[];
+}
+
+export interface ReconcileResult extends ShadowMark {
+  path: string;
+  /** As found, before the call; null when the file couldn't be read. */
+  state: StorageState | null;
+  tags: string[];
+  comment: SetDiff;
+  field: SetDiff;
+  /** Written, or in a dry run would be. */
+  changed: boolean;
+  /** Conflicting and no policy given: left alone. */
+  needsPolicy: boolean;
+  error: string | null;
+  skippedLocked?: LockedField[];
+}
+
+/**
+ * Writes one tag set per file to the `tagStorage` location of Settings,
+ * clearing the other location (`clearOther`) or keeping it in step.
+ * Conflicting files are only touched with a `policy`.
+ */
+export async function reconcileTagStorage(
+  items: string[],
+  policy: ReconcilePolicy | null,
+  clearOther: boolean,
+  dryRun: boolean
+): Promise<{ results: ReconcileResult[]; snapshotId: string | null; preflight: Preflight }> {
+  return invoke<{ results: ReconcileResult[]; snapshotId: string | null; preflight: Preflight }>("reconcile_tag_storage", { items, policy, clearOther, dryRun }).catch(rethrowTyped);
+}
+
+export interface Workspace {
+  name: string;
+  roots: string[];
+}
+
+export async function saveWorkspace(name: string, roots: string[]): Promise<Workspace> {
+  return invoke<Workspace>("save_workspace", { name, roots });
+}
+
+/** Most recently used first. */
+export async function listWorkspaces(): Promise<Workspace[]> {
+  return invoke<Workspace[]>("list_workspaces");
+}
+
+export async function deleteWorkspace(name: string): Promise<void> {
+  await invoke<void>("delete_workspace", { name });
+}
+
+/** A scanned or workspace root with the stable id file references use. */
+export interface KnownRoot {
+  id: string;
+  path: string;
+  registeredAt: string;
+}
+
+/** How many stored references of each kind were rewritten. */
+export interface RebaseReport {
+  roots: number;
+  workspaceRoots: number;
+  touched: number;
+  retryItems: number;
+  pendingWrites: number;
+}
+
+export async function listKnownRoots(): Promise<KnownRoot[]> {
+  return invoke<KnownRoot[]>("list_known_roots");
+}
+
+/** Move everything stored under `oldRoot` to `newRoot` (drive letter or mount point changed). */
+export async function rebaseReferences(oldRoot: string, newRoot: string): Promise<RebaseReport> {
+  return invoke<RebaseReport>("rebase_references", { oldRoot, newRoot });
+}
+
+export interface TagUsage {
+  /** Bank tag name, or the raw token when the bank doesn't define it. */
+  name: string;
+  tagId?: string | null;
+  color?: string | null;
+  group?: string | null;
+  count: number;
+}
+
+export async function tagUsageStats(paths: string[], bank: string): Promise<TagUsage[]> {
+  return invoke<TagUsage[]>("tag_usage_stats", { paths, bank });
+}
+
+/** Payload of `volume-lost` / `volume-restored`. */
+export interface VolumeEvent {
+  root: string;
+  pendingWrites: number;
+}
+
+export interface PendingWrite {
+  path: string;
+  root: string;
+  comment: string;
+  source: string;
+  queuedAt: string;
+}
+
+export interface RetryReport {
+  root: string;
+  available: boolean;
+  flushed: string[];
+  failed: { path: string; error: string }[];
+  stillQueued: number;
+}
+
+/** Re-checks a lost root; when it is mounted again, queued writes are flushed. */
+export async function retryVolume(root: string): Promise<RetryReport> {
+  return invoke<RetryReport>("retry_volume", { root });
+}
+
+export async function pendingWrites(): Promise<PendingWrite[]> {
+  return invoke<PendingWrite[]>("pending_writes");
+}
+
+/**
+ * A batch write that failed on a locked file or missing volume and is being
+ * retried in the background. Successful retries emit `track-updated`
+ * ({ path, meta }).
+ */
+export type RetryItem = ({ op: "comment"; comment: string } | { op: "metadata"; patch: MetaPatch }) & {
+  id: string;
+  path: string;
+  source: AuditSource;
+  class: "locked" | "sharing-violation" | "volume-unavailable";
+  /** Including the original failure. */
+  attempts: number;
+  lastError: string;
+  queuedAt: string;
+  nextAttemptAt: string | null;
+  /** Out of attempts or older than a week; kept until dropped. */
+  dead: boolean;
+  deadReason: string | null;
+};
+
+/** Item ids per outcome. */
+export interface RetryRunReport {
+  succeeded: string[];
+  failed: string[];
+  died: string[];
+  /** Succeeded item id -> values cut or left out for a length limit. */
+  limited?: Record<string, FieldLimitHit[]>;
+}
+
+export async function listRetryQueue(): Promise<RetryItem[]> {
+  return invoke<RetryItem[]>("list_retry_queue");
+}
+
+/** Retry every live item now, ignoring backoff. */
+export async function retryNow(): Promise<RetryRunReport> {
+  return invoke<RetryRunReport>("retry_now");
+}
+
+export async function dropRetryItem(id: string): Promise<boolean> {
+  return invoke<boolean>("drop_retry_item", { id });
+}
+
+export interface ManifestExportSummary {
+  dest: string;
+  files: number;
+  /** Files whose audio couldn't be hashed (can only be matched by path). */
+  unhashed: number;
+  cancelled: boolean;
+}
+
+export interface ManifestApplyResult {
+  path: string;
+  matched?: string | null;
+  changed: boolean;
+  warning?: string | null;
+  error?: string | null;
+  /** Dry runs only. */
+  plan?: WritePlan;
+  skippedLocked?: LockedField[];
+}
+
+export interface ManifestApplyReport {
+  results: ManifestApplyResult[];
+  unmatchedEntries: string[];
+  cancelled: boolean;
+  /** See `listSnapshots`; set for batches above `snapshotThreshold`. */
+  snapshotId?: string | null;
+  preflight: Preflight;
+}
+
+/** Snapshots comment/title/artist plus an audio hash for every file under `folder`. Runs as a job. */
+export async function exportTagManifest(folder: string, dest: string): Promise<ManifestExportSummary> {
+  return invoke<ManifestExportSummary>("export_tag_manifest", { folder, dest });
+}
+
+export async function applyTagManifest(
+  folder: string,
+  manifestPath: string,
+  matchBy: "path" | "hash",
+  dryRun: boolean
+): Promise<ManifestApplyReport> {
+  return invoke<ManifestApplyReport>("apply_tag_manifest", { folder, manifestPath, matchBy, dryRun }).catch(rethrowTyped);
+}
+
+export interface WritePlan {
+  /** In write order; `exists: false` means the write creates that tag. */
+  targets: { tagType: string; exists: boolean }[];
+  /** Why the targets differ from the extension default: the `writeTargets` setting, an APE-only MP3, ... */
+  overrideReason?: string | null;
+}
+
+/** Which tag types a comment/metadata write to `path` would touch. */
+export async function writePlan(path: string): Promise<WritePlan> {
+  return invoke<WritePlan>("write_plan", { path });
+}
+
+export interface QuarantineReport {
+  /** false when the confirmation dialog was declined; nothing moved. */
+  confirmed: boolean;
+  files: {
+    path: string;
+    movedTo?: string | null;
+    health: { status: FileStatus; statusReason?: string | null };
+    error?: string | null;
+  }[];
+}
+
+/** Moves 0-byte/corrupt files in `folder` into `folder/_corrupt` after a native confirm. */
+export async function quarantineBadFiles(folder: string): Promise<QuarantineReport> {
+  return invoke<QuarantineReport>("quarantine_bad_files", { folder });
+}
+
+export interface ExtensionMismatch {
+  path: string;
+  extension: string;
+  realFormat: string;
+  /** Same name with the extension the content calls for. */
+  suggestedPath: string;
+  /** Whether that extension is enabled; `fixExtension` refuses when not. */
+  suggestedEnabled: boolean;
+}
+
+/** Files directly in `folder` whose content doesn't match their extension. */
+export async function verifyExtensions(folder: string): Promise<ExtensionMismatch[]> {
+  return invoke<ExtensionMismatch[]>("verify_extensions", { folder });
+}
+
+export interface ExtensionFix {
+  /** false when the confirmation dialog was declined; nothing renamed. */
+  confirmed: boolean;
+  path: string;
+  realFormat: string;
+  renamedTo?: string | null;
+}
+
+/** Renames `path` to the extension its content calls for, after a native confirm. */
+export async function fixExtension(path: string): Promise<ExtensionFix> {
+  return invoke<ExtensionFix>("fix_extension", { path });
+}
+
+export interface FileHash {
+  size: number;
+  hash: string;
+}
+
+export interface VerifiedOpen {
+  folder: string;
+  createdAt: string;
+  files: number;
+  errors: { path: string; error: string }[];
+  /** An earlier snapshot was kept. */
+  existing: boolean;
+  cancelled: boolean;
+}
+
+export interface VerifyReport {
+  folder: string;
+  snapshotAt: string;
+  checked: number;
+  /** `before: null` = new file, `after: null` = missing or unreadable. */
+  changed: { path: string; before?: FileHash | null; after?: FileHash | null; error?: string | null }[];
+  cancelled: boolean;
+}
+
+/** Snapshots quick hashes for `path` and blocks all writes under it (CommandError kind "ReadOnly"). Runs as a job. */
+export async function openFolderVerified(path: string): Promise<VerifiedOpen> {
+  return invoke<VerifiedOpen>("open_folder_verified", { path });
+}
+
+/** Re-hashes a verified folder against its snapshot. Runs as a job. */
+export async function verifyFolderUnchanged(path: string): Promise<VerifyReport> {
+  return invoke<VerifyReport>("verify_folder_unchanged", { path });
+}
+
+/** Drops the snapshot and allows writes again. */
+export async function closeFolderVerified(path: string): Promise<boolean> {
+  return invoke<boolean>("close_folder_verified", { path });
+}
+
+export interface PreviewInfo {
+  path: string;
+  /** dB to apply in WebAudio, clamped to ±12. */
+  previewGainDb?: number | null;
+  gainSource?: "replayGain" | "rms" | null;
+  /** An RMS estimate is running; listen for the `preview-gain` event (same shape). */
+  pending: boolean;
+}
+
+/** Preview gain from REPLAYGAIN_TRACK_GAIN, else a first-minute RMS estimate. */
+export async function previewInfoForPath(path: string): Promise<PreviewInfo> {
+  return invoke<PreviewInfo>("preview_info_for_path", { path });
+}
+
+export interface FormatInfo {
+  ext: string;
+  readable: boolean;
+  writable: boolean;
+  /** Decodes in every platform's webview. */
+  playableInWebview: boolean;
+  /** Listed under the current `Settings.extensions`. */
+  enabled: boolean;
+}
+
+export async function supportedFormats(): Promise<FormatInfo[]> {
+  return invoke<FormatInfo[]>("supported_formats");
+}
+
+export interface BankDedupeReport {
+  bank: string;
+  dryRun: boolean;
+  merged: { kept: { id: string; name: string }; removed: { id: string; name: string }[] }[];
+  emptyRemoved: { id: string; name: string }[];
+}
+
+/**
+ * Merges bank entries whose names collide under the tag policy (case-folded)
+ * and drops empty ones. Loading a bank emits `bank-duplicates` with a dry-run
+ * report of the same shape when there is something to merge.
+ * Throws CommandError "SchemaMismatch" after a mismatched `handshake`.
+ */
+export async function dedupeBank(bank: string, dryRun: boolean): Promise<BankDedupeReport> {
+  return invoke<BankDedupeReport>("dedupe_bank", { bank, dryRun }).catch(rethrowTyped);
+}
+
+/**
+ * Listed ids first, in that order; the rest keep their stored order. Returns the bank's entries as stored.
+ * Throws CommandError "SchemaMismatch" after a mismatched `handshake`.
+ */
+export async function reorderBankTags(bank: string, orderedIds: string[]): Promise<TagDef[]> {
+  return invoke<TagDef[]>("reorder_bank_tags", { bank, orderedIds }).catch(rethrowTyped);
+}
+
+export interface BankImportReport {
+  bank: string;
+  dryRun: boolean;
+  added: { id: string; name: string }[];
+  /** Names the bank already had; its entries are kept. */
+  skipped: { id: string; name: string }[];
+  /** Source ids the bank already used, and the ids the imported entries got. */
+  idMap: { from: string; to: string }[];
+}
+
+/** Appends the entries of the bank file at `source` that `bank` lacks by name. Throws CommandError "SchemaMismatch" after a mismatched `handshake`. */
+export async function importBankTags(bank: string, source: string, dryRun: boolean): Promise<BankImportReport> {
+  return invoke<BankImportReport>("import_bank_tags", { bank, source, dryRun }).catch(rethrowTyped);
+}
+
+export interface BankChangeBundle {
+  format: number;
+  bank: string;
+  since: string | null;
+  exportedAt: string;
+  changed: TagDef[];
+  removed: { id: string; name: string; removedAt: string }[];
+}
+
+/**
+ * Entries added, modified or removed since `since` (RFC 3339; null = all),
+ * for applying on another machine. `dest` also writes the bundle there.
+ */
+export async function exportBankChanges(bank: string, since: string | null, dest?: string): Promise<BankChangeBundle> {
+  return invoke<BankChangeBundle>("export_bank_changes", { bank, sinceTimestamp: since, dest: dest ?? null });
+}
+
+export type ConflictStrategy = "last-writer-wins" | "manual";
+
+export interface BankConflict {
+  id: string;
+  name: string;
+  /** null when removed on that side; see the matching `...RemovedAt`. */
+  local: TagDef | null;
+  incoming: TagDef | null;
+  localRemovedAt: string | null;
+  incomingRemovedAt: string | null;
+  /** Which side last-writer-wins kept; null under "manual" (nothing applied). */
+  resolution: "local" | "incoming" | null;
+}
+
+export interface BankSyncReport {
+  dryRun: boolean;
+  added: { id: string; name: string }[];
+  updated: { id: string; name: string }[];
+  removed: { id: string; name: string }[];
+  unchanged: number;
+  conflicts: BankConflict[];
+}
+
+/**
+ * Merge a bundle from the other machine; entries edited on both sides come back in `conflicts`.
+ * Throws CommandError "SchemaMismatch" after a mismatched `handshake`.
+ */
+export async function applyBankChanges(
+  bank: string,
+  bundle: BankChangeBundle,
+  conflictStrategy: ConflictStrategy,
+  dryRun: boolean
+): Promise<BankSyncReport> {
+  return invoke<BankSyncReport>("apply_bank_changes", { bank, bundle, conflictStrategy, dryRun }).catch(rethrowTyped);
+}
+
+export interface SnapshotSummary {
+  id: string;
+  createdAt: string;
+  /** Command that took it, e.g. "apply_comment_template". */
+  operation: string;
+  files: number;
+}
+
+/**
+ * Pre-batch snapshots, newest first. Batches that return a plain array report
+ * their snapshot through the `snapshot-created` event (a SnapshotSummary).
+ */
+export async function listSnapshots(): Promise<SnapshotSummary[]> {
+  return invoke<SnapshotSummary[]>("list_snapshots");
+}
+
+/** Re-applies snapshot values to every file in it, or only `paths`. */
+export async function restoreSnapshot(
+  id: string,
+  paths?: string[]
+): Promise<{ path: string; changed: boolean; error?: string | null; skippedLocked?: LockedField[] }[]> {
+  return invoke("restore_snapshot", { id, paths: paths ?? null });
+}
+
+/**
+ * Payload of `file-renamed`: a file in the open folder was renamed by another
+ * app. Cached metadata and touched records already follow the new path.
+ * Plain additions and removals arrive as `folder-file-added` and
+ * `folder-file-removed` (FolderFileEvent).
+ */
+export interface FileRenamedEvent {
+  old: string;
+  new: string;
+}
+
+export interface FolderFileEvent {
+  path: string;
+}
+
+export interface PreviewEntry {
+  path: string;
+  listenedMs: number;
+  /** RFC 3339. */
+  at: string;
+}
+
+export interface PreviewItem extends PreviewEntry {
+  /** From the metadata cache; null when the file can't be read any more. */
+  meta: TrackMeta | null;
+}
+
+export interface PreviewCount {
+  path: string;
+  plays: number;
+  listenedMs: number;
+  lastAt: string;
+}
+
+/** Records one preview; call when playback stops. */
+export async function notePreview(path: string, durationListenedMs: number): Promise<void> {
+  await invoke("note_preview", { path, durationListenedMs: Math.max(0, Math.round(durationListenedMs)) });
+}
+
+/** Recent previews, newest first (50 by default), optionally only those at or after `since` (RFC 3339). */
+export async function getPreviewHistory(limit?: number, since?: string): Promise<PreviewItem[]> {
+  return invoke<PreviewItem[]>("get_preview_history", { limit: limit ?? null, since: since ?? null });
+}
+
+/** Play counts of the previewed files under `folder`, for the "listened" mark. */
+export async function previewCounts(folder: string): Promise<PreviewCount[]> {
+  return invoke<PreviewCount[]>("preview_counts", { folder });
+}
+
+export type ExportKind = "auto" | "m3u" | "rekordboxXml" | "nml";
+
+export interface ExportIssue {
+  severity: "error" | "warning";
+  code: "noLocation" | "badLocation" | "missing" | "caseMismatch" | "xmlEscape" | "duration" | "bpm" | "encoding" | "danglingReference";
+  message: string;
+}
+
+export interface ExportEntryReport {
+  /** Position among the export's tracks, from 0. */
+  index: number;
+  line: number;
+  title: string | null;
+  /** As written in the file. */
+  location: string | null;
+  /** Where that points on this machine. */
+  resolved: string | null;
+  issues: ExportIssue[];
+}
+
+export interface ExportValidation {
+  path: string;
+  kind: ExportKind;
+  entries: number;
+  errors: number;
+  warnings: number;
+  /** Encoding and playlist-reference issues not tied to one entry. */
+  fileIssues: ExportIssue[];
+  /** Only the entries with issues. */
+  problems: ExportEntryReport[];
+}
+
+/**
+ * Re-reads an M3U/M3U8, Rekordbox XML or Traktor NML file and checks every
+ * location on this machine (case included), XML escaping, and duration/BPM
+ * ranges. `kind` defaults to a guess from the extension and contents.
+ */
+export async function validateExport(path: string, kind: ExportKind = "auto"): Promise<ExportValidation> {
+  return invoke<ExportValidation>("validate_export", { path, kind });
+}
+
+export interface TagCount {
+  /** Files carrying the tag. */
+  files: number;
+  /** Tokens, a tag repeated in one comment counting each time. */
+  occurrences: number;
+  /** Audio files looked at. */
+  scanned: number;
+  /** Of those, files opened because they were new or changed since cached. */
+  read: number;
+}
+
+/** Files under `folder` with `tag` as they are now; only new or changed files are opened, so it's cheap enough per keystroke. */
+export async function countTagOccurrences(folder: string, tag: string, recursive = false): Promise<TagCount> {
+  return invoke<TagCount>("count_tag_occurrences", { folder, tag, recursive }).catch(rethrowTyped);
+}
+
+export interface RenameTagResult {
+  path: string;
+  before: string;
+  after: string;
+  changed: boolean;
+  error: string | null;
+  skippedLocked?: LockedField[];
+  /** Values cut or left out for a tag type's length limit. */
+  limited?: FieldLimitHit[];
+}
+
+export interface RenameTagReport {
+  /** The new tag after the tag policy. */
+  tag: string;
+  /** One per file `countTagOccurrences` counts. */
+  results: RenameTagResult[];
+  cancelled: boolean;
+  snapshotId: string | null;
+  /** Null for dry runs. */
+  preflight: Preflight | null;
+}
+
+/** Renames `oldTag` to `newTag` in the files `countTagOccurrences` counts; `dryRun` previews each new comment. */
+export async function renameTagInFolder(folder: string, oldTag: string, newTag: string, dryRun: boolean, recursive = false): Promise<RenameTagReport> {
+  return invoke<RenameTagReport>("rename_tag_in_folder", { folder, old: oldTag, new: newTag, dryRun, recursive }).catch(rethrowTyped);
+}
+
+export interface FinderTag {
+  name: string;
+  /** 0 none, 1 gray, 2 green, 3 purple, 4 blue, 5 yellow, 6 red, 7 orange. */
+  color: number;
+}
+
+/** Finder tags of a file, in Finder's order. Rejects off macOS. */
+export async function readFinderTags(path: string): Promise<FinderTag[]> {
+  return invoke<FinderTag[]>("read_finder_tags", { path });
+}
+
+/** Replaces a file's Finder tags; an empty list removes them. Rejects off macOS. */
+export async function writeFinderTags(path: string, tags: FinderTag[]): Promise<void> {
+  return invoke<void>("write_finder_tags", { path, tags }).catch(rethrowTyped);
+}
+
+export interface OpHealth {
+  ops: number;
+  failures: number;
+  errorRate: number;
+  /** Upper bound of the latency bucket the 95th percentile is in; 10000 also means slower. */
+  p95Ms: number | null;
+}
+
+export interface VolumeHealth {
+  /** Drive letter or share on Windows, mount point elsewhere. */
+  volume: string;
+  reads: OpHealth;
+  writes: OpHealth;
+  streams: OpHealth;
+  /** The current hour is over the error-rate or latency threshold; `volume-degraded` was emitted. */
+  degraded: boolean;
+}
+
+/** Per-volume error rates and p95 latencies over the last 24 hours, worst first. */
+export async function volumeHealthReport(): Promise<VolumeHealth[]> {
+  return invoke<VolumeHealth[]>("volume_health_report");
+}
+
+export type TagImportFormat = "auto" | "text" | "csv" | "json";
+
+/** CSV columns by 0-based index or header name. */
+export interface TagImportOptions {
+  nameColumn?: number | string;
+  colorColumn?: number | string;
+  groupColumn?: number | string;
+  descriptionColumn?: number | string;
+  /** Default: the first row is a header when it has a "name" or "tag" column. */
+  hasHeader?: boolean;
+  /** Default: sniffed from the first row (comma, semicolon or tab). */
+  delimiter?: string;
+  /** Type of new entries when a row has none. Default "optional". */
+  kind?: TagType;
+  dryRun?: boolean;
+}
+
+export interface TagImportRow {
+  line: number;
+  raw: string;
+  name: string | null;
+  /** New entry's id; for skipped rows, the bank entry it matched. */
+  id: string | null;
+  reason: string | null;
+}
+
+export interface TagImportReport {
+  bank: string;
+  dryRun: boolean;
+  format: TagImportFormat;
+  added: TagImportRow[];
+  skipped: TagImportRow[];
+  invalid: TagImportRow[];
+}
+
+/** Merges a shared tag list (lines, CSV or JSON) into `bank`; `dryRun` previews the rows. Throws CommandError "SchemaMismatch" after a mismatched `handshake`. */
+export async function importTagsFromText(
+  bank: string,
+  content: string,
+  format: TagImportFormat = "auto",
+  options: TagImportOptions = {}
+): Promise<TagImportReport> {
+  return invoke<TagImportReport>("import_tags_from_text", { bank, content, format, options }).catch(rethrowTyped);
+}
+
+/**
+ * Revision of the last change the app saved to `path` (0 for none), as sent
+ * with `track-updated`; survives restarts.
+ */
+export async function getTrackRevision(path: string): Promise<number> {
+  return invoke<number>("get_track_revision", { path });
+}
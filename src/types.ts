@@ -1,14 +1,39 @@
 export type TagType = "main" | "mandatory" | "optional";
 export interface TagDef {
+  /** Stable and unique in the bank (schema 4); the backend never changes an existing one. */
   id: string;
   name: string; // no spaces; written into comment
   type: TagType;
   parent?: string | null;
   amountRange?: { min: number; max: number } | null;
+  color?: string | null;
+  group?: string | null;
+  description?: string | null;
+  /** Set by the backend on every write that changes the entry (schema 2). */
+  modifiedAt?: string;
 }
 export interface TagsFile {
   version: number;
   tags: TagDef[];
+  /** Tombstones of deleted entries, kept by the backend for bank sync. */
+  removed?: { id: string; name: string; removedAt: string }[];
+  /** Schema 3; kept by the backend when a write leaves them out. See `savePreset`. */
+  presets?: Preset[];
+}
+
+export type PresetField = "title" | "artist" | "album" | "genre" | "grouping" | "bpm" | "key" | "isrc" | "label" | "composer";
+
+export type PresetAction =
+  | { type: "add_tags"; tags: string[] }
+  | { type: "remove_tags"; tags: string[] }
+  /** An empty value clears the field. */
+  | { type: "set_field"; field: PresetField; value: string }
+  /** Warns when the field is empty after the earlier actions. */
+  | { type: "require_field"; field: PresetField };
+
+export interface Preset {
+  name: string;
+  actions: PresetAction[];
 }
 
 export interface TrackMeta {
@@ -20,6 +45,29 @@ export interface TrackMeta {
   comment: string;
   pictureDataUrl?: string | null;
   format?: string;
+  /** ISO yyyy-mm-dd (or yyyy-mm / yyyy) */
+  releaseDate?: string | null;
+  originalDate?: string | null;
+  /** RFC 3339 time of this app's last write to the file. */
+  lastTouchedByApp?: string | null;
+  /** Our fields changed on disk since that write. */
+  externallyModifiedSince?: boolean;
+  /** Another tag type on the file holds a different comment; see resolveCommentConflict. */
+  commentConflicts?: boolean;
+  /** TAGGED_AT provenance stamp (RFC 3339), written when embedTaggingTimestamp is on. */
+  taggedAt?: string | null;
+  /** Fields locked against writes, for padlock icons; see `lockFields`. */
+  lockedFields?: string[];
+  /** "#RRGGBB": Serato's track colour, else TRACK_COLOR; see `writeColorLabel`. */
+  colorLabel?: string | null;
+  /** Compilation flag; null when the file has none. */
+  compilation?: boolean | null;
+  /** "LAME 3.100", "FhG", a FLAC vendor string; see `encodingInfo`. */
+  encoder?: string | null;
+  /** MP3 only: VBR or ABR. null when unknown or not an MP3. */
+  vbr?: boolean | null;
+  /** Embedded pictures past `pictureBudgetMb` weren't read; see `scanEmbeddedPictures`. */
+  picturesTruncated?: boolean;
 }
 
 export interface Settings {
@@ -29,4 +77,80 @@ export interface Settings {
   showAlbum?: boolean;
   showComment?: boolean;
   instantPlayback: boolean;
+  maxMediaStreams?: number;
+  /** Streams of a file being saved: "wait" up to 2 s, then close them (default), or "close" at once. */
+  streamWritePolicy?: "wait" | "close";
+  /** Token-guarded JSON API on the media server for external tools. */
+  apiEnabled?: boolean;
+  /** Applied to tags added through mergeTags / inbox rules / the API. */
+  tagPolicy?: TagPolicy;
+  /** "Track 2" before "Track 10", accents folded; false = plain lowercase order. Default true. */
+  sortLocaleNatural?: boolean;
+  /** Search, autocomplete and tag suggestions ignore accents ("senorita" finds "Señorita"). Default on; turn off when accents tell values apart. */
+  accentFolding?: boolean;
+  /** Extensions scans list, lowercase without the dot; must come from `supportedFormats()`. */
+  extensions?: string[];
+  /** Gitignore-style patterns every scan leaves out, under any `.taggerignore` files. */
+  scanExcludes?: string[];
+  /** Batches over more files than this snapshot their targets first. Default 20. */
+  snapshotThreshold?: number;
+  /** Snapshots older than this are pruned. Default 30. */
+  snapshotRetentionDays?: number;
+  /** MP3 saves keep 4 KiB of ID3 padding and rewrite in place when possible. Default off. */
+  compactPadding?: boolean;
+  /** Session log verbosity; "debug" logs every command with its duration. Default "info". */
+  logLevel?: "error" | "warn" | "info" | "debug";
+  /** Scans flag files whose content doesn't match the extension. Default off. */
+  verifyExtensionsOnScan?: boolean;
+  /** Read saved fields back: "network" (network volumes only, default), "always" or "off". */
+  verifyWrites?: "network" | "always" | "off";
+  /** Scan the last opened folder on launch. Default on. */
+  reopenLastFolder?: boolean;
+  /** Extension -> tag types to read the comment from, first wins, e.g. { mp3: ["Ape", "Id3v2"] }. */
+  commentPrecedence?: Record<string, string[]>;
+  /** Extension -> tag types every write goes to, in order, instead of the built-in ones, e.g. { wav: ["Id3v2"] }. */
+  writeTargets?: Record<string, string[]>;
+  /** Every edit also stamps TAGGED_AT with the current time. Default off. */
+  embedTaggingTimestamp?: boolean;
+  /** "light": no background palette/gain/cache fills, 2 batch threads, slower flushes, snapshots written when idle. */
+  operationProfile?: OperationProfile;
+  /** Values too long for a tag type (RIFF INFO, ID3v1): reject the write, cut at a tag/word boundary (default), or skip that tag type. */
+  fieldLimitStrategy?: "reject" | "truncate" | "skip";
+  /** Where `reconcileTagStorage` puts a file's tags: the comment (default) or the AUDIOTAGGER_TAGS field. */
+  tagStorage?: "comment" | "field";
+  /** OS notification when a job of `notifyAfterSecs` (default 30) or longer ends while the window is in the background. Default on. */
+  notificationsEnabled?: boolean;
+  notifyAfterSecs?: number;
+  /** Embedded pictures read per file (MP3, FLAC) before the rest are skipped. Default 50. */
+  pictureBudgetMb?: number;
+  /** Minutes without a command before caches are pruned and trimmed to `cacheBudgets`; 0 = never. Default 10. */
+  maintenanceIdleMinutes?: number;
+  cacheBudgets?: CacheBudgets;
+  /** Saves through a temp file keep the file's extended attributes (Finder tags among them). Default on for macOS. */
+  preserveXattrs?: boolean;
+  /** macOS: tag edits keep the Finder tags of the hashtags in `finderTagColors` in step with the comment. Default off. */
+  syncFinderTags?: boolean;
+  /** Hashtag -> Finder colour (see `FinderTag`). */
+  finderTagColors?: Record<string, number>;
+  /** Command answers bigger than this come back in chunks (see `resolveChunked`). Default 8. */
+  ipcPayloadCapMb?: number;
+}
+
+export interface CacheBudgets {
+  /** Metadata cache entries kept, least recently used evicted first. */
+  metadataEntries: number;
+  peaksMb: number;
+  paletteEntries: number;
+}
+
+export type OperationProfile = "full" | "light";
+
+export interface TagPolicy {
+  lowercase?: boolean;
+  stripTrailingPunctuation?: boolean;
+  spacesToDashes?: boolean;
+  /** 0 = unlimited */
+  maxLength?: number;
+  /** Allowed besides letters and digits; null allows anything. */
+  allowedChars?: string | null;
 }